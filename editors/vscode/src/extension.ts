@@ -2,10 +2,16 @@
 //
 // This extension provides Language Server Protocol support for the Hone
 // configuration language. It launches the `hone lsp` command and communicates
-// with it via stdio.
+// with it via stdio. Debugging launches `hone dap` the same way.
 
 import * as path from 'path';
-import { workspace, ExtensionContext, window } from 'vscode';
+import {
+    debug,
+    workspace,
+    DebugAdapterExecutable,
+    ExtensionContext,
+    window,
+} from 'vscode';
 import {
     LanguageClient,
     LanguageClientOptions,
@@ -54,6 +60,14 @@ export function activate(context: ExtensionContext) {
         );
     });
 
+    // Debug adapter - launch `hone dap` via stdio
+    context.subscriptions.push(
+        debug.registerDebugAdapterDescriptorFactory('hone', {
+            createDebugAdapterDescriptor: () =>
+                new DebugAdapterExecutable(serverPath, ['dap', '--stdio']),
+        })
+    );
+
     context.subscriptions.push({
         dispose: () => {
             if (client) {
@@ -61,21 +61,16 @@ fn err_result(error: String) -> CompileResult {
     }
 }
 
-/// Compile Hone source to JSON or YAML.
+/// Compile Hone source to any output format.
 ///
 /// - `source`: Hone source code
-/// - `format`: "json" or "yaml"
+/// - `format`: a name from `output_formats()`, e.g. "json" or "yaml"
 /// - `variant_json`: JSON object of variant selections, e.g. `{"env": "production"}`
 /// - `args_json`: JSON object of args, e.g. `{"port": "8080", "env": "prod"}`
 #[wasm_bindgen]
 pub fn compile(source: &str, format: &str, variant_json: &str, args_json: &str) -> CompileResult {
-    let output_format = match format {
-        "yaml" | "YAML" => OutputFormat::Yaml,
-        "toml" | "TOML" => OutputFormat::Toml,
-        "dotenv" | "env" => OutputFormat::Dotenv,
-        "json-pretty" => OutputFormat::JsonPretty,
-        _ => OutputFormat::Json,
-    };
+    // Built-in names plus any formats registered with `register_emitter`
+    let output_format = OutputFormat::parse(format).unwrap_or(OutputFormat::Json);
 
     // Parse variant selections from JSON
     let variants: HashMap<String, String> = if variant_json.is_empty() {
@@ -185,6 +180,7 @@ fn validate_schemas(
             });
         }
 
+        checker.set_partial(use_stmt.partial);
         checker.check_type(
             value,
             &Type::Schema(use_stmt.schema_name.clone()),
@@ -199,7 +195,7 @@ fn validate_schemas(
 ///
 /// - `files_json`: JSON object mapping filenames to source, e.g. `{"./main.hone": "...", "./config.hone": "..."}`
 /// - `entry_point`: the entry file path, e.g. `"./main.hone"`
-/// - `format`: output format ("json", "yaml", "toml", "dotenv", "json-pretty")
+/// - `format`: a name from `output_formats()`, e.g. "json" or "yaml"
 /// - `variant_json`: JSON object of variant selections
 /// - `args_json`: JSON object of args
 #[wasm_bindgen]
@@ -228,13 +224,8 @@ fn compile_project_inner(
     variant_json: &str,
     args_json: &str,
 ) -> Result<(String, bool), String> {
-    let output_format = match format {
-        "yaml" | "YAML" => OutputFormat::Yaml,
-        "toml" | "TOML" => OutputFormat::Toml,
-        "dotenv" | "env" => OutputFormat::Dotenv,
-        "json-pretty" => OutputFormat::JsonPretty,
-        _ => OutputFormat::Json,
-    };
+    // Built-in names plus any formats registered with `register_emitter`
+    let output_format = OutputFormat::parse(format).unwrap_or(OutputFormat::Json);
 
     // Parse variant selections
     let variants: HashMap<String, String> = if variant_json.is_empty() {
@@ -544,6 +535,7 @@ fn validate_schemas_with_imports(
             });
         }
 
+        checker.set_partial(use_stmt.partial);
         checker.check_type(
             value,
             &Type::Schema(use_stmt.schema_name.clone()),
@@ -554,6 +546,13 @@ fn validate_schemas_with_imports(
     Ok(())
 }
 
+/// Names accepted as `format`: the built-in formats, then any registered
+/// with `hone::register_emitter` by the embedding crate. Returns a JSON array.
+#[wasm_bindgen]
+pub fn output_formats() -> String {
+    serde_json::to_string(&OutputFormat::names()).unwrap_or_else(|_| "[]".to_string())
+}
+
 #[wasm_bindgen]
 pub fn format_source(source: &str) -> CompileResult {
     match hone::format_source(source) {
@@ -641,13 +640,13 @@ pub fn get_diagnostics(source: &str) -> String {
 
     // Lex
     let mut lexer = Lexer::new(source, None);
-    let tokens = match lexer.tokenize() {
-        Ok(t) => t,
-        Err(e) => {
-            push_error(&mut diagnostics, &e, source);
-            return serde_json::to_string(&diagnostics).unwrap_or_else(|_| "[]".to_string());
+    let (tokens, lex_errors) = lexer.tokenize_recovering();
+    if !lex_errors.is_empty() {
+        for e in &lex_errors {
+            push_error(&mut diagnostics, e, source);
         }
-    };
+        return serde_json::to_string(&diagnostics).unwrap_or_else(|_| "[]".to_string());
+    }
 
     // Parse
     let mut parser = Parser::new(tokens, source, None);
@@ -683,6 +682,7 @@ pub fn get_diagnostics(source: &str) -> String {
                 if checker.collect_schemas(&ast).is_ok() {
                     for use_stmt in &use_statements {
                         if checker.get_schema(&use_stmt.schema_name).is_some() {
+                            checker.set_partial(use_stmt.partial);
                             if let Err(e) = checker.check_type(
                                 &value,
                                 &Type::Schema(use_stmt.schema_name.clone()),
@@ -709,7 +709,9 @@ pub fn get_diagnostics(source: &str) -> String {
                 .collect();
 
             if !policies.is_empty() {
-                if let Ok(violations) = evaluator.check_policies(&policies, &value) {
+                if let Ok(violations) =
+                    evaluator.check_policies(&policies, &value, hone::OutputFormat::JsonPretty)
+                {
                     for (name, level, msg) in &violations {
                         let severity = match level {
                             PolicyLevel::Deny => 8,
@@ -752,12 +754,14 @@ pub fn get_completions(source: &str, line: u32, col: u32) -> String {
         ("for", "For loop", "for $1 in $2 {\n\t$3\n}"),
         ("import", "Import module", "import \"$1\" as $2"),
         ("from", "Inherit from file", "from \"$1\""),
+        ("final", "Final key", "final $1: $2"),
         ("true", "Boolean true", "true"),
         ("false", "Boolean false", "false"),
         ("null", "Null value", "null"),
         ("assert", "Assertion", "assert $1 : \"$2\""),
         ("type", "Type definition", "type $1 = $2"),
         ("schema", "Schema definition", "schema $1 {\n\t$2\n}"),
+        ("enum", "Enum definition", "enum $1 { $2 }"),
         (
             "variant",
             "Variant definition",
@@ -796,8 +800,33 @@ pub fn get_completions(source: &str, line: u32, col: u32) -> String {
         ),
         ("concat", "Concatenate arrays or strings", "concat($1, $2)"),
         ("merge", "Shallow merge objects", "merge($1, $2)"),
+        (
+            "deep_merge",
+            "Deep merge with a strategy",
+            "deep_merge($1, $2)",
+        ),
         ("flatten", "Flatten nested arrays", "flatten($1)"),
+        (
+            "flatten_keys",
+            "Nested objects to dotted keys",
+            "flatten_keys($1)",
+        ),
+        (
+            "unflatten_keys",
+            "Dotted keys to nested objects",
+            "unflatten_keys($1)",
+        ),
         ("default", "Null coalescing", "default($1, $2)"),
+        (
+            "dig",
+            "Value at a path, or a default",
+            "dig($1, \"$2\", $3)",
+        ),
+        (
+            "set_path",
+            "Copy with a value set at a path",
+            "set_path($1, \"$2\", $3)",
+        ),
         ("upper", "Convert string to uppercase", "upper($1)"),
         ("lower", "Convert string to lowercase", "lower($1)"),
         ("trim", "Trim whitespace from string", "trim($1)"),
@@ -809,6 +838,8 @@ pub fn get_completions(source: &str, line: u32, col: u32) -> String {
         ("base64_decode", "Decode from base64", "base64_decode($1)"),
         ("to_json", "Convert to JSON string", "to_json($1)"),
         ("from_json", "Parse JSON string", "from_json($1)"),
+        ("to_toml", "Convert to TOML document", "to_toml($1)"),
+        ("from_toml", "Parse TOML document", "from_toml($1)"),
         ("to_str", "Convert value to string", "to_str($1)"),
         ("to_int", "Convert value to integer", "to_int($1)"),
         ("to_float", "Convert value to float", "to_float($1)"),
@@ -1066,8 +1097,10 @@ pub fn get_hover(source: &str, line: u32, col: u32) -> String {
         ("for", "**for** - Iteration\n\nIterates over an array or object.\n\n```hone\nlet doubled = for x in [1, 2, 3] { x * 2 }\n```"),
         ("import", "**import** - Module import\n\nImports definitions from another Hone file.\n\n```hone\nimport \"./config.hone\" as config\nimport { a, b } from \"./utils.hone\"\n```"),
         ("from", "**from** - Inheritance\n\nInherits and extends from a base configuration.\n\n```hone\nfrom \"./base.hone\"\n\noverrides {\n  key: \"new value\"\n}\n```"),
+        ("final", "**final** - Final key\n\nMakes a key impossible to override: setting it anywhere else, in an overlay, a variant case, or a file built `from` this one, is a compile error.\n\n```hone\nfinal replicas: 3\nfinal security {\n  tls: true\n}\n```"),
         ("assert", "**assert** - Assertion\n\nValidates a condition and fails with message if false.\n\n```hone\nassert len(name) > 0 : \"name cannot be empty\"\n```"),
         ("type", "**type** - Type alias\n\nDefines a type alias for documentation.\n\n```hone\ntype Port = int\n```"),
+        ("enum", "**enum** - Enum definition\n\nDefines a closed set of string values, usable as a type, a variant's case list, and a value (`Env.prod` is `\"prod\"`).\n\n```hone\nenum Env { dev, staging, prod }\n\nexpect args.env: Env = \"dev\"\n```"),
         ("schema", "**schema** - Schema definition\n\nDefines a schema for validating object structure.\n\n```hone\nschema Person {\n  name: string\n  age: int\n}\n```"),
         ("expect", "**expect** - Argument declaration\n\nDeclares expected CLI arguments with type and optional default.\n\n```hone\nexpect args.env: string\nexpect args.port: int = 8080\n```"),
         ("secret", "**secret** - Secret declaration\n\nDeclares a secret placeholder that is never emitted as a real value.\n\n```hone\nsecret db_pass from \"vault:secret/data/db#password\"\nsecret api_key from \"env:API_KEY\"\n```\n\nUse `--secrets-mode env` to resolve `env:` secrets from environment variables."),
@@ -1090,8 +1123,13 @@ pub fn get_hover(source: &str, line: u32, col: u32) -> String {
         ("contains", "**contains**(collection, value) -> bool\n\nChecks if collection contains the value.\n\n```hone\ncontains([1, 2, 3], 2)  // true\ncontains(\"hello\", \"ell\")  // true\n```"),
         ("concat", "**concat**(arrays...) -> array | concat(strings...) -> string\n\nConcatenates arrays or strings.\n\n```hone\nconcat([1, 2], [3, 4])  // [1, 2, 3, 4]\n```"),
         ("merge", "**merge**(objects...) -> object\n\nShallow merges objects, right wins on conflicts.\n\n```hone\nmerge({ a: 1 }, { b: 2 })  // { a: 1, b: 2 }\n```"),
-        ("flatten", "**flatten**(array) -> array\n\nFlattens one level of nesting.\n\n```hone\nflatten([[1, 2], [3]])  // [1, 2, 3]\n```"),
+        ("deep_merge", "**deep_merge**(base, overlay, strategy?) -> value\n\nMerges overlay over base as an assignment would: \"normal\" (`:`, the default) deep-merges objects, \"append\" (`+:`) also concatenates arrays, \"replace\" (`!:`) keeps overlay.\n\n```hone\ndeep_merge({ p: [1] }, { p: [2] }, \"append\")  // { p: [1, 2] }\n```"),
+        ("flatten", "**flatten**(array, depth?) -> array\n\nRemoves `depth` levels of nesting (default 1).\n\n```hone\nflatten([[1, 2], [3]])  // [1, 2, 3]\nflatten([1, [2, [3]]], 2)  // [1, 2, 3]\n```"),
+        ("flatten_keys", "**flatten_keys**(object, sep?) -> object\n\nTurns nested objects into one level of keys joined with sep (default \".\"). Arrays and empty objects are kept as values.\n\n```hone\nflatten_keys({ server: { port: 80 } })  // { \"server.port\": 80 }\nflatten_keys(cfg, \"__\")  // for .env-style keys\n```"),
+        ("unflatten_keys", "**unflatten_keys**(object, sep?) -> object\n\nSplits each key on sep (default \".\") into nested objects; the reverse of flatten_keys.\n\n```hone\nunflatten_keys({ \"server.port\": 80 })  // { server: { port: 80 } }\n```"),
         ("default", "**default**(value, fallback) -> value\n\nReturns value if not null, otherwise fallback.\n\n```hone\ndefault(null, 42)  // 42\ndefault(1, 42)  // 1\n```"),
+        ("dig", "**dig**(value, path, default?) -> value\n\nWalks a dotted path (`\"a.b.0\"`) or an array of keys and indices, returning default (null if omitted) when any segment is missing.\n\n```hone\ndig({a: [{b: 1}]}, \"a.0.b\", 0)  // 1\ndig({a: {}}, [\"a\", \"b\"], 0)  // 0\n```"),
+        ("set_path", "**set_path**(value, path, new) -> value\n\nReturns a copy of value with new at path, a dotted string or an array of keys and indices. Missing keys are created as objects.\n\n```hone\nset_path(base, \"spec.replicas\", 3)\n```"),
         ("upper", "**upper**(string) -> string\n\nConverts string to uppercase.\n\n```hone\nupper(\"hello\")  // \"HELLO\"\n```"),
         ("lower", "**lower**(string) -> string\n\nConverts string to lowercase.\n\n```hone\nlower(\"HELLO\")  // \"hello\"\n```"),
         ("trim", "**trim**(string) -> string\n\nRemoves leading and trailing whitespace.\n\n```hone\ntrim(\"  hello  \")  // \"hello\"\n```"),
@@ -1103,6 +1141,8 @@ pub fn get_hover(source: &str, line: u32, col: u32) -> String {
         ("base64_decode", "**base64_decode**(string) -> string\n\nDecodes base64 string.\n\n```hone\nbase64_decode(\"aGVsbG8=\")  // \"hello\"\n```"),
         ("to_json", "**to_json**(value) -> string\n\nConverts value to JSON string.\n\n```hone\nto_json({ a: 1 })  // \"{\\\"a\\\":1}\"\n```"),
         ("from_json", "**from_json**(string) -> value\n\nParses JSON string to value.\n\n```hone\nfrom_json(\"{\\\"a\\\":1}\")  // { a: 1 }\n```"),
+        ("to_toml", "**to_toml**(object) -> string\n\nConverts an object to a TOML document. TOML has no null, so null values are an error.\n\n```hone\nto_toml({ a: 1 })  // \"a = 1\\n\"\n```"),
+        ("from_toml", "**from_toml**(string) -> object\n\nParses a TOML document to an object. Datetimes become strings.\n\n```hone\nfrom_toml(\"a = 1\")  // { a: 1 }\n```"),
         ("to_str", "**to_str**(value) -> string\n\nConverts a scalar value to string.\n\n```hone\nto_str(42)  // \"42\"\nto_str(true)  // \"true\"\n```"),
         ("to_int", "**to_int**(value) -> int\n\nConverts value to integer.\n\n```hone\nto_int(\"42\")  // 42\nto_int(3.7)  // 3\n```"),
         ("to_float", "**to_float**(value) -> float\n\nConverts value to float.\n\n```hone\nto_float(\"3.14\")  // 3.14\nto_float(42)  // 42.0\n```"),
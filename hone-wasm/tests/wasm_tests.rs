@@ -95,3 +95,10 @@ port:  8080"#;
     assert!(result.output().contains("name: \"hello\""));
     assert!(result.output().contains("port: 8080"));
 }
+
+#[wasm_bindgen_test]
+fn test_output_formats_lists_builtins() {
+    let formats: Vec<String> = serde_json::from_str(&output_formats()).unwrap();
+    assert_eq!(formats.first().map(String::as_str), Some("json"));
+    assert!(formats.iter().any(|f| f == "yaml"));
+}
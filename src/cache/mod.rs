@@ -241,11 +241,17 @@ impl CachedResult {
 
 /// Get the default cache directory
 fn default_cache_dir() -> Option<PathBuf> {
+    cache_root().map(|root| root.join("v1"))
+}
+
+/// Root of Hone's per-user cache (`~/.cache/hone`), shared by the build cache
+/// and other downloaded data
+pub fn cache_root() -> Option<PathBuf> {
     // Try XDG_CACHE_HOME first, then ~/.cache
     if let Ok(xdg) = std::env::var("XDG_CACHE_HOME") {
-        Some(PathBuf::from(xdg).join("hone").join("v1"))
+        Some(PathBuf::from(xdg).join("hone"))
     } else if let Ok(home) = std::env::var("HOME") {
-        Some(PathBuf::from(home).join(".cache").join("hone").join("v1"))
+        Some(PathBuf::from(home).join(".cache").join("hone"))
     } else {
         dirs_fallback()
     }
@@ -260,7 +266,7 @@ fn dirs_fallback() -> Option<PathBuf> {
 fn dirs_fallback() -> Option<PathBuf> {
     std::env::var("LOCALAPPDATA")
         .ok()
-        .map(|d| PathBuf::from(d).join("hone").join("cache").join("v1"))
+        .map(|d| PathBuf::from(d).join("hone").join("cache"))
 }
 
 /// Hex-encode bytes (no external dependency needed)
@@ -0,0 +1,55 @@
+//! Cooperative cancellation
+//!
+//! A [`CancelToken`] is shared between whoever starts a compilation and the
+//! lexer, parser and evaluator doing the work. They check it between tokens,
+//! items and expressions and stop with [`HoneError::Cancelled`] once it is
+//! cancelled, so an editor can abandon the analysis of a document that has
+//! already changed again.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::errors::{HoneError, HoneResult};
+
+/// Shared flag asking work in progress to stop
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every holder of this token to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// `Err(HoneError::Cancelled)` once cancelled
+    pub fn check(&self) -> HoneResult<()> {
+        if self.is_cancelled() {
+            Err(HoneError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_cancellation() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert!(clone.check().is_ok());
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert!(matches!(clone.check(), Err(HoneError::Cancelled)));
+    }
+}
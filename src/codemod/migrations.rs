@@ -0,0 +1,155 @@
+//! The migrations, grouped by the edition that deprecated their old form
+
+use super::{Migration, Outcome, Site};
+use crate::parser::ast::*;
+
+pub const MIGRATIONS: &[Migration] = &[
+    // Edition 2025
+    Migration {
+        name: "default-to-coalesce",
+        edition: "2025",
+        description: "`default(a, b)` becomes `a ?? b`",
+        rewrite: default_to_coalesce,
+    },
+    Migration {
+        name: "substring-to-slice",
+        edition: "2025",
+        description: "`substring(s, start, end)` becomes `slice(s, start, end)`",
+        rewrite: substring_to_slice,
+    },
+];
+
+/// A call to the builtin `name`, unless the file defines its own `name`
+fn builtin_call<'a>(expr: &'a mut Expr, name: &str, site: &Site) -> Option<&'a mut CallExpr> {
+    match expr {
+        Expr::Call(call)
+            if matches!(&*call.func, Expr::Ident(f, _) if f == name)
+                && !site.defined.contains(name) =>
+        {
+            Some(call)
+        }
+        _ => None,
+    }
+}
+
+/// `default(a, b)` is `a ?? b`, except that it evaluates `b` even when `a`
+/// is not null
+fn default_to_coalesce(expr: &mut Expr, site: &Site) -> Outcome {
+    let Some(call) = builtin_call(expr, "default", site) else {
+        return Outcome::Unchanged;
+    };
+    // A call with the wrong arity is an error either way; leave it be
+    if call.args.len() != 2 {
+        return Outcome::Unchanged;
+    }
+    let location = call.location.clone();
+    let right = call.args.pop().expect("two arguments");
+    let left = call.args.pop().expect("two arguments");
+    let coalesce = Expr::Binary(BinaryExpr {
+        // `??` is left-associative: a left `??` chain needs no parentheses
+        left: Box::new(operand(left, true)),
+        op: BinaryOp::NullCoalesce,
+        right: Box::new(operand(right, false)),
+        location: location.clone(),
+    });
+    *expr = if site.operand {
+        Expr::Paren(Box::new(coalesce), location)
+    } else {
+        coalesce
+    };
+    Outcome::Rewritten
+}
+
+/// Parenthesize an operand of `??` that binds no tighter than `??`
+fn operand(expr: Expr, left: bool) -> Expr {
+    let needs_parens = match &expr {
+        Expr::Binary(binary) => match binary.op {
+            BinaryOp::NullCoalesce => !left,
+            BinaryOp::And | BinaryOp::Or => true,
+            _ => false,
+        },
+        Expr::Conditional(_) | Expr::Annotated(_) | Expr::Lambda(_) => true,
+        _ => false,
+    };
+    if needs_parens {
+        let location = expr.location().clone();
+        Expr::Paren(Box::new(expr), location)
+    } else {
+        expr
+    }
+}
+
+/// `substring(s, start, end)` is `slice(s, start, end)`, except that
+/// `slice` counts negative indices from the end, where `substring` gives ""
+fn substring_to_slice(expr: &mut Expr, site: &Site) -> Outcome {
+    let Some(call) = builtin_call(expr, "substring", site) else {
+        return Outcome::Unchanged;
+    };
+    if !(2..=3).contains(&call.args.len()) {
+        return Outcome::Unchanged;
+    }
+    if !call.args[1..]
+        .iter()
+        .all(|arg| matches!(arg, Expr::Integer(n, _) if *n >= 0))
+    {
+        return Outcome::Manual(
+            "substring() with computed or negative indices: slice() counts negative indices \
+             from the end, so check them before renaming the call"
+                .to_string(),
+        );
+    }
+    if let Expr::Ident(name, _) = &mut *call.func {
+        *name = "slice".to_string();
+    }
+    Outcome::Rewritten
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codemod::upgrade_source;
+
+    fn upgrade(source: &str) -> String {
+        upgrade_source(source, None, "2025").unwrap().source
+    }
+
+    #[test]
+    fn test_default_keeps_precedence() {
+        assert_eq!(
+            upgrade("let a = null\nx: default(a || false, 1)\n"),
+            "let a = null\n\nx: (a || false) ?? 1\n"
+        );
+        assert_eq!(
+            upgrade("let a = null\nx: default(default(a, null), 1) == 1\n"),
+            "let a = null\n\nx: (a ?? null ?? 1) == 1\n"
+        );
+        assert_eq!(
+            upgrade("let a = null\nx: default(1, a ?? 2)\n"),
+            "let a = null\n\nx: 1 ?? (a ?? 2)\n"
+        );
+    }
+
+    #[test]
+    fn test_leaves_uses_it_cannot_rewrite() {
+        // A file's own `default` hides the builtin
+        let source = "fn default(a, b) {\n  b\n}\n\nx: default(1, 2)\n";
+        let upgrade = upgrade_source(source, None, "2025").unwrap();
+        assert_eq!(upgrade.source, source);
+        assert!(upgrade.changes.is_empty());
+
+        // After `|>` the call has an argument more than it shows
+        let source = "let a = null\n\nx: a |> default(1, 2)\n";
+        assert_eq!(upgrade_source(source, None, "2025").unwrap().source, source);
+
+        // Computed indices may be negative
+        let source = "let i = 1\nx:  substring(\"abc\", i)\n";
+        let upgrade = upgrade_source(source, None, "2025").unwrap();
+        assert_eq!(upgrade.source, source);
+        assert_eq!(upgrade.changes.len(), 1);
+        assert_eq!(upgrade.changes[0].line, 2);
+        assert!(upgrade.changes[0]
+            .manual
+            .as_deref()
+            .unwrap()
+            .contains("negative"));
+    }
+}
@@ -0,0 +1,418 @@
+//! Codemods: mechanical rewrites of deprecated syntax
+//!
+//! Every [`Migration`] belongs to the edition that deprecated the form it
+//! rewrites. `hone upgrade --to <edition>` runs the migrations of every
+//! edition up to that one over a file's AST and prints the result with the
+//! formatter, so an upgraded file reads as if it had been written that way.
+//! A file no migration touches is returned as it was, not reformatted. When
+//! a migration finds a use it cannot rewrite without changing what the file
+//! means, it leaves it alone and says why, for a person to finish.
+
+mod migrations;
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+pub use migrations::MIGRATIONS;
+
+use crate::errors::{HoneError, HoneResult};
+use crate::formatter::format_file;
+use crate::lexer::Lexer;
+use crate::parser::ast::*;
+use crate::parser::Parser;
+
+/// Language editions, oldest first. Files written without deprecated forms
+/// are in the first one.
+pub const EDITIONS: &[&str] = &["2024", "2025"];
+
+/// A rewrite from a deprecated form to its replacement
+pub struct Migration {
+    /// Short name, e.g. `default-to-coalesce`
+    pub name: &'static str,
+    /// Edition that deprecated the old form
+    pub edition: &'static str,
+    /// What the migration changes
+    pub description: &'static str,
+    rewrite: fn(&mut Expr, &Site) -> Outcome,
+}
+
+/// Where an expression a migration may rewrite stands
+pub struct Site<'a> {
+    /// An operand of an operator, index or call, where a replacement that
+    /// binds more loosely needs parentheses
+    pub operand: bool,
+    /// Names the file defines itself, which hide builtins of the same name
+    pub defined: &'a HashSet<String>,
+}
+
+/// What a migration did to an expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Unchanged,
+    Rewritten,
+    /// A deprecated use left alone, with the reason
+    Manual(String),
+}
+
+/// A deprecated use found in a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub migration: &'static str,
+    pub line: usize,
+    pub column: usize,
+    /// Why the use was left for a person to migrate; `None` once rewritten
+    pub manual: Option<String>,
+}
+
+/// A file after its migrations
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upgrade {
+    /// The upgraded source; the original when nothing was rewritten
+    pub source: String,
+    /// Every deprecated use, in source order
+    pub changes: Vec<Change>,
+}
+
+impl Upgrade {
+    pub fn rewritten(&self) -> usize {
+        self.changes.iter().filter(|c| c.manual.is_none()).count()
+    }
+}
+
+/// Migrations of every edition up to `edition`
+pub fn migrations_to(edition: &str) -> HoneResult<Vec<&'static Migration>> {
+    let Some(target) = EDITIONS.iter().position(|e| *e == edition) else {
+        return Err(HoneError::compilation_error(format!(
+            "unknown edition '{}' (editions: {})",
+            edition,
+            EDITIONS.join(", ")
+        )));
+    };
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|m| EDITIONS.iter().position(|e| *e == m.edition) <= Some(target))
+        .collect())
+}
+
+/// Upgrade `source` to `edition`
+pub fn upgrade_source(source: &str, path: Option<PathBuf>, edition: &str) -> HoneResult<Upgrade> {
+    let migrations = migrations_to(edition)?;
+    let mut lexer = Lexer::new(source, path.clone());
+    let tokens = lexer.tokenize()?;
+    let comments = lexer.take_comments();
+    let mut ast = Parser::new(tokens, source, path).parse()?;
+
+    let defined = defined_names(&ast);
+    let mut walker = Walker {
+        migrations: &migrations,
+        defined: &defined,
+        changes: Vec::new(),
+    };
+    walker.file(&mut ast);
+    let mut changes = walker.changes;
+    changes.sort_by_key(|c| (c.line, c.column));
+
+    let rewritten = changes.iter().any(|c| c.manual.is_none());
+    Ok(Upgrade {
+        source: if rewritten {
+            format_file(&ast, source, comments)
+        } else {
+            source.to_string()
+        },
+        changes,
+    })
+}
+
+/// Top-level names of a file, including imported ones
+fn defined_names(ast: &File) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let preambles = std::iter::once(&ast.preamble).chain(ast.documents.iter().map(|d| &d.preamble));
+    for preamble in preambles {
+        for item in preamble {
+            let name = match item {
+                PreambleItem::Let(i) => {
+                    names.extend(i.names().into_iter().map(String::from));
+                    continue;
+                }
+                PreambleItem::FnDef(i) => &i.name,
+                PreambleItem::Secret(i) => &i.name,
+                PreambleItem::Enum(i) => &i.name,
+                PreambleItem::Import(import) => {
+                    match &import.kind {
+                        ImportKind::Whole { alias, .. } => names.extend(alias.clone()),
+                        ImportKind::Named {
+                            names: imported, ..
+                        } => names.extend(
+                            imported
+                                .iter()
+                                .map(|n| n.alias.clone().unwrap_or_else(|| n.name.clone())),
+                        ),
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            names.insert(name.clone());
+        }
+    }
+    for item in &ast.body {
+        if let BodyItem::Let(binding) = item {
+            names.extend(binding.names().into_iter().map(String::from));
+        }
+    }
+    names
+}
+
+/// Visits every expression of a file, innermost first, and runs the
+/// migrations on it
+struct Walker<'a> {
+    migrations: &'a [&'static Migration],
+    defined: &'a HashSet<String>,
+    changes: Vec<Change>,
+}
+
+impl Walker<'_> {
+    fn file(&mut self, file: &mut File) {
+        self.preamble(&mut file.preamble);
+        self.body(&mut file.body);
+        for doc in &mut file.documents {
+            self.preamble(&mut doc.preamble);
+            self.body(&mut doc.body);
+        }
+    }
+
+    fn preamble(&mut self, items: &mut [PreambleItem]) {
+        for item in items {
+            match item {
+                PreambleItem::Let(binding) => self.expr(&mut binding.value, false),
+                PreambleItem::Schema(schema) => {
+                    for field in &mut schema.fields {
+                        if let Some(default) = &mut field.default {
+                            self.expr(default, false);
+                        }
+                    }
+                }
+                PreambleItem::Variant(variant) => {
+                    for case in &mut variant.cases {
+                        self.body(&mut case.body);
+                    }
+                }
+                PreambleItem::Expect(expect) => {
+                    if let Some(default) = &mut expect.default {
+                        self.expr(default, false);
+                    }
+                }
+                PreambleItem::Policy(policy) => self.expr(&mut policy.condition, false),
+                PreambleItem::FnDef(fn_def) => self.expr(&mut fn_def.body, false),
+                PreambleItem::From(_)
+                | PreambleItem::Import(_)
+                | PreambleItem::TypeAlias(_)
+                | PreambleItem::Enum(_)
+                | PreambleItem::Use(_)
+                | PreambleItem::Secret(_) => {}
+            }
+        }
+    }
+
+    fn body(&mut self, items: &mut [BodyItem]) {
+        for item in items {
+            match item {
+                BodyItem::KeyValue(kv) => {
+                    if let Key::Computed(key) = &mut kv.key {
+                        self.expr(key, false);
+                    }
+                    self.expr(&mut kv.value, false);
+                }
+                BodyItem::Block(block) => self.body(&mut block.items),
+                BodyItem::When(when) => self.when(when),
+                BodyItem::For(for_loop) => self.for_loop(for_loop),
+                BodyItem::Assert(assert) => {
+                    self.expr(&mut assert.condition, false);
+                    if let Some(message) = &mut assert.message {
+                        self.expr(message, false);
+                    }
+                }
+                BodyItem::Let(binding) => self.expr(&mut binding.value, false),
+                BodyItem::Spread(spread) => self.expr(&mut spread.expr, false),
+                BodyItem::Use(_) | BodyItem::Include(_) => {}
+            }
+        }
+    }
+
+    fn when(&mut self, when: &mut WhenBlock) {
+        self.expr(&mut when.condition, false);
+        self.body(&mut when.body);
+        match &mut when.else_branch {
+            Some(ElseBranch::ElseWhen(next)) => self.when(next),
+            Some(ElseBranch::Else(body, _)) => self.body(body),
+            None => {}
+        }
+    }
+
+    fn for_loop(&mut self, for_loop: &mut ForLoop) {
+        self.expr(&mut for_loop.iterable, false);
+        if let Some(filter) = &mut for_loop.filter {
+            self.expr(filter, false);
+        }
+        match &mut for_loop.body {
+            ForBody::Object(items) => self.body(items),
+            ForBody::Expr(expr) => self.expr(expr, false),
+            ForBody::Block(items, expr) => {
+                self.body(items);
+                self.expr(expr, false);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr, operand: bool) {
+        match expr {
+            Expr::Null(_)
+            | Expr::Bool(..)
+            | Expr::Integer(..)
+            | Expr::Float(..)
+            | Expr::Duration(..)
+            | Expr::Size(..)
+            | Expr::Ident(..) => {}
+            Expr::String(string) => {
+                for part in &mut string.parts {
+                    if let StringPart::Interpolation(inner) | StringPart::Formatted(inner, _) = part
+                    {
+                        self.expr(inner, false);
+                    }
+                }
+            }
+            Expr::Path(path) => {
+                for part in &mut path.parts {
+                    if let PathPart::Index(index) = part {
+                        self.expr(index, false);
+                    }
+                }
+            }
+            Expr::Array(array) => {
+                for element in &mut array.elements {
+                    match element {
+                        ArrayElement::Expr(inner) | ArrayElement::Spread(inner) => {
+                            self.expr(inner, false)
+                        }
+                        ArrayElement::For(for_loop) => self.for_loop(for_loop),
+                        ArrayElement::When(when) => self.when(when),
+                    }
+                }
+            }
+            Expr::Object(object) | Expr::Block(object) => self.body(&mut object.items),
+            Expr::Binary(binary) if binary.op == BinaryOp::Pipe => {
+                self.expr(&mut binary.left, true);
+                // The call after `|>` gets an argument not written in it, so
+                // it is not matched itself, only what is inside it
+                if let Expr::Call(call) = binary.right.as_mut() {
+                    self.expr(&mut call.func, true);
+                    for arg in &mut call.args {
+                        self.expr(arg, false);
+                    }
+                }
+            }
+            Expr::Binary(binary) => {
+                self.expr(&mut binary.left, true);
+                self.expr(&mut binary.right, true);
+            }
+            Expr::Unary(unary) => self.expr(&mut unary.operand, true),
+            Expr::Call(call) => {
+                self.expr(&mut call.func, true);
+                for arg in &mut call.args {
+                    self.expr(arg, false);
+                }
+            }
+            Expr::Index(index) => {
+                self.expr(&mut index.base, true);
+                self.expr(&mut index.index, false);
+            }
+            Expr::Conditional(conditional) => {
+                // `? :` binds loosest, so its parts never need parentheses
+                self.expr(&mut conditional.condition, false);
+                self.expr(&mut conditional.then_branch, false);
+                self.expr(&mut conditional.else_branch, false);
+            }
+            Expr::Annotated(annotated) => self.expr(&mut annotated.expr, true),
+            Expr::Paren(inner, _) => self.expr(inner, false),
+            Expr::For(for_loop) => self.for_loop(for_loop),
+            Expr::When(when) => self.when(when),
+            Expr::Match(m) => {
+                self.expr(&mut m.subject, false);
+                for arm in &mut m.arms {
+                    for pattern in &mut arm.patterns {
+                        self.expr(pattern, false);
+                    }
+                    self.expr(&mut arm.value, false);
+                }
+            }
+            Expr::Lambda(lambda) => self.expr(&mut lambda.body, false),
+        }
+
+        let site = Site {
+            operand,
+            defined: self.defined,
+        };
+        for migration in self.migrations {
+            let location = expr.location().clone();
+            let manual = match (migration.rewrite)(expr, &site) {
+                Outcome::Unchanged => continue,
+                Outcome::Rewritten => None,
+                Outcome::Manual(reason) => Some(reason),
+            };
+            self.changes.push(Change {
+                migration: migration.name,
+                line: location.line,
+                column: location.column,
+                manual,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upgrade_rewrites_and_formats() {
+        let source = "let env_host = null\n# defaults\nlet host = default(env_host,   \"localhost\")\n\nname: substring(\"service\", 0, 3)\nport: default(null, 80) + 1\n";
+        let upgrade = upgrade_source(source, None, "2025").unwrap();
+        assert_eq!(
+            upgrade.source,
+            "let env_host = null\n# defaults\nlet host = env_host ?? \"localhost\"\n\nname: slice(\"service\", 0, 3)\nport: (null ?? 80) + 1\n"
+        );
+        let names: Vec<(&str, usize)> = upgrade
+            .changes
+            .iter()
+            .map(|c| (c.migration, c.line))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("default-to-coalesce", 3),
+                ("substring-to-slice", 5),
+                ("default-to-coalesce", 6)
+            ]
+        );
+        assert_eq!(upgrade.rewritten(), 3);
+
+        // Upgrading again finds nothing, and the result still compiles
+        let again = upgrade_source(&upgrade.source, None, "2025").unwrap();
+        assert_eq!(again.source, upgrade.source);
+        assert!(again.changes.is_empty());
+        let mut compiler = crate::Compiler::new(".");
+        let value = compiler.compile_source(&upgrade.source).unwrap();
+        assert_eq!(value.to_serde_json()["port"], 81);
+    }
+
+    #[test]
+    fn test_upgrade_to_older_edition_and_unknown_edition() {
+        let source = "x:   default(null, 1)\n";
+        let upgrade = upgrade_source(source, None, "2024").unwrap();
+        assert_eq!(upgrade.source, source);
+        assert!(upgrade.changes.is_empty());
+
+        let err = upgrade_source(source, None, "1999").unwrap_err();
+        assert!(err.to_string().contains("2024, 2025"), "{}", err);
+    }
+}
@@ -0,0 +1,292 @@
+//! Build derivations for `--emit-derivation`
+//!
+//! A derivation describes one compile as data: every source file and read
+//! file with its SHA-256, the args and variant selections, the compiler
+//! version, and the hash of each output. It is written as canonical JSON
+//! (RFC 8785), so the same build always produces the same bytes and a build
+//! system such as Nix or Bazel can treat the compile as a hermetic action.
+//!
+//! Paths are relative to the entry file's directory. Environment variables
+//! read with `env()` are listed by name only; a build that reads any, or
+//! calls `now()` (`"clock": true`), is not hermetic and says so.
+
+use std::path::{Component, Path, PathBuf};
+
+use indexmap::IndexMap;
+
+use crate::cache::CacheKey;
+use crate::emitter::{emit, OutputFormat};
+use crate::errors::HoneResult;
+use crate::evaluator::{ExternalInputKind, Value};
+
+use super::Compiler;
+
+/// Version of the derivation layout, bumped when fields change meaning
+pub const DERIVATION_VERSION: i64 = 1;
+
+/// Description of one compile
+#[derive(Debug, Clone)]
+pub struct Derivation {
+    /// Directory every path is relative to
+    root: PathBuf,
+    entry: PathBuf,
+    /// Output format, with any style options (`yaml+indent4`)
+    format: String,
+    sources: Vec<(PathBuf, String)>,
+    files: Vec<(PathBuf, Option<String>)>,
+    env: Vec<String>,
+    /// Whether `now()` was called
+    clock: bool,
+    args: Value,
+    variants: Vec<(String, String)>,
+    outputs: Vec<(Option<PathBuf>, String)>,
+}
+
+impl Derivation {
+    /// Describe the compile `compiler` just ran of `entry`. Sources are the
+    /// files it resolved; `file()` reads are hashed as they are now.
+    pub fn new(compiler: &Compiler, entry: &Path, format: impl Into<String>) -> Self {
+        let root = entry.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let mut sources: Vec<_> = compiler
+            .source_files()
+            .map(|file| (file.path.clone(), CacheKey::hash_string(&file.source)))
+            .collect();
+        sources.sort();
+
+        let mut files = Vec::new();
+        let mut env = Vec::new();
+        let mut clock = false;
+        for input in compiler.external_inputs() {
+            match input.kind {
+                ExternalInputKind::Env => env.push(input.name.clone()),
+                ExternalInputKind::File => {
+                    let path = PathBuf::from(&input.name);
+                    let hash = std::fs::read_to_string(&path)
+                        .ok()
+                        .map(|contents| CacheKey::hash_string(&contents));
+                    files.push((path, hash));
+                }
+                ExternalInputKind::Clock => clock = true,
+            }
+        }
+        files.sort();
+        files.dedup();
+        env.sort();
+        env.dedup();
+
+        Self {
+            root,
+            entry: entry.to_path_buf(),
+            format: format.into(),
+            sources,
+            files,
+            env,
+            clock,
+            args: Value::Object(IndexMap::new()),
+            variants: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Record the args object the compile was given
+    pub fn with_args(mut self, args: Value) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Record the variant selections
+    pub fn with_variants(mut self, variants: &[(String, String)]) -> Self {
+        self.variants = variants.to_vec();
+        self.variants.sort();
+        self
+    }
+
+    /// Record an output: where it was written (`None` for stdout) and its
+    /// exact contents
+    pub fn add_output(&mut self, path: Option<&Path>, contents: &str) {
+        self.outputs
+            .push((path.map(Path::to_path_buf), CacheKey::hash_string(contents)));
+    }
+
+    /// Whether the compile read nothing but hashed files
+    pub fn is_hermetic(&self) -> bool {
+        !self.clock && self.env.is_empty() && self.files.iter().all(|(_, hash)| hash.is_some())
+    }
+
+    /// The derivation as a value
+    pub fn to_value(&self) -> Value {
+        let path = |p: &Path| Value::String(self.relative(p));
+        let hashed = |p: &Path, hash: Option<&String>| {
+            let mut entry = IndexMap::new();
+            entry.insert("path".to_string(), path(p));
+            entry.insert(
+                "sha256".to_string(),
+                hash.map_or(Value::Null, |h| Value::String(h.clone())),
+            );
+            Value::Object(entry)
+        };
+
+        let mut compiler = IndexMap::new();
+        compiler.insert("name".to_string(), Value::String("hone".into()));
+        compiler.insert(
+            "version".to_string(),
+            Value::String(env!("CARGO_PKG_VERSION").into()),
+        );
+
+        let mut variants = IndexMap::new();
+        for (name, case) in &self.variants {
+            variants.insert(name.clone(), Value::String(case.clone()));
+        }
+
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|(p, hash)| {
+                let mut entry = IndexMap::new();
+                entry.insert("path".to_string(), p.as_deref().map_or(Value::Null, path));
+                entry.insert("sha256".to_string(), Value::String(hash.clone()));
+                Value::Object(entry)
+            })
+            .collect();
+
+        let mut obj = IndexMap::new();
+        obj.insert("version".to_string(), Value::Int(DERIVATION_VERSION));
+        obj.insert("compiler".to_string(), Value::Object(compiler));
+        obj.insert("entry".to_string(), path(&self.entry));
+        obj.insert("format".to_string(), Value::String(self.format.clone()));
+        obj.insert(
+            "sources".to_string(),
+            Value::Array(
+                self.sources
+                    .iter()
+                    .map(|(p, hash)| hashed(p, Some(hash)))
+                    .collect(),
+            ),
+        );
+        obj.insert(
+            "files".to_string(),
+            Value::Array(
+                self.files
+                    .iter()
+                    .map(|(p, hash)| hashed(p, hash.as_ref()))
+                    .collect(),
+            ),
+        );
+        obj.insert(
+            "env".to_string(),
+            Value::Array(self.env.iter().cloned().map(Value::String).collect()),
+        );
+        if self.clock {
+            obj.insert("clock".to_string(), Value::Bool(true));
+        }
+        obj.insert("args".to_string(), self.args.clone());
+        obj.insert("variants".to_string(), Value::Object(variants));
+        obj.insert("outputs".to_string(), Value::Array(outputs));
+        obj.insert("hermetic".to_string(), Value::Bool(self.is_hermetic()));
+        Value::Object(obj)
+    }
+
+    /// The derivation as canonical JSON
+    pub fn to_json(&self) -> HoneResult<String> {
+        emit(&self.to_value(), OutputFormat::JsonCanonical)
+    }
+
+    /// `path` relative to the root, with `/` separators
+    fn relative(&self, path: &Path) -> String {
+        let absolute = |p: &Path| {
+            let p = if p.is_absolute() {
+                p.to_path_buf()
+            } else {
+                std::env::current_dir().unwrap_or_default().join(p)
+            };
+            // An output may not exist yet; its directory usually does
+            p.canonicalize()
+                .or_else(|_| match (p.parent(), p.file_name()) {
+                    (Some(dir), Some(name)) => dir.canonicalize().map(|d| d.join(name)),
+                    _ => Err(std::io::ErrorKind::NotFound.into()),
+                })
+                .unwrap_or(p)
+        };
+        let (path, root) = (absolute(path), absolute(&self.root));
+        let path: Vec<Component> = path.components().collect();
+        let root: Vec<Component> = root.components().collect();
+        let common = path.iter().zip(&root).take_while(|(a, b)| a == b).count();
+        let parts: Vec<String> = std::iter::repeat_n("..".to_string(), root.len() - common)
+            .chain(
+                path[common..]
+                    .iter()
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+            )
+            .collect();
+        if parts.is_empty() {
+            ".".to_string()
+        } else {
+            parts.join("/")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derivation_is_stable_and_relative() {
+        let dir = std::env::temp_dir().join(format!("hone_derivation_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(dir.join("lib/base.hone"), "port: 80\n").unwrap();
+        std::fs::write(
+            dir.join("app.hone"),
+            "from \"./lib/base.hone\"\nname: args.name\n",
+        )
+        .unwrap();
+
+        let build = || {
+            let entry = dir.join("app.hone");
+            let mut compiler = Compiler::new(&dir);
+            let mut args = IndexMap::new();
+            args.insert("name".to_string(), Value::String("api".into()));
+            compiler.set_args(Value::Object(args.clone()));
+            let value = compiler.compile(&entry).unwrap();
+            let output = emit(&value, OutputFormat::Yaml).unwrap();
+            let mut derivation = Derivation::new(&compiler, &entry, "yaml")
+                .with_args(Value::Object(args))
+                .with_variants(&[("env".into(), "prod".into())]);
+            derivation.add_output(Some(&dir.join("out/app.yaml")), &output);
+            derivation
+        };
+
+        let derivation = build();
+        let json = derivation.to_json().unwrap();
+        assert_eq!(json, build().to_json().unwrap());
+        assert!(derivation.is_hermetic());
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["entry"], "app.hone");
+        assert_eq!(value["sources"][0]["path"], "app.hone");
+        assert_eq!(value["sources"][1]["path"], "lib/base.hone");
+        assert_eq!(
+            value["sources"][1]["sha256"],
+            CacheKey::hash_string("port: 80\n")
+        );
+        assert_eq!(value["outputs"][0]["path"], "out/app.yaml");
+        assert_eq!(value["args"]["name"], "api");
+        assert_eq!(value["variants"]["env"], "prod");
+        assert!(value.get("clock").is_none());
+
+        // Reading the clock makes the build not hermetic
+        std::fs::write(dir.join("app.hone"), "built: now()\n").unwrap();
+        let entry = dir.join("app.hone");
+        let mut compiler = Compiler::new(&dir);
+        compiler.set_allow_env(true);
+        compiler.compile(&entry).unwrap();
+        let derivation = Derivation::new(&compiler, &entry, "yaml");
+        assert!(!derivation.is_hermetic());
+        assert_eq!(
+            derivation.to_value().get_path(&["clock"]),
+            Some(&Value::Bool(true))
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -5,18 +5,38 @@
 //! 2. Evaluate files in topological order
 //! 3. Handle `import` statements (inject exports into scope)
 //! 4. Handle `from` inheritance (overlay on parent output)
+//! 5. Hand `include`d fragments to the evaluator, which splices them in
 
-use std::collections::HashMap;
+pub mod derivation;
+pub mod precision;
+pub mod sourcemap;
+pub mod workspace;
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 use indexmap::IndexMap;
 
-use crate::errors::{HoneError, HoneResult, Warning};
-use crate::evaluator::{merge_values, Evaluator, LocationMap, MergeStrategy, Value};
+use crate::emitter::{OutputFormat, StreamEmitter};
+use crate::errors::{Fix, HoneError, HoneResult, Warning};
+use crate::evaluator::keys::KeyCollision;
+use crate::evaluator::{
+    merge_values, DocMap, Evaluator, ExternalInput, Fragment, KeyWrite, LocationMap, MergeStrategy,
+    PolicyCache, PolicyTiming, Redactor, ScopeSnapshot, Value, ValueSizes,
+};
 use crate::lexer::token::SourceLocation;
-use crate::parser::ast::{File, ImportKind, PreambleItem};
-use crate::resolver::ImportResolver;
-use crate::typechecker::{Type, TypeChecker};
+use crate::lexer::token::TokenKind;
+use crate::parser::ast::{
+    BinaryOp, BodyItem, ElseBranch, Expr, File, ForBody, ForLoop, ImportKind, ImportName, Key,
+    LetBinding, PathPart, PreambleItem, TypeConstraint, UseStatement, WhenBlock,
+};
+use crate::resolver::{ImportResolver, ResolvedFile};
+use crate::typechecker::{compare_ranges, RangeRelation, Type, TypeChecker};
+use precision::Imprecise;
 
 /// Result of compiling a single file
 #[derive(Debug, Clone)]
@@ -29,6 +49,13 @@ pub struct CompiledFile {
     pub fn_exports: HashMap<String, FnExportDef>,
     /// Maps dot-paths to source locations for precise error reporting
     pub location_map: LocationMap,
+    /// Maps dot-paths to `##` doc comments, including the `from` base's
+    pub doc_comments: DocMap,
+    /// Schemas named by `partial use`, checked in full in files built `from`
+    /// this one
+    pub partial_uses: Vec<String>,
+    /// Keys declared `final` here or in the `from` base, with their file
+    pub final_keys: LocationMap,
 }
 
 /// A stored user function definition for cross-file export
@@ -44,6 +71,7 @@ struct EvalExports {
     exports: HashMap<String, Value>,
     fn_exports: HashMap<String, FnExportDef>,
     location_map: LocationMap,
+    doc_comments: DocMap,
 }
 
 /// Compiler that handles multi-file compilation
@@ -52,6 +80,9 @@ pub struct Compiler {
     resolver: ImportResolver,
     /// Cache of compiled files
     compiled: HashMap<PathBuf, CompiledFile>,
+    /// Files named by `include`, which are evaluated where they are
+    /// included rather than compiled on their own
+    fragments: HashMap<PathBuf, Arc<Fragment>>,
     /// CLI args to inject as `args` variable
     args: Option<Value>,
     /// Whether env() and file() are allowed
@@ -62,6 +93,35 @@ pub struct Compiler {
     variants: HashMap<String, String>,
     /// Whether to skip policy checks
     ignore_policies: bool,
+    /// Scope snapshots from every evaluated file (only recorded when enabled)
+    snapshots: Option<Vec<ScopeSnapshot>>,
+    /// Environment variables and files read by `env()`/`file()`, first use only
+    external_inputs: Vec<ExternalInput>,
+    /// Assignments to output keys by file (only recorded when enabled)
+    writes: Option<HashMap<PathBuf, Vec<KeyWrite>>>,
+    /// Doc comments of the most recently compiled root file
+    doc_comments: DocMap,
+    /// Where each output key of the most recently compiled root file was set
+    key_locations: LocationMap,
+    /// Source, AST and import paths of the most recently compiled root file,
+    /// whose schemas `check_schema` validates against
+    last_root: Option<(String, File, Vec<PathBuf>)>,
+    /// Whether to fold constants and prune dead branches before evaluation
+    optimize: bool,
+    /// Format the output will be emitted in, for `output_size_bytes()`
+    output_format: OutputFormat,
+    /// Whether a computed key may repeat in one object
+    key_collision: KeyCollision,
+    /// Values of `@sensitive` expressions in every evaluated file
+    redactor: Redactor,
+    /// Largest value each let, `for` loop and call produced (only when enabled)
+    value_sizes: Option<ValueSizes>,
+    /// Values of output-independent calls in policy conditions
+    policy_cache: PolicyCache,
+    /// Longest each policy's condition may take
+    policy_timeout: Option<Duration>,
+    /// How long each policy checked so far took, in order
+    policy_timings: Vec<PolicyTiming>,
 }
 
 impl Compiler {
@@ -70,11 +130,26 @@ impl Compiler {
         Self {
             resolver: ImportResolver::new(base_dir),
             compiled: HashMap::new(),
+            fragments: HashMap::new(),
             args: None,
             allow_env: false,
             warnings: Vec::new(),
             variants: HashMap::new(),
             ignore_policies: false,
+            snapshots: None,
+            external_inputs: Vec::new(),
+            writes: None,
+            doc_comments: DocMap::new(),
+            key_locations: LocationMap::new(),
+            last_root: None,
+            optimize: false,
+            output_format: OutputFormat::JsonPretty,
+            key_collision: KeyCollision::default(),
+            redactor: Redactor::default(),
+            value_sizes: None,
+            policy_cache: PolicyCache::global(),
+            policy_timeout: None,
+            policy_timings: Vec::new(),
         }
     }
 
@@ -83,6 +158,25 @@ impl Compiler {
         &self.warnings
     }
 
+    /// Masks for every `@sensitive` value evaluated so far, for showing
+    /// values outside the output (diffs, traces)
+    pub fn redactor(&self) -> &Redactor {
+        &self.redactor
+    }
+
+    /// `##` doc comments of the last file compiled with `compile` or
+    /// `compile_source`, by output dot-path
+    pub fn doc_comments(&self) -> &DocMap {
+        &self.doc_comments
+    }
+
+    /// Where each output key of the last file compiled with `compile` or
+    /// `compile_source` was set, by dot-path; keys from a `from` base point
+    /// into the base
+    pub fn key_locations(&self) -> &LocationMap {
+        &self.key_locations
+    }
+
     /// Set CLI args to inject into the evaluator scope
     pub fn set_args(&mut self, args: Value) {
         self.args = Some(args);
@@ -98,49 +192,219 @@ impl Compiler {
         self.variants = variants;
     }
 
+    /// Set whether to run the optimizer (constant folding and dead-branch
+    /// elimination) on each file before evaluating it
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// Set the format the output will be emitted in, which policies measure
+    /// with `output_size_bytes()` (default: pretty JSON)
+    pub fn set_output_format(&mut self, format: OutputFormat) {
+        self.output_format = format;
+    }
+
+    /// Set whether a computed key that repeats in one object overrides the
+    /// earlier one (the default) or is an error
+    pub fn set_key_collision(&mut self, mode: KeyCollision) {
+        self.key_collision = mode;
+    }
+
     /// Set whether to ignore policy checks
     pub fn set_ignore_policies(&mut self, ignore: bool) {
         self.ignore_policies = ignore;
     }
 
-    /// Compile source code directly (for stdin/inline input)
-    /// Imports resolve relative to the compiler's base directory.
-    pub fn compile_source(&mut self, source: &str) -> HoneResult<Value> {
-        let mut lexer = crate::Lexer::new(source, None);
-        let tokens = lexer.tokenize()?;
+    /// Set the cache for output-independent calls in policy conditions
+    /// (default: one shared by every compiler in the process, so repeated
+    /// compiles in `hone watch` and `hone serve` reuse it)
+    pub fn set_policy_cache(&mut self, cache: PolicyCache) {
+        self.policy_cache = cache;
+    }
 
-        let mut parser = crate::Parser::new(tokens, source, None);
-        let ast = parser.parse()?;
+    /// Set the longest a policy's condition may take before the compile
+    /// fails (default: no limit)
+    pub fn set_policy_timeout(&mut self, timeout: Option<Duration>) {
+        self.policy_timeout = timeout;
+    }
+
+    /// How long each policy checked so far took, in order (for
+    /// `compile --profile`)
+    pub fn policy_timings(&self) -> &[PolicyTiming] {
+        &self.policy_timings
+    }
+
+    /// Record scope snapshots at every body item of every evaluated file
+    pub fn enable_snapshots(&mut self) {
+        self.snapshots.get_or_insert_with(Vec::new);
+    }
+
+    /// Scope snapshots recorded so far. Snapshots taken before an evaluation
+    /// error are kept, so they can be inspected after a failed compile.
+    pub fn snapshots(&self) -> &[ScopeSnapshot] {
+        self.snapshots.as_deref().unwrap_or(&[])
+    }
+
+    /// Measure the values lets, `for` loops and calls produce in every
+    /// evaluated file (for `compile --report-memory`)
+    pub fn enable_value_sizes(&mut self) {
+        self.value_sizes.get_or_insert_with(ValueSizes::default);
+    }
+
+    /// Value sizes measured so far, if enabled
+    pub fn value_sizes(&self) -> Option<&ValueSizes> {
+        self.value_sizes.as_ref()
+    }
 
+    /// Record every assignment to an output key (for `hone conflicts`)
+    pub fn enable_writes(&mut self) {
+        self.writes.get_or_insert_with(HashMap::new);
+    }
+
+    /// Assignments to the output keys of `path`, in the order they took
+    /// effect: its `from` bases first (deepest first), then the file itself.
+    /// Assignments in imported files are not included.
+    pub fn writes(&self, path: &Path) -> Vec<KeyWrite> {
+        let Some(writes) = self.writes.as_ref() else {
+            return Vec::new();
+        };
+        let mut chain = Vec::new();
+        let mut next = Some(path.to_path_buf());
+        while let Some(file) = next {
+            if chain.contains(&file) {
+                break;
+            }
+            next = self.resolver.get(&file).and_then(|r| r.from_path.clone());
+            chain.push(file);
+        }
+        chain
+            .iter()
+            .rev()
+            .filter_map(|file| writes.get(file))
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Environment variables and files read by `env()`/`file()` across every
+    /// evaluated file, each listed once at its first read
+    pub fn external_inputs(&self) -> &[ExternalInput] {
+        &self.external_inputs
+    }
+
+    /// Every source file resolved so far: the import and `from` closure of
+    /// each file compiled
+    pub fn source_files(&self) -> impl Iterator<Item = &ResolvedFile> {
+        self.resolver.files()
+    }
+
+    /// Create an evaluator configured from the compiler's settings
+    fn new_evaluator(&self, source: &str) -> Evaluator {
         let mut evaluator = Evaluator::new(source);
         evaluator.set_allow_env(self.allow_env);
+        evaluator.set_key_collision(self.key_collision);
         if !self.variants.is_empty() {
             evaluator.set_variant_selections(self.variants.clone());
         }
         if let Some(ref args) = self.args {
             evaluator.define("args", args.clone());
         }
+        if self.snapshots.is_some() {
+            evaluator.enable_snapshots();
+        }
+        if self.writes.is_some() {
+            evaluator.enable_writes();
+        }
+        if self.value_sizes.is_some() {
+            evaluator.enable_value_sizes();
+        }
+        if !self.fragments.is_empty() {
+            evaluator.set_fragments(self.fragments.clone());
+        }
+        evaluator
+    }
+
+    /// The AST to evaluate: `ast` itself, or an optimized copy when enabled
+    fn eval_ast<'a>(&self, ast: &'a File) -> Cow<'a, File> {
+        if !self.optimize {
+            return Cow::Borrowed(ast);
+        }
+        let mut ast = ast.clone();
+        crate::optimizer::optimize(&mut ast, &self.variants);
+        Cow::Owned(ast)
+    }
+
+    /// Keep what an evaluator recorded: scope snapshots, ambient inputs,
+    /// value sizes and sensitive values
+    fn collect_records(&mut self, evaluator: &mut Evaluator) {
+        self.redactor.extend(evaluator.redactor());
+        if let Some(ref mut snapshots) = self.snapshots {
+            snapshots.extend(evaluator.take_snapshots());
+        }
+        if let (Some(sizes), Some(measured)) =
+            (self.value_sizes.as_mut(), evaluator.take_value_sizes())
+        {
+            sizes.extend(measured);
+        }
+        for input in evaluator.take_external_inputs() {
+            let seen = self
+                .external_inputs
+                .iter()
+                .any(|i| i.kind == input.kind && i.name == input.name);
+            if !seen {
+                self.external_inputs.push(input);
+            }
+        }
+    }
+
+    /// Compile source code directly (for stdin/inline input)
+    /// Imports resolve relative to the compiler's base directory.
+    pub fn compile_source(&mut self, source: &str) -> HoneResult<Value> {
+        let mut lexer = crate::Lexer::new(source, None);
+        let tokens = lexer.tokenize()?;
 
-        let value = evaluator.evaluate(&ast)?;
+        let mut parser = crate::Parser::new(tokens, source, None);
+        let ast = parser.parse()?;
+
+        let mut evaluator = self.new_evaluator(source);
+
+        let value = evaluator.evaluate(&self.eval_ast(&ast));
+        self.collect_records(&mut evaluator);
+        let value = value.map_err(|e| self.redactor.error(e))?;
 
         // Collect unchecked paths
         let unchecked_paths = evaluator.unchecked_paths().clone();
 
         // Generate warnings for unchecked paths
         for path in &unchecked_paths {
-            self.warnings.push(Warning {
-                message: format!("@unchecked used at {}", path),
-                file: None,
-                line: 0,
-                column: 0,
-            });
+            let mut warning = Warning::new("unchecked", format!("@unchecked used at {}", path));
+            if let Some(loc) = evaluator.location_map().get(path) {
+                warning = warning.at(source, loc);
+            }
+            self.warnings.push(warning);
         }
+        self.warn_unused_lets(source, &ast, None);
+        self.warn_enum_whens(source, &ast, None);
+        self.warn_constraint_ranges(source, &ast, None, &[]);
 
         // Build location map from evaluator
         let location_map = evaluator.location_map().clone();
+        self.warn_number_precision(&value, source, None, &location_map);
+        self.doc_comments = evaluator.doc_comments().clone();
+        self.key_locations = location_map.clone();
 
         // Type check against use statements if any (no imports for stdin)
-        self.validate_against_schemas(&ast, &value, source, &[], &unchecked_paths, &location_map)?;
+        self.validate_against_schemas(
+            &ast,
+            &value,
+            source,
+            &[],
+            evaluator.applied_uses(),
+            &unchecked_paths,
+            &location_map,
+            None,
+        )?;
+        self.last_root = Some((source.to_string(), ast.clone(), Vec::new()));
 
         // Check policies
         if !self.ignore_policies {
@@ -164,7 +428,7 @@ impl Compiler {
         self.resolve_all(path)?;
 
         // Get topological order (dependencies first)
-        let canonical = path.canonicalize().map_err(|e| {
+        let canonical = crate::resolver::canonicalize(path).map_err(|e| {
             HoneError::io_error(format!("failed to resolve path {}: {}", path.display(), e))
         })?;
 
@@ -173,6 +437,7 @@ impl Compiler {
             .resolver
             .topological_order(&canonical)?
             .iter()
+            .filter(|r| r.path == canonical || !self.fragments.contains_key(&r.path))
             .map(|r| r.path.clone())
             .collect();
 
@@ -181,11 +446,40 @@ impl Compiler {
             self.compile_file_by_path(&file_path)?;
         }
 
+        if let Some(resolved) = self.resolver.get(&canonical) {
+            let (source, ast) = (resolved.source.clone(), resolved.ast.clone());
+            let import_paths = resolved.import_paths.clone();
+            self.warn_unused_lets(&source, &ast, Some(&canonical));
+            self.warn_enum_whens(&source, &ast, Some(&canonical));
+            self.warn_constraint_ranges(&source, &ast, Some(&canonical), &import_paths);
+            self.last_root = Some((source, ast, import_paths));
+        }
+
         // Return the main file's output
-        self.compiled
+        let compiled = self
+            .compiled
             .get(&canonical)
-            .map(|c| c.value.clone())
-            .ok_or_else(|| HoneError::io_error("compilation produced no output".to_string()))
+            .ok_or_else(|| HoneError::io_error("compilation produced no output".to_string()))?;
+        let (value, locations) = (compiled.value.clone(), compiled.location_map.clone());
+        self.doc_comments = compiled.doc_comments.clone();
+        if let Some((source, _, _)) = &self.last_root {
+            let source = source.clone();
+            self.warn_number_precision(&value, &source, Some(&canonical), &locations);
+        }
+        self.key_locations = locations;
+        Ok(value)
+    }
+
+    /// Compile several entry points with one shared resolver and value cache,
+    /// so files imported by more than one root are parsed and evaluated once.
+    /// Each root gets its own result; one failing does not stop the others.
+    pub fn compile_many(&mut self, paths: &[PathBuf]) -> IndexMap<PathBuf, HoneResult<Value>> {
+        let mut results = IndexMap::new();
+        for path in paths {
+            let result = self.compile(path);
+            results.insert(path.clone(), result);
+        }
+        results
     }
 
     /// Compile a file and return multiple documents (for `---name` multi-doc output).
@@ -200,7 +494,7 @@ impl Compiler {
         self.resolve_all(path)?;
 
         // Get topological order (dependencies first)
-        let canonical = path.canonicalize().map_err(|e| {
+        let canonical = crate::resolver::canonicalize(path).map_err(|e| {
             HoneError::io_error(format!("failed to resolve path {}: {}", path.display(), e))
         })?;
 
@@ -209,6 +503,7 @@ impl Compiler {
             .resolver
             .topological_order(&canonical)?
             .iter()
+            .filter(|r| r.path == canonical || !self.fragments.contains_key(&r.path))
             .map(|r| r.path.clone())
             .collect();
 
@@ -230,15 +525,9 @@ impl Compiler {
         let import_paths = resolved.import_paths.clone();
 
         // Create evaluator with full configuration
-        let mut evaluator = Evaluator::new(&source);
-        evaluator.set_allow_env(self.allow_env);
-        if !self.variants.is_empty() {
-            evaluator.set_variant_selections(self.variants.clone());
-        }
-        if let Some(ref args) = self.args {
-            evaluator.define("args", args.clone());
-        }
-        self.inject_imports(&mut evaluator, &ast, &import_paths)?;
+        let mut evaluator = self.new_evaluator(&source);
+        self.inject_imports(&mut evaluator, &ast, &source, &import_paths)?;
+        self.inherit_final_keys(&mut evaluator, from_path.as_deref());
 
         // Get base value from `from` if present
         let base_value = if let Some(ref from) = from_path {
@@ -248,7 +537,9 @@ impl Compiler {
         };
 
         // Evaluate as multi-document
-        let mut documents = evaluator.evaluate_multi(&ast)?;
+        let documents = evaluator.evaluate_multi(&self.eval_ast(&ast));
+        self.collect_records(&mut evaluator);
+        let mut documents = documents.map_err(|e| self.redactor.error(e))?;
 
         // Merge main document with base if present
         if let Some(base) = base_value {
@@ -263,12 +554,18 @@ impl Compiler {
 
         // Generate warnings for unchecked paths
         for path_str in &unchecked_paths {
-            self.warnings.push(Warning {
-                message: format!("type check skipped for '{}' (@unchecked)", path_str),
-                file: Some(canonical.clone()),
-                line: 0,
-                column: 0,
-            });
+            self.warnings.push(unchecked_warning(
+                path_str,
+                &canonical,
+                &source,
+                evaluator.location_map(),
+            ));
+        }
+        self.warn_unused_lets(&source, &ast, Some(&canonical));
+        self.warn_enum_whens(&source, &ast, Some(&canonical));
+        self.warn_constraint_ranges(&source, &ast, Some(&canonical), &import_paths);
+        for (_, value) in &documents {
+            self.warn_number_precision(value, &source, Some(&canonical), &location_map);
         }
 
         // Type check the main document against use statements
@@ -278,8 +575,10 @@ impl Compiler {
                 main_value,
                 &source,
                 &import_paths,
+                evaluator.applied_uses(),
                 &unchecked_paths,
                 &location_map,
+                from_path.as_deref(),
             )?;
         }
 
@@ -289,10 +588,129 @@ impl Compiler {
                 self.check_policies(&mut evaluator, &ast, doc_value, &source, &canonical)?;
             }
         }
+        self.last_root = Some((source, ast, import_paths));
 
         Ok(documents)
     }
 
+    /// Compile a file straight into a [`StreamEmitter`], producing top-level
+    /// `key: for ...` arrays one element at a time instead of building them.
+    ///
+    /// Schemas and policies check the whole output, and `from` merges into it,
+    /// so files using them cannot be streamed; neither can multi-document files,
+    /// or files where something else writes or reads a streamed key.
+    pub fn compile_stream<W: Write>(
+        &mut self,
+        path: impl AsRef<Path>,
+        emitter: &mut StreamEmitter<W>,
+    ) -> HoneResult<()> {
+        let path = path.as_ref();
+        self.resolve_all(path)?;
+
+        let canonical = crate::resolver::canonicalize(path).map_err(|e| {
+            HoneError::io_error(format!("failed to resolve path {}: {}", path.display(), e))
+        })?;
+        let order: Vec<PathBuf> = self
+            .resolver
+            .topological_order(&canonical)?
+            .iter()
+            .filter(|r| r.path == canonical || !self.fragments.contains_key(&r.path))
+            .map(|r| r.path.clone())
+            .collect();
+        for file_path in &order {
+            if *file_path != canonical {
+                self.compile_file_by_path(file_path)?;
+            }
+        }
+
+        let resolved = self.resolver.get(&canonical).ok_or_else(|| {
+            HoneError::io_error(format!("file not resolved: {}", canonical.display()))
+        })?;
+        let source = resolved.source.clone();
+        let ast = resolved.ast.clone();
+        let import_paths = resolved.import_paths.clone();
+
+        let unsupported = if resolved.from_path.is_some() {
+            Some("`from`")
+        } else if !ast.documents.is_empty() {
+            Some("multiple documents")
+        } else if ast
+            .preamble
+            .iter()
+            .any(|item| matches!(item, PreambleItem::Use(_)))
+        {
+            Some("`use` schemas")
+        } else if !self.ignore_policies
+            && ast
+                .preamble
+                .iter()
+                .any(|item| matches!(item, PreambleItem::Policy(_)))
+        {
+            Some("policies")
+        } else {
+            None
+        };
+        if let Some(feature) = unsupported {
+            return Err(HoneError::io_error(format!(
+                "{} cannot be streamed: it uses {}, which needs the whole output",
+                path.display(),
+                feature
+            )));
+        }
+        if let Some((key, what)) = crate::evaluator::stream::first_conflict(&ast) {
+            return Err(HoneError::io_error(format!(
+                "{} cannot be streamed: `{}` is also written or read by {}, which needs the whole array",
+                path.display(),
+                key,
+                what
+            )));
+        }
+
+        let mut evaluator = self.new_evaluator(&source);
+        self.inject_imports(&mut evaluator, &ast, &source, &import_paths)?;
+        evaluator.enable_streaming();
+
+        let result = evaluator.evaluate(&self.eval_ast(&ast)).and_then(|value| {
+            if !evaluator.applied_uses().is_empty() {
+                return Err(HoneError::io_error(format!(
+                    "{} cannot be streamed: it uses `use` schemas, which need the whole output",
+                    path.display()
+                )));
+            }
+            let mut streamed: HashMap<String, ForLoop> =
+                evaluator.take_streamed().into_iter().collect();
+            let Value::Object(entries) = value else {
+                unreachable!("documents evaluate to objects")
+            };
+            for (key, value) in &entries {
+                match streamed.remove(key) {
+                    Some(for_loop) => {
+                        emitter.begin_array(key)?;
+                        evaluator.stream_for(key, &for_loop, &mut |item| emitter.item(&item))?;
+                        emitter.end_array()?;
+                    }
+                    None => emitter.entry(key, value)?,
+                }
+            }
+            Ok(())
+        });
+        self.collect_records(&mut evaluator);
+        result.map_err(|e| self.redactor.error(e))?;
+
+        for path_str in evaluator.unchecked_paths() {
+            self.warnings.push(unchecked_warning(
+                path_str,
+                &canonical,
+                &source,
+                evaluator.location_map(),
+            ));
+        }
+        self.warn_unused_lets(&source, &ast, Some(&canonical));
+        self.warn_enum_whens(&source, &ast, Some(&canonical));
+        self.warn_constraint_ranges(&source, &ast, Some(&canonical), &import_paths);
+        Ok(())
+    }
+
     /// Resolve a file and all its dependencies recursively
     fn resolve_all(&mut self, path: &Path) -> HoneResult<()> {
         let resolved = self.resolver.resolve(path)?;
@@ -300,6 +718,7 @@ impl Compiler {
         // Collect dependencies to resolve
         let from_path = resolved.from_path.clone();
         let import_paths = resolved.import_paths.clone();
+        let include_paths = resolved.include_paths.clone();
 
         // Resolve from dependency
         if let Some(ref from) = from_path {
@@ -311,9 +730,41 @@ impl Compiler {
             self.resolve_all(import)?;
         }
 
+        // Resolve included fragments, keeping their items for the evaluator
+        for include in &include_paths {
+            self.resolve_all(include)?;
+            self.add_fragment(include);
+        }
+
         Ok(())
     }
 
+    /// Keep the items of an included file: its `let`s, then its body
+    fn add_fragment(&mut self, path: &Path) {
+        if self.fragments.contains_key(path) {
+            return;
+        }
+        let Some(resolved) = self.resolver.get(path) else {
+            return;
+        };
+        let items = resolved
+            .ast
+            .preamble
+            .iter()
+            .filter_map(|item| match item {
+                PreambleItem::Let(binding) => Some(BodyItem::Let(binding.clone())),
+                _ => None,
+            })
+            .chain(resolved.ast.body.iter().cloned())
+            .collect();
+        let fragment = Fragment {
+            items,
+            source: resolved.source.clone(),
+        };
+        self.fragments
+            .insert(path.to_path_buf(), Arc::new(fragment));
+    }
+
     /// Compile a single file by path
     fn compile_file_by_path(&mut self, file_path: &Path) -> HoneResult<()> {
         // Skip if already compiled
@@ -326,26 +777,35 @@ impl Compiler {
             HoneError::io_error(format!("file not resolved: {}", file_path.display()))
         })?;
 
+        // A data file is already a value
+        if let Some(data) = resolved.data.clone() {
+            self.compiled.insert(
+                file_path.to_path_buf(),
+                CompiledFile {
+                    value: data,
+                    exports: HashMap::new(),
+                    fn_exports: HashMap::new(),
+                    location_map: LocationMap::default(),
+                    doc_comments: DocMap::default(),
+                    partial_uses: Vec::new(),
+                    final_keys: LocationMap::default(),
+                },
+            );
+            return Ok(());
+        }
+
         // Extract data we need (to avoid holding borrow)
         let source = resolved.source.clone();
         let ast = resolved.ast.clone();
         let from_path = resolved.from_path.clone();
         let import_paths = resolved.import_paths.clone();
 
-        // Create evaluator
-        let mut evaluator = Evaluator::new(&source);
-        evaluator.set_allow_env(self.allow_env);
-        if !self.variants.is_empty() {
-            evaluator.set_variant_selections(self.variants.clone());
-        }
-
-        // Inject CLI args if provided
-        if let Some(ref args) = self.args {
-            evaluator.define("args", args.clone());
-        }
+        // Create evaluator (with CLI args injected if provided)
+        let mut evaluator = self.new_evaluator(&source);
 
         // Inject imports into scope (use already-resolved paths from resolver)
-        self.inject_imports(&mut evaluator, &ast, &import_paths)?;
+        self.inject_imports(&mut evaluator, &ast, &source, &import_paths)?;
+        self.inherit_final_keys(&mut evaluator, from_path.as_deref());
 
         // Get base value from `from` if present
         let base_value = if let Some(ref from) = from_path {
@@ -355,7 +815,12 @@ impl Compiler {
         };
 
         // Evaluate the file
-        let eval_result = self.evaluate_with_exports(&mut evaluator, &ast)?;
+        let eval_result = self.evaluate_with_exports(&mut evaluator, &self.eval_ast(&ast));
+        self.collect_records(&mut evaluator);
+        if let Some(writes) = self.writes.as_mut() {
+            writes.insert(file_path.to_path_buf(), evaluator.take_writes());
+        }
+        let eval_result = eval_result.map_err(|e| self.redactor.error(e))?;
 
         // Get unchecked paths from evaluator
         let unchecked_paths = evaluator.unchecked_paths().clone();
@@ -372,6 +837,12 @@ impl Compiler {
         } else {
             eval_result.location_map.clone()
         };
+        let mut final_doc_comments = from_path
+            .as_ref()
+            .and_then(|from| self.compiled.get(from))
+            .map(|c| c.doc_comments.clone())
+            .unwrap_or_default();
+        final_doc_comments.extend(eval_result.doc_comments);
 
         // Merge with base if present
         let final_value = if let Some(base) = base_value {
@@ -382,12 +853,12 @@ impl Compiler {
 
         // Generate warnings for unchecked paths
         for path in &unchecked_paths {
-            self.warnings.push(Warning {
-                message: format!("type check skipped for '{}' (@unchecked)", path),
-                file: Some(file_path.to_path_buf()),
-                line: 0,
-                column: 0,
-            });
+            self.warnings.push(unchecked_warning(
+                path,
+                file_path,
+                &source,
+                evaluator.location_map(),
+            ));
         }
 
         // Type check against use statements if any
@@ -396,8 +867,10 @@ impl Compiler {
             &final_value,
             &source,
             &import_paths,
+            evaluator.applied_uses(),
             &unchecked_paths,
             &final_location_map,
+            from_path.as_deref(),
         )?;
 
         // Check policies
@@ -413,17 +886,43 @@ impl Compiler {
                 exports: eval_result.exports,
                 fn_exports: eval_result.fn_exports,
                 location_map: final_location_map,
+                doc_comments: final_doc_comments,
+                final_keys: evaluator
+                    .final_keys()
+                    .iter()
+                    .map(|(path, location)| {
+                        let mut location = location.clone();
+                        location.file.get_or_insert_with(|| file_path.to_path_buf());
+                        (path.clone(), location)
+                    })
+                    .collect(),
+                partial_uses: ast
+                    .preamble
+                    .iter()
+                    .filter_map(|item| match item {
+                        PreambleItem::Use(u) if u.partial => Some(u.schema_name.clone()),
+                        _ => None,
+                    })
+                    .collect(),
             },
         );
 
         Ok(())
     }
 
+    /// Forbid the file from setting the `final` keys of its `from` base
+    fn inherit_final_keys(&self, evaluator: &mut Evaluator, from_path: Option<&Path>) {
+        if let Some(base) = from_path.and_then(|from| self.compiled.get(from)) {
+            evaluator.inherit_final_keys(base.final_keys.clone());
+        }
+    }
+
     /// Inject imported values into the evaluator's scope
     fn inject_imports(
         &self,
         evaluator: &mut Evaluator,
         ast: &File,
+        source: &str,
         resolved_import_paths: &[PathBuf],
     ) -> HoneResult<()> {
         // Import paths in resolved_import_paths are ordered to match the import
@@ -457,6 +956,16 @@ impl Compiler {
                                     .to_string()
                             });
 
+                            // A data file is bound as it is, arrays included
+                            if self
+                                .resolver
+                                .get(import_path)
+                                .is_some_and(|r| r.data.is_some())
+                            {
+                                evaluator.add_import(&alias_name, compiled.value.clone());
+                                continue;
+                            }
+
                             // Create an object containing all exports
                             let mut exports_obj = IndexMap::new();
                             for (name, value) in &compiled.exports {
@@ -479,6 +988,12 @@ impl Compiler {
                                 let local_name =
                                     name_import.alias.as_ref().unwrap_or(&name_import.name);
 
+                                // Schema imports are registered at validation time
+                                if name_import.is_schema {
+                                    self.check_schema_import(name_import, source, import_path)?;
+                                    continue;
+                                }
+
                                 // Check if it's a function export first
                                 if let Some(fn_def) = compiled.fn_exports.get(&name_import.name) {
                                     evaluator.register_user_function(
@@ -514,17 +1029,20 @@ impl Compiler {
     }
 
     /// Validate output against schemas specified by `use` statements
+    #[allow(clippy::too_many_arguments)]
     fn validate_against_schemas(
         &self,
         ast: &File,
         value: &Value,
         source: &str,
         import_paths: &[PathBuf],
+        applied_uses: &[UseStatement],
         unchecked_paths: &std::collections::HashSet<String>,
         location_map: &LocationMap,
+        from_path: Option<&Path>,
     ) -> HoneResult<()> {
-        // Collect use statements
-        let use_statements: Vec<_> = ast
+        // Collect use statements, then those from taken `when`/variant branches
+        let mut use_statements: Vec<_> = ast
             .preamble
             .iter()
             .filter_map(|item| {
@@ -534,27 +1052,64 @@ impl Compiler {
                     None
                 }
             })
+            .chain(applied_uses)
+            .cloned()
+            .collect();
+
+        // A base's `partial use` is checked in full here, at the `from`,
+        // unless this file leaves the schema partial too
+        let inherited: Vec<String> = from_path
+            .and_then(|from| self.compiled.get(from))
+            .map(|base| base.partial_uses.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|name| !use_statements.iter().any(|u| &u.schema_name == name))
             .collect();
+        if let Some(from_stmt) = ast.preamble.iter().find_map(|item| match item {
+            PreambleItem::From(from) => Some(from),
+            _ => None,
+        }) {
+            use_statements.extend(inherited.iter().map(|name| UseStatement {
+                schema_name: name.clone(),
+                partial: false,
+                location: from_stmt.location.clone(),
+            }));
+        }
 
         // No use statements means no validation
         if use_statements.is_empty() {
             return Ok(());
         }
 
-        // Create type checker and collect schemas from current file
+        // Create type checker; schemas the `from` chain uses partially are
+        // collected first, so this file's own definitions win
         let mut checker = TypeChecker::new(source.to_string());
         checker.set_unchecked_paths(unchecked_paths.clone());
-        checker.collect_schemas(ast)?;
-
-        // Also collect schemas from all imported files
-        for import_path in import_paths {
-            if let Some(resolved) = self.resolver.get(import_path) {
+        if !inherited.is_empty() {
+            let mut base = from_path.map(Path::to_path_buf);
+            while let Some(base_path) = base {
+                let Some(resolved) = self.resolver.get(&base_path) else {
+                    break;
+                };
                 checker.collect_schemas(&resolved.ast)?;
+                for import_path in &resolved.import_paths {
+                    if let Some(imported) = self.resolver.get(import_path) {
+                        checker.collect_schemas(&imported.ast)?;
+                    }
+                }
+                self.collect_imported_schema_names(
+                    &mut checker,
+                    &resolved.ast,
+                    &resolved.import_paths,
+                )?;
+                base = resolved.from_path.clone();
             }
         }
+        self.collect_file_schemas(&mut checker, ast, import_paths)?;
 
         // Validate against each schema in use statements
         for use_stmt in use_statements {
+            checker.set_partial(use_stmt.partial);
             let location = SourceLocation {
                 file: None,
                 line: use_stmt.location.line,
@@ -584,30 +1139,146 @@ impl Compiler {
                 location_map,
             );
 
-            if !errors.is_empty() {
-                if errors.len() == 1 {
-                    // Single error: return directly (backward compatible)
-                    return Err(errors.into_iter().next().unwrap());
-                } else {
-                    let count = errors.len();
-                    return Err(HoneError::SchemaValidationErrors {
-                        src: source.to_string(),
-                        span: (location.offset, location.length).into(),
-                        count,
-                        s: if count == 1 {
-                            "".to_string()
-                        } else {
-                            "s".to_string()
-                        },
-                        errors,
+            schema_errors(source, &location, errors).map_err(|e| self.redactor.error(e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Validate `value` against `schema_name`, resolved the way a `use` in
+    /// the last compiled root file would see it: the file's own schemas,
+    /// schemas of imported files, and import aliases (`alias.Name`,
+    /// `schema Name as X`). Works for stdin input compiled with
+    /// `compile_source` too.
+    pub fn check_schema(&self, value: &Value, schema_name: &str) -> HoneResult<()> {
+        let Some((source, ast, import_paths)) = &self.last_root else {
+            return Err(HoneError::compilation_error(
+                "no file has been compiled to check a schema against",
+            ));
+        };
+
+        let mut checker = TypeChecker::new(source.clone());
+        self.collect_file_schemas(&mut checker, ast, import_paths)?;
+
+        if checker.get_schema(schema_name).is_none() {
+            return Err(HoneError::UndefinedVariable {
+                src: source.clone(),
+                span: (0, 1).into(),
+                name: schema_name.to_string(),
+                help: format!(
+                    "schema '{}' is not defined in the file or its imports",
+                    schema_name
+                ),
+            });
+        }
+
+        let location = SourceLocation {
+            file: None,
+            line: 1,
+            column: 1,
+            offset: 0,
+            length: 1,
+        };
+        let errors = checker.check_type_all(
+            value,
+            &Type::Schema(schema_name.to_string()),
+            &location,
+            &LocationMap::new(),
+        );
+        schema_errors(source, &location, errors)
+    }
+
+    /// Collect the schemas visible in `ast`: its own, those of its imports,
+    /// and the names its imports give them
+    fn collect_file_schemas(
+        &self,
+        checker: &mut TypeChecker,
+        ast: &File,
+        import_paths: &[PathBuf],
+    ) -> HoneResult<()> {
+        checker.collect_schemas(ast)?;
+        for import_path in import_paths {
+            if let Some(resolved) = self.resolver.get(import_path) {
+                checker.collect_schemas(&resolved.ast)?;
+            }
+        }
+        self.collect_imported_schema_names(checker, ast, import_paths)
+    }
+
+    /// Register the names imports give to schemas: `alias.Name` for every
+    /// schema of a whole import, and the local name of `schema Name [as X]`
+    /// in a named import (so it wins over a same-named schema elsewhere).
+    fn collect_imported_schema_names(
+        &self,
+        checker: &mut TypeChecker,
+        ast: &File,
+        import_paths: &[PathBuf],
+    ) -> HoneResult<()> {
+        let imports = ast.preamble.iter().filter_map(|item| match item {
+            PreambleItem::Import(import) => Some(import),
+            _ => None,
+        });
+
+        for (import, import_path) in imports.zip(import_paths) {
+            let Some(resolved) = self.resolver.get(import_path) else {
+                continue;
+            };
+            match &import.kind {
+                ImportKind::Whole { alias, .. } => {
+                    let namespace = alias.clone().unwrap_or_else(|| {
+                        import_path
+                            .file_stem()
+                            .and_then(|s| s.to_str())
+                            .unwrap_or("imported")
+                            .to_string()
                     });
+                    for item in &resolved.ast.preamble {
+                        if let PreambleItem::Schema(def) = item {
+                            let local_name = format!("{}.{}", namespace, def.name);
+                            checker.collect_schema_as(&resolved.ast, &def.name, &local_name)?;
+                        }
+                    }
+                }
+                ImportKind::Named { names, .. } => {
+                    for name_import in names.iter().filter(|n| n.is_schema) {
+                        let local_name = name_import.alias.as_ref().unwrap_or(&name_import.name);
+                        checker.collect_schema_as(&resolved.ast, &name_import.name, local_name)?;
+                    }
                 }
             }
         }
-
         Ok(())
     }
 
+    /// Check that a `schema Name` import names a schema the file defines
+    fn check_schema_import(
+        &self,
+        name_import: &ImportName,
+        source: &str,
+        import_path: &Path,
+    ) -> HoneResult<()> {
+        let defined = self.resolver.get(import_path).is_some_and(|resolved| {
+            resolved.ast.preamble.iter().any(
+                |item| matches!(item, PreambleItem::Schema(def) if def.name == name_import.name),
+            )
+        });
+        if defined {
+            return Ok(());
+        }
+
+        let location = &name_import.location;
+        Err(HoneError::UndefinedVariable {
+            src: source.to_string(),
+            span: (location.offset, location.length).into(),
+            name: name_import.name.clone(),
+            help: format!(
+                "{} does not define schema '{}'",
+                import_path.display(),
+                name_import.name
+            ),
+        })
+    }
+
     /// Check policy declarations against the output value
     fn check_policies(
         &mut self,
@@ -635,9 +1306,16 @@ impl Compiler {
             return Ok(());
         }
 
-        let violations = evaluator.check_policies(&policies, value)?;
+        evaluator.set_policy_cache(self.policy_cache.clone());
+        evaluator.set_policy_timeout(self.policy_timeout);
+        let checked = evaluator.policy_timings().len();
+        let violations = evaluator.check_policies(&policies, value, self.output_format);
+        self.policy_timings
+            .extend_from_slice(&evaluator.policy_timings()[checked..]);
+        let violations = violations?;
 
         for (name, level, message) in violations {
+            let message = self.redactor.text(&message);
             match level {
                 PolicyLevel::Deny => {
                     // Find the policy's location for error reporting
@@ -660,15 +1338,18 @@ impl Compiler {
                 }
                 PolicyLevel::Warn => {
                     let policy = policies.iter().find(|p| p.name == name);
-                    let (line, column) = policy
-                        .map(|p| (p.location.line, p.location.column))
-                        .unwrap_or((0, 0));
-                    self.warnings.push(Warning {
-                        message: format!("policy '{}': {}", name, message),
-                        file: Some(file_path.to_path_buf()),
-                        line,
-                        column,
-                    });
+                    // Budgets get their own code, so CI can deny just them
+                    let code = if evaluator.is_budget_policy(&name) {
+                        "budget"
+                    } else {
+                        "policy"
+                    };
+                    let mut warning = Warning::new(code, format!("policy '{}': {}", name, message))
+                        .in_file(file_path);
+                    if let Some(p) = policy {
+                        warning = warning.at(source, &p.location);
+                    }
+                    self.warnings.push(warning);
                 }
             }
         }
@@ -676,23 +1357,347 @@ impl Compiler {
         Ok(())
     }
 
-    /// Evaluate a file and extract both the output value and exports
-    fn evaluate_with_exports(
-        &self,
-        evaluator: &mut Evaluator,
-        ast: &File,
-    ) -> HoneResult<EvalExports> {
-        // Collect export names from let bindings
-        let export_names: Vec<String> = ast
-            .preamble
-            .iter()
-            .filter_map(|item| {
-                if let PreambleItem::Let(binding) = item {
-                    Some(binding.name.clone())
-                } else {
-                    None
+    /// Warn about `let` bindings in the root file that are never referenced.
+    /// Imported files are skipped: their top-level lets are exports. So are a
+    /// root file's top-level lets when it has no body, since it is a module
+    /// meant to be imported.
+    fn warn_unused_lets(&mut self, source: &str, ast: &File, file: Option<&Path>) {
+        let Ok(tokens) = crate::Lexer::new(source, None).tokenize() else {
+            return;
+        };
+        // Included fragments may read any `let` in scope where they are
+        // included, so their mentions count too
+        let fragment_tokens: Vec<_> = self
+            .fragments
+            .values()
+            .filter_map(|fragment| crate::Lexer::new(&fragment.source, None).tokenize().ok())
+            .collect();
+        let mut mentions: HashMap<&str, usize> = HashMap::new();
+        for token in tokens.iter().chain(fragment_tokens.iter().flatten()) {
+            if let TokenKind::Ident(name) = &token.kind {
+                *mentions.entry(name.as_str()).or_default() += 1;
+            }
+        }
+
+        let mut lets = Vec::new();
+        for item in &ast.preamble {
+            match item {
+                PreambleItem::Let(binding) if !ast.body.is_empty() => lets.push(binding),
+                PreambleItem::Variant(variant) => {
+                    for case in &variant.cases {
+                        collect_body_lets(&case.body, &mut lets);
+                    }
+                }
+                _ => {}
+            }
+        }
+        collect_body_lets(&ast.body, &mut lets);
+
+        let mut declared: HashMap<&str, usize> = HashMap::new();
+        for binding in &lets {
+            for name in binding.names() {
+                *declared.entry(name).or_default() += 1;
+            }
+        }
+
+        for binding in lets {
+            for name in binding.names() {
+                // Every mention is a declaration; nothing reads the name
+                if name.starts_with('_') || mentions.get(name) > declared.get(name) {
+                    continue;
+                }
+                let mut warning = Warning::new("unused-let", format!("unused variable '{}'", name))
+                    .at(source, &binding.location)
+                    .with_help(format!(
+                        "remove it, or rename it to '_{}' if it is intentionally unused",
+                        name
+                    ));
+                // Removing a destructuring let would drop its other names too
+                if binding.pattern.is_none() {
+                    warning = warning.with_fix(Fix {
+                        message: format!("remove `let {}`", name),
+                        span: (binding.location.offset, binding.location.length).into(),
+                        replacement: String::new(),
+                    });
+                }
+                if let Some(file) = file {
+                    warning = warning.in_file(file);
+                }
+                self.warnings.push(warning);
+            }
+        }
+    }
+
+    /// Warn about `when` chains that compare a value against an enum's
+    /// values: `unknown-enum-value` for a value the enum does not have, and
+    /// `non-exhaustive-when` when a chain without `else` misses some values.
+    /// The enum is known from `expect args.x: Enum` or an `Enum.value` operand.
+    fn warn_enum_whens(&mut self, source: &str, ast: &File, file: Option<&Path>) {
+        let mut enums: HashMap<&str, &[String]> = HashMap::new();
+        for item in &ast.preamble {
+            if let PreambleItem::Enum(def) = item {
+                enums.insert(def.name.as_str(), &def.values);
+            }
+        }
+        if enums.is_empty() {
+            return;
+        }
+        let mut typed: HashMap<String, &str> = HashMap::new();
+        for item in &ast.preamble {
+            if let PreambleItem::Expect(expect) = item {
+                if enums.contains_key(expect.type_name.as_str()) {
+                    typed.insert(expect.path.join("."), expect.type_name.as_str());
+                }
+            }
+        }
+
+        let mut chains = Vec::new();
+        for item in &ast.preamble {
+            if let PreambleItem::Variant(variant) = item {
+                for case in &variant.cases {
+                    collect_when_chains(&case.body, &mut chains);
+                }
+            }
+        }
+        collect_when_chains(&ast.body, &mut chains);
+
+        for when in chains {
+            // Every branch must compare the same subject against enum values
+            let mut subject = None;
+            let mut enum_name = None;
+            let mut compared: Vec<(String, &SourceLocation)> = Vec::new();
+            let mut has_else = false;
+            let mut branch = Some(when);
+            let mut uniform = true;
+            while let Some(w) = branch {
+                let mut arms = Vec::new();
+                if !enum_comparisons(&w.condition, &enums, &mut arms) {
+                    uniform = false;
+                    break;
+                }
+                for (subj, value, named_enum) in arms {
+                    if subject.get_or_insert_with(|| subj.clone()) != &subj {
+                        uniform = false;
+                    }
+                    if let Some(named) = named_enum {
+                        enum_name.get_or_insert(named);
+                    }
+                    compared.push((value, w.condition.location()));
+                }
+                branch = match &w.else_branch {
+                    Some(ElseBranch::ElseWhen(next)) => Some(next),
+                    Some(ElseBranch::Else(..)) => {
+                        has_else = true;
+                        None
+                    }
+                    None => None,
+                };
+            }
+            let Some(subject) = subject.filter(|_| uniform) else {
+                continue;
+            };
+            let Some(name) = typed.get(&subject).copied().or(enum_name) else {
+                continue;
+            };
+            let values = enums[name];
+
+            let mut warnings = Vec::new();
+            for (value, loc) in &compared {
+                if !values.contains(value) {
+                    warnings.push(
+                        Warning::new(
+                            "unknown-enum-value",
+                            format!("'{}' is not a value of enum {}", value, name),
+                        )
+                        .at(source, loc)
+                        .with_help(format!(
+                            "{} is one of: {}",
+                            name,
+                            values.join(", ")
+                        )),
+                    );
+                }
+            }
+            let missing: Vec<&str> = values
+                .iter()
+                .filter(|v| !compared.iter().any(|(c, _)| c == *v))
+                .map(String::as_str)
+                .collect();
+            if !has_else && !missing.is_empty() {
+                warnings.push(
+                    Warning::new(
+                        "non-exhaustive-when",
+                        format!(
+                            "when chain on {} does not handle {}",
+                            subject,
+                            missing.join(", ")
+                        ),
+                    )
+                    .at(source, &when.location)
+                    .with_help(format!(
+                        "add `else when {} == \"{}\"` or an `else` branch",
+                        subject, missing[0]
+                    )),
+                );
+            }
+            for mut warning in warnings {
+                if let Some(file) = file {
+                    warning = warning.in_file(file);
+                }
+                self.warnings.push(warning);
+            }
+        }
+    }
+
+    /// Warn where a value annotated with a range, such as `@int(1, 65535)`,
+    /// is set on a ranged field of a schema the file `use`s, and the two
+    /// ranges do not line up: `impossible-constraint` when no value fits
+    /// both, `constraint-overlap` when the annotation admits values the
+    /// field rejects, and `always-true-constraint` when the field's range
+    /// cannot fail for the value
+    fn warn_constraint_ranges(
+        &mut self,
+        source: &str,
+        ast: &File,
+        file: Option<&Path>,
+        import_paths: &[PathBuf],
+    ) {
+        let uses: Vec<&str> = ast
+            .preamble
+            .iter()
+            .filter_map(|item| match item {
+                PreambleItem::Use(use_stmt) => Some(use_stmt.schema_name.as_str()),
+                _ => None,
+            })
+            .collect();
+        if uses.is_empty() {
+            return;
+        }
+        let mut checker = TypeChecker::new(source.to_string());
+        if self
+            .collect_file_schemas(&mut checker, ast, import_paths)
+            .is_err()
+        {
+            return;
+        }
+
+        let mut lets = HashMap::new();
+        for item in &ast.preamble {
+            if let PreambleItem::Let(binding) = item {
+                match &binding.value {
+                    Expr::Annotated(annotated) if binding.pattern.is_none() => {
+                        lets.insert(binding.name.as_str(), &annotated.constraint);
+                    }
+                    _ => {}
                 }
+            }
+        }
+        let mut fields = Vec::new();
+        for schema in uses {
+            collect_ranged_fields(&checker, &ast.body, schema, lets.clone(), &mut fields);
+        }
+
+        for field in fields {
+            let annotation = format!("@{}", field.annotation);
+            let target = format!("{}: {}", field.name, field.field_type);
+            let warning = match compare_ranges(&field.annotation, &field.field_type) {
+                Some(RangeRelation::Disjoint) => Warning::new(
+                    "impossible-constraint",
+                    format!("no value satisfies both {} and {}", annotation, target),
+                )
+                .with_help("the schema rejects every value the annotation allows; one of the ranges is wrong"),
+                Some(RangeRelation::Overlapping) => Warning::new(
+                    "constraint-overlap",
+                    format!("{} allows values that {} rejects", annotation, target),
+                )
+                .with_help(format!(
+                    "narrow the annotation to {}, or widen the field",
+                    field.field_type
+                )),
+                Some(RangeRelation::Within) => Warning::new(
+                    "always-true-constraint",
+                    format!("{} always holds for a value annotated {}", target, annotation),
+                )
+                .with_severity(miette::Severity::Advice)
+                .with_help("the annotation already keeps the value in the field's range"),
+                None => continue,
+            };
+            let mut warning = warning.at(source, field.location);
+            if let Some(file) = file {
+                warning = warning.in_file(file);
+            }
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Warn about output numbers that may not round-trip through the output
+    /// format, once per source location
+    fn warn_number_precision(
+        &mut self,
+        value: &Value,
+        source: &str,
+        file: Option<&Path>,
+        locations: &LocationMap,
+    ) {
+        let mut seen = HashSet::new();
+        for (path, number) in precision::imprecise_numbers(value, self.output_format) {
+            // Array elements are reported at the key holding the array
+            let location = std::iter::successors(Some(path.as_str()), |p| {
+                p.rsplit_once('.').map(|(parent, _)| parent)
+            })
+            .find_map(|p| locations.get(p));
+            if !seen.insert(location.map(|loc| (loc.file.clone(), loc.offset))) {
+                continue;
+            }
+            let warning = match number {
+                Imprecise::Float(n) => Warning::new(
+                    "float-precision",
+                    format!(
+                        "'{}' is {}, which takes {} significant digits to write exactly",
+                        path,
+                        n,
+                        precision::significant_digits(n)
+                    ),
+                )
+                .with_help(format!(
+                    "a float is binary, so most decimals are approximated and only {} digits read back the same everywhere; round it with decimal(value, places)",
+                    precision::FLOAT_DIGITS
+                )),
+                Imprecise::Int(n) => Warning::new(
+                    "int-precision",
+                    format!(
+                        "'{}' is {}, beyond the ints a JSON reader that parses numbers as doubles keeps exactly (2^53)",
+                        path, n
+                    ),
+                )
+                .with_help("write it as a string with to_str() if readers need every digit"),
+            };
+            let mut warning = match location {
+                Some(loc) => warning.at(source, loc),
+                None => warning,
+            };
+            if let Some(file) = file {
+                warning = warning.in_file(file);
+            }
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Evaluate a file and extract both the output value and exports
+    fn evaluate_with_exports(
+        &self,
+        evaluator: &mut Evaluator,
+        ast: &File,
+    ) -> HoneResult<EvalExports> {
+        // Collect export names from let bindings
+        let export_names: Vec<String> = ast
+            .preamble
+            .iter()
+            .flat_map(|item| match item {
+                PreambleItem::Let(binding) => binding.names(),
+                _ => Vec::new(),
             })
+            .map(String::from)
             .collect();
 
         // Collect fn export definitions
@@ -719,6 +1724,7 @@ impl Compiler {
 
         // Extract location map
         let location_map = evaluator.location_map().clone();
+        let doc_comments = evaluator.doc_comments().clone();
 
         // Extract exports by looking up the defined variables
         let mut exports = HashMap::new();
@@ -733,6 +1739,7 @@ impl Compiler {
             exports,
             fn_exports: fn_defs,
             location_map,
+            doc_comments,
         })
     }
 }
@@ -742,7 +1749,7 @@ pub fn compile_file(path: impl AsRef<Path>) -> HoneResult<Value> {
     let path = path.as_ref();
 
     // Canonicalize the path first to get absolute path
-    let canonical = path.canonicalize().map_err(|e| {
+    let canonical = crate::resolver::canonicalize(path).map_err(|e| {
         HoneError::io_error(format!("failed to resolve path {}: {}", path.display(), e))
     })?;
 
@@ -755,7 +1762,7 @@ pub fn compile_file(path: impl AsRef<Path>) -> HoneResult<Value> {
 pub fn compile_file_with_args(path: impl AsRef<Path>, args: Value) -> HoneResult<Value> {
     let path = path.as_ref();
 
-    let canonical = path.canonicalize().map_err(|e| {
+    let canonical = crate::resolver::canonicalize(path).map_err(|e| {
         HoneError::io_error(format!("failed to resolve path {}: {}", path.display(), e))
     })?;
 
@@ -772,6 +1779,10 @@ pub fn compile_file_with_args(path: impl AsRef<Path>, args: Value) -> HoneResult
 /// - Parseable as i64 -> `Value::Int`
 /// - Parseable as f64 -> `Value::Float`
 /// - Everything else -> `Value::String`
+///
+/// Numbers are only inferred when they print back as the same text, so
+/// `"0755"` or `"1.50"` stay strings and an `expect args.x: string` can
+/// recover exactly what was typed.
 pub fn infer_value(s: &str) -> Value {
     if s == "null" {
         return Value::Null;
@@ -782,13 +1793,18 @@ pub fn infer_value(s: &str) -> Value {
     if s == "false" {
         return Value::Bool(false);
     }
-    if let Ok(n) = s.parse::<i64>() {
-        return Value::Int(n);
-    }
-    if let Ok(n) = s.parse::<f64>() {
-        return Value::Float(n);
+    let inferred = if let Ok(n) = s.parse::<i64>() {
+        Value::Int(n)
+    } else if let Ok(n) = s.parse::<f64>() {
+        Value::Float(n)
+    } else {
+        return Value::String(s.to_string());
+    };
+    if inferred.to_string() == s {
+        inferred
+    } else {
+        Value::String(s.to_string())
     }
-    Value::String(s.to_string())
 }
 
 /// Set a nested value in an object using a dotted key path.
@@ -819,6 +1835,221 @@ fn set_nested(obj: &mut IndexMap<String, Value>, key: &str, value: Value) {
     current.insert(last.to_string(), value);
 }
 
+/// Warning for a path whose type check was skipped with `@unchecked`
+fn unchecked_warning(path: &str, file: &Path, source: &str, locations: &LocationMap) -> Warning {
+    let mut warning = Warning::new(
+        "unchecked",
+        format!("type check skipped for '{}' (@unchecked)", path),
+    )
+    .with_severity(miette::Severity::Advice)
+    .in_file(file);
+    if let Some(loc) = locations.get(path) {
+        warning = warning.at(source, loc);
+    }
+    warning
+}
+
+/// `let` bindings among body items, including nested blocks and branches
+fn collect_body_lets<'a>(items: &'a [BodyItem], lets: &mut Vec<&'a LetBinding>) {
+    for item in items {
+        match item {
+            BodyItem::Let(binding) => lets.push(binding),
+            BodyItem::Block(block) => collect_body_lets(&block.items, lets),
+            BodyItem::When(when) => {
+                collect_body_lets(&when.body, lets);
+                let mut branch = when.else_branch.as_ref();
+                while let Some(b) = branch {
+                    match b {
+                        ElseBranch::ElseWhen(w) => {
+                            collect_body_lets(&w.body, lets);
+                            branch = w.else_branch.as_ref();
+                        }
+                        ElseBranch::Else(body, _) => {
+                            collect_body_lets(body, lets);
+                            branch = None;
+                        }
+                    }
+                }
+            }
+            BodyItem::For(for_loop) => match &for_loop.body {
+                ForBody::Object(body) | ForBody::Block(body, _) => collect_body_lets(body, lets),
+                ForBody::Expr(_) => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Collect the first `when` of every when chain, however deeply nested
+fn collect_when_chains<'a>(items: &'a [BodyItem], chains: &mut Vec<&'a WhenBlock>) {
+    for item in items {
+        match item {
+            BodyItem::Block(block) => collect_when_chains(&block.items, chains),
+            BodyItem::When(when) => {
+                chains.push(when);
+                let mut branch = Some(when);
+                while let Some(w) = branch {
+                    collect_when_chains(&w.body, chains);
+                    branch = match &w.else_branch {
+                        Some(ElseBranch::ElseWhen(next)) => Some(next),
+                        Some(ElseBranch::Else(body, _)) => {
+                            collect_when_chains(body, chains);
+                            None
+                        }
+                        None => None,
+                    };
+                }
+            }
+            BodyItem::For(for_loop) => match &for_loop.body {
+                ForBody::Object(body) | ForBody::Block(body, _) => {
+                    collect_when_chains(body, chains)
+                }
+                ForBody::Expr(_) => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+/// A key of a schema in `use` whose value carries a range annotation
+struct RangedField<'a> {
+    /// `Schema.field`
+    name: String,
+    field_type: Type,
+    annotation: Type,
+    location: &'a SourceLocation,
+}
+
+/// Collect the keys of `items`, checked against `schema`, whose value is
+/// annotated with a type, directly or through a `let` in `lets`. Blocks
+/// for fields of a schema type are followed, and so are `when` branches.
+fn collect_ranged_fields<'a>(
+    checker: &TypeChecker,
+    items: &'a [BodyItem],
+    schema: &str,
+    mut lets: HashMap<&'a str, &'a TypeConstraint>,
+    fields: &mut Vec<RangedField<'a>>,
+) {
+    let nested_schema = |name: &str| match checker.field_type(schema, name)? {
+        Type::Schema(inner) => Some(inner.clone()),
+        Type::Optional(inner) => match inner.as_ref() {
+            Type::Schema(inner) => Some(inner.clone()),
+            _ => None,
+        },
+        _ => None,
+    };
+    for item in items {
+        match item {
+            BodyItem::Let(binding) => match &binding.value {
+                Expr::Annotated(annotated) if binding.pattern.is_none() => {
+                    lets.insert(binding.name.as_str(), &annotated.constraint);
+                }
+                _ => {
+                    for name in binding.names() {
+                        lets.remove(name);
+                    }
+                }
+            },
+            BodyItem::KeyValue(kv) => {
+                let (Key::Ident(name) | Key::String(name)) = &kv.key else {
+                    continue;
+                };
+                let constraint = match &kv.value {
+                    Expr::Annotated(annotated) => Some(&annotated.constraint),
+                    Expr::Ident(var, _) => lets.get(var.as_str()).copied(),
+                    Expr::Object(obj) => {
+                        if let Some(inner) = nested_schema(name) {
+                            collect_ranged_fields(
+                                checker,
+                                &obj.items,
+                                &inner,
+                                lets.clone(),
+                                fields,
+                            );
+                        }
+                        None
+                    }
+                    _ => None,
+                };
+                let (Some(constraint), Some(field_type)) =
+                    (constraint, checker.field_type(schema, name))
+                else {
+                    continue;
+                };
+                if let Ok(annotation) = checker.parse_type_constraint(constraint) {
+                    fields.push(RangedField {
+                        name: format!("{}.{}", schema, name),
+                        field_type: field_type.clone(),
+                        annotation,
+                        location: &kv.location,
+                    });
+                }
+            }
+            BodyItem::Block(block) => {
+                if let Some(inner) = nested_schema(&block.name) {
+                    collect_ranged_fields(checker, &block.items, &inner, lets.clone(), fields);
+                }
+            }
+            BodyItem::When(when) => {
+                let mut branch = Some(when);
+                while let Some(w) = branch {
+                    collect_ranged_fields(checker, &w.body, schema, lets.clone(), fields);
+                    branch = match &w.else_branch {
+                        Some(ElseBranch::ElseWhen(next)) => Some(next),
+                        Some(ElseBranch::Else(body, _)) => {
+                            collect_ranged_fields(checker, body, schema, lets.clone(), fields);
+                            None
+                        }
+                        None => None,
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Split a condition like `args.env == "dev" || args.env == Env.staging` into
+/// (subject, value, enum named by an `Enum.value` operand) arms. Returns
+/// false if any part is not such a comparison.
+fn enum_comparisons<'a>(
+    expr: &Expr,
+    enums: &HashMap<&'a str, &[String]>,
+    arms: &mut Vec<(String, String, Option<&'a str>)>,
+) -> bool {
+    let Expr::Binary(b) = expr else {
+        return false;
+    };
+    match b.op {
+        BinaryOp::Or => {
+            enum_comparisons(&b.left, enums, arms) && enum_comparisons(&b.right, enums, arms)
+        }
+        BinaryOp::Eq => {
+            let value = |e: &Expr| -> Option<(String, Option<&'a str>)> {
+                match e {
+                    Expr::String(s) => s.as_literal().map(|v| (v, None)),
+                    Expr::Path(p) => match p.parts.as_slice() {
+                        [PathPart::Ident(name), PathPart::Ident(member)] => enums
+                            .get_key_value(name.as_str())
+                            .map(|(name, _)| (member.clone(), Some(*name))),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            };
+            let subject = |e: &Expr| matches!(e, Expr::Ident(..) | Expr::Path(_));
+            let arm = match (value(&b.left), value(&b.right)) {
+                (None, Some((v, named))) if subject(&b.left) => (b.left.display(), v, named),
+                (Some((v, named)), None) if subject(&b.right) => (b.right.display(), v, named),
+                _ => return false,
+            };
+            arms.push(arm);
+            true
+        }
+        _ => false,
+    }
+}
+
 /// Build an args object from CLI --set, --set-file, and --set-string flags.
 pub fn build_args_object(
     set: &[(String, String)],
@@ -897,6 +2128,16 @@ pub fn validate_against_schema(
         &empty_map,
     );
 
+    schema_errors(&source, &location, errors)
+}
+
+/// Turn the errors of checking a value against a schema into a result: a
+/// single error is returned as is, several are grouped
+fn schema_errors(
+    source: &str,
+    location: &SourceLocation,
+    errors: Vec<HoneError>,
+) -> HoneResult<()> {
     if errors.is_empty() {
         Ok(())
     } else if errors.len() == 1 {
@@ -904,7 +2145,7 @@ pub fn validate_against_schema(
     } else {
         let count = errors.len();
         Err(HoneError::SchemaValidationErrors {
-            src: source,
+            src: source.to_string(),
             span: (location.offset, location.length).into(),
             count,
             s: if count == 1 {
@@ -917,6 +2158,121 @@ pub fn validate_against_schema(
     }
 }
 
+/// An `expect args.*` declaration, as shown to a user being prompted for it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpectedArg {
+    /// Dotted key under `args` (e.g. `db.host` for `expect args.db.host`)
+    pub key: String,
+    /// Declared type name (string, int, float, bool, any)
+    pub type_name: String,
+    /// Source text of the default value, if any
+    pub default: Option<String>,
+    /// Comment lines directly above the declaration
+    pub description: Option<String>,
+}
+
+/// A variant dimension and its cases
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariantChoice {
+    pub name: String,
+    pub cases: Vec<String>,
+    pub default: Option<String>,
+}
+
+/// The command-line inputs a file (and everything it imports or inherits
+/// from) can take: `expect args.*` declarations and variant dimensions.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompileInputs {
+    pub args: Vec<ExpectedArg>,
+    pub variants: Vec<VariantChoice>,
+}
+
+/// Collect the `expect` declarations and variants of a file's import closure,
+/// dependencies first. Duplicates (same key or variant name) keep the first.
+pub fn collect_inputs(path: impl AsRef<Path>) -> HoneResult<CompileInputs> {
+    let path = path.as_ref();
+    let canonical = crate::resolver::canonicalize(path).map_err(|e| {
+        HoneError::io_error(format!("failed to resolve path {}: {}", path.display(), e))
+    })?;
+    let base_dir = canonical.parent().unwrap_or(Path::new("."));
+
+    let mut resolver = ImportResolver::new(base_dir);
+    let mut seen = std::collections::HashSet::new();
+    let mut pending = vec![canonical.clone()];
+    while let Some(next) = pending.pop() {
+        if !seen.insert(next.clone()) {
+            continue;
+        }
+        let resolved = resolver.resolve(&next)?;
+        pending.extend(resolved.from_path.iter().cloned());
+        pending.extend(resolved.import_paths.iter().cloned());
+        pending.extend(resolved.include_paths.iter().cloned());
+    }
+
+    let mut inputs = CompileInputs::default();
+    for file in resolver.topological_order(&canonical)? {
+        for item in &file.ast.preamble {
+            match item {
+                PreambleItem::Expect(expect) => {
+                    let key = expect.path.iter().skip(1).cloned().collect::<Vec<_>>();
+                    let key = key.join(".");
+                    if key.is_empty() || inputs.args.iter().any(|a| a.key == key) {
+                        continue;
+                    }
+                    let start = expect.location.offset;
+                    let text = &file.source[start..start + expect.location.length];
+                    inputs.args.push(ExpectedArg {
+                        key,
+                        type_name: expect.type_name.clone(),
+                        default: expect
+                            .default
+                            .as_ref()
+                            .and_then(|_| text.split_once('='))
+                            .map(|(_, d)| d.trim().to_string()),
+                        description: leading_comment(&file.source, start),
+                    });
+                }
+                PreambleItem::Variant(variant) => {
+                    if inputs.variants.iter().any(|v| v.name == variant.name) {
+                        continue;
+                    }
+                    inputs.variants.push(VariantChoice {
+                        name: variant.name.clone(),
+                        cases: variant.cases.iter().map(|c| c.name.clone()).collect(),
+                        default: variant
+                            .cases
+                            .iter()
+                            .find(|c| c.is_default)
+                            .map(|c| c.name.clone()),
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(inputs)
+}
+
+/// The `#` comment block immediately above the line containing `offset`
+fn leading_comment(source: &str, offset: usize) -> Option<String> {
+    let line_start = source[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let mut lines: Vec<&str> = source[..line_start]
+        .lines()
+        .rev()
+        .map(str::trim)
+        .take_while(|l| l.starts_with('#'))
+        .map(|l| l.trim_start_matches('#').trim())
+        .collect();
+    lines.reverse();
+    let text = lines.join(" ");
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -934,70 +2290,290 @@ mod tests {
     }
 
     #[test]
-    fn test_compile_single_file() {
+    fn test_collect_inputs_across_from_and_imports() {
+        let dir = TempDir::new().unwrap();
+        create_test_files(
+            dir.path(),
+            &[
+                (
+                    "base.hone",
+                    "# Region to deploy into\nexpect args.region: string = \"us-east-1\"\n\nvariant env {\n  default dev {\n  }\n  prod {\n  }\n}\n\nregion: args.region\n",
+                ),
+                ("lib.hone", "expect args.db.port: int\nlet x = 1\n"),
+                (
+                    "main.hone",
+                    "from \"./base.hone\"\nimport \"./lib.hone\" as lib\n# Image tag\n# (required)\nexpect args.tag: string\nexpect args.region: string\n\nvariant size {\n  small {\n  }\n  large {\n  }\n}\n\ntag: args.tag\n",
+                ),
+            ],
+        );
+
+        let inputs = collect_inputs(dir.path().join("main.hone")).unwrap();
+        let region = inputs.args.iter().find(|a| a.key == "region").unwrap();
+        assert_eq!(region.default.as_deref(), Some("\"us-east-1\""));
+        assert_eq!(region.description.as_deref(), Some("Region to deploy into"));
+        let tag = inputs.args.iter().find(|a| a.key == "tag").unwrap();
+        assert_eq!(tag.default, None);
+        assert_eq!(tag.description.as_deref(), Some("Image tag (required)"));
+        let port = inputs.args.iter().find(|a| a.key == "db.port").unwrap();
+        assert_eq!(port.type_name, "int");
+        assert_eq!(inputs.args.len(), 3);
+
+        assert_eq!(
+            inputs.variants,
+            vec![
+                VariantChoice {
+                    name: "env".into(),
+                    cases: vec!["dev".into(), "prod".into()],
+                    default: Some("dev".into()),
+                },
+                VariantChoice {
+                    name: "size".into(),
+                    cases: vec!["small".into(), "large".into()],
+                    default: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_single_file() {
+        let dir = TempDir::new().unwrap();
+        create_test_files(
+            dir.path(),
+            &[(
+                "main.hone",
+                r#"
+let name = "test"
+greeting: "Hello, ${name}!"
+"#,
+            )],
+        );
+
+        let result = compile_file(dir.path().join("main.hone")).unwrap();
+
+        if let Value::Object(obj) = result {
+            assert_eq!(
+                obj.get("greeting"),
+                Some(&Value::String("Hello, test!".into()))
+            );
+        } else {
+            panic!("Expected object");
+        }
+    }
+
+    #[test]
+    fn test_compile_with_import() {
+        let dir = TempDir::new().unwrap();
+        create_test_files(
+            dir.path(),
+            &[
+                (
+                    "config.hone",
+                    r#"
+let version = "1.0.0"
+let port = 8080
+"#,
+                ),
+                (
+                    "main.hone",
+                    r#"
+import "./config.hone" as config
+
+app {
+    version: config.version
+    port: config.port
+}
+"#,
+                ),
+            ],
+        );
+
+        let result = compile_file(dir.path().join("main.hone")).unwrap();
+
+        if let Value::Object(obj) = result {
+            if let Some(Value::Object(app)) = obj.get("app") {
+                assert_eq!(app.get("version"), Some(&Value::String("1.0.0".into())));
+                assert_eq!(app.get("port"), Some(&Value::Int(8080)));
+            } else {
+                panic!("Expected app object");
+            }
+        } else {
+            panic!("Expected object");
+        }
+    }
+
+    #[test]
+    fn test_compile_include_splices_fragment() {
+        let dir = TempDir::new().unwrap();
+        create_test_files(
+            dir.path(),
+            &[
+                (
+                    "probes.hone",
+                    r#"
+let probe_path = "/healthz"
+
+liveness {
+    path: probe_path
+    port: port
+}
+readiness { path: probe_path }
+"#,
+                ),
+                (
+                    "main.hone",
+                    r#"
+let port = 8080
+
+web {
+    image: "web"
+    include "./probes.hone"
+}
+worker {
+    when port > 0 {
+        include "./probes.hone"
+    }
+}
+"#,
+                ),
+            ],
+        );
+
+        let mut compiler = Compiler::new(dir.path());
+        let result = compiler.compile(dir.path().join("main.hone")).unwrap();
+        let Value::Object(obj) = result else {
+            panic!("Expected object");
+        };
+        let Some(Value::Object(web)) = obj.get("web") else {
+            panic!("Expected web object");
+        };
+        assert_eq!(web.get("image"), Some(&Value::String("web".into())));
+        let Some(Value::Object(liveness)) = web.get("liveness") else {
+            panic!("Expected liveness object");
+        };
+        assert_eq!(liveness.get("port"), Some(&Value::Int(8080)));
+        assert_eq!(
+            liveness.get("path"),
+            Some(&Value::String("/healthz".into()))
+        );
+        let Some(Value::Object(worker)) = obj.get("worker") else {
+            panic!("Expected worker object");
+        };
+        assert!(worker.contains_key("readiness"));
+        // The fragment's items are spliced in, not compiled on their own
+        assert!(!obj.contains_key("liveness"));
+        // `port` is read by the fragment, so it is not unused
+        assert!(compiler.warnings().iter().all(|w| w.code != "unused-let"));
+    }
+
+    #[test]
+    fn test_error_include_fragment_with_import() {
+        let dir = TempDir::new().unwrap();
+        create_test_files(
+            dir.path(),
+            &[
+                ("shared.hone", "let x = 1\n"),
+                (
+                    "fragment.hone",
+                    "import \"./shared.hone\" as shared\nx: shared.x\n",
+                ),
+                ("main.hone", "app {\n    include \"./fragment.hone\"\n}\n"),
+            ],
+        );
+
+        let err = compile_file(dir.path().join("main.hone")).unwrap_err();
+        assert!(
+            matches!(&err, HoneError::InvalidFragment { found, .. } if found == "an `import`"),
+            "expected invalid fragment, got: {:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_error_include_inside_expression() {
+        let dir = TempDir::new().unwrap();
+        create_test_files(
+            dir.path(),
+            &[
+                ("fragment.hone", "x: 1\n"),
+                ("main.hone", "app: {\n    include \"./fragment.hone\"\n}\n"),
+            ],
+        );
+
+        let err = compile_file(dir.path().join("main.hone")).unwrap_err();
+        assert!(
+            matches!(err, HoneError::IncludeNotAllowed { .. }),
+            "expected include not allowed, got: {:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_error_include_cycle() {
         let dir = TempDir::new().unwrap();
         create_test_files(
             dir.path(),
-            &[(
-                "main.hone",
-                r#"
-let name = "test"
-greeting: "Hello, ${name}!"
-"#,
-            )],
+            &[
+                ("a.hone", "include \"./b.hone\"\n"),
+                ("b.hone", "include \"./a.hone\"\n"),
+                ("main.hone", "include \"./a.hone\"\n"),
+            ],
         );
 
-        let result = compile_file(dir.path().join("main.hone")).unwrap();
-
-        if let Value::Object(obj) = result {
-            assert_eq!(
-                obj.get("greeting"),
-                Some(&Value::String("Hello, test!".into()))
-            );
-        } else {
-            panic!("Expected object");
-        }
+        let err = compile_file(dir.path().join("main.hone")).unwrap_err();
+        assert!(
+            matches!(err, HoneError::CircularImport { .. }),
+            "expected circular import, got: {:?}",
+            err
+        );
     }
 
     #[test]
-    fn test_compile_with_import() {
+    fn test_compile_many_shares_imports() {
         let dir = TempDir::new().unwrap();
         create_test_files(
             dir.path(),
             &[
+                ("shared.hone", "let port = 8080\n"),
                 (
-                    "config.hone",
-                    r#"
-let version = "1.0.0"
-let port = 8080
-"#,
+                    "a.hone",
+                    "import { port } from \"./shared.hone\"\nname: \"a\"\nport: port\n",
                 ),
                 (
-                    "main.hone",
-                    r#"
-import "./config.hone" as config
-
-app {
-    version: config.version
-    port: config.port
-}
-"#,
+                    "b.hone",
+                    "import { port } from \"./shared.hone\"\nname: \"b\"\nport: port\n",
                 ),
+                ("mid.hone", "import \"./broken.hone\" as broken\nx: 1\n"),
+                ("broken.hone", "x: {\n"),
+                ("c.hone", "import \"./mid.hone\" as mid\ny: 2\n"),
             ],
         );
 
-        let result = compile_file(dir.path().join("main.hone")).unwrap();
+        let roots: Vec<PathBuf> = ["a.hone", "c.hone", "mid.hone", "b.hone"]
+            .iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        let mut compiler = Compiler::new(dir.path());
+        let results = compiler.compile_many(&roots);
 
-        if let Value::Object(obj) = result {
-            if let Some(Value::Object(app)) = obj.get("app") {
-                assert_eq!(app.get("version"), Some(&Value::String("1.0.0".into())));
-                assert_eq!(app.get("port"), Some(&Value::Int(8080)));
-            } else {
-                panic!("Expected app object");
-            }
-        } else {
-            panic!("Expected object");
-        }
+        assert_eq!(results.keys().cloned().collect::<Vec<_>>(), roots);
+        let port = |path: &PathBuf| match &results[path] {
+            Ok(Value::Object(obj)) => obj.get("port").cloned(),
+            other => panic!("expected object, got {:?}", other),
+        };
+        assert_eq!(port(&roots[0]), Some(Value::Int(8080)));
+        assert_eq!(port(&roots[3]), Some(Value::Int(8080)));
+
+        // A failed root leaves the resolver usable: `mid` reports its own
+        // parse error, not a bogus import cycle
+        assert!(results[&roots[1]].is_err());
+        assert!(matches!(
+            results[&roots[2]],
+            Err(HoneError::UnexpectedToken { .. })
+        ));
+
+        // shared.hone was evaluated once for both roots
+        assert_eq!(compiler.compiled.len(), 3);
     }
 
     #[test]
@@ -1194,6 +2770,141 @@ port: "8080"
         assert!(matches!(err, HoneError::TypeMismatch { .. }));
     }
 
+    #[test]
+    fn test_schema_import_named_and_namespaced() {
+        let dir = TempDir::new().unwrap();
+        create_test_files(
+            dir.path(),
+            &[
+                (
+                    "schemas.hone",
+                    r#"
+schema Server {
+    host: string
+    port: int
+}
+"#,
+                ),
+                (
+                    "named.hone",
+                    r#"
+import { schema Server as S } from "./schemas.hone"
+
+use S
+
+host: "localhost"
+port: "8080"
+"#,
+                ),
+                (
+                    "namespaced.hone",
+                    r#"
+import "./schemas.hone" as config
+
+use config.Server
+
+host: "localhost"
+port: 8080
+"#,
+                ),
+                (
+                    "missing.hone",
+                    r#"
+import { schema Database } from "./schemas.hone"
+
+host: "localhost"
+"#,
+                ),
+            ],
+        );
+
+        let err = compile_file(dir.path().join("named.hone")).unwrap_err();
+        assert!(matches!(err, HoneError::TypeMismatch { .. }));
+
+        assert!(compile_file(dir.path().join("namespaced.hone")).is_ok());
+
+        let err = compile_file(dir.path().join("missing.hone")).unwrap_err();
+        assert!(matches!(err, HoneError::UndefinedVariable { ref name, .. } if name == "Database"));
+    }
+
+    #[test]
+    fn test_conditional_use_in_when_and_variant() {
+        let dir = TempDir::new().unwrap();
+        create_test_files(
+            dir.path(),
+            &[
+                (
+                    "when.hone",
+                    r#"
+schema ProdServer {
+    host: string
+    replicas: int(3, 10)
+}
+
+let env = args.env
+
+when env == "prod" {
+    use ProdServer
+}
+
+host: "localhost"
+replicas: 1
+"#,
+                ),
+                (
+                    "variant.hone",
+                    r#"
+schema ProdServer {
+    replicas: int(3, 10)
+}
+
+variant env {
+    default dev {
+        replicas: 1
+    }
+    prod {
+        use ProdServer
+        replicas: 1
+    }
+}
+"#,
+                ),
+                (
+                    "nested.hone",
+                    r#"
+schema Server {
+    port: int
+}
+
+server {
+    use Server
+    port: 8080
+}
+"#,
+                ),
+            ],
+        );
+
+        let path = dir.path().join("when.hone");
+        let mut args = IndexMap::new();
+        args.insert("env".to_string(), Value::String("dev".into()));
+        assert!(compile_file_with_args(&path, Value::Object(args.clone())).is_ok());
+        args.insert("env".to_string(), Value::String("prod".into()));
+        let err = compile_file_with_args(&path, Value::Object(args)).unwrap_err();
+        assert!(matches!(err, HoneError::ValueOutOfRange { .. }));
+
+        assert!(compile_file(dir.path().join("variant.hone")).is_ok());
+        let mut compiler = Compiler::new(dir.path());
+        compiler.set_variants(HashMap::from([("env".to_string(), "prod".to_string())]));
+        let err = compiler
+            .compile(dir.path().join("variant.hone"))
+            .unwrap_err();
+        assert!(matches!(err, HoneError::ValueOutOfRange { .. }));
+
+        let err = compile_file(dir.path().join("nested.hone")).unwrap_err();
+        assert!(matches!(err, HoneError::UseNotAtTopLevel { .. }));
+    }
+
     #[test]
     fn test_schema_validation_missing_field() {
         let dir = TempDir::new().unwrap();
@@ -1588,7 +3299,11 @@ port: 8080
         assert_eq!(infer_value("42"), Value::Int(42));
         assert_eq!(infer_value("-17"), Value::Int(-17));
         assert_eq!(infer_value("0"), Value::Int(0));
-        assert_eq!(infer_value("3.14"), Value::Float(3.14));
+        assert_eq!(infer_value("3.25"), Value::Float(3.25));
+        assert_eq!(infer_value("2.0"), Value::Float(2.0));
+        assert_eq!(infer_value("0755"), Value::String("0755".into()));
+        assert_eq!(infer_value("1.50"), Value::String("1.50".into()));
+        assert_eq!(infer_value("+5"), Value::String("+5".into()));
         assert_eq!(infer_value("hello"), Value::String("hello".into()));
         assert_eq!(infer_value(""), Value::String("".into()));
         assert_eq!(
@@ -1817,6 +3532,50 @@ host: "localhost"
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_check_schema_from_imports() {
+        let dir = TempDir::new().unwrap();
+        create_test_files(
+            dir.path(),
+            &[
+                (
+                    "schemas.hone",
+                    r#"
+schema Server {
+    host: string
+    port: int
+}
+"#,
+                ),
+                (
+                    "main.hone",
+                    r#"
+import "./schemas.hone" as s
+import { schema Server as S } from "./schemas.hone"
+host: "localhost"
+port: 8080
+"#,
+                ),
+            ],
+        );
+
+        let mut compiler = Compiler::new(dir.path());
+        let value = compiler.compile(dir.path().join("main.hone")).unwrap();
+        assert!(compiler.check_schema(&value, "Server").is_ok());
+        assert!(compiler.check_schema(&value, "s.Server").is_ok());
+        assert!(compiler.check_schema(&value, "S").is_ok());
+        assert!(compiler.check_schema(&value, "Missing").is_err());
+    }
+
+    #[test]
+    fn test_check_schema_compiled_source() {
+        let mut compiler = Compiler::new(".");
+        let value = compiler
+            .compile_source("schema Server {\n    port: int\n}\nport: \"80\"\n")
+            .unwrap();
+        assert!(compiler.check_schema(&value, "Server").is_err());
+    }
+
     #[test]
     fn test_unchecked_suppresses_type_error() {
         let dir = TempDir::new().unwrap();
@@ -1873,6 +3632,150 @@ port: 99999 @unchecked
             warnings[0].message.contains("port"),
             "warning should mention the field name"
         );
+        assert_eq!(warnings[0].code, "unchecked");
+        assert_eq!(warnings[0].line, 8, "warning should point at the key");
+    }
+
+    #[test]
+    fn test_enum_when_warnings() {
+        let source = r#"
+enum Env { dev, staging, prod }
+
+expect args.env: Env = "dev"
+
+when args.env == "dev" {
+    replicas: 1
+} else when args.env == "prd" {
+    replicas: 3
+}
+
+when Env.dev == args.env || args.env == Env.staging {
+    debug: true
+} else {
+    debug: false
+}
+"#;
+        let mut compiler = Compiler::new(".");
+        compiler.compile_source(source).unwrap();
+        let warnings: Vec<(&str, &str)> = compiler
+            .warnings()
+            .iter()
+            .map(|w| (w.code.as_str(), w.message.as_str()))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                ("unknown-enum-value", "'prd' is not a value of enum Env"),
+                (
+                    "non-exhaustive-when",
+                    "when chain on args.env does not handle staging, prod"
+                ),
+            ]
+        );
+        assert_eq!(compiler.warnings()[1].line, 6);
+    }
+
+    #[test]
+    fn test_constraint_range_warnings() {
+        let source = r#"
+schema Tls {
+    port: int(1024, 2048)
+}
+schema Server {
+    port: int(1024, 2048)
+    weight: float(0.0, 1.0)
+    retries: int(0, 10)
+    timeout: int
+    tls: Tls
+}
+use Server
+
+let port = 1500 @int(1, 65535)
+
+port: port
+weight: 0.5 @float(2.0, 3.0)
+retries: 3 @int(1, 5)
+timeout: 30 @int(1, 60)
+tls {
+    port: 1500 @int(1024, 4096)
+}
+"#;
+        let mut compiler = Compiler::new(".");
+        compiler.compile_source(source).unwrap();
+        let warnings: Vec<(&str, &str)> = compiler
+            .warnings()
+            .iter()
+            .map(|w| (w.code.as_str(), w.message.as_str()))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                (
+                    "constraint-overlap",
+                    "@int(1, 65535) allows values that Server.port: int(1024, 2048) rejects"
+                ),
+                (
+                    "impossible-constraint",
+                    "no value satisfies both @float(2, 3) and Server.weight: float(0, 1)"
+                ),
+                (
+                    "always-true-constraint",
+                    "Server.retries: int(0, 10) always holds for a value annotated @int(1, 5)"
+                ),
+                (
+                    "constraint-overlap",
+                    "@int(1024, 4096) allows values that Tls.port: int(1024, 2048) rejects"
+                ),
+            ]
+        );
+        assert_eq!(compiler.warnings()[0].line, 16);
+    }
+
+    #[test]
+    fn test_unused_let_warning() {
+        let dir = TempDir::new().unwrap();
+        create_test_files(
+            dir.path(),
+            &[
+                (
+                    "lib.hone",
+                    "let exported = 1
+",
+                ),
+                (
+                    "main.hone",
+                    r#"
+import "./lib.hone" as lib
+
+let used = 1
+let unused = 2
+let _ignored = 3
+
+server {
+    let inner = "x"
+    port: used + lib.exported
+}
+"#,
+                ),
+            ],
+        );
+
+        let canonical = dir.path().join("main.hone").canonicalize().unwrap();
+        let mut compiler = Compiler::new(dir.path());
+        compiler.compile(&canonical).unwrap();
+
+        let unused: Vec<&Warning> = compiler
+            .warnings()
+            .iter()
+            .filter(|w| w.code == "unused-let")
+            .collect();
+        let messages: Vec<&str> = unused.iter().map(|w| w.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec!["unused variable 'unused'", "unused variable 'inner'"]
+        );
+        assert_eq!(unused[0].line, 5);
+        assert_eq!(unused[0].fix.as_ref().unwrap().replacement, "");
     }
 
     #[test]
@@ -2249,8 +4152,7 @@ val: missing.x
         let result = compile_file(dir.path().join("main.hone"));
         // This should either error with FromInPreamble or behave in some documented way
         // We're testing to document the actual behavior
-        if result.is_err() {
-            let err = result.unwrap_err();
+        if let Err(err) = result {
             assert!(
                 matches!(err, HoneError::FromInPreamble { .. }),
                 "expected FromInPreamble, got: {:?}",
@@ -2413,4 +4315,59 @@ b: b_version
         assert_eq!(result.get_path(&["a"]), Some(&Value::String("1.0".into())));
         assert_eq!(result.get_path(&["b"]), Some(&Value::String("1.0".into())));
     }
+
+    #[test]
+    fn test_policy_calls_cached_across_compiles() {
+        let source = r#"
+let limits = [3, 5, 8]
+fn total(xs) { reduce(xs, 0, (acc, x) => acc + x) }
+
+policy cap deny when len(output.items) > total(limits) { "too many items" }
+policy size warn when len(output.items) > len(limits) && len(keys(output)) > 0 { "large" }
+
+items: [1, 2]
+"#;
+        let cache = PolicyCache::new();
+        let compile = |edit: &str| {
+            let mut compiler = Compiler::new(".");
+            compiler.set_policy_cache(cache.clone());
+            compiler
+                .compile_source(&source.replace("[1, 2]", edit))
+                .unwrap();
+            compiler.policy_timings().to_vec()
+        };
+
+        let first = compile("[1, 2]");
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0].name, "cap");
+        assert!(first.iter().all(|t| t.cached_calls == 0));
+        // total(limits) and len(limits) do not read output, so an edit to
+        // the output reuses them; the calls reading output are evaluated again
+        let second = compile("[1, 2, 4]");
+        assert_eq!(second[0].cached_calls, 1);
+        assert_eq!(second[1].cached_calls, 1);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_policy_timeout() {
+        let mut compiler = Compiler::new(".");
+        compiler.set_policy_cache(PolicyCache::new());
+        compiler.set_policy_timeout(Some(Duration::from_millis(1)));
+        let err = compiler
+            .compile_source(
+                r#"
+fn spin(n) { len([for i in range(0, n) { for j in range(0, n) { i * j } }]) }
+policy slow warn when spin(3000) < 0 && output.x > 0 { "never" }
+x: 1
+"#,
+            )
+            .unwrap_err();
+        assert!(
+            format!("{:?}", err).contains("policy 'slow' still running"),
+            "{:?}",
+            err
+        );
+        assert_eq!(compiler.policy_timings().len(), 1);
+    }
 }
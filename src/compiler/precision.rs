@@ -0,0 +1,131 @@
+//! Output numbers a reader may not get back exactly
+//!
+//! Floats are binary, so most decimals are stored as the nearest double and
+//! `0.1 + 0.2` is `0.30000000000000004`. Every emitter writes the shortest
+//! text that reads back as the same double, which for such a value takes 16
+//! or 17 significant digits; only 15 survive every reader, so those floats
+//! get a `float-precision` warning. JSON readers commonly parse every number
+//! as a double, so in the JSON formats ints beyond 2^53 get `int-precision`.
+
+use crate::emitter::OutputFormat;
+use crate::evaluator::Value;
+
+/// Significant digits any reader of a double reproduces
+pub const FLOAT_DIGITS: usize = 15;
+
+/// Largest magnitude at which every int is exactly a double
+pub const MAX_EXACT_INT: i64 = 1 << 53;
+
+/// A number in the output that may not round-trip
+#[derive(Debug, Clone, PartialEq)]
+pub enum Imprecise {
+    Float(f64),
+    Int(i64),
+}
+
+/// Every number in `value` that may not round-trip through `format`, with
+/// its dot-path (array elements by index)
+pub fn imprecise_numbers(value: &Value, format: OutputFormat) -> Vec<(String, Imprecise)> {
+    let mut found = Vec::new();
+    collect(value, format, &mut String::new(), &mut found);
+    found
+}
+
+fn collect(
+    value: &Value,
+    format: OutputFormat,
+    path: &mut String,
+    found: &mut Vec<(String, Imprecise)>,
+) {
+    match value {
+        Value::Float(n) if n.is_finite() && significant_digits(*n) > FLOAT_DIGITS => {
+            found.push((path.clone(), Imprecise::Float(*n)));
+        }
+        Value::Int(n) if reads_as_double(format) && n.unsigned_abs() > MAX_EXACT_INT as u64 => {
+            found.push((path.clone(), Imprecise::Int(*n)));
+        }
+        Value::Object(obj) => {
+            for (key, v) in obj {
+                let len = path.len();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key);
+                collect(v, format, path, found);
+                path.truncate(len);
+            }
+        }
+        Value::Array(arr) => {
+            for (i, v) in arr.iter().enumerate() {
+                let len = path.len();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(&i.to_string());
+                collect(v, format, path, found);
+                path.truncate(len);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Digits in the shortest decimal that reads back as `n`
+pub fn significant_digits(n: f64) -> usize {
+    let shortest = format!("{:e}", n);
+    let mantissa = shortest.split('e').next().unwrap_or_default();
+    mantissa.chars().filter(char::is_ascii_digit).count()
+}
+
+/// Whether readers of `format` commonly parse numbers as doubles
+fn reads_as_double(format: OutputFormat) -> bool {
+    matches!(
+        format,
+        OutputFormat::Json
+            | OutputFormat::JsonPretty
+            | OutputFormat::JsonCanonical
+            | OutputFormat::Jsonc
+            | OutputFormat::Ndjson
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    #[test]
+    fn test_significant_digits() {
+        assert_eq!(significant_digits(0.1), 1);
+        assert_eq!(significant_digits(1e300), 1);
+        assert_eq!(significant_digits(3.25), 3);
+        assert_eq!(significant_digits(0.1 + 0.2), 17);
+        assert_eq!(significant_digits(-2.0 / 3.0), 16);
+    }
+
+    #[test]
+    fn test_imprecise_numbers() {
+        let mut obj = IndexMap::new();
+        obj.insert("exact".to_string(), Value::Float(0.25));
+        obj.insert(
+            "prices".to_string(),
+            Value::Array(vec![Value::Float(1.5), Value::Float(0.1 + 0.2)]),
+        );
+        obj.insert("id".to_string(), Value::Int(MAX_EXACT_INT + 1));
+        obj.insert("count".to_string(), Value::Int(MAX_EXACT_INT));
+        let value = Value::Object(obj);
+
+        assert_eq!(
+            imprecise_numbers(&value, OutputFormat::Json),
+            vec![
+                ("prices.1".to_string(), Imprecise::Float(0.1 + 0.2)),
+                ("id".to_string(), Imprecise::Int(MAX_EXACT_INT + 1)),
+            ]
+        );
+        // YAML and TOML readers keep 64-bit ints
+        assert_eq!(
+            imprecise_numbers(&value, OutputFormat::Yaml),
+            vec![("prices.1".to_string(), Imprecise::Float(0.1 + 0.2))]
+        );
+    }
+}
@@ -0,0 +1,385 @@
+//! Source maps for `compile --source-map`
+//!
+//! A source map pairs each output key with the Hone file, line and column
+//! that set it and, in YAML, pretty JSON and JSONC, with the line and column
+//! where the key is written, so an editor can jump from a line of rendered
+//! output to the expression behind it, and from a source line to the output
+//! it produced.
+//!
+//! Output positions are found by emitting the value again with a marker
+//! comment above each key and noting the line after each marker. With the
+//! markers taken out, that text must match the real output; if it doesn't,
+//! the map gives source positions only. Paths are dot-paths without array
+//! indices, as for doc comments, so a key inside an array of objects maps to
+//! where it is written in the first element.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use indexmap::IndexMap;
+
+use crate::emitter::{emit, emit_with, EmitOptions, OutputFormat};
+use crate::errors::HoneResult;
+use crate::evaluator::{DocMap, LocationMap, Value};
+use crate::lexer::token::SourceLocation;
+
+/// Version of the source map layout, bumped when fields change meaning
+pub const SOURCE_MAP_VERSION: i64 = 1;
+
+/// Starts each marker comment; a private-use character, so it can't be
+/// mistaken for a comment a user wrote
+const MARKER: char = '\u{E000}';
+
+/// A 1-based line and column in the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Where one output key is written and where it was set
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mapping {
+    /// Dot-path of the key
+    pub path: String,
+    /// Where the key is written, for formats that report it
+    pub output: Option<Position>,
+    pub source: SourceLocation,
+}
+
+/// Every output key of one compiled file with its output and source position
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceMap {
+    pub format: OutputFormat,
+    /// Ordered by output position; keys without one come last, by path
+    pub mappings: Vec<Mapping>,
+}
+
+impl SourceMap {
+    /// Map `output`, the text `value` was emitted as with `options`, back to
+    /// `locations`. Locations of keys that are not in the output are skipped.
+    pub fn new(
+        value: &Value,
+        format: OutputFormat,
+        options: &EmitOptions,
+        output: &str,
+        locations: &LocationMap,
+    ) -> Self {
+        let value = if options.omit_nulls {
+            value.without_nulls()
+        } else {
+            value.clone()
+        };
+        let mut paths: Vec<&String> = locations
+            .keys()
+            .filter(|path| has_path(&value, &path.split('.').collect::<Vec<_>>()))
+            .collect();
+        paths.sort();
+
+        let positions = output_positions(&value, format, options, output, &paths);
+        let mut mappings: Vec<Mapping> = paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| Mapping {
+                path: path.to_string(),
+                output: positions.get(&i).copied(),
+                source: locations[*path].clone(),
+            })
+            .collect();
+        mappings.sort_by_key(|m| (m.output.is_none(), m.output));
+        SourceMap { format, mappings }
+    }
+
+    /// Move every output position down by `lines`, for a header written
+    /// above the emitted text
+    pub fn offset_lines(&mut self, lines: usize) {
+        for mapping in &mut self.mappings {
+            if let Some(position) = mapping.output.as_mut() {
+                position.line += lines;
+            }
+        }
+    }
+
+    /// The key written on or most recently above output `line`
+    pub fn source_at(&self, line: usize) -> Option<&Mapping> {
+        self.mappings
+            .iter()
+            .filter(|m| m.output.is_some_and(|p| p.line <= line))
+            .max_by_key(|m| m.output)
+    }
+
+    /// The keys set on `line` of `file` (`None` for source read from stdin)
+    pub fn outputs_at(&self, file: Option<&Path>, line: usize) -> Vec<&Mapping> {
+        self.mappings
+            .iter()
+            .filter(|m| m.source.file.as_deref() == file && m.source.line == line)
+            .collect()
+    }
+
+    /// The map as a value, with source files relative to `base_dir`
+    pub fn to_value(&self, base_dir: &Path) -> Value {
+        let position = |line: usize, column: usize| {
+            let mut obj = IndexMap::new();
+            obj.insert("line".to_string(), Value::Int(line as i64));
+            obj.insert("column".to_string(), Value::Int(column as i64));
+            obj
+        };
+        let mappings = self
+            .mappings
+            .iter()
+            .map(|m| {
+                let file = match &m.source.file {
+                    Some(file) => file
+                        .strip_prefix(base_dir)
+                        .unwrap_or(file)
+                        .display()
+                        .to_string(),
+                    None => "<stdin>".to_string(),
+                };
+                let mut source = IndexMap::new();
+                source.insert("file".to_string(), Value::String(file));
+                source.extend(position(m.source.line, m.source.column));
+
+                let mut obj = IndexMap::new();
+                obj.insert("path".to_string(), Value::String(m.path.clone()));
+                obj.insert(
+                    "output".to_string(),
+                    match m.output {
+                        Some(p) => Value::Object(position(p.line, p.column)),
+                        None => Value::Null,
+                    },
+                );
+                obj.insert("source".to_string(), Value::Object(source));
+                Value::Object(obj)
+            })
+            .collect();
+
+        let mut obj = IndexMap::new();
+        obj.insert("version".to_string(), Value::Int(SOURCE_MAP_VERSION));
+        obj.insert(
+            "format".to_string(),
+            Value::String(self.format.name().to_string()),
+        );
+        obj.insert("mappings".to_string(), Value::Array(mappings));
+        Value::Object(obj)
+    }
+
+    /// The map as pretty JSON, with source files relative to `base_dir`
+    pub fn to_json(&self, base_dir: &Path) -> HoneResult<String> {
+        emit(&self.to_value(base_dir), OutputFormat::JsonPretty)
+    }
+}
+
+/// Whether `path` names a key in `value`; array elements are searched
+/// without an index segment
+fn has_path(value: &Value, path: &[&str]) -> bool {
+    let Some((first, rest)) = path.split_first() else {
+        return true;
+    };
+    match value {
+        Value::Object(obj) => obj.get(*first).is_some_and(|v| has_path(v, rest)),
+        Value::Array(arr) => arr.iter().any(|v| has_path(v, path)),
+        _ => false,
+    }
+}
+
+/// Output position of each of `paths`, by index, found through marker
+/// comments. Empty for formats without doc comments, or when the marked
+/// output doesn't match `output`.
+fn output_positions(
+    value: &Value,
+    format: OutputFormat,
+    options: &EmitOptions,
+    output: &str,
+    paths: &[&String],
+) -> HashMap<usize, Position> {
+    // Pretty JSON is JSONC without comments
+    let (marked_format, options) = match format {
+        OutputFormat::Yaml | OutputFormat::Jsonc => (format, options.clone()),
+        OutputFormat::JsonPretty => (
+            OutputFormat::Jsonc,
+            EmitOptions {
+                doc_comments: DocMap::new(),
+                key_origins: false,
+                trailing_commas: false,
+                ..options.clone()
+            },
+        ),
+        _ => return HashMap::new(),
+    };
+    let docs: DocMap = paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let marker = format!("{}{}", MARKER, i);
+            let doc = match options.doc_comments.get(*path) {
+                Some(doc) => format!("{}\n{}", marker, doc),
+                None => marker,
+            };
+            (path.to_string(), doc)
+        })
+        .collect();
+    let options = EmitOptions {
+        omit_nulls: false,
+        doc_comments: docs,
+        ..options
+    };
+    let Ok(marked) = emit_with(value, marked_format, &options) else {
+        return HashMap::new();
+    };
+
+    let mut positions = HashMap::new();
+    let mut pending = Vec::new();
+    let mut unmarked = Vec::new();
+    for line in marked.split('\n') {
+        let text = line.trim_start();
+        let comment = text.strip_prefix("# ").or_else(|| text.strip_prefix("// "));
+        if let Some(index) = comment.and_then(|c| c.strip_prefix(MARKER)) {
+            if let Ok(index) = index.parse::<usize>() {
+                pending.push(index);
+                continue;
+            }
+        }
+        let is_comment = text.starts_with('#') || text.starts_with("//");
+        if !pending.is_empty() && !is_comment {
+            // A YAML key in an array of objects follows the item's dash
+            let key = line.trim_start_matches([' ', '-']);
+            let position = Position {
+                line: unmarked.len() + 1,
+                column: line.len() - key.len() + 1,
+            };
+            for index in pending.drain(..) {
+                positions.entry(index).or_insert(position);
+            }
+        }
+        unmarked.push(line);
+    }
+    if unmarked.join("\n") != output {
+        return HashMap::new();
+    }
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn at(line: usize, column: usize) -> SourceLocation {
+        SourceLocation {
+            file: Some(PathBuf::from("/app/main.hone")),
+            line,
+            column,
+            offset: 0,
+            length: 1,
+        }
+    }
+
+    fn example() -> (Value, LocationMap) {
+        let mut server = IndexMap::new();
+        server.insert("host".to_string(), Value::String("web".to_string()));
+        server.insert("port".to_string(), Value::Int(80));
+        let mut route = IndexMap::new();
+        route.insert("path".to_string(), Value::String("/".to_string()));
+        route.insert("backend".to_string(), Value::String("api".to_string()));
+        let mut obj = IndexMap::new();
+        obj.insert("name".to_string(), Value::String("api".to_string()));
+        obj.insert("server".to_string(), Value::Object(server));
+        obj.insert(
+            "routes".to_string(),
+            Value::Array(vec![Value::Object(route)]),
+        );
+
+        let locations: LocationMap = [
+            ("name", at(1, 1)),
+            ("server", at(2, 1)),
+            ("server.host", at(3, 3)),
+            ("server.port", at(4, 3)),
+            ("routes", at(6, 1)),
+            ("routes.path", at(7, 5)),
+            ("routes.backend", at(8, 5)),
+            ("removed", at(10, 1)),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+        (Value::Object(obj), locations)
+    }
+
+    fn positions(map: &SourceMap) -> Vec<(&str, Option<(usize, usize)>)> {
+        map.mappings
+            .iter()
+            .map(|m| (m.path.as_str(), m.output.map(|p| (p.line, p.column))))
+            .collect()
+    }
+
+    #[test]
+    fn test_yaml_positions() {
+        let (value, locations) = example();
+        let options = EmitOptions::new();
+        let output = emit_with(&value, OutputFormat::Yaml, &options).unwrap();
+        let map = SourceMap::new(&value, OutputFormat::Yaml, &options, &output, &locations);
+        assert_eq!(
+            positions(&map),
+            vec![
+                ("name", Some((1, 1))),
+                ("server", Some((2, 1))),
+                ("server.host", Some((3, 3))),
+                ("server.port", Some((4, 3))),
+                ("routes", Some((5, 1))),
+                ("routes.path", Some((6, 5))),
+                ("routes.backend", Some((7, 5))),
+            ]
+        );
+        assert_eq!(map.source_at(4).unwrap().path, "server.port");
+        assert_eq!(map.source_at(4).unwrap().source.line, 4);
+        let set = map.outputs_at(Some(Path::new("/app/main.hone")), 7);
+        assert_eq!(set.len(), 1);
+        assert_eq!(set[0].output, Some(Position { line: 6, column: 5 }));
+    }
+
+    #[test]
+    fn test_json_positions_keep_doc_comments() {
+        let (value, locations) = example();
+        let docs: DocMap = [("server.port".to_string(), "Listen port".to_string())]
+            .into_iter()
+            .collect();
+        let options = EmitOptions::new().with_doc_comments(docs);
+
+        let output = emit_with(&value, OutputFormat::JsonPretty, &options).unwrap();
+        let map = SourceMap::new(
+            &value,
+            OutputFormat::JsonPretty,
+            &options,
+            &output,
+            &locations,
+        );
+        assert_eq!(
+            map.mappings[0].output,
+            Some(Position { line: 2, column: 3 })
+        );
+        assert_eq!(map.source_at(5).unwrap().path, "server.port");
+
+        // The doc comment above `port` pushes it down a line in JSONC
+        let output = emit_with(&value, OutputFormat::Jsonc, &options).unwrap();
+        let map = SourceMap::new(&value, OutputFormat::Jsonc, &options, &output, &locations);
+        assert_eq!(map.source_at(6).unwrap().path, "server.port");
+        assert!(output.split('\n').nth(5).unwrap().contains("\"port\""));
+    }
+
+    #[test]
+    fn test_other_formats_map_sources_only() {
+        let (value, locations) = example();
+        let options = EmitOptions::new();
+        let output = emit_with(&value, OutputFormat::Toml, &options).unwrap();
+        let mut map = SourceMap::new(&value, OutputFormat::Toml, &options, &output, &locations);
+        map.offset_lines(1);
+        assert_eq!(map.mappings.len(), 7);
+        assert!(map.mappings.iter().all(|m| m.output.is_none()));
+        assert_eq!(map.source_at(1), None);
+
+        let json = map.to_json(Path::new("/app")).unwrap();
+        assert!(json.contains("\"format\": \"toml\""));
+        assert!(json.contains("\"file\": \"main.hone\""));
+    }
+}
@@ -0,0 +1,243 @@
+//! Checking every file of a workspace for `hone check --all`
+//!
+//! Files are checked in dependency order, a wave at a time: a wave holds
+//! the files whose imports and `from` bases in the workspace are all
+//! checked, and its files run in parallel. A file whose dependency failed
+//! is not checked but skipped, naming the failed files, so an error in a
+//! base is reported once where it is rather than again in every file built
+//! on it. Files in an import cycle are checked together, which reports the
+//! cycle.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::errors::{HoneError, HoneResult, Warning};
+use crate::resolver::{canonicalize, ImportResolver};
+
+/// How checking one file went
+#[derive(Debug)]
+pub enum Outcome {
+    Ok,
+    Failed(HoneError),
+    /// Not checked, because these files it depends on failed
+    Skipped(Vec<PathBuf>),
+}
+
+/// What checking one file returns: its warnings and whether it passed
+type Checked = (Vec<Warning>, HoneResult<()>);
+
+/// One file's result
+#[derive(Debug)]
+pub struct FileCheck {
+    pub path: PathBuf,
+    pub warnings: Vec<Warning>,
+    pub outcome: Outcome,
+}
+
+/// Every `.hone` file under `root`, sorted, skipping directories whose name
+/// starts with `.` (`.git`, the build cache)
+pub fn discover(root: &Path) -> HoneResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    collect(root, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) -> HoneResult<()> {
+    let entries = std::fs::read_dir(dir).map_err(|e| {
+        HoneError::io_error(format!("failed to read directory {}: {}", dir.display(), e))
+    })?;
+    for entry in entries {
+        let path = entry
+            .map_err(|e| HoneError::io_error(format!("failed to read entry: {}", e)))?
+            .path();
+        let hidden = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with('.'));
+        if path.is_dir() {
+            if !hidden {
+                collect(&path, files)?;
+            }
+        } else if path.extension().and_then(|e| e.to_str()) == Some("hone") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Check `files` in dependency order with up to `jobs` threads. `check`
+/// checks one file, returning its warnings and whether it passed. Results
+/// come back in the order the files were checked.
+pub fn check_in_order<F>(files: &[PathBuf], jobs: usize, check: F) -> Vec<FileCheck>
+where
+    F: Fn(&Path) -> Checked + Sync,
+{
+    let files: Vec<PathBuf> = files
+        .iter()
+        .map(|f| canonicalize(f).unwrap_or_else(|_| f.clone()))
+        .collect();
+    let mut pending: BTreeMap<PathBuf, BTreeSet<PathBuf>> = files
+        .iter()
+        .map(|f| {
+            let deps = dependencies(f)
+                .into_iter()
+                .filter(|d| d != f && files.contains(d))
+                .collect();
+            (f.clone(), deps)
+        })
+        .collect();
+
+    let mut results = Vec::new();
+    // Failed files each checked file traces its failures back to
+    let mut failed: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    while !pending.is_empty() {
+        let mut wave: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, deps)| deps.iter().all(|d| !pending.contains_key(d)))
+            .map(|(f, _)| f.clone())
+            .collect();
+        if wave.is_empty() {
+            // Only cycles are left
+            wave = pending.keys().cloned().collect();
+        }
+
+        let mut to_check = Vec::new();
+        for file in wave {
+            let deps = pending.remove(&file).unwrap_or_default();
+            let upstream: BTreeSet<PathBuf> = deps
+                .iter()
+                .filter_map(|d| failed.get(d))
+                .flatten()
+                .cloned()
+                .collect();
+            if upstream.is_empty() {
+                to_check.push(file);
+            } else {
+                let upstream: Vec<PathBuf> = upstream.into_iter().collect();
+                failed.insert(file.clone(), upstream.clone());
+                results.push(FileCheck {
+                    path: file,
+                    warnings: Vec::new(),
+                    outcome: Outcome::Skipped(upstream),
+                });
+            }
+        }
+
+        for (path, (warnings, result)) in to_check.iter().zip(run_wave(&to_check, jobs, &check)) {
+            let outcome = match result {
+                Ok(()) => Outcome::Ok,
+                Err(e) => {
+                    failed.insert(path.clone(), vec![path.clone()]);
+                    Outcome::Failed(e)
+                }
+            };
+            results.push(FileCheck {
+                path: path.clone(),
+                warnings,
+                outcome,
+            });
+        }
+    }
+    results
+}
+
+/// Check every file of a wave, in parallel
+fn run_wave<F>(files: &[PathBuf], jobs: usize, check: &F) -> Vec<Checked>
+where
+    F: Fn(&Path) -> Checked + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let slots: Vec<Mutex<Option<Checked>>> = files.iter().map(|_| Mutex::new(None)).collect();
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, files.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(file) = files.get(i) else {
+                    break;
+                };
+                let result = check(file);
+                *slots[i].lock().unwrap() = Some(result);
+            });
+        }
+    });
+    slots
+        .into_iter()
+        .map(|slot| {
+            slot.into_inner()
+                .unwrap()
+                .expect("every file of a wave is checked")
+        })
+        .collect()
+}
+
+/// The files `path` imports, builds `from` or includes. A file that does not parse
+/// has none here; checking it reports why.
+fn dependencies(path: &Path) -> Vec<PathBuf> {
+    let Ok(source) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut resolver = ImportResolver::new(dir);
+    match resolver.resolve_source(path, source) {
+        Ok(resolved) => resolved
+            .from_path
+            .iter()
+            .chain(&resolved.import_paths)
+            .chain(&resolved.include_paths)
+            .cloned()
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_checks_in_dependency_order_and_skips_dependents() {
+        let dir = TempDir::new().unwrap();
+        let write = |name: &str, source: &str| {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, source).unwrap();
+        };
+        write("base.hone", "broken: \n");
+        write("app/main.hone", "from \"../base.hone\"\nname: \"app\"\n");
+        write("app/lib.hone", "let x = 1\n");
+        write(
+            "app/uses_lib.hone",
+            "import \"./lib.hone\" as lib\nx: lib.x\n",
+        );
+        write(".cache/ignored.hone", "ignored: \n");
+
+        let files = discover(dir.path()).unwrap();
+        assert_eq!(files.len(), 4);
+
+        let checked = check_in_order(&files, 4, |path| {
+            let mut compiler = crate::Compiler::new(path.parent().unwrap());
+            let result = compiler.compile(path).map(|_| ());
+            (compiler.warnings().to_vec(), result)
+        });
+        let name = |c: &FileCheck| c.path.file_name().unwrap().to_str().unwrap().to_string();
+        let order: Vec<String> = checked.iter().map(name).collect();
+        let position = |n: &str| order.iter().position(|o| o == n).unwrap();
+        assert!(position("lib.hone") < position("uses_lib.hone"));
+        assert!(position("base.hone") < position("main.hone"));
+
+        let outcome = |n: &str| &checked[position(n)].outcome;
+        assert!(matches!(outcome("base.hone"), Outcome::Failed(_)));
+        assert!(matches!(outcome("uses_lib.hone"), Outcome::Ok));
+        match outcome("main.hone") {
+            Outcome::Skipped(upstream) => {
+                assert_eq!(upstream.len(), 1);
+                assert!(upstream[0].ends_with("base.hone"));
+            }
+            other => panic!("expected main.hone to be skipped, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,298 @@
+//! Output keys written more than once
+//!
+//! `hone conflicts` lists every output key that more than one assignment
+//! wrote — in `from` bases, variant cases, `when` blocks or the file itself —
+//! with each writer in the order they ran and the one whose value was kept.
+//! Objects assigned with `:` deep-merge, so a key written only that way is
+//! not a conflict; the keys inside it are compared instead.
+
+use std::path::Path;
+
+use indexmap::IndexMap;
+
+use crate::evaluator::{KeyWrite, Redactor, Value, WriteKind};
+use crate::style::{self, Style};
+
+/// Longest value shown before it is cut off
+const MAX_VALUE_LEN: usize = 60;
+
+/// A key written more than once
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// Dot-path of the key
+    pub path: String,
+    /// Every write, in evaluation order
+    pub writes: Vec<KeyWrite>,
+    /// Index of the write whose value was kept (appends after it add to it)
+    pub winner: usize,
+    /// The key's value in the output
+    pub value: Value,
+}
+
+impl Conflict {
+    /// This conflict with `@sensitive` values masked
+    pub fn redacted(&self, redactor: &Redactor) -> Conflict {
+        Conflict {
+            path: redactor.text(&self.path),
+            writes: self
+                .writes
+                .iter()
+                .map(|w| KeyWrite {
+                    value: redactor.value(&w.value),
+                    ..w.clone()
+                })
+                .collect(),
+            winner: self.winner,
+            value: redactor.value(&self.value),
+        }
+    }
+}
+
+/// Group `writes` by key and keep the keys with more than one writer
+pub fn find_conflicts(writes: &[KeyWrite], output: &Value) -> Vec<Conflict> {
+    let mut by_path: IndexMap<&str, Vec<KeyWrite>> = IndexMap::new();
+    for write in writes {
+        by_path
+            .entry(write.path.as_str())
+            .or_default()
+            .push(write.clone());
+    }
+    by_path
+        .into_iter()
+        .filter(|(_, writes)| writes.len() > 1 && !writes.iter().all(is_merge))
+        .map(|(path, writes)| {
+            let winner = winner(&writes);
+            let parts: Vec<&str> = path.split('.').collect();
+            Conflict {
+                path: path.to_string(),
+                value: output.get_path(&parts).cloned().unwrap_or(Value::Null),
+                writes,
+                winner,
+            }
+        })
+        .collect()
+}
+
+/// The write whose value was kept. An append adds to the value set earlier
+/// in the same file; files are merged with `:`, so an append in a file built
+/// `from` another replaces the base's array.
+fn winner(writes: &[KeyWrite]) -> usize {
+    let mut winner = 0;
+    for (i, write) in writes.iter().enumerate().skip(1) {
+        if write.kind != WriteKind::Append || write.location.file != writes[winner].location.file {
+            winner = i;
+        }
+    }
+    winner
+}
+
+/// An object assigned with `:`, which merges into what is there
+fn is_merge(write: &KeyWrite) -> bool {
+    write.kind == WriteKind::Assign && matches!(write.value, Value::Object(_))
+}
+
+/// Format conflicts as text; file paths are shown relative to `base`
+pub fn format_conflicts_text(conflicts: &[Conflict], base: &Path) -> String {
+    format_conflicts_text_with(conflicts, base, &Style::plain())
+}
+
+/// Format conflicts as text, colored by `style`
+pub fn format_conflicts_text_with(conflicts: &[Conflict], base: &Path, style: &Style) -> String {
+    if conflicts.is_empty() {
+        return "no key is written more than once\n".to_string();
+    }
+    let mut output = String::new();
+    for conflict in conflicts {
+        output.push_str(&format!(
+            "{} = {}\n",
+            style.paint(style::CYAN, &conflict.path),
+            short_value(&conflict.value)
+        ));
+        let sites: Vec<String> = conflict.writes.iter().map(|w| site(w, base)).collect();
+        let width = sites.iter().map(|s| s.chars().count()).max().unwrap_or(0);
+        for (i, (write, site)) in conflict.writes.iter().zip(&sites).enumerate() {
+            let outcome = if i == conflict.winner {
+                style.paint(style::GREEN, "wins")
+            } else if i < conflict.winner {
+                style.paint(style::DIM, "overridden")
+            } else {
+                "appended".to_string()
+            };
+            output.push_str(&format!(
+                "  {:<width$}  {}{}  {}\n",
+                site,
+                operator(write.kind),
+                short_value(&write.value),
+                outcome,
+                width = width
+            ));
+        }
+    }
+    output.push_str(&format!(
+        "\n{} key{} written more than once\n",
+        conflicts.len(),
+        if conflicts.len() == 1 { "" } else { "s" }
+    ));
+    output
+}
+
+/// Format conflicts as JSON
+pub fn format_conflicts_json(conflicts: &[Conflict], base: &Path) -> String {
+    let json: Vec<serde_json::Value> = conflicts
+        .iter()
+        .map(|conflict| {
+            let writes: Vec<serde_json::Value> = conflict
+                .writes
+                .iter()
+                .enumerate()
+                .map(|(i, w)| {
+                    serde_json::json!({
+                        "file": w.location.file.as_deref().map(|f| relative(f, base)),
+                        "line": w.location.line,
+                        "column": w.location.column,
+                        "kind": kind_name(w.kind),
+                        "value": w.value.to_serde_json(),
+                        "wins": i == conflict.winner,
+                    })
+                })
+                .collect();
+            serde_json::json!({
+                "path": conflict.path,
+                "value": conflict.value.to_serde_json(),
+                "writes": writes,
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&json).unwrap_or_default() + "\n"
+}
+
+/// `file:line:column` of a write
+fn site(write: &KeyWrite, base: &Path) -> String {
+    let file = write
+        .location
+        .file
+        .as_deref()
+        .map(|f| relative(f, base))
+        .unwrap_or_else(|| "<stdin>".to_string());
+    format!("{}:{}:{}", file, write.location.line, write.location.column)
+}
+
+fn relative(file: &Path, base: &Path) -> String {
+    file.strip_prefix(base)
+        .unwrap_or(file)
+        .display()
+        .to_string()
+}
+
+fn operator(kind: WriteKind) -> &'static str {
+    match kind {
+        WriteKind::Assign => "",
+        WriteKind::Append => "+: ",
+        WriteKind::Replace => "!: ",
+        WriteKind::Spread => "...",
+        WriteKind::For => "for ",
+    }
+}
+
+fn kind_name(kind: WriteKind) -> &'static str {
+    match kind {
+        WriteKind::Assign => "assign",
+        WriteKind::Append => "append",
+        WriteKind::Replace => "replace",
+        WriteKind::Spread => "spread",
+        WriteKind::For => "for",
+    }
+}
+
+/// A value as compact JSON, cut off after [`MAX_VALUE_LEN`] characters
+fn short_value(value: &Value) -> String {
+    let text = value.to_serde_json().to_string();
+    if text.chars().count() <= MAX_VALUE_LEN {
+        return text;
+    }
+    let cut: String = text.chars().take(MAX_VALUE_LEN - 1).collect();
+    format!("{}…", cut)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::token::SourceLocation;
+
+    fn write(path: &str, kind: WriteKind, value: Value, file: &str, line: usize) -> KeyWrite {
+        let location = SourceLocation::new(Some(Path::new("/proj").join(file)), line, 3, 0, 0);
+        KeyWrite {
+            path: path.to_string(),
+            kind,
+            value,
+            location,
+        }
+    }
+
+    fn output(port: i64) -> Value {
+        let mut server = IndexMap::new();
+        server.insert("port".to_string(), Value::Int(port));
+        let mut root = IndexMap::new();
+        root.insert("server".to_string(), Value::Object(server));
+        Value::Object(root)
+    }
+
+    #[test]
+    fn test_find_conflicts() {
+        let server = || Value::Object(IndexMap::new());
+        let writes = vec![
+            write("server", WriteKind::Assign, server(), "base.hone", 1),
+            write(
+                "server.port",
+                WriteKind::Assign,
+                Value::Int(80),
+                "base.hone",
+                2,
+            ),
+            write("server", WriteKind::Assign, server(), "main.hone", 3),
+            write(
+                "server.port",
+                WriteKind::Assign,
+                Value::Int(81),
+                "main.hone",
+                4,
+            ),
+            write("name", WriteKind::Assign, Value::Int(1), "main.hone", 5),
+        ];
+        let conflicts = find_conflicts(&writes, &output(81));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, "server.port");
+        assert_eq!(conflicts[0].winner, 1);
+        assert_eq!(conflicts[0].value, Value::Int(81));
+
+        let text = format_conflicts_text(&conflicts, Path::new("/proj"));
+        assert_eq!(
+            text,
+            "server.port = 81\n  base.hone:2:3  80  overridden\n  main.hone:4:3  81  wins\n\n1 key written more than once\n"
+        );
+    }
+
+    #[test]
+    fn test_appends_after_winner() {
+        let list = |items: &[i64]| Value::Array(items.iter().map(|i| Value::Int(*i)).collect());
+        let writes = vec![
+            write("tags", WriteKind::Assign, list(&[1]), "main.hone", 1),
+            write("tags", WriteKind::Append, list(&[2]), "main.hone", 2),
+        ];
+        let conflicts = find_conflicts(&writes, &Value::Null);
+        assert_eq!(conflicts[0].winner, 0);
+        let text = format_conflicts_text(&conflicts, Path::new("/proj"));
+        assert!(text.contains("+: [2]  appended"), "{}", text);
+
+        // Files are merged with `:`, so an overlay's append replaces
+        let writes = vec![
+            write("tags", WriteKind::Assign, list(&[1]), "base.hone", 1),
+            write("tags", WriteKind::Append, list(&[2]), "main.hone", 2),
+        ];
+        assert_eq!(find_conflicts(&writes, &Value::Null)[0].winner, 1);
+        assert_eq!(
+            format_conflicts_text(&[], Path::new("/")),
+            "no key is written more than once\n"
+        );
+    }
+}
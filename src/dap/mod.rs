@@ -0,0 +1,682 @@
+//! Debug Adapter Protocol server (`hone dap`)
+//!
+//! Hone evaluation has no side effects, so the adapter evaluates the program
+//! once at launch with scope snapshots enabled and then replays the recorded
+//! trace: every body item is a step, breakpoints stop on a line or a key path
+//! (`server.port`, set as a function breakpoint), and the variables view shows
+//! the bindings visible at the current item.
+//!
+//! Messages use the standard DAP framing (`Content-Length` header, JSON body)
+//! over stdin/stdout.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value as Json};
+
+use crate::compiler::{build_args_object, Compiler};
+use crate::errors::{HoneError, HoneResult};
+use crate::evaluator::{ScopeSnapshot, Value};
+
+/// The only thread a Hone program has
+const THREAD_ID: i64 = 1;
+
+/// `variablesReference` of the "Locals" scope; container values count up from 2
+const LOCALS_REF: usize = 1;
+
+/// Run the debug adapter on stdin/stdout until the client disconnects
+pub fn run_stdio() -> HoneResult<()> {
+    let stdin = std::io::stdin();
+    let mut reader = stdin.lock();
+    let stdout = std::io::stdout();
+    let mut session = DebugSession::new();
+
+    while let Some(request) = read_message(&mut reader)? {
+        for message in session.handle(&request) {
+            let mut out = stdout.lock();
+            write_message(&mut out, &message)?;
+        }
+        if session.is_finished() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Read one framed message. Returns `None` at end of input.
+pub fn read_message(reader: &mut impl BufRead) -> HoneResult<Option<Json>> {
+    let io_err = |e: std::io::Error| HoneError::io_error(format!("dap: {}", e));
+
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).map_err(io_err)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length.unwrap_or(0)];
+    reader.read_exact(&mut body).map_err(io_err)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| HoneError::io_error(format!("dap: invalid message: {}", e)))
+}
+
+/// Write one framed message
+pub fn write_message(writer: &mut impl Write, message: &Json) -> HoneResult<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|()| writer.flush())
+        .map_err(|e| HoneError::io_error(format!("dap: {}", e)))
+}
+
+/// State of one debugging session
+#[derive(Debug, Default)]
+pub struct DebugSession {
+    /// Sequence number of the last message sent
+    seq: i64,
+    /// The launched program (canonical path)
+    program: Option<PathBuf>,
+    /// Body items in evaluation order
+    trace: Vec<ScopeSnapshot>,
+    /// Evaluation error, reported when the replay reaches the end
+    error: Option<String>,
+    /// Index into `trace` of the current stop
+    position: Option<usize>,
+    stop_on_entry: bool,
+    /// Breakpoint lines per file
+    line_breakpoints: HashMap<PathBuf, Vec<usize>>,
+    /// Breakpoints on key paths (`server.port`)
+    key_breakpoints: Vec<String>,
+    /// Container values shown in the variables view, by reference - 2
+    handles: Vec<Value>,
+    finished: bool,
+}
+
+impl DebugSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the client has disconnected
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Handle one request, returning the response and any events to send
+    pub fn handle(&mut self, request: &Json) -> Vec<Json> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let args = &request["arguments"];
+        let mut events = Vec::new();
+
+        let result = match command {
+            "initialize" => {
+                events.push(("initialized", Json::Null));
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsFunctionBreakpoints": true,
+                    "supportsEvaluateForHovers": true,
+                }))
+            }
+            "launch" => self.launch(args).map(|()| Json::Null),
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "setFunctionBreakpoints" => Ok(self.set_key_breakpoints(args)),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    let entry = self.trace.iter().position(|s| self.in_program(s));
+                    self.stop_or_finish(entry, "entry", &mut events);
+                } else {
+                    self.resume(|_, _| false, &mut events);
+                }
+                Ok(Json::Null)
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({
+                "scopes": [{
+                    "name": "Locals",
+                    "variablesReference": LOCALS_REF,
+                    "expensive": false,
+                }]
+            })),
+            "variables" => Ok(self.variables(args)),
+            "evaluate" => self.evaluate(args),
+            "continue" => {
+                self.resume(|_, _| false, &mut events);
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" => {
+                self.resume(|cur, s| s.depth <= cur.depth, &mut events);
+                Ok(Json::Null)
+            }
+            "stepIn" => {
+                self.resume(|_, _| true, &mut events);
+                Ok(Json::Null)
+            }
+            "stepOut" => {
+                self.resume(|cur, s| s.depth < cur.depth, &mut events);
+                Ok(Json::Null)
+            }
+            // Evaluation already ran to completion; there is nothing to interrupt
+            "pause" => Ok(Json::Null),
+            "disconnect" | "terminate" => {
+                self.finished = true;
+                Ok(Json::Null)
+            }
+            other => Err(format!("unsupported request '{}'", other)),
+        };
+
+        let mut messages = vec![self.response(request, result)];
+        for (event, body) in events {
+            messages.push(self.event(event, body));
+        }
+        messages
+    }
+
+    /// Evaluate the program, recording the trace to replay
+    fn launch(&mut self, args: &Json) -> Result<(), String> {
+        let program = args["program"]
+            .as_str()
+            .ok_or("launch: missing 'program'")?;
+        let program = Path::new(program)
+            .canonicalize()
+            .map_err(|e| format!("failed to resolve path {}: {}", program, e))?;
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+
+        let base_dir = program.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let mut compiler = Compiler::new(base_dir);
+        compiler.enable_snapshots();
+        compiler.set_allow_env(args["allowEnv"].as_bool().unwrap_or(false));
+        if let Some(variants) = args["variants"].as_object() {
+            compiler.set_variants(
+                variants
+                    .iter()
+                    .map(|(k, v)| (k.clone(), json_to_arg(v)))
+                    .collect(),
+            );
+        }
+        if let Some(set) = args["args"].as_object() {
+            let set: Vec<(String, String)> = set
+                .iter()
+                .map(|(k, v)| (k.clone(), json_to_arg(v)))
+                .collect();
+            compiler.set_args(build_args_object(&set, &[], &[]).map_err(|e| e.to_string())?);
+        }
+
+        // Snapshots before an evaluation error are kept, so a failing config
+        // can still be stepped up to the failure
+        self.error = compiler.compile(&program).err().map(|e| error_message(&e));
+        self.trace = compiler
+            .snapshots()
+            .iter()
+            .map(|s| s.redacted(compiler.redactor()))
+            .collect();
+        self.program = Some(program);
+        Ok(())
+    }
+
+    fn set_breakpoints(&mut self, args: &Json) -> Json {
+        let path = args["source"]["path"].as_str().map(canonical_or_given);
+        let lines: Vec<usize> = args["breakpoints"]
+            .as_array()
+            .map(|bps| {
+                bps.iter()
+                    .filter_map(|bp| bp["line"].as_u64())
+                    .map(|line| line as usize)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let breakpoints: Vec<Json> = lines
+            .iter()
+            .map(|&line| {
+                // Before launch there is no trace to check against
+                let verified = self.trace.is_empty()
+                    || self.trace.iter().any(|s| {
+                        s.location.line == line && s.location.file.as_deref() == path.as_deref()
+                    });
+                json!({ "verified": verified, "line": line })
+            })
+            .collect();
+
+        if let Some(path) = path {
+            self.line_breakpoints.insert(path, lines);
+        }
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn set_key_breakpoints(&mut self, args: &Json) -> Json {
+        self.key_breakpoints = args["breakpoints"]
+            .as_array()
+            .map(|bps| {
+                bps.iter()
+                    .filter_map(|bp| bp["name"].as_str())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let breakpoints: Vec<Json> = self
+            .key_breakpoints
+            .iter()
+            .map(|name| {
+                let verified = self.trace.is_empty()
+                    || self
+                        .trace
+                        .iter()
+                        .any(|s| s.key_path().as_deref() == Some(name.as_str()));
+                json!({ "verified": verified })
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn in_program(&self, snapshot: &ScopeSnapshot) -> bool {
+        snapshot.location.file.as_deref() == self.program.as_deref()
+    }
+
+    fn is_breakpoint(&self, snapshot: &ScopeSnapshot) -> bool {
+        let on_line = snapshot
+            .location
+            .file
+            .as_ref()
+            .and_then(|file| self.line_breakpoints.get(file))
+            .is_some_and(|lines| lines.contains(&snapshot.location.line));
+        on_line
+            || snapshot
+                .key_path()
+                .is_some_and(|path| self.key_breakpoints.contains(&path))
+    }
+
+    /// Advance past the current item to the next one where `step` holds (a
+    /// completed step) or that has a breakpoint
+    fn resume(
+        &mut self,
+        step: impl Fn(&ScopeSnapshot, &ScopeSnapshot) -> bool,
+        events: &mut Vec<(&'static str, Json)>,
+    ) {
+        let start = self.position.map(|p| p + 1).unwrap_or(0);
+        let current = self.position.map(|p| &self.trace[p]);
+
+        let mut next = None;
+        for (i, snapshot) in self.trace.iter().enumerate().skip(start) {
+            if self.is_breakpoint(snapshot) {
+                next = Some((i, "breakpoint"));
+                break;
+            }
+            if current.is_some_and(|cur| step(cur, snapshot)) {
+                next = Some((i, "step"));
+                break;
+            }
+        }
+
+        match next {
+            Some((i, reason)) => self.stop_or_finish(Some(i), reason, events),
+            None => self.stop_or_finish(None, "step", events),
+        }
+    }
+
+    fn stop_or_finish(
+        &mut self,
+        index: Option<usize>,
+        reason: &str,
+        events: &mut Vec<(&'static str, Json)>,
+    ) {
+        self.handles.clear();
+        match index {
+            Some(i) => {
+                self.position = Some(i);
+                let mut body = json!({
+                    "reason": reason,
+                    "threadId": THREAD_ID,
+                    "allThreadsStopped": true,
+                });
+                if let Some(path) = self.trace[i].key_path() {
+                    body["description"] = json!(format!("at {}", path));
+                }
+                events.push(("stopped", body));
+            }
+            None => {
+                self.position = None;
+                let exit_code = match self.error {
+                    Some(ref message) => {
+                        events.push((
+                            "output",
+                            json!({ "category": "stderr", "output": format!("{}\n", message) }),
+                        ));
+                        1
+                    }
+                    None => 0,
+                };
+                events.push(("exited", json!({ "exitCode": exit_code })));
+                events.push(("terminated", json!({})));
+            }
+        }
+    }
+
+    fn current(&self) -> Option<&ScopeSnapshot> {
+        self.position.map(|p| &self.trace[p])
+    }
+
+    fn stack_trace(&self) -> Json {
+        let frames: Vec<Json> = self
+            .current()
+            .map(|s| {
+                let name = s
+                    .key_path()
+                    .or_else(|| (!s.path.is_empty()).then(|| s.path.clone()))
+                    .unwrap_or_else(|| "<top level>".to_string());
+                let mut frame = json!({
+                    "id": 1,
+                    "name": name,
+                    "line": s.location.line,
+                    "column": s.location.column,
+                });
+                if let Some(ref file) = s.location.file {
+                    frame["source"] = json!({
+                        "name": file.file_name().map(|n| n.to_string_lossy().into_owned()),
+                        "path": file.display().to_string(),
+                    });
+                }
+                frame
+            })
+            .into_iter()
+            .collect();
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn variables(&mut self, args: &Json) -> Json {
+        let reference = args["variablesReference"].as_u64().unwrap_or(0) as usize;
+        let children: Vec<(String, Value)> = if reference == LOCALS_REF {
+            self.current()
+                .map(|s| s.bindings.clone())
+                .unwrap_or_default()
+        } else {
+            match self.handles.get(reference.wrapping_sub(LOCALS_REF + 1)) {
+                Some(Value::Object(obj)) => {
+                    obj.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+                }
+                Some(Value::Array(arr)) => arr
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| (format!("[{}]", i), v.clone()))
+                    .collect(),
+                _ => Vec::new(),
+            }
+        };
+
+        let variables: Vec<Json> = children
+            .into_iter()
+            .map(|(name, value)| {
+                let mut var = self.present(&value);
+                var["name"] = json!(name);
+                var
+            })
+            .collect();
+        json!({ "variables": variables })
+    }
+
+    /// Look up a variable or a dotted path into one (`server.port`, `items.0`)
+    fn evaluate(&mut self, args: &Json) -> Result<Json, String> {
+        let expression = args["expression"].as_str().unwrap_or_default().trim();
+        let mut parts = expression.split('.');
+        let name = parts.next().unwrap_or_default();
+        let mut value = self
+            .current()
+            .and_then(|s| s.bindings.iter().find(|(n, _)| n == name))
+            .map(|(_, v)| v.clone())
+            .ok_or_else(|| format!("'{}' is not in scope", name))?;
+        for part in parts {
+            value = match value {
+                Value::Object(ref obj) => obj.get(part).cloned(),
+                Value::Array(ref arr) => {
+                    part.parse::<usize>().ok().and_then(|i| arr.get(i).cloned())
+                }
+                _ => None,
+            }
+            .ok_or_else(|| format!("'{}' has no field '{}'", expression, part))?;
+        }
+
+        let mut result = self.present(&value);
+        result["result"] = result["value"].take();
+        Ok(result)
+    }
+
+    /// DAP variable fields for a value, registering containers for expansion
+    fn present(&mut self, value: &Value) -> Json {
+        let (display, reference) = match value {
+            Value::Object(obj) => {
+                self.handles.push(value.clone());
+                (
+                    format!("{{{} keys}}", obj.len()),
+                    self.handles.len() + LOCALS_REF,
+                )
+            }
+            Value::Array(arr) => {
+                self.handles.push(value.clone());
+                (
+                    format!("[{} items]", arr.len()),
+                    self.handles.len() + LOCALS_REF,
+                )
+            }
+            other => (other.to_serde_json().to_string(), 0),
+        };
+        json!({
+            "value": display,
+            "type": value.type_name(),
+            "variablesReference": reference,
+        })
+    }
+
+    fn response(&mut self, request: &Json, result: Result<Json, String>) -> Json {
+        self.seq += 1;
+        let mut response = json!({
+            "seq": self.seq,
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+        });
+        match result {
+            Ok(body) => {
+                response["success"] = json!(true);
+                if !body.is_null() {
+                    response["body"] = body;
+                }
+            }
+            Err(message) => {
+                response["success"] = json!(false);
+                response["message"] = json!(message);
+            }
+        }
+        response
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> Json {
+        self.seq += 1;
+        let mut message = json!({ "seq": self.seq, "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        message
+    }
+}
+
+/// Launch `args`/`variants` values as they would be passed to `--set`
+fn json_to_arg(value: &Json) -> String {
+    match value {
+        Json::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn canonical_or_given(path: &str) -> PathBuf {
+    Path::new(path)
+        .canonicalize()
+        .unwrap_or_else(|_| PathBuf::from(path))
+}
+
+fn error_message(error: &HoneError) -> String {
+    use miette::Diagnostic;
+    match error.help() {
+        Some(help) => format!("{}\nhelp: {}", error, help),
+        None => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn request(seq: i64, command: &str, arguments: Json) -> Json {
+        json!({ "seq": seq, "type": "request", "command": command, "arguments": arguments })
+    }
+
+    fn events<'a>(messages: &'a [Json], name: &str) -> Vec<&'a Json> {
+        messages.iter().filter(|m| m["event"] == name).collect()
+    }
+
+    fn launch(source: &str, stop_on_entry: bool) -> (TempDir, PathBuf, DebugSession) {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("app.hone");
+        std::fs::write(&path, source).unwrap();
+        let path = path.canonicalize().unwrap();
+
+        let mut session = DebugSession::new();
+        let messages = session.handle(&request(1, "initialize", json!({})));
+        assert_eq!(messages[0]["success"], true);
+        assert_eq!(events(&messages, "initialized").len(), 1);
+
+        let messages = session.handle(&request(
+            2,
+            "launch",
+            json!({ "program": path.display().to_string(), "stopOnEntry": stop_on_entry }),
+        ));
+        assert_eq!(messages[0]["success"], true, "{:?}", messages);
+        (dir, path, session)
+    }
+
+    const SOURCE: &str = "let env = \"prod\"\n\nserver {\n  let port = 8080\n  host: \"api.${env}\"\n  url: \"http://${env}:${port}\"\n}\n\nname: \"app\"\n";
+
+    #[test]
+    fn test_line_breakpoint_and_variables() {
+        let (_dir, path, mut session) = launch(SOURCE, false);
+
+        let messages = session.handle(&request(
+            3,
+            "setBreakpoints",
+            json!({ "source": { "path": path.display().to_string() }, "breakpoints": [{ "line": 6 }] }),
+        ));
+        assert_eq!(messages[0]["body"]["breakpoints"][0]["verified"], true);
+
+        let messages = session.handle(&request(4, "configurationDone", json!({})));
+        let stopped = events(&messages, "stopped");
+        assert_eq!(stopped[0]["body"]["reason"], "breakpoint");
+
+        let messages = session.handle(&request(5, "stackTrace", json!({ "threadId": 1 })));
+        let frame = &messages[0]["body"]["stackFrames"][0];
+        assert_eq!(frame["line"], 6);
+        assert_eq!(frame["name"], "server.url");
+
+        let messages = session.handle(&request(6, "variables", json!({ "variablesReference": 1 })));
+        let vars = messages[0]["body"]["variables"].as_array().unwrap();
+        let names: Vec<&str> = vars.iter().map(|v| v["name"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["env", "port"]);
+        assert_eq!(vars[1]["value"], "8080");
+
+        // Continuing past the last breakpoint runs to the end
+        let messages = session.handle(&request(7, "continue", json!({ "threadId": 1 })));
+        assert_eq!(events(&messages, "exited")[0]["body"]["exitCode"], 0);
+        assert_eq!(events(&messages, "terminated").len(), 1);
+    }
+
+    #[test]
+    fn test_step_over_skips_block_body() {
+        let (_dir, _path, mut session) = launch(SOURCE, true);
+
+        let messages = session.handle(&request(3, "configurationDone", json!({})));
+        assert_eq!(events(&messages, "stopped")[0]["body"]["reason"], "entry");
+        let line = |session: &mut DebugSession| {
+            let messages = session.handle(&request(9, "stackTrace", json!({})));
+            messages[0]["body"]["stackFrames"][0]["line"]
+                .as_u64()
+                .unwrap()
+        };
+        assert_eq!(line(&mut session), 3);
+
+        // `next` steps over the block's items; `stepIn` enters them
+        session.handle(&request(4, "next", json!({ "threadId": 1 })));
+        assert_eq!(line(&mut session), 9);
+
+        let (_dir, _path, mut session) = launch(SOURCE, true);
+        session.handle(&request(3, "configurationDone", json!({})));
+        session.handle(&request(4, "stepIn", json!({ "threadId": 1 })));
+        assert_eq!(line(&mut session), 4);
+        session.handle(&request(5, "stepOut", json!({ "threadId": 1 })));
+        assert_eq!(line(&mut session), 9);
+    }
+
+    #[test]
+    fn test_key_breakpoint_and_evaluate() {
+        let (_dir, _path, mut session) = launch(SOURCE, false);
+
+        let messages = session.handle(&request(
+            3,
+            "setFunctionBreakpoints",
+            json!({ "breakpoints": [{ "name": "server.host" }, { "name": "nope" }] }),
+        ));
+        let bps = &messages[0]["body"]["breakpoints"];
+        assert_eq!(bps[0]["verified"], true);
+        assert_eq!(bps[1]["verified"], false);
+
+        let messages = session.handle(&request(4, "configurationDone", json!({})));
+        assert_eq!(
+            events(&messages, "stopped")[0]["body"]["description"],
+            "at server.host"
+        );
+
+        let messages = session.handle(&request(5, "evaluate", json!({ "expression": "env" })));
+        assert_eq!(messages[0]["body"]["result"], "\"prod\"");
+        let messages = session.handle(&request(6, "evaluate", json!({ "expression": "missing" })));
+        assert_eq!(messages[0]["success"], false);
+    }
+
+    #[test]
+    fn test_evaluation_error_reported_at_end() {
+        let (_dir, _path, mut session) = launch("a: 1\nb: undefined_var\n", false);
+        let messages = session.handle(&request(3, "configurationDone", json!({})));
+        let output = events(&messages, "output");
+        assert!(output[0]["body"]["output"]
+            .as_str()
+            .unwrap()
+            .contains("undefined"));
+        assert_eq!(events(&messages, "exited")[0]["body"]["exitCode"], 1);
+    }
+
+    #[test]
+    fn test_message_framing() {
+        let mut buf = Vec::new();
+        write_message(&mut buf, &json!({ "seq": 1 })).unwrap();
+        write_message(&mut buf, &json!({ "seq": 2 })).unwrap();
+        assert!(buf.starts_with(b"Content-Length: 9\r\n\r\n{\"seq\":1}"));
+
+        let mut reader = std::io::Cursor::new(buf);
+        assert_eq!(read_message(&mut reader).unwrap().unwrap()["seq"], 1);
+        assert_eq!(read_message(&mut reader).unwrap().unwrap()["seq"], 2);
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+}
@@ -3,7 +3,11 @@
 //! Compares two Value trees recursively and produces a list of differences
 //! at specific paths within the structure.
 
-use crate::evaluator::Value;
+pub mod release;
+pub mod vcs;
+
+use crate::evaluator::{Redactor, Value};
+use crate::style::{self, Style};
 
 /// A single difference between two value trees
 #[derive(Debug, Clone, PartialEq)]
@@ -31,16 +35,56 @@ pub enum DiffKind {
     },
 }
 
+/// Options for [`diff_with_options`]
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Match array elements that are objects by this field instead of by
+    /// position (e.g. `name` for Kubernetes containers)
+    pub array_key: Option<String>,
+    /// Report equal values at different paths as moves
+    pub detect_moves: bool,
+}
+
+impl DiffOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_array_key(mut self, key: Option<String>) -> Self {
+        self.array_key = key;
+        self
+    }
+
+    pub fn with_detect_moves(mut self, detect_moves: bool) -> Self {
+        self.detect_moves = detect_moves;
+        self
+    }
+}
+
 /// Compare two Value trees and return a list of differences.
 ///
 /// Returns an empty vec if the values are structurally identical.
 pub fn diff_values(left: &Value, right: &Value) -> Vec<DiffEntry> {
+    diff_with_options(left, right, &DiffOptions::new())
+}
+
+/// Compare two Value trees with the given options
+pub fn diff_with_options(left: &Value, right: &Value, options: &DiffOptions) -> Vec<DiffEntry> {
     let mut entries = Vec::new();
-    diff_recursive(left, right, String::new(), &mut entries);
+    diff_recursive(left, right, String::new(), options, &mut entries);
+    if options.detect_moves {
+        entries = collapse_moves(entries);
+    }
     entries
 }
 
-fn diff_recursive(left: &Value, right: &Value, path: String, entries: &mut Vec<DiffEntry>) {
+fn diff_recursive(
+    left: &Value,
+    right: &Value,
+    path: String,
+    options: &DiffOptions,
+    entries: &mut Vec<DiffEntry>,
+) {
     if left == right {
         return;
     }
@@ -57,7 +101,7 @@ fn diff_recursive(left: &Value, right: &Value, path: String, entries: &mut Vec<D
 
                 match right_map.get(key) {
                     Some(right_val) => {
-                        diff_recursive(left_val, right_val, child_path, entries);
+                        diff_recursive(left_val, right_val, child_path, options, entries);
                     }
                     None => {
                         entries.push(DiffEntry {
@@ -85,32 +129,13 @@ fn diff_recursive(left: &Value, right: &Value, path: String, entries: &mut Vec<D
         }
 
         (Value::Array(left_arr), Value::Array(right_arr)) => {
-            let max_len = left_arr.len().max(right_arr.len());
-            for i in 0..max_len {
-                let child_path = if path.is_empty() {
-                    format!("[{}]", i)
-                } else {
-                    format!("{}[{}]", path, i)
-                };
-
-                match (left_arr.get(i), right_arr.get(i)) {
-                    (Some(l), Some(r)) => {
-                        diff_recursive(l, r, child_path, entries);
-                    }
-                    (Some(l), None) => {
-                        entries.push(DiffEntry {
-                            path: child_path,
-                            kind: DiffKind::Removed(l.clone()),
-                        });
-                    }
-                    (None, Some(r)) => {
-                        entries.push(DiffEntry {
-                            path: child_path,
-                            kind: DiffKind::Added(r.clone()),
-                        });
-                    }
-                    (None, None) => unreachable!(),
-                }
+            let keyed = options
+                .array_key
+                .as_deref()
+                .filter(|key| all_keyed(left_arr, key) && all_keyed(right_arr, key));
+            match keyed {
+                Some(key) => diff_keyed_arrays(left_arr, right_arr, key, &path, options, entries),
+                None => diff_arrays(left_arr, right_arr, &path, options, entries),
             }
         }
 
@@ -131,13 +156,170 @@ fn diff_recursive(left: &Value, right: &Value, path: String, entries: &mut Vec<D
     }
 }
 
+fn index_path(path: &str, index: usize) -> String {
+    format!("{}[{}]", path, index)
+}
+
+/// Diff arrays element by element along their longest common subsequence, so
+/// an insertion reports one added element rather than shifting every later
+/// index. Within each gap between common elements, removed and added elements
+/// are paired up and diffed recursively; the rest are reported as removed
+/// (left index) or added (right index).
+fn diff_arrays(
+    left: &[Value],
+    right: &[Value],
+    path: &str,
+    options: &DiffOptions,
+    entries: &mut Vec<DiffEntry>,
+) {
+    let mut common = lcs_pairs(left, right);
+    common.push((left.len(), right.len()));
+
+    let (mut li, mut ri) = (0, 0);
+    for (lend, rend) in common {
+        let paired = (lend - li).min(rend - ri);
+        for k in 0..paired {
+            diff_recursive(
+                &left[li + k],
+                &right[ri + k],
+                index_path(path, ri + k),
+                options,
+                entries,
+            );
+        }
+        for (i, value) in left.iter().enumerate().take(lend).skip(li + paired) {
+            entries.push(DiffEntry {
+                path: index_path(path, i),
+                kind: DiffKind::Removed(value.clone()),
+            });
+        }
+        for (i, value) in right.iter().enumerate().take(rend).skip(ri + paired) {
+            entries.push(DiffEntry {
+                path: index_path(path, i),
+                kind: DiffKind::Added(value.clone()),
+            });
+        }
+        li = lend + 1;
+        ri = rend + 1;
+    }
+}
+
+/// Index pairs of a longest common subsequence of equal elements
+fn lcs_pairs(left: &[Value], right: &[Value]) -> Vec<(usize, usize)> {
+    // Common prefix and suffix are cheap and cover most config edits
+    let prefix = left.iter().zip(right).take_while(|(l, r)| l == r).count();
+    let suffix = left[prefix..]
+        .iter()
+        .rev()
+        .zip(right[prefix..].iter().rev())
+        .take_while(|(l, r)| l == r)
+        .count();
+    let l_mid = &left[prefix..left.len() - suffix];
+    let r_mid = &right[prefix..right.len() - suffix];
+
+    // lengths[i][j] = LCS length of l_mid[i..] and r_mid[j..]
+    let mut lengths = vec![vec![0usize; r_mid.len() + 1]; l_mid.len() + 1];
+    for i in (0..l_mid.len()).rev() {
+        for j in (0..r_mid.len()).rev() {
+            lengths[i][j] = if l_mid[i] == r_mid[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < l_mid.len() && j < r_mid.len() {
+        if l_mid[i] == r_mid[j] {
+            pairs.push((prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    let (l_tail, r_tail) = (left.len() - suffix, right.len() - suffix);
+    pairs.extend((0..suffix).map(|k| (l_tail + k, r_tail + k)));
+    pairs
+}
+
+/// Whether every element is an object with a scalar `key` field
+fn all_keyed(arr: &[Value], key: &str) -> bool {
+    arr.iter().all(|v| array_key_of(v, key).is_some())
+}
+
+fn array_key_of(value: &Value, key: &str) -> Option<String> {
+    match value {
+        Value::Object(obj) => match obj.get(key)? {
+            Value::Array(_) | Value::Object(_) => None,
+            Value::String(s) => Some(s.clone()),
+            other => Some(other.to_string()),
+        },
+        _ => None,
+    }
+}
+
+/// Diff arrays of objects by matching elements on a key field. Paths name the
+/// element by key (`containers[name=web].image`); reordering is not a change.
+fn diff_keyed_arrays(
+    left: &[Value],
+    right: &[Value],
+    key: &str,
+    path: &str,
+    options: &DiffOptions,
+    entries: &mut Vec<DiffEntry>,
+) {
+    let keyed_path = |value: &Value| {
+        format!(
+            "{}[{}={}]",
+            path,
+            key,
+            array_key_of(value, key).unwrap_or_default()
+        )
+    };
+    let right_by_key: std::collections::HashMap<String, &Value> = right
+        .iter()
+        .filter_map(|v| Some((array_key_of(v, key)?, v)))
+        .collect();
+    let left_keys: std::collections::HashSet<String> =
+        left.iter().filter_map(|v| array_key_of(v, key)).collect();
+
+    for left_val in left {
+        let id = array_key_of(left_val, key).unwrap_or_default();
+        match right_by_key.get(&id) {
+            Some(right_val) => {
+                diff_recursive(left_val, right_val, keyed_path(left_val), options, entries)
+            }
+            None => entries.push(DiffEntry {
+                path: keyed_path(left_val),
+                kind: DiffKind::Removed(left_val.clone()),
+            }),
+        }
+    }
+    for right_val in right {
+        if !left_keys.contains(&array_key_of(right_val, key).unwrap_or_default()) {
+            entries.push(DiffEntry {
+                path: keyed_path(right_val),
+                kind: DiffKind::Added(right_val.clone()),
+            });
+        }
+    }
+}
+
 /// Compare two Value trees with move detection.
 ///
 /// When a key is removed from one path and an identical value appears at
 /// another path, this is reported as a `Moved` instead of Remove + Add.
 pub fn diff_with_moves(left: &Value, right: &Value) -> Vec<DiffEntry> {
-    let mut entries = diff_values(left, right);
+    diff_with_options(left, right, &DiffOptions::new().with_detect_moves(true))
+}
 
+/// Replace matching (Removed, Added) pairs with `Moved` entries
+fn collapse_moves(mut entries: Vec<DiffEntry>) -> Vec<DiffEntry> {
     // Find moves: matching (Removed, Added) pairs with equal values
     let mut removed: Vec<(usize, String, Value)> = Vec::new();
     let mut added: Vec<(usize, String, Value)> = Vec::new();
@@ -188,34 +370,12 @@ pub fn diff_with_moves(left: &Value, right: &Value) -> Vec<DiffEntry> {
     entries
 }
 
-/// Compile a Hone file at a specific git ref and return the output value
+/// Compile a Hone file at a specific revision and return the output value
 pub fn compile_at_ref(
     file_path: &std::path::Path,
     git_ref: &str,
 ) -> Result<Value, crate::errors::HoneError> {
-    let file_name = file_path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .ok_or_else(|| crate::errors::HoneError::io_error("invalid file path"))?;
-
-    // Get the file content at the given git ref
-    let output = std::process::Command::new("git")
-        .args(["show", &format!("{}:{}", git_ref, file_name)])
-        .current_dir(file_path.parent().unwrap_or(std::path::Path::new(".")))
-        .output()
-        .map_err(|e| crate::errors::HoneError::io_error(format!("failed to run git: {}", e)))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(crate::errors::HoneError::io_error(format!(
-            "git show failed for {}:{}: {}",
-            git_ref,
-            file_name,
-            stderr.trim()
-        )));
-    }
-
-    let source = String::from_utf8_lossy(&output.stdout).to_string();
+    let source = source_at_ref(file_path, git_ref)?;
 
     // Compile the source
     let base_dir = file_path.parent().unwrap_or(std::path::Path::new("."));
@@ -223,21 +383,99 @@ pub fn compile_at_ref(
     compiler.compile_source(&source)
 }
 
-/// Annotate diff entries with git blame information
+/// The contents of a file at a specific revision: a git ref, a jj revision,
+/// or `dir:PATH`, a directory holding an earlier copy of the file's directory (see
+/// [`vcs::detect`])
+pub fn source_at_ref(
+    file_path: &std::path::Path,
+    git_ref: &str,
+) -> Result<String, crate::errors::HoneError> {
+    let dir = file_path.parent().unwrap_or(std::path::Path::new("."));
+    let vcs = vcs::detect(dir, git_ref)?;
+    source_at_ref_with(vcs.as_ref(), file_path, git_ref)
+}
+
+/// The contents of a file at a revision of `vcs`
+pub fn source_at_ref_with(
+    vcs: &dyn vcs::VcsProvider,
+    file_path: &std::path::Path,
+    rev: &str,
+) -> Result<String, crate::errors::HoneError> {
+    let canonical = crate::resolver::canonicalize(file_path).map_err(|e| {
+        crate::errors::HoneError::io_error(format!(
+            "failed to resolve path {}: {}",
+            file_path.display(),
+            e
+        ))
+    })?;
+    let relative = canonical.strip_prefix(vcs.root()).map_err(|_| {
+        crate::errors::HoneError::io_error(format!(
+            "{} is outside the {} working copy at {}",
+            file_path.display(),
+            vcs.name(),
+            vcs.root().display()
+        ))
+    })?;
+    vcs.read(rev, relative)
+}
+
+/// Diff entries with `@sensitive` values masked, for display. Diff first
+/// and redact after, so a changed secret still shows as a change.
+pub fn redact_entries(entries: &[DiffEntry], redactor: &Redactor) -> Vec<DiffEntry> {
+    entries
+        .iter()
+        .map(|entry| DiffEntry {
+            path: redactor.text(&entry.path),
+            kind: match &entry.kind {
+                DiffKind::Removed(v) => DiffKind::Removed(redactor.value(v)),
+                DiffKind::Added(v) => DiffKind::Added(redactor.value(v)),
+                DiffKind::Changed { left, right } => DiffKind::Changed {
+                    left: redactor.value(left),
+                    right: redactor.value(right),
+                },
+                DiffKind::Moved { from, to, value } => DiffKind::Moved {
+                    from: redactor.text(from),
+                    to: redactor.text(to),
+                    value: redactor.value(value),
+                },
+            },
+        })
+        .collect()
+}
+
+/// Annotate diff entries with information about the last change to the
+/// file, from the version control of its working copy
 pub fn blame_diff(
     entries: &[DiffEntry],
     file_path: &std::path::Path,
 ) -> Vec<(DiffEntry, Option<BlameInfo>)> {
+    let dir = file_path.parent().unwrap_or(std::path::Path::new("."));
+    match vcs::working_copy(dir) {
+        Ok(Some(vcs)) => blame_diff_with(vcs.as_ref(), entries, file_path),
+        _ => entries.iter().map(|entry| (entry.clone(), None)).collect(),
+    }
+}
+
+/// Annotate diff entries with the last change `vcs` records for the file
+pub fn blame_diff_with(
+    vcs: &dyn vcs::VcsProvider,
+    entries: &[DiffEntry],
+    file_path: &std::path::Path,
+) -> Vec<(DiffEntry, Option<BlameInfo>)> {
+    let blame = crate::resolver::canonicalize(file_path)
+        .ok()
+        .and_then(|path| {
+            path.strip_prefix(vcs.root())
+                .ok()
+                .and_then(|relative| vcs.last_change(relative))
+        });
     entries
         .iter()
-        .map(|entry| {
-            let blame = get_blame_for_path(file_path, &entry.path);
-            (entry.clone(), blame)
-        })
+        .map(|entry| (entry.clone(), blame.clone()))
         .collect()
 }
 
-/// Git blame information for a diff entry
+/// Blame information for a diff entry
 #[derive(Debug, Clone)]
 pub struct BlameInfo {
     pub commit: String,
@@ -245,112 +483,70 @@ pub struct BlameInfo {
     pub date: String,
 }
 
-/// Try to get blame info for a specific path in a file
-fn get_blame_for_path(file_path: &std::path::Path, _key_path: &str) -> Option<BlameInfo> {
-    // Run git log to find the last commit that touched this file
-    let output = std::process::Command::new("git")
-        .args(["log", "-1", "--format=%H|%an|%ai", "--"])
-        .arg(file_path)
-        .current_dir(file_path.parent().unwrap_or(std::path::Path::new(".")))
-        .output()
-        .ok()?;
-
-    if !output.status.success() {
-        return None;
-    }
-
-    let line = String::from_utf8_lossy(&output.stdout);
-    let line = line.trim();
-    let parts: Vec<&str> = line.splitn(3, '|').collect();
-    if parts.len() == 3 {
-        Some(BlameInfo {
-            commit: parts[0][..8.min(parts[0].len())].to_string(),
-            author: parts[1].to_string(),
-            date: parts[2].to_string(),
-        })
-    } else {
-        None
-    }
-}
-
 /// Format blame-annotated diff entries as text
 pub fn format_blame_text(entries: &[(DiffEntry, Option<BlameInfo>)]) -> String {
+    format_blame_text_with(entries, &Style::plain())
+}
+
+/// Format blame-annotated diff entries as text, colored and wrapped by `style`
+pub fn format_blame_text_with(entries: &[(DiffEntry, Option<BlameInfo>)], style: &Style) -> String {
     let mut output = String::new();
     for (entry, blame) in entries {
         let blame_prefix = match blame {
-            Some(info) => format!("[{} {} {}] ", info.commit, info.author, info.date),
+            Some(info) => format!(
+                "{} ",
+                style.paint(
+                    style::DIM,
+                    &format!("[{} {} {}]", info.commit, info.author, info.date)
+                )
+            ),
             None => String::new(),
         };
-        match &entry.kind {
-            DiffKind::Added(val) => {
-                output.push_str(&format!(
-                    "{}+ {}: {}\n",
-                    blame_prefix,
-                    entry.path,
-                    format_value_short(val)
-                ));
-            }
-            DiffKind::Removed(val) => {
-                output.push_str(&format!(
-                    "{}- {}: {}\n",
-                    blame_prefix,
-                    entry.path,
-                    format_value_short(val)
-                ));
-            }
-            DiffKind::Changed { left, right } => {
-                output.push_str(&format!(
-                    "{}~ {}: {} -> {}\n",
-                    blame_prefix,
-                    entry.path,
-                    format_value_short(left),
-                    format_value_short(right)
-                ));
-            }
-            DiffKind::Moved { from, to, value } => {
-                output.push_str(&format!(
-                    "{}> {} -> {}: {}\n",
-                    blame_prefix,
-                    from,
-                    to,
-                    format_value_short(value)
-                ));
-            }
-        }
+        output.push_str(&blame_prefix);
+        output.push_str(&diff_line(entry, style));
     }
-    output
+    style.wrap(&output, 4)
 }
 
 /// Format diff entries as human-readable text
 pub fn format_diff_text(entries: &[DiffEntry]) -> String {
-    let mut output = String::new();
-    for entry in entries {
-        match &entry.kind {
-            DiffKind::Added(val) => {
-                output.push_str(&format!("+ {}: {}\n", entry.path, format_value_short(val)));
-            }
-            DiffKind::Removed(val) => {
-                output.push_str(&format!("- {}: {}\n", entry.path, format_value_short(val)));
-            }
-            DiffKind::Changed { left, right } => {
-                output.push_str(&format!(
-                    "~ {}: {} -> {}\n",
-                    entry.path,
-                    format_value_short(left),
-                    format_value_short(right)
-                ));
-            }
-            DiffKind::Moved { from, to, value } => {
-                output.push_str(&format!(
-                    "> {} -> {}: {}\n",
-                    from,
-                    to,
-                    format_value_short(value)
-                ));
-            }
-        }
+    format_diff_text_with(entries, &Style::plain())
+}
+
+/// Format diff entries as human-readable text, colored and wrapped by `style`
+pub fn format_diff_text_with(entries: &[DiffEntry], style: &Style) -> String {
+    let output: String = entries
+        .iter()
+        .map(|entry| diff_line(entry, style))
+        .collect();
+    style.wrap(&output, 4)
+}
+
+/// One line of text diff: a marker, the path(s) and the value(s)
+fn diff_line(entry: &DiffEntry, style: &Style) -> String {
+    match &entry.kind {
+        DiffKind::Added(val) => format!(
+            "{} {}\n",
+            style.paint(style::GREEN, &format!("+ {}:", entry.path)),
+            format_value_short(val)
+        ),
+        DiffKind::Removed(val) => format!(
+            "{} {}\n",
+            style.paint(style::RED, &format!("- {}:", entry.path)),
+            format_value_short(val)
+        ),
+        DiffKind::Changed { left, right } => format!(
+            "{} {} -> {}\n",
+            style.paint(style::YELLOW, &format!("~ {}:", entry.path)),
+            format_value_short(left),
+            format_value_short(right)
+        ),
+        DiffKind::Moved { from, to, value } => format!(
+            "{} {}\n",
+            style.paint(style::CYAN, &format!("> {} -> {}:", from, to)),
+            format_value_short(value)
+        ),
     }
-    output
 }
 
 /// Format diff entries as JSON
@@ -393,9 +589,11 @@ fn format_value_short(val: &Value) -> String {
         Value::Bool(b) => b.to_string(),
         Value::Int(n) => n.to_string(),
         Value::Float(f) => format!("{}", f),
+        Value::Duration(_) | Value::Size(_) => val.to_string(),
         Value::String(s) => format!("\"{}\"", s),
         Value::Array(a) => format!("[{} items]", a.len()),
         Value::Object(o) => format!("{{{} keys}}", o.len()),
+        Value::Function(_) => val.to_string(),
     }
 }
 
@@ -406,6 +604,7 @@ fn value_to_json(val: &Value) -> String {
         Value::Bool(b) => b.to_string(),
         Value::Int(n) => n.to_string(),
         Value::Float(f) => format!("{}", f),
+        Value::Duration(_) | Value::Size(_) => format!("\"{}\"", val),
         Value::String(s) => format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
         Value::Array(a) => {
             let items: Vec<String> = a.iter().map(value_to_json).collect();
@@ -418,6 +617,7 @@ fn value_to_json(val: &Value) -> String {
                 .collect();
             format!("{{{}}}", items.join(", "))
         }
+        Value::Function(_) => format!("\"{}\"", val),
     }
 }
 
@@ -552,6 +752,73 @@ mod tests {
         assert_eq!(entries[0].path, "[1]");
     }
 
+    fn container(name: &str, image: &str) -> Value {
+        Value::Object({
+            let mut m = IndexMap::new();
+            m.insert("name".to_string(), Value::String(name.to_string()));
+            m.insert("image".to_string(), Value::String(image.to_string()));
+            m
+        })
+    }
+
+    #[test]
+    fn test_diff_array_insert_reports_one_element() {
+        let left = Value::Array(vec![container("app", "app:1"), container("log", "log:1")]);
+        let right = Value::Array(vec![
+            container("init", "busybox"),
+            container("app", "app:1"),
+            container("log", "log:1"),
+        ]);
+        let entries = diff_values(&left, &right);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, "[0]");
+        assert!(matches!(&entries[0].kind, DiffKind::Added(_)));
+    }
+
+    #[test]
+    fn test_diff_array_remove_and_change() {
+        let left = Value::Array(vec![
+            Value::Int(1),
+            Value::Int(2),
+            Value::Int(3),
+            Value::Int(4),
+        ]);
+        let right = Value::Array(vec![Value::Int(1), Value::Int(3), Value::Int(5)]);
+        let entries = diff_values(&left, &right);
+        assert_eq!(
+            entries,
+            vec![
+                DiffEntry {
+                    path: "[1]".to_string(),
+                    kind: DiffKind::Removed(Value::Int(2)),
+                },
+                DiffEntry {
+                    path: "[2]".to_string(),
+                    kind: DiffKind::Changed {
+                        left: Value::Int(4),
+                        right: Value::Int(5),
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_array_key_matches_by_field() {
+        let left = Value::Array(vec![container("app", "app:1"), container("log", "log:1")]);
+        let right = Value::Array(vec![container("log", "log:1"), container("app", "app:2")]);
+        let options = DiffOptions::new().with_array_key(Some("name".to_string()));
+        let entries = diff_with_options(&left, &right, &options);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, "[name=app].image");
+
+        // Elements without the key fall back to positional LCS matching
+        let left = Value::Array(vec![Value::Int(1)]);
+        let right = Value::Array(vec![Value::Int(2)]);
+        let entries = diff_with_options(&left, &right, &options);
+        assert_eq!(entries[0].path, "[0]");
+    }
+
     #[test]
     fn test_diff_type_change() {
         let left = Value::String("42".to_string());
@@ -716,4 +983,67 @@ mod tests {
         assert!(json.contains("\"from\": \"old_key\""));
         assert!(json.contains("\"to\": \"new_key\""));
     }
+
+    /// Revisions held in memory, standing in for version control
+    struct Stub {
+        root: std::path::PathBuf,
+        files: Vec<(&'static str, &'static str, &'static str)>,
+    }
+
+    impl vcs::VcsProvider for Stub {
+        fn name(&self) -> &'static str {
+            "stub"
+        }
+
+        fn root(&self) -> &std::path::Path {
+            &self.root
+        }
+
+        fn read(&self, rev: &str, path: &std::path::Path) -> crate::errors::HoneResult<String> {
+            self.files
+                .iter()
+                .find(|(r, p, _)| *r == rev && std::path::Path::new(p) == path)
+                .map(|(_, _, source)| source.to_string())
+                .ok_or_else(|| crate::errors::HoneError::io_error("no such revision"))
+        }
+
+        fn checkout(&self, _rev: &str) -> crate::errors::HoneResult<vcs::Checkout> {
+            Err(crate::errors::HoneError::io_error("not supported"))
+        }
+
+        fn last_change(&self, _path: &std::path::Path) -> Option<BlameInfo> {
+            Some(BlameInfo {
+                commit: "abc12345".into(),
+                author: "Ada".into(),
+                date: "2024-05-01".into(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_source_at_ref_with_stub_provider() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("app")).unwrap();
+        let file = dir.path().join("app/main.hone");
+        std::fs::write(&file, "port: 81\n").unwrap();
+        let stub = Stub {
+            root: crate::resolver::canonicalize(dir.path()).unwrap(),
+            files: vec![("v1", "app/main.hone", "port: 80\n")],
+        };
+
+        assert_eq!(
+            source_at_ref_with(&stub, &file, "v1").unwrap(),
+            "port: 80\n"
+        );
+        assert!(source_at_ref_with(&stub, &file, "v2").is_err());
+
+        let entries = diff_values(&Value::Int(80), &Value::Int(81));
+        let blamed = blame_diff_with(&stub, &entries, &file);
+        assert_eq!(blamed[0].1.as_ref().unwrap().author, "Ada");
+
+        let outside = tempfile::tempdir().unwrap();
+        let other = outside.path().join("main.hone");
+        std::fs::write(&other, "").unwrap();
+        assert!(source_at_ref_with(&stub, &other, "v1").is_err());
+    }
 }
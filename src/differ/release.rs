@@ -0,0 +1,447 @@
+//! `hone release-notes`: what changed in every build target between two
+//! revisions
+//!
+//! The project is exported at each revision (the newer side defaults to the
+//! working tree) through a [`VcsProvider`], every target of the `hone.toml`
+//! is rendered on both sides, and each output file is diffed. The result is
+//! grouped by target and then by output file, with added, changed and removed
+//! keys listed separately, for pasting into a deployment PR. `@sensitive`
+//! values show as `***`.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::errors::{HoneError, HoneResult};
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::serve::{render_target, RenderedTarget};
+
+use super::vcs::{self, VcsProvider};
+use super::{diff_values, format_value_short, redact_entries, DiffEntry, DiffKind};
+
+/// Changes to every target between two refs
+#[derive(Debug, Clone)]
+pub struct ReleaseNotes {
+    pub since: String,
+    /// The newer ref, or `None` for the working tree
+    pub until: Option<String>,
+    pub targets: Vec<TargetChanges>,
+}
+
+/// How one target changed
+#[derive(Debug, Clone)]
+pub struct TargetChanges {
+    pub name: String,
+    pub status: TargetStatus,
+    /// Output files that differ, by path relative to the manifest
+    pub files: Vec<FileChanges>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetStatus {
+    Added,
+    Removed,
+    Changed,
+    Unchanged,
+}
+
+/// How one output file changed
+#[derive(Debug, Clone)]
+pub struct FileChanges {
+    pub path: PathBuf,
+    pub status: FileStatus,
+}
+
+#[derive(Debug, Clone)]
+pub enum FileStatus {
+    Added,
+    Removed,
+    Changed(Vec<DiffEntry>),
+}
+
+/// Compare the targets of the `hone.toml` in or above `dir` at `since`
+/// with `until`, or with the working tree. Revisions are read through the
+/// provider [`vcs::detect`] picks for `since`.
+pub fn release_notes(dir: &Path, since: &str, until: Option<&str>) -> HoneResult<ReleaseNotes> {
+    let manifest = discover_manifest(dir)?;
+    let vcs = vcs::detect(&manifest.root, since)?;
+    notes_for(vcs.as_ref(), manifest, since, until)
+}
+
+/// [`release_notes`] with revisions read through `vcs`
+pub fn release_notes_with(
+    vcs: &dyn VcsProvider,
+    dir: &Path,
+    since: &str,
+    until: Option<&str>,
+) -> HoneResult<ReleaseNotes> {
+    notes_for(vcs, discover_manifest(dir)?, since, until)
+}
+
+fn discover_manifest(dir: &Path) -> HoneResult<Manifest> {
+    Manifest::discover(dir)?.ok_or_else(|| {
+        HoneError::io_error(format!(
+            "no {} found in {} or its parents",
+            MANIFEST_FILE,
+            dir.display()
+        ))
+    })
+}
+
+fn notes_for(
+    vcs: &dyn VcsProvider,
+    manifest: Manifest,
+    since: &str,
+    until: Option<&str>,
+) -> HoneResult<ReleaseNotes> {
+    let root = crate::resolver::canonicalize(&manifest.root)
+        .map_err(|e| HoneError::io_error(format!("failed to resolve path: {}", e)))?;
+    let within = root.strip_prefix(vcs.root()).unwrap_or(Path::new(""));
+
+    let old_checkout = vcs.checkout(since)?;
+    let old = manifest_in(&old_checkout.path().join(within))?;
+    let new_checkout = until.map(|until| vcs.checkout(until)).transpose()?;
+    let new = match &new_checkout {
+        Some(checkout) => manifest_in(&checkout.path().join(within))?,
+        None => Some(manifest),
+    };
+
+    let names: BTreeSet<&String> = old
+        .iter()
+        .chain(&new)
+        .flat_map(|m| m.targets.keys())
+        .collect();
+    let mut targets = Vec::new();
+    for name in names {
+        let render = |manifest: &Option<Manifest>, side: &str| {
+            manifest
+                .as_ref()
+                .filter(|m| m.targets.contains_key(name))
+                .map(|m| {
+                    render_target(m, name)
+                        .map(|rendered| (rendered, m.root.clone()))
+                        .map_err(|e| {
+                            HoneError::compilation_error(format!(
+                                "target '{}' at {}: {}",
+                                name, side, e
+                            ))
+                        })
+                })
+                .transpose()
+        };
+        let before = render(&old, since)?;
+        let after = render(&new, until.unwrap_or("the working tree"))?;
+        targets.push(target_changes(name, before, after));
+    }
+
+    Ok(ReleaseNotes {
+        since: since.to_string(),
+        until: until.map(str::to_string),
+        targets,
+    })
+}
+
+fn target_changes(
+    name: &str,
+    before: Option<(RenderedTarget, PathBuf)>,
+    after: Option<(RenderedTarget, PathBuf)>,
+) -> TargetChanges {
+    let status = match (&before, &after) {
+        (None, _) => TargetStatus::Added,
+        (_, None) => TargetStatus::Removed,
+        _ => TargetStatus::Unchanged,
+    };
+    let mut redactor = crate::evaluator::Redactor::default();
+    let mut files: Vec<(PathBuf, Option<_>, Option<_>)> = Vec::new();
+    for (side, rendered) in [before, after].into_iter().enumerate() {
+        let Some((rendered, root)) = rendered else {
+            continue;
+        };
+        redactor.extend(&rendered.redactor);
+        for file in rendered.files {
+            let path = file
+                .path
+                .strip_prefix(&root)
+                .unwrap_or(&file.path)
+                .to_path_buf();
+            let slot = match files.iter_mut().position(|(p, _, _)| *p == path) {
+                Some(i) => &mut files[i],
+                None => {
+                    files.push((path, None, None));
+                    files.last_mut().unwrap()
+                }
+            };
+            if side == 0 {
+                slot.1 = Some(file.value);
+            } else {
+                slot.2 = Some(file.value);
+            }
+        }
+    }
+
+    let files: Vec<FileChanges> = files
+        .into_iter()
+        .filter_map(|(path, before, after)| {
+            let status = match (before, after) {
+                (None, _) => FileStatus::Added,
+                (_, None) => FileStatus::Removed,
+                (Some(before), Some(after)) => {
+                    let entries = diff_values(&before, &after);
+                    if entries.is_empty() {
+                        return None;
+                    }
+                    FileStatus::Changed(redact_entries(&entries, &redactor))
+                }
+            };
+            Some(FileChanges { path, status })
+        })
+        .collect();
+    let status = match status {
+        TargetStatus::Unchanged if !files.is_empty() => TargetStatus::Changed,
+        status => status,
+    };
+    TargetChanges {
+        name: name.to_string(),
+        status,
+        files,
+    }
+}
+
+/// The manifest at the root of a checkout, if the project had one then
+fn manifest_in(root: &Path) -> HoneResult<Option<Manifest>> {
+    let path = root.join(MANIFEST_FILE);
+    if path.exists() {
+        Manifest::load(&path).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+impl ReleaseNotes {
+    /// Targets that were added, removed or changed
+    fn changed(&self) -> impl Iterator<Item = &TargetChanges> {
+        self.targets
+            .iter()
+            .filter(|t| t.status != TargetStatus::Unchanged)
+    }
+
+    fn title(&self) -> String {
+        match &self.until {
+            Some(until) => format!("Changes from {} to {}", self.since, until),
+            None => format!("Changes since {}", self.since),
+        }
+    }
+
+    /// One line counting the targets by how they changed
+    fn summary(&self) -> String {
+        let count = |status| self.targets.iter().filter(|t| t.status == status).count();
+        let total = self.targets.len();
+        format!(
+            "{} target{}: {} changed, {} added, {} removed, {} unchanged",
+            total,
+            if total == 1 { "" } else { "s" },
+            count(TargetStatus::Changed),
+            count(TargetStatus::Added),
+            count(TargetStatus::Removed),
+            count(TargetStatus::Unchanged),
+        )
+    }
+
+    /// Plain text, indented by target and file
+    pub fn format_text(&self) -> String {
+        let mut out = format!("{}\n", self.title());
+        for target in self.changed() {
+            out.push_str(&format!(
+                "\n{}{}\n",
+                target.name,
+                status_note(target.status)
+            ));
+            for file in &target.files {
+                match &file.status {
+                    FileStatus::Added => {
+                        out.push_str(&format!("  {} (new file)\n", file.path.display()))
+                    }
+                    FileStatus::Removed => {
+                        out.push_str(&format!("  {} (removed file)\n", file.path.display()))
+                    }
+                    FileStatus::Changed(entries) => {
+                        out.push_str(&format!("  {}\n", file.path.display()));
+                        for (heading, group) in groups(entries) {
+                            out.push_str(&format!("    {}:\n", heading));
+                            for entry in group {
+                                out.push_str(&format!("      {}\n", describe(entry, false)));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        out.push_str(&format!("\n{}\n", self.summary()));
+        out
+    }
+
+    /// Markdown, with a heading per target, for PR descriptions
+    pub fn format_markdown(&self) -> String {
+        let mut out = format!("## {}\n", self.title());
+        for target in self.changed() {
+            out.push_str(&format!(
+                "\n### `{}`{}\n",
+                target.name,
+                status_note(target.status)
+            ));
+            for file in &target.files {
+                let path = file.path.display();
+                match &file.status {
+                    FileStatus::Added => out.push_str(&format!("\n- New file `{}`\n", path)),
+                    FileStatus::Removed => out.push_str(&format!("\n- Removed file `{}`\n", path)),
+                    FileStatus::Changed(entries) => {
+                        out.push_str(&format!("\n**`{}`**\n\n", path));
+                        for (heading, group) in groups(entries) {
+                            for entry in group {
+                                out.push_str(&format!("- {} {}\n", heading, describe(entry, true)));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        out.push_str(&format!("\n_{}_\n", self.summary()));
+        out
+    }
+}
+
+fn status_note(status: TargetStatus) -> &'static str {
+    match status {
+        TargetStatus::Added => " (new target)",
+        TargetStatus::Removed => " (removed target)",
+        _ => "",
+    }
+}
+
+/// `entries` split into added, changed and removed keys, leaving out empty
+/// groups. Moves are not detected here, so there are none.
+fn groups(entries: &[DiffEntry]) -> Vec<(&'static str, Vec<&DiffEntry>)> {
+    let pick = |want: fn(&DiffKind) -> bool| -> Vec<&DiffEntry> {
+        entries.iter().filter(|e| want(&e.kind)).collect()
+    };
+    [
+        ("Added", pick(|k| matches!(k, DiffKind::Added(_)))),
+        ("Changed", pick(|k| matches!(k, DiffKind::Changed { .. }))),
+        ("Removed", pick(|k| matches!(k, DiffKind::Removed(_)))),
+    ]
+    .into_iter()
+    .filter(|(_, group)| !group.is_empty())
+    .collect()
+}
+
+/// `path: value`, `path: old -> new` or `path (was value)`, with code spans
+/// in Markdown
+fn describe(entry: &DiffEntry, markdown: bool) -> String {
+    let code = |text: &str| {
+        if markdown {
+            format!("`{}`", text)
+        } else {
+            text.to_string()
+        }
+    };
+    let path = code(&entry.path);
+    match &entry.kind {
+        DiffKind::Added(value) => format!("{}: {}", path, code(&format_value_short(value))),
+        DiffKind::Changed { left, right } => format!(
+            "{}: {} -> {}",
+            path,
+            code(&format_value_short(left)),
+            code(&format_value_short(right))
+        ),
+        DiffKind::Removed(value) => {
+            format!("{} (was {})", path, code(&format_value_short(value)))
+        }
+        DiffKind::Moved { from, value, .. } => format!(
+            "{} (moved from {}): {}",
+            path,
+            code(from),
+            code(&format_value_short(value))
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Value;
+
+    fn notes() -> ReleaseNotes {
+        let changed = vec![
+            DiffEntry {
+                path: "spec.replicas".into(),
+                kind: DiffKind::Changed {
+                    left: Value::Int(2),
+                    right: Value::Int(3),
+                },
+            },
+            DiffEntry {
+                path: "debug".into(),
+                kind: DiffKind::Removed(Value::Bool(true)),
+            },
+            DiffEntry {
+                path: "image".into(),
+                kind: DiffKind::Added(Value::String("api:2".into())),
+            },
+        ];
+        ReleaseNotes {
+            since: "v1.2.0".into(),
+            until: None,
+            targets: vec![
+                TargetChanges {
+                    name: "api".into(),
+                    status: TargetStatus::Changed,
+                    files: vec![FileChanges {
+                        path: "out/api.yaml".into(),
+                        status: FileStatus::Changed(changed),
+                    }],
+                },
+                TargetChanges {
+                    name: "db".into(),
+                    status: TargetStatus::Unchanged,
+                    files: Vec::new(),
+                },
+                TargetChanges {
+                    name: "worker".into(),
+                    status: TargetStatus::Added,
+                    files: vec![FileChanges {
+                        path: "out/worker.yaml".into(),
+                        status: FileStatus::Added,
+                    }],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_format_text_groups_by_target_and_kind() {
+        assert_eq!(
+            notes().format_text(),
+            "Changes since v1.2.0\n\
+             \n\
+             api\n  out/api.yaml\n\
+             \x20   Added:\n      image: \"api:2\"\n\
+             \x20   Changed:\n      spec.replicas: 2 -> 3\n\
+             \x20   Removed:\n      debug (was true)\n\
+             \n\
+             worker (new target)\n  out/worker.yaml (new file)\n\
+             \n\
+             3 targets: 1 changed, 1 added, 0 removed, 1 unchanged\n"
+        );
+    }
+
+    #[test]
+    fn test_format_markdown() {
+        let markdown = notes().format_markdown();
+        assert!(markdown.starts_with("## Changes since v1.2.0\n"));
+        assert!(markdown.contains("### `api`\n\n**`out/api.yaml`**\n\n"));
+        assert!(markdown.contains("- Added `image`: `\"api:2\"`\n"));
+        assert!(markdown.contains("- Changed `spec.replicas`: `2` -> `3`\n"));
+        assert!(markdown.contains("- Removed `debug` (was `true`)\n"));
+        assert!(markdown.contains("### `worker` (new target)\n\n- New file `out/worker.yaml`\n"));
+        assert!(!markdown.contains("`db`"));
+    }
+}
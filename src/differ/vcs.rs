@@ -0,0 +1,456 @@
+//! Version control access for `hone diff --base` and `hone release-notes`
+//!
+//! Earlier revisions are read through a [`VcsProvider`]. [`detect`] picks
+//! one: a `dir:PATH` revision is a plain snapshot of the project (a backup,
+//! an unpacked release tarball), a working copy under a `.jj` directory uses
+//! Jujutsu, and anything else uses git. Snapshots need the prefix so that a
+//! branch named like a directory in the current one is still a branch. Callers that
+//! already have a provider, such as tests stubbing out version control,
+//! pass it to the `_with` functions instead.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::errors::{HoneError, HoneResult};
+
+use super::BlameInfo;
+
+/// Read access to earlier revisions of a working copy
+pub trait VcsProvider {
+    /// Name used in messages: `git`, `jj` or `directory`
+    fn name(&self) -> &'static str;
+
+    /// Top of the working copy; paths passed to the other methods are
+    /// relative to it
+    fn root(&self) -> &Path;
+
+    /// The contents of `path` at `rev`
+    fn read(&self, rev: &str, path: &Path) -> HoneResult<String>;
+
+    /// The whole tree at `rev`, as a directory on disk
+    fn checkout(&self, rev: &str) -> HoneResult<Checkout>;
+
+    /// The last change to `path`, for `hone diff --blame`
+    fn last_change(&self, _path: &Path) -> Option<BlameInfo> {
+        None
+    }
+}
+
+/// Prefix of a revision that names a directory snapshot
+pub const DIR_PREFIX: &str = "dir:";
+
+/// The provider for reading `rev` of the project in `dir`: a directory
+/// snapshot when `rev` is `dir:PATH`, otherwise the working copy's version
+/// control
+pub fn detect(dir: &Path, rev: &str) -> HoneResult<Box<dyn VcsProvider>> {
+    if rev.starts_with(DIR_PREFIX) {
+        let root = crate::resolver::canonicalize(dir)
+            .map_err(|e| HoneError::io_error(format!("failed to resolve path: {}", e)))?;
+        return Ok(Box::new(Directory { root }));
+    }
+    working_copy(dir)?.ok_or_else(|| {
+        let hint = if Path::new(rev).is_dir() {
+            format!(
+                "; to compare with the directory, write {}{}",
+                DIR_PREFIX, rev
+            )
+        } else {
+            String::new()
+        };
+        HoneError::io_error(format!(
+            "{} is not in a git or jj repository{}",
+            dir.display(),
+            hint
+        ))
+    })
+}
+
+/// The version control of the working copy holding `dir`, if any
+pub fn working_copy(dir: &Path) -> HoneResult<Option<Box<dyn VcsProvider>>> {
+    let dir = crate::resolver::canonicalize(dir)
+        .map_err(|e| HoneError::io_error(format!("failed to resolve path: {}", e)))?;
+    // A colocated jj repository also has a `.git`; its revisions are jj's
+    if dir.ancestors().any(|d| d.join(".jj").is_dir()) {
+        return Ok(Some(Box::new(Jj::open(&dir)?)));
+    }
+    Ok(Git::open(&dir)?.map(|git| Box::new(git) as Box<dyn VcsProvider>))
+}
+
+/// A tree exported for reading, removed when dropped if it is a copy
+pub struct Checkout {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl Checkout {
+    /// A fresh temporary directory to export a revision into. Its name is
+    /// unpredictable and it must not exist yet, so nothing another user put
+    /// in the shared temp directory is reused; on Unix only the owner can
+    /// read it.
+    fn temporary() -> HoneResult<Self> {
+        use std::hash::{BuildHasher, Hasher};
+
+        static EXPORTS: AtomicUsize = AtomicUsize::new(0);
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        let mut attempt = 0;
+        loop {
+            let n = EXPORTS.fetch_add(1, Ordering::Relaxed);
+            let mut random = std::collections::hash_map::RandomState::new().build_hasher();
+            random.write_usize(n);
+            let path = std::env::temp_dir().join(format!(
+                "hone_vcs_{}_{}_{:016x}",
+                std::process::id(),
+                n,
+                random.finish()
+            ));
+            match builder.create(&path) {
+                Ok(()) => {
+                    return Ok(Checkout {
+                        path,
+                        temporary: true,
+                    })
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && attempt < 100 => {
+                    attempt += 1;
+                }
+                Err(e) => {
+                    return Err(HoneError::io_error(format!(
+                        "failed to create {}: {}",
+                        path.display(),
+                        e
+                    )))
+                }
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Checkout {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+/// A path relative to the root in the `a/b/c` form version control expects
+fn slash_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Run a version control command in `dir`, returning its stdout
+fn run(program: &str, args: &[&str], dir: &Path) -> HoneResult<Vec<u8>> {
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| HoneError::io_error(format!("failed to run {}: {}", program, e)))?;
+    if !output.status.success() {
+        return Err(HoneError::io_error(format!(
+            "{} {} failed: {}",
+            program,
+            args.first().copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// Parse `commit|author|date`, the format both `git log` and `jj log` are
+/// asked for
+fn parse_blame(line: &str) -> Option<BlameInfo> {
+    let parts: Vec<&str> = line.trim().splitn(3, '|').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    Some(BlameInfo {
+        commit: parts[0][..8.min(parts[0].len())].to_string(),
+        author: parts[1].to_string(),
+        date: parts[2].to_string(),
+    })
+}
+
+/// Revisions are git refs: branches, tags or commits
+pub struct Git {
+    root: PathBuf,
+}
+
+impl Git {
+    /// The git repository holding `dir`, if it is in one
+    pub fn open(dir: &Path) -> HoneResult<Option<Git>> {
+        let Ok(stdout) = run("git", &["rev-parse", "--show-toplevel"], dir) else {
+            return Ok(None);
+        };
+        let toplevel = PathBuf::from(String::from_utf8_lossy(&stdout).trim());
+        let root = crate::resolver::canonicalize(&toplevel)
+            .map_err(|e| HoneError::io_error(format!("failed to resolve path: {}", e)))?;
+        Ok(Some(Git { root }))
+    }
+
+    /// Refuse a ref git would take for an option
+    fn check_ref(rev: &str) -> HoneResult<()> {
+        if rev.starts_with('-') {
+            return Err(HoneError::io_error(format!("'{}' is not a git ref", rev)));
+        }
+        Ok(())
+    }
+}
+
+impl VcsProvider for Git {
+    fn name(&self) -> &'static str {
+        "git"
+    }
+
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn read(&self, rev: &str, path: &Path) -> HoneResult<String> {
+        Git::check_ref(rev)?;
+        let spec = format!("{}:{}", rev, slash_path(path));
+        let stdout = run("git", &["show", &spec], &self.root)?;
+        Ok(String::from_utf8_lossy(&stdout).into_owned())
+    }
+
+    fn checkout(&self, rev: &str) -> HoneResult<Checkout> {
+        Git::check_ref(rev)?;
+        let checkout = Checkout::temporary()?;
+        let mut git = Command::new("git")
+            .args(["archive", "--format=tar", rev])
+            .current_dir(&self.root)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| HoneError::io_error(format!("failed to run git: {}", e)))?;
+        let archive = git.stdout.take().expect("git stdout is piped");
+        let tar = Command::new("tar")
+            .arg("-xf")
+            .arg("-")
+            .arg("-C")
+            .arg(&checkout.path)
+            .stdin(archive)
+            .output()
+            .map_err(|e| HoneError::io_error(format!("failed to run tar: {}", e)))?;
+        let git = git
+            .wait_with_output()
+            .map_err(|e| HoneError::io_error(format!("failed to run git: {}", e)))?;
+        if !git.status.success() {
+            return Err(HoneError::io_error(format!(
+                "git archive failed for {}: {}",
+                rev,
+                String::from_utf8_lossy(&git.stderr).trim()
+            )));
+        }
+        if !tar.status.success() {
+            return Err(HoneError::io_error(format!(
+                "failed to unpack {}: {}",
+                rev,
+                String::from_utf8_lossy(&tar.stderr).trim()
+            )));
+        }
+        Ok(checkout)
+    }
+
+    fn last_change(&self, path: &Path) -> Option<BlameInfo> {
+        let path = slash_path(path);
+        let stdout = run(
+            "git",
+            &["log", "-1", "--format=%H|%an|%ai", "--", &path],
+            &self.root,
+        )
+        .ok()?;
+        parse_blame(&String::from_utf8_lossy(&stdout))
+    }
+}
+
+/// Revisions are Jujutsu revsets: change ids, bookmarks, `@-`
+pub struct Jj {
+    root: PathBuf,
+}
+
+impl Jj {
+    pub fn open(dir: &Path) -> HoneResult<Jj> {
+        let stdout = run("jj", &["root"], dir)?;
+        let root = PathBuf::from(String::from_utf8_lossy(&stdout).trim());
+        let root = crate::resolver::canonicalize(&root)
+            .map_err(|e| HoneError::io_error(format!("failed to resolve path: {}", e)))?;
+        Ok(Jj { root })
+    }
+
+    /// A fileset matching exactly `path`
+    fn fileset(path: &str) -> String {
+        format!("root-file:{:?}", path)
+    }
+}
+
+impl VcsProvider for Jj {
+    fn name(&self) -> &'static str {
+        "jj"
+    }
+
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn read(&self, rev: &str, path: &Path) -> HoneResult<String> {
+        let fileset = Jj::fileset(&slash_path(path));
+        let stdout = run("jj", &["file", "show", "-r", rev, &fileset], &self.root)?;
+        Ok(String::from_utf8_lossy(&stdout).into_owned())
+    }
+
+    /// jj has no archive command, so each file is written out one by one
+    fn checkout(&self, rev: &str) -> HoneResult<Checkout> {
+        let checkout = Checkout::temporary()?;
+        let stdout = run("jj", &["file", "list", "-r", rev], &self.root)?;
+        for file in String::from_utf8_lossy(&stdout).lines() {
+            let fileset = Jj::fileset(file);
+            let contents = run("jj", &["file", "show", "-r", rev, &fileset], &self.root)?;
+            let dest = checkout.path.join(file);
+            if let Some(parent) = dest.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| {
+                    HoneError::io_error(format!("failed to create {}: {}", parent.display(), e))
+                })?;
+            }
+            std::fs::write(&dest, contents).map_err(|e| {
+                HoneError::io_error(format!("failed to write {}: {}", dest.display(), e))
+            })?;
+        }
+        Ok(checkout)
+    }
+
+    fn last_change(&self, path: &Path) -> Option<BlameInfo> {
+        let revset = format!("latest(::@ & files({}))", Jj::fileset(&slash_path(path)));
+        let template =
+            "commit_id ++ \"|\" ++ author.name() ++ \"|\" ++ author.timestamp() ++ \"\\n\"";
+        let stdout = run(
+            "jj",
+            &["log", "--no-graph", "-r", &revset, "-T", template],
+            &self.root,
+        )
+        .ok()?;
+        parse_blame(&String::from_utf8_lossy(&stdout))
+    }
+}
+
+/// Revisions are `dir:PATH`, a directory holding a copy of the project, with
+/// `root` standing for the directory they copy
+pub struct Directory {
+    root: PathBuf,
+}
+
+impl Directory {
+    pub fn new(root: impl Into<PathBuf>) -> Directory {
+        Directory { root: root.into() }
+    }
+
+    fn snapshot(rev: &str) -> HoneResult<&Path> {
+        let dir = Path::new(rev.strip_prefix(DIR_PREFIX).unwrap_or(rev));
+        if !dir.is_dir() {
+            return Err(HoneError::io_error(format!(
+                "'{}' is not a directory",
+                dir.display()
+            )));
+        }
+        Ok(dir)
+    }
+}
+
+impl VcsProvider for Directory {
+    fn name(&self) -> &'static str {
+        "directory"
+    }
+
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn read(&self, rev: &str, path: &Path) -> HoneResult<String> {
+        let file = Directory::snapshot(rev)?.join(path);
+        std::fs::read_to_string(&file)
+            .map_err(|e| HoneError::io_error(format!("failed to read {}: {}", file.display(), e)))
+    }
+
+    /// The snapshot is read in place
+    fn checkout(&self, rev: &str) -> HoneResult<Checkout> {
+        Ok(Checkout {
+            path: Directory::snapshot(rev)?.to_path_buf(),
+            temporary: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_snapshots() {
+        let project = tempfile::tempdir().unwrap();
+        let snapshot = tempfile::tempdir().unwrap();
+        std::fs::create_dir(snapshot.path().join("app")).unwrap();
+        std::fs::write(snapshot.path().join("app/main.hone"), "port: 80\n").unwrap();
+        let rev = &format!("dir:{}", snapshot.path().display());
+
+        let vcs = detect(project.path(), rev).unwrap();
+        assert_eq!(vcs.name(), "directory");
+        // Without the prefix a directory is not a revision
+        let path = snapshot.path().to_str().unwrap();
+        let err = detect(project.path(), path).err().unwrap().to_string();
+        assert!(err.contains(&format!("write dir:{}", path)), "{}", err);
+        assert_eq!(
+            vcs.read(rev, Path::new("app/main.hone")).unwrap(),
+            "port: 80\n"
+        );
+        assert!(vcs.read(rev, Path::new("missing.hone")).is_err());
+
+        // Reading in place leaves the snapshot alone
+        drop(vcs.checkout(rev).unwrap());
+        assert!(snapshot.path().join("app/main.hone").exists());
+        assert!(vcs.checkout("dir:no-such-directory").is_err());
+    }
+
+    #[test]
+    fn test_temporary_checkouts_are_fresh_and_private() {
+        let a = Checkout::temporary().unwrap();
+        let b = Checkout::temporary().unwrap();
+        assert_ne!(a.path(), b.path());
+        assert_eq!(std::fs::read_dir(a.path()).unwrap().count(), 0);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(a.path()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        let path = a.path().to_path_buf();
+        drop(a);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_parse_blame() {
+        let info = parse_blame("0123456789abcdef|Ada|2024-05-01 10:00:00 +0000\n").unwrap();
+        assert_eq!(info.commit, "01234567");
+        assert_eq!(info.author, "Ada");
+        assert_eq!(info.date, "2024-05-01 10:00:00 +0000");
+        assert!(parse_blame("").is_none());
+    }
+
+    #[test]
+    fn test_slash_path() {
+        let path: PathBuf = ["app", "env", "prod.hone"].iter().collect();
+        assert_eq!(slash_path(&path), "app/env/prod.hone");
+    }
+}
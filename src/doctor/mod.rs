@@ -0,0 +1,599 @@
+//! Self-diagnostics for `hone doctor`
+//!
+//! Each check looks at one thing hone depends on outside the source it
+//! compiles: the build cache directory, git (for `diff` and `release-notes`
+//! against revisions), the `hone` binary editors start as a language server,
+//! the project's `hone.toml`, and the imports of every `.hone` file in the
+//! workspace. Nothing is changed, and a failed check explains what is broken
+//! and what still works. The report ends with the version and platform
+//! details a bug report needs.
+
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::Command;
+
+use crate::cache::cache_root;
+use crate::compiler::workspace;
+use crate::errors::HoneError;
+use crate::manifest::{find_manifest, Manifest, MANIFEST_FILE};
+use crate::resolver::ImportResolver;
+use crate::style::{self, Style};
+
+/// How a check went
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// Works, but something is likely to surprise the user
+    Warn,
+    /// Broken; a feature won't work until it is fixed
+    Fail,
+}
+
+impl Status {
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "fail",
+        }
+    }
+}
+
+/// The outcome of one check
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub summary: String,
+    /// One line per problem found, or advice on fixing it
+    pub details: Vec<String>,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, summary: impl Into<String>) -> Self {
+        Check {
+            name,
+            status,
+            summary: summary.into(),
+            details: Vec::new(),
+        }
+    }
+
+    fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.details.push(detail.into());
+        self
+    }
+}
+
+/// Every check, and the environment to quote in a bug report
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub checks: Vec<Check>,
+    pub environment: Vec<(&'static str, String)>,
+}
+
+impl Report {
+    /// How many checks failed
+    pub fn failed(&self) -> usize {
+        self.checks
+            .iter()
+            .filter(|c| c.status == Status::Fail)
+            .count()
+    }
+
+    pub fn format_text(&self, style: &Style) -> String {
+        let width = self.checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+        let mut out = String::new();
+        for check in &self.checks {
+            let color = match check.status {
+                Status::Ok => style::GREEN,
+                Status::Warn => style::YELLOW,
+                Status::Fail => style::RED,
+            };
+            out.push_str(&format!(
+                "{}  {:<width$}  {}\n",
+                style.paint(color, &format!("{:<4}", check.status.name())),
+                check.name,
+                check.summary,
+                width = width
+            ));
+            for detail in &check.details {
+                out.push_str(&format!(
+                    "{:<4}  {:<width$}  {}\n",
+                    "",
+                    "",
+                    detail,
+                    width = width
+                ));
+            }
+        }
+
+        out.push_str("\nEnvironment (include this in bug reports):\n");
+        let width = self
+            .environment
+            .iter()
+            .map(|(k, _)| k.len())
+            .max()
+            .unwrap_or(0);
+        for (key, value) in &self.environment {
+            out.push_str(&format!("  {:<width$}  {}\n", key, value, width = width));
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let checks: Vec<serde_json::Value> = self
+            .checks
+            .iter()
+            .map(|c| {
+                serde_json::json!({
+                    "name": c.name,
+                    "status": c.status.name(),
+                    "summary": c.summary,
+                    "details": c.details,
+                })
+            })
+            .collect();
+        let environment: serde_json::Map<String, serde_json::Value> = self
+            .environment
+            .iter()
+            .map(|(k, v)| (k.to_string(), serde_json::Value::String(v.clone())))
+            .collect();
+        let json = serde_json::json!({ "checks": checks, "environment": environment });
+        serde_json::to_string_pretty(&json).unwrap_or_default() + "\n"
+    }
+}
+
+/// Run every check for the project containing `dir`
+pub fn diagnose(dir: &Path) -> Report {
+    let git = git_version();
+    let cache_dir = cache_root().map(|root| root.join("v1"));
+    let exe = std::env::current_exe().ok();
+    let manifest = find_manifest(dir);
+    let root = manifest
+        .as_deref()
+        .and_then(Path::parent)
+        .unwrap_or(dir)
+        .to_path_buf();
+
+    let checks = vec![
+        check_cache(cache_dir.as_deref()),
+        check_git(git.as_deref(), dir),
+        check_lsp(std::env::var_os("PATH").as_deref(), exe.as_deref()),
+        check_manifest(manifest.as_deref()),
+        check_imports(&root),
+    ];
+
+    let mut features = Vec::new();
+    if cfg!(feature = "cli") {
+        features.push("cli");
+    }
+    if cfg!(feature = "lsp") {
+        features.push("lsp");
+    }
+    if cfg!(feature = "self-update") {
+        features.push("self-update");
+    }
+    let environment = vec![
+        ("hone", env!("CARGO_PKG_VERSION").to_string()),
+        (
+            "platform",
+            format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+        ),
+        ("features", features.join(", ")),
+        ("executable", display_or(exe.as_deref(), "unknown")),
+        ("cache", display_or(cache_dir.as_deref(), "none")),
+        ("git", git.unwrap_or_else(|| "not found".to_string())),
+    ];
+    Report {
+        checks,
+        environment,
+    }
+}
+
+fn display_or(path: Option<&Path>, missing: &str) -> String {
+    path.map_or_else(|| missing.to_string(), |p| p.display().to_string())
+}
+
+/// The build cache directory exists (or can be created) and is writable
+pub fn check_cache(dir: Option<&Path>) -> Check {
+    const NAME: &str = "cache";
+    let Some(dir) = dir else {
+        return Check::new(NAME, Status::Fail, "no cache directory")
+            .with_detail("set XDG_CACHE_HOME or HOME; until then builds are never cached");
+    };
+
+    if !dir.exists() {
+        let parent = dir.ancestors().find(|d| d.exists());
+        let writable = parent
+            .and_then(|p| std::fs::metadata(p).ok())
+            .is_some_and(|m| !m.permissions().readonly());
+        return if writable {
+            Check::new(
+                NAME,
+                Status::Ok,
+                format!(
+                    "{} does not exist yet; the first cached build creates it",
+                    dir.display()
+                ),
+            )
+        } else {
+            Check::new(
+                NAME,
+                Status::Fail,
+                format!("{} does not exist and cannot be created", dir.display()),
+            )
+            .with_detail("builds will not be cached; fix the permissions or use --no-cache")
+        };
+    }
+    if !dir.is_dir() {
+        return Check::new(
+            NAME,
+            Status::Fail,
+            format!("{} is not a directory", dir.display()),
+        )
+        .with_detail("remove it so the cache can be created");
+    }
+
+    let probe = dir.join(format!(".doctor-{}.tmp", std::process::id()));
+    if let Err(e) = std::fs::write(&probe, b"") {
+        return Check::new(
+            NAME,
+            Status::Fail,
+            format!("{} is not writable: {}", dir.display(), e),
+        )
+        .with_detail("builds will not be cached; fix the permissions or use --no-cache");
+    }
+    let _ = std::fs::remove_file(&probe);
+
+    let (entries, bytes) = cache_usage(dir);
+    Check::new(
+        NAME,
+        Status::Ok,
+        format!(
+            "{} is writable ({} entries, {})",
+            dir.display(),
+            entries,
+            human_bytes(bytes)
+        ),
+    )
+}
+
+/// Cached results under `dir` and their total size
+fn cache_usage(dir: &Path) -> (usize, u64) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return (0, 0);
+    };
+    let mut usage = (0, 0);
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            let (n, size) = cache_usage(&path);
+            usage = (usage.0 + n, usage.1 + size);
+        } else if path.extension().and_then(|e| e.to_str()) == Some("json") {
+            usage.0 += 1;
+            usage.1 += entry.metadata().map(|m| m.len()).unwrap_or(0);
+        }
+    }
+    usage
+}
+
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// `git --version`, if git can be run
+fn git_version() -> Option<String> {
+    let output = Command::new("git").arg("--version").output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// git is available for `diff` and `release-notes` against revisions
+pub fn check_git(version: Option<&str>, dir: &Path) -> Check {
+    const NAME: &str = "git";
+    let Some(version) = version else {
+        return Check::new(NAME, Status::Warn, "git not found on PATH").with_detail(
+            "`hone diff` and `release-notes` need git to read revisions; comparing files still works",
+        );
+    };
+    let toplevel = Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .current_dir(dir)
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string());
+    match toplevel {
+        Some(root) => Check::new(
+            NAME,
+            Status::Ok,
+            format!("{}; repository at {}", version, root),
+        ),
+        None => Check::new(
+            NAME,
+            Status::Ok,
+            format!("{}; {} is not in a git repository", version, dir.display()),
+        ),
+    }
+}
+
+/// Editors start `hone lsp` from PATH unless told otherwise; that `hone`
+/// should be this binary
+pub fn check_lsp(path_var: Option<&OsStr>, exe: Option<&Path>) -> Check {
+    const NAME: &str = "lsp";
+    if !cfg!(feature = "lsp") {
+        return Check::new(
+            NAME,
+            Status::Fail,
+            "this binary was built without the language server",
+        )
+        .with_detail("rebuild with the `lsp` feature (on by default) for `hone lsp`");
+    }
+    let name = format!("hone{}", std::env::consts::EXE_SUFFIX);
+    let found = path_var
+        .map(|p| std::env::split_paths(p).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|dir| dir.join(&name))
+        .find(|candidate| candidate.is_file());
+    let exe_display = display_or(exe, "this binary");
+
+    let Some(found) = found else {
+        return Check::new(NAME, Status::Warn, "hone is not on PATH").with_detail(format!(
+            "point your editor at {} (`hone.serverPath` in VS Code)",
+            exe_display
+        ));
+    };
+    let same = match exe {
+        Some(exe) => {
+            let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+            canonical(&found) == canonical(exe)
+        }
+        None => false,
+    };
+    if same {
+        Check::new(
+            NAME,
+            Status::Ok,
+            format!("editors will start {} lsp", found.display()),
+        )
+    } else {
+        Check::new(
+            NAME,
+            Status::Warn,
+            format!("PATH finds {}, not this binary", found.display()),
+        )
+        .with_detail(format!(
+            "editors will start that one; to use this build, set `hone.serverPath` to {}",
+            exe_display
+        ))
+    }
+}
+
+/// The nearest `hone.toml` parses and its targets' entry files exist
+pub fn check_manifest(path: Option<&Path>) -> Check {
+    const NAME: &str = "manifest";
+    let Some(path) = path else {
+        return Check::new(NAME, Status::Ok, format!("no {} found", MANIFEST_FILE));
+    };
+    let manifest = match Manifest::load(path) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            return Check::new(NAME, Status::Fail, format!("{} is invalid", path.display()))
+                .with_detail(e.to_string())
+        }
+    };
+
+    let missing: Vec<String> = manifest
+        .targets
+        .iter()
+        .filter(|(_, target)| !manifest.root.join(&target.entry).is_file())
+        .map(|(name, target)| {
+            format!(
+                "target '{}': entry {} not found",
+                name,
+                target.entry.display()
+            )
+        })
+        .collect();
+    let summary = format!(
+        "{} ({} target{})",
+        path.display(),
+        manifest.targets.len(),
+        if manifest.targets.len() == 1 { "" } else { "s" }
+    );
+    let mut check = Check::new(
+        NAME,
+        if missing.is_empty() {
+            Status::Ok
+        } else {
+            Status::Fail
+        },
+        summary,
+    );
+    check.details = missing;
+    check
+}
+
+/// Every `import`, `from` and `include` in the workspace under `root` names a
+/// file that exists. Files that don't parse are left to `hone check --all`.
+pub fn check_imports(root: &Path) -> Check {
+    const NAME: &str = "imports";
+    let files = match workspace::discover(root) {
+        Ok(files) => files,
+        Err(e) => return Check::new(NAME, Status::Fail, e.to_string()),
+    };
+
+    let mut dangling = Vec::new();
+    for file in &files {
+        let Ok(source) = std::fs::read_to_string(file) else {
+            continue;
+        };
+        let dir = file.parent().unwrap_or(root);
+        let mut resolver = ImportResolver::new(dir);
+        if let Err(HoneError::ImportNotFound { span, path, .. }) =
+            resolver.resolve_source(file, source.as_str())
+        {
+            let line = source[..span.offset().min(source.len())]
+                .matches('\n')
+                .count()
+                + 1;
+            let name = file.strip_prefix(root).unwrap_or(file);
+            dangling.push(format!(
+                "{}:{}: \"{}\" not found",
+                name.display(),
+                line,
+                path
+            ));
+        }
+    }
+
+    let checked = format!(
+        "{} file{} under {}",
+        files.len(),
+        if files.len() == 1 { "" } else { "s" },
+        root.display()
+    );
+    if dangling.is_empty() {
+        Check::new(
+            NAME,
+            Status::Ok,
+            format!("no dangling imports in {}", checked),
+        )
+    } else {
+        let mut check = Check::new(
+            NAME,
+            Status::Fail,
+            format!("{} dangling import(s) in {}", dangling.len(), checked),
+        );
+        check.details = dangling;
+        check
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_check_cache() {
+        let dir = TempDir::new().unwrap();
+        let cache = dir.path().join("hone").join("v1");
+        let check = check_cache(Some(&cache));
+        assert_eq!(check.status, Status::Ok, "{:?}", check);
+        assert!(check.summary.contains("does not exist yet"));
+
+        std::fs::create_dir_all(cache.join("ab")).unwrap();
+        std::fs::write(cache.join("ab").join("abcd.json"), "{}").unwrap();
+        let check = check_cache(Some(&cache));
+        assert_eq!(check.status, Status::Ok);
+        assert!(
+            check.summary.contains("1 entries, 2 B"),
+            "{}",
+            check.summary
+        );
+        assert_eq!(std::fs::read_dir(&cache).unwrap().count(), 1);
+
+        let file = dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+        assert_eq!(check_cache(Some(&file)).status, Status::Fail);
+        assert_eq!(check_cache(None).status, Status::Fail);
+    }
+
+    #[test]
+    fn test_check_lsp() {
+        let dir = TempDir::new().unwrap();
+        let exe = dir
+            .path()
+            .join("bin")
+            .join(format!("hone{}", std::env::consts::EXE_SUFFIX));
+        std::fs::create_dir_all(exe.parent().unwrap()).unwrap();
+        std::fs::write(&exe, "").unwrap();
+        let other = dir.path().join("other");
+
+        let path = std::env::join_paths([&other, &exe.parent().unwrap().to_path_buf()]).unwrap();
+        assert_eq!(check_lsp(Some(&path), Some(&exe)).status, Status::Ok);
+
+        let elsewhere = dir.path().join("elsewhere");
+        let check = check_lsp(Some(&path), Some(&elsewhere));
+        assert_eq!(check.status, Status::Warn);
+        assert!(check.summary.contains("not this binary"));
+
+        let check = check_lsp(Some(other.as_os_str()), Some(&exe));
+        assert_eq!(check.status, Status::Warn);
+        assert!(check.details[0].contains("hone.serverPath"));
+    }
+
+    #[test]
+    fn test_check_manifest() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(MANIFEST_FILE);
+        assert_eq!(check_manifest(None).status, Status::Ok);
+
+        std::fs::write(&path, "[targets.api]\nentry = \"app.hone\"\n").unwrap();
+        let check = check_manifest(Some(&path));
+        assert_eq!(check.status, Status::Fail);
+        assert!(check.details[0].contains("needs exactly one of"));
+
+        std::fs::write(
+            &path,
+            "[targets.api]\nentry = \"app.hone\"\noutput = \"out.yaml\"\n",
+        )
+        .unwrap();
+        let check = check_manifest(Some(&path));
+        assert_eq!(check.status, Status::Fail);
+        assert_eq!(
+            check.details,
+            vec!["target 'api': entry app.hone not found"]
+        );
+
+        std::fs::write(dir.path().join("app.hone"), "a: 1\n").unwrap();
+        let check = check_manifest(Some(&path));
+        assert_eq!(check.status, Status::Ok);
+        assert!(check.summary.ends_with("(1 target)"));
+    }
+
+    #[test]
+    fn test_check_imports() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("base.hone"), "port: 80\n").unwrap();
+        std::fs::create_dir_all(dir.path().join("app")).unwrap();
+        std::fs::write(
+            dir.path().join("app/main.hone"),
+            "from \"../base.hone\"\nname: \"api\"\n",
+        )
+        .unwrap();
+        let check = check_imports(dir.path());
+        assert_eq!(check.status, Status::Ok, "{:?}", check);
+
+        std::fs::write(
+            dir.path().join("app/broken.hone"),
+            "# config\nimport \"./gone.hone\" as gone\nx: gone.x\n",
+        )
+        .unwrap();
+        // Files that don't parse are not this check's concern
+        std::fs::write(dir.path().join("bad.hone"), "a: \n").unwrap();
+        let check = check_imports(dir.path());
+        assert_eq!(check.status, Status::Fail);
+        let expected = format!(
+            "{}:2: \"./gone.hone\" not found",
+            Path::new("app").join("broken.hone").display()
+        );
+        assert_eq!(check.details, vec![expected]);
+    }
+}
@@ -0,0 +1,221 @@
+//! CSV emitter for Hone values
+//!
+//! Writes an array of flat objects as a header row plus one row per object,
+//! for loading into spreadsheets. The header is every key in first-seen
+//! order; a row without a key, or with `null`, leaves that cell empty. Cells
+//! are quoted per RFC 4180 only when they contain a comma, quote, or line
+//! break, or start or end with a space.
+//!
+//! Hone documents are objects, so a top-level object with one key holding an
+//! array (`machines: [...]`) emits that array.
+
+use std::io::Write;
+
+use indexmap::IndexSet;
+
+use super::output::Output;
+use super::Emitter;
+use crate::errors::{HoneError, HoneResult};
+use crate::evaluator::Value;
+use crate::lexer::{duration, size};
+
+/// CSV output emitter
+pub struct CsvEmitter;
+
+impl CsvEmitter {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Emit the header, then each row
+    fn emit_rows(&self, result: &mut Output, value: &Value) -> HoneResult<()> {
+        let rows = Self::rows(value)?;
+
+        let mut header: IndexSet<&str> = IndexSet::new();
+        for (i, row) in rows.iter().enumerate() {
+            let Value::Object(obj) = row else {
+                return Err(HoneError::io_error(format!(
+                    "csv output requires an array of objects, but row {} is {}",
+                    i,
+                    row.type_name()
+                )));
+            };
+            header.extend(obj.keys().map(String::as_str));
+        }
+        if header.is_empty() {
+            return Ok(());
+        }
+
+        let cells: Vec<String> = header.iter().map(|key| Self::quote(key)).collect();
+        result.push_str(&cells.join(","));
+        result.push('\n');
+        result.section()?;
+
+        for (i, row) in rows.iter().enumerate() {
+            let Value::Object(obj) = row else {
+                unreachable!("rows were checked above");
+            };
+            let mut cells = Vec::with_capacity(header.len());
+            for key in &header {
+                cells.push(match obj.get(*key) {
+                    Some(value) => Self::cell(value, i, key)?,
+                    None => String::new(),
+                });
+            }
+            result.push_str(&cells.join(","));
+            result.push('\n');
+            result.section()?;
+        }
+        Ok(())
+    }
+
+    /// The array to write, unwrapping a single-key top-level object
+    fn rows(value: &Value) -> HoneResult<&[Value]> {
+        match value {
+            Value::Array(rows) => Ok(rows),
+            Value::Object(obj) if obj.len() == 1 => match obj.values().next() {
+                Some(Value::Array(rows)) => Ok(rows),
+                _ => Err(Self::shape_error()),
+            },
+            _ => Err(Self::shape_error()),
+        }
+    }
+
+    fn shape_error() -> HoneError {
+        HoneError::io_error(
+            "csv output requires an array of objects, or an object with a single key holding one"
+                .to_string(),
+        )
+    }
+
+    /// Text of one cell
+    fn cell(value: &Value, row: usize, key: &str) -> HoneResult<String> {
+        Ok(match value {
+            Value::Null => String::new(),
+            Value::Bool(b) => b.to_string(),
+            Value::Int(n) => n.to_string(),
+            Value::Float(n) => {
+                if !n.is_finite() {
+                    return Err(HoneError::io_error(format!(
+                        "csv output does not support non-finite numbers ({} at row {}, '{}')",
+                        n, row, key
+                    )));
+                }
+                if n.fract() == 0.0 {
+                    format!("{:.1}", n)
+                } else {
+                    n.to_string()
+                }
+            }
+            Value::Duration(ms) => Self::quote(&duration::format(*ms)),
+            Value::Size(bytes) => Self::quote(&size::format(*bytes)),
+            Value::String(s) => Self::quote(s),
+            Value::Function(_) => return Err(super::function_error(value)),
+            Value::Array(_) | Value::Object(_) => {
+                return Err(HoneError::io_error(format!(
+                    "csv output requires scalar fields, but row {} has {} at '{}'",
+                    row,
+                    value.type_name(),
+                    key
+                )))
+            }
+        })
+    }
+
+    /// Quote a field if it would otherwise be read differently
+    fn quote(s: &str) -> String {
+        let needs_quotes =
+            s.contains([',', '"', '\n', '\r']) || s.starts_with(' ') || s.ends_with(' ');
+        if needs_quotes {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_string()
+        }
+    }
+}
+
+impl Default for CsvEmitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Emitter for CsvEmitter {
+    fn emit(&self, value: &Value) -> HoneResult<String> {
+        let mut result = Output::new();
+        self.emit_rows(&mut result, value)?;
+        result.finish()
+    }
+
+    fn emit_to_writer<W: Write>(&self, value: &Value, writer: &mut W) -> HoneResult<()> {
+        let mut result = Output::to_writer(writer);
+        self.emit_rows(&mut result, value)?;
+        result.finish().map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    fn obj(pairs: &[(&str, Value)]) -> Value {
+        let mut map = IndexMap::new();
+        for (k, v) in pairs {
+            map.insert(k.to_string(), v.clone());
+        }
+        Value::Object(map)
+    }
+
+    #[test]
+    fn test_header_is_union_of_keys() {
+        let emitter = CsvEmitter::new();
+        let value = obj(&[(
+            "machines",
+            Value::Array(vec![
+                obj(&[
+                    ("name", Value::String("web-1".into())),
+                    ("cpus", Value::Int(4)),
+                ]),
+                obj(&[
+                    ("name", Value::String("db-1".into())),
+                    ("ssd", Value::Bool(true)),
+                    ("cpus", Value::Null),
+                ]),
+            ]),
+        )]);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "name,cpus,ssd\nweb-1,4,\ndb-1,,true\n"
+        );
+    }
+
+    #[test]
+    fn test_quotes_only_when_needed() {
+        let emitter = CsvEmitter::new();
+        let value = Value::Array(vec![obj(&[
+            ("plain", Value::String("a b".into())),
+            ("comma", Value::String("x,y".into())),
+            ("quote", Value::String("say \"hi\"".into())),
+            ("lines", Value::String("one\ntwo".into())),
+            ("padded", Value::String(" p".into())),
+            ("ratio", Value::Float(2.0)),
+        ])]);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "plain,comma,quote,lines,padded,ratio\n\
+             a b,\"x,y\",\"say \"\"hi\"\"\",\"one\ntwo\",\" p\",2.0\n"
+        );
+    }
+
+    #[test]
+    fn test_rejects_other_shapes() {
+        let emitter = CsvEmitter::new();
+        assert!(emitter.emit(&obj(&[("a", Value::Int(1))])).is_err());
+        assert!(emitter.emit(&Value::Array(vec![Value::Int(1)])).is_err());
+        let nested = Value::Array(vec![obj(&[("tags", Value::Array(vec![]))])]);
+        let err = emitter.emit(&nested).unwrap_err().to_string();
+        assert!(err.contains("'tags'"), "{}", err);
+        assert_eq!(emitter.emit(&Value::Array(vec![])).unwrap(), "");
+    }
+}
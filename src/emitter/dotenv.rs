@@ -3,59 +3,166 @@
 //! Flattens nested objects into KEY=VALUE pairs using double-underscore (`__`)
 //! separation for hierarchy levels (matching .NET configuration convention)
 //! and uppercase keys. Suitable for .env files and environment variable configs.
+//!
+//! The separator is configurable (`_` gives docker-compose style `DB_HOST`),
+//! as is how arrays are written: see [`DotenvArrays`]. Two paths that flatten
+//! to the same key are an error rather than one silently replacing the other.
+
+use std::io::Write;
 
+use super::json::JsonEmitter;
+use super::output::Output;
 use super::Emitter;
 use crate::errors::{HoneError, HoneResult};
 use crate::evaluator::Value;
+use crate::lexer::{duration, size};
+
+/// How arrays are written to .env output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DotenvArrays {
+    /// Comma-join arrays of scalars (`PORTS=80,443`); index arrays that
+    /// contain objects or arrays (`SERVERS__0__NAME=api`)
+    #[default]
+    Join,
+    /// One key per element, always (`PORTS__0=80`)
+    Indexed,
+    /// The whole array as compact JSON (`PORTS=[80,443]`)
+    Json,
+}
+
+impl DotenvArrays {
+    /// Parse a style name: `join`, `indexed`, or `json`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "join" => Some(DotenvArrays::Join),
+            "indexed" => Some(DotenvArrays::Indexed),
+            "json" => Some(DotenvArrays::Json),
+            _ => None,
+        }
+    }
+}
+
+/// One line of .env output
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvVar {
+    pub name: String,
+    /// Dot-path of the value, array elements by index
+    pub path: String,
+    /// Unquoted value text
+    pub value: String,
+}
 
 /// .env output emitter
-pub struct DotenvEmitter;
+pub struct DotenvEmitter {
+    /// Between the keys of nested levels
+    separator: String,
+    arrays: DotenvArrays,
+}
 
 impl DotenvEmitter {
     pub fn new() -> Self {
-        Self
+        Self {
+            separator: "__".to_string(),
+            arrays: DotenvArrays::Join,
+        }
+    }
+
+    /// Join nested keys with `separator` instead of `__`
+    pub fn with_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
     }
 
-    /// Flatten a value into key=value pairs
+    /// Write arrays in the given style
+    pub fn with_arrays(mut self, arrays: DotenvArrays) -> Self {
+        self.arrays = arrays;
+        self
+    }
+
+    /// The variables `value` is written as, in output order. Two paths that
+    /// flatten to the same name are an error.
+    pub fn variables(&self, value: &Value) -> HoneResult<Vec<EnvVar>> {
+        if !matches!(value, Value::Object(_)) {
+            return Err(HoneError::io_error(
+                ".env output requires a top-level object".to_string(),
+            ));
+        }
+        let mut vars = Vec::new();
+        self.flatten(value, "", "", &mut vars)?;
+
+        let mut seen = std::collections::HashSet::new();
+        for var in &vars {
+            if !seen.insert(var.name.as_str()) {
+                return Err(HoneError::io_error(format!(
+                    ".env output has two values for {}; rename a key or use another separator",
+                    var.name
+                )));
+            }
+        }
+        Ok(vars)
+    }
+
+    /// Emit one `key=value` line per flattened pair
+    fn emit_lines(&self, result: &mut Output, value: &Value) -> HoneResult<()> {
+        for var in self.variables(value)? {
+            result.push_str(&var.name);
+            result.push('=');
+            result.push_str(&Self::quote_value(&var.value));
+            result.push('\n');
+            result.section()?;
+        }
+        Ok(())
+    }
+
+    /// Flatten a value at dot-path `path` into variables named from `prefix`
     fn flatten(
         &self,
         value: &Value,
         prefix: &str,
-        pairs: &mut Vec<(String, String)>,
+        path: &str,
+        vars: &mut Vec<EnvVar>,
     ) -> HoneResult<()> {
+        let join = |path: &str, key: &str| {
+            if path.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}.{}", path, key)
+            }
+        };
+        let mut push = |text: String| {
+            vars.push(EnvVar {
+                name: prefix.to_string(),
+                path: path.to_string(),
+                value: text,
+            })
+        };
         match value {
             Value::Object(obj) => {
                 for (key, val) in obj {
                     let full_key = if prefix.is_empty() {
                         Self::to_env_key(key)
                     } else {
-                        format!("{}__{}", prefix, Self::to_env_key(key))
+                        format!("{}{}{}", prefix, self.separator, Self::to_env_key(key))
                     };
-                    self.flatten(val, &full_key, pairs)?;
+                    self.flatten(val, &full_key, &join(path, key), vars)?;
                 }
             }
+            Value::Function(_) => return Err(super::function_error(value)),
             Value::Null => {
                 // Skip null values
             }
-            Value::Bool(b) => {
-                pairs.push((
-                    prefix.to_string(),
-                    if *b { "true" } else { "false" }.to_string(),
-                ));
-            }
-            Value::Int(n) => {
-                pairs.push((prefix.to_string(), n.to_string()));
-            }
+            Value::Bool(b) => push(if *b { "true" } else { "false" }.to_string()),
+            Value::Int(n) => push(n.to_string()),
             Value::Float(n) => {
                 if n.fract() == 0.0 {
-                    pairs.push((prefix.to_string(), format!("{:.1}", n)));
+                    push(format!("{:.1}", n));
                 } else {
-                    pairs.push((prefix.to_string(), n.to_string()));
+                    push(n.to_string());
                 }
             }
-            Value::String(s) => {
-                pairs.push((prefix.to_string(), s.clone()));
-            }
+            Value::Duration(ms) => push(duration::format(*ms)),
+            Value::Size(bytes) => push(size::format(*bytes)),
+            Value::String(s) => push(s.clone()),
             Value::Array(arr) => {
                 // If all non-null elements are scalars, comma-join them.
                 // Otherwise, flatten with __index__ separators (dotnet-style).
@@ -63,10 +170,12 @@ impl DotenvEmitter {
                     .iter()
                     .any(|item| matches!(item, Value::Object(_) | Value::Array(_)));
 
-                if has_complex {
+                if self.arrays == DotenvArrays::Json {
+                    push(JsonEmitter::new(false).emit(value)?);
+                } else if has_complex || self.arrays == DotenvArrays::Indexed {
                     for (i, item) in arr.iter().enumerate() {
-                        let indexed_key = format!("{}__{}", prefix, i);
-                        self.flatten(item, &indexed_key, pairs)?;
+                        let indexed_key = format!("{}{}{}", prefix, self.separator, i);
+                        self.flatten(item, &indexed_key, &join(path, &i.to_string()), vars)?;
                     }
                 } else {
                     let mut items = Vec::new();
@@ -77,7 +186,7 @@ impl DotenvEmitter {
                             other => items.push(other.to_string()),
                         }
                     }
-                    pairs.push((prefix.to_string(), items.join(",")));
+                    push(items.join(","));
                 }
             }
         }
@@ -130,24 +239,15 @@ impl Default for DotenvEmitter {
 
 impl Emitter for DotenvEmitter {
     fn emit(&self, value: &Value) -> HoneResult<String> {
-        match value {
-            Value::Object(_) => {
-                let mut pairs = Vec::new();
-                self.flatten(value, "", &mut pairs)?;
-
-                let mut result = String::new();
-                for (key, val) in &pairs {
-                    result.push_str(key);
-                    result.push('=');
-                    result.push_str(&Self::quote_value(val));
-                    result.push('\n');
-                }
-                Ok(result)
-            }
-            _ => Err(HoneError::io_error(
-                ".env output requires a top-level object".to_string(),
-            )),
-        }
+        let mut result = Output::new();
+        self.emit_lines(&mut result, value)?;
+        result.finish()
+    }
+
+    fn emit_to_writer<W: Write>(&self, value: &Value, writer: &mut W) -> HoneResult<()> {
+        let mut result = Output::to_writer(writer);
+        self.emit_lines(&mut result, value)?;
+        result.finish().map(drop)
     }
 }
 
@@ -247,6 +347,37 @@ mod tests {
         assert!(!result.contains("MISSING"));
     }
 
+    #[test]
+    fn test_separator_and_array_styles() {
+        let value = obj(&[
+            ("db", obj(&[("host", Value::String("pg".into()))])),
+            ("ports", Value::Array(vec![Value::Int(80), Value::Int(443)])),
+        ]);
+        let single = DotenvEmitter::new().with_separator("_");
+        assert_eq!(single.emit(&value).unwrap(), "DB_HOST=pg\nPORTS=80,443\n");
+        let indexed = single.with_arrays(DotenvArrays::Indexed);
+        assert_eq!(
+            indexed.emit(&value).unwrap(),
+            "DB_HOST=pg\nPORTS_0=80\nPORTS_1=443\n"
+        );
+        let json = DotenvEmitter::new().with_arrays(DotenvArrays::Json);
+        assert_eq!(json.emit(&value).unwrap(), "DB__HOST=pg\nPORTS=[80,443]\n");
+    }
+
+    #[test]
+    fn test_colliding_keys_rejected() {
+        let value = obj(&[
+            ("db", obj(&[("host", Value::String("pg".into()))])),
+            ("db_host", Value::String("other".into())),
+        ]);
+        assert!(DotenvEmitter::new().emit(&value).is_ok());
+        let err = DotenvEmitter::new()
+            .with_separator("_")
+            .emit(&value)
+            .unwrap_err();
+        assert!(err.to_string().contains("DB_HOST"), "{}", err);
+    }
+
     #[test]
     fn test_scalar_array() {
         let emitter = DotenvEmitter::new();
@@ -355,4 +486,37 @@ mod tests {
         let result = emitter.emit(&value).unwrap();
         assert!(result.contains("MY_SERVICE__API_KEY=abc\n"));
     }
+
+    #[test]
+    fn test_variables_keep_paths() {
+        let emitter = DotenvEmitter::new().with_separator("_");
+        let value = obj(&[
+            (
+                "db",
+                obj(&[
+                    ("host", Value::String("localhost".into())),
+                    ("ports", Value::Array(vec![Value::Int(80), Value::Int(443)])),
+                ]),
+            ),
+            (
+                "servers",
+                Value::Array(vec![obj(&[("name", Value::String("api".into()))])]),
+            ),
+            ("unset", Value::Null),
+        ]);
+        let vars: Vec<(String, String)> = emitter
+            .variables(&value)
+            .unwrap()
+            .into_iter()
+            .map(|v| (v.name, v.path))
+            .collect();
+        assert_eq!(
+            vars,
+            [
+                ("DB_HOST".to_string(), "db.host".to_string()),
+                ("DB_PORTS".to_string(), "db.ports".to_string()),
+                ("SERVERS_0_NAME".to_string(), "servers.0.name".to_string()),
+            ]
+        );
+    }
 }
@@ -0,0 +1,340 @@
+//! HCL emitter for Hone values
+//!
+//! Follows the conventions of Terraform-style HCL: nested objects become
+//! blocks, arrays of objects become repeated blocks, and everything else is an
+//! attribute. Consecutive attributes have their `=` aligned like `terraform fmt`.
+
+use std::io::Write;
+
+use super::output::Output;
+use super::Emitter;
+use crate::errors::{HoneError, HoneResult};
+use crate::evaluator::Value;
+use crate::lexer::{duration, size};
+use indexmap::IndexMap;
+
+/// HCL output emitter
+pub struct HclEmitter {
+    indent: String,
+}
+
+impl HclEmitter {
+    pub fn new() -> Self {
+        Self {
+            indent: "  ".to_string(),
+        }
+    }
+
+    /// Create an HCL emitter with custom indentation
+    pub fn with_indent(indent: impl Into<String>) -> Self {
+        Self {
+            indent: indent.into(),
+        }
+    }
+
+    /// Emit the attributes and blocks of a body at the given depth, ending a
+    /// section after each block
+    fn emit_body(
+        &self,
+        result: &mut Output,
+        obj: &IndexMap<String, Value>,
+        depth: usize,
+    ) -> HoneResult<()> {
+        let pad = self.indent.repeat(depth);
+        // Attributes are buffered so each run of them can be aligned
+        let mut attributes: Vec<(String, String)> = Vec::new();
+
+        for (key, val) in obj {
+            if !Self::is_identifier(key) {
+                return Err(HoneError::io_error(format!(
+                    "HCL attribute and block names must be identifiers, got '{}'",
+                    key
+                )));
+            }
+            if !self.is_block(val) {
+                attributes.push((key.clone(), self.emit_value(val, depth)?));
+                continue;
+            }
+
+            self.flush_attributes(result, &mut attributes, &pad)?;
+            let blocks = match val {
+                Value::Array(arr) => arr.iter().collect(),
+                _ => vec![val],
+            };
+            for block in blocks {
+                if !result.is_empty() && !result.ends_with("{\n") && !result.ends_with("\n\n") {
+                    result.push('\n');
+                }
+                if let Value::Object(inner) = block {
+                    result.push_str(&format!("{}{} {{\n", pad, key));
+                    self.emit_body(result, inner, depth + 1)?;
+                    result.push_str(&format!("{}}}\n", pad));
+                    result.section()?;
+                }
+            }
+        }
+
+        self.flush_attributes(result, &mut attributes, &pad)
+    }
+
+    /// Write buffered attributes with their `=` signs aligned
+    fn flush_attributes(
+        &self,
+        result: &mut Output,
+        attributes: &mut Vec<(String, String)>,
+        pad: &str,
+    ) -> HoneResult<()> {
+        if attributes.is_empty() {
+            return Ok(());
+        }
+        if result.ends_with("}\n") {
+            result.push('\n');
+        }
+        let width = attributes.iter().map(|(k, _)| k.len()).max().unwrap_or(0);
+        for (key, value) in attributes.drain(..) {
+            result.push_str(&format!(
+                "{}{:width$} = {}\n",
+                pad,
+                key,
+                value,
+                width = width
+            ));
+        }
+        result.section()
+    }
+
+    /// Non-empty objects and non-empty arrays of objects are written as blocks
+    fn is_block(&self, value: &Value) -> bool {
+        match value {
+            Value::Object(obj) => !obj.is_empty() && obj.keys().all(|k| Self::is_identifier(k)),
+            Value::Array(arr) => {
+                !arr.is_empty()
+                    && arr
+                        .iter()
+                        .all(|v| matches!(v, Value::Object(_)) && self.is_block(v))
+            }
+            _ => false,
+        }
+    }
+
+    /// Emit a value as an HCL expression
+    fn emit_value(&self, value: &Value, depth: usize) -> HoneResult<String> {
+        match value {
+            Value::Null => Ok("null".to_string()),
+            Value::Bool(b) => Ok(b.to_string()),
+            Value::Int(n) => Ok(n.to_string()),
+            Value::Float(n) => {
+                if !n.is_finite() {
+                    Err(HoneError::io_error(format!(
+                        "HCL does not support non-finite numbers ({})",
+                        n
+                    )))
+                } else if n.fract() == 0.0 {
+                    Ok(format!("{:.1}", n))
+                } else {
+                    Ok(n.to_string())
+                }
+            }
+            Value::Duration(ms) => Ok(Self::escape_string(&duration::format(*ms))),
+            Value::Size(bytes) => Ok(Self::escape_string(&size::format(*bytes))),
+            Value::String(s) => Ok(Self::escape_string(s)),
+            Value::Function(_) => Err(super::function_error(value)),
+            Value::Array(arr) => {
+                let mut items = Vec::new();
+                for val in arr {
+                    items.push(self.emit_value(val, depth)?);
+                }
+                Ok(format!("[{}]", items.join(", ")))
+            }
+            Value::Object(obj) if obj.is_empty() => Ok("{}".to_string()),
+            Value::Object(obj) => {
+                // Object expression, one key per line
+                let pad = self.indent.repeat(depth + 1);
+                let mut result = String::from("{\n");
+                for (key, val) in obj {
+                    let key = if Self::is_identifier(key) {
+                        key.clone()
+                    } else {
+                        Self::escape_string(key)
+                    };
+                    result.push_str(&format!(
+                        "{}{} = {}\n",
+                        pad,
+                        key,
+                        self.emit_value(val, depth + 1)?
+                    ));
+                }
+                result.push_str(&self.indent.repeat(depth));
+                result.push('}');
+                Ok(result)
+            }
+        }
+    }
+
+    /// HCL identifiers: a letter or underscore, then letters, digits, `_`, `-`
+    fn is_identifier(key: &str) -> bool {
+        let mut chars = key.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    }
+
+    /// Quote a string, escaping template sequences so `${` and `%{` stay literal
+    fn escape_string(s: &str) -> String {
+        let mut result = String::with_capacity(s.len() + 2);
+        result.push('"');
+        let mut chars = s.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '"' => result.push_str("\\\""),
+                '\\' => result.push_str("\\\\"),
+                '\n' => result.push_str("\\n"),
+                '\r' => result.push_str("\\r"),
+                '\t' => result.push_str("\\t"),
+                '$' | '%' if chars.peek() == Some(&'{') => {
+                    result.push(ch);
+                    result.push(ch);
+                }
+                c if c.is_control() => {
+                    result.push_str(&format!("\\u{:04X}", c as u32));
+                }
+                c => result.push(c),
+            }
+        }
+        result.push('"');
+        result
+    }
+}
+
+impl Default for HclEmitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Emitter for HclEmitter {
+    fn emit(&self, value: &Value) -> HoneResult<String> {
+        match value {
+            Value::Object(obj) => {
+                let mut result = Output::new();
+                self.emit_body(&mut result, obj, 0)?;
+                result.finish()
+            }
+            _ => Err(HoneError::io_error(
+                "HCL output requires a top-level object".to_string(),
+            )),
+        }
+    }
+
+    fn emit_to_writer<W: Write>(&self, value: &Value, writer: &mut W) -> HoneResult<()> {
+        match value {
+            Value::Object(obj) => {
+                let mut result = Output::to_writer(writer);
+                self.emit_body(&mut result, obj, 0)?;
+                result.finish().map(drop)
+            }
+            _ => Err(HoneError::io_error(
+                "HCL output requires a top-level object".to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn obj(pairs: &[(&str, Value)]) -> Value {
+        let mut map = IndexMap::new();
+        for (k, v) in pairs {
+            map.insert(k.to_string(), v.clone());
+        }
+        Value::Object(map)
+    }
+
+    #[test]
+    fn test_attributes_aligned() {
+        let emitter = HclEmitter::new();
+        let value = obj(&[
+            ("region", Value::String("eu-west-1".into())),
+            ("instance_count", Value::Int(3)),
+            ("enabled", Value::Bool(true)),
+            ("ratio", Value::Float(2.0)),
+            ("zones", Value::Array(vec![Value::String("a".into())])),
+        ]);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "region         = \"eu-west-1\"\ninstance_count = 3\nenabled        = true\nratio          = 2.0\nzones          = [\"a\"]\n"
+        );
+    }
+
+    #[test]
+    fn test_nested_blocks() {
+        let emitter = HclEmitter::new();
+        let value = obj(&[
+            ("name", Value::String("web".into())),
+            (
+                "backend",
+                obj(&[
+                    ("bucket", Value::String("state".into())),
+                    ("encrypt", Value::Bool(true)),
+                ]),
+            ),
+        ]);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "name = \"web\"\n\nbackend {\n  bucket  = \"state\"\n  encrypt = true\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_array_of_objects_repeats_block() {
+        let emitter = HclEmitter::new();
+        let value = obj(&[(
+            "ingress",
+            Value::Array(vec![
+                obj(&[("port", Value::Int(80))]),
+                obj(&[("port", Value::Int(443))]),
+            ]),
+        )]);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "ingress {\n  port = 80\n}\n\ningress {\n  port = 443\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_object_with_non_identifier_keys_is_attribute() {
+        let emitter = HclEmitter::new();
+        let value = obj(&[(
+            "tags",
+            obj(&[
+                ("Name", Value::String("web".into())),
+                ("kubernetes.io/role", Value::String("node".into())),
+            ]),
+        )]);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "tags = {\n  Name = \"web\"\n  \"kubernetes.io/role\" = \"node\"\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_escapes_template_sequences() {
+        let emitter = HclEmitter::new();
+        let value = obj(&[("cmd", Value::String("echo ${HOME} \"%{x}\"\n".into()))]);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "cmd = \"echo $${HOME} \\\"%%{x}\\\"\\n\"\n"
+        );
+    }
+
+    #[test]
+    fn test_requires_top_level_object() {
+        let emitter = HclEmitter::new();
+        assert!(emitter.emit(&Value::Int(1)).is_err());
+        let value = obj(&[("bad key", Value::Int(1))]);
+        assert!(emitter.emit(&value).is_err());
+    }
+}
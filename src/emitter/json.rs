@@ -1,8 +1,24 @@
 //! JSON emitter for Hone values
-
+//!
+//! Besides compact and pretty output there is a canonical mode following the
+//! JSON Canonicalization Scheme (RFC 8785): keys sorted by UTF-16 code units,
+//! no whitespace, ECMAScript number formatting, and minimal string escaping.
+//! Equal values always produce identical bytes, so the output can be hashed,
+//! signed, or diffed without false positives.
+//!
+//! JSONC mode is pretty JSON with `//` comments, for files people read in
+//! editors that accept them (`tsconfig.json`-style). The `##` doc comment
+//! and, when given, the origin (`file:line`) of each key are written above
+//! it. Trailing commas are left out unless asked for, since not every JSONC
+//! reader accepts them.
+
+use std::io::Write;
+
+use super::output::Output;
 use super::Emitter;
-use crate::errors::HoneResult;
-use crate::evaluator::Value;
+use crate::errors::{HoneError, HoneResult};
+use crate::evaluator::{DocMap, Value};
+use crate::lexer::{duration, size};
 
 /// JSON output emitter
 pub struct JsonEmitter {
@@ -10,6 +26,16 @@ pub struct JsonEmitter {
     pretty: bool,
     /// Indentation string (spaces or tabs)
     indent: String,
+    /// Canonical (RFC 8785) output; implies compact
+    canonical: bool,
+    /// JSONC: comments above keys; implies pretty
+    jsonc: bool,
+    /// Doc comments to write above keys in JSONC, by dot-path
+    doc_comments: DocMap,
+    /// Where each key was set (`file:line`), by dot-path, for JSONC
+    key_origins: DocMap,
+    /// End the last array element and object entry with a comma (JSONC)
+    trailing_commas: bool,
 }
 
 impl JsonEmitter {
@@ -18,44 +44,161 @@ impl JsonEmitter {
         Self {
             pretty,
             indent: "  ".to_string(),
+            canonical: false,
+            jsonc: false,
+            doc_comments: DocMap::new(),
+            key_origins: DocMap::new(),
+            trailing_commas: false,
+        }
+    }
+
+    /// Create an emitter for canonical, byte-stable JSON
+    pub fn canonical() -> Self {
+        Self {
+            pretty: false,
+            indent: String::new(),
+            canonical: true,
+            ..Self::new(false)
+        }
+    }
+
+    /// Create an emitter for JSON with comments
+    pub fn jsonc() -> Self {
+        Self {
+            jsonc: true,
+            ..Self::new(true)
         }
     }
 
     /// Create a JSON emitter with custom indentation
     pub fn with_indent(indent: impl Into<String>) -> Self {
         Self {
-            pretty: true,
             indent: indent.into(),
+            ..Self::new(true)
         }
     }
 
+    /// Use this indentation instead of two spaces
+    pub fn indented(mut self, indent: impl Into<String>) -> Self {
+        self.indent = indent.into();
+        self
+    }
+
+    /// Write these doc comments (dot-path -> text) above their keys in JSONC
+    pub fn with_doc_comments(mut self, doc_comments: DocMap) -> Self {
+        self.doc_comments = doc_comments;
+        self
+    }
+
+    /// Note where each key was set (dot-path -> `file:line`) in JSONC
+    pub fn with_key_origins(mut self, key_origins: DocMap) -> Self {
+        self.key_origins = key_origins;
+        self
+    }
+
+    /// End the last element of arrays and objects with a comma in JSONC
+    pub fn with_trailing_commas(mut self, trailing_commas: bool) -> Self {
+        self.trailing_commas = trailing_commas;
+        self
+    }
+
+    /// Dot-path of `key` under `path`; `None` turns comments off below
+    fn child_path(path: Option<&str>, key: &str) -> Option<String> {
+        match path {
+            Some("") => Some(key.to_string()),
+            Some(path) => Some(format!("{}.{}", path, key)),
+            None => None,
+        }
+    }
+
+    /// `// ...` lines for the key at `path`, each ending in a newline
+    fn comment_lines(&self, path: Option<&str>, indent: &str) -> String {
+        let Some(path) = path else {
+            return String::new();
+        };
+        let mut lines = String::new();
+        if let Some(doc) = self.doc_comments.get(path) {
+            for line in doc.split('\n') {
+                if line.is_empty() {
+                    lines.push_str(&format!("{}//\n", indent));
+                } else {
+                    lines.push_str(&format!("{}// {}\n", indent, line));
+                }
+            }
+        }
+        if let Some(origin) = self.key_origins.get(path) {
+            lines.push_str(&format!("{}// from {}\n", indent, origin));
+        }
+        lines
+    }
+
+    /// Whether element `i` of `len` is followed by a comma
+    fn comma_after(&self, i: usize, len: usize) -> bool {
+        i + 1 < len || (self.jsonc && self.trailing_commas)
+    }
+
     /// Emit a value with the given depth
-    fn emit_value(&self, value: &Value, depth: usize) -> String {
+    pub(crate) fn emit_value(&self, value: &Value, depth: usize) -> String {
+        let mut out = Output::new();
+        // Collected output has no writer to fail
+        let _ = self.write_value(&mut out, value, depth, self.jsonc.then_some(""));
+        out.finish().unwrap_or_default()
+    }
+
+    /// Canonical JSON has no way to write non-finite numbers
+    fn check_finite(&self, value: &Value) -> HoneResult<()> {
+        if !self.canonical {
+            return Ok(());
+        }
+        match find_non_finite(value) {
+            Some(n) => Err(HoneError::io_error(format!(
+                "canonical JSON cannot represent the non-finite number {}",
+                n
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Write a value with the given depth, ending a section after each
+    /// array element and object entry. `path` is the value's dot-path when
+    /// writing comments.
+    fn write_value(
+        &self,
+        out: &mut Output,
+        value: &Value,
+        depth: usize,
+        path: Option<&str>,
+    ) -> HoneResult<()> {
         match value {
-            Value::Null => "null".to_string(),
-            Value::Bool(b) => if *b { "true" } else { "false" }.to_string(),
-            Value::Int(n) => n.to_string(),
+            Value::Null => out.push_str("null"),
+            Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Value::Int(n) => out.push_str(&n.to_string()),
+            Value::Float(n) if self.canonical => out.push_str(&canonical_number(*n)),
             Value::Float(n) => {
                 if n.is_infinite() || n.is_nan() {
                     eprintln!(
                         "warning: non-finite float ({}) converted to null in JSON output; use --format yaml for non-finite float support",
                         if n.is_nan() { "NaN".to_string() } else { format!("{}", n) }
                     );
-                    "null".to_string()
+                    out.push_str("null");
                 } else if n.fract() == 0.0 {
-                    format!("{:.1}", n)
+                    out.push_str(&format!("{:.1}", n));
                 } else {
-                    n.to_string()
+                    out.push_str(&n.to_string());
                 }
             }
-            Value::String(s) => self.escape_string(s),
-            Value::Array(arr) => self.emit_array(arr, depth),
-            Value::Object(obj) => self.emit_object(obj, depth),
+            Value::Duration(ms) => out.push_str(&self.escape_string(&duration::format(*ms))),
+            Value::Size(bytes) => out.push_str(&self.escape_string(&size::format(*bytes))),
+            Value::String(s) => out.push_str(&self.escape_string(s)),
+            Value::Function(_) => return Err(super::function_error(value)),
+            Value::Array(arr) => return self.write_array(out, arr, depth, path),
+            Value::Object(obj) => return self.write_object(out, obj, depth, path),
         }
+        Ok(())
     }
 
     /// Escape a string for JSON
-    fn escape_string(&self, s: &str) -> String {
+    pub(crate) fn escape_string(&self, s: &str) -> String {
         let mut result = String::with_capacity(s.len() + 2);
         result.push('"');
 
@@ -66,6 +209,10 @@ impl JsonEmitter {
                 '\n' => result.push_str("\\n"),
                 '\r' => result.push_str("\\r"),
                 '\t' => result.push_str("\\t"),
+                '\u{8}' if self.canonical => result.push_str("\\b"),
+                '\u{c}' if self.canonical => result.push_str("\\f"),
+                // Canonical form escapes only what JSON requires
+                c if self.canonical && c >= ' ' => result.push(c),
                 c if c.is_control() => {
                     result.push_str(&format!("\\u{:04x}", c as u32));
                 }
@@ -77,80 +224,129 @@ impl JsonEmitter {
         result
     }
 
-    /// Emit an array
-    fn emit_array(&self, arr: &[Value], depth: usize) -> String {
+    /// Write an array. Keys inside it are commented once, in the first
+    /// element.
+    fn write_array(
+        &self,
+        out: &mut Output,
+        arr: &[Value],
+        depth: usize,
+        path: Option<&str>,
+    ) -> HoneResult<()> {
         if arr.is_empty() {
-            return "[]".to_string();
+            out.push_str("[]");
+            return Ok(());
         }
 
-        if self.pretty {
-            let mut result = String::from("[\n");
-            let inner_indent = self.indent.repeat(depth + 1);
-            let outer_indent = self.indent.repeat(depth);
-
-            for (i, item) in arr.iter().enumerate() {
-                result.push_str(&inner_indent);
-                result.push_str(&self.emit_value(item, depth + 1));
-                if i < arr.len() - 1 {
-                    result.push(',');
-                }
-                result.push('\n');
+        out.push('[');
+        let inner_indent = self.indent.repeat(depth + 1);
+        for (i, item) in arr.iter().enumerate() {
+            if self.pretty {
+                out.push('\n');
+                out.push_str(&inner_indent);
             }
-
-            result.push_str(&outer_indent);
-            result.push(']');
-            result
-        } else {
-            let items: Vec<_> = arr.iter().map(|v| self.emit_value(v, depth + 1)).collect();
-            format!("[{}]", items.join(","))
+            self.write_value(out, item, depth + 1, path.filter(|_| i == 0))?;
+            if self.comma_after(i, arr.len()) {
+                out.push(',');
+            }
+            out.section()?;
+        }
+        if self.pretty {
+            out.push('\n');
+            out.push_str(&self.indent.repeat(depth));
         }
+        out.push(']');
+        Ok(())
     }
 
-    /// Emit an object
-    fn emit_object(&self, obj: &indexmap::IndexMap<String, Value>, depth: usize) -> String {
+    /// Write an object
+    fn write_object(
+        &self,
+        out: &mut Output,
+        obj: &indexmap::IndexMap<String, Value>,
+        depth: usize,
+        path: Option<&str>,
+    ) -> HoneResult<()> {
         if obj.is_empty() {
-            return "{}".to_string();
+            out.push_str("{}");
+            return Ok(());
         }
 
-        if self.pretty {
-            let mut result = String::from("{\n");
-            let inner_indent = self.indent.repeat(depth + 1);
-            let outer_indent = self.indent.repeat(depth);
-
-            for (i, (key, value)) in obj.iter().enumerate() {
-                result.push_str(&inner_indent);
-                result.push_str(&self.escape_string(key));
-                result.push_str(": ");
-                result.push_str(&self.emit_value(value, depth + 1));
-                if i < obj.len() - 1 {
-                    result.push(',');
-                }
-                result.push('\n');
-            }
+        let mut entries: Vec<_> = obj.iter().collect();
+        if self.canonical {
+            entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+        }
 
-            result.push_str(&outer_indent);
-            result.push('}');
-            result
-        } else {
-            let items: Vec<_> = obj
-                .iter()
-                .map(|(k, v)| {
-                    format!(
-                        "{}:{}",
-                        self.escape_string(k),
-                        self.emit_value(v, depth + 1)
-                    )
-                })
-                .collect();
-            format!("{{{}}}", items.join(","))
+        out.push('{');
+        let inner_indent = self.indent.repeat(depth + 1);
+        for (i, (key, value)) in entries.into_iter().enumerate() {
+            let child = Self::child_path(path, key);
+            if self.pretty {
+                out.push('\n');
+                out.push_str(&self.comment_lines(child.as_deref(), &inner_indent));
+                out.push_str(&inner_indent);
+                out.push_str(&self.escape_string(key));
+                out.push_str(": ");
+            } else {
+                out.push_str(&self.escape_string(key));
+                out.push(':');
+            }
+            self.write_value(out, value, depth + 1, child.as_deref())?;
+            if self.comma_after(i, obj.len()) {
+                out.push(',');
+            }
+            out.section()?;
         }
+        if self.pretty {
+            out.push('\n');
+            out.push_str(&self.indent.repeat(depth));
+        }
+        out.push('}');
+        Ok(())
+    }
+}
+
+/// Format a finite float the way ECMAScript's `Number.prototype.toString`
+/// does: shortest round-trip digits, no trailing `.0`, and exponent notation
+/// only outside `1e-6 <= |n| < 1e21`
+fn canonical_number(n: f64) -> String {
+    if n == 0.0 {
+        // Also covers -0
+        return "0".to_string();
+    }
+    let abs = n.abs();
+    if (1e-6..1e21).contains(&abs) {
+        return n.to_string();
+    }
+    let formatted = format!("{:e}", n);
+    match formatted.split_once('e') {
+        Some((mantissa, exp)) if !exp.starts_with('-') => format!("{}e+{}", mantissa, exp),
+        _ => formatted,
+    }
+}
+
+/// First non-finite float in a value, which canonical JSON cannot represent
+fn find_non_finite(value: &Value) -> Option<f64> {
+    match value {
+        Value::Float(n) if !n.is_finite() => Some(*n),
+        Value::Array(arr) => arr.iter().find_map(find_non_finite),
+        Value::Object(obj) => obj.values().find_map(find_non_finite),
+        _ => None,
     }
 }
 
 impl Emitter for JsonEmitter {
     fn emit(&self, value: &Value) -> HoneResult<String> {
+        self.check_finite(value)?;
         Ok(self.emit_value(value, 0))
     }
+
+    fn emit_to_writer<W: Write>(&self, value: &Value, writer: &mut W) -> HoneResult<()> {
+        self.check_finite(value)?;
+        let mut out = Output::to_writer(writer);
+        self.write_value(&mut out, value, 0, self.jsonc.then_some(""))?;
+        out.finish().map(drop)
+    }
 }
 
 #[cfg(test)]
@@ -189,7 +385,7 @@ mod tests {
     #[test]
     fn test_emit_float() {
         let emitter = JsonEmitter::new(false);
-        assert_eq!(emitter.emit(&Value::Float(3.14)).unwrap(), "3.14");
+        assert_eq!(emitter.emit(&Value::Float(3.25)).unwrap(), "3.25");
         assert_eq!(emitter.emit(&Value::Float(2.0)).unwrap(), "2.0");
     }
 
@@ -282,4 +478,98 @@ mod tests {
         let emitter = JsonEmitter::new(false);
         assert_eq!(emitter.emit(&Value::Float(3.0)).unwrap(), "3.0");
     }
+
+    #[test]
+    fn test_canonical_sorts_keys_recursively() {
+        let emitter = JsonEmitter::canonical();
+        let value = obj(&[
+            ("b", Value::Int(1)),
+            ("a", obj(&[("z", Value::Null), ("é", Value::Bool(true))])),
+            (
+                "A",
+                Value::Array(vec![obj(&[("y", Value::Int(2)), ("x", Value::Int(1))])]),
+            ),
+        ]);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            r#"{"A":[{"x":1,"y":2}],"a":{"z":null,"é":true},"b":1}"#
+        );
+    }
+
+    #[test]
+    fn test_canonical_numbers() {
+        let emitter = JsonEmitter::canonical();
+        let cases = [
+            (3.0, "3"),
+            (-0.0, "0"),
+            (0.1, "0.1"),
+            (1e20, "100000000000000000000"),
+            (1e21, "1e+21"),
+            (1.5e-7, "1.5e-7"),
+            (0.000001, "0.000001"),
+        ];
+        for (n, expected) in cases {
+            assert_eq!(emitter.emit(&Value::Float(n)).unwrap(), expected, "{}", n);
+        }
+        assert!(emitter.emit(&Value::Float(f64::NAN)).is_err());
+    }
+
+    #[test]
+    fn test_canonical_string_escapes() {
+        let emitter = JsonEmitter::canonical();
+        assert_eq!(
+            emitter
+                .emit(&Value::String("a\u{8}\u{c}\u{1}\u{7f}€\"".into()))
+                .unwrap(),
+            "\"a\\b\\f\\u0001\u{7f}€\\\"\""
+        );
+    }
+
+    #[test]
+    fn test_emit_jsonc_comments_and_trailing_commas() {
+        let docs: DocMap = [
+            ("server".to_string(), "Where to listen".to_string()),
+            ("server.port".to_string(), "TCP port\n\nNot 80".to_string()),
+            ("hosts.name".to_string(), "Host name".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let origins: DocMap = [("server.port".to_string(), "base.hone:3".to_string())]
+            .into_iter()
+            .collect();
+        let value = obj(&[
+            ("server", obj(&[("port", Value::Int(8080))])),
+            (
+                "hosts",
+                Value::Array(vec![
+                    obj(&[("name", Value::String("a".into()))]),
+                    obj(&[("name", Value::String("b".into()))]),
+                ]),
+            ),
+        ]);
+
+        let emitter = JsonEmitter::jsonc()
+            .with_doc_comments(docs.clone())
+            .with_key_origins(origins);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "{\n  // Where to listen\n  \"server\": {\n    // TCP port\n    //\n    // Not 80\n    \
+             // from base.hone:3\n    \"port\": 8080\n  },\n  \"hosts\": [\n    {\n      \
+             // Host name\n      \"name\": \"a\"\n    },\n    {\n      \"name\": \"b\"\n    }\n  ]\n}"
+        );
+
+        let emitter = JsonEmitter::jsonc().with_trailing_commas(true);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "{\n  \"server\": {\n    \"port\": 8080,\n  },\n  \"hosts\": [\n    {\n      \
+             \"name\": \"a\",\n    },\n    {\n      \"name\": \"b\",\n    },\n  ],\n}"
+        );
+
+        // Plain JSON ignores both
+        let emitter = JsonEmitter::new(true)
+            .with_doc_comments(docs)
+            .with_trailing_commas(true);
+        assert!(!emitter.emit(&value).unwrap().contains("//"));
+        assert!(!emitter.emit(&value).unwrap().contains(",\n  }"));
+    }
 }
@@ -1,42 +1,183 @@
 //! Emitters for Hone configuration language
 //!
-//! Converts evaluated Value trees to JSON, YAML, or other output formats.
+//! Converts evaluated Value trees to JSON, YAML, TOML, HCL, XML, Java
+//! .properties, CSV, or other output formats.
+//! Custom formats can be added at runtime through the emitter registry.
 
+mod csv;
 mod dotenv;
+mod hcl;
 mod json;
+mod output;
+mod properties;
+mod registry;
+mod stream;
 mod toml;
+mod writer;
+mod xml;
 mod yaml;
 
-pub use dotenv::DotenvEmitter;
+pub use csv::CsvEmitter;
+pub use dotenv::{DotenvArrays, DotenvEmitter, EnvVar};
+pub use hcl::HclEmitter;
 pub use json::JsonEmitter;
+pub use properties::PropertiesEmitter;
+pub use registry::{register_emitter, registered_names};
+pub use stream::StreamEmitter;
 pub use toml::TomlEmitter;
+pub use writer::{parse_mode, stamp_comment, write_output, write_output_with, WriteOptions};
+pub use xml::XmlEmitter;
 pub use yaml::YamlEmitter;
 
-use crate::errors::HoneResult;
-use crate::evaluator::Value;
+use crate::errors::{HoneError, HoneResult};
+use crate::evaluator::{DocMap, Value};
 
 /// Output format for emission
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OutputFormat {
     Json,
     JsonPretty,
+    /// Sorted, whitespace-free JSON per RFC 8785, for hashing and signing
+    JsonCanonical,
+    /// Pretty JSON with `//` comments above keys (JSONC)
+    Jsonc,
+    /// One compact JSON document per line (JSON Lines), for stream processors
+    Ndjson,
     Yaml,
     Toml,
     Dotenv,
+    Hcl,
+    Xml,
+    Properties,
+    /// A header row plus one row per object of a top-level array
+    Csv,
+    /// A format added with [`register_emitter`], by registered name
+    Custom(&'static str),
 }
 
 impl OutputFormat {
-    /// Parse from string
+    /// Parse from string, falling back to registered custom formats
     pub fn parse(s: &str) -> Option<Self> {
+        Self::parse_builtin(s)
+            .or_else(|| registry::registered_emitter(s).map(|(name, _)| OutputFormat::Custom(name)))
+    }
+
+    /// Parse a built-in format name
+    pub(crate) fn parse_builtin(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "json" => Some(OutputFormat::Json),
             "json-pretty" | "jsonpretty" => Some(OutputFormat::JsonPretty),
+            "json-canonical" | "jcs" => Some(OutputFormat::JsonCanonical),
+            "jsonc" | "json5" => Some(OutputFormat::Jsonc),
+            "ndjson" | "jsonl" | "json-lines" => Some(OutputFormat::Ndjson),
             "yaml" | "yml" => Some(OutputFormat::Yaml),
             "toml" => Some(OutputFormat::Toml),
             "dotenv" | "env" => Some(OutputFormat::Dotenv),
+            "hcl" => Some(OutputFormat::Hcl),
+            "xml" => Some(OutputFormat::Xml),
+            "properties" => Some(OutputFormat::Properties),
+            "csv" => Some(OutputFormat::Csv),
             _ => None,
         }
     }
+
+    /// Detect the format from an output file extension
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "yaml" | "yml" => Some(OutputFormat::Yaml),
+            "json" => Some(OutputFormat::JsonPretty),
+            "jsonc" | "json5" => Some(OutputFormat::Jsonc),
+            "ndjson" | "jsonl" => Some(OutputFormat::Ndjson),
+            "toml" => Some(OutputFormat::Toml),
+            "env" => Some(OutputFormat::Dotenv),
+            "hcl" | "tf" => Some(OutputFormat::Hcl),
+            "xml" => Some(OutputFormat::Xml),
+            "properties" => Some(OutputFormat::Properties),
+            "csv" => Some(OutputFormat::Csv),
+            ext => registry::registered_for_extension(ext).map(OutputFormat::Custom),
+        }
+    }
+
+    /// Canonical name, as accepted by `parse`
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::JsonPretty => "json-pretty",
+            OutputFormat::JsonCanonical => "json-canonical",
+            OutputFormat::Jsonc => "jsonc",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Yaml => "yaml",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Dotenv => "dotenv",
+            OutputFormat::Hcl => "hcl",
+            OutputFormat::Xml => "xml",
+            OutputFormat::Properties => "properties",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Custom(name) => name,
+        }
+    }
+
+    /// File extension for output files in this format
+    pub fn extension(&self) -> String {
+        match self {
+            OutputFormat::Json | OutputFormat::JsonPretty | OutputFormat::JsonCanonical => {
+                "json".to_string()
+            }
+            OutputFormat::Jsonc => "jsonc".to_string(),
+            OutputFormat::Ndjson => "ndjson".to_string(),
+            OutputFormat::Yaml => "yaml".to_string(),
+            OutputFormat::Toml => "toml".to_string(),
+            OutputFormat::Dotenv => "env".to_string(),
+            OutputFormat::Hcl => "hcl".to_string(),
+            OutputFormat::Xml => "xml".to_string(),
+            OutputFormat::Properties => "properties".to_string(),
+            OutputFormat::Csv => "csv".to_string(),
+            OutputFormat::Custom(name) => registry::registered_emitter(name)
+                .and_then(|(_, e)| e.extensions().first().map(|x| x.to_string()))
+                .unwrap_or_else(|| name.to_string()),
+        }
+    }
+
+    /// Line comment prefix, if the format has comments
+    pub fn comment_prefix(&self) -> Option<String> {
+        match self {
+            // XML comments can't precede the declaration, so XML gets none
+            OutputFormat::Json
+            | OutputFormat::JsonPretty
+            | OutputFormat::JsonCanonical
+            | OutputFormat::Ndjson
+            | OutputFormat::Xml
+            | OutputFormat::Csv => None,
+            OutputFormat::Yaml
+            | OutputFormat::Toml
+            | OutputFormat::Dotenv
+            | OutputFormat::Hcl
+            | OutputFormat::Properties => Some("#".to_string()),
+            OutputFormat::Jsonc => Some("//".to_string()),
+            OutputFormat::Custom(name) => registry::registered_emitter(name)
+                .and_then(|(_, e)| e.comment_prefix().map(str::to_string)),
+        }
+    }
+
+    /// Names of every available format, built-in first
+    pub fn names() -> Vec<&'static str> {
+        let mut names = vec![
+            "json",
+            "json-pretty",
+            "json-canonical",
+            "jsonc",
+            "ndjson",
+            "yaml",
+            "toml",
+            "dotenv",
+            "hcl",
+            "xml",
+            "properties",
+            "csv",
+        ];
+        names.extend(registry::registered_names());
+        names
+    }
 }
 
 /// Trait for output emitters
@@ -44,8 +185,23 @@ pub trait Emitter {
     /// Emit a value to string
     fn emit(&self, value: &Value) -> HoneResult<String>;
 
-    /// Emit a value to a writer
-    fn emit_to_writer<W: std::io::Write>(&self, value: &Value, writer: &mut W) -> HoneResult<()> {
+    /// File extensions that select this emitter (custom formats only)
+    fn extensions(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Line comment prefix, used for `--stamp` and document separators
+    /// (custom formats only)
+    fn comment_prefix(&self) -> Option<&str> {
+        None
+    }
+
+    /// Emit a value to a writer. The default builds the whole string first;
+    /// the built-in emitters override it to write each section as it is done.
+    fn emit_to_writer<W: std::io::Write>(&self, value: &Value, writer: &mut W) -> HoneResult<()>
+    where
+        Self: Sized,
+    {
         let output = self.emit(value)?;
         writer
             .write_all(output.as_bytes())
@@ -55,28 +211,249 @@ pub trait Emitter {
     }
 }
 
+/// Style options for emitted output. Formats ignore the options that don't
+/// apply to them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EmitOptions {
+    /// Spaces per indentation level in pretty JSON, YAML, HCL and XML.
+    /// When unset, each format keeps its default of two.
+    pub indent: Option<usize>,
+    /// Double-quote every YAML string value instead of only the ones that
+    /// would otherwise read as another type
+    pub quote_strings: bool,
+    /// Leave out object keys whose value is null instead of writing `null`
+    pub omit_nulls: bool,
+    /// Doc comments to write above YAML and JSONC keys, by dot-path
+    pub doc_comments: DocMap,
+    /// Note above each JSONC key where it was set
+    pub key_origins: bool,
+    /// Where each key was set (`file:line`), by dot-path
+    pub origins: DocMap,
+    /// End the last array element and object entry with a comma in JSONC
+    pub trailing_commas: bool,
+    /// Separator between nested keys in .env output. When unset, `__`.
+    pub env_separator: Option<String>,
+    /// How arrays are written in .env output
+    pub env_arrays: DotenvArrays,
+}
+
+impl EmitOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_indent(mut self, indent: Option<usize>) -> Self {
+        self.indent = indent;
+        self
+    }
+
+    pub fn with_quote_strings(mut self, quote_strings: bool) -> Self {
+        self.quote_strings = quote_strings;
+        self
+    }
+
+    pub fn with_omit_nulls(mut self, omit_nulls: bool) -> Self {
+        self.omit_nulls = omit_nulls;
+        self
+    }
+
+    pub fn with_doc_comments(mut self, doc_comments: DocMap) -> Self {
+        self.doc_comments = doc_comments;
+        self
+    }
+
+    pub fn with_key_origins(mut self, key_origins: bool) -> Self {
+        self.key_origins = key_origins;
+        self
+    }
+
+    pub fn with_origins(mut self, origins: DocMap) -> Self {
+        self.origins = origins;
+        self
+    }
+
+    pub fn with_trailing_commas(mut self, trailing_commas: bool) -> Self {
+        self.trailing_commas = trailing_commas;
+        self
+    }
+
+    pub fn with_env_separator(mut self, separator: Option<String>) -> Self {
+        self.env_separator = separator;
+        self
+    }
+
+    pub fn with_env_arrays(mut self, arrays: DotenvArrays) -> Self {
+        self.env_arrays = arrays;
+        self
+    }
+
+    /// The .env emitter these options configure
+    pub fn dotenv_emitter(&self) -> DotenvEmitter {
+        let emitter = DotenvEmitter::new().with_arrays(self.env_arrays);
+        match &self.env_separator {
+            Some(separator) => emitter.with_separator(separator.as_str()),
+            None => emitter,
+        }
+    }
+
+    /// Indentation string, when one was set
+    fn indent_str(&self) -> Option<String> {
+        self.indent.map(|n| " ".repeat(n))
+    }
+}
+
+/// Error for a function reaching an emitter. The compiler rejects output
+/// holding functions, so this is only seen when emitting values built in code.
+pub(crate) fn function_error(value: &Value) -> HoneError {
+    HoneError::io_error(format!("cannot write a function ({}) to output", value))
+}
+
+/// Fail if a function is anywhere inside `value`
+pub(crate) fn reject_functions(value: &Value) -> HoneResult<()> {
+    match value.function_path() {
+        Some(path) if path.is_empty() => Err(function_error(value)),
+        Some(path) => Err(HoneError::io_error(format!(
+            "cannot write a function to output (at '{}')",
+            path
+        ))),
+        None => Ok(()),
+    }
+}
+
 /// Emit a value to a string in the specified format
 pub fn emit(value: &Value, format: OutputFormat) -> HoneResult<String> {
+    emit_with(value, format, &EmitOptions::default())
+}
+
+/// Emit a value to a string in the specified format and style
+pub fn emit_with(value: &Value, format: OutputFormat, options: &EmitOptions) -> HoneResult<String> {
+    if options.omit_nulls {
+        let options = EmitOptions {
+            omit_nulls: false,
+            ..options.clone()
+        };
+        return emit_with(&value.without_nulls(), format, &options);
+    }
+
     match format {
-        OutputFormat::Json => JsonEmitter::new(false).emit(value),
-        OutputFormat::JsonPretty => JsonEmitter::new(true).emit(value),
-        OutputFormat::Yaml => YamlEmitter::new().emit(value),
+        OutputFormat::Json | OutputFormat::Ndjson => JsonEmitter::new(false).emit(value),
+        OutputFormat::JsonPretty => match options.indent_str() {
+            Some(indent) => JsonEmitter::with_indent(indent).emit(value),
+            None => JsonEmitter::new(true).emit(value),
+        },
+        OutputFormat::JsonCanonical => JsonEmitter::canonical().emit(value),
+        OutputFormat::Jsonc => {
+            let emitter = match options.indent_str() {
+                Some(indent) => JsonEmitter::jsonc().indented(indent),
+                None => JsonEmitter::jsonc(),
+            };
+            let origins = if options.key_origins {
+                options.origins.clone()
+            } else {
+                DocMap::new()
+            };
+            emitter
+                .with_doc_comments(options.doc_comments.clone())
+                .with_key_origins(origins)
+                .with_trailing_commas(options.trailing_commas)
+                .emit(value)
+        }
+        OutputFormat::Yaml => {
+            let emitter = match options.indent_str() {
+                Some(indent) => YamlEmitter::with_indent(indent),
+                None => YamlEmitter::new(),
+            };
+            emitter
+                .with_quoted_strings(options.quote_strings)
+                .with_doc_comments(options.doc_comments.clone())
+                .emit(value)
+        }
         OutputFormat::Toml => TomlEmitter::new().emit(value),
-        OutputFormat::Dotenv => DotenvEmitter::new().emit(value),
+        OutputFormat::Dotenv => options.dotenv_emitter().emit(value),
+        OutputFormat::Hcl => match options.indent_str() {
+            Some(indent) => HclEmitter::with_indent(indent).emit(value),
+            None => HclEmitter::new().emit(value),
+        },
+        OutputFormat::Xml => match options.indent_str() {
+            Some(indent) => XmlEmitter::with_indent(indent).emit(value),
+            None => XmlEmitter::new().emit(value),
+        },
+        OutputFormat::Properties => PropertiesEmitter::new().emit(value),
+        OutputFormat::Csv => CsvEmitter::new().emit(value),
+        OutputFormat::Custom(name) => match registry::registered_emitter(name) {
+            Some((_, emitter)) => emitter.emit(value),
+            None => Err(HoneError::io_error(format!(
+                "output format '{}' is not registered",
+                name
+            ))),
+        },
+    }
+}
+
+/// Emit a value to a writer in the specified format, without holding the
+/// whole output in memory (custom formats are still emitted to a string first)
+pub fn emit_to_writer(
+    value: &Value,
+    format: OutputFormat,
+    writer: &mut impl std::io::Write,
+) -> HoneResult<()> {
+    match format {
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            JsonEmitter::new(false).emit_to_writer(value, writer)
+        }
+        OutputFormat::JsonPretty => JsonEmitter::new(true).emit_to_writer(value, writer),
+        OutputFormat::JsonCanonical => JsonEmitter::canonical().emit_to_writer(value, writer),
+        OutputFormat::Jsonc => JsonEmitter::jsonc().emit_to_writer(value, writer),
+        OutputFormat::Yaml => YamlEmitter::new().emit_to_writer(value, writer),
+        OutputFormat::Toml => TomlEmitter::new().emit_to_writer(value, writer),
+        OutputFormat::Dotenv => DotenvEmitter::new().emit_to_writer(value, writer),
+        OutputFormat::Hcl => HclEmitter::new().emit_to_writer(value, writer),
+        OutputFormat::Xml => XmlEmitter::new().emit_to_writer(value, writer),
+        OutputFormat::Properties => PropertiesEmitter::new().emit_to_writer(value, writer),
+        OutputFormat::Csv => CsvEmitter::new().emit_to_writer(value, writer),
+        OutputFormat::Custom(_) => {
+            let output = emit(value, format)?;
+            writer
+                .write_all(output.as_bytes())
+                .map_err(|e| HoneError::io_error(format!("failed to write output: {}", e)))
+        }
     }
 }
 
 /// Emit multiple values (for multi-document output)
 pub fn emit_multi(values: &[(Option<String>, Value)], format: OutputFormat) -> HoneResult<String> {
+    emit_multi_with(values, format, &EmitOptions::default())
+}
+
+/// Emit multiple values in the specified style
+pub fn emit_multi_with(
+    values: &[(Option<String>, Value)],
+    format: OutputFormat,
+    options: &EmitOptions,
+) -> HoneResult<String> {
+    let emit = |value: &Value, format: OutputFormat| emit_with(value, format, options);
+    // Documents are rows of one table; names are dropped, as CSV has no comments
+    if format == OutputFormat::Csv {
+        let rows = values.iter().map(|(_, value)| value.clone()).collect();
+        return emit(&Value::Array(rows), format);
+    }
     let mut output = String::new();
 
     for (i, (name, value)) in values.iter().enumerate() {
-        if i > 0 {
+        if i > 0 && format != OutputFormat::Ndjson {
             output.push('\n');
         }
 
         match format {
-            OutputFormat::Json | OutputFormat::JsonPretty => {
+            // Every document is one line; names are dropped, as JSON has no comments
+            OutputFormat::Ndjson => {
+                output.push_str(&emit(value, format)?);
+                output.push('\n');
+            }
+            OutputFormat::Json
+            | OutputFormat::JsonPretty
+            | OutputFormat::JsonCanonical
+            | OutputFormat::Jsonc => {
                 if let Some(name) = name {
                     output.push_str(&format!("// Document: {}\n", name));
                 }
@@ -98,12 +475,21 @@ pub fn emit_multi(values: &[(Option<String>, Value)], format: OutputFormat) -> H
                 }
                 output.push_str(&emit(value, format)?);
             }
-            OutputFormat::Dotenv => {
+            OutputFormat::Dotenv | OutputFormat::Hcl | OutputFormat::Properties => {
                 if let Some(name) = name {
                     output.push_str(&format!("# Document: {}\n", name));
                 }
                 output.push_str(&emit(value, format)?);
             }
+            OutputFormat::Xml | OutputFormat::Csv => {
+                output.push_str(&emit(value, format)?);
+            }
+            OutputFormat::Custom(_) => {
+                if let (Some(name), Some(prefix)) = (name, format.comment_prefix()) {
+                    output.push_str(&format!("{} Document: {}\n", prefix, name));
+                }
+                output.push_str(&emit(value, format)?);
+            }
         }
     }
 
@@ -133,10 +519,25 @@ mod tests {
             OutputFormat::parse("json-pretty"),
             Some(OutputFormat::JsonPretty)
         );
+        assert_eq!(
+            OutputFormat::parse("json-canonical"),
+            Some(OutputFormat::JsonCanonical)
+        );
+        assert_eq!(OutputFormat::parse("jsonc"), Some(OutputFormat::Jsonc));
+        assert_eq!(OutputFormat::parse("json5"), Some(OutputFormat::Jsonc));
+        assert_eq!(OutputFormat::parse("ndjson"), Some(OutputFormat::Ndjson));
+        assert_eq!(OutputFormat::parse("jsonl"), Some(OutputFormat::Ndjson));
         assert_eq!(OutputFormat::parse("toml"), Some(OutputFormat::Toml));
         assert_eq!(OutputFormat::parse("TOML"), Some(OutputFormat::Toml));
         assert_eq!(OutputFormat::parse("dotenv"), Some(OutputFormat::Dotenv));
         assert_eq!(OutputFormat::parse("env"), Some(OutputFormat::Dotenv));
+        assert_eq!(OutputFormat::parse("hcl"), Some(OutputFormat::Hcl));
+        assert_eq!(OutputFormat::parse("xml"), Some(OutputFormat::Xml));
+        assert_eq!(
+            OutputFormat::parse("properties"),
+            Some(OutputFormat::Properties)
+        );
+        assert_eq!(OutputFormat::parse("csv"), Some(OutputFormat::Csv));
         assert_eq!(OutputFormat::parse("unknown"), None);
     }
 
@@ -153,4 +554,121 @@ mod tests {
         let yaml = emit(&value, OutputFormat::Yaml).unwrap();
         assert!(yaml.contains("name: test"));
     }
+
+    /// Writer recording each write, to see that output arrives in pieces
+    #[derive(Default)]
+    struct Chunks(Vec<Vec<u8>>);
+
+    impl std::io::Write for Chunks {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_emit_to_writer_matches_emit() {
+        let server = obj(&[
+            ("host", Value::String("localhost".into())),
+            ("port", Value::Int(8080)),
+            ("motd", Value::String("line one\nline two".into())),
+        ]);
+        let items: Vec<Value> = (0..8)
+            .map(|i| obj(&[("id", Value::Int(i)), ("ratio", Value::Float(0.5))]))
+            .collect();
+        let value = obj(&[
+            ("name", Value::String("app".into())),
+            ("tags", Value::Array(vec!["a".into(), "b".into()])),
+            ("server", server),
+            ("items", Value::Array(items)),
+        ]);
+
+        for format in [
+            OutputFormat::Json,
+            OutputFormat::JsonPretty,
+            OutputFormat::JsonCanonical,
+            OutputFormat::Jsonc,
+            OutputFormat::Yaml,
+            OutputFormat::Toml,
+            OutputFormat::Dotenv,
+            OutputFormat::Hcl,
+            OutputFormat::Xml,
+            OutputFormat::Properties,
+        ] {
+            let mut chunks = Chunks::default();
+            emit_to_writer(&value, format, &mut chunks).unwrap();
+            assert!(
+                chunks.0.len() > 1,
+                "{} was written in one piece",
+                format.name()
+            );
+            assert_eq!(
+                String::from_utf8(chunks.0.concat()).unwrap(),
+                emit(&value, format).unwrap(),
+                "{}",
+                format.name()
+            );
+        }
+    }
+
+    #[test]
+    fn test_emit_with_options() {
+        let value = obj(&[
+            ("name", Value::String("api".into())),
+            ("note", Value::Null),
+            (
+                "server",
+                obj(&[("port", Value::Int(80)), ("tls", Value::Null)]),
+            ),
+            ("list", Value::Array(vec![Value::Int(1), Value::Null])),
+        ]);
+        let options = EmitOptions::new()
+            .with_indent(Some(4))
+            .with_quote_strings(true)
+            .with_omit_nulls(true);
+        assert_eq!(
+            emit_with(&value, OutputFormat::Yaml, &options).unwrap(),
+            "name: \"api\"\nserver:\n    port: 80\nlist: [1, null]"
+        );
+        assert_eq!(
+            emit_with(&value, OutputFormat::JsonPretty, &options).unwrap(),
+            "{\n    \"name\": \"api\",\n    \"server\": {\n        \"port\": 80\n    },\n    \"list\": [\n        1,\n        null\n    ]\n}"
+        );
+        // Defaults match plain `emit`
+        assert_eq!(
+            emit_with(&value, OutputFormat::Yaml, &EmitOptions::new()).unwrap(),
+            emit(&value, OutputFormat::Yaml).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_emit_multi_ndjson() {
+        let docs = vec![
+            (None, obj(&[("a", Value::Int(1))])),
+            (
+                Some("second".to_string()),
+                obj(&[("b", Value::Array(vec![Value::Int(1), Value::Int(2)]))]),
+            ),
+        ];
+        assert_eq!(
+            emit_multi(&docs, OutputFormat::Ndjson).unwrap(),
+            "{\"a\":1}\n{\"b\":[1,2]}\n"
+        );
+    }
+
+    #[test]
+    fn test_emit_multi_csv_documents_are_rows() {
+        let docs = vec![
+            (Some("web".to_string()), obj(&[("cpus", Value::Int(2))])),
+            (Some("db".to_string()), obj(&[("cpus", Value::Int(8))])),
+        ];
+        assert_eq!(
+            emit_multi(&docs, OutputFormat::Csv).unwrap(),
+            "cpus\n2\n8\n"
+        );
+    }
 }
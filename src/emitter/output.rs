@@ -0,0 +1,96 @@
+//! Text produced by an emitter
+//!
+//! Emitters append to an [`Output`] and mark the points where a section of the
+//! document is complete. For `emit` everything is kept and returned as one
+//! string; for `emit_to_writer` each completed section is handed to the writer,
+//! so a large document is never held in memory twice.
+
+use std::io::Write;
+
+use crate::errors::{HoneError, HoneResult};
+
+/// Bytes kept back at a section boundary, so separators can still be chosen
+/// by what the output ends with
+const TAIL: usize = 2;
+
+/// Text being emitted, either collected or streamed to a writer
+pub(crate) struct Output<'a> {
+    text: String,
+    writer: Option<&'a mut dyn Write>,
+    /// Whether anything has been handed to the writer
+    written: bool,
+}
+
+impl<'a> Output<'a> {
+    /// Collect the whole output
+    pub(crate) fn new() -> Self {
+        Self {
+            text: String::new(),
+            writer: None,
+            written: false,
+        }
+    }
+
+    /// Stream completed sections to `writer`
+    pub(crate) fn to_writer(writer: &'a mut dyn Write) -> Self {
+        Self {
+            text: String::new(),
+            writer: Some(writer),
+            written: false,
+        }
+    }
+
+    pub(crate) fn push_str(&mut self, s: &str) {
+        self.text.push_str(s);
+    }
+
+    pub(crate) fn push(&mut self, ch: char) {
+        self.text.push(ch);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        !self.written && self.text.is_empty()
+    }
+
+    /// Whether the output so far ends with `suffix` (at most two bytes)
+    pub(crate) fn ends_with(&self, suffix: &str) -> bool {
+        debug_assert!(suffix.len() <= TAIL);
+        self.text.ends_with(suffix)
+    }
+
+    /// Mark a section boundary; a streaming output writes what it has
+    pub(crate) fn section(&mut self) -> HoneResult<()> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        let mut keep = self.text.len().saturating_sub(TAIL);
+        while !self.text.is_char_boundary(keep) {
+            keep -= 1;
+        }
+        if keep > 0 {
+            writer
+                .write_all(&self.text.as_bytes()[..keep])
+                .map_err(write_error)?;
+            self.text.drain(..keep);
+            self.written = true;
+        }
+        Ok(())
+    }
+
+    /// The collected text; empty when streaming, after writing the rest
+    pub(crate) fn finish(self) -> HoneResult<String> {
+        match self.writer {
+            Some(writer) => {
+                writer
+                    .write_all(self.text.as_bytes())
+                    .map_err(write_error)?;
+                Ok(String::new())
+            }
+            None => Ok(self.text),
+        }
+    }
+}
+
+fn write_error(e: std::io::Error) -> HoneError {
+    HoneError::io_error(format!("failed to write output: {}", e))
+}
@@ -0,0 +1,219 @@
+//! Java .properties emitter for Hone values
+//!
+//! Nested objects flatten into dotted keys (`server.port=8080`) and arrays into
+//! indexed keys (`hosts[0]=a`), the layout Spring Boot binds to lists and maps.
+//! Escaping follows `java.util.Properties.store`: separators and comment
+//! characters are backslash-escaped and anything outside printable ASCII is
+//! written as `\uXXXX`, so the file reads the same in any encoding.
+
+use std::io::Write;
+
+use super::output::Output;
+use super::Emitter;
+use crate::errors::{HoneError, HoneResult};
+use crate::evaluator::Value;
+use crate::lexer::{duration, size};
+
+/// .properties output emitter
+pub struct PropertiesEmitter;
+
+impl PropertiesEmitter {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Emit one `key=value` line per flattened pair
+    fn emit_lines(&self, result: &mut Output, value: &Value) -> HoneResult<()> {
+        if !matches!(value, Value::Object(_)) {
+            return Err(HoneError::io_error(
+                "properties output requires a top-level object".to_string(),
+            ));
+        }
+        let mut pairs = Vec::new();
+        self.flatten(value, "", &mut pairs)?;
+
+        for (key, val) in &pairs {
+            result.push_str(&Self::escape(key, true));
+            result.push('=');
+            result.push_str(&Self::escape(val, false));
+            result.push('\n');
+            result.section()?;
+        }
+        Ok(())
+    }
+
+    /// Flatten a value into key=value pairs
+    fn flatten(
+        &self,
+        value: &Value,
+        prefix: &str,
+        pairs: &mut Vec<(String, String)>,
+    ) -> HoneResult<()> {
+        match value {
+            Value::Object(obj) => {
+                for (key, val) in obj {
+                    let full_key = if prefix.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", prefix, key)
+                    };
+                    self.flatten(val, &full_key, pairs)?;
+                }
+            }
+            Value::Array(arr) => {
+                for (i, item) in arr.iter().enumerate() {
+                    self.flatten(item, &format!("{}[{}]", prefix, i), pairs)?;
+                }
+            }
+            Value::Null => {
+                // Skip null values
+            }
+            Value::Function(_) => return Err(super::function_error(value)),
+            Value::Bool(b) => pairs.push((prefix.to_string(), b.to_string())),
+            Value::Int(n) => pairs.push((prefix.to_string(), n.to_string())),
+            Value::Duration(ms) => pairs.push((prefix.to_string(), duration::format(*ms))),
+            Value::Size(bytes) => pairs.push((prefix.to_string(), size::format(*bytes))),
+            Value::Float(n) => {
+                if !n.is_finite() {
+                    return Err(HoneError::io_error(format!(
+                        "properties output does not support non-finite numbers ({} at '{}')",
+                        n, prefix
+                    )));
+                }
+                if n.fract() == 0.0 {
+                    pairs.push((prefix.to_string(), format!("{:.1}", n)));
+                } else {
+                    pairs.push((prefix.to_string(), n.to_string()));
+                }
+            }
+            Value::String(s) => pairs.push((prefix.to_string(), s.clone())),
+        }
+        Ok(())
+    }
+
+    /// Escape a key or value. Every space in a key is escaped, since a space
+    /// would end the key; in a value only a leading space needs it.
+    fn escape(s: &str, is_key: bool) -> String {
+        let mut result = String::with_capacity(s.len());
+        for (i, ch) in s.chars().enumerate() {
+            match ch {
+                ' ' if is_key || i == 0 => result.push_str("\\ "),
+                '\\' => result.push_str("\\\\"),
+                '\t' => result.push_str("\\t"),
+                '\n' => result.push_str("\\n"),
+                '\r' => result.push_str("\\r"),
+                '\x0c' => result.push_str("\\f"),
+                '=' | ':' | '#' | '!' => {
+                    result.push('\\');
+                    result.push(ch);
+                }
+                c if (' '..='~').contains(&c) => result.push(c),
+                c => {
+                    let mut units = [0u16; 2];
+                    for unit in c.encode_utf16(&mut units) {
+                        result.push_str(&format!("\\u{:04X}", unit));
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+impl Default for PropertiesEmitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Emitter for PropertiesEmitter {
+    fn emit(&self, value: &Value) -> HoneResult<String> {
+        let mut result = Output::new();
+        self.emit_lines(&mut result, value)?;
+        result.finish()
+    }
+
+    fn emit_to_writer<W: Write>(&self, value: &Value, writer: &mut W) -> HoneResult<()> {
+        let mut result = Output::to_writer(writer);
+        self.emit_lines(&mut result, value)?;
+        result.finish().map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    fn obj(pairs: &[(&str, Value)]) -> Value {
+        let mut map = IndexMap::new();
+        for (k, v) in pairs {
+            map.insert(k.to_string(), v.clone());
+        }
+        Value::Object(map)
+    }
+
+    #[test]
+    fn test_nested_objects_flatten_to_dotted_keys() {
+        let emitter = PropertiesEmitter::new();
+        let value = obj(&[
+            (
+                "server",
+                obj(&[
+                    ("port", Value::Int(8080)),
+                    ("ssl", obj(&[("enabled", Value::Bool(true))])),
+                ]),
+            ),
+            ("ratio", Value::Float(1.0)),
+            ("unset", Value::Null),
+        ]);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "server.port=8080\nserver.ssl.enabled=true\nratio=1.0\n"
+        );
+    }
+
+    #[test]
+    fn test_arrays_use_indexed_keys() {
+        let emitter = PropertiesEmitter::new();
+        let value = obj(&[
+            (
+                "hosts",
+                Value::Array(vec![Value::String("a".into()), Value::String("b".into())]),
+            ),
+            (
+                "routes",
+                Value::Array(vec![obj(&[("path", Value::String("/api".into()))])]),
+            ),
+        ]);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "hosts[0]=a\nhosts[1]=b\nroutes[0].path=/api\n"
+        );
+    }
+
+    #[test]
+    fn test_escapes_separators_and_unicode() {
+        let emitter = PropertiesEmitter::new();
+        let value = obj(&[
+            ("url", Value::String("jdbc:h2:mem:test;a=b".into())),
+            ("key with:sep", Value::String(" padded # note".into())),
+            ("greeting", Value::String("héllo 😀\nworld".into())),
+        ]);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "url=jdbc\\:h2\\:mem\\:test;a\\=b\n\
+             key\\ with\\:sep=\\ padded \\# note\n\
+             greeting=h\\u00E9llo \\uD83D\\uDE00\\nworld\n"
+        );
+    }
+
+    #[test]
+    fn test_requires_top_level_object() {
+        let emitter = PropertiesEmitter::new();
+        assert!(emitter.emit(&Value::Int(1)).is_err());
+        assert!(emitter
+            .emit(&obj(&[("x", Value::Float(f64::NAN))]))
+            .is_err());
+    }
+}
@@ -0,0 +1,117 @@
+//! Registry of custom output formats
+//!
+//! Library users register an [`Emitter`] under a name; the name then works
+//! everywhere a built-in format does: `OutputFormat::parse`, `--format`,
+//! output extension detection, and the wasm bindings.
+
+use std::sync::{Arc, RwLock};
+
+use super::{Emitter, OutputFormat};
+use crate::errors::{HoneError, HoneResult};
+
+type SharedEmitter = Arc<dyn Emitter + Send + Sync>;
+
+/// Registered emitters, in registration order
+static EMITTERS: RwLock<Vec<(&'static str, SharedEmitter)>> = RwLock::new(Vec::new());
+
+/// Register a custom output format. Registering a name again replaces the
+/// previous emitter. Built-in format names cannot be overridden.
+pub fn register_emitter(
+    name: &str,
+    emitter: Box<dyn Emitter + Send + Sync>,
+) -> HoneResult<OutputFormat> {
+    let name = name.to_lowercase();
+    if OutputFormat::parse_builtin(&name).is_some() {
+        return Err(HoneError::io_error(format!(
+            "cannot register emitter '{}': it is a built-in output format",
+            name
+        )));
+    }
+
+    let mut emitters = EMITTERS.write().unwrap_or_else(|e| e.into_inner());
+    let emitter: SharedEmitter = Arc::from(emitter);
+    if let Some(entry) = emitters.iter_mut().find(|(n, _)| *n == name) {
+        entry.1 = emitter;
+        return Ok(OutputFormat::Custom(entry.0));
+    }
+
+    // Names live for the whole process, like the registry itself
+    let name: &'static str = Box::leak(name.into_boxed_str());
+    emitters.push((name, emitter));
+    Ok(OutputFormat::Custom(name))
+}
+
+/// Look up a registered emitter by name
+pub fn registered_emitter(name: &str) -> Option<(&'static str, SharedEmitter)> {
+    let emitters = EMITTERS.read().unwrap_or_else(|e| e.into_inner());
+    emitters
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(n, e)| (*n, Arc::clone(e)))
+}
+
+/// Find the registered format claiming a file extension
+pub fn registered_for_extension(ext: &str) -> Option<&'static str> {
+    let emitters = EMITTERS.read().unwrap_or_else(|e| e.into_inner());
+    emitters
+        .iter()
+        .find(|(_, e)| e.extensions().iter().any(|x| x.eq_ignore_ascii_case(ext)))
+        .map(|(n, _)| *n)
+}
+
+/// Names of all registered formats, in registration order
+pub fn registered_names() -> Vec<&'static str> {
+    let emitters = EMITTERS.read().unwrap_or_else(|e| e.into_inner());
+    emitters.iter().map(|(n, _)| *n).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Value;
+
+    struct KeysEmitter;
+
+    impl Emitter for KeysEmitter {
+        fn emit(&self, value: &Value) -> HoneResult<String> {
+            match value {
+                Value::Object(obj) => Ok(obj.keys().cloned().collect::<Vec<_>>().join("\n")),
+                _ => Ok(String::new()),
+            }
+        }
+
+        fn extensions(&self) -> &[&str] {
+            &["keys"]
+        }
+    }
+
+    #[test]
+    fn test_register_custom_emitter() {
+        let format = register_emitter("Keys-Test", Box::new(KeysEmitter)).unwrap();
+        assert_eq!(format, OutputFormat::Custom("keys-test"));
+        assert_eq!(OutputFormat::parse("keys-test"), Some(format));
+        assert_eq!(OutputFormat::from_extension("keys"), Some(format));
+        assert_eq!(format.name(), "keys-test");
+        assert_eq!(format.extension(), "keys");
+
+        let value = Value::Object(
+            [
+                ("a".to_string(), Value::Int(1)),
+                ("b".to_string(), Value::Int(2)),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(super::super::emit(&value, format).unwrap(), "a\nb");
+
+        // Re-registering keeps the same name
+        let again = register_emitter("keys-test", Box::new(KeysEmitter)).unwrap();
+        assert_eq!(again, format);
+    }
+
+    #[test]
+    fn test_register_builtin_name_rejected() {
+        assert!(register_emitter("yaml", Box::new(KeysEmitter)).is_err());
+        assert!(registered_emitter("yaml").is_none());
+    }
+}
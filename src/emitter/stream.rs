@@ -0,0 +1,261 @@
+//! Incremental emission for outputs too large to hold in memory
+//!
+//! A top-level object is written one entry at a time, and an array entry can be
+//! written one element at a time, so only the element being emitted is ever
+//! materialized. The result matches the regular JSON emitters byte for byte;
+//! YAML streamed arrays are always written in block style, since whether an
+//! array is short enough for flow style is not known until it ends.
+
+use std::io::Write;
+
+use indexmap::IndexMap;
+
+use super::{Emitter, JsonEmitter, OutputFormat, YamlEmitter};
+use crate::errors::{HoneError, HoneResult};
+use crate::evaluator::Value;
+
+/// Writes a top-level object incrementally in JSON or YAML
+pub struct StreamEmitter<W: Write> {
+    out: W,
+    format: OutputFormat,
+    /// Top-level entries written so far
+    entries: usize,
+    /// Key of the array being streamed and the number of elements written
+    array: Option<(String, usize)>,
+}
+
+impl<W: Write> StreamEmitter<W> {
+    /// Create a streaming emitter; only JSON and YAML can be streamed
+    pub fn new(format: OutputFormat, out: W) -> HoneResult<Self> {
+        if !matches!(
+            format,
+            OutputFormat::Json | OutputFormat::JsonPretty | OutputFormat::Yaml
+        ) {
+            return Err(HoneError::io_error(format!(
+                "streaming is only supported for json and yaml output, not {}",
+                format.name()
+            )));
+        }
+        Ok(Self {
+            out,
+            format,
+            entries: 0,
+            array: None,
+        })
+    }
+
+    /// Write a complete `key: value` entry
+    pub fn entry(&mut self, key: &str, value: &Value) -> HoneResult<()> {
+        self.check_no_array()?;
+        let text = match self.format {
+            OutputFormat::Yaml => {
+                let mut obj = IndexMap::new();
+                obj.insert(key.to_string(), value.clone());
+                format!("{}\n", YamlEmitter::new().emit(&Value::Object(obj))?)
+            }
+            _ => format!("{}{}", self.json_key(key), self.json().emit_value(value, 1)),
+        };
+        self.entries += 1;
+        self.write(&text)
+    }
+
+    /// Start an array entry whose elements follow through [`StreamEmitter::item`]
+    pub fn begin_array(&mut self, key: &str) -> HoneResult<()> {
+        self.check_no_array()?;
+        if self.format != OutputFormat::Yaml {
+            let text = format!("{}[", self.json_key(key));
+            self.write(&text)?;
+        }
+        self.entries += 1;
+        self.array = Some((key.to_string(), 0));
+        Ok(())
+    }
+
+    /// Write the next element of the current array
+    pub fn item(&mut self, value: &Value) -> HoneResult<()> {
+        super::reject_functions(value)?;
+        let json = self.json();
+        let Some((key, count)) = self.array.as_mut() else {
+            return Err(HoneError::io_error(
+                "stream item written outside of an array".to_string(),
+            ));
+        };
+        let text = match self.format {
+            OutputFormat::Yaml => {
+                let header = if *count == 0 {
+                    format!("{}:\n", YamlEmitter::new().escape_key(key))
+                } else {
+                    String::new()
+                };
+                format!(
+                    "{}{}\n",
+                    header,
+                    YamlEmitter::new().emit_block_item(value, 1, None)
+                )
+            }
+            OutputFormat::JsonPretty => format!(
+                "{}\n    {}",
+                if *count == 0 { "" } else { "," },
+                json.emit_value(value, 2)
+            ),
+            _ => format!(
+                "{}{}",
+                if *count == 0 { "" } else { "," },
+                json.emit_value(value, 2)
+            ),
+        };
+        *count += 1;
+        self.write(&text)
+    }
+
+    /// Close the current array
+    pub fn end_array(&mut self) -> HoneResult<()> {
+        let Some((key, count)) = self.array.take() else {
+            return Err(HoneError::io_error("no stream array to end".to_string()));
+        };
+        let text = match self.format {
+            OutputFormat::Yaml if count == 0 => {
+                format!("{}: []\n", YamlEmitter::new().escape_key(&key))
+            }
+            OutputFormat::Yaml => return Ok(()),
+            OutputFormat::JsonPretty if count > 0 => "\n  ]".to_string(),
+            _ => "]".to_string(),
+        };
+        self.write(&text)
+    }
+
+    /// Close the document and return the writer
+    pub fn finish(mut self) -> HoneResult<W> {
+        self.check_no_array()?;
+        let text = match (self.format, self.entries) {
+            (OutputFormat::Yaml, 0) => "{}\n",
+            (OutputFormat::Yaml, _) => "",
+            (_, 0) => "{}\n",
+            (OutputFormat::JsonPretty, _) => "\n}\n",
+            _ => "}\n",
+        };
+        self.write(text)?;
+        self.out
+            .flush()
+            .map_err(|e| HoneError::io_error(format!("failed to write output: {}", e)))?;
+        Ok(self.out)
+    }
+
+    fn json(&self) -> JsonEmitter {
+        JsonEmitter::new(self.format == OutputFormat::JsonPretty)
+    }
+
+    /// Separator, then the quoted key and colon of a JSON entry
+    fn json_key(&self, key: &str) -> String {
+        let json = self.json();
+        match (self.format, self.entries) {
+            (OutputFormat::JsonPretty, 0) => format!("{{\n  {}: ", json.escape_string(key)),
+            (OutputFormat::JsonPretty, _) => format!(",\n  {}: ", json.escape_string(key)),
+            (_, 0) => format!("{{{}:", json.escape_string(key)),
+            _ => format!(",{}:", json.escape_string(key)),
+        }
+    }
+
+    fn check_no_array(&self) -> HoneResult<()> {
+        match &self.array {
+            Some((key, _)) => Err(HoneError::io_error(format!(
+                "stream array '{}' was not ended",
+                key
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn write(&mut self, text: &str) -> HoneResult<()> {
+        self.out
+            .write_all(text.as_bytes())
+            .map_err(|e| HoneError::io_error(format!("failed to write output: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emitter::emit;
+
+    fn obj(pairs: &[(&str, Value)]) -> Value {
+        let mut map = IndexMap::new();
+        for (k, v) in pairs {
+            map.insert(k.to_string(), v.clone());
+        }
+        Value::Object(map)
+    }
+
+    /// Stream `value`, writing its array entries element by element
+    fn stream(value: &Value, format: OutputFormat) -> String {
+        let mut emitter = StreamEmitter::new(format, Vec::new()).unwrap();
+        if let Value::Object(map) = value {
+            for (key, val) in map {
+                match val {
+                    Value::Array(items) => {
+                        emitter.begin_array(key).unwrap();
+                        for item in items {
+                            emitter.item(item).unwrap();
+                        }
+                        emitter.end_array().unwrap();
+                    }
+                    other => emitter.entry(key, other).unwrap(),
+                }
+            }
+        }
+        String::from_utf8(emitter.finish().unwrap()).unwrap()
+    }
+
+    fn sample() -> Value {
+        obj(&[
+            ("name", Value::String("fixtures".into())),
+            (
+                "items",
+                Value::Array(
+                    (0..3)
+                        .map(|i| obj(&[("id", Value::Int(i)), ("on", Value::Bool(i % 2 == 0))]))
+                        .collect(),
+                ),
+            ),
+            ("empty", Value::Array(vec![])),
+            ("meta", obj(&[("count", Value::Int(3))])),
+        ])
+    }
+
+    #[test]
+    fn test_json_matches_regular_emitter() {
+        for format in [OutputFormat::Json, OutputFormat::JsonPretty] {
+            let value = sample();
+            assert_eq!(
+                stream(&value, format),
+                format!("{}\n", emit(&value, format).unwrap())
+            );
+            let empty = obj(&[]);
+            assert_eq!(stream(&empty, format), "{}\n");
+        }
+    }
+
+    #[test]
+    fn test_yaml_matches_regular_emitter_for_block_arrays() {
+        let value = sample();
+        assert_eq!(
+            stream(&value, OutputFormat::Yaml),
+            format!("{}\n", emit(&value, OutputFormat::Yaml).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_yaml_short_arrays_stay_block_style() {
+        let value = obj(&[("ports", Value::Array(vec![Value::Int(80)]))]);
+        assert_eq!(stream(&value, OutputFormat::Yaml), "ports:\n  - 80\n");
+    }
+
+    #[test]
+    fn test_rejects_other_formats_and_misuse() {
+        assert!(StreamEmitter::new(OutputFormat::Toml, Vec::new()).is_err());
+        let mut emitter = StreamEmitter::new(OutputFormat::Json, Vec::new()).unwrap();
+        assert!(emitter.item(&Value::Int(1)).is_err());
+        emitter.begin_array("a").unwrap();
+        assert!(emitter.entry("b", &Value::Null).is_err());
+    }
+}
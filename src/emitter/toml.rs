@@ -1,8 +1,12 @@
 //! TOML emitter for Hone values
 
+use std::io::Write;
+
+use super::output::Output;
 use super::Emitter;
 use crate::errors::{HoneError, HoneResult};
 use crate::evaluator::Value;
+use crate::lexer::{duration, size};
 
 /// TOML output emitter
 pub struct TomlEmitter;
@@ -12,11 +16,11 @@ impl TomlEmitter {
         Self
     }
 
-    /// Emit the top-level value as TOML
-    fn emit_toplevel(&self, value: &Value) -> HoneResult<String> {
+    /// Emit the top-level value as TOML, ending a section after each line
+    /// of top-level keys and each table
+    fn emit_toplevel(&self, result: &mut Output, value: &Value) -> HoneResult<()> {
         match value {
             Value::Object(obj) => {
-                let mut result = String::new();
                 let mut tables = Vec::new();
 
                 // First pass: emit simple key-value pairs at the top level
@@ -35,6 +39,7 @@ impl TomlEmitter {
                             result.push_str(" = ");
                             result.push_str(&self.emit_value(val)?);
                             result.push('\n');
+                            result.section()?;
                         }
                     }
                 }
@@ -46,20 +51,16 @@ impl TomlEmitter {
                     }
                     match val {
                         Value::Object(ref inner) => {
-                            self.emit_table(&mut result, std::slice::from_ref(&key), inner)?;
+                            self.emit_table(result, std::slice::from_ref(&key), inner)?;
                         }
                         Value::Array(ref arr) => {
-                            self.emit_array_of_tables(
-                                &mut result,
-                                std::slice::from_ref(&key),
-                                arr,
-                            )?;
+                            self.emit_array_of_tables(result, std::slice::from_ref(&key), arr)?;
                         }
                         _ => unreachable!(),
                     }
                 }
 
-                Ok(result)
+                Ok(())
             }
             _ => Err(HoneError::io_error(
                 "TOML output requires a top-level object".to_string(),
@@ -70,7 +71,7 @@ impl TomlEmitter {
     /// Emit a [table] section
     fn emit_table(
         &self,
-        result: &mut String,
+        result: &mut Output,
         path: &[String],
         obj: &indexmap::IndexMap<String, Value>,
     ) -> HoneResult<()> {
@@ -99,6 +100,7 @@ impl TomlEmitter {
                 }
             }
         }
+        result.section()?;
 
         for (key, val) in sub_tables {
             let mut sub_path = path.to_vec();
@@ -123,7 +125,7 @@ impl TomlEmitter {
     /// Emit [[array.of.tables]]
     fn emit_array_of_tables(
         &self,
-        result: &mut String,
+        result: &mut Output,
         path: &[String],
         arr: &[Value],
     ) -> HoneResult<()> {
@@ -177,6 +179,7 @@ impl TomlEmitter {
                     }
 
                     result.push('\n');
+                    result.section()?;
                 }
                 _ => {
                     return Err(HoneError::io_error(
@@ -208,7 +211,10 @@ impl TomlEmitter {
                     Ok(n.to_string())
                 }
             }
+            Value::Duration(ms) => Ok(self.escape_string(&duration::format(*ms))),
+            Value::Size(bytes) => Ok(self.escape_string(&size::format(*bytes))),
             Value::String(s) => Ok(self.escape_string(s)),
+            Value::Function(_) => Err(super::function_error(value)),
             Value::Array(arr) => self.emit_inline_array(arr),
             Value::Object(obj) => self.emit_inline_object(obj),
         }
@@ -322,7 +328,15 @@ impl Default for TomlEmitter {
 
 impl Emitter for TomlEmitter {
     fn emit(&self, value: &Value) -> HoneResult<String> {
-        self.emit_toplevel(value)
+        let mut result = Output::new();
+        self.emit_toplevel(&mut result, value)?;
+        result.finish()
+    }
+
+    fn emit_to_writer<W: Write>(&self, value: &Value, writer: &mut W) -> HoneResult<()> {
+        let mut result = Output::to_writer(writer);
+        self.emit_toplevel(&mut result, value)?;
+        result.finish().map(drop)
     }
 }
 
@@ -346,13 +360,13 @@ mod tests {
             ("name", Value::String("test".into())),
             ("port", Value::Int(8080)),
             ("debug", Value::Bool(true)),
-            ("ratio", Value::Float(3.14)),
+            ("ratio", Value::Float(3.25)),
         ]);
         let result = emitter.emit(&value).unwrap();
         assert!(result.contains("name = \"test\""));
         assert!(result.contains("port = 8080"));
         assert!(result.contains("debug = true"));
-        assert!(result.contains("ratio = 3.14"));
+        assert!(result.contains("ratio = 3.25"));
     }
 
     #[test]
@@ -0,0 +1,258 @@
+//! Writing emitted output to disk
+//!
+//! Files are written to a temporary sibling and renamed into place, so a
+//! reader (or a crashed build) never sees a half-written config. With
+//! `no_clobber` the temporary file is hard-linked into place instead, which
+//! fails if the destination exists by then, however recently it appeared.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::OutputFormat;
+use crate::errors::{HoneError, HoneResult};
+
+/// Options controlling how an output file is written
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// Unix permission bits for the file (e.g. `0o600`). When unset, an
+    /// existing file keeps its permissions and a new one gets the umask default.
+    pub mode: Option<u32>,
+    /// Refuse to overwrite an existing file
+    pub no_clobber: bool,
+}
+
+impl WriteOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_mode(mut self, mode: Option<u32>) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_no_clobber(mut self, no_clobber: bool) -> Self {
+        self.no_clobber = no_clobber;
+        self
+    }
+}
+
+/// Parse an octal permission string like `0600` or `600`
+pub fn parse_mode(s: &str) -> Option<u32> {
+    let digits = s.strip_prefix("0o").unwrap_or(s);
+    if digits.is_empty() || digits.len() > 4 {
+        return None;
+    }
+    u32::from_str_radix(digits, 8).ok().filter(|m| *m <= 0o7777)
+}
+
+/// Header comment marking a file as generated, in the output format's comment
+/// syntax. JSON has no comments, so it gets no stamp.
+pub fn stamp_comment(format: OutputFormat, source: &str) -> Option<String> {
+    format.comment_prefix().map(|prefix| {
+        format!(
+            "{} Code generated by hone from {}. DO NOT EDIT.\n",
+            prefix, source
+        )
+    })
+}
+
+/// Atomically write `contents` to `path`
+pub fn write_output(path: &Path, contents: &str, options: &WriteOptions) -> HoneResult<()> {
+    write_output_with(path, options, |out| {
+        out.write_all(contents.as_bytes())
+            .map_err(|e| HoneError::io_error(format!("failed to write {}: {}", path.display(), e)))
+    })
+}
+
+/// Atomically write to `path` through a writer, for output produced
+/// incrementally. Nothing replaces `path` unless `write` succeeds.
+pub fn write_output_with(
+    path: &Path,
+    options: &WriteOptions,
+    write: impl FnOnce(&mut dyn Write) -> HoneResult<()>,
+) -> HoneResult<()> {
+    let already_exists =
+        || HoneError::io_error(format!("{} already exists (--no-clobber)", path.display()));
+    // Fail before doing the work when the answer is already known
+    if options.no_clobber && path.exists() {
+        return Err(already_exists());
+    }
+
+    let err = |e: std::io::Error| {
+        HoneError::io_error(format!("failed to write {}: {}", path.display(), e))
+    };
+    let (tmp_path, file) = create_temp(path, options).map_err(err)?;
+    let result = write_temp(file, &tmp_path, path, write, options).and_then(|()| {
+        if options.no_clobber {
+            // Unlike a rename, a link never replaces an existing file
+            std::fs::hard_link(&tmp_path, path).map_err(|e| match e.kind() {
+                std::io::ErrorKind::AlreadyExists => already_exists(),
+                _ => err(e),
+            })
+        } else {
+            std::fs::rename(&tmp_path, path).map_err(err)
+        }
+    });
+    if result.is_err() || options.no_clobber {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Create a new hidden temp file next to `path`, so the final rename stays on
+/// one filesystem. A name that is taken, by another writer or left over from
+/// a crash, is skipped rather than reused.
+fn create_temp(path: &Path, options: &WriteOptions) -> std::io::Result<(PathBuf, std::fs::File)> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "output".to_string());
+
+    let mut open = std::fs::OpenOptions::new();
+    open.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        // Create with the final mode so secrets are never briefly world-readable
+        if let Some(mode) = options.mode {
+            open.mode(mode);
+        }
+    }
+
+    let mut attempt = 0;
+    loop {
+        let tmp_path = path.with_file_name(format!(
+            ".{}.{}.{}.tmp",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        match open.open(&tmp_path) {
+            Ok(file) => return Ok((tmp_path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && attempt < 100 => {
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn write_temp(
+    file: std::fs::File,
+    tmp_path: &Path,
+    path: &Path,
+    write: impl FnOnce(&mut dyn Write) -> HoneResult<()>,
+    options: &WriteOptions,
+) -> HoneResult<()> {
+    let err = |e: std::io::Error| {
+        HoneError::io_error(format!("failed to write {}: {}", path.display(), e))
+    };
+
+    let mut buffered = std::io::BufWriter::new(file);
+    write(&mut buffered)?;
+    let file = buffered.into_inner().map_err(|e| err(e.into_error()))?;
+    file.sync_all().map_err(err)?;
+
+    // The umask applies at creation; set the exact bits explicitly
+    if let Some(mode) = options.mode {
+        set_mode(tmp_path, mode).map_err(err)?;
+    } else if let Ok(existing) = std::fs::metadata(path) {
+        std::fs::set_permissions(tmp_path, existing.permissions()).map_err(err)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_output_replaces_and_cleans_up() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("out.yaml");
+
+        write_output(&path, "a: 1\n", &WriteOptions::new()).unwrap();
+        write_output(&path, "a: 2\n", &WriteOptions::new()).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a: 2\n");
+
+        // Only the output itself is left behind
+        let entries: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn test_write_output_no_clobber() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("out.json");
+        let options = WriteOptions::new().with_no_clobber(true);
+
+        write_output(&path, "{}", &options).unwrap();
+        let err = write_output(&path, "[]", &options).unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+
+        // A file that appears while the output is being produced is kept
+        let late = dir.path().join("late.json");
+        let err = write_output_with(&late, &options, |out| {
+            std::fs::write(&late, "theirs").unwrap();
+            out.write_all(b"ours")
+                .map_err(|e| HoneError::io_error(e.to_string()))
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        assert_eq!(std::fs::read_to_string(&late).unwrap(), "theirs");
+
+        // No temp files are left behind
+        let entries: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_output_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("secrets.env");
+
+        write_output(&path, "A=1\n", &WriteOptions::new().with_mode(Some(0o600))).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // Rewriting without --mode keeps the existing permissions
+        write_output(&path, "A=2\n", &WriteOptions::new()).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_parse_mode_and_stamp() {
+        assert_eq!(parse_mode("0600"), Some(0o600));
+        assert_eq!(parse_mode("644"), Some(0o644));
+        assert_eq!(parse_mode("0o640"), Some(0o640));
+        assert_eq!(parse_mode("0900"), None);
+        assert_eq!(parse_mode("rw"), None);
+
+        assert_eq!(stamp_comment(OutputFormat::Json, "a.hone"), None);
+        assert_eq!(
+            stamp_comment(OutputFormat::Yaml, "a.hone").unwrap(),
+            "# Code generated by hone from a.hone. DO NOT EDIT.\n"
+        );
+    }
+}
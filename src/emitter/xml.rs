@@ -0,0 +1,297 @@
+//! XML emitter for Hone values
+//!
+//! Each key becomes an element and arrays become repeated elements with the
+//! key's name. Two key conventions map to the rest of XML:
+//! - `@name` keys become attributes of the enclosing element
+//! - a `#text` key becomes the element's text content
+//!
+//! A top-level object with a single key uses that key as the root element;
+//! otherwise the document is wrapped in `<root>`.
+
+use std::io::Write;
+
+use super::output::Output;
+use super::Emitter;
+use crate::errors::{HoneError, HoneResult};
+use crate::evaluator::Value;
+use crate::lexer::{duration, size};
+
+/// Prefix marking a key as an attribute
+const ATTRIBUTE_PREFIX: char = '@';
+/// Key holding an element's text content
+const TEXT_KEY: &str = "#text";
+
+/// XML output emitter
+pub struct XmlEmitter {
+    indent: String,
+}
+
+impl XmlEmitter {
+    pub fn new() -> Self {
+        Self {
+            indent: "  ".to_string(),
+        }
+    }
+
+    /// Create an XML emitter with custom indentation
+    pub fn with_indent(indent: impl Into<String>) -> Self {
+        Self {
+            indent: indent.into(),
+        }
+    }
+
+    /// Emit `<name ...>...</name>` for a value, repeating it for arrays; each
+    /// element ends a section
+    fn emit_element(
+        &self,
+        result: &mut Output,
+        name: &str,
+        value: &Value,
+        depth: usize,
+    ) -> HoneResult<()> {
+        Self::check_name(name)?;
+        let pad = self.indent.repeat(depth);
+
+        match value {
+            Value::Array(items) => {
+                for item in items {
+                    if matches!(item, Value::Array(_)) {
+                        return Err(HoneError::io_error(format!(
+                            "XML output cannot represent nested arrays (in <{}>)",
+                            name
+                        )));
+                    }
+                    self.emit_element(result, name, item, depth)?;
+                }
+            }
+            Value::Null => result.push_str(&format!("{}<{}/>\n", pad, name)),
+            Value::Object(obj) => {
+                let mut attributes = String::new();
+                let mut text = None;
+                let mut children = Vec::new();
+                for (key, val) in obj {
+                    if let Some(attr) = key.strip_prefix(ATTRIBUTE_PREFIX) {
+                        Self::check_name(attr)?;
+                        let val = self.scalar_text(val, key)?;
+                        attributes.push_str(&format!(" {}=\"{}\"", attr, escape(&val, true)));
+                    } else if key == TEXT_KEY {
+                        text = Some(self.scalar_text(val, key)?);
+                    } else {
+                        children.push((key, val));
+                    }
+                }
+
+                match (text, children.is_empty()) {
+                    (None, true) => {
+                        result.push_str(&format!("{}<{}{}/>\n", pad, name, attributes));
+                    }
+                    (Some(text), true) => result.push_str(&format!(
+                        "{}<{}{}>{}</{}>\n",
+                        pad,
+                        name,
+                        attributes,
+                        escape(&text, false),
+                        name
+                    )),
+                    (text, false) => {
+                        result.push_str(&format!("{}<{}{}>\n", pad, name, attributes));
+                        if let Some(text) = text {
+                            result.push_str(&format!(
+                                "{}{}\n",
+                                self.indent.repeat(depth + 1),
+                                escape(&text, false)
+                            ));
+                        }
+                        for (key, val) in children {
+                            self.emit_element(result, key, val, depth + 1)?;
+                        }
+                        result.push_str(&format!("{}</{}>\n", pad, name));
+                    }
+                }
+            }
+            scalar => {
+                let text = self.scalar_text(scalar, name)?;
+                result.push_str(&format!(
+                    "{}<{}>{}</{}>\n",
+                    pad,
+                    name,
+                    escape(&text, false),
+                    name
+                ));
+            }
+        }
+        result.section()
+    }
+
+    /// Emit the declaration and root element
+    fn emit_document(&self, result: &mut Output, value: &Value) -> HoneResult<()> {
+        result.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        match value {
+            Value::Object(obj)
+                if obj.len() == 1
+                    && !obj
+                        .keys()
+                        .any(|k| k.starts_with(ATTRIBUTE_PREFIX) || k == TEXT_KEY)
+                    && !matches!(obj.values().next(), Some(Value::Array(_))) =>
+            {
+                let (name, root) = obj.iter().next().expect("one key");
+                self.emit_element(result, name, root, 0)
+            }
+            Value::Object(_) => self.emit_element(result, "root", value, 0),
+            _ => Err(HoneError::io_error(
+                "XML output requires a top-level object".to_string(),
+            )),
+        }
+    }
+
+    /// Text for an attribute or text node; only scalars fit there
+    fn scalar_text(&self, value: &Value, key: &str) -> HoneResult<String> {
+        match value {
+            Value::Null => Ok(String::new()),
+            Value::Bool(b) => Ok(b.to_string()),
+            Value::Int(n) => Ok(n.to_string()),
+            Value::Float(n) => Ok(if n.fract() == 0.0 && n.is_finite() {
+                format!("{:.1}", n)
+            } else {
+                n.to_string()
+            }),
+            Value::Duration(ms) => Ok(duration::format(*ms)),
+            Value::Size(bytes) => Ok(size::format(*bytes)),
+            Value::String(s) => Ok(s.clone()),
+            Value::Function(_) => Err(super::function_error(value)),
+            Value::Array(_) | Value::Object(_) => Err(HoneError::io_error(format!(
+                "XML attribute or text '{}' must be a scalar, got {}",
+                key,
+                value.type_name()
+            ))),
+        }
+    }
+
+    /// Reject keys that are not valid XML element or attribute names
+    fn check_name(name: &str) -> HoneResult<()> {
+        let mut chars = name.chars();
+        let valid = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+            && chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':'))
+            && !name.to_lowercase().starts_with("xml");
+        if valid {
+            Ok(())
+        } else {
+            Err(HoneError::io_error(format!(
+                "'{}' is not a valid XML name",
+                name
+            )))
+        }
+    }
+}
+
+/// Escape text content, or attribute values when `attribute` is set
+fn escape(s: &str, attribute: bool) -> String {
+    let mut result = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' if attribute => result.push_str("&quot;"),
+            '\n' if attribute => result.push_str("&#10;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+impl Default for XmlEmitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Emitter for XmlEmitter {
+    fn emit(&self, value: &Value) -> HoneResult<String> {
+        let mut result = Output::new();
+        self.emit_document(&mut result, value)?;
+        result.finish()
+    }
+
+    fn emit_to_writer<W: Write>(&self, value: &Value, writer: &mut W) -> HoneResult<()> {
+        let mut result = Output::to_writer(writer);
+        self.emit_document(&mut result, value)?;
+        result.finish().map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    fn obj(pairs: &[(&str, Value)]) -> Value {
+        let mut map = IndexMap::new();
+        for (k, v) in pairs {
+            map.insert(k.to_string(), v.clone());
+        }
+        Value::Object(map)
+    }
+
+    #[test]
+    fn test_single_key_is_root() {
+        let emitter = XmlEmitter::new();
+        let value = obj(&[(
+            "server",
+            obj(&[
+                ("host", Value::String("localhost".into())),
+                ("port", Value::Int(8080)),
+            ]),
+        )]);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<server>\n  <host>localhost</host>\n  <port>8080</port>\n</server>\n"
+        );
+    }
+
+    #[test]
+    fn test_attributes_text_and_repeated_elements() {
+        let emitter = XmlEmitter::new();
+        let value = obj(&[(
+            "beans",
+            obj(&[(
+                "bean",
+                Value::Array(vec![
+                    obj(&[
+                        ("@id", Value::String("pool".into())),
+                        ("@lazy", Value::Bool(true)),
+                        ("#text", Value::String("a < b".into())),
+                    ]),
+                    obj(&[("@id", Value::String("cache".into()))]),
+                ]),
+            )]),
+        )]);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<beans>\n  <bean id=\"pool\" lazy=\"true\">a &lt; b</bean>\n  <bean id=\"cache\"/>\n</beans>\n"
+        );
+    }
+
+    #[test]
+    fn test_multiple_keys_wrap_in_root() {
+        let emitter = XmlEmitter::new();
+        let value = obj(&[("a", Value::Int(1)), ("b", Value::Null)]);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<root>\n  <a>1</a>\n  <b/>\n</root>\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_names_and_values_rejected() {
+        let emitter = XmlEmitter::new();
+        assert!(emitter.emit(&obj(&[("1bad", Value::Int(1))])).is_err());
+        assert!(emitter
+            .emit(&obj(&[(
+                "a",
+                obj(&[("@attr", Value::Array(vec![Value::Int(1)]))])
+            )]))
+            .is_err());
+        assert!(emitter.emit(&Value::Int(1)).is_err());
+    }
+}
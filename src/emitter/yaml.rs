@@ -1,13 +1,25 @@
 //! YAML emitter for Hone values
+//!
+//! Given the `##` doc comments of a compiled file, block-style keys are
+//! preceded by them as `#` comments. A key inside an array is documented once,
+//! above the first element.
 
+use std::io::Write;
+
+use super::output::Output;
 use super::Emitter;
 use crate::errors::HoneResult;
-use crate::evaluator::Value;
+use crate::evaluator::{DocMap, Value};
+use crate::lexer::{duration, size};
 
 /// YAML output emitter
 pub struct YamlEmitter {
     /// Indentation string
     indent: String,
+    /// Doc comments to write above keys, by dot-path
+    doc_comments: DocMap,
+    /// Quote every string value, not only those that need it
+    quote_strings: bool,
 }
 
 impl Default for YamlEmitter {
@@ -21,6 +33,8 @@ impl YamlEmitter {
     pub fn new() -> Self {
         Self {
             indent: "  ".to_string(),
+            doc_comments: DocMap::new(),
+            quote_strings: false,
         }
     }
 
@@ -28,9 +42,49 @@ impl YamlEmitter {
     pub fn with_indent(indent: impl Into<String>) -> Self {
         Self {
             indent: indent.into(),
+            doc_comments: DocMap::new(),
+            quote_strings: false,
         }
     }
 
+    /// Write these doc comments (dot-path -> text) above their keys
+    pub fn with_doc_comments(mut self, doc_comments: DocMap) -> Self {
+        self.doc_comments = doc_comments;
+        self
+    }
+
+    /// Double-quote every string value, for consumers that reject plain
+    /// scalars; multiline strings keep the literal block style
+    pub fn with_quoted_strings(mut self, quote_strings: bool) -> Self {
+        self.quote_strings = quote_strings;
+        self
+    }
+
+    /// Dot-path of `key` under `path`; `None` turns doc comments off below
+    fn child_path(path: Option<&str>, key: &str) -> Option<String> {
+        match path {
+            Some("") => Some(key.to_string()),
+            Some(path) => Some(format!("{}.{}", path, key)),
+            None => None,
+        }
+    }
+
+    /// `# ...` lines for the doc comment at `path`, each ending in a newline
+    fn doc_lines(&self, path: Option<&str>, indent: &str) -> String {
+        let Some(doc) = path.and_then(|p| self.doc_comments.get(p)) else {
+            return String::new();
+        };
+        doc.split('\n')
+            .map(|line| {
+                if line.is_empty() {
+                    format!("{}#\n", indent)
+                } else {
+                    format!("{}# {}\n", indent, line)
+                }
+            })
+            .collect()
+    }
+
     /// Emit a value at the given depth
     fn emit_value(&self, value: &Value, depth: usize, inline: bool) -> String {
         match value {
@@ -53,39 +107,45 @@ impl YamlEmitter {
                     n.to_string()
                 }
             }
+            Value::Duration(ms) => self.escape_string(&duration::format(*ms)),
+            Value::Size(bytes) => self.escape_string(&size::format(*bytes)),
             Value::String(s) if s.contains('\n') && !inline => self.emit_block_string(s, depth),
             Value::String(s) => self.escape_string(s),
-            Value::Array(arr) => self.emit_array(arr, depth, inline),
-            Value::Object(obj) => self.emit_object(obj, depth, inline),
+            Value::Array(arr) => self.emit_array(arr, depth, inline, None),
+            Value::Object(obj) => self.emit_object(obj, depth, inline, None),
+            // `emit` rejects functions before writing anything
+            Value::Function(_) => "null".to_string(),
         }
     }
 
-    /// Emit a multiline string using YAML literal block style (|)
+    /// Emit a multiline string using YAML literal block style (|), without
+    /// a trailing newline
     fn emit_block_string(&self, s: &str, depth: usize) -> String {
         let indent = self.indent.repeat(depth + 1);
-        let chomp = if s.ends_with('\n') { "" } else { "-" };
-        let mut result = format!("|{}\n", chomp);
-        for line in s.split('\n') {
-            if line.is_empty() {
-                result.push('\n');
-            } else {
+        // The chomp indicator says how many trailing newlines there are
+        let chomp = if s.ends_with("\n\n") {
+            "+"
+        } else if s.ends_with('\n') {
+            ""
+        } else {
+            "-"
+        };
+        let mut result = format!("|{}", chomp);
+        for line in s.strip_suffix('\n').unwrap_or(s).split('\n') {
+            result.push('\n');
+            if !line.is_empty() {
                 result.push_str(&indent);
                 result.push_str(line);
-                result.push('\n');
             }
         }
-        // Remove the trailing newline we added for the last line
-        // (the chomp indicator handles whether the original had one)
-        if !s.ends_with('\n') {
-            result.pop();
-        }
         result
     }
 
     /// Escape a string for YAML
     fn escape_string(&self, s: &str) -> String {
         // Check if we need quoting
-        let needs_quotes = s.is_empty()
+        let needs_quotes = self.quote_strings
+            || s.is_empty()
             || s.starts_with(' ')
             || s.ends_with(' ')
             || s.contains(':')
@@ -145,7 +205,7 @@ impl YamlEmitter {
     }
 
     /// Emit an array
-    fn emit_array(&self, arr: &[Value], depth: usize, inline: bool) -> String {
+    fn emit_array(&self, arr: &[Value], depth: usize, inline: bool, path: Option<&str>) -> String {
         if arr.is_empty() {
             return "[]".to_string();
         }
@@ -160,30 +220,60 @@ impl YamlEmitter {
         }
 
         // Block format
+        let items: Vec<_> = arr
+            .iter()
+            .enumerate()
+            .map(|(i, item)| self.emit_block_item(item, depth, path.filter(|_| i == 0)))
+            .collect();
+        items.join("\n")
+    }
+
+    /// Write the items of a block-style array one at a time
+    fn write_block_items(
+        &self,
+        result: &mut Output,
+        items: &[Value],
+        depth: usize,
+        path: Option<&str>,
+    ) -> HoneResult<()> {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                result.push('\n');
+            }
+            result.push_str(&self.emit_block_item(item, depth, path.filter(|_| i == 0)));
+            result.section()?;
+        }
+        Ok(())
+    }
+
+    /// Emit one `- item` entry of a block-style array, without a trailing newline
+    pub(crate) fn emit_block_item(&self, item: &Value, depth: usize, path: Option<&str>) -> String {
         let indent = self.indent.repeat(depth);
         let mut result = String::new();
 
-        for item in arr {
-            if !result.is_empty() {
+        // The first key shares the dash line, so its doc goes above the dash
+        if let Value::Object(obj) = item {
+            if let Some(first) = obj.keys().next() {
+                let first = Self::child_path(path, first);
+                result.push_str(&self.doc_lines(first.as_deref(), &indent));
+            }
+        }
+        result.push_str(&indent);
+        result.push_str("- ");
+
+        // Handle nested structures
+        match item {
+            Value::Object(obj) if !obj.is_empty() => {
+                // For objects in arrays, emit inline-style on first line
+                // then continue with proper indentation
+                result.push_str(&self.emit_object_as_array_item(obj, depth + 1, path));
+            }
+            Value::Array(inner) if !inner.is_empty() && !self.is_simple_array(inner) => {
                 result.push('\n');
+                result.push_str(&self.emit_array(inner, depth + 1, false, path));
             }
-            result.push_str(&indent);
-            result.push_str("- ");
-
-            // Handle nested structures
-            match item {
-                Value::Object(obj) if !obj.is_empty() => {
-                    // For objects in arrays, emit inline-style on first line
-                    // then continue with proper indentation
-                    result.push_str(&self.emit_object_as_array_item(obj, depth + 1));
-                }
-                Value::Array(inner) if !inner.is_empty() && !self.is_simple_array(inner) => {
-                    result.push('\n');
-                    result.push_str(&self.emit_array(inner, depth + 1, false));
-                }
-                _ => {
-                    result.push_str(&self.emit_value(item, depth + 1, true));
-                }
+            _ => {
+                result.push_str(&self.emit_value(item, depth + 1, true));
             }
         }
 
@@ -195,6 +285,7 @@ impl YamlEmitter {
         &self,
         obj: &indexmap::IndexMap<String, Value>,
         depth: usize,
+        path: Option<&str>,
     ) -> String {
         if obj.is_empty() {
             return "{}".to_string();
@@ -205,8 +296,10 @@ impl YamlEmitter {
         let mut first = true;
 
         for (key, value) in obj {
+            let child = Self::child_path(path, key);
             if !first {
                 result.push('\n');
+                result.push_str(&self.doc_lines(child.as_deref(), &indent));
                 result.push_str(&indent);
             }
             first = false;
@@ -217,11 +310,11 @@ impl YamlEmitter {
             match value {
                 Value::Object(inner) if !inner.is_empty() => {
                     result.push('\n');
-                    result.push_str(&self.emit_object(inner, depth + 1, false));
+                    result.push_str(&self.emit_object(inner, depth + 1, false, child.as_deref()));
                 }
                 Value::Array(inner) if !inner.is_empty() && !self.is_simple_array(inner) => {
                     result.push('\n');
-                    result.push_str(&self.emit_array(inner, depth + 1, false));
+                    result.push_str(&self.emit_array(inner, depth + 1, false, child.as_deref()));
                 }
                 Value::String(s) if s.contains('\n') => {
                     result.push(' ');
@@ -243,6 +336,7 @@ impl YamlEmitter {
         obj: &indexmap::IndexMap<String, Value>,
         depth: usize,
         inline: bool,
+        path: Option<&str>,
     ) -> String {
         if obj.is_empty() {
             return "{}".to_string();
@@ -264,34 +358,39 @@ impl YamlEmitter {
         }
 
         // Block format
+        let entries: Vec<_> = obj
+            .iter()
+            .map(|(key, value)| self.emit_entry(key, value, depth, path))
+            .collect();
+        entries.join("\n")
+    }
+
+    /// Emit one `key: value` entry of a block-style object, without a
+    /// trailing newline
+    fn emit_entry(&self, key: &str, value: &Value, depth: usize, path: Option<&str>) -> String {
         let indent = self.indent.repeat(depth);
-        let mut result = String::new();
+        let child = Self::child_path(path, key);
+        let mut result = self.doc_lines(child.as_deref(), &indent);
+        result.push_str(&indent);
+        result.push_str(&self.escape_key(key));
+        result.push(':');
 
-        for (key, value) in obj {
-            if !result.is_empty() {
+        match value {
+            Value::Object(inner) if !inner.is_empty() => {
                 result.push('\n');
+                result.push_str(&self.emit_object(inner, depth + 1, false, child.as_deref()));
             }
-            result.push_str(&indent);
-            result.push_str(&self.escape_key(key));
-            result.push(':');
-
-            match value {
-                Value::Object(inner) if !inner.is_empty() => {
-                    result.push('\n');
-                    result.push_str(&self.emit_object(inner, depth + 1, false));
-                }
-                Value::Array(inner) if !inner.is_empty() && !self.is_simple_array(inner) => {
-                    result.push('\n');
-                    result.push_str(&self.emit_array(inner, depth + 1, false));
-                }
-                Value::String(s) if s.contains('\n') => {
-                    result.push(' ');
-                    result.push_str(&self.emit_block_string(s, depth));
-                }
-                _ => {
-                    result.push(' ');
-                    result.push_str(&self.emit_value(value, depth + 1, true));
-                }
+            Value::Array(inner) if !inner.is_empty() && !self.is_simple_array(inner) => {
+                result.push('\n');
+                result.push_str(&self.emit_array(inner, depth + 1, false, child.as_deref()));
+            }
+            Value::String(s) if s.contains('\n') => {
+                result.push(' ');
+                result.push_str(&self.emit_block_string(s, depth));
+            }
+            _ => {
+                result.push(' ');
+                result.push_str(&self.emit_value(value, depth + 1, true));
             }
         }
 
@@ -299,7 +398,7 @@ impl YamlEmitter {
     }
 
     /// Escape a key for YAML
-    fn escape_key(&self, key: &str) -> String {
+    pub(crate) fn escape_key(&self, key: &str) -> String {
         // Keys need quoting for special chars, bool-like, number-like, or null-like values
         let needs_quotes = key.is_empty()
             || key.contains(':')
@@ -342,23 +441,64 @@ impl YamlEmitter {
     /// Check if a value is simple (scalar or small)
     fn is_simple_value(&self, value: &Value) -> bool {
         match value {
-            Value::Null | Value::Bool(_) | Value::Int(_) | Value::Float(_) => true,
+            Value::Null
+            | Value::Bool(_)
+            | Value::Int(_)
+            | Value::Float(_)
+            | Value::Duration(_)
+            | Value::Size(_) => true,
             Value::String(s) => s.len() <= 50,
             Value::Array(arr) => arr.is_empty(),
             Value::Object(obj) => obj.is_empty(),
+            Value::Function(_) => false,
         }
     }
 }
 
 impl Emitter for YamlEmitter {
     fn emit(&self, value: &Value) -> HoneResult<String> {
+        super::reject_functions(value)?;
         let result = match value {
-            Value::Object(obj) if !obj.is_empty() => self.emit_object(obj, 0, false),
-            Value::Array(arr) if !arr.is_empty() => self.emit_array(arr, 0, false),
+            Value::Object(obj) if !obj.is_empty() => self.emit_object(obj, 0, false, Some("")),
+            Value::Array(arr) if !arr.is_empty() => self.emit_array(arr, 0, false, None),
             _ => self.emit_value(value, 0, false),
         };
         Ok(result)
     }
+
+    /// Writes each top-level entry as it is emitted, and each element of a
+    /// top-level block array, so only one of them is held at a time
+    fn emit_to_writer<W: Write>(&self, value: &Value, writer: &mut W) -> HoneResult<()> {
+        super::reject_functions(value)?;
+        let mut result = Output::to_writer(writer);
+        match value {
+            Value::Object(obj) if !obj.is_empty() => {
+                for (i, (key, value)) in obj.iter().enumerate() {
+                    if i > 0 {
+                        result.push('\n');
+                    }
+                    match value {
+                        Value::Array(items)
+                            if !items.is_empty() && !self.is_simple_array(items) =>
+                        {
+                            let child = Self::child_path(Some(""), key);
+                            result.push_str(&self.doc_lines(child.as_deref(), ""));
+                            result.push_str(&self.escape_key(key));
+                            result.push_str(":\n");
+                            self.write_block_items(&mut result, items, 1, child.as_deref())?;
+                        }
+                        _ => result.push_str(&self.emit_entry(key, value, 0, Some(""))),
+                    }
+                    result.section()?;
+                }
+            }
+            Value::Array(items) if !items.is_empty() && !self.is_simple_array(items) => {
+                self.write_block_items(&mut result, items, 0, None)?;
+            }
+            _ => result.push_str(&self.emit(value)?),
+        }
+        result.finish().map(drop)
+    }
 }
 
 #[cfg(test)]
@@ -396,7 +536,7 @@ mod tests {
     #[test]
     fn test_emit_float() {
         let emitter = YamlEmitter::new();
-        assert_eq!(emitter.emit(&Value::Float(3.14)).unwrap(), "3.14");
+        assert_eq!(emitter.emit(&Value::Float(3.25)).unwrap(), "3.25");
     }
 
     #[test]
@@ -520,13 +660,18 @@ mod tests {
     fn test_emit_block_string_trailing_newline() {
         let emitter = YamlEmitter::new();
         // String ending with newline uses | (not |-)
-        let obj = obj(&[("content", Value::String("line1\nline2\n".into()))]);
-        let result = emitter.emit(&obj).unwrap();
+        let clipped = obj(&[("content", Value::String("line1\nline2\n".into()))]);
+        let result = emitter.emit(&clipped).unwrap();
         assert!(
-            result.contains("content: |\n"),
+            result.contains("content: |\n  line1\n  line2"),
             "Expected | chomp, got: {}",
             result
         );
+        assert!(!result.contains("line2\n\n"), "{}", result);
+
+        let kept = obj(&[("content", Value::String("line1\n\n".into()))]);
+        let result = emitter.emit(&kept).unwrap();
+        assert!(result.contains("content: |+\n  line1\n"), "{}", result);
     }
 
     #[test]
@@ -582,4 +727,44 @@ mod tests {
         assert!(result.contains("name:"));
         assert!(!result.contains("\"name\""));
     }
+
+    #[test]
+    fn test_emit_doc_comments() {
+        let docs: DocMap = [
+            ("name", "Service name\n\nShown in dashboards"),
+            ("server.port", "Listen port"),
+            ("sidecars", "Extra containers"),
+            ("sidecars.image", "Pinned image"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let sidecar = |image: &str| {
+            obj(&[
+                ("image", Value::String(image.into())),
+                ("cpu", Value::Int(1)),
+            ])
+        };
+        let value = obj(&[
+            ("name", Value::String("api".into())),
+            ("server", obj(&[("port", Value::Int(80))])),
+            (
+                "sidecars",
+                Value::Array(vec![sidecar("log"), sidecar("proxy")]),
+            ),
+        ]);
+        let emitter = YamlEmitter::new().with_doc_comments(docs);
+        assert_eq!(
+            emitter.emit(&value).unwrap(),
+            "# Service name\n#\n# Shown in dashboards\nname: api\nserver:\n  # Listen port\n  port: 80\n\
+             # Extra containers\nsidecars:\n  # Pinned image\n  - image: log\n    cpu: 1\n  - image: proxy\n    cpu: 1"
+        );
+        // Streaming keeps the comments, including the one above the first element
+        let mut streamed = Vec::new();
+        emitter.emit_to_writer(&value, &mut streamed).unwrap();
+        assert_eq!(
+            String::from_utf8(streamed).unwrap(),
+            emitter.emit(&value).unwrap()
+        );
+    }
 }
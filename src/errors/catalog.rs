@@ -0,0 +1,245 @@
+//! Message catalogs for diagnostics
+//!
+//! A catalog replaces the title and help text of errors, looked up by
+//! [`HoneError::message_key`] (the error code, e.g. `E0202`). Entries are
+//! templates: `{name}` is replaced by the error's field of that name, and
+//! `{help}` by the English help, so a translation can keep the details the
+//! compiler computed. Errors without an entry keep their English text, and so
+//! do labels.
+//!
+//! `en` (no entries) and `ja` are built in. Any other catalog is a TOML file
+//! with one table per key:
+//!
+//! ```toml
+//! [E0202]
+//! title = "Typfehler"
+//! help = "{help}"
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use miette::{Diagnostic, LabeledSpan, Severity, SourceCode};
+use serde::Deserialize;
+
+use super::{HoneError, HoneResult};
+
+/// Built-in catalogs by language tag
+const BUILTIN: &[(&str, &str)] = &[("en", ""), ("ja", include_str!("catalogs/ja.toml"))];
+
+/// Replacement text for one error
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Message {
+    pub title: Option<String>,
+    pub help: Option<String>,
+}
+
+/// Messages by key
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Catalog {
+    messages: HashMap<String, Message>,
+}
+
+impl Catalog {
+    /// Names of the built-in catalogs
+    pub fn builtin_names() -> Vec<&'static str> {
+        BUILTIN.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// A built-in catalog
+    pub fn builtin(lang: &str) -> Option<Self> {
+        let (_, source) = BUILTIN.iter().find(|(name, _)| *name == lang)?;
+        Some(Self::parse(source).expect("built-in catalogs are valid TOML"))
+    }
+
+    /// Parse a TOML catalog
+    pub fn parse(source: &str) -> HoneResult<Self> {
+        let messages = toml::from_str(source).map_err(|e| {
+            HoneError::io_error(format!("invalid message catalog: {}", e.message()))
+        })?;
+        Ok(Self { messages })
+    }
+
+    /// A built-in catalog by language tag (`ja`), or a TOML catalog file
+    pub fn load(lang: &str) -> HoneResult<Self> {
+        if let Some(catalog) = Self::builtin(lang) {
+            return Ok(catalog);
+        }
+        let path = Path::new(lang);
+        if !path.is_file() {
+            return Err(HoneError::io_error(format!(
+                "unknown language '{}'. Use one of: {}, or the path of a TOML message catalog",
+                lang,
+                Self::builtin_names().join(", ")
+            )));
+        }
+        let source = std::fs::read_to_string(path).map_err(|e| {
+            HoneError::io_error(format!("failed to read {}: {}", path.display(), e))
+        })?;
+        Self::parse(&source)
+    }
+
+    /// Look up the message for a key
+    pub fn get(&self, key: &str) -> Option<&Message> {
+        self.messages.get(key)
+    }
+
+    /// Wrap `error` so it renders with this catalog's title and help
+    pub fn localize(&self, mut error: HoneError) -> Localized {
+        let related = match &mut error {
+            HoneError::SchemaValidationErrors { errors, .. } => std::mem::take(errors)
+                .into_iter()
+                .map(|e| self.localize(e))
+                .collect(),
+            _ => Vec::new(),
+        };
+        let message = self.get(&error.message_key());
+        let args = error.message_args();
+        let fill = |template: &String| fill(template, &args);
+        Localized {
+            title: message
+                .and_then(|m| m.title.as_ref())
+                .map(fill)
+                .unwrap_or_else(|| error.to_string()),
+            help: message.and_then(|m| m.help.as_ref()).map(fill),
+            related,
+            error,
+        }
+    }
+}
+
+/// Replace `{name}` placeholders with `args`
+fn fill(template: &str, args: &[(&str, String)]) -> String {
+    args.iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+/// An error rendered with a catalog's messages. Code, severity, source and
+/// labels are the wrapped error's.
+#[derive(Debug)]
+pub struct Localized {
+    error: HoneError,
+    title: String,
+    help: Option<String>,
+    related: Vec<Localized>,
+}
+
+impl Localized {
+    /// The wrapped error
+    pub fn error(&self) -> &HoneError {
+        &self.error
+    }
+
+    pub fn into_error(self) -> HoneError {
+        self.error
+    }
+}
+
+impl fmt::Display for Localized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.title)
+    }
+}
+
+impl std::error::Error for Localized {}
+
+impl Diagnostic for Localized {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.error.code()
+    }
+
+    fn severity(&self) -> Option<Severity> {
+        self.error.severity()
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        match &self.help {
+            Some(help) => Some(Box::new(help)),
+            None => self.error.help(),
+        }
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.error.source_code()
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.error.labels()
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        if self.related.is_empty() {
+            return None;
+        }
+        Some(Box::new(self.related.iter().map(|e| e as &dyn Diagnostic)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_mismatch() -> HoneError {
+        HoneError::TypeMismatch {
+            src: "port: \"80\"".to_string(),
+            span: (6, 4).into(),
+            expected: "int".to_string(),
+            found: "string".to_string(),
+            help: "remove the quotes".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_builtin_catalogs_parse() {
+        for name in Catalog::builtin_names() {
+            assert!(Catalog::builtin(name).is_some());
+        }
+        assert!(Catalog::builtin("ja").unwrap().get("E0202").is_some());
+        assert!(Catalog::load("xx").is_err());
+    }
+
+    #[test]
+    fn test_localize_fills_placeholders() {
+        let catalog = Catalog::parse(
+            "[E0202]\ntitle = \"Typfehler: {expected}\"\nhelp = \"Hinweis: {help}\"\n",
+        )
+        .unwrap();
+        let localized = catalog.localize(type_mismatch());
+        assert_eq!(localized.to_string(), "Typfehler: int");
+        assert_eq!(
+            localized.help().unwrap().to_string(),
+            "Hinweis: remove the quotes"
+        );
+        assert_eq!(localized.code().unwrap().to_string(), "E0202");
+    }
+
+    #[test]
+    fn test_localize_without_entry_keeps_english() {
+        let localized = Catalog::default().localize(type_mismatch());
+        assert_eq!(localized.to_string(), "type mismatch");
+        assert_eq!(localized.help().unwrap().to_string(), "remove the quotes");
+    }
+
+    #[test]
+    fn test_localize_related_errors() {
+        let error = HoneError::SchemaValidationErrors {
+            src: String::new(),
+            span: (0, 0).into(),
+            count: 2,
+            s: "s".to_string(),
+            errors: vec![type_mismatch(), type_mismatch()],
+        };
+        let localized = Catalog::builtin("ja").unwrap().localize(error);
+        assert!(localized.to_string().contains('2'));
+        let related: Vec<String> = localized
+            .related()
+            .unwrap()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(related.len(), 2);
+        assert_ne!(related[0], "type mismatch");
+    }
+}
@@ -2,20 +2,190 @@
 //!
 //! All user-facing errors are variants of [`HoneError`], rendered via `miette` diagnostics.
 
+pub mod catalog;
+
 use std::path::PathBuf;
 
-use miette::{Diagnostic, SourceSpan};
+use miette::{Diagnostic, LabeledSpan, Severity, SourceCode, SourceSpan};
 use thiserror::Error;
 
 use crate::lexer::token::SourceLocation;
 
+pub use catalog::{Catalog, Localized};
+
 /// Warning from compilation (non-fatal)
+///
+/// Warnings render through miette like errors. The `code` is stable and is
+/// what `--deny warn:<code>` matches.
 #[derive(Debug, Clone)]
 pub struct Warning {
+    /// Stable kebab-case code, e.g. `unused-let`
+    pub code: String,
+    pub severity: Severity,
     pub message: String,
     pub file: Option<PathBuf>,
     pub line: usize,
     pub column: usize,
+    /// Source text of `file`, for rendering the span
+    pub src: Option<String>,
+    pub span: Option<SourceSpan>,
+    pub help: Option<String>,
+    pub fix: Option<Fix>,
+}
+
+/// A suggested edit that resolves a warning
+#[derive(Debug, Clone)]
+pub struct Fix {
+    pub message: String,
+    pub span: SourceSpan,
+    pub replacement: String,
+}
+
+impl Warning {
+    pub fn new(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            code: code.into(),
+            severity: Severity::Warning,
+            message: message.into(),
+            file: None,
+            line: 0,
+            column: 0,
+            src: None,
+            span: None,
+            help: None,
+            fix: None,
+        }
+    }
+
+    pub fn in_file(mut self, file: impl Into<PathBuf>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Point the warning at a location in `src`
+    pub fn at(mut self, src: impl Into<String>, location: &SourceLocation) -> Self {
+        self.src = Some(src.into());
+        self.span = Some((location.offset, location.length).into());
+        self.line = location.line;
+        self.column = location.column;
+        self
+    }
+
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn with_fix(mut self, fix: Fix) -> Self {
+        self.fix = Some(fix);
+        self
+    }
+
+    /// Machine-readable form for `--error-format json`
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = diagnostic_json(self);
+        if let Some(ref file) = self.file {
+            json["file"] = serde_json::json!(file.display().to_string());
+        }
+        if self.line > 0 {
+            json["line"] = serde_json::json!(self.line);
+            json["column"] = serde_json::json!(self.column);
+        }
+        if let Some(ref fix) = self.fix {
+            json["fix"] = serde_json::json!({
+                "message": fix.message,
+                "offset": fix.span.offset(),
+                "length": fix.span.len(),
+                "replacement": fix.replacement,
+            });
+        }
+        json
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Warning {}
+
+impl Diagnostic for Warning {
+    fn code<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        Some(Box::new(&self.code))
+    }
+
+    fn severity(&self) -> Option<Severity> {
+        Some(self.severity)
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        self.help
+            .as_ref()
+            .or(self.fix.as_ref().map(|fix| &fix.message))
+            .map(|help| Box::new(help) as Box<dyn std::fmt::Display>)
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.src.as_ref().map(|src| src as &dyn SourceCode)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let span = self.span?;
+        self.src.as_ref()?;
+        Some(Box::new(std::iter::once(LabeledSpan::new_with_span(
+            None, span,
+        ))))
+    }
+}
+
+/// Machine-readable form of any diagnostic (error or warning): severity,
+/// code, message, help, and labeled spans with 1-indexed line/column
+pub fn diagnostic_json(diagnostic: &dyn Diagnostic) -> serde_json::Value {
+    let severity = match diagnostic.severity().unwrap_or(Severity::Error) {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Advice => "advice",
+    };
+    let mut json = serde_json::json!({
+        "severity": severity,
+        "code": diagnostic.code().map(|c| c.to_string()),
+        "message": diagnostic.to_string(),
+    });
+    if let Some(help) = diagnostic.help() {
+        json["help"] = serde_json::json!(help.to_string());
+    }
+
+    let spans: Vec<serde_json::Value> = diagnostic
+        .labels()
+        .into_iter()
+        .flatten()
+        .map(|label| {
+            let mut span = serde_json::json!({
+                "offset": label.offset(),
+                "length": label.len(),
+                "label": label.label(),
+            });
+            if let Some(contents) = diagnostic
+                .source_code()
+                .and_then(|src| src.read_span(label.inner(), 0, 0).ok())
+            {
+                span["line"] = serde_json::json!(contents.line() + 1);
+                span["column"] = serde_json::json!(contents.column() + 1);
+            }
+            span
+        })
+        .collect();
+    if !spans.is_empty() {
+        json["spans"] = serde_json::json!(spans);
+    }
+    json
 }
 
 /// Calculate Levenshtein distance between two strings
@@ -108,6 +278,8 @@ pub enum ErrorCode {
     E0101, // File not found
     E0102, // Circular import
     E0103, // Import resolution failed (interpolation in path)
+    E0104, // Cycle through a `from` base
+    E0105, // Included file is not a fragment
 
     // Type Errors (E02xx)
     E0201, // Value out of range
@@ -124,6 +296,7 @@ pub enum ErrorCode {
     E0304, // from in preamble of multi-document
     E0305, // No matching document in base
     E0306, // Cannot inherit from multi-document base
+    E0307, // Computed key given twice (--key-collision error)
 
     // Evaluation Errors (E04xx)
     E0401, // Missing required argument
@@ -141,6 +314,9 @@ pub enum ErrorCode {
     // Control Flow Errors (E07xx)
     E0701, // for not allowed at top level
     E0702, // Assertion failed
+    E0703, // use not allowed here
+    E0704, // include not allowed here
+    E0705, // No match arm for the value
 
     // Hermeticity Errors (E08xx)
     E0801, // env/file requires --allow-env
@@ -158,6 +334,8 @@ impl std::fmt::Display for ErrorCode {
             ErrorCode::E0101 => write!(f, "E0101"),
             ErrorCode::E0102 => write!(f, "E0102"),
             ErrorCode::E0103 => write!(f, "E0103"),
+            ErrorCode::E0104 => write!(f, "E0104"),
+            ErrorCode::E0105 => write!(f, "E0105"),
             ErrorCode::E0201 => write!(f, "E0201"),
             ErrorCode::E0202 => write!(f, "E0202"),
             ErrorCode::E0203 => write!(f, "E0203"),
@@ -170,6 +348,7 @@ impl std::fmt::Display for ErrorCode {
             ErrorCode::E0304 => write!(f, "E0304"),
             ErrorCode::E0305 => write!(f, "E0305"),
             ErrorCode::E0306 => write!(f, "E0306"),
+            ErrorCode::E0307 => write!(f, "E0307"),
             ErrorCode::E0401 => write!(f, "E0401"),
             ErrorCode::E0402 => write!(f, "E0402"),
             ErrorCode::E0403 => write!(f, "E0403"),
@@ -179,6 +358,9 @@ impl std::fmt::Display for ErrorCode {
             ErrorCode::E0603 => write!(f, "E0603"),
             ErrorCode::E0701 => write!(f, "E0701"),
             ErrorCode::E0702 => write!(f, "E0702"),
+            ErrorCode::E0703 => write!(f, "E0703"),
+            ErrorCode::E0704 => write!(f, "E0704"),
+            ErrorCode::E0705 => write!(f, "E0705"),
             ErrorCode::E0801 => write!(f, "E0801"),
             ErrorCode::E0802 => write!(f, "E0802"),
         }
@@ -272,6 +454,31 @@ pub enum HoneError {
         chain: String,
     },
 
+    #[error("circular from/import chain")]
+    #[diagnostic(code(E0104), help("{chain}\n{help}"))]
+    CircularFrom {
+        #[source_code]
+        src: String,
+        #[label("this closes the cycle")]
+        span: SourceSpan,
+        chain: String,
+        help: String,
+    },
+
+    #[error("'{path}' cannot be included")]
+    #[diagnostic(
+        code(E0105),
+        help("an included file holds only body items and `let`s, but it has {found}; import the file instead, or move that declaration into a file the includer imports")
+    )]
+    InvalidFragment {
+        #[source_code]
+        src: String,
+        #[label("included here")]
+        span: SourceSpan,
+        path: String,
+        found: String,
+    },
+
     #[error("value out of range")]
     #[diagnostic(code(E0201), help("{help}"))]
     ValueOutOfRange {
@@ -345,6 +552,33 @@ pub enum HoneError {
         first_span: SourceSpan,
     },
 
+    #[error("final key '{key}' is set again")]
+    #[diagnostic(code(E0303), help("{help}"))]
+    FinalKeyOverridden {
+        #[source_code]
+        src: String,
+        #[label("sets final key '{key}'")]
+        span: SourceSpan,
+        /// The `final` declaration, when it is in the same file
+        #[label("declared final here")]
+        final_span: Option<SourceSpan>,
+        key: String,
+        help: String,
+    },
+
+    #[error("key '{key}' is given twice")]
+    #[diagnostic(code(E0307), help("{help}"))]
+    KeyCollision {
+        #[source_code]
+        src: String,
+        #[label("gives '{key}' again")]
+        span: SourceSpan,
+        #[label("first gave it here")]
+        first_span: Option<SourceSpan>,
+        key: String,
+        help: String,
+    },
+
     #[error("'from' not allowed in preamble of multi-document file")]
     #[diagnostic(
         code(E0304),
@@ -380,6 +614,41 @@ pub enum HoneError {
         span: SourceSpan,
     },
 
+    #[error("'use' not allowed here")]
+    #[diagnostic(
+        code(E0703),
+        help("'use' is only valid in the preamble or inside a top-level 'when' or variant case")
+    )]
+    UseNotAtTopLevel {
+        #[source_code]
+        src: String,
+        #[label("'use' inside a nested block")]
+        span: SourceSpan,
+    },
+
+    #[error("'include' not allowed here")]
+    #[diagnostic(
+        code(E0704),
+        help("'include' is only valid in a file's body, a block, a 'when' branch or a variant case, not inside an expression, and only in a file read from disk, whose directory its path is relative to")
+    )]
+    IncludeNotAllowed {
+        #[source_code]
+        src: String,
+        #[label("'include' cannot be resolved here")]
+        span: SourceSpan,
+    },
+
+    #[error("no match arm for {value}")]
+    #[diagnostic(code(E0705), help("{help}"))]
+    NoMatchArm {
+        #[source_code]
+        src: String,
+        #[label("no arm matches this value")]
+        span: SourceSpan,
+        value: String,
+        help: String,
+    },
+
     #[error("assertion failed: {message}")]
     #[diagnostic(code(E0702), help("{help}"))]
     AssertionFailed {
@@ -462,6 +731,10 @@ pub enum HoneError {
 
     #[error("{message}")]
     CompilationError { message: String },
+
+    /// Work stopped by a [`crate::cancel::CancelToken`]
+    #[error("cancelled")]
+    Cancelled,
 }
 
 impl HoneError {
@@ -557,6 +830,146 @@ impl HoneError {
         }
     }
 
+    /// Rewrite the text of the fields that can show evaluated values, for
+    /// hiding `@sensitive` values. The source snippet is left as it is.
+    pub fn redact(self, mask: &dyn Fn(&str) -> String) -> Self {
+        match self {
+            HoneError::UnexpectedToken {
+                src,
+                span,
+                expected,
+                found,
+                help,
+            } => HoneError::UnexpectedToken {
+                src,
+                span,
+                expected,
+                found: mask(&found),
+                help: mask(&help),
+            },
+            HoneError::ValueOutOfRange {
+                src,
+                span,
+                expected,
+                value,
+                help,
+            } => HoneError::ValueOutOfRange {
+                src,
+                span,
+                expected,
+                value: mask(&value),
+                help: mask(&help),
+            },
+            HoneError::TypeMismatch {
+                src,
+                span,
+                expected,
+                found,
+                help,
+            } => HoneError::TypeMismatch {
+                src,
+                span,
+                expected,
+                found: mask(&found),
+                help: mask(&help),
+            },
+            HoneError::PatternMismatch {
+                src,
+                span,
+                pattern,
+                value,
+                help,
+            } => HoneError::PatternMismatch {
+                src,
+                span,
+                pattern,
+                value: mask(&value),
+                help: mask(&help),
+            },
+            HoneError::KeyCollision {
+                src,
+                span,
+                first_span,
+                key,
+                help,
+            } => HoneError::KeyCollision {
+                src,
+                span,
+                first_span,
+                key: mask(&key),
+                help: mask(&help),
+            },
+            HoneError::AssertionFailed {
+                src,
+                span,
+                condition,
+                message,
+                help,
+            } => HoneError::AssertionFailed {
+                src,
+                span,
+                condition,
+                message: mask(&message),
+                help: mask(&help),
+            },
+            HoneError::NoMatchArm {
+                src,
+                span,
+                value,
+                help,
+            } => HoneError::NoMatchArm {
+                src,
+                span,
+                value: mask(&value),
+                help: mask(&help),
+            },
+            HoneError::ArithmeticOverflow {
+                src,
+                span,
+                operation,
+                help,
+            } => HoneError::ArithmeticOverflow {
+                src,
+                span,
+                operation: mask(&operation),
+                help,
+            },
+            HoneError::SchemaValidationErrors {
+                src,
+                span,
+                count,
+                s,
+                errors,
+            } => HoneError::SchemaValidationErrors {
+                src,
+                span,
+                count,
+                s,
+                errors: errors.into_iter().map(|e| e.redact(mask)).collect(),
+            },
+            HoneError::CompilationError { message } => HoneError::CompilationError {
+                message: mask(&message),
+            },
+            other => other,
+        }
+    }
+
+    /// Whether `try()` falls back on this error: a value that does not fit
+    /// the expression (a bad index, a wrong type, a failed parse, overflow,
+    /// a value no `match` arm covers), rather than a mistake in the source,
+    /// a hermeticity check or a limit
+    pub fn is_recoverable(&self) -> bool {
+        matches!(
+            self,
+            HoneError::ValueOutOfRange { .. }
+                | HoneError::TypeMismatch { .. }
+                | HoneError::PatternMismatch { .. }
+                | HoneError::NoMatchArm { .. }
+                | HoneError::ArithmeticOverflow { .. }
+                | HoneError::DivisionByZero { .. }
+        )
+    }
+
     /// Get the span (start, end) for this error, if it has one
     pub fn span(&self) -> Option<Span> {
         match self {
@@ -568,15 +981,22 @@ impl HoneError {
             HoneError::UnexpectedCharacter { span, .. } => Some(Span::from(*span)),
             HoneError::ImportNotFound { span, .. } => Some(Span::from(*span)),
             HoneError::CircularImport { span, .. } => Some(Span::from(*span)),
+            HoneError::InvalidFragment { span, .. } => Some(Span::from(*span)),
+            HoneError::IncludeNotAllowed { span, .. } => Some(Span::from(*span)),
+            HoneError::NoMatchArm { span, .. } => Some(Span::from(*span)),
+            HoneError::CircularFrom { span, .. } => Some(Span::from(*span)),
             HoneError::ValueOutOfRange { span, .. } => Some(Span::from(*span)),
             HoneError::TypeMismatch { span, .. } => Some(Span::from(*span)),
             HoneError::MissingField { span, .. } => Some(Span::from(*span)),
             HoneError::UnknownField { span, .. } => Some(Span::from(*span)),
             HoneError::PatternMismatch { span, .. } => Some(Span::from(*span)),
             HoneError::MultipleFrom { span, .. } => Some(Span::from(*span)),
+            HoneError::FinalKeyOverridden { span, .. } => Some(Span::from(*span)),
+            HoneError::KeyCollision { span, .. } => Some(Span::from(*span)),
             HoneError::FromInPreamble { span, .. } => Some(Span::from(*span)),
             HoneError::CircularDependency { span, .. } => Some(Span::from(*span)),
             HoneError::ForAtTopLevel { span, .. } => Some(Span::from(*span)),
+            HoneError::UseNotAtTopLevel { span, .. } => Some(Span::from(*span)),
             HoneError::AssertionFailed { span, .. } => Some(Span::from(*span)),
             HoneError::ArithmeticOverflow { span, .. } => Some(Span::from(*span)),
             HoneError::DivisionByZero { span, .. } => Some(Span::from(*span)),
@@ -586,9 +1006,67 @@ impl HoneError {
             HoneError::SchemaValidationErrors { span, .. } => Some(Span::from(*span)),
             HoneError::IoError { .. } => None,
             HoneError::CompilationError { .. } => None,
+            HoneError::Cancelled => None,
         }
     }
 
+    /// Key of this error in a message [`Catalog`]: its code, or a suffixed
+    /// code for the errors that share one with another
+    pub fn message_key(&self) -> String {
+        match self {
+            HoneError::UnexpectedCharacter { .. } => "E0001.char".to_string(),
+            HoneError::DivisionByZero { .. } => "E0402.div".to_string(),
+            HoneError::SchemaValidationErrors { .. } => "schema-validation".to_string(),
+            HoneError::IoError { .. } => "io".to_string(),
+            HoneError::CompilationError { .. } => "compilation".to_string(),
+            HoneError::Cancelled => "cancelled".to_string(),
+            _ => self.code().map(|code| code.to_string()).unwrap_or_default(),
+        }
+    }
+
+    /// Values a catalog message can refer to as `{name}`: the English help
+    /// as `{help}`, and the fields the English title and help are built from
+    pub fn message_args(&self) -> Vec<(&'static str, String)> {
+        let mut args = Vec::new();
+        if let Some(help) = self.help() {
+            args.push(("help", help.to_string()));
+        }
+        match self {
+            HoneError::UndefinedVariable { name, .. } => args.push(("name", name.clone())),
+            HoneError::ReservedWordAsKey { keyword, .. } => args.push(("keyword", keyword.clone())),
+            HoneError::TypeMismatch {
+                expected, found, ..
+            } => {
+                args.push(("expected", expected.clone()));
+                args.push(("found", found.clone()));
+            }
+            HoneError::MissingField { field, schema, .. }
+            | HoneError::UnknownField { field, schema, .. } => {
+                args.push(("field", field.clone()));
+                args.push(("schema", schema.clone()));
+            }
+            HoneError::FinalKeyOverridden { key, .. } | HoneError::KeyCollision { key, .. } => {
+                args.push(("key", key.clone()))
+            }
+            HoneError::AssertionFailed { message, .. }
+            | HoneError::IoError { message }
+            | HoneError::CompilationError { message } => args.push(("message", message.clone())),
+            HoneError::EnvNotAllowed { func_name, .. } => {
+                args.push(("func_name", func_name.clone()))
+            }
+            HoneError::InvalidFragment { path, found, .. } => {
+                args.push(("path", path.clone()));
+                args.push(("found", found.clone()));
+            }
+            HoneError::NoMatchArm { value, .. } => args.push(("value", value.clone())),
+            HoneError::SchemaValidationErrors { count, .. } => {
+                args.push(("count", count.to_string()))
+            }
+            _ => {}
+        }
+        args
+    }
+
     /// Get a simple error message (without source context)
     pub fn message(&self) -> String {
         match self {
@@ -616,6 +1094,12 @@ impl HoneError {
             HoneError::CircularImport { chain, .. } => {
                 format!("circular import detected: {}", chain)
             }
+            HoneError::CircularFrom { chain, .. } => {
+                format!("circular from/import chain: {}", chain)
+            }
+            HoneError::InvalidFragment { path, found, .. } => {
+                format!("'{}' cannot be included: it has {}", path, found)
+            }
             HoneError::ValueOutOfRange {
                 expected, value, ..
             } => {
@@ -636,6 +1120,10 @@ impl HoneError {
                 format!("value \"{}\" does not match pattern /{}/", value, pattern)
             }
             HoneError::MultipleFrom { .. } => "multiple 'from' declarations".to_string(),
+            HoneError::FinalKeyOverridden { key, .. } => {
+                format!("final key '{}' is set again", key)
+            }
+            HoneError::KeyCollision { key, .. } => format!("key '{}' is given twice", key),
             HoneError::FromInPreamble { .. } => {
                 "'from' not allowed in preamble of multi-document file".to_string()
             }
@@ -643,6 +1131,9 @@ impl HoneError {
                 format!("circular dependency: {}", cycle)
             }
             HoneError::ForAtTopLevel { .. } => "'for' not allowed at top level".to_string(),
+            HoneError::UseNotAtTopLevel { .. } => "'use' not allowed here".to_string(),
+            HoneError::IncludeNotAllowed { .. } => "'include' not allowed here".to_string(),
+            HoneError::NoMatchArm { value, .. } => format!("no match arm for {}", value),
             HoneError::AssertionFailed { message, .. } => {
                 format!("assertion failed: {}", message)
             }
@@ -670,6 +1161,7 @@ impl HoneError {
             }
             HoneError::IoError { message } => format!("I/O error: {}", message),
             HoneError::CompilationError { message } => message.clone(),
+            HoneError::Cancelled => "cancelled".to_string(),
         }
     }
 }
@@ -697,6 +1189,36 @@ pub type HoneResult<T> = Result<T, HoneError>;
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_warning_diagnostic_and_json() {
+        let loc = SourceLocation {
+            file: None,
+            line: 3,
+            column: 1,
+            offset: 6,
+            length: 9,
+        };
+        let warning = Warning::new("unused-let", "unused variable 'x'")
+            .in_file("app.hone")
+            .at("a: 1\n\nlet x = 1\n", &loc)
+            .with_help("remove it");
+
+        assert_eq!(warning.code().unwrap().to_string(), "unused-let");
+        assert_eq!(warning.severity(), Some(Severity::Warning));
+        assert_eq!(warning.labels().unwrap().count(), 1);
+
+        let json = warning.to_json();
+        assert_eq!(json["severity"], "warning");
+        assert_eq!(json["code"], "unused-let");
+        assert_eq!(json["help"], "remove it");
+        assert_eq!(json["file"], "app.hone");
+        assert_eq!(json["spans"][0]["line"], 3);
+        assert_eq!(json["spans"][0]["column"], 1);
+
+        let error = HoneError::compilation_error("boom");
+        assert_eq!(diagnostic_json(&error)["severity"], "error");
+    }
+
     #[test]
     fn test_levenshtein_distance() {
         assert_eq!(levenshtein_distance("", ""), 0);
@@ -0,0 +1,200 @@
+//! Budget helpers for policy conditions
+//!
+//! `output_size_bytes()` and `count(path_glob)` measure the compiled output, so
+//! they are only callable inside a `policy` condition. The size is that of the
+//! output emitted in the format being compiled to; nothing is kept in memory
+//! to measure it.
+
+use std::io::Write;
+
+use crate::emitter::{emit_to_writer, OutputFormat};
+use crate::errors::HoneResult;
+use crate::lexer::token::SourceLocation;
+
+use super::builtins::{check_arity, type_error};
+use super::value::Value;
+
+/// Whether `name` is a budget helper
+pub fn is_budget_helper(name: &str) -> bool {
+    matches!(name, "output_size_bytes" | "count")
+}
+
+/// Call a budget helper against the compiled `output`
+pub fn call_budget_helper(
+    name: &str,
+    args: Vec<Value>,
+    output: &Value,
+    format: OutputFormat,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    match name {
+        "output_size_bytes" => {
+            check_arity(name, &args, 0, location, source)?;
+            Ok(Value::Int(output_size_bytes(output, format)? as i64))
+        }
+        _ => {
+            check_arity(name, &args, 1, location, source)?;
+            match &args[0] {
+                Value::String(glob) if !glob.is_empty() => {
+                    let mut segments: Vec<&str> = glob.split('.').collect();
+                    // `**.**` matches nothing `**` doesn't, but would count twice
+                    segments.dedup_by(|a, b| *a == "**" && *b == "**");
+                    Ok(Value::Int(count_matches(output, &segments) as i64))
+                }
+                Value::String(_) => Err(type_error(
+                    name,
+                    "a non-empty path glob",
+                    "empty string",
+                    location,
+                    source,
+                )),
+                other => Err(type_error(
+                    name,
+                    "string",
+                    other.type_name(),
+                    location,
+                    source,
+                )),
+            }
+        }
+    }
+}
+
+/// Size in bytes of `output` emitted as `format`
+pub fn output_size_bytes(output: &Value, format: OutputFormat) -> HoneResult<usize> {
+    let mut counter = ByteCount(0);
+    emit_to_writer(output, format, &mut counter)?;
+    Ok(counter.0)
+}
+
+/// Number of values at paths matching `segments`: `*` matches any one key or
+/// array index, `**` any number of levels (including none)
+fn count_matches(value: &Value, segments: &[&str]) -> usize {
+    let Some((segment, rest)) = segments.split_first() else {
+        return 1;
+    };
+    let children = || -> Box<dyn Iterator<Item = &Value> + '_> {
+        match value {
+            Value::Object(obj) => Box::new(obj.values()),
+            Value::Array(arr) => Box::new(arr.iter()),
+            _ => Box::new(std::iter::empty()),
+        }
+    };
+    match *segment {
+        "**" => {
+            count_matches(value, rest)
+                + children()
+                    .map(|child| count_matches(child, segments))
+                    .sum::<usize>()
+        }
+        "*" => children().map(|child| count_matches(child, rest)).sum(),
+        key => {
+            let child = match value {
+                Value::Object(obj) => obj.get(key),
+                Value::Array(arr) => key.parse::<usize>().ok().and_then(|i| arr.get(i)),
+                _ => None,
+            };
+            child.map_or(0, |child| count_matches(child, rest))
+        }
+    }
+}
+
+/// Writer that only counts what is written to it
+struct ByteCount(usize);
+
+impl Write for ByteCount {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emitter::emit;
+    use indexmap::IndexMap;
+
+    fn obj(pairs: &[(&str, Value)]) -> Value {
+        let mut map = IndexMap::new();
+        for (k, v) in pairs {
+            map.insert(k.to_string(), v.clone());
+        }
+        Value::Object(map)
+    }
+
+    fn loc() -> SourceLocation {
+        SourceLocation::new(None, 1, 1, 0, 1)
+    }
+
+    fn container(env: usize) -> Value {
+        let env = (0..env)
+            .map(|i| obj(&[("name", Value::String(format!("VAR_{}", i)))]))
+            .collect();
+        obj(&[("env", Value::Array(env))])
+    }
+
+    fn count(value: &Value, glob: &str) -> i64 {
+        let result = call_budget_helper(
+            "count",
+            vec![Value::String(glob.into())],
+            value,
+            OutputFormat::Json,
+            &loc(),
+            "",
+        )
+        .unwrap();
+        match result {
+            Value::Int(n) => n,
+            other => panic!("expected int, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_count_globs() {
+        let value = obj(&[("containers", Value::Array(vec![container(3), container(2)]))]);
+        assert_eq!(count(&value, "containers"), 1);
+        assert_eq!(count(&value, "containers.*"), 2);
+        assert_eq!(count(&value, "containers.*.env.*"), 5);
+        assert_eq!(count(&value, "containers.1.env.*"), 2);
+        assert_eq!(count(&value, "**.name"), 5);
+        assert_eq!(count(&value, "**.**.name"), 5);
+        assert_eq!(count(&value, "containers.*.missing"), 0);
+    }
+
+    #[test]
+    fn test_output_size_matches_emitted_length() {
+        let value = obj(&[
+            ("name", Value::String("api".into())),
+            ("replicas", Value::Int(3)),
+        ]);
+        for format in [
+            OutputFormat::JsonPretty,
+            OutputFormat::Yaml,
+            OutputFormat::Toml,
+        ] {
+            assert_eq!(
+                output_size_bytes(&value, format).unwrap(),
+                emit(&value, format).unwrap().len()
+            );
+        }
+    }
+
+    #[test]
+    fn test_count_rejects_empty_glob() {
+        let result = call_budget_helper(
+            "count",
+            vec![Value::String(String::new())],
+            &Value::Null,
+            OutputFormat::Json,
+            &loc(),
+            "",
+        );
+        assert!(result.is_err());
+    }
+}
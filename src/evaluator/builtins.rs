@@ -7,9 +7,15 @@ use base64::Engine;
 use indexmap::IndexMap;
 use sha2::{Digest, Sha256};
 
+use crate::emitter::{Emitter, TomlEmitter};
 use crate::errors::{HoneError, HoneResult};
 use crate::lexer::token::SourceLocation;
+use crate::lexer::{duration, size};
+use crate::resolver::data::parse_toml;
 
+use super::datetime;
+use super::function::Function;
+use super::merge::{merge_values, MergeStrategy};
 use super::value::Value;
 
 /// Evaluate a built-in function call
@@ -26,22 +32,18 @@ pub fn call_builtin(
         "contains" => builtin_contains(args, location, source),
         "concat" => builtin_concat(args, location, source),
         "merge" => builtin_merge(args, location, source),
+        "deep_merge" => builtin_deep_merge(args, location, source),
         "range" => builtin_range(args, location, source),
         "flatten" => builtin_flatten(args, location, source),
-        "map" | "filter" | "reduce" => Err(HoneError::undefined_variable(
-            source.to_string(),
-            location,
-            name,
-            format!(
-                "'{}' is not a built-in function. Use a for comprehension instead: for x in items {{ x * 2 }}",
-                name
-            ),
-        )),
+        "flatten_keys" => builtin_flatten_keys(args, location, source),
+        "unflatten_keys" => builtin_unflatten_keys(args, location, source),
         "to_str" => builtin_to_str(args, location, source),
         "to_int" => builtin_to_int(args, location, source),
         "to_float" => builtin_to_float(args, location, source),
         "to_bool" => builtin_to_bool(args, location, source),
         "default" => builtin_default(args, location, source),
+        "dig" => builtin_dig(args, location, source),
+        "set_path" => builtin_set_path(args, location, source),
         "upper" => builtin_upper(args, location, source),
         "lower" => builtin_lower(args, location, source),
         "trim" => builtin_trim(args, location, source),
@@ -52,6 +54,8 @@ pub fn call_builtin(
         "base64_decode" => builtin_base64_decode(args, location, source),
         "to_json" => builtin_to_json(args, location, source),
         "from_json" => builtin_from_json(args, location, source),
+        "to_toml" => builtin_to_toml(args, location, source),
+        "from_toml" => builtin_from_toml(args, location, source),
         "env" => builtin_env(args, location, source),
         "file" => builtin_file(args, location, source),
         // P0: core missing builtins
@@ -61,10 +65,13 @@ pub fn call_builtin(
         "min" => builtin_min(args, location, source),
         "max" => builtin_max(args, location, source),
         "abs" => builtin_abs(args, location, source),
+        "decimal" => builtin_decimal(args, location, source),
         // P1: important utilities
         "unique" => builtin_unique(args, location, source),
         "sha256" => builtin_sha256(args, location, source),
         "type_of" => builtin_type_of(args, location, source),
+        "assert_type" => builtin_assert_type(args, location, source),
+        "size_of" => builtin_size_of(args, location, source),
         "substring" => builtin_substring(args, location, source),
         // P2: object/array manipulation
         "entries" => builtin_entries(args, location, source),
@@ -72,6 +79,25 @@ pub fn call_builtin(
         "clamp" => builtin_clamp(args, location, source),
         "reverse" => builtin_reverse(args, location, source),
         "slice" => builtin_slice(args, location, source),
+        "zip" => builtin_zip(args, location, source),
+        "chunk" => builtin_chunk(args, location, source),
+        // Strings and naming conventions
+        "pad_left" => builtin_pad("pad_left", args, location, source),
+        "pad_right" => builtin_pad("pad_right", args, location, source),
+        "index_of" => builtin_index_of(args, location, source),
+        "title_case" => builtin_title_case(args, location, source),
+        "snake_case" => builtin_snake_case(args, location, source),
+        "kebab_case" => builtin_kebab_case(args, location, source),
+        // Date and time
+        "now" => builtin_now(args, location, source),
+        "format_timestamp" => builtin_format_timestamp(args, location, source),
+        "parse_timestamp" => builtin_parse_timestamp(args, location, source),
+        // Durations
+        "to_seconds" => builtin_to_seconds(args, location, source),
+        "to_millis" => builtin_to_millis(args, location, source),
+        // Sizes
+        "to_bytes" => builtin_to_bytes(args, location, source),
+        "to_mebibytes" => builtin_to_mebibytes(args, location, source),
         _ => Err(HoneError::undefined_variable(
             source.to_string(),
             location,
@@ -94,13 +120,18 @@ pub fn is_builtin(name: &str) -> bool {
             | "contains"
             | "concat"
             | "merge"
+            | "deep_merge"
             | "range"
             | "flatten"
+            | "flatten_keys"
+            | "unflatten_keys"
             | "to_str"
             | "to_int"
             | "to_float"
             | "to_bool"
             | "default"
+            | "dig"
+            | "set_path"
             | "upper"
             | "lower"
             | "trim"
@@ -111,6 +142,8 @@ pub fn is_builtin(name: &str) -> bool {
             | "base64_decode"
             | "to_json"
             | "from_json"
+            | "to_toml"
+            | "from_toml"
             | "env"
             | "file"
             | "sort"
@@ -119,18 +152,232 @@ pub fn is_builtin(name: &str) -> bool {
             | "min"
             | "max"
             | "abs"
+            | "decimal"
             | "unique"
             | "sha256"
             | "type_of"
+            | "assert_type"
+            | "size_of"
             | "substring"
             | "entries"
             | "from_entries"
             | "clamp"
             | "reverse"
             | "slice"
+            | "zip"
+            | "chunk"
+            | "pad_left"
+            | "pad_right"
+            | "index_of"
+            | "title_case"
+            | "snake_case"
+            | "kebab_case"
+            | "now"
+            | "format_timestamp"
+            | "parse_timestamp"
+            | "to_seconds"
+            | "to_millis"
+            | "to_bytes"
+            | "to_mebibytes"
+            | "map"
+            | "filter"
+            | "reduce"
+            | "group_by"
+            | "update"
+            | "try"
     )
 }
 
+/// Calls a function value on behalf of a higher-order builtin
+pub type Apply<'a> = dyn FnMut(&Function, Vec<Value>) -> HoneResult<Value> + 'a;
+
+/// Check if a name is a built-in that takes a function argument. These need
+/// the evaluator to call it, so they go through `call_higher_order` instead
+/// of `call_builtin`.
+pub fn is_higher_order(name: &str) -> bool {
+    matches!(name, "map" | "filter" | "reduce" | "group_by" | "update")
+}
+
+/// Evaluate a higher-order built-in call, using `apply` to call its
+/// function argument
+pub fn call_higher_order(
+    name: &str,
+    args: Vec<Value>,
+    apply: &mut Apply,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    match name {
+        "map" => builtin_map(args, apply, location, source),
+        "filter" => builtin_filter(args, apply, location, source),
+        "reduce" => builtin_reduce(args, apply, location, source),
+        "group_by" => builtin_group_by(args, apply, location, source),
+        "update" => builtin_update(args, apply, location, source),
+        _ => Err(HoneError::undefined_variable(
+            source.to_string(),
+            location,
+            name,
+            format!("'{}' is not a built-in function", name),
+        )),
+    }
+}
+
+/// map(array, fn) -> array: `fn(x)` for each element
+fn builtin_map(
+    args: Vec<Value>,
+    apply: &mut Apply,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("map", &args, 2, location, source)?;
+    let (items, func) = array_and_function("map", &args[0], &args[1], location, source)?;
+    let mapped = items
+        .iter()
+        .map(|item| apply(func, vec![item.clone()]))
+        .collect::<HoneResult<_>>()?;
+    Ok(Value::Array(mapped))
+}
+
+/// filter(array, fn) -> array: the elements for which `fn(x)` is truthy
+fn builtin_filter(
+    args: Vec<Value>,
+    apply: &mut Apply,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("filter", &args, 2, location, source)?;
+    let (items, func) = array_and_function("filter", &args[0], &args[1], location, source)?;
+    let mut kept = Vec::new();
+    for item in items {
+        if apply(func, vec![item.clone()])?.is_truthy() {
+            kept.push(item.clone());
+        }
+    }
+    Ok(Value::Array(kept))
+}
+
+/// reduce(array, init, fn) -> any: folds `fn(acc, x)` over the elements,
+/// starting from `init`
+fn builtin_reduce(
+    args: Vec<Value>,
+    apply: &mut Apply,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("reduce", &args, 3, location, source)?;
+    let (items, func) = array_and_function("reduce", &args[0], &args[2], location, source)?;
+    let mut acc = args[1].clone();
+    for item in items {
+        acc = apply(func, vec![acc, item.clone()])?;
+    }
+    Ok(acc)
+}
+
+/// group_by(array, key) -> object: elements grouped under `key`, which is a
+/// field name or a function `fn(x)`. Groups keep the order they first appear in.
+fn builtin_group_by(
+    args: Vec<Value>,
+    apply: &mut Apply,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("group_by", &args, 2, location, source)?;
+    let Value::Array(items) = &args[0] else {
+        return Err(type_error(
+            "group_by",
+            "array",
+            args[0].type_name(),
+            location,
+            source,
+        ));
+    };
+    let mut groups: IndexMap<String, Value> = IndexMap::new();
+    for item in items {
+        let key = match (&args[1], item) {
+            (Value::String(field), Value::Object(obj)) => match obj.get(field) {
+                Some(key) => key.clone(),
+                None => {
+                    return Err(type_error(
+                        "group_by",
+                        &format!("objects with field '{}'", field),
+                        "object without it",
+                        location,
+                        source,
+                    ))
+                }
+            },
+            (Value::String(_), other) => {
+                return Err(type_error(
+                    "group_by",
+                    "an array of objects",
+                    other.type_name(),
+                    location,
+                    source,
+                ))
+            }
+            (Value::Function(func), _) => apply(func, vec![item.clone()])?,
+            (other, _) => {
+                return Err(type_error(
+                    "group_by",
+                    "string or function",
+                    other.type_name(),
+                    location,
+                    source,
+                ))
+            }
+        };
+        let key = match key {
+            Value::String(s) => s,
+            Value::Int(_) | Value::Bool(_) => key.to_string(),
+            other => {
+                return Err(type_error(
+                    "group_by",
+                    "string, int or bool keys",
+                    other.type_name(),
+                    location,
+                    source,
+                ))
+            }
+        };
+        match groups
+            .entry(key)
+            .or_insert_with(|| Value::Array(Vec::new()))
+        {
+            Value::Array(group) => group.push(item.clone()),
+            _ => unreachable!("groups are arrays"),
+        }
+    }
+    Ok(Value::Object(groups))
+}
+
+fn array_and_function<'a>(
+    name: &str,
+    array: &'a Value,
+    func: &'a Value,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<(&'a [Value], &'a Function)> {
+    let Value::Array(items) = array else {
+        return Err(type_error(
+            name,
+            "array",
+            array.type_name(),
+            location,
+            source,
+        ));
+    };
+    let Value::Function(func) = func else {
+        return Err(type_error(
+            name,
+            "function",
+            func.type_name(),
+            location,
+            source,
+        ));
+    };
+    Ok((items, func))
+}
+
 /// len(array) -> int, len(string) -> int, len(object) -> int
 fn builtin_len(args: Vec<Value>, location: &SourceLocation, source: &str) -> HoneResult<Value> {
     check_arity("len", &args, 1, location, source)?;
@@ -314,6 +561,69 @@ fn builtin_merge(args: Vec<Value>, location: &SourceLocation, source: &str) -> H
     Ok(Value::Object(result))
 }
 
+/// deep_merge(base, overlay, strategy?) -> value, merging as `key: value`
+/// ("normal", the default), `key +: value` ("append") or `key !: value`
+/// ("replace") would
+fn builtin_deep_merge(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    if !(2..=3).contains(&args.len()) {
+        return Err(arity_error(
+            "deep_merge",
+            "2 or 3",
+            args.len(),
+            location,
+            source,
+        ));
+    }
+    let strategy = match args.get(2) {
+        None => MergeStrategy::Normal,
+        Some(Value::String(s)) => match s.as_str() {
+            "normal" => MergeStrategy::Normal,
+            "append" => MergeStrategy::Append,
+            "replace" => MergeStrategy::Replace,
+            _ => {
+                return Err(HoneError::TypeMismatch {
+                    src: source.to_string(),
+                    span: (location.offset, location.length).into(),
+                    expected: "\"normal\", \"append\" or \"replace\"".to_string(),
+                    found: format!("\"{}\"", s),
+                    help: "the strategy names the operator to merge with: \"normal\" (:), \"append\" (+:) or \"replace\" (!:)".to_string(),
+                })
+            }
+        },
+        Some(other) => {
+            return Err(type_error(
+                "deep_merge",
+                "string",
+                other.type_name(),
+                location,
+                source,
+            ))
+        }
+    };
+    let mut args = args.into_iter();
+    let (base, overlay) = (args.next().unwrap(), args.next().unwrap());
+    // Like `+:`, append needs two arrays or two objects to combine
+    if strategy == MergeStrategy::Append
+        && !matches!(
+            (&base, &overlay),
+            (Value::Array(_), Value::Array(_)) | (Value::Object(_), Value::Object(_))
+        )
+    {
+        return Err(HoneError::TypeMismatch {
+            src: source.to_string(),
+            span: (location.offset, location.length).into(),
+            expected: "two arrays or two objects".to_string(),
+            found: format!("{} and {}", base.type_name(), overlay.type_name()),
+            help: "deep_merge(a, b, \"append\") concatenates arrays and merges objects".to_string(),
+        });
+    }
+    Ok(merge_values(base, overlay, strategy))
+}
+
 /// range(end) -> [0, 1, ..., end-1], range(start, end) -> [start, ..., end-1], range(start, end, step)
 fn builtin_range(args: Vec<Value>, location: &SourceLocation, source: &str) -> HoneResult<Value> {
     if args.is_empty() || args.len() > 3 {
@@ -372,20 +682,36 @@ fn builtin_range(args: Vec<Value>, location: &SourceLocation, source: &str) -> H
     Ok(Value::Array(result))
 }
 
-/// flatten(array) -> array (flattens one level)
+/// flatten(array, depth?) -> array: removes `depth` levels of nesting
+/// (default 1)
 fn builtin_flatten(args: Vec<Value>, location: &SourceLocation, source: &str) -> HoneResult<Value> {
-    check_arity("flatten", &args, 1, location, source)?;
+    if !(1..=2).contains(&args.len()) {
+        return Err(arity_error(
+            "flatten",
+            "1 or 2",
+            args.len(),
+            location,
+            source,
+        ));
+    }
+    let depth = match args.get(1) {
+        None => 1,
+        Some(depth) => {
+            let depth = expect_int("flatten", depth, location, source)?;
+            usize::try_from(depth).map_err(|_| HoneError::TypeMismatch {
+                src: source.to_string(),
+                span: (location.offset, location.length).into(),
+                expected: "depth >= 0".to_string(),
+                found: depth.to_string(),
+                help: "flatten() removes this many levels of nesting".to_string(),
+            })?
+        }
+    };
 
     match &args[0] {
         Value::Array(arr) => {
             let mut result = Vec::new();
-            for item in arr {
-                if let Value::Array(inner) = item {
-                    result.extend(inner.clone());
-                } else {
-                    result.push(item.clone());
-                }
-            }
+            flatten_into(arr, depth, &mut result);
             Ok(Value::Array(result))
         }
         other => Err(type_error(
@@ -398,6 +724,139 @@ fn builtin_flatten(args: Vec<Value>, location: &SourceLocation, source: &str) ->
     }
 }
 
+fn flatten_into(arr: &[Value], depth: usize, result: &mut Vec<Value>) {
+    for item in arr {
+        match item {
+            Value::Array(inner) if depth > 0 => flatten_into(inner, depth - 1, result),
+            _ => result.push(item.clone()),
+        }
+    }
+}
+
+/// flatten_keys(object, sep?) -> object: nested objects as one level of
+/// `sep`-joined keys (default "."); arrays and empty objects are values
+fn builtin_flatten_keys(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    let (obj, sep) = object_and_separator("flatten_keys", &args, location, source)?;
+    let mut flat = IndexMap::new();
+    flatten_keys_into(obj, sep, "", &mut flat, location, source)?;
+    Ok(Value::Object(flat))
+}
+
+fn flatten_keys_into(
+    obj: &IndexMap<String, Value>,
+    sep: &str,
+    prefix: &str,
+    flat: &mut IndexMap<String, Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<()> {
+    for (key, value) in obj {
+        let key = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}{}{}", prefix, sep, key)
+        };
+        match value {
+            Value::Object(inner) if !inner.is_empty() => {
+                flatten_keys_into(inner, sep, &key, flat, location, source)?
+            }
+            _ => {
+                if flat.contains_key(&key) {
+                    return Err(HoneError::TypeMismatch {
+                        src: source.to_string(),
+                        span: (location.offset, location.length).into(),
+                        expected: "distinct flattened keys".to_string(),
+                        found: format!("'{}' twice", key),
+                        help: "a key containing the separator collides with a nested key; pick another separator".to_string(),
+                    });
+                }
+                flat.insert(key, value.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// unflatten_keys(object, sep?) -> object: splits each key on `sep`
+/// (default ".") into nested objects
+fn builtin_unflatten_keys(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    let (obj, sep) = object_and_separator("unflatten_keys", &args, location, source)?;
+    let mut nested = IndexMap::new();
+    for (key, value) in obj {
+        let mut parts: Vec<&str> = key.split(sep).collect();
+        let last = parts.pop().unwrap_or_default();
+        let mut target = &mut nested;
+        for (i, part) in parts.iter().enumerate() {
+            let entry = target
+                .entry(part.to_string())
+                .or_insert_with(|| Value::Object(IndexMap::new()));
+            target = match entry {
+                Value::Object(inner) => inner,
+                _ => return Err(unflatten_conflict(&parts[..=i].join(sep), location, source)),
+            };
+        }
+        if target.contains_key(last) {
+            return Err(unflatten_conflict(key, location, source));
+        }
+        target.insert(last.to_string(), value.clone());
+    }
+    Ok(Value::Object(nested))
+}
+
+fn unflatten_conflict(key: &str, location: &SourceLocation, source: &str) -> HoneError {
+    HoneError::TypeMismatch {
+        src: source.to_string(),
+        span: (location.offset, location.length).into(),
+        expected: "each key set once".to_string(),
+        found: format!("'{}' is both a value and an object", key),
+        help: "unflatten_keys() can't nest keys under a key that holds a value".to_string(),
+    }
+}
+
+/// The object and separator (default ".") arguments of flatten_keys() and
+/// unflatten_keys()
+fn object_and_separator<'a>(
+    name: &str,
+    args: &'a [Value],
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<(&'a IndexMap<String, Value>, &'a str)> {
+    if !(1..=2).contains(&args.len()) {
+        return Err(arity_error(name, "1 or 2", args.len(), location, source));
+    }
+    let Value::Object(obj) = &args[0] else {
+        return Err(type_error(
+            name,
+            "object",
+            args[0].type_name(),
+            location,
+            source,
+        ));
+    };
+    let sep = match args.get(1) {
+        None => ".",
+        Some(sep) => expect_string(name, sep, location, source)?,
+    };
+    if sep.is_empty() {
+        return Err(HoneError::TypeMismatch {
+            src: source.to_string(),
+            span: (location.offset, location.length).into(),
+            expected: "non-empty separator".to_string(),
+            found: "\"\"".to_string(),
+            help: format!("{}() needs a separator such as \".\" or \"__\"", name),
+        });
+    }
+    Ok((obj, sep))
+}
+
 /// to_str(value) -> string
 fn builtin_to_str(args: Vec<Value>, location: &SourceLocation, source: &str) -> HoneResult<Value> {
     check_arity("to_str", &args, 1, location, source)?;
@@ -407,8 +866,10 @@ fn builtin_to_str(args: Vec<Value>, location: &SourceLocation, source: &str) ->
         Value::Bool(b) => b.to_string(),
         Value::Int(n) => n.to_string(),
         Value::Float(n) => n.to_string(),
+        Value::Duration(ms) => duration::format(*ms),
+        Value::Size(bytes) => size::format(*bytes),
         Value::String(s) => s.clone(),
-        Value::Array(_) | Value::Object(_) => {
+        Value::Array(_) | Value::Object(_) | Value::Function(_) => {
             return Err(type_error(
                 "to_str",
                 "scalar value",
@@ -506,6 +967,194 @@ fn builtin_default(args: Vec<Value>, location: &SourceLocation, source: &str) ->
     }
 }
 
+/// dig(value, path, default?) -> any: the value at `path`, a dotted string
+/// ("a.b.0") or an array of keys and indices, or `default` (null when
+/// omitted) if any segment is missing
+fn builtin_dig(args: Vec<Value>, location: &SourceLocation, source: &str) -> HoneResult<Value> {
+    if !(2..=3).contains(&args.len()) {
+        return Err(arity_error("dig", "2 or 3", args.len(), location, source));
+    }
+    let segments = path_segments("dig", &args[1], location, source)?;
+
+    let mut current = &args[0];
+    for segment in &segments {
+        let next = match (current, segment) {
+            (Value::Object(obj), segment) => obj.get(&segment_key(segment)),
+            (Value::Array(arr), segment) => segment_index(segment).and_then(|i| arr.get(i)),
+            _ => None,
+        };
+        match next {
+            Some(value) => current = value,
+            None => return Ok(args.get(2).cloned().unwrap_or(Value::Null)),
+        }
+    }
+    Ok(current.clone())
+}
+
+/// set_path(value, path, new) -> any: a copy of `value` with `new` at
+/// `path`, creating objects for missing keys
+fn builtin_set_path(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("set_path", &args, 3, location, source)?;
+    let segments = path_segments("set_path", &args[1], location, source)?;
+    let new = &args[2];
+    replace_at(
+        "set_path",
+        &args[0],
+        &segments,
+        &mut |_| Ok(new.clone()),
+        location,
+        source,
+    )
+}
+
+/// update(value, path, fn) -> any: a copy of `value` with the value at
+/// `path` (null if missing) replaced by `fn(old)`
+fn builtin_update(
+    args: Vec<Value>,
+    apply: &mut Apply,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("update", &args, 3, location, source)?;
+    let segments = path_segments("update", &args[1], location, source)?;
+    let Value::Function(func) = &args[2] else {
+        return Err(type_error(
+            "update",
+            "function",
+            args[2].type_name(),
+            location,
+            source,
+        ));
+    };
+    replace_at(
+        "update",
+        &args[0],
+        &segments,
+        &mut |old| apply(func, vec![old]),
+        location,
+        source,
+    )
+}
+
+/// The segments of a path argument: a dotted string ("a.b.0"), or an array
+/// of keys and indices
+fn path_segments(
+    name: &str,
+    path: &Value,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Vec<Value>> {
+    let segments = match path {
+        Value::String(path) if path.is_empty() => Vec::new(),
+        Value::String(path) => path
+            .split('.')
+            .map(|s| Value::String(s.to_string()))
+            .collect(),
+        Value::Array(path) => path.clone(),
+        other => {
+            return Err(type_error(
+                name,
+                "string or array path",
+                other.type_name(),
+                location,
+                source,
+            ))
+        }
+    };
+    if let Some(bad) = segments
+        .iter()
+        .find(|s| !matches!(s, Value::String(_) | Value::Int(_)))
+    {
+        return Err(HoneError::TypeMismatch {
+            src: source.to_string(),
+            span: (location.offset, location.length).into(),
+            expected: "string or int path segment".to_string(),
+            found: bad.type_name().to_string(),
+            help: format!(
+                "each segment of a {}() path is an object key or an array index",
+                name
+            ),
+        });
+    }
+    Ok(segments)
+}
+
+/// A path segment as an object key
+fn segment_key(segment: &Value) -> String {
+    match segment {
+        Value::String(key) => key.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// A path segment as an array index, if it is one
+fn segment_index(segment: &Value) -> Option<usize> {
+    match segment {
+        Value::Int(i) => usize::try_from(*i).ok(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// A copy of `value` with the value at `segments` replaced by `replace(old)`.
+/// Missing keys and nulls on the way become objects; array indices must
+/// exist.
+fn replace_at(
+    name: &str,
+    value: &Value,
+    segments: &[Value],
+    replace: &mut dyn FnMut(Value) -> HoneResult<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    let Some((segment, rest)) = segments.split_first() else {
+        return replace(value.clone());
+    };
+    match value {
+        Value::Object(_) | Value::Null => {
+            let mut obj = match value {
+                Value::Object(obj) => obj.clone(),
+                _ => IndexMap::new(),
+            };
+            let key = segment_key(segment);
+            let old = obj.get(&key).cloned().unwrap_or(Value::Null);
+            let new = replace_at(name, &old, rest, replace, location, source)?;
+            obj.insert(key, new);
+            Ok(Value::Object(obj))
+        }
+        Value::Array(arr) => match segment_index(segment).filter(|i| *i < arr.len()) {
+            Some(i) => {
+                let mut arr = arr.clone();
+                arr[i] = replace_at(name, &arr[i], rest, replace, location, source)?;
+                Ok(Value::Array(arr))
+            }
+            None => Err(HoneError::TypeMismatch {
+                src: source.to_string(),
+                span: (location.offset, location.length).into(),
+                expected: format!("index < {}", arr.len()),
+                found: segment_key(segment),
+                help: format!("{}() can only replace existing array elements", name),
+            }),
+        },
+        other => Err(HoneError::TypeMismatch {
+            src: source.to_string(),
+            span: (location.offset, location.length).into(),
+            expected: "object or array".to_string(),
+            found: other.type_name().to_string(),
+            help: format!(
+                "{}() can't set '{}' inside a {}",
+                name,
+                segment_key(segment),
+                other.type_name()
+            ),
+        }),
+    }
+}
+
 /// upper(string) -> string
 fn builtin_upper(args: Vec<Value>, location: &SourceLocation, source: &str) -> HoneResult<Value> {
     check_arity("upper", &args, 1, location, source)?;
@@ -675,6 +1324,58 @@ fn builtin_from_json(
     }
 }
 
+/// to_toml(object) -> string
+fn builtin_to_toml(args: Vec<Value>, location: &SourceLocation, source: &str) -> HoneResult<Value> {
+    check_arity("to_toml", &args, 1, location, source)?;
+    if !matches!(args[0], Value::Object(_)) {
+        return Err(type_error(
+            "to_toml",
+            "object",
+            args[0].type_name(),
+            location,
+            source,
+        ));
+    }
+    let toml_string = TomlEmitter::new()
+        .emit(&args[0])
+        .map_err(|e| HoneError::TypeMismatch {
+            src: source.to_string(),
+            span: (location.offset, location.length).into(),
+            expected: "serializable value".to_string(),
+            found: match e {
+                HoneError::IoError { message } => message,
+                other => other.to_string(),
+            },
+            help: "value could not be serialized to TOML".to_string(),
+        })?;
+    Ok(Value::String(toml_string))
+}
+
+/// from_toml(string) -> object
+fn builtin_from_toml(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("from_toml", &args, 1, location, source)?;
+    match &args[0] {
+        Value::String(s) => parse_toml(s).map_err(|e| HoneError::TypeMismatch {
+            src: source.to_string(),
+            span: (location.offset, location.length).into(),
+            expected: "valid TOML string".to_string(),
+            found: format!("parse error: {}", e.trim_end()),
+            help: "the input string is not valid TOML".to_string(),
+        }),
+        other => Err(type_error(
+            "from_toml",
+            "string",
+            other.type_name(),
+            location,
+            source,
+        )),
+    }
+}
+
 /// env(name, default?) -> string
 fn builtin_env(args: Vec<Value>, location: &SourceLocation, source: &str) -> HoneResult<Value> {
     if args.is_empty() || args.len() > 2 {
@@ -832,6 +1533,113 @@ fn builtin_abs(args: Vec<Value>, location: &SourceLocation, source: &str) -> Hon
     }
 }
 
+/// decimal(number or string, places) -> float
+///
+/// Rounds the decimal the value is written as, not its binary float, so
+/// `decimal(2.675, 2)` is `2.68` and `decimal(0.1 + 0.2, 2)` is `0.3`.
+/// Halves round away from zero.
+fn builtin_decimal(args: Vec<Value>, location: &SourceLocation, source: &str) -> HoneResult<Value> {
+    check_arity("decimal", &args, 2, location, source)?;
+    let places = match &args[1] {
+        Value::Int(n) if (0..=17).contains(n) => *n as usize,
+        Value::Int(n) => {
+            return Err(HoneError::TypeMismatch {
+                src: source.to_string(),
+                span: (location.offset, location.length).into(),
+                expected: "0 to 17 places".to_string(),
+                found: n.to_string(),
+                help: "decimal() rounds to between 0 and 17 fractional digits".to_string(),
+            })
+        }
+        other => {
+            return Err(type_error(
+                "decimal",
+                "int",
+                other.type_name(),
+                location,
+                source,
+            ))
+        }
+    };
+    let text = match &args[0] {
+        Value::Int(n) => n.to_string(),
+        // Display writes the shortest decimal that reads back as the float
+        Value::Float(n) if n.is_finite() => n.to_string(),
+        Value::String(s) if is_decimal_text(s) => s.clone(),
+        Value::Float(_) | Value::String(_) => {
+            return Err(HoneError::TypeMismatch {
+                src: source.to_string(),
+                span: (location.offset, location.length).into(),
+                expected: "finite number or decimal string".to_string(),
+                found: args[0].to_string(),
+                help: "decimal() takes a number, or a string like \"12.345\"".to_string(),
+            })
+        }
+        other => {
+            return Err(type_error(
+                "decimal",
+                "number or string",
+                other.type_name(),
+                location,
+                source,
+            ))
+        }
+    };
+    let rounded = round_decimal(&text, places);
+    Ok(Value::Float(rounded.parse().unwrap_or(f64::NAN)))
+}
+
+/// Whether `s` is plain decimal text: `-12.345`
+fn is_decimal_text(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    let (int, frac) = digits.split_once('.').unwrap_or((digits, "0"));
+    !int.is_empty()
+        && !frac.is_empty()
+        && int.bytes().all(|b| b.is_ascii_digit())
+        && frac.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Round plain decimal text to `places` fractional digits, halves away
+/// from zero, working on the digits so nothing passes through binary
+fn round_decimal(text: &str, places: usize) -> String {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (int, frac) = digits.split_once('.').unwrap_or((digits, ""));
+    if frac.len() <= places {
+        return text.to_string();
+    }
+    let mut kept: Vec<u8> = int.bytes().chain(frac.bytes().take(places)).collect();
+    if frac.as_bytes()[places] >= b'5' {
+        let mut i = kept.len();
+        loop {
+            if i == 0 {
+                kept.insert(0, b'1');
+                break;
+            }
+            i -= 1;
+            if kept[i] == b'9' {
+                kept[i] = b'0';
+            } else {
+                kept[i] += 1;
+                break;
+            }
+        }
+    }
+    let int_len = kept.len() - places;
+    let mut rounded = String::with_capacity(kept.len() + 2);
+    if negative {
+        rounded.push('-');
+    }
+    rounded.push_str(std::str::from_utf8(&kept[..int_len]).unwrap_or("0"));
+    if places > 0 {
+        rounded.push('.');
+        rounded.push_str(std::str::from_utf8(&kept[int_len..]).unwrap_or("0"));
+    }
+    rounded
+}
+
 // ── P1 builtins ────────────────────────────────────────────────────────
 
 /// unique(array) -> array (preserves first occurrence order)
@@ -839,12 +1647,9 @@ fn builtin_unique(args: Vec<Value>, location: &SourceLocation, source: &str) ->
     check_arity("unique", &args, 1, location, source)?;
     match &args[0] {
         Value::Array(arr) => {
-            let mut seen = Vec::new();
-            let mut result = Vec::new();
+            let mut result: Vec<Value> = Vec::new();
             for item in arr {
-                let json_key = format!("{}", item);
-                if !seen.contains(&json_key) {
-                    seen.push(json_key);
+                if !result.contains(item) {
                     result.push(item.clone());
                 }
             }
@@ -876,6 +1681,82 @@ fn builtin_type_of(args: Vec<Value>, location: &SourceLocation, source: &str) ->
     Ok(Value::String(args[0].type_name().to_string()))
 }
 
+/// Type names `assert_type` accepts: those `type_of` returns, `number` for
+/// int or float, and `any`
+const TYPE_NAMES: &[&str] = &[
+    "null", "bool", "int", "float", "number", "duration", "size", "string", "array", "object",
+    "function", "block", "any",
+];
+
+/// assert_type(value, type, message?) -> value, or an error at the call when
+/// `value` is not of `type`: a type name, `name?` for it or null, or a union
+/// such as `"int | string"`
+fn builtin_assert_type(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    if !(2..=3).contains(&args.len()) {
+        return Err(arity_error(
+            "assert_type",
+            "2 or 3",
+            args.len(),
+            location,
+            source,
+        ));
+    }
+    let spec = expect_string("assert_type", &args[1], location, source)?;
+    let message = args
+        .get(2)
+        .map(|m| expect_string("assert_type", m, location, source))
+        .transpose()?;
+
+    let mut allowed = Vec::new();
+    for part in spec.split('|') {
+        let part = part.trim();
+        let (name, optional) = match part.strip_suffix('?') {
+            Some(name) => (name.trim_end(), true),
+            None => (part, false),
+        };
+        if !TYPE_NAMES.contains(&name) {
+            return Err(HoneError::TypeMismatch {
+                src: source.to_string(),
+                span: (location.offset, location.length).into(),
+                expected: "a type name".to_string(),
+                found: format!("\"{}\"", part),
+                help: format!("assert_type() takes one of {}", TYPE_NAMES.join(", ")),
+            });
+        }
+        allowed.push(name);
+        if optional {
+            allowed.push("null");
+        }
+    }
+
+    let found = args[0].type_name();
+    let matches = allowed.iter().any(|&name| {
+        name == found || name == "any" || (name == "number" && matches!(found, "int" | "float"))
+    });
+    if !matches {
+        return Err(HoneError::TypeMismatch {
+            src: source.to_string(),
+            span: (location.offset, location.length).into(),
+            expected: spec.to_string(),
+            found: found.to_string(),
+            help: message
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("assert_type() requires {}", spec)),
+        });
+    }
+    Ok(args.into_iter().next().unwrap_or(Value::Null))
+}
+
+/// size_of(value) -> int: approximate size in bytes as compact JSON
+fn builtin_size_of(args: Vec<Value>, location: &SourceLocation, source: &str) -> HoneResult<Value> {
+    check_arity("size_of", &args, 1, location, source)?;
+    Ok(Value::Int(args[0].approx_size() as i64))
+}
+
 /// substring(string, start, end?) -> string
 fn builtin_substring(
     args: Vec<Value>,
@@ -1051,8 +1932,382 @@ fn builtin_slice(args: Vec<Value>, location: &SourceLocation, source: &str) -> H
             Ok(Value::String(chars[start..end].iter().collect()))
         }
         other => Err(type_error(
-            "slice",
-            "array or string",
+            "slice",
+            "array or string",
+            other.type_name(),
+            location,
+            source,
+        )),
+    }
+}
+
+/// zip(arrays...) -> array of arrays, as long as the shortest input
+fn builtin_zip(args: Vec<Value>, location: &SourceLocation, source: &str) -> HoneResult<Value> {
+    if args.len() < 2 {
+        return Err(arity_error(
+            "zip",
+            "at least 2",
+            args.len(),
+            location,
+            source,
+        ));
+    }
+    let mut arrays = Vec::new();
+    for arg in &args {
+        match arg {
+            Value::Array(arr) => arrays.push(arr),
+            other => {
+                return Err(type_error(
+                    "zip",
+                    "array",
+                    other.type_name(),
+                    location,
+                    source,
+                ))
+            }
+        }
+    }
+    let len = arrays.iter().map(|a| a.len()).min().unwrap_or(0);
+    Ok(Value::Array(
+        (0..len)
+            .map(|i| Value::Array(arrays.iter().map(|a| a[i].clone()).collect()))
+            .collect(),
+    ))
+}
+
+/// chunk(array, n) -> [array]: consecutive slices of `n` elements; the last
+/// one may be shorter
+fn builtin_chunk(args: Vec<Value>, location: &SourceLocation, source: &str) -> HoneResult<Value> {
+    check_arity("chunk", &args, 2, location, source)?;
+    let Value::Array(arr) = &args[0] else {
+        return Err(type_error(
+            "chunk",
+            "array",
+            args[0].type_name(),
+            location,
+            source,
+        ));
+    };
+    let size = expect_int("chunk", &args[1], location, source)?;
+    if size < 1 {
+        return Err(type_error(
+            "chunk",
+            "a size of at least 1",
+            &size.to_string(),
+            location,
+            source,
+        ));
+    }
+    Ok(Value::Array(
+        arr.chunks(size as usize)
+            .map(|chunk| Value::Array(chunk.to_vec()))
+            .collect(),
+    ))
+}
+
+// ── Strings and naming conventions ─────────────────────────────────────
+
+/// pad_left(string, width, fill?) / pad_right(string, width, fill?) ->
+/// string: `fill` (a space by default) added to reach `width` characters
+fn builtin_pad(
+    name: &str,
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    if args.len() < 2 || args.len() > 3 {
+        return Err(arity_error(name, "2 or 3", args.len(), location, source));
+    }
+    let s = expect_string(name, &args[0], location, source)?;
+    let width = expect_int(name, &args[1], location, source)?;
+    let fill = match args.get(2) {
+        Some(fill) => {
+            let fill = expect_string(name, fill, location, source)?;
+            let mut chars = fill.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => {
+                    return Err(type_error(
+                        name,
+                        "a single fill character",
+                        &format!("\"{}\"", fill),
+                        location,
+                        source,
+                    ))
+                }
+            }
+        }
+        None => ' ',
+    };
+    let missing = (width.max(0) as usize).saturating_sub(s.chars().count());
+    let padding: String = std::iter::repeat_n(fill, missing).collect();
+    Ok(Value::String(if name == "pad_left" {
+        padding + s
+    } else {
+        s.to_string() + &padding
+    }))
+}
+
+/// index_of(string | array, needle) -> int: the character index of a
+/// substring or the index of an element, first match, or -1 if absent
+fn builtin_index_of(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("index_of", &args, 2, location, source)?;
+    let index = match &args[0] {
+        Value::Array(arr) => arr.iter().position(|v| v.equals(&args[1])),
+        Value::String(s) => {
+            let needle = expect_string("index_of", &args[1], location, source)?;
+            s.find(needle).map(|byte| s[..byte].chars().count())
+        }
+        other => {
+            return Err(type_error(
+                "index_of",
+                "array or string",
+                other.type_name(),
+                location,
+                source,
+            ))
+        }
+    };
+    Ok(Value::Int(index.map_or(-1, |i| i as i64)))
+}
+
+/// title_case(string) -> string: "api_gateway" -> "Api Gateway"
+fn builtin_title_case(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("title_case", &args, 1, location, source)?;
+    let s = expect_string("title_case", &args[0], location, source)?;
+    let words: Vec<String> = words(s)
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect(),
+                None => String::new(),
+            }
+        })
+        .collect();
+    Ok(Value::String(words.join(" ")))
+}
+
+/// snake_case(string) -> string: "apiGateway" -> "api_gateway"
+fn builtin_snake_case(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("snake_case", &args, 1, location, source)?;
+    let s = expect_string("snake_case", &args[0], location, source)?;
+    Ok(Value::String(words(s).join("_").to_lowercase()))
+}
+
+/// kebab_case(string) -> string: "ApiGateway" -> "api-gateway"
+fn builtin_kebab_case(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("kebab_case", &args, 1, location, source)?;
+    let s = expect_string("kebab_case", &args[0], location, source)?;
+    Ok(Value::String(words(s).join("-").to_lowercase()))
+}
+
+/// The words of an identifier in any naming convention. Words are
+/// separated by anything but letters and digits, and begin at a capital
+/// after a lowercase letter or digit (`apiGateway`) or at the last capital
+/// of a run followed by a lowercase letter (`HTTPServer`).
+fn words(s: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let chars: Vec<(usize, char)> = s.char_indices().collect();
+    let mut start: Option<usize> = None;
+    for (i, &(byte, c)) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if let Some(begin) = start.take() {
+                words.push(&s[begin..byte]);
+            }
+            continue;
+        }
+        let prev = i.checked_sub(1).map(|p| chars[p].1);
+        let next = chars.get(i + 1).map(|&(_, n)| n);
+        let boundary = c.is_uppercase()
+            && prev.is_some_and(|p| {
+                p.is_lowercase()
+                    || p.is_ascii_digit()
+                    || (p.is_uppercase() && next.is_some_and(char::is_lowercase))
+            });
+        match start {
+            Some(begin) if boundary => {
+                words.push(&s[begin..byte]);
+                start = Some(byte);
+            }
+            Some(_) => {}
+            None => start = Some(byte),
+        }
+    }
+    if let Some(begin) = start {
+        words.push(&s[begin..]);
+    }
+    words
+}
+
+// ── Date and time ──────────────────────────────────────────────────────
+
+/// now() -> int (seconds since the epoch)
+fn builtin_now(args: Vec<Value>, location: &SourceLocation, source: &str) -> HoneResult<Value> {
+    check_arity("now", &args, 0, location, source)?;
+    Ok(Value::Int(datetime::now()))
+}
+
+/// Error for a timestamp that cannot be formatted or parsed
+fn timestamp_error(
+    name: &str,
+    expected: &str,
+    message: String,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneError {
+    HoneError::TypeMismatch {
+        src: source.to_string(),
+        span: (location.offset, location.length).into(),
+        expected: expected.to_string(),
+        found: message,
+        help: format!(
+            "{}() formats use %Y %m %d %H %M %S %j %s %z %Z %F %T and %%",
+            name
+        ),
+    }
+}
+
+/// format_timestamp(ts, fmt?) -> string, in UTC
+fn builtin_format_timestamp(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(arity_error(
+            "format_timestamp",
+            "1 or 2",
+            args.len(),
+            location,
+            source,
+        ));
+    }
+    let timestamp = expect_int("format_timestamp", &args[0], location, source)?;
+    let format = match args.get(1) {
+        Some(format) => expect_string("format_timestamp", format, location, source)?,
+        None => datetime::DEFAULT_FORMAT,
+    };
+    datetime::format(timestamp, format)
+        .map(Value::String)
+        .map_err(|e| timestamp_error("format_timestamp", "a valid format", e, location, source))
+}
+
+/// parse_timestamp(s, fmt?) -> int, reading the text as UTC unless it has an
+/// offset
+fn builtin_parse_timestamp(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(arity_error(
+            "parse_timestamp",
+            "1 or 2",
+            args.len(),
+            location,
+            source,
+        ));
+    }
+    let text = expect_string("parse_timestamp", &args[0], location, source)?;
+    let format = match args.get(1) {
+        Some(format) => expect_string("parse_timestamp", format, location, source)?,
+        None => datetime::DEFAULT_FORMAT,
+    };
+    datetime::parse(text, format).map(Value::Int).map_err(|e| {
+        timestamp_error(
+            "parse_timestamp",
+            &format!("a timestamp matching \"{}\"", format),
+            e,
+            location,
+            source,
+        )
+    })
+}
+
+// ── Durations ──────────────────────────────────────────────────────────
+
+fn expect_duration(
+    name: &str,
+    value: &Value,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<i64> {
+    match value {
+        Value::Duration(ms) => Ok(*ms),
+        other => Err(type_error(
+            name,
+            "duration",
+            other.type_name(),
+            location,
+            source,
+        )),
+    }
+}
+
+/// to_seconds(duration) -> int; the duration must be whole seconds
+fn builtin_to_seconds(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("to_seconds", &args, 1, location, source)?;
+    let ms = expect_duration("to_seconds", &args[0], location, source)?;
+    if ms % 1000 != 0 {
+        return Err(HoneError::TypeMismatch {
+            src: source.to_string(),
+            span: (location.offset, location.length).into(),
+            expected: "a whole number of seconds".to_string(),
+            found: duration::format(ms),
+            help: "use to_millis(), or to_millis(d) / 1000 to round toward zero".to_string(),
+        });
+    }
+    Ok(Value::Int(ms / 1000))
+}
+
+/// to_millis(duration) -> int
+fn builtin_to_millis(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("to_millis", &args, 1, location, source)?;
+    let ms = expect_duration("to_millis", &args[0], location, source)?;
+    Ok(Value::Int(ms))
+}
+
+// ── Sizes ──────────────────────────────────────────────────────────────
+
+fn expect_size(
+    name: &str,
+    value: &Value,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<i64> {
+    match value {
+        Value::Size(bytes) => Ok(*bytes),
+        other => Err(type_error(
+            name,
+            "size",
             other.type_name(),
             location,
             source,
@@ -1060,6 +2315,37 @@ fn builtin_slice(args: Vec<Value>, location: &SourceLocation, source: &str) -> H
     }
 }
 
+/// to_bytes(size) -> int
+fn builtin_to_bytes(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("to_bytes", &args, 1, location, source)?;
+    let bytes = expect_size("to_bytes", &args[0], location, source)?;
+    Ok(Value::Int(bytes))
+}
+
+/// to_mebibytes(size) -> int; the size must be whole mebibytes
+fn builtin_to_mebibytes(
+    args: Vec<Value>,
+    location: &SourceLocation,
+    source: &str,
+) -> HoneResult<Value> {
+    check_arity("to_mebibytes", &args, 1, location, source)?;
+    let bytes = expect_size("to_mebibytes", &args[0], location, source)?;
+    if bytes % (1 << 20) != 0 {
+        return Err(HoneError::TypeMismatch {
+            src: source.to_string(),
+            span: (location.offset, location.length).into(),
+            expected: "a whole number of mebibytes".to_string(),
+            found: size::format(bytes),
+            help: "use to_bytes(), or to_bytes(s) / 1048576 to round toward zero".to_string(),
+        });
+    }
+    Ok(Value::Int(bytes >> 20))
+}
+
 /// Normalize a possibly-negative index: negative counts from end
 fn normalize_index(idx: i64, len: i64) -> i64 {
     if idx < 0 {
@@ -1071,7 +2357,7 @@ fn normalize_index(idx: i64, len: i64) -> i64 {
 
 // Helper functions
 
-fn check_arity(
+pub(super) fn check_arity(
     name: &str,
     args: &[Value],
     expected: usize,
@@ -1091,7 +2377,7 @@ fn check_arity(
     }
 }
 
-fn arity_error(
+pub(super) fn arity_error(
     name: &str,
     expected: &str,
     got: usize,
@@ -1107,7 +2393,7 @@ fn arity_error(
     }
 }
 
-fn type_error(
+pub(super) fn type_error(
     name: &str,
     expected: &str,
     got: &str,
@@ -1314,6 +2600,88 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_flatten_depth() {
+        let nested = Value::Array(vec![
+            Value::Int(1),
+            Value::Array(vec![
+                Value::Int(2),
+                Value::Array(vec![Value::Int(3), Value::Array(vec![Value::Int(4)])]),
+            ]),
+        ]);
+        let flatten = |depth: i64| {
+            call_builtin(
+                "flatten",
+                vec![nested.clone(), Value::Int(depth)],
+                &loc(),
+                "",
+            )
+            .map(|v| v.to_string())
+        };
+        assert_eq!(flatten(0).unwrap(), "[1, [2, [3, [4]]]]");
+        assert_eq!(flatten(1).unwrap(), "[1, 2, [3, [4]]]");
+        assert_eq!(flatten(2).unwrap(), "[1, 2, 3, [4]]");
+        assert_eq!(flatten(10).unwrap(), "[1, 2, 3, 4]");
+        assert!(flatten(-1).is_err());
+    }
+
+    #[test]
+    fn test_flatten_and_unflatten_keys() {
+        let nested = call_builtin(
+            "from_json",
+            vec![Value::String(
+                r#"{"server": {"port": 80, "tls": {"enabled": true}}, "hosts": ["a"], "extra": {}}"#
+                    .into(),
+            )],
+            &loc(),
+            "",
+        )
+        .unwrap();
+        let flat = call_builtin("flatten_keys", vec![nested.clone()], &loc(), "").unwrap();
+        assert_eq!(
+            flat.to_string(),
+            "{server.port: 80, server.tls.enabled: true, hosts: [a], extra: {}}"
+        );
+        let back = call_builtin("unflatten_keys", vec![flat], &loc(), "").unwrap();
+        assert_eq!(back, nested);
+
+        let sep = Value::String("__".into());
+        let env = call_builtin(
+            "flatten_keys",
+            vec![nested.clone(), sep.clone()],
+            &loc(),
+            "",
+        )
+        .unwrap();
+        assert!(env.get_path(&["server__tls__enabled"]).is_some());
+        let back = call_builtin("unflatten_keys", vec![env, sep], &loc(), "").unwrap();
+        assert_eq!(back, nested);
+
+        let clash = call_builtin(
+            "from_json",
+            vec![Value::String(r#"{"a.b": 1, "a": {"b": 2}}"#.into())],
+            &loc(),
+            "",
+        )
+        .unwrap();
+        assert!(call_builtin("flatten_keys", vec![clash], &loc(), "").is_err());
+        let clash = call_builtin(
+            "from_json",
+            vec![Value::String(r#"{"a": 1, "a.b": 2}"#.into())],
+            &loc(),
+            "",
+        )
+        .unwrap();
+        assert!(call_builtin("unflatten_keys", vec![clash], &loc(), "").is_err());
+        assert!(call_builtin(
+            "flatten_keys",
+            vec![nested, Value::String(String::new())],
+            &loc(),
+            ""
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_to_str() {
         assert_eq!(
@@ -1350,6 +2718,124 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_dig() {
+        let mut container = IndexMap::new();
+        container.insert("image".to_string(), Value::String("api:1".into()));
+        container.insert("port".to_string(), Value::Null);
+        let mut spec = IndexMap::new();
+        spec.insert(
+            "containers".to_string(),
+            Value::Array(vec![Value::Object(container)]),
+        );
+        let mut obj = IndexMap::new();
+        obj.insert("spec".to_string(), Value::Object(spec));
+        let obj = Value::Object(obj);
+        let dig = |path: Value, default: Option<Value>| {
+            let mut args = vec![obj.clone(), path];
+            args.extend(default);
+            call_builtin("dig", args, &loc(), "")
+        };
+
+        assert_eq!(
+            dig(Value::String("spec.containers.0.image".into()), None).unwrap(),
+            Value::String("api:1".into())
+        );
+        let path = Value::Array(vec![
+            Value::String("spec".into()),
+            Value::String("containers".into()),
+            Value::Int(0),
+            Value::String("image".into()),
+        ]);
+        assert_eq!(dig(path, None).unwrap(), Value::String("api:1".into()));
+        // A key that is present but null is not missing
+        assert_eq!(
+            dig(
+                Value::String("spec.containers.0.port".into()),
+                Some(Value::Int(80))
+            )
+            .unwrap(),
+            Value::Null
+        );
+        for missing in [
+            "spec.volumes",
+            "spec.containers.1.image",
+            "spec.containers.0.image.tag",
+        ] {
+            assert_eq!(
+                dig(Value::String(missing.into()), Some(Value::Int(80))).unwrap(),
+                Value::Int(80),
+                "{}",
+                missing
+            );
+        }
+        assert_eq!(
+            dig(Value::String("nope".into()), None).unwrap(),
+            Value::Null
+        );
+        assert_eq!(dig(Value::String(String::new()), None).unwrap(), obj);
+
+        assert!(dig(Value::Int(1), None).is_err());
+        assert!(dig(Value::Array(vec![Value::Bool(true)]), None).is_err());
+        assert!(call_builtin("dig", vec![obj.clone()], &loc(), "").is_err());
+    }
+
+    #[test]
+    fn test_set_path() {
+        let base = call_builtin(
+            "from_json",
+            vec![Value::String(
+                r#"{"spec": {"replicas": 1, "ports": [{"port": 80}]}, "name": "api"}"#.into(),
+            )],
+            &loc(),
+            "",
+        )
+        .unwrap();
+        let set = |path: &str, value: Value| {
+            call_builtin(
+                "set_path",
+                vec![base.clone(), Value::String(path.into()), value],
+                &loc(),
+                "",
+            )
+        };
+        let to_json = |v: Value| {
+            call_builtin("to_json", vec![v], &loc(), "")
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(
+            to_json(set("spec.replicas", Value::Int(3)).unwrap()),
+            r#"{"spec":{"replicas":3,"ports":[{"port":80}]},"name":"api"}"#
+        );
+        assert_eq!(
+            to_json(set("spec.ports.0.port", Value::Int(443)).unwrap()),
+            r#"{"spec":{"replicas":1,"ports":[{"port":443}]},"name":"api"}"#
+        );
+        // Missing keys become objects, added at the end
+        assert_eq!(
+            to_json(set("metadata.labels.app", Value::String("api".into())).unwrap()),
+            r#"{"spec":{"replicas":1,"ports":[{"port":80}]},"name":"api","metadata":{"labels":{"app":"api"}}}"#
+        );
+        let path = Value::Array(vec![
+            Value::String("spec".into()),
+            Value::String("a.b".into()),
+        ]);
+        let dotted = call_builtin(
+            "set_path",
+            vec![base.clone(), path, Value::Bool(true)],
+            &loc(),
+            "",
+        )
+        .unwrap();
+        assert_eq!(dotted.get_path(&["spec", "a.b"]), Some(&Value::Bool(true)));
+
+        assert!(set("spec.ports.1.port", Value::Int(443)).is_err());
+        assert!(set("name.first", Value::Int(1)).is_err());
+        assert!(call_builtin("set_path", vec![base.clone()], &loc(), "").is_err());
+    }
+
     #[test]
     fn test_unknown_builtin() {
         let result = call_builtin("unknown_func", vec![], &loc(), "");
@@ -1647,69 +3133,98 @@ mod tests {
     }
 
     #[test]
-    fn test_map_removed_with_helpful_error() {
-        let result = call_builtin(
-            "map",
-            vec![Value::Array(vec![]), Value::Array(vec![])],
-            &loc(),
-            "test",
+    fn test_toml_roundtrip() {
+        let mut server = IndexMap::new();
+        server.insert("port".to_string(), Value::Int(8080));
+        server.insert(
+            "hosts".to_string(),
+            Value::Array(vec![Value::String("a".into())]),
         );
-        assert!(result.is_err());
-        let err = result.unwrap_err();
-        match &err {
-            HoneError::UndefinedVariable { help, .. } => {
-                assert!(
-                    help.contains("for comprehension"),
-                    "error should suggest for comprehension, got: {}",
-                    help
-                );
-            }
-            other => panic!("expected UndefinedVariable, got: {:?}", other),
-        }
+        let mut obj = IndexMap::new();
+        obj.insert("name".to_string(), Value::String("test".into()));
+        obj.insert("server".to_string(), Value::Object(server));
+        let original = Value::Object(obj);
+
+        let toml = call_builtin("to_toml", vec![original.clone()], &loc(), "").unwrap();
+        assert_eq!(
+            toml,
+            Value::String("name = \"test\"\n\n[server]\nport = 8080\nhosts = [\"a\"]\n".into())
+        );
+        let restored = call_builtin("from_toml", vec![toml], &loc(), "").unwrap();
+        assert_eq!(restored, original);
     }
 
     #[test]
-    fn test_filter_removed_with_helpful_error() {
-        let result = call_builtin(
+    fn test_toml_errors() {
+        // TOML documents are tables, with no null
+        assert!(call_builtin("to_toml", vec![Value::Int(1)], &loc(), "").is_err());
+        let mut obj = IndexMap::new();
+        obj.insert("a".to_string(), Value::Null);
+        assert!(call_builtin("to_toml", vec![Value::Object(obj)], &loc(), "").is_err());
+
+        assert!(call_builtin("from_toml", vec![Value::String("a = ".into())], &loc(), "").is_err());
+        assert!(call_builtin("from_toml", vec![Value::Int(42)], &loc(), "").is_err());
+    }
+
+    fn ints(values: &[i64]) -> Value {
+        Value::Array(values.iter().map(|n| Value::Int(*n)).collect())
+    }
+
+    /// Stand-in for the evaluator: calls every function as `(x, y?) => x * 10 + y`
+    fn apply(_: &Function, args: Vec<Value>) -> HoneResult<Value> {
+        let n: i64 = args
+            .iter()
+            .map(|v| v.as_int().unwrap())
+            .fold(0, |acc, n| acc * 10 + n);
+        Ok(Value::Int(n))
+    }
+
+    fn func() -> Value {
+        Value::Function(std::sync::Arc::new(Function {
+            params: vec!["x".into()],
+            body: crate::parser::ast::Expr::Null(loc()),
+            captured: vec![],
+        }))
+    }
+
+    #[test]
+    fn test_map_filter_reduce() {
+        let mapped = call_higher_order("map", vec![ints(&[1, 2]), func()], &mut apply, &loc(), "");
+        assert_eq!(mapped.unwrap(), ints(&[1, 2]));
+
+        let mut odd =
+            |_: &Function, args: Vec<Value>| Ok(Value::Bool(args[0].as_int().unwrap() % 2 == 1));
+        let filtered = call_higher_order(
             "filter",
-            vec![Value::Array(vec![]), Value::Array(vec![])],
+            vec![ints(&[1, 2, 3]), func()],
+            &mut odd,
             &loc(),
-            "test",
+            "",
         );
-        assert!(result.is_err());
-        let err = result.unwrap_err();
-        match &err {
-            HoneError::UndefinedVariable { help, .. } => {
-                assert!(
-                    help.contains("for comprehension"),
-                    "error should suggest for comprehension, got: {}",
-                    help
-                );
-            }
-            other => panic!("expected UndefinedVariable, got: {:?}", other),
-        }
+        assert_eq!(filtered.unwrap(), ints(&[1, 3]));
+
+        let reduced = call_higher_order(
+            "reduce",
+            vec![ints(&[2, 3]), Value::Int(1), func()],
+            &mut apply,
+            &loc(),
+            "",
+        );
+        assert_eq!(reduced.unwrap(), Value::Int(123));
     }
 
     #[test]
-    fn test_reduce_removed_with_helpful_error() {
-        let result = call_builtin(
-            "reduce",
-            vec![Value::Array(vec![]), Value::Int(0), Value::Array(vec![])],
+    fn test_map_requires_function() {
+        let result = call_higher_order("map", vec![ints(&[1]), ints(&[])], &mut apply, &loc(), "");
+        assert!(matches!(result, Err(HoneError::TypeMismatch { .. })));
+        let result = call_higher_order(
+            "filter",
+            vec![Value::Int(1), func()],
+            &mut apply,
             &loc(),
-            "test",
+            "",
         );
-        assert!(result.is_err());
-        let err = result.unwrap_err();
-        match &err {
-            HoneError::UndefinedVariable { help, .. } => {
-                assert!(
-                    help.contains("for comprehension"),
-                    "error should suggest for comprehension, got: {}",
-                    help
-                );
-            }
-            other => panic!("expected UndefinedVariable, got: {:?}", other),
-        }
+        assert!(matches!(result, Err(HoneError::TypeMismatch { .. })));
     }
 
     #[test]
@@ -1782,10 +3297,10 @@ mod tests {
         assert!(is_builtin("clamp"));
         assert!(is_builtin("reverse"));
         assert!(is_builtin("slice"));
-        // Removed builtins
-        assert!(!is_builtin("map"));
-        assert!(!is_builtin("filter"));
-        assert!(!is_builtin("reduce"));
+        assert!(is_builtin("zip"));
+        assert!(is_builtin("map"));
+        assert!(is_builtin("filter"));
+        assert!(is_builtin("reduce"));
         assert!(!is_builtin("nonexistent"));
     }
 
@@ -1959,12 +3474,38 @@ mod tests {
             Value::Int(5)
         );
         assert_eq!(
-            call_builtin("abs", vec![Value::Float(-3.14)], &loc(), "").unwrap(),
-            Value::Float(3.14)
+            call_builtin("abs", vec![Value::Float(-3.25)], &loc(), "").unwrap(),
+            Value::Float(3.25)
         );
         assert!(call_builtin("abs", vec![Value::String("x".into())], &loc(), "").is_err());
     }
 
+    #[test]
+    fn test_decimal() {
+        let decimal = |value: Value, places: i64| {
+            call_builtin("decimal", vec![value, Value::Int(places)], &loc(), "")
+        };
+        // Rounds the decimal the float is written as, not its binary value
+        assert_eq!(
+            decimal(Value::Float(0.1 + 0.2), 2).unwrap(),
+            Value::Float(0.3)
+        );
+        assert_eq!(decimal(Value::Float(2.675), 2).unwrap(), Value::Float(2.68));
+        assert_eq!(decimal(Value::Float(-2.5), 0).unwrap(), Value::Float(-3.0));
+        assert_eq!(decimal(Value::Float(9.995), 2).unwrap(), Value::Float(10.0));
+        assert_eq!(decimal(Value::Float(1.5), 3).unwrap(), Value::Float(1.5));
+        assert_eq!(decimal(Value::Int(7), 2).unwrap(), Value::Float(7.0));
+        assert_eq!(
+            decimal(Value::String("19.9951".into()), 2).unwrap(),
+            Value::Float(20.0)
+        );
+
+        assert!(decimal(Value::String("1e3".into()), 2).is_err());
+        assert!(decimal(Value::Float(f64::NAN), 2).is_err());
+        assert!(decimal(Value::Float(1.0), -1).is_err());
+        assert!(decimal(Value::Bool(true), 2).is_err());
+    }
+
     // ── P1 builtin tests ──────────────────────────────────────────────
 
     #[test]
@@ -2012,6 +3553,124 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_unique_distinguishes_types() {
+        let arr = Value::Array(vec![
+            Value::Int(1),
+            Value::String("1".into()),
+            Value::Int(1),
+        ]);
+        let result = call_builtin("unique", vec![arr], &loc(), "").unwrap();
+        assert_eq!(
+            result,
+            Value::Array(vec![Value::Int(1), Value::String("1".into())])
+        );
+    }
+
+    #[test]
+    fn test_chunk() {
+        let result = call_builtin(
+            "chunk",
+            vec![ints(&[1, 2, 3, 4, 5]), Value::Int(2)],
+            &loc(),
+            "",
+        );
+        assert_eq!(
+            result.unwrap(),
+            Value::Array(vec![ints(&[1, 2]), ints(&[3, 4]), ints(&[5])])
+        );
+        let result = call_builtin("chunk", vec![ints(&[]), Value::Int(3)], &loc(), "");
+        assert_eq!(result.unwrap(), ints(&[]));
+        assert!(call_builtin("chunk", vec![ints(&[1]), Value::Int(0)], &loc(), "").is_err());
+    }
+
+    fn string(name: &str, args: &[Value]) -> HoneResult<Value> {
+        call_builtin(name, args.to_vec(), &loc(), "")
+    }
+
+    #[test]
+    fn test_pad() {
+        let s = |v: &str| Value::String(v.into());
+        assert_eq!(
+            string("pad_left", &[s("7"), Value::Int(3), s("0")]).unwrap(),
+            s("007")
+        );
+        assert_eq!(
+            string("pad_right", &[s("ab"), Value::Int(4)]).unwrap(),
+            s("ab  ")
+        );
+        assert_eq!(
+            string("pad_left", &[s("héllo"), Value::Int(3)]).unwrap(),
+            s("héllo")
+        );
+        assert!(string("pad_left", &[s("a"), Value::Int(3), s("ab")]).is_err());
+    }
+
+    #[test]
+    fn test_index_of() {
+        let s = |v: &str| Value::String(v.into());
+        assert_eq!(
+            string("index_of", &[s("héllo"), s("llo")]).unwrap(),
+            Value::Int(2)
+        );
+        assert_eq!(
+            string("index_of", &[s("hello"), s("x")]).unwrap(),
+            Value::Int(-1)
+        );
+        assert_eq!(
+            string("index_of", &[ints(&[4, 5, 6]), Value::Int(6)]).unwrap(),
+            Value::Int(2)
+        );
+        assert!(string("index_of", &[Value::Int(1), Value::Int(1)]).is_err());
+    }
+
+    #[test]
+    fn test_naming_conventions() {
+        let s = |v: &str| Value::String(v.into());
+        for (input, snake, kebab, title) in [
+            ("apiGateway", "api_gateway", "api-gateway", "Api Gateway"),
+            ("HTTPServer", "http_server", "http-server", "Http Server"),
+            (
+                "my-service_name",
+                "my_service_name",
+                "my-service-name",
+                "My Service Name",
+            ),
+            (
+                "  order  service ",
+                "order_service",
+                "order-service",
+                "Order Service",
+            ),
+            (
+                "k8sCluster2",
+                "k8s_cluster2",
+                "k8s-cluster2",
+                "K8s Cluster2",
+            ),
+            ("", "", "", ""),
+        ] {
+            assert_eq!(
+                string("snake_case", &[s(input)]).unwrap(),
+                s(snake),
+                "{}",
+                input
+            );
+            assert_eq!(
+                string("kebab_case", &[s(input)]).unwrap(),
+                s(kebab),
+                "{}",
+                input
+            );
+            assert_eq!(
+                string("title_case", &[s(input)]).unwrap(),
+                s(title),
+                "{}",
+                input
+            );
+        }
+    }
+
     #[test]
     fn test_sha256() {
         let result =
@@ -2067,6 +3726,67 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_assert_type() {
+        let check = |value: Value, spec: &str| {
+            call_builtin(
+                "assert_type",
+                vec![value, Value::String(spec.into())],
+                &loc(),
+                "",
+            )
+        };
+        assert_eq!(check(Value::Int(8080), "int").unwrap(), Value::Int(8080));
+        assert!(check(Value::Float(0.5), "number").is_ok());
+        assert!(check(Value::Null, "string?").is_ok());
+        assert!(check(Value::String("x".into()), "int | string").is_ok());
+        assert!(check(Value::Array(vec![]), "any").is_ok());
+
+        let err = check(Value::String("8080".into()), "int").unwrap_err();
+        match err {
+            HoneError::TypeMismatch {
+                expected, found, ..
+            } => {
+                assert_eq!(expected, "int");
+                assert_eq!(found, "string");
+            }
+            other => panic!("expected TypeMismatch, got {:?}", other),
+        }
+
+        let err = call_builtin(
+            "assert_type",
+            vec![
+                Value::Null,
+                Value::String("string".into()),
+                Value::String("args.region is required".into()),
+            ],
+            &loc(),
+            "",
+        )
+        .unwrap_err();
+        assert!(
+            matches!(err, HoneError::TypeMismatch { help, .. } if help == "args.region is required")
+        );
+
+        let err = check(Value::Int(1), "integer").unwrap_err();
+        assert!(matches!(err, HoneError::TypeMismatch { found, .. } if found == "\"integer\""));
+    }
+
+    #[test]
+    fn test_size_of() {
+        // ["ab",12] is 9 bytes as compact JSON
+        let value = Value::Array(vec![Value::String("ab".into()), Value::Int(12)]);
+        assert_eq!(
+            call_builtin("size_of", vec![value], &loc(), "").unwrap(),
+            Value::Int(9)
+        );
+        assert_eq!(
+            call_builtin("size_of", vec![Value::Null], &loc(), "").unwrap(),
+            Value::Int(4)
+        );
+        assert!(call_builtin("size_of", vec![], &loc(), "").is_err());
+    }
+
     #[test]
     fn test_substring() {
         assert_eq!(
@@ -2203,7 +3923,7 @@ mod tests {
         assert_eq!(
             call_builtin(
                 "clamp",
-                vec![Value::Float(3.14), Value::Float(0.0), Value::Float(1.0)],
+                vec![Value::Float(3.25), Value::Float(0.0), Value::Float(1.0)],
                 &loc(),
                 ""
             )
@@ -2349,8 +4069,8 @@ mod tests {
     #[test]
     fn test_to_float_from_string() {
         assert_eq!(
-            call_builtin("to_float", vec![Value::String("3.14".into())], &loc(), "").unwrap(),
-            Value::Float(3.14)
+            call_builtin("to_float", vec![Value::String("3.25".into())], &loc(), "").unwrap(),
+            Value::Float(3.25)
         );
     }
 
@@ -2408,6 +4128,54 @@ mod tests {
         assert_eq!(result, Value::Object(expected));
     }
 
+    #[test]
+    fn test_deep_merge_strategies() {
+        let object = |pairs: Vec<(&str, Value)>| {
+            Value::Object(pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+        };
+        let base = object(vec![
+            ("ports", ints(&[80])),
+            ("tls", object(vec![("enabled", Value::Bool(true))])),
+        ]);
+        let overlay = object(vec![
+            ("ports", ints(&[443])),
+            ("tls", object(vec![("cert", Value::String("a.pem".into()))])),
+        ]);
+        let deep_merge = |strategy: Option<&str>| {
+            let mut args = vec![base.clone(), overlay.clone()];
+            args.extend(strategy.map(|s| Value::String(s.into())));
+            call_builtin("deep_merge", args, &loc(), "")
+        };
+
+        let both_tls = object(vec![
+            ("enabled", Value::Bool(true)),
+            ("cert", Value::String("a.pem".into())),
+        ]);
+        assert_eq!(
+            deep_merge(None).unwrap(),
+            object(vec![("ports", ints(&[443])), ("tls", both_tls.clone())])
+        );
+        assert_eq!(
+            deep_merge(Some("normal")).unwrap(),
+            deep_merge(None).unwrap()
+        );
+        assert_eq!(
+            deep_merge(Some("append")).unwrap(),
+            object(vec![("ports", ints(&[80, 443])), ("tls", both_tls)])
+        );
+        assert_eq!(deep_merge(Some("replace")).unwrap(), overlay);
+
+        assert!(deep_merge(Some("union")).is_err());
+        assert!(call_builtin("deep_merge", vec![base.clone()], &loc(), "").is_err());
+        assert!(call_builtin(
+            "deep_merge",
+            vec![ints(&[1]), Value::Int(2), Value::String("append".into())],
+            &loc(),
+            ""
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_range_negative_step() {
         assert_eq!(
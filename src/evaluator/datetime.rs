@@ -0,0 +1,281 @@
+//! Timestamps for `now()`, `format_timestamp()` and `parse_timestamp()`
+//!
+//! A timestamp is an int: seconds since 1970-01-01T00:00:00Z. Formatting
+//! and parsing always use UTC, so both are deterministic; only `now()` reads
+//! the clock. Formats use these `strftime` directives:
+//!
+//! | Directive | Meaning |
+//! |---|---|
+//! | `%Y` | Year (`2024`) |
+//! | `%m` | Month, `01`-`12` |
+//! | `%d` | Day of the month, `01`-`31` |
+//! | `%H` / `%M` / `%S` | Hour, minute, second |
+//! | `%j` | Day of the year, `001`-`366` |
+//! | `%s` | Seconds since the epoch |
+//! | `%z` | UTC offset: `+0000` when formatting; `+hhmm`, `+hh:mm` or `Z` when parsing |
+//! | `%Z` | `UTC` |
+//! | `%F` | `%Y-%m-%d` |
+//! | `%T` | `%H:%M:%S` |
+//! | `%%` | `%` |
+
+/// Format used when none is given: RFC 3339 in UTC
+pub const DEFAULT_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Year, month and day of a number of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn is_leap(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Expand the `%F` and `%T` shorthands
+fn expand(format: &str) -> String {
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('F') => out.push_str("%Y-%m-%d"),
+            Some('T') => out.push_str("%H:%M:%S"),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// Format a timestamp in UTC
+pub fn format(timestamp: i64, format: &str) -> Result<String, String> {
+    let days = timestamp.div_euclid(SECONDS_PER_DAY);
+    let secs = timestamp.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    let mut out = String::new();
+    let format = expand(format);
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", year)),
+            Some('m') => out.push_str(&format!("{:02}", month)),
+            Some('d') => out.push_str(&format!("{:02}", day)),
+            Some('H') => out.push_str(&format!("{:02}", secs / 3600)),
+            Some('M') => out.push_str(&format!("{:02}", secs / 60 % 60)),
+            Some('S') => out.push_str(&format!("{:02}", secs % 60)),
+            Some('j') => {
+                let day_of_year = days - days_from_civil(year, 1, 1) + 1;
+                out.push_str(&format!("{:03}", day_of_year))
+            }
+            Some('s') => out.push_str(&timestamp.to_string()),
+            Some('z') => out.push_str("+0000"),
+            Some('Z') => out.push_str("UTC"),
+            Some('%') => out.push('%'),
+            Some(other) => return Err(format!("unknown format directive '%{}'", other)),
+            None => return Err("format ends with a lone '%'".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+/// Input left to parse
+struct Input<'a> {
+    text: &'a str,
+}
+
+impl Input<'_> {
+    /// Up to `max` digits, with a sign if `signed`
+    fn number(&mut self, what: &str, max: usize, signed: bool) -> Result<i64, String> {
+        let sign_len = usize::from(signed && self.text.starts_with(['-', '+']));
+        let digits = self.text[sign_len..]
+            .bytes()
+            .take(max)
+            .take_while(u8::is_ascii_digit)
+            .count();
+        if digits == 0 {
+            return Err(format!("expected {} at '{}'", what, self.text));
+        }
+        let (number, rest) = self.text.split_at(sign_len + digits);
+        self.text = rest;
+        number
+            .parse()
+            .map_err(|_| format!("{} '{}' is out of range", what, number))
+    }
+
+    fn literal(&mut self, expected: char) -> Result<(), String> {
+        match self.text.strip_prefix(expected) {
+            Some(rest) => {
+                self.text = rest;
+                Ok(())
+            }
+            None => Err(format!("expected '{}' at '{}'", expected, self.text)),
+        }
+    }
+
+    /// A UTC offset in seconds: `Z`, `+hhmm` or `+hh:mm`
+    fn offset(&mut self) -> Result<i64, String> {
+        if let Some(rest) = self.text.strip_prefix('Z') {
+            self.text = rest;
+            return Ok(0);
+        }
+        let sign = match self.text.chars().next() {
+            Some('+') => 1,
+            Some('-') => -1,
+            _ => return Err(format!("expected a UTC offset at '{}'", self.text)),
+        };
+        self.text = &self.text[1..];
+        let hours = self.number("offset hours", 2, false)?;
+        if let Some(rest) = self.text.strip_prefix(':') {
+            self.text = rest;
+        }
+        let minutes = self.number("offset minutes", 2, false)?;
+        Ok(sign * (hours * 3600 + minutes * 60))
+    }
+}
+
+/// Parse a timestamp; the text must match the whole format
+pub fn parse(text: &str, format: &str) -> Result<i64, String> {
+    let mut input = Input { text };
+    let (mut year, mut month, mut day) = (1970, 1, 1);
+    let (mut hour, mut minute, mut second) = (0, 0, 0);
+    let mut day_of_year = None;
+    let mut epoch = None;
+    let mut offset = 0;
+    let format = expand(format);
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            input.literal(c)?;
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => year = input.number("year", 4, true)?,
+            Some('m') => month = input.number("month", 2, false)?,
+            Some('d') => day = input.number("day", 2, false)?,
+            Some('H') => hour = input.number("hour", 2, false)?,
+            Some('M') => minute = input.number("minute", 2, false)?,
+            Some('S') => second = input.number("second", 2, false)?,
+            Some('j') => day_of_year = Some(input.number("day of the year", 3, false)?),
+            Some('s') => epoch = Some(input.number("seconds", 19, true)?),
+            Some('z') => offset = input.offset()?,
+            Some('Z') => match input.text.strip_prefix("UTC") {
+                Some(rest) => input.text = rest,
+                None => input.literal('Z')?,
+            },
+            Some('%') => input.literal('%')?,
+            Some(other) => return Err(format!("unknown format directive '%{}'", other)),
+            None => return Err("format ends with a lone '%'".to_string()),
+        }
+    }
+    if !input.text.is_empty() {
+        return Err(format!("unexpected '{}' after the timestamp", input.text));
+    }
+    if let Some(epoch) = epoch {
+        return Ok(epoch);
+    }
+
+    if !(1..=12).contains(&month) {
+        return Err(format!("month {} is not between 1 and 12", month));
+    }
+    if !(1..=days_in_month(year, month)).contains(&day) {
+        return Err(format!("{:04}-{:02} has no day {}", year, month, day));
+    }
+    if hour > 23 || minute > 59 || second > 59 {
+        return Err(format!(
+            "{:02}:{:02}:{:02} is not a time of day",
+            hour, minute, second
+        ));
+    }
+    let days = match day_of_year {
+        Some(n) if (1..=if is_leap(year) { 366 } else { 365 }).contains(&n) => {
+            days_from_civil(year, 1, 1) + n - 1
+        }
+        Some(n) => return Err(format!("{} has no day {}", year, n)),
+        None => days_from_civil(year, month, day),
+    };
+    Ok(days * SECONDS_PER_DAY + hour * 3600 + minute * 60 + second - offset)
+}
+
+/// Seconds since the epoch, now
+pub fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_and_parse_round_trip() {
+        // 2024-02-29T13:05:09Z
+        let ts = 1_709_211_909;
+        assert_eq!(format(ts, DEFAULT_FORMAT).unwrap(), "2024-02-29T13:05:09Z");
+        assert_eq!(
+            format(ts, "%F %T %Z, day %j").unwrap(),
+            "2024-02-29 13:05:09 UTC, day 060"
+        );
+        assert_eq!(format(0, "%s%z 100%%").unwrap(), "0+0000 100%");
+        assert_eq!(format(-1, DEFAULT_FORMAT).unwrap(), "1969-12-31T23:59:59Z");
+
+        assert_eq!(parse("2024-02-29T13:05:09Z", DEFAULT_FORMAT), Ok(ts));
+        assert_eq!(parse("2024-02-29T15:35:09+02:30", "%FT%T%z"), Ok(ts));
+        assert_eq!(parse("2024/060", "%Y/%j"), Ok(1_709_164_800));
+        assert_eq!(parse("1709211909", "%s"), Ok(ts));
+        for days in [-800_000, -1, 0, 19_782, 2_932_896] {
+            let ts = days * SECONDS_PER_DAY + 3_723;
+            assert_eq!(parse(&format(ts, "%F %T").unwrap(), "%F %T"), Ok(ts));
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("2023-02-29", "%F").unwrap_err().contains("no day 29"));
+        assert!(parse("2024-13-01", "%F").unwrap_err().contains("month 13"));
+        assert!(parse("2024-01-01 25:00:00", "%F %T").is_err());
+        assert!(parse("2024-01-01x", "%F").unwrap_err().contains("'x'"));
+        assert!(parse("2024-01", "%F").unwrap_err().contains("expected '-'"));
+        assert!(format(0, "%Q").unwrap_err().contains("%Q"));
+    }
+}
@@ -0,0 +1,291 @@
+//! `final` keys
+//!
+//! A key declared `final` may only be set by its own declaration. Every
+//! assignment in a document is recorded with the paths it writes, so a
+//! conflict is caught whichever side runs first: an overlay after the
+//! `final`, or a variant case (evaluated before the body) that sets it.
+
+use crate::lexer::token::SourceLocation;
+use crate::parser::ast::{BodyItem, ElseBranch, ForBody, PreambleItem};
+
+use super::value::Value;
+use super::LocationMap;
+
+/// An assignment to a key declared `final` elsewhere
+#[derive(Debug, Clone)]
+pub struct FinalConflict {
+    /// Dot-path of the final key
+    pub key: String,
+    /// The other assignment
+    pub at: SourceLocation,
+    /// The `final` declaration (its `file` is set when inherited via `from`)
+    pub final_at: SourceLocation,
+}
+
+/// Final declarations and assignment sites of one document
+#[derive(Debug, Default)]
+pub struct FinalKeys {
+    /// Paths declared final, with their declarations
+    declared: LocationMap,
+    /// Where each written path was first set
+    assigned: LocationMap,
+    /// Final blocks being evaluated; their own contents are not overrides
+    open: Vec<String>,
+    /// Nothing is recorded until a document can have final keys
+    enabled: bool,
+}
+
+impl FinalKeys {
+    /// Start recording if the document declares a final key
+    pub fn watch(&mut self, preamble: &[PreambleItem], body: &[BodyItem]) {
+        let in_variants = preamble.iter().any(|item| match item {
+            PreambleItem::Variant(v) => v.cases.iter().any(|c| declares_final(&c.body)),
+            _ => false,
+        });
+        self.enabled |= in_variants || declares_final(body);
+    }
+
+    /// Add the final keys of a `from` base; this document may not set them
+    pub fn inherit(&mut self, declared: LocationMap) {
+        self.enabled |= !declared.is_empty();
+        self.declared.extend(declared);
+    }
+
+    /// Paths declared final, including inherited ones
+    pub fn declared(&self) -> &LocationMap {
+        &self.declared
+    }
+
+    /// Record an assignment of `value` at `path`. `value` is `None` for a
+    /// block, whose items record themselves; `replace` is set for `!:`,
+    /// which drops everything below `path`.
+    pub fn assign(
+        &mut self,
+        path: &str,
+        value: Option<&Value>,
+        replace: bool,
+        location: &SourceLocation,
+        is_final: bool,
+    ) -> Result<(), Box<FinalConflict>> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let mut written = vec![path.to_string()];
+        if let Some(value) = value {
+            nested_paths(path, value, &mut written);
+        }
+
+        // Setting a final key, or something below it
+        for written_path in &written {
+            let covering = std::iter::once(written_path.as_str()).chain(
+                written_path
+                    .match_indices('.')
+                    .map(|(i, _)| &written_path[..i]),
+            );
+            for key in covering {
+                self.check_declared(key, location)?;
+            }
+        }
+        if replace {
+            let prefix = format!("{}.", path);
+            let below: Vec<String> = self
+                .declared
+                .keys()
+                .filter(|key| key.starts_with(&prefix))
+                .cloned()
+                .collect();
+            for key in below {
+                self.check_declared(&key, location)?;
+            }
+        }
+
+        // Declaring a key final that something else already set
+        if is_final {
+            let prefix = format!("{}.", path);
+            let earlier = self.assigned.iter().find(|(key, at)| {
+                (key.as_str() == path || key.starts_with(&prefix)) && *at != location
+            });
+            if let Some((_, at)) = earlier {
+                return Err(Box::new(FinalConflict {
+                    key: path.to_string(),
+                    at: at.clone(),
+                    final_at: location.clone(),
+                }));
+            }
+            self.declared.insert(path.to_string(), location.clone());
+        }
+
+        for written_path in written {
+            self.assigned
+                .entry(written_path)
+                .or_insert_with(|| location.clone());
+        }
+        Ok(())
+    }
+
+    /// Start evaluating the items of a final block at `path`
+    pub fn open(&mut self, path: &str) {
+        self.open.push(path.to_string());
+    }
+
+    /// Finish the innermost final block
+    pub fn close(&mut self) {
+        self.open.pop();
+    }
+
+    fn check_declared(
+        &self,
+        key: &str,
+        location: &SourceLocation,
+    ) -> Result<(), Box<FinalConflict>> {
+        match self.declared.get(key) {
+            Some(final_at) if final_at != location && !self.open.iter().any(|k| k == key) => {
+                Err(Box::new(FinalConflict {
+                    key: key.to_string(),
+                    at: location.clone(),
+                    final_at: final_at.clone(),
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Dot-paths of every key inside an object value
+fn nested_paths(path: &str, value: &Value, out: &mut Vec<String>) {
+    if let Value::Object(obj) = value {
+        for (key, val) in obj {
+            let child = format!("{}.{}", path, key);
+            nested_paths(&child, val, out);
+            out.push(child);
+        }
+    }
+}
+
+/// Whether any key or block in `items` is declared final
+fn declares_final(items: &[BodyItem]) -> bool {
+    items.iter().any(|item| match item {
+        BodyItem::KeyValue(kv) => kv.is_final,
+        BodyItem::Block(block) => block.is_final || declares_final(&block.items),
+        BodyItem::When(when) => {
+            let mut branch = Some(when);
+            let mut found = false;
+            while let Some(when) = branch {
+                found |= declares_final(&when.body);
+                branch = match &when.else_branch {
+                    Some(ElseBranch::ElseWhen(next)) => Some(next),
+                    Some(ElseBranch::Else(body, _)) => {
+                        found |= declares_final(body);
+                        None
+                    }
+                    None => None,
+                };
+            }
+            found
+        }
+        BodyItem::For(for_loop) => match &for_loop.body {
+            ForBody::Object(items) => declares_final(items),
+            ForBody::Expr(_) | ForBody::Block(..) => false,
+        },
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    fn loc(offset: usize) -> SourceLocation {
+        SourceLocation {
+            file: None,
+            line: 1,
+            column: offset + 1,
+            offset,
+            length: 1,
+        }
+    }
+
+    fn enabled() -> FinalKeys {
+        FinalKeys {
+            enabled: true,
+            ..FinalKeys::default()
+        }
+    }
+
+    #[test]
+    fn test_override_after_final_conflicts() {
+        let mut finals = enabled();
+        finals
+            .assign("replicas", Some(&Value::Int(3)), false, &loc(0), true)
+            .unwrap();
+        // The same site again (a loop) is not an override
+        finals
+            .assign("replicas", Some(&Value::Int(3)), false, &loc(0), true)
+            .unwrap();
+        let conflict = finals
+            .assign("replicas", Some(&Value::Int(5)), false, &loc(9), false)
+            .unwrap_err();
+        assert_eq!(conflict.key, "replicas");
+        assert_eq!(conflict.at.offset, 9);
+        assert_eq!(conflict.final_at.offset, 0);
+    }
+
+    #[test]
+    fn test_final_after_earlier_assignment_conflicts() {
+        let mut finals = enabled();
+        let mut server = IndexMap::new();
+        server.insert("port".to_string(), Value::Int(80));
+        finals
+            .assign(
+                "server",
+                Some(&Value::Object(server)),
+                false,
+                &loc(0),
+                false,
+            )
+            .unwrap();
+        let conflict = finals
+            .assign("server.port", Some(&Value::Int(8080)), false, &loc(5), true)
+            .unwrap_err();
+        assert_eq!(conflict.key, "server.port");
+        assert_eq!(conflict.at.offset, 0);
+    }
+
+    #[test]
+    fn test_final_block_contents_and_siblings() {
+        let mut finals = enabled();
+        finals.assign("db", None, false, &loc(0), true).unwrap();
+        finals.open("db");
+        finals
+            .assign("db.host", Some(&Value::Int(1)), false, &loc(3), false)
+            .unwrap();
+        finals.close();
+        // Below a final block from elsewhere, or replacing above a final key
+        assert!(finals
+            .assign("db.port", Some(&Value::Int(1)), false, &loc(7), false)
+            .is_err());
+        finals
+            .assign("app.name", Some(&Value::Int(1)), false, &loc(9), true)
+            .unwrap();
+        finals.assign("app", None, false, &loc(11), false).unwrap();
+        assert!(finals
+            .assign("app", Some(&Value::Null), true, &loc(13), false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_disabled_records_nothing() {
+        let mut finals = FinalKeys::default();
+        finals
+            .assign("a", Some(&Value::Int(1)), false, &loc(0), false)
+            .unwrap();
+        let mut inherited = LocationMap::new();
+        inherited.insert("a".to_string(), loc(4));
+        finals.inherit(inherited);
+        assert!(finals
+            .assign("a", Some(&Value::Int(2)), false, &loc(6), false)
+            .is_err());
+    }
+}
@@ -0,0 +1,226 @@
+//! First-class functions
+//!
+//! A lambda `(x, y) => body` evaluates to a [`Function`]: its parameters, its
+//! body, and the bindings the body refers to. Values are immutable, so the
+//! bindings are copied when the lambda is created and a call sees exactly
+//! them plus its arguments, wherever the function is passed or stored.
+//!
+//! A `block { ... }` is a function value too, with no parameters and the
+//! block as its body. It is never called: `...name` evaluates its items into
+//! the enclosing body, so `+:` and `!:` merge there as if written in place.
+
+use std::collections::HashSet;
+
+use crate::parser::ast::{
+    ArrayElement, BodyItem, ElseBranch, Expr, ForBody, ForLoop, Key, ObjectExpr, PathPart,
+    StringPart, WhenBlock,
+};
+
+use super::value::Value;
+
+/// A function value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub params: Vec<String>,
+    pub body: Expr,
+    /// Bindings the body closes over, as they were at creation
+    pub captured: Vec<(String, Value)>,
+}
+
+impl Function {
+    /// Short form for messages: `(x, y) => ...`, or `block { ... }`
+    pub fn signature(&self) -> String {
+        match self.block() {
+            Some(_) => "block { ... }".to_string(),
+            None => format!("({}) => ...", self.params.join(", ")),
+        }
+    }
+
+    /// The body of a `block { ... }` value, which is spliced rather than called
+    pub fn block(&self) -> Option<&ObjectExpr> {
+        match &self.body {
+            Expr::Block(body) => Some(body),
+            _ => None,
+        }
+    }
+}
+
+/// Every name `expr` might read from an enclosing scope. Names the
+/// expression binds itself (loop variables, inner lets) are included too;
+/// capturing an extra binding is harmless since the inner one shadows it.
+pub(crate) fn referenced_names(expr: &Expr) -> HashSet<String> {
+    let mut names = HashSet::new();
+    collect_expr(expr, &mut names);
+    names
+}
+
+/// Every name the items of an object body might read; see [`referenced_names`]
+pub(crate) fn body_referenced_names(items: &[BodyItem]) -> HashSet<String> {
+    let mut names = HashSet::new();
+    collect_body(items, &mut names);
+    names
+}
+
+fn collect_expr(expr: &Expr, names: &mut HashSet<String>) {
+    match expr {
+        Expr::Null(_)
+        | Expr::Bool(..)
+        | Expr::Integer(..)
+        | Expr::Float(..)
+        | Expr::Duration(..)
+        | Expr::Size(..) => {}
+        Expr::Ident(name, _) => {
+            names.insert(name.clone());
+        }
+        Expr::String(s) => {
+            for part in &s.parts {
+                if let StringPart::Interpolation(e) | StringPart::Formatted(e, _) = part {
+                    collect_expr(e, names);
+                }
+            }
+        }
+        Expr::Path(path) => {
+            for (i, part) in path.parts.iter().enumerate() {
+                match part {
+                    PathPart::Ident(name) if i == 0 => {
+                        names.insert(name.clone());
+                    }
+                    PathPart::Ident(_) => {}
+                    PathPart::Index(e) => collect_expr(e, names),
+                }
+            }
+        }
+        Expr::Array(array) => {
+            for element in &array.elements {
+                match element {
+                    ArrayElement::Expr(e) | ArrayElement::Spread(e) => collect_expr(e, names),
+                    ArrayElement::For(f) => collect_for(f, names),
+                    ArrayElement::When(w) => collect_when(w, names),
+                }
+            }
+        }
+        Expr::Object(object) | Expr::Block(object) => collect_body(&object.items, names),
+        Expr::Binary(b) => {
+            collect_expr(&b.left, names);
+            collect_expr(&b.right, names);
+        }
+        Expr::Unary(u) => collect_expr(&u.operand, names),
+        Expr::Call(call) => {
+            collect_expr(&call.func, names);
+            for arg in &call.args {
+                collect_expr(arg, names);
+            }
+        }
+        Expr::Index(index) => {
+            collect_expr(&index.base, names);
+            collect_expr(&index.index, names);
+        }
+        Expr::Conditional(c) => {
+            collect_expr(&c.condition, names);
+            collect_expr(&c.then_branch, names);
+            collect_expr(&c.else_branch, names);
+        }
+        Expr::Annotated(a) => {
+            collect_expr(&a.expr, names);
+            for arg in &a.constraint.args {
+                collect_expr(arg, names);
+            }
+            for arg in &a.constraint.named_args {
+                collect_expr(&arg.value, names);
+            }
+        }
+        Expr::Paren(inner, _) => collect_expr(inner, names),
+        Expr::For(f) => collect_for(f, names),
+        Expr::When(w) => collect_when(w, names),
+        Expr::Match(m) => {
+            collect_expr(&m.subject, names);
+            for arm in &m.arms {
+                for pattern in &arm.patterns {
+                    collect_expr(pattern, names);
+                }
+                collect_expr(&arm.value, names);
+            }
+        }
+        Expr::Lambda(lambda) => collect_expr(&lambda.body, names),
+    }
+}
+
+fn collect_body(items: &[BodyItem], names: &mut HashSet<String>) {
+    for item in items {
+        match item {
+            BodyItem::KeyValue(kv) => {
+                if let Key::Computed(key) = &kv.key {
+                    collect_expr(key, names);
+                }
+                collect_expr(&kv.value, names);
+            }
+            BodyItem::Block(block) => collect_body(&block.items, names),
+            BodyItem::When(w) => collect_when(w, names),
+            BodyItem::For(f) => collect_for(f, names),
+            BodyItem::Assert(assert) => {
+                collect_expr(&assert.condition, names);
+                if let Some(message) = &assert.message {
+                    collect_expr(message, names);
+                }
+            }
+            BodyItem::Let(binding) => collect_expr(&binding.value, names),
+            BodyItem::Spread(spread) => collect_expr(&spread.expr, names),
+            BodyItem::Use(_) | BodyItem::Include(_) => {}
+        }
+    }
+}
+
+fn collect_for(for_loop: &ForLoop, names: &mut HashSet<String>) {
+    collect_expr(&for_loop.iterable, names);
+    if let Some(filter) = &for_loop.filter {
+        collect_expr(filter, names);
+    }
+    match &for_loop.body {
+        ForBody::Object(items) => collect_body(items, names),
+        ForBody::Expr(e) => collect_expr(e, names),
+        ForBody::Block(items, e) => {
+            collect_body(items, names);
+            collect_expr(e, names);
+        }
+    }
+}
+
+fn collect_when(when: &WhenBlock, names: &mut HashSet<String>) {
+    collect_expr(&when.condition, names);
+    collect_body(&when.body, names);
+    match &when.else_branch {
+        Some(ElseBranch::ElseWhen(next)) => collect_when(next, names),
+        Some(ElseBranch::Else(items, _)) => collect_body(items, names),
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::ast::PreambleItem;
+    use crate::parser::Parser;
+
+    fn let_value(source: &str) -> Expr {
+        let tokens = Lexer::new(source, None).tokenize().unwrap();
+        let file = Parser::new(tokens, source, None).parse().unwrap();
+        match &file.preamble[0] {
+            PreambleItem::Let(binding) => binding.value.clone(),
+            _ => panic!("expected a let"),
+        }
+    }
+
+    #[test]
+    fn test_referenced_names() {
+        let expr = let_value(
+            "let f = (x) => { port: base.port + x, tags: [for t in extra { \"${t}-${env}\" }] }\n",
+        );
+        let Expr::Lambda(lambda) = expr else {
+            panic!("expected a lambda");
+        };
+        let mut names: Vec<_> = referenced_names(&lambda.body).into_iter().collect();
+        names.sort();
+        assert_eq!(names, ["base", "env", "extra", "t", "x"]);
+    }
+}
@@ -0,0 +1,116 @@
+//! Computed keys: the key a `[expr]` value becomes, and the order numeric
+//! keys sort in
+//!
+//! A string is its own key. A number becomes its decimal form, the same for
+//! an int and a float holding the same value: `[2]` and `[2.0]` are both
+//! the key "2", and `[-0.0]` is "0". Other floats keep the shortest form that
+//! reads back as the same number: `1.5`, `-0.25`.
+
+use std::cmp::Ordering;
+
+use super::Value;
+
+/// What to do when two computed keys in one object become the same key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyCollision {
+    /// The later key overrides the earlier one, as any repeated key does
+    #[default]
+    Override,
+    /// The later key is an error (E0307)
+    Error,
+}
+
+impl KeyCollision {
+    /// Parse a mode name: `override` or `error`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "override" => Some(KeyCollision::Override),
+            "error" => Some(KeyCollision::Error),
+            _ => None,
+        }
+    }
+}
+
+/// The key a computed key's value becomes, or why the value cannot be one
+pub fn computed_key(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Int(n) => Ok(n.to_string()),
+        Value::Float(f) if f.is_finite() => Ok(float_key(*f)),
+        Value::Float(f) => Err(format!("{} cannot be a key: use a finite number", f)),
+        _ => Err("computed keys must evaluate to a string or a number".to_string()),
+    }
+}
+
+fn float_key(f: f64) -> String {
+    // Every f64 in this range with no fraction is exactly an i64
+    if f.fract() == 0.0 && f.abs() < 9.2e18 {
+        (f as i64).to_string()
+    } else {
+        f.to_string()
+    }
+}
+
+/// A key written the way a computed number key is, as its number
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn of(key: &str) -> Option<Number> {
+        if let Ok(n) = key.parse::<i64>() {
+            return (n.to_string() == key).then_some(Number::Int(n));
+        }
+        let f = key.parse::<f64>().ok().filter(|f| f.is_finite())?;
+        (float_key(f) == key).then_some(Number::Float(f))
+    }
+
+    fn as_f64(&self) -> f64 {
+        match self {
+            Number::Int(n) => *n as f64,
+            Number::Float(f) => *f,
+        }
+    }
+}
+
+/// Sorted key order: keys that are numbers first, by value, then the rest
+/// by their text. A key is a number when a computed number key would be
+/// written that way, so "10" sorts after "2" but "02" is text.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    match (Number::of(a), Number::of(b)) {
+        (Some(Number::Int(x)), Some(Number::Int(y))) => x.cmp(&y),
+        (Some(x), Some(y)) => x
+            .as_f64()
+            .partial_cmp(&y.as_f64())
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.cmp(b)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_computed_key_normalizes_numbers() {
+        assert_eq!(computed_key(&Value::Int(-3)).unwrap(), "-3");
+        assert_eq!(computed_key(&Value::Float(2.0)).unwrap(), "2");
+        assert_eq!(computed_key(&Value::Float(-0.0)).unwrap(), "0");
+        assert_eq!(computed_key(&Value::Float(1.5)).unwrap(), "1.5");
+        assert_eq!(computed_key(&Value::Float(-0.25)).unwrap(), "-0.25");
+        assert_eq!(computed_key(&Value::String("2.0".into())).unwrap(), "2.0");
+        assert!(computed_key(&Value::Float(f64::NAN)).is_err());
+        assert!(computed_key(&Value::Bool(true)).is_err());
+    }
+
+    #[test]
+    fn test_compare_sorts_numbers_by_value() {
+        let mut keys = vec!["b", "10", "-1", "a", "2", "1.5", "02", "-0.5"];
+        keys.sort_by(|a, b| compare(a, b));
+        assert_eq!(keys, vec!["-1", "-0.5", "1.5", "2", "10", "02", "a", "b"]);
+    }
+}
@@ -0,0 +1,115 @@
+//! Value sizes for `compile --report-memory`
+//!
+//! When enabled, the evaluator measures the value each `let`, `for` loop and
+//! function call produces, keeping the largest one per source location, so
+//! the loop that builds an oversized intermediate value can be found.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::lexer::token::SourceLocation;
+
+/// The largest value one `let`, `for` loop or call produced
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueSize {
+    /// What produced it: `let name`, `for` loop or `name()`
+    pub what: String,
+    /// Approximate size in bytes as compact JSON (see [`super::Value::approx_size`])
+    pub bytes: usize,
+    /// How many times the site was evaluated
+    pub evaluations: usize,
+    pub location: SourceLocation,
+}
+
+/// Largest value per site, across every evaluated file
+#[derive(Debug, Clone, Default)]
+pub struct ValueSizes {
+    sites: HashMap<(Option<PathBuf>, usize), ValueSize>,
+}
+
+impl ValueSizes {
+    /// Note that the site at `location` produced a value of `bytes`
+    pub fn record(
+        &mut self,
+        bytes: usize,
+        location: &SourceLocation,
+        what: impl FnOnce() -> String,
+    ) {
+        let key = (location.file.clone(), location.offset);
+        let site = self.sites.entry(key).or_insert_with(|| ValueSize {
+            what: what(),
+            bytes: 0,
+            evaluations: 0,
+            location: location.clone(),
+        });
+        site.bytes = site.bytes.max(bytes);
+        site.evaluations += 1;
+    }
+
+    /// Add the sites another evaluator recorded
+    pub fn extend(&mut self, other: ValueSizes) {
+        for (key, theirs) in other.sites {
+            match self.sites.get_mut(&key) {
+                Some(ours) => {
+                    ours.bytes = ours.bytes.max(theirs.bytes);
+                    ours.evaluations += theirs.evaluations;
+                }
+                None => {
+                    self.sites.insert(key, theirs);
+                }
+            }
+        }
+    }
+
+    /// The `n` sites with the largest values, largest first
+    pub fn largest(&self, n: usize) -> Vec<&ValueSize> {
+        let mut sites: Vec<&ValueSize> = self.sites.values().collect();
+        sites.sort_by(|a, b| {
+            b.bytes
+                .cmp(&a.bytes)
+                .then_with(|| a.location.file.cmp(&b.location.file))
+                .then_with(|| a.location.offset.cmp(&b.location.offset))
+        });
+        sites.truncate(n);
+        sites
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sites.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(offset: usize) -> SourceLocation {
+        SourceLocation {
+            file: None,
+            line: 1,
+            column: offset + 1,
+            offset,
+            length: 1,
+        }
+    }
+
+    #[test]
+    fn test_keeps_largest_per_site() {
+        let mut sizes = ValueSizes::default();
+        sizes.record(10, &at(0), || "let a".to_string());
+        sizes.record(40, &at(0), || unreachable!());
+        sizes.record(25, &at(5), || "`for` loop".to_string());
+
+        let mut other = ValueSizes::default();
+        other.record(5, &at(5), || "`for` loop".to_string());
+        sizes.extend(other);
+
+        let largest = sizes.largest(5);
+        let summary: Vec<_> = largest
+            .iter()
+            .map(|s| (s.what.as_str(), s.bytes, s.evaluations))
+            .collect();
+        assert_eq!(summary, vec![("let a", 40, 2), ("`for` loop", 25, 2)]);
+        assert_eq!(sizes.largest(1).len(), 1);
+    }
+}
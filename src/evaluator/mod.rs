@@ -9,16 +9,32 @@
 //! - Built-in function calls
 //! - Merge semantics for assignment operators
 
+pub mod budget;
 pub mod builtins;
+pub mod datetime;
+pub mod finals;
+pub mod function;
+pub mod keys;
+pub mod memory;
 pub mod merge;
+pub mod policy;
+pub mod pretty;
+pub mod redact;
 pub mod scope;
+pub mod selfref;
+pub mod snapshot;
+pub mod stream;
 pub mod value;
 
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 use indexmap::IndexMap;
 
+use crate::cancel::CancelToken;
+use crate::emitter::OutputFormat;
 use crate::errors::{HoneError, HoneResult};
+use crate::lexer::duration;
 use crate::lexer::token::SourceLocation;
 use crate::parser::ast::*;
 
@@ -26,6 +42,63 @@ use crate::parser::ast::*;
 /// Used by the type checker to point errors at the offending value, not the `use` statement.
 pub type LocationMap = HashMap<String, SourceLocation>;
 
+/// Maps dot-paths to the `##` doc comment written above that key
+pub type DocMap = HashMap<String, String>;
+
+/// The body of a file that `include` splices into a block, with the source
+/// its locations point into
+#[derive(Debug)]
+pub struct Fragment {
+    pub items: Vec<BodyItem>,
+    pub source: String,
+}
+
+/// An ambient input read by `env()`, `file()` or `now()` during evaluation
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalInput {
+    pub kind: ExternalInputKind,
+    /// Environment variable name or file path, as written in the call;
+    /// `now()` for the clock
+    pub name: String,
+    /// The call that read it
+    pub location: SourceLocation,
+}
+
+/// Kind of ambient input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalInputKind {
+    Env,
+    File,
+    /// `now()`
+    Clock,
+}
+
+/// An assignment to an output key (recorded for `hone conflicts`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyWrite {
+    /// Dot-path of the key
+    pub path: String,
+    pub kind: WriteKind,
+    /// The value assigned, before merging with what was there
+    pub value: Value,
+    pub location: SourceLocation,
+}
+
+/// How a key was written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteKind {
+    /// `key: value`; objects deep-merge
+    Assign,
+    /// `key +: value`
+    Append,
+    /// `key !: value`
+    Replace,
+    /// A key of an object spread with `...`
+    Spread,
+    /// A key produced by a body-level `for`
+    For,
+}
+
 /// A user-defined function stored in the evaluator
 #[derive(Debug, Clone)]
 struct UserFunction {
@@ -33,8 +106,18 @@ struct UserFunction {
     body: Expr,
 }
 
+pub use finals::{FinalConflict, FinalKeys};
+pub use function::Function;
+use keys::KeyCollision;
+pub use memory::{ValueSize, ValueSizes};
 pub use merge::{merge_values, MergeBuilder, MergeStrategy};
+use policy::{Deadline, KeyHasher};
+pub use policy::{PolicyCache, PolicyTiming};
+pub use pretty::{pretty_print, PrettyOptions};
+pub use redact::Redactor;
 pub use scope::{Scope, ScopeStack};
+use selfref::{SelfFrame, SELF};
+pub use snapshot::{offset_at, snapshots_at, ScopeSnapshot};
 pub use value::Value;
 
 /// Maximum expression nesting depth before the evaluator bails out
@@ -50,16 +133,66 @@ pub struct Evaluator {
     allow_env: bool,
     /// Paths marked with @unchecked annotations
     unchecked_paths: HashSet<String>,
+    /// Values of @sensitive expressions, to hide outside the output
+    redactor: Redactor,
     /// Current output key path (for tracking @unchecked)
     current_path: Vec<String>,
     /// Variant selections (variant_name -> case_name)
     variant_selections: HashMap<String, String>,
     /// User-defined functions (name -> definition)
     user_functions: HashMap<String, UserFunction>,
+    /// Enum declarations (name -> values)
+    enums: HashMap<String, Vec<String>>,
     /// Current recursion depth
     depth: usize,
     /// Maps dot-paths to source locations where keys are defined
     location_map: LocationMap,
+    /// Maps dot-paths to the doc comments of the keys defining them
+    doc_comments: DocMap,
+    /// Scope snapshots per body item (only recorded when enabled)
+    snapshots: Option<Vec<ScopeSnapshot>>,
+    /// `use` statements reached in the body (inside a taken `when` or variant case)
+    applied_uses: Vec<UseStatement>,
+    /// Environment variables and files read by `env()`/`file()`, in call order
+    external_inputs: Vec<ExternalInput>,
+    /// Top-level `key: for ...` entries left for `stream_for` (only when enabled)
+    streamed: Option<Vec<(String, ForLoop)>>,
+    /// `final` keys of the document being evaluated
+    finals: FinalKeys,
+    /// Policy being checked and the output format, which budget helpers need
+    policy: Option<(String, OutputFormat)>,
+    /// Policies whose conditions called a budget helper
+    budget_policies: HashSet<String>,
+    /// Bodies being evaluated that read `self`, innermost last
+    self_frames: Vec<SelfFrame>,
+    /// Assignments to output keys, in evaluation order (only when enabled)
+    writes: Option<Vec<KeyWrite>>,
+    /// Whether body items being evaluated write to the output (rather than
+    /// to an object inside a `let`, call argument or array)
+    in_output: bool,
+    /// Stops evaluation once cancelled
+    cancel: Option<CancelToken>,
+    /// Whether a computed key may repeat in one object
+    key_collision: KeyCollision,
+    /// Keys given by computed keys and `for` loops in each object being
+    /// built, innermost last, with where each came from
+    computed_keys: Vec<HashMap<String, (SourceLocation, String)>>,
+    /// Files `include` can splice in, by canonical path
+    fragments: HashMap<std::path::PathBuf, Arc<Fragment>>,
+    /// Largest value each let, `for` loop and call produced (only when enabled)
+    value_sizes: Option<ValueSizes>,
+    /// Values of output-independent calls in policy conditions
+    policy_cache: Option<PolicyCache>,
+    /// Longest each policy's condition may take
+    policy_timeout: Option<std::time::Duration>,
+    /// When the policy being checked must finish
+    deadline: Option<Deadline>,
+    /// Calls of the policy being checked that may be cached, by address
+    cacheable_calls: HashSet<usize>,
+    /// Calls of the policy being checked answered from the cache
+    cached_calls: usize,
+    /// How long each checked policy took, in order
+    policy_timings: Vec<PolicyTiming>,
 }
 
 impl Evaluator {
@@ -70,11 +203,35 @@ impl Evaluator {
             source: source.into(),
             allow_env: false,
             unchecked_paths: HashSet::new(),
+            redactor: Redactor::default(),
             current_path: Vec::new(),
             variant_selections: HashMap::new(),
             user_functions: HashMap::new(),
+            enums: HashMap::new(),
             depth: 0,
             location_map: LocationMap::new(),
+            doc_comments: DocMap::new(),
+            snapshots: None,
+            applied_uses: Vec::new(),
+            external_inputs: Vec::new(),
+            streamed: None,
+            finals: FinalKeys::default(),
+            policy: None,
+            budget_policies: HashSet::new(),
+            self_frames: Vec::new(),
+            writes: None,
+            in_output: false,
+            cancel: None,
+            key_collision: KeyCollision::default(),
+            computed_keys: Vec::new(),
+            fragments: HashMap::new(),
+            value_sizes: None,
+            policy_cache: None,
+            policy_timeout: None,
+            deadline: None,
+            cacheable_calls: HashSet::new(),
+            cached_calls: 0,
+            policy_timings: Vec::new(),
         }
     }
 
@@ -83,6 +240,39 @@ impl Evaluator {
         self.allow_env = allow;
     }
 
+    /// Set whether a computed key that repeats in one object overrides the
+    /// earlier one or is an error
+    pub fn set_key_collision(&mut self, mode: KeyCollision) {
+        self.key_collision = mode;
+    }
+
+    /// Keep the values of output-independent calls in policy conditions in
+    /// `cache`, and reuse the ones it already holds
+    pub fn set_policy_cache(&mut self, cache: PolicyCache) {
+        self.policy_cache = Some(cache);
+    }
+
+    /// Stop checking a policy whose condition takes longer than `timeout`
+    pub fn set_policy_timeout(&mut self, timeout: Option<std::time::Duration>) {
+        self.policy_timeout = timeout;
+    }
+
+    /// How long each policy checked so far took, in order
+    pub fn policy_timings(&self) -> &[PolicyTiming] {
+        &self.policy_timings
+    }
+
+    /// Stop with [`HoneError::Cancelled`] once `token` is cancelled; checked
+    /// before every body item and expression
+    pub fn set_cancel(&mut self, token: CancelToken) {
+        self.cancel = Some(token);
+    }
+
+    /// Set the files `include` statements name, by canonical path
+    pub fn set_fragments(&mut self, fragments: HashMap<std::path::PathBuf, Arc<Fragment>>) {
+        self.fragments = fragments;
+    }
+
     /// Set variant selections (variant_name -> case_name)
     pub fn set_variant_selections(&mut self, selections: HashMap<String, String>) {
         self.variant_selections = selections;
@@ -93,24 +283,126 @@ impl Evaluator {
         &self.unchecked_paths
     }
 
+    /// Masks for the values of @sensitive expressions evaluated so far
+    pub fn redactor(&self) -> &Redactor {
+        &self.redactor
+    }
+
     /// Get the location map (dot-path -> SourceLocation)
     pub fn location_map(&self) -> &LocationMap {
         &self.location_map
     }
 
-    /// Evaluate policy declarations against the final output value.
+    /// Get the doc comments of evaluated keys (dot-path -> text)
+    pub fn doc_comments(&self) -> &DocMap {
+        &self.doc_comments
+    }
+
+    /// Make the `final` keys of a `from` base off limits to this file
+    pub fn inherit_final_keys(&mut self, declared: LocationMap) {
+        self.finals.inherit(declared);
+    }
+
+    /// Get the main document's `final` keys, including inherited ones
+    pub fn final_keys(&self) -> &LocationMap {
+        self.finals.declared()
+    }
+
+    /// Get the conditional `use` statements whose branch was taken
+    pub fn applied_uses(&self) -> &[UseStatement] {
+        &self.applied_uses
+    }
+
+    /// Take the environment variables and files read so far
+    pub fn take_external_inputs(&mut self) -> Vec<ExternalInput> {
+        std::mem::take(&mut self.external_inputs)
+    }
+
+    /// Measure the values lets, `for` loops and calls produce (for
+    /// `compile --report-memory`)
+    pub fn enable_value_sizes(&mut self) {
+        self.value_sizes.get_or_insert_with(ValueSizes::default);
+    }
+
+    /// Take the value sizes measured so far, leaving measuring enabled
+    pub fn take_value_sizes(&mut self) -> Option<ValueSizes> {
+        self.value_sizes.as_mut().map(std::mem::take)
+    }
+
+    /// Record the visible bindings at every body item (for `hone debug`)
+    pub fn enable_snapshots(&mut self) {
+        self.snapshots.get_or_insert_with(Vec::new);
+    }
+
+    /// Record every assignment to an output key (for `hone conflicts`)
+    pub fn enable_writes(&mut self) {
+        self.writes.get_or_insert_with(Vec::new);
+    }
+
+    /// Take the recorded assignments, leaving recording enabled
+    pub fn take_writes(&mut self) -> Vec<KeyWrite> {
+        self.writes.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Leave top-level `key: for ...` arrays unevaluated so they can be
+    /// produced one element at a time with [`Evaluator::stream_for`]. Each
+    /// such key holds `null` in the value returned by `evaluate`.
+    pub fn enable_streaming(&mut self) {
+        self.streamed.get_or_insert_with(Vec::new);
+    }
+
+    /// Take the keys whose arrays were deferred by streaming, in output order
+    pub fn take_streamed(&mut self) -> Vec<(String, ForLoop)> {
+        self.streamed
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Take the recorded scope snapshots, leaving recording enabled
+    pub fn take_snapshots(&mut self) -> Vec<ScopeSnapshot> {
+        self.snapshots
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Evaluate policy declarations against the final output value, which
+    /// budget helpers measure as emitted in `format`.
     /// Returns a list of (policy_name, level, message) for violations.
     pub fn check_policies(
         &mut self,
         policies: &[PolicyDeclaration],
         output: &Value,
+        format: OutputFormat,
     ) -> HoneResult<Vec<(String, PolicyLevel, String)>> {
         // Inject `output` as a variable in scope
         self.scopes.define("output", output.clone());
 
         let mut violations = Vec::new();
         for policy in policies {
-            let result = self.eval_expr(&policy.condition)?;
+            self.policy = Some((policy.name.clone(), format));
+            if self.policy_cache.is_some() {
+                self.cacheable_calls = policy::cacheable_calls(&policy.condition);
+            }
+            self.cached_calls = 0;
+            let start = std::time::Instant::now();
+            self.deadline = self.policy_timeout.map(|timeout| Deadline {
+                at: start + timeout,
+                timeout,
+                location: policy.location.clone(),
+            });
+            let result = self.eval_expr(&policy.condition);
+            self.policy_timings.push(PolicyTiming {
+                name: policy.name.clone(),
+                location: policy.location.clone(),
+                elapsed: start.elapsed(),
+                cached_calls: self.cached_calls,
+            });
+            self.policy = None;
+            self.deadline = None;
+            self.cacheable_calls.clear();
+            let result = result?;
             let triggered = match result {
                 Value::Bool(b) => b,
                 _ => result.is_truthy(),
@@ -127,6 +419,11 @@ impl Evaluator {
         Ok(violations)
     }
 
+    /// Whether a policy's condition measured the output with a budget helper
+    pub fn is_budget_policy(&self, name: &str) -> bool {
+        self.budget_policies.contains(name)
+    }
+
     /// Evaluate a file AST and return the result as a Value.
     ///
     /// Uses a two-pass approach over the preamble:
@@ -136,6 +433,8 @@ impl Evaluator {
     ///    key-value output that merges into the result object (not variable definitions).
     ///    Variants must run after all let bindings are resolved so they can reference them.
     pub fn evaluate(&mut self, file: &File) -> HoneResult<Value> {
+        self.finals.watch(&file.preamble, &file.body);
+
         // Pass 1: evaluate preamble items (let bindings, imports, etc.)
         for item in &file.preamble {
             self.eval_preamble_item(item)?;
@@ -143,6 +442,8 @@ impl Evaluator {
 
         // Then evaluate body items into an object
         let mut result = IndexMap::new();
+        self.in_output = true;
+        self.computed_keys.push(HashMap::new());
 
         // Pass 2: evaluate variant selections and merge their body items
         for item in &file.preamble {
@@ -151,11 +452,56 @@ impl Evaluator {
             }
         }
 
-        for item in &file.body {
-            self.eval_body_item(item, &mut result)?;
+        let framed = self.push_self_frame(&file.body, false);
+        let body = file.body.iter().enumerate().try_for_each(|(i, item)| {
+            if let Some((key, for_loop)) = self.streamable(item, file, &result) {
+                result.insert(key.clone(), Value::Null);
+                if let Some(streamed) = self.streamed.as_mut() {
+                    streamed.push((key, for_loop.clone()));
+                }
+                return Ok(());
+            }
+            self.eval_item_at(i, item, &mut result, framed)
+        });
+        self.pop_self_frame(framed);
+        self.computed_keys.pop();
+        self.in_output = false;
+        body?;
+
+        let result = Value::Object(result);
+        self.reject_functions(&result)?;
+        Ok(result)
+    }
+
+    /// A top-level `key: for ...` that streaming can defer: the key is plain
+    /// and nothing else in the document writes or reads it (see
+    /// [`stream::conflict`])
+    fn streamable<'a>(
+        &self,
+        item: &'a BodyItem,
+        file: &File,
+        result: &IndexMap<String, Value>,
+    ) -> Option<(String, &'a ForLoop)> {
+        self.streamed.as_ref()?;
+        let (key, for_loop) = stream::candidate(item)?;
+        if result.contains_key(key) || stream::conflict(file, item, key).is_some() {
+            return None;
         }
+        Some((key.clone(), for_loop))
+    }
 
-        Ok(Value::Object(result))
+    /// Evaluate a for loop deferred by streaming, handing each element to
+    /// `sink` as soon as it is produced instead of collecting the array
+    pub fn stream_for(
+        &mut self,
+        key: &str,
+        for_loop: &ForLoop,
+        sink: &mut dyn FnMut(Value) -> HoneResult<()>,
+    ) -> HoneResult<()> {
+        self.current_path.push(key.to_string());
+        let result = self.each_for_item(for_loop, sink);
+        self.current_path.pop();
+        result
     }
 
     /// Evaluate multiple documents and return them as a vector
@@ -165,11 +511,14 @@ impl Evaluator {
         // Evaluate main document
         let main = self.evaluate(file)?;
         results.push((None, main));
+        let main_finals = std::mem::take(&mut self.finals);
 
         // Evaluate sub-documents
         for doc in &file.documents {
             // Create a child scope for each document
             self.scopes.push();
+            self.finals = FinalKeys::default();
+            self.finals.watch(&doc.preamble, &doc.body);
 
             // Evaluate document preamble
             for item in &doc.preamble {
@@ -178,6 +527,7 @@ impl Evaluator {
 
             // Evaluate document body
             let mut obj = IndexMap::new();
+            self.in_output = true;
 
             // Process variant blocks from sub-document preamble
             for item in &doc.preamble {
@@ -186,24 +536,98 @@ impl Evaluator {
                 }
             }
 
-            for item in &doc.body {
-                self.eval_body_item(item, &mut obj)?;
-            }
+            let body = self.eval_items(&doc.body, &mut obj, false);
+            self.in_output = false;
+            body?;
 
             self.scopes.pop();
 
-            results.push((doc.name.clone(), Value::Object(obj)));
+            let obj = Value::Object(obj);
+            self.reject_functions(&obj)?;
+            results.push((doc.name.clone(), obj));
         }
+        self.finals = main_finals;
 
         Ok(results)
     }
 
+    /// Measure `value` for `--report-memory`, when enabled
+    fn note_size(
+        &mut self,
+        value: &Value,
+        location: &SourceLocation,
+        what: impl FnOnce() -> String,
+    ) {
+        if let Some(sizes) = self.value_sizes.as_mut() {
+            sizes.record(value.approx_size(), location, what);
+        }
+    }
+
+    /// Define the name(s) a let binding introduces. Destructured object keys
+    /// that are missing bind null, like a path to a missing key would.
+    fn bind_let(&mut self, binding: &LetBinding, value: Value) -> HoneResult<()> {
+        self.note_size(&value, &binding.location, || {
+            format!("let {}", binding.names().join(", "))
+        });
+        let mismatch = |expected: String, found: String, help: &str| HoneError::TypeMismatch {
+            src: self.source.clone(),
+            span: (binding.location.offset, binding.location.length).into(),
+            expected,
+            found,
+            help: help.to_string(),
+        };
+        match &binding.pattern {
+            None => self.scopes.define(&binding.name, value),
+            Some(LetPattern::Object(names)) => {
+                let Value::Object(obj) = value else {
+                    return Err(mismatch(
+                        "object".to_string(),
+                        value.type_name().to_string(),
+                        "`let { ... }` can only destructure an object",
+                    ));
+                };
+                for name in names {
+                    let field = obj.get(name).cloned().unwrap_or(Value::Null);
+                    self.scopes.define(name, field);
+                }
+            }
+            Some(LetPattern::Array { names, rest }) => {
+                let Value::Array(items) = value else {
+                    return Err(mismatch(
+                        "array".to_string(),
+                        value.type_name().to_string(),
+                        "`let [...]` can only destructure an array",
+                    ));
+                };
+                if items.len() < names.len() {
+                    return Err(mismatch(
+                        format!("at least {} elements", names.len()),
+                        match items.len() {
+                            1 => "1 element".to_string(),
+                            n => format!("{} elements", n),
+                        },
+                        "bind fewer names, or collect the tail with `...rest`",
+                    ));
+                }
+                let mut items = items.into_iter();
+                for name in names {
+                    let item = items.next().unwrap_or(Value::Null);
+                    self.scopes.define(name, item);
+                }
+                if let Some(rest) = rest {
+                    self.scopes.define(rest, Value::Array(items.collect()));
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Evaluate a preamble item
     fn eval_preamble_item(&mut self, item: &PreambleItem) -> HoneResult<()> {
         match item {
             PreambleItem::Let(binding) => {
                 let value = self.eval_expr(&binding.value)?;
-                self.scopes.define(&binding.name, value);
+                self.bind_let(binding, value)?;
             }
             PreambleItem::From(_) => {
                 // From is handled by the merge engine, not here
@@ -219,6 +643,17 @@ impl Evaluator {
             PreambleItem::TypeAlias(_) => {
                 // Type aliases are handled by the type checker
             }
+            PreambleItem::Enum(def) => {
+                // The type checker treats the enum as a union of its values;
+                // as a value, `Env.prod` evaluates to "prod"
+                let members = def
+                    .values
+                    .iter()
+                    .map(|v| (v.clone(), Value::String(v.clone())))
+                    .collect();
+                self.scopes.define(&def.name, Value::Object(members));
+                self.enums.insert(def.name.clone(), def.values.clone());
+            }
             PreambleItem::Use(_) => {
                 // Use statements are handled by the type checker
             }
@@ -287,29 +722,35 @@ impl Evaluator {
             Some(v)
         });
 
+        let enum_values = self.enums.get(&expect.type_name).cloned();
+
         match current_value {
             Some(value) => {
-                // Value exists - validate type
-                let type_ok = match expect.type_name.as_str() {
-                    "string" => matches!(value, Value::String(_)),
-                    "int" => matches!(value, Value::Int(_)),
-                    "float" => matches!(value, Value::Float(_) | Value::Int(_)),
-                    "bool" => matches!(value, Value::Bool(_)),
-                    "any" => true,
-                    _ => true, // unknown types pass through
-                };
-                if !type_ok {
-                    return Err(HoneError::TypeMismatch {
-                        src: self.source.clone(),
-                        span: (expect.location.offset, expect.location.length).into(),
-                        expected: expect.type_name.clone(),
-                        found: format!("{} (value: {})", value.type_name(), value),
-                        help: format!(
-                            "pass the correct type: --set {}=<{}>",
-                            arg_path.join("."),
-                            expect.type_name
-                        ),
-                    });
+                if let Some(values) = enum_values {
+                    let value = coerce_arg(&value, "string").unwrap_or(value);
+                    self.check_enum_value(&value, &expect.type_name, &values, expect, arg_path)?;
+                    self.set_arg(arg_path, value);
+                    return Ok(());
+                }
+                // Value exists - coerce it to the declared type
+                match coerce_arg(&value, &expect.type_name) {
+                    Some(coerced) if coerced != value => {
+                        self.set_arg(arg_path, coerced);
+                    }
+                    Some(_) => {}
+                    None => {
+                        return Err(HoneError::TypeMismatch {
+                            src: self.source.clone(),
+                            span: (expect.location.offset, expect.location.length).into(),
+                            expected: expect.type_name.clone(),
+                            found: format!("{} (value: {})", value.type_name(), value),
+                            help: format!(
+                                "pass the correct type: --set {}=<{}>",
+                                arg_path.join("."),
+                                expect.type_name
+                            ),
+                        });
+                    }
                 }
             }
             None => {
@@ -317,28 +758,16 @@ impl Evaluator {
                 if let Some(ref default_expr) = expect.default {
                     // Apply default: inject into args object
                     let default_value = self.eval_expr(default_expr)?;
-                    let mut args = self
-                        .scopes
-                        .get("args")
-                        .cloned()
-                        .unwrap_or_else(|| Value::Object(IndexMap::new()));
-                    // Navigate/create the nested path and set the value
-                    {
-                        let mut current = &mut args;
-                        for (i, part) in arg_path.iter().enumerate() {
-                            if i == arg_path.len() - 1 {
-                                if let Value::Object(ref mut obj) = current {
-                                    obj.insert(part.clone(), default_value.clone());
-                                }
-                            } else if let Value::Object(ref mut obj) = current {
-                                if !obj.contains_key(part.as_str()) {
-                                    obj.insert(part.clone(), Value::Object(IndexMap::new()));
-                                }
-                                current = obj.get_mut(part.as_str()).unwrap();
-                            }
-                        }
+                    if let Some(values) = enum_values {
+                        self.check_enum_value(
+                            &default_value,
+                            &expect.type_name,
+                            &values,
+                            expect,
+                            arg_path,
+                        )?;
                     }
-                    self.scopes.define("args", args);
+                    self.set_arg(arg_path, default_value);
                 } else {
                     // No default, no value: error
                     let path_str = expect.path.join(".");
@@ -356,6 +785,106 @@ impl Evaluator {
         Ok(())
     }
 
+    /// Set `args.<arg_path>`, creating intermediate objects as needed
+    fn set_arg(&mut self, arg_path: &[String], value: Value) {
+        let mut args = self
+            .scopes
+            .get("args")
+            .cloned()
+            .unwrap_or_else(|| Value::Object(IndexMap::new()));
+        let mut current = &mut args;
+        for (i, part) in arg_path.iter().enumerate() {
+            let Value::Object(obj) = current else {
+                break;
+            };
+            if i == arg_path.len() - 1 {
+                obj.insert(part.clone(), value);
+                break;
+            }
+            current = obj
+                .entry(part.clone())
+                .or_insert_with(|| Value::Object(IndexMap::new()));
+        }
+        self.scopes.define("args", args);
+    }
+
+    /// Check that an `expect`ed arg of enum type holds one of the enum's values
+    fn check_enum_value(
+        &self,
+        value: &Value,
+        enum_name: &str,
+        values: &[String],
+        expect: &ExpectDeclaration,
+        arg_path: &[String],
+    ) -> HoneResult<()> {
+        if matches!(value, Value::String(s) if values.contains(s)) {
+            return Ok(());
+        }
+        Err(HoneError::TypeMismatch {
+            src: self.source.clone(),
+            span: (expect.location.offset, expect.location.length).into(),
+            expected: format!("{} (one of: {})", enum_name, values.join(", ")),
+            found: format!("{} (value: {})", value.type_name(), value),
+            help: format!(
+                "pass one of the enum's values: --set {}={}",
+                arg_path.join("."),
+                values.join("|")
+            ),
+        })
+    }
+
+    /// Check a `variant name: Enum` against its enum: every case must be a
+    /// value of the enum, and every value needs a case unless there is a default
+    fn check_variant_enum(&self, variant: &VariantDefinition, enum_name: &str) -> HoneResult<()> {
+        let span = (variant.location.offset, variant.location.length);
+        let Some(values) = self.enums.get(enum_name) else {
+            return Err(HoneError::UndefinedVariable {
+                src: self.source.clone(),
+                span: span.into(),
+                name: enum_name.to_string(),
+                help: format!(
+                    "variant '{}' is typed by an enum that is not declared: enum {} {{ ... }}",
+                    variant.name, enum_name
+                ),
+            });
+        };
+
+        if let Some(case) = variant.cases.iter().find(|c| !values.contains(&c.name)) {
+            return Err(HoneError::TypeMismatch {
+                src: self.source.clone(),
+                span: (case.location.offset, case.location.length).into(),
+                expected: format!("{} (one of: {})", enum_name, values.join(", ")),
+                found: case.name.clone(),
+                help: format!(
+                    "case '{}' of variant '{}' is not a value of enum {}",
+                    case.name, variant.name, enum_name
+                ),
+            });
+        }
+
+        if !variant.cases.iter().any(|c| c.is_default) {
+            let missing: Vec<_> = values
+                .iter()
+                .filter(|v| !variant.cases.iter().any(|c| &c.name == *v))
+                .map(String::as_str)
+                .collect();
+            if !missing.is_empty() {
+                return Err(HoneError::TypeMismatch {
+                    src: self.source.clone(),
+                    span: span.into(),
+                    expected: format!("a case for every value of {}", enum_name),
+                    found: format!("no case for {}", missing.join(", ")),
+                    help: format!(
+                        "add cases for {} to variant '{}', or mark one case `default`",
+                        missing.join(", "),
+                        variant.name
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
     /// Evaluate a secret declaration: define as placeholder string
     fn eval_secret(&mut self, secret: &SecretDeclaration) -> HoneResult<()> {
         let placeholder = format!("<SECRET:{}>", secret.provider);
@@ -369,6 +898,14 @@ impl Evaluator {
         variant: &VariantDefinition,
         target: &mut IndexMap<String, Value>,
     ) -> HoneResult<()> {
+        let enum_values = match &variant.enum_name {
+            Some(enum_name) => {
+                self.check_variant_enum(variant, enum_name)?;
+                self.enums.get(enum_name).cloned()
+            }
+            None => None,
+        };
+
         // Find the selected case
         let selected_name = self.variant_selections.get(&variant.name).cloned();
 
@@ -377,8 +914,18 @@ impl Evaluator {
                 .cases
                 .iter()
                 .find(|c| &c.name == name)
+                // An enum value without its own case takes the default
+                .or_else(|| {
+                    enum_values
+                        .as_ref()
+                        .filter(|values| values.contains(name))
+                        .and_then(|_| variant.cases.iter().find(|c| c.is_default))
+                })
                 .ok_or_else(|| {
-                    let valid: Vec<_> = variant.cases.iter().map(|c| c.name.as_str()).collect();
+                    let valid: Vec<String> = match &enum_values {
+                        Some(values) => values.clone(),
+                        None => variant.cases.iter().map(|c| c.name.clone()).collect(),
+                    };
                     HoneError::TypeMismatch {
                         src: self.source.clone(),
                         span: (variant.location.offset, variant.location.length).into(),
@@ -412,9 +959,7 @@ impl Evaluator {
         };
 
         // Evaluate the selected case's body items
-        for item in &case.body {
-            self.eval_body_item(item, target)?;
-        }
+        self.eval_items(&case.body, target, true)?;
 
         Ok(())
     }
@@ -425,60 +970,39 @@ impl Evaluator {
         item: &BodyItem,
         target: &mut IndexMap<String, Value>,
     ) -> HoneResult<()> {
+        self.check_cancel()?;
+        if self.snapshots.is_some() {
+            self.record_snapshot(item);
+        }
+
         match item {
             BodyItem::KeyValue(kv) => {
                 let key = self.eval_key(&kv.key)?;
-                self.current_path.push(key.clone());
-                let path_str = self.current_path.join(".");
-                self.location_map.insert(path_str, kv.location.clone());
-                let value = self.eval_expr(&kv.value)?;
-                self.current_path.pop();
-
-                // Determine merge strategy from assignment operator
-                let strategy = match kv.op {
-                    AssignOp::Colon => MergeStrategy::Normal,
-                    AssignOp::Append => MergeStrategy::Append,
-                    AssignOp::Replace => MergeStrategy::Replace,
-                };
-
-                // Apply merge strategy
-                match target.get(&key).cloned() {
-                    Some(existing) => {
-                        // Validate append operator usage
-                        if matches!(kv.op, AssignOp::Append)
-                            && !matches!(
-                                (&existing, &value),
-                                (Value::Array(_), Value::Array(_))
-                                    | (Value::Object(_), Value::Object(_))
-                            )
-                        {
-                            return Err(HoneError::TypeMismatch {
-                                src: self.source.clone(),
-                                span: (kv.location.offset, kv.location.length).into(),
-                                expected: "array or object".to_string(),
-                                found: value.type_name().to_string(),
-                                help: "+: (append) requires both sides to be arrays or objects"
-                                    .to_string(),
-                            });
-                        }
-                        let merged = merge_values(existing, value, strategy);
-                        target.insert(key, merged);
-                    }
-                    None => {
-                        target.insert(key, value);
-                    }
-                }
+                let value = self.eval_key_value(&key, kv)?;
+                self.assign_key_value(kv, key, value, target)?;
             }
             BodyItem::Block(block) => {
                 // Block is shorthand for key: { ... }
                 self.current_path.push(block.name.clone());
                 let path_str = self.current_path.join(".");
-                self.location_map.insert(path_str, block.location.clone());
+                if let Some(doc) = &block.doc {
+                    self.doc_comments.insert(path_str.clone(), doc.clone());
+                }
+                self.location_map
+                    .insert(path_str.clone(), block.location.clone());
+                self.finals
+                    .assign(&path_str, None, false, &block.location, block.is_final)
+                    .map_err(|c| self.final_conflict(*c))?;
+                if block.is_final {
+                    self.finals.open(&path_str);
+                }
                 self.scopes.push();
                 let mut obj = IndexMap::new();
-                for item in &block.items {
-                    self.eval_body_item(item, &mut obj)?;
+                let result = self.eval_items(&block.items, &mut obj, false);
+                if block.is_final {
+                    self.finals.close();
                 }
+                result?;
                 self.scopes.pop();
                 self.current_path.pop();
 
@@ -499,17 +1023,27 @@ impl Evaluator {
             }
             BodyItem::For(for_loop) => {
                 // For loops at body level merge key-value pairs into the target object
-                let results = self.eval_for_in_array(for_loop)?;
+                let results = self.detached(|e| e.eval_for_in_array(for_loop))?;
                 for result in results {
                     if let Value::Object(obj) = result {
                         for (k, v) in obj {
+                            self.note_computed_key(
+                                &k,
+                                &for_loop.location,
+                                "the `for` loop".to_string(),
+                            )?;
+                            let path_str = self.child_path(&k);
+                            self.record_write(&path_str, WriteKind::For, &v, &for_loop.location);
+                            self.finals
+                                .assign(&path_str, Some(&v), false, &for_loop.location, false)
+                                .map_err(|c| self.final_conflict(*c))?;
                             target.insert(k, v);
                         }
                     }
                 }
             }
             BodyItem::Assert(assert) => {
-                let condition = self.eval_expr(&assert.condition)?;
+                let condition = self.detached(|e| e.eval_expr(&assert.condition))?;
                 if !condition.is_truthy() {
                     let message = if let Some(ref msg_expr) = assert.message {
                         let msg = self.eval_expr(msg_expr)?;
@@ -544,18 +1078,52 @@ impl Evaluator {
                 }
             }
             BodyItem::Let(binding) => {
-                let value = self.eval_expr(&binding.value)?;
-                self.scopes.define(&binding.name, value);
+                let value = self.detached(|e| e.eval_expr(&binding.value))?;
+                self.bind_let(binding, value)?;
+            }
+            BodyItem::Use(use_stmt) => {
+                // Only the main document's root can be validated; blocks, for
+                // bodies and sub-documents all push a scope
+                if !self.current_path.is_empty() || self.scopes.depth() > 1 {
+                    return Err(HoneError::UseNotAtTopLevel {
+                        src: self.source.clone(),
+                        span: (use_stmt.location.offset, use_stmt.location.length).into(),
+                    });
+                }
+                self.applied_uses.push(use_stmt.clone());
+            }
+            BodyItem::Include(include) => {
+                // The resolver only resolves includes in statement position
+                let Some(fragment) = include
+                    .resolved
+                    .as_ref()
+                    .and_then(|path| self.fragments.get(path))
+                    .cloned()
+                else {
+                    return Err(HoneError::IncludeNotAllowed {
+                        src: self.source.clone(),
+                        span: (include.location.offset, include.location.length).into(),
+                    });
+                };
+                let source = std::mem::replace(&mut self.source, fragment.source.clone());
+                let result = self.eval_items(&fragment.items, target, true);
+                self.source = source;
+                result?;
             }
             BodyItem::Spread(spread) => {
-                let value = self.eval_expr(&spread.expr)?;
+                let value = self.detached(|e| e.eval_expr(&spread.expr))?;
+                if let Value::Function(func) = &value {
+                    if let Some(block) = func.block() {
+                        return self.splice_block(func, block, target);
+                    }
+                }
                 if let Value::Object(obj) = value {
                     for (k, v) in obj {
-                        let path_str = if self.current_path.is_empty() {
-                            k.clone()
-                        } else {
-                            format!("{}.{}", self.current_path.join("."), k)
-                        };
+                        let path_str = self.child_path(&k);
+                        self.record_write(&path_str, WriteKind::Spread, &v, &spread.location);
+                        self.finals
+                            .assign(&path_str, Some(&v), false, &spread.location, false)
+                            .map_err(|c| self.final_conflict(*c))?;
                         self.location_map.insert(path_str, spread.location.clone());
                         target.insert(k, v);
                     }
@@ -573,6 +1141,348 @@ impl Evaluator {
         Ok(())
     }
 
+    /// Evaluate the items of a `block { ... }` value into `target`, with the
+    /// bindings the block captured, so each item merges with its own operator
+    fn splice_block(
+        &mut self,
+        func: &Function,
+        block: &ObjectExpr,
+        target: &mut IndexMap<String, Value>,
+    ) -> HoneResult<()> {
+        let mut scopes = ScopeStack::new();
+        for (name, value) in &func.captured {
+            scopes.define(name, value.clone());
+        }
+        scopes.push();
+        let enclosing = std::mem::replace(&mut self.scopes, scopes);
+        let result = self.eval_items(&block.items, target, true);
+        self.scopes = enclosing;
+        result
+    }
+
+    /// Evaluate the value of `key: value`, recording its location and doc comment
+    fn eval_key_value(&mut self, key: &str, kv: &KeyValue) -> HoneResult<Value> {
+        self.current_path.push(key.to_string());
+        let path_str = self.current_path.join(".");
+        if let Some(doc) = &kv.doc {
+            self.doc_comments.insert(path_str.clone(), doc.clone());
+        }
+        self.location_map.insert(path_str, kv.location.clone());
+        // The keys of an object literal are output keys too
+        let value = match &kv.value {
+            Expr::Object(_) => self.eval_expr(&kv.value),
+            _ => self.detached(|e| e.eval_expr(&kv.value)),
+        };
+        self.current_path.pop();
+        value
+    }
+
+    /// Assign the value of `key: value` to `target` with the item's operator
+    fn assign_key_value(
+        &mut self,
+        kv: &KeyValue,
+        key: String,
+        value: Value,
+        target: &mut IndexMap<String, Value>,
+    ) -> HoneResult<()> {
+        if let (Key::Computed(expr), KeyCollision::Error) = (&kv.key, self.key_collision) {
+            let site = self.computed_key_site(expr);
+            self.note_computed_key(&key, expr.location(), site)?;
+        }
+        let path_str = self.child_path(&key);
+        let kind = match kv.op {
+            AssignOp::Colon => WriteKind::Assign,
+            AssignOp::Append => WriteKind::Append,
+            AssignOp::Replace => WriteKind::Replace,
+        };
+        self.record_write(&path_str, kind, &value, &kv.location);
+        self.finals
+            .assign(
+                &path_str,
+                Some(&value),
+                matches!(kv.op, AssignOp::Replace),
+                &kv.location,
+                kv.is_final,
+            )
+            .map_err(|c| self.final_conflict(*c))?;
+
+        // Determine merge strategy from assignment operator
+        let strategy = match kv.op {
+            AssignOp::Colon => MergeStrategy::Normal,
+            AssignOp::Append => MergeStrategy::Append,
+            AssignOp::Replace => MergeStrategy::Replace,
+        };
+
+        // Apply merge strategy
+        match target.get(&key).cloned() {
+            Some(existing) => {
+                // Validate append operator usage
+                if matches!(kv.op, AssignOp::Append)
+                    && !matches!(
+                        (&existing, &value),
+                        (Value::Array(_), Value::Array(_)) | (Value::Object(_), Value::Object(_))
+                    )
+                {
+                    return Err(HoneError::TypeMismatch {
+                        src: self.source.clone(),
+                        span: (kv.location.offset, kv.location.length).into(),
+                        expected: "array or object".to_string(),
+                        found: value.type_name().to_string(),
+                        help: "+: (append) requires both sides to be arrays or objects".to_string(),
+                    });
+                }
+                let merged = merge_values(existing, value, strategy);
+                target.insert(key, merged);
+            }
+            None => {
+                target.insert(key, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluate the items of a body into `target`. `transparent` bodies
+    /// (`when` bodies, variant cases) add to an object assigned around them.
+    fn eval_items(
+        &mut self,
+        items: &[BodyItem],
+        target: &mut IndexMap<String, Value>,
+        transparent: bool,
+    ) -> HoneResult<()> {
+        let framed = self.push_self_frame(items, transparent);
+        if !transparent {
+            self.computed_keys.push(HashMap::new());
+        }
+        let result = items
+            .iter()
+            .enumerate()
+            .try_for_each(|(i, item)| self.eval_item_at(i, item, target, framed));
+        if !transparent {
+            self.computed_keys.pop();
+        }
+        self.pop_self_frame(framed);
+        result
+    }
+
+    /// Start a `self` frame for a body that reads `self`; returns whether it did
+    fn push_self_frame(&mut self, items: &[BodyItem], transparent: bool) -> bool {
+        if !selfref::mentions_self(items) {
+            return false;
+        }
+        let frame = SelfFrame::new(items, transparent, self.current_path.len());
+        self.self_frames.push(frame);
+        true
+    }
+
+    fn pop_self_frame(&mut self, framed: bool) {
+        if framed {
+            self.self_frames.pop();
+        }
+    }
+
+    /// Evaluate item `index` of a body, keeping its `self` frame current
+    fn eval_item_at(
+        &mut self,
+        index: usize,
+        item: &BodyItem,
+        target: &mut IndexMap<String, Value>,
+        framed: bool,
+    ) -> HoneResult<()> {
+        let Some(frame) = self.self_frames.last_mut().filter(|_| framed) else {
+            return self.eval_body_item(item, target);
+        };
+        frame.position = index;
+        frame.object = target.clone();
+        let BodyItem::KeyValue(kv) = item else {
+            return self.eval_body_item(item, target);
+        };
+        if let Some(value) = frame.take_early(index) {
+            let key = self.eval_key(&kv.key)?;
+            return self.assign_key_value(kv, key, value, target);
+        }
+        let (Key::Ident(key) | Key::String(key)) = &kv.key else {
+            return self.eval_body_item(item, target);
+        };
+        frame.enter(key);
+        let result = self.eval_body_item(item, target);
+        if let Some(frame) = self.self_frames.last_mut() {
+            frame.leave();
+        }
+        result
+    }
+
+    /// Error for `self` outside of an object body
+    fn self_outside_object(&self, loc: &SourceLocation) -> HoneError {
+        HoneError::undefined_variable(
+            self.source.clone(),
+            loc,
+            SELF,
+            "`self` is the object being built, so it can only be used inside one",
+        )
+    }
+
+    /// Evaluate `self.key`
+    fn eval_self(&mut self, key: &str, loc: &SourceLocation) -> HoneResult<Value> {
+        let Some(frame) = self.self_frames.last() else {
+            return Err(self.self_outside_object(loc));
+        };
+        if let Some(value) = frame.object.get(key) {
+            return Ok(value.clone());
+        }
+
+        for f in (0..self.self_frames.len()).rev() {
+            let frame = &self.self_frames[f];
+            if let Some(cycle) = frame.cycle(key) {
+                return Err(HoneError::CircularDependency {
+                    src: self.source.clone(),
+                    span: (loc.offset, loc.length).into(),
+                    cycle,
+                    help: format!(
+                        "'{}' is still being evaluated here; set one of these keys without `self`",
+                        key
+                    ),
+                });
+            }
+            if let Some((index, kv)) = frame.later_item(key) {
+                if let Some(value) = frame.early_value(index) {
+                    return Ok(value.clone());
+                }
+                let kv = kv.clone();
+                return self.eval_early(f, index, key, &kv);
+            }
+            if !frame.transparent {
+                break;
+            }
+        }
+
+        let frame = &self.self_frames[self.self_frames.len() - 1];
+        let assigned: Vec<String> = frame.object.keys().cloned().collect();
+        let help = if assigned.is_empty() {
+            format!("no key '{}' is assigned in this object", key)
+        } else {
+            format!(
+                "no key '{}' is assigned in this object (keys: {})",
+                key,
+                assigned.join(", ")
+            )
+        };
+        Err(HoneError::undefined_variable(
+            self.source.clone(),
+            loc,
+            format!("{}.{}", SELF, key),
+            help,
+        ))
+    }
+
+    /// Evaluate a later `key: value` of frame `f` ahead of its turn
+    fn eval_early(
+        &mut self,
+        f: usize,
+        index: usize,
+        key: &str,
+        kv: &KeyValue,
+    ) -> HoneResult<Value> {
+        let saved_path = self.current_path.clone();
+        self.current_path.truncate(self.self_frames[f].depth);
+        self.self_frames[f].enter(key);
+        let value = self.eval_key_value(key, kv);
+        self.self_frames[f].leave();
+        self.current_path = saved_path;
+        let value = value?;
+        self.self_frames[f].set_early(index, value.clone());
+        Ok(value)
+    }
+
+    /// Run `f` with body items writing to something other than the output
+    fn detached<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let in_output = std::mem::replace(&mut self.in_output, false);
+        let result = f(self);
+        self.in_output = in_output;
+        result
+    }
+
+    fn record_write(&mut self, path: &str, kind: WriteKind, value: &Value, at: &SourceLocation) {
+        if !self.in_output {
+            return;
+        }
+        if let Some(writes) = self.writes.as_mut() {
+            writes.push(KeyWrite {
+                path: path.to_string(),
+                kind,
+                value: value.clone(),
+                location: at.clone(),
+            });
+        }
+    }
+
+    /// Dot-path of `key` in the object being evaluated
+    fn child_path(&self, key: &str) -> String {
+        if self.current_path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.current_path.join("."), key)
+        }
+    }
+
+    /// Report an assignment to a `final` key with both sites
+    fn final_conflict(&self, conflict: FinalConflict) -> HoneError {
+        let (final_span, help) = match &conflict.final_at.file {
+            Some(file) if conflict.at.file.as_ref() != Some(file) => (
+                None,
+                format!(
+                    "'{}' is declared final in {}:{}:{}, so files built from it cannot set it",
+                    conflict.key,
+                    file.display(),
+                    conflict.final_at.line,
+                    conflict.final_at.column
+                ),
+            ),
+            _ => (
+                Some((conflict.final_at.offset, conflict.final_at.length).into()),
+                "remove this assignment, or drop `final` from the declaration".to_string(),
+            ),
+        };
+        HoneError::FinalKeyOverridden {
+            src: self.source.clone(),
+            span: (conflict.at.offset, conflict.at.length).into(),
+            final_span,
+            key: conflict.key,
+            help,
+        }
+    }
+
+    fn record_snapshot(&mut self, item: &BodyItem) {
+        let location = match item {
+            BodyItem::KeyValue(kv) => &kv.location,
+            BodyItem::Block(block) => &block.location,
+            BodyItem::When(when) => &when.location,
+            BodyItem::For(for_loop) => &for_loop.location,
+            BodyItem::Assert(assert) => &assert.location,
+            BodyItem::Let(binding) => &binding.location,
+            BodyItem::Spread(spread) => &spread.location,
+            BodyItem::Use(use_stmt) => &use_stmt.location,
+            BodyItem::Include(include) => &include.location,
+        };
+        let key = match item {
+            BodyItem::KeyValue(KeyValue {
+                key: Key::Ident(name) | Key::String(name),
+                ..
+            }) => Some(name.clone()),
+            BodyItem::Block(block) => Some(block.name.clone()),
+            _ => None,
+        };
+        let snapshot = ScopeSnapshot {
+            location: location.clone(),
+            path: self.current_path.join("."),
+            key,
+            depth: self.scopes.depth(),
+            bindings: self.scopes.visible_bindings(),
+        };
+        if let Some(ref mut snapshots) = self.snapshots {
+            snapshots.push(snapshot);
+        }
+    }
+
     /// Evaluate a key
     fn eval_key(&mut self, key: &Key) -> HoneResult<String> {
         match key {
@@ -580,23 +1490,156 @@ impl Evaluator {
             Key::String(s) => Ok(s.clone()),
             Key::Computed(expr) => {
                 let value = self.eval_expr(expr)?;
-                match value {
-                    Value::String(s) => Ok(s),
-                    Value::Int(n) => Ok(n.to_string()),
-                    other => Err(HoneError::TypeMismatch {
-                        src: self.source.clone(),
-                        span: (expr.location().offset, expr.location().length).into(),
-                        expected: "string or int".to_string(),
-                        found: other.type_name().to_string(),
-                        help: "computed keys must evaluate to string or int".to_string(),
-                    }),
-                }
+                keys::computed_key(&value).map_err(|help| HoneError::TypeMismatch {
+                    src: self.source.clone(),
+                    span: (expr.location().offset, expr.location().length).into(),
+                    expected: "string or number".to_string(),
+                    found: value.type_name().to_string(),
+                    help,
+                })
+            }
+        }
+    }
+
+    /// A computed key as written, for errors: `[expr]` or `"${...}"`
+    fn computed_key_site(&self, expr: &Expr) -> String {
+        let loc = expr.location();
+        let written = self
+            .source
+            .get(loc.offset..loc.offset + loc.length)
+            .map(str::to_string)
+            .unwrap_or_else(|| expr.display());
+        match expr {
+            Expr::String(_) => written,
+            _ => format!("[{}]", written),
+        }
+    }
+
+    /// Note `key`, given by a computed key or `for` loop at `location`, in
+    /// the object being built; with `--key-collision error` a key given
+    /// twice is an error
+    fn note_computed_key(
+        &mut self,
+        key: &str,
+        location: &SourceLocation,
+        site: String,
+    ) -> HoneResult<()> {
+        if self.key_collision != KeyCollision::Error {
+            return Ok(());
+        }
+        let Some(frame) = self.computed_keys.last_mut() else {
+            return Ok(());
+        };
+        let Some((first, first_site)) = frame.get(key) else {
+            frame.insert(key.to_string(), (location.clone(), site));
+            return Ok(());
+        };
+        let same = first == location;
+        let help = if same {
+            format!("{} gives '{}' more than once", site, key)
+        } else {
+            format!("{} and {} both give '{}'", first_site, site, key)
+        };
+        Err(HoneError::KeyCollision {
+            src: self.source.clone(),
+            span: (location.offset, location.length).into(),
+            first_span: (!same).then(|| (first.offset, first.length).into()),
+            key: key.to_string(),
+            help: format!(
+                "{}; --key-collision error rejects a key given twice in one object",
+                help
+            ),
+        })
+    }
+
+    fn check_cancel(&self) -> HoneResult<()> {
+        match &self.cancel {
+            Some(token) => token.check(),
+            None => Ok(()),
+        }
+    }
+
+    /// The error for a policy still running at its deadline
+    fn check_deadline(&self) -> HoneResult<()> {
+        let Some(deadline) = &self.deadline else {
+            return Ok(());
+        };
+        if std::time::Instant::now() < deadline.at {
+            return Ok(());
+        }
+        let name = self.policy.as_ref().map(|(name, _)| name.as_str());
+        let timeout = duration::format(deadline.timeout.as_millis() as i64);
+        Err(HoneError::unexpected_token(
+            self.source.clone(),
+            &deadline.location,
+            format!("policy to finish within {}", timeout),
+            format!("policy '{}' still running", name.unwrap_or_default()),
+            "simplify its condition, or raise --policy-timeout",
+        ))
+    }
+
+    /// Cache key for a call in the policy being checked: its text and the
+    /// values of every name it reads, following user functions into their
+    /// bodies. `None` when the call may not be cached.
+    fn policy_cache_key(&self, expr: &Expr, call: &CallExpr) -> Option<u64> {
+        if !self.cacheable_calls.contains(&policy::call_address(call)) {
+            return None;
+        }
+        let loc = &call.location;
+        let mut key = KeyHasher::new();
+        key.add(&self.source.get(loc.offset..loc.offset + loc.length)?);
+        key.add(&self.key_collision);
+
+        let mut pending: Vec<String> = function::referenced_names(expr).into_iter().collect();
+        pending.sort();
+        let mut seen = HashSet::new();
+        while let Some(name) = pending.pop() {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            if policy::is_impure(&name) {
+                return None;
+            }
+            key.add(&name);
+            if let Some(value) = self.scopes.get(&name) {
+                key.add(value);
+            } else if let Some(user_fn) = self.user_functions.get(&name) {
+                key.add(&user_fn.params);
+                key.add(&user_fn.body);
+                let mut names: Vec<String> = function::referenced_names(&user_fn.body)
+                    .into_iter()
+                    .collect();
+                names.sort();
+                pending.extend(names);
+            }
+        }
+        Some(key.finish())
+    }
+
+    /// Evaluate a call in a policy condition, through the policy cache when
+    /// it does not depend on the output
+    fn eval_policy_call(&mut self, expr: &Expr, call: &CallExpr) -> HoneResult<Value> {
+        let cache = self.policy_cache.clone();
+        let key = cache
+            .as_ref()
+            .and_then(|_| self.policy_cache_key(expr, call));
+        if let (Some(cache), Some(key)) = (&cache, key) {
+            if let Some(value) = cache.get(key) {
+                self.cached_calls += 1;
+                return Ok(value);
             }
         }
+        let value = self.eval_call(call, None)?;
+        if let (Some(cache), Some(key)) = (&cache, key) {
+            cache.insert(key, value.clone());
+        }
+        Ok(value)
     }
 
     /// Evaluate an expression
     pub fn eval_expr(&mut self, expr: &Expr) -> HoneResult<Value> {
+        self.check_cancel()?;
+        self.check_deadline()?;
         self.depth += 1;
         if self.depth > MAX_EVAL_DEPTH {
             let loc = expr.location();
@@ -621,6 +1664,8 @@ impl Evaluator {
             Expr::Bool(b, _) => Ok(Value::Bool(*b)),
             Expr::Integer(n, _) => Ok(Value::Int(*n)),
             Expr::Float(n, _) => Ok(Value::Float(*n)),
+            Expr::Duration(ms, _) => Ok(Value::Duration(*ms)),
+            Expr::Size(bytes, _) => Ok(Value::Size(*bytes)),
             Expr::String(s) => self.eval_string_expr(s),
             Expr::Ident(name, loc) => self.eval_ident(name, loc),
             Expr::Path(path) => self.eval_path(path),
@@ -628,9 +1673,17 @@ impl Evaluator {
             Expr::Object(obj) => self.eval_object(obj),
             Expr::Binary(bin) => self.eval_binary(bin),
             Expr::Unary(unary) => self.eval_unary(unary),
-            Expr::Call(call) => self.eval_call(call),
+            Expr::Call(call) if self.policy.is_some() => self.eval_policy_call(expr, call),
+            Expr::Call(call) => {
+                let value = self.eval_call(call, None)?;
+                self.note_size(&value, &call.location, || {
+                    format!("{}()", call.func.display())
+                });
+                Ok(value)
+            }
             Expr::Index(idx) => self.eval_index(idx),
             Expr::Conditional(cond) => self.eval_conditional(cond),
+            Expr::Match(m) => self.eval_match(m),
             Expr::Annotated(ann) => {
                 // Record @unchecked paths for the type checker to skip
                 if ann.constraint.name == "unchecked" {
@@ -641,12 +1694,102 @@ impl Evaluator {
                 }
                 // Type annotations are checked by the type checker
                 // Here we just evaluate the expression
-                self.eval_expr(&ann.expr)
+                let value = self.eval_expr(&ann.expr)?;
+                if ann.constraint.name == "sensitive" {
+                    self.redactor.add(&value);
+                }
+                Ok(value)
             }
             Expr::Paren(inner, _) => self.eval_expr(inner),
             Expr::For(for_loop) => self.eval_for_expr(for_loop),
             Expr::When(when) => self.eval_when_expr(when),
+            Expr::Lambda(lambda) => Ok(self.make_function(&lambda.params, &lambda.body)),
+            Expr::Block(_) => Ok(self.make_function(&[], expr)),
+        }
+    }
+
+    /// Create a function value, capturing the bindings `body` refers to
+    fn make_function(&self, params: &[String], body: &Expr) -> Value {
+        let mut captured: Vec<(String, Value)> = function::referenced_names(body)
+            .into_iter()
+            .filter(|name| !params.contains(name))
+            .filter_map(|name| self.scopes.get(&name).cloned().map(|v| (name, v)))
+            .collect();
+        captured.sort_by(|a, b| a.0.cmp(&b.0));
+        Value::Function(Arc::new(Function {
+            params: params.to_vec(),
+            body: body.clone(),
+            captured,
+        }))
+    }
+
+    /// Call a function value. The body sees only its captured bindings and
+    /// its arguments, not the scopes of the caller.
+    pub fn call_function(
+        &mut self,
+        func: &Function,
+        args: Vec<Value>,
+        location: &SourceLocation,
+    ) -> HoneResult<Value> {
+        if func.block().is_some() {
+            return Err(HoneError::TypeMismatch {
+                src: self.source.clone(),
+                span: (location.offset, location.length).into(),
+                expected: "function".to_string(),
+                found: "block".to_string(),
+                help: "a block is not called; splice it into an object with ...name".to_string(),
+            });
+        }
+        if args.len() != func.params.len() {
+            return Err(HoneError::TypeMismatch {
+                src: self.source.clone(),
+                span: (location.offset, location.length).into(),
+                expected: format!("{} argument(s)", func.params.len()),
+                found: format!("{} argument(s)", args.len()),
+                help: format!(
+                    "{} takes exactly {} argument(s)",
+                    func.signature(),
+                    func.params.len()
+                ),
+            });
+        }
+
+        let mut scopes = ScopeStack::new();
+        for (name, value) in &func.captured {
+            scopes.define(name, value.clone());
+        }
+        scopes.push();
+        for (param, arg) in func.params.iter().zip(args) {
+            scopes.define(param, arg);
         }
+        let caller = std::mem::replace(&mut self.scopes, scopes);
+        let result = self.eval_expr(&func.body);
+        self.scopes = caller;
+        result
+    }
+
+    /// Fail if a function value ended up in the output, pointing at the key
+    /// that holds it
+    fn reject_functions(&self, value: &Value) -> HoneResult<()> {
+        let Some(path) = value.function_path() else {
+            return Ok(());
+        };
+        let top = path.split('.').next().unwrap_or_default();
+        let span = self
+            .location_map
+            .get(&path)
+            .or_else(|| self.location_map.get(top))
+            .map_or((0, 0), |loc| (loc.offset, loc.length));
+        Err(HoneError::TypeMismatch {
+            src: self.source.clone(),
+            span: span.into(),
+            expected: "a value that can be written to output".to_string(),
+            found: "function".to_string(),
+            help: format!(
+                "'{}' holds a function; call it to produce a value, or bind it with let",
+                path
+            ),
+        })
     }
 
     /// Evaluate a string expression (with potential interpolation)
@@ -660,6 +1803,26 @@ impl Evaluator {
                     let value = self.eval_expr(e)?;
                     result.push_str(&value.to_string());
                 }
+                StringPart::Formatted(e, spec) => {
+                    let value = self.eval_expr(e)?;
+                    let formatted = match &value {
+                        Value::Int(n) => spec.format_int(*n),
+                        Value::Float(n) => spec.format_float(*n),
+                        Value::String(s) => spec.format_value(s, true),
+                        _ => spec.format_value(&value.to_string(), false),
+                    };
+                    let formatted = formatted.map_err(|needs| {
+                        let loc = e.location();
+                        HoneError::TypeMismatch {
+                            src: self.source.clone(),
+                            span: (loc.offset, loc.length).into(),
+                            expected: needs.to_string(),
+                            found: value.type_name().to_string(),
+                            help: format!("the format spec ':{}' applies to {}s", spec, needs),
+                        }
+                    })?;
+                    result.push_str(&formatted);
+                }
             }
         }
 
@@ -670,6 +1833,14 @@ impl Evaluator {
     fn eval_ident(&self, name: &str, loc: &SourceLocation) -> HoneResult<Value> {
         if let Some(value) = self.scopes.get(name) {
             Ok(value.clone())
+        } else if let Some(user_fn) = self.user_functions.get(name) {
+            // A named fn used as a value, e.g. passed to another function
+            Ok(self.make_function(&user_fn.params, &user_fn.body))
+        } else if name == SELF {
+            match self.self_frames.last() {
+                Some(frame) => Ok(Value::Object(frame.object.clone())),
+                None => Err(self.self_outside_object(loc)),
+            }
         } else {
             let available = self.scopes.available_names();
             let help = crate::errors::undefined_variable_help(name, &available);
@@ -708,11 +1879,17 @@ impl Evaluator {
             }
         };
 
-        // Look up the base value
-        let mut current = self.eval_ident(first, &path.location)?;
+        // Look up the base value; `self.key` is looked up (or bound late) as
+        // a whole so keys that are not assigned yet can be evaluated early
+        let (mut current, skip) = match path.parts.get(1) {
+            Some(PathPart::Ident(key)) if first == SELF && self.scopes.get(SELF).is_none() => {
+                (self.eval_self(key, &path.location)?, 2)
+            }
+            _ => (self.eval_ident(first, &path.location)?, 1),
+        };
 
         // Navigate through remaining parts
-        for (_i, part) in path.parts.iter().enumerate().skip(1) {
+        for (_i, part) in path.parts.iter().enumerate().skip(skip) {
             match part {
                 PathPart::Ident(name) => {
                     current = match current {
@@ -806,17 +1983,33 @@ impl Evaluator {
     fn eval_object(&mut self, obj: &ObjectExpr) -> HoneResult<Value> {
         self.scopes.push();
         let mut result = IndexMap::new();
-
-        for item in &obj.items {
-            self.eval_body_item(item, &mut result)?;
-        }
-
+        self.eval_items(&obj.items, &mut result, false)?;
         self.scopes.pop();
         Ok(Value::Object(result))
     }
 
     /// Evaluate a for loop in array context
     fn eval_for_in_array(&mut self, for_loop: &ForLoop) -> HoneResult<Vec<Value>> {
+        let mut result = Vec::new();
+        self.each_for_item(for_loop, &mut |item| {
+            result.push(item);
+            Ok(())
+        })?;
+        if let Some(sizes) = self.value_sizes.as_mut() {
+            let bytes = 2
+                + result.len().saturating_sub(1)
+                + result.iter().map(Value::approx_size).sum::<usize>();
+            sizes.record(bytes, &for_loop.location, || "`for` loop".to_string());
+        }
+        Ok(result)
+    }
+
+    /// Evaluate a for loop, passing each produced element to `sink` in order
+    fn each_for_item(
+        &mut self,
+        for_loop: &ForLoop,
+        sink: &mut dyn FnMut(Value) -> HoneResult<()>,
+    ) -> HoneResult<()> {
         let iterable = self.eval_expr(&for_loop.iterable)?;
         let items = match iterable {
             Value::Array(arr) => arr.into_iter().enumerate().collect(),
@@ -841,7 +2034,11 @@ impl Evaluator {
             }
         };
 
-        let mut result = Vec::new();
+        // `for (a, b) in zip(xs, ys)` destructures the zipped pairs
+        let zipped = matches!(
+            &for_loop.iterable,
+            Expr::Call(call) if matches!(call.func.as_ref(), Expr::Ident(name, _) if name == "zip")
+        );
 
         for (idx, item) in items {
             self.scopes.push();
@@ -851,6 +2048,18 @@ impl Evaluator {
                 ForBinding::Single(name) => {
                     self.scopes.define(name, item);
                 }
+                ForBinding::Indexed(name, index) => {
+                    self.scopes.define(name, item);
+                    self.scopes.define(index, Value::Int(idx as i64));
+                }
+                ForBinding::Pair(a, b) if zipped => {
+                    let mut parts = match item {
+                        Value::Array(parts) => parts.into_iter(),
+                        _ => Vec::new().into_iter(),
+                    };
+                    self.scopes.define(a, parts.next().unwrap_or(Value::Null));
+                    self.scopes.define(b, parts.next().unwrap_or(Value::Null));
+                }
                 ForBinding::Pair(k, v) => {
                     if let Value::Object(obj) = item {
                         // Object iteration: pair binding gives (key, value)
@@ -866,31 +2075,39 @@ impl Evaluator {
                 }
             }
 
+            if let Some(filter) = &for_loop.filter {
+                if !self.eval_expr(filter)?.is_truthy() {
+                    self.scopes.pop();
+                    continue;
+                }
+            }
+
             // Evaluate body
             match &for_loop.body {
                 ForBody::Expr(e) => {
-                    result.push(self.eval_expr(e)?);
+                    sink(self.eval_expr(e)?)?;
                 }
                 ForBody::Object(items) => {
                     let mut obj = IndexMap::new();
-                    for item in items {
-                        self.eval_body_item(item, &mut obj)?;
-                    }
-                    result.push(Value::Object(obj));
+                    self.eval_items(items, &mut obj, false)?;
+                    sink(Value::Object(obj))?;
                 }
                 ForBody::Block(items, expr) => {
                     let mut obj = IndexMap::new();
-                    for item in items {
-                        self.eval_body_item(item, &mut obj)?;
-                    }
-                    result.push(self.eval_expr(expr)?);
+                    self.computed_keys.push(HashMap::new());
+                    let result = items
+                        .iter()
+                        .try_for_each(|item| self.eval_body_item(item, &mut obj));
+                    self.computed_keys.pop();
+                    result?;
+                    sink(self.eval_expr(expr)?)?;
                 }
             }
 
             self.scopes.pop();
         }
 
-        Ok(result)
+        Ok(())
     }
 
     /// Evaluate a for expression
@@ -901,6 +2118,13 @@ impl Evaluator {
 
     /// Evaluate a binary expression
     fn eval_binary(&mut self, bin: &BinaryExpr) -> HoneResult<Value> {
+        // `expr |> try(fallback)` attempts the left side itself
+        if let (BinaryOp::Pipe, Expr::Call(call)) = (&bin.op, bin.right.as_ref()) {
+            if self.is_try_call(call) {
+                return self.eval_try(call, Some(&bin.left));
+            }
+        }
+
         let left = self.eval_expr(&bin.left)?;
 
         // Short-circuit evaluation for && and ||
@@ -925,6 +2149,16 @@ impl Evaluator {
                 }
                 return self.eval_expr(&bin.right);
             }
+            BinaryOp::Pipe => {
+                let Expr::Call(call) = bin.right.as_ref() else {
+                    unreachable!("the parser only accepts a call after |>")
+                };
+                let value = self.eval_call(call, Some(left))?;
+                self.note_size(&value, &call.location, || {
+                    format!("{}()", call.func.display())
+                });
+                return Ok(value);
+            }
             _ => {}
         }
 
@@ -942,7 +2176,7 @@ impl Evaluator {
             BinaryOp::Gt => self.eval_comparison(&left, &right, |a, b| a > b, &bin.location),
             BinaryOp::LtEq => self.eval_comparison(&left, &right, |a, b| a <= b, &bin.location),
             BinaryOp::GtEq => self.eval_comparison(&left, &right, |a, b| a >= b, &bin.location),
-            BinaryOp::And | BinaryOp::Or | BinaryOp::NullCoalesce => {
+            BinaryOp::And | BinaryOp::Or | BinaryOp::NullCoalesce | BinaryOp::Pipe => {
                 unreachable!("handled above")
             }
         }
@@ -978,7 +2212,75 @@ impl Evaluator {
         }
     }
 
+    /// Duration and size arithmetic: durations add and subtract, and scale
+    /// by ints, and so do sizes. Returns None when neither operand is one.
+    fn eval_quantity(
+        &self,
+        left: &Value,
+        right: &Value,
+        loc: &SourceLocation,
+        op: BinaryOp,
+    ) -> HoneResult<Option<Value>> {
+        let Some(kind) = [left, right].into_iter().find_map(quantity_kind) else {
+            return Ok(None);
+        };
+        let (make, help, overflow): (fn(i64) -> Value, _, _) = match kind {
+            "duration" => (
+                Value::Duration,
+                "use to_seconds() or to_millis() to do other math on a duration",
+                "duration overflow: result exceeds i64 milliseconds",
+            ),
+            _ => (
+                Value::Size,
+                "use to_bytes() or to_mebibytes() to do other math on a size",
+                "size overflow: result exceeds i64 bytes",
+            ),
+        };
+        let same = |v: &Value| quantity_kind(v) == Some(kind);
+        let amount = |v: &Value| match v {
+            Value::Duration(n) | Value::Size(n) | Value::Int(n) => *n,
+            _ => 0,
+        };
+        let (op_sym, result) = match op {
+            BinaryOp::Add if same(left) && same(right) => {
+                ("+", amount(left).checked_add(amount(right)))
+            }
+            BinaryOp::Sub if same(left) && same(right) => {
+                ("-", amount(left).checked_sub(amount(right)))
+            }
+            BinaryOp::Mul
+                if same(left) && matches!(right, Value::Int(_))
+                    || matches!(left, Value::Int(_)) && same(right) =>
+            {
+                ("*", amount(left).checked_mul(amount(right)))
+            }
+            BinaryOp::Div if same(left) && matches!(right, Value::Int(_)) => {
+                ("/", amount(left).checked_div(amount(right)))
+            }
+            _ => {
+                return Err(HoneError::TypeMismatch {
+                    src: self.source.clone(),
+                    span: (loc.offset, loc.length).into(),
+                    expected: format!("{0} +/- {0}, or {0} * and / int", kind),
+                    found: format!("{} {} {}", left.type_name(), op, right.type_name()),
+                    help: help.to_string(),
+                })
+            }
+        };
+        result
+            .map(|n| Some(make(n)))
+            .ok_or_else(|| HoneError::ArithmeticOverflow {
+                src: self.source.clone(),
+                span: (loc.offset, loc.length).into(),
+                operation: format!("{} {} {}", left, op_sym, right),
+                help: overflow.to_string(),
+            })
+    }
+
     fn eval_add(&self, left: &Value, right: &Value, loc: &SourceLocation) -> HoneResult<Value> {
+        if let Some(result) = self.eval_quantity(left, right, loc, BinaryOp::Add)? {
+            return Ok(result);
+        }
         if let Some(result) =
             self.eval_numeric(left, right, loc, "+", i64::checked_add, |a, b| a + b)?
         {
@@ -1002,6 +2304,9 @@ impl Evaluator {
     }
 
     fn eval_sub(&self, left: &Value, right: &Value, loc: &SourceLocation) -> HoneResult<Value> {
+        if let Some(result) = self.eval_quantity(left, right, loc, BinaryOp::Sub)? {
+            return Ok(result);
+        }
         self.eval_numeric(left, right, loc, "-", i64::checked_sub, |a, b| a - b)?
             .ok_or_else(|| HoneError::TypeMismatch {
                 src: self.source.clone(),
@@ -1013,6 +2318,9 @@ impl Evaluator {
     }
 
     fn eval_mul(&self, left: &Value, right: &Value, loc: &SourceLocation) -> HoneResult<Value> {
+        if let Some(result) = self.eval_quantity(left, right, loc, BinaryOp::Mul)? {
+            return Ok(result);
+        }
         self.eval_numeric(left, right, loc, "*", i64::checked_mul, |a, b| a * b)?
             .ok_or_else(|| HoneError::TypeMismatch {
                 src: self.source.clone(),
@@ -1036,6 +2344,9 @@ impl Evaluator {
                 span: (loc.offset, loc.length).into(),
             });
         }
+        if let Some(result) = self.eval_quantity(left, right, loc, BinaryOp::Div)? {
+            return Ok(result);
+        }
 
         self.eval_numeric(left, right, loc, "/", i64::checked_div, |a, b| a / b)?
             .ok_or_else(|| HoneError::TypeMismatch {
@@ -1096,6 +2407,9 @@ impl Evaluator {
                     (Value::String(a), Value::String(b)) => {
                         Ok(Value::Bool(op(a.cmp(b) as i32 as f64, 0.0)))
                     }
+                    (Value::Duration(a), Value::Duration(b)) | (Value::Size(a), Value::Size(b)) => {
+                        Ok(Value::Bool(op(a.cmp(b) as i32 as f64, 0.0)))
+                    }
                     _ => Err(HoneError::TypeMismatch {
                         src: self.source.clone(),
                         span: (loc.offset, loc.length).into(),
@@ -1125,6 +2439,22 @@ impl Evaluator {
                         }
                     }),
                     Value::Float(n) => Ok(Value::Float(-n)),
+                    Value::Duration(ms) => ms.checked_neg().map(Value::Duration).ok_or_else(|| {
+                        HoneError::ArithmeticOverflow {
+                            src: self.source.clone(),
+                            span: (unary.location.offset, unary.location.length).into(),
+                            operation: format!("-({})", operand),
+                            help: "duration overflow: result exceeds i64 milliseconds".to_string(),
+                        }
+                    }),
+                    Value::Size(bytes) => bytes.checked_neg().map(Value::Size).ok_or_else(|| {
+                        HoneError::ArithmeticOverflow {
+                            src: self.source.clone(),
+                            span: (unary.location.offset, unary.location.length).into(),
+                            operation: format!("-({})", operand),
+                            help: "size overflow: result exceeds i64 bytes".to_string(),
+                        }
+                    }),
                     _ => Err(HoneError::TypeMismatch {
                         src: self.source.clone(),
                         span: (unary.location.offset, unary.location.length).into(),
@@ -1138,52 +2468,32 @@ impl Evaluator {
     }
 
     /// Evaluate a function call
-    fn eval_call(&mut self, call: &CallExpr) -> HoneResult<Value> {
-        // Get the function name
+    /// Evaluate a call. `piped` is the value a `|>` feeds in as the first
+    /// argument.
+    fn eval_call(&mut self, call: &CallExpr, piped: Option<Value>) -> HoneResult<Value> {
+        // Get the function name; any other callee must evaluate to a function
         let func_name = match &*call.func {
             Expr::Ident(name, _) => name.clone(),
-            Expr::Path(path) => {
-                // For now, only support simple function names
-                // Method calls (obj.method()) would need more work
-                if path.parts.len() == 1 {
-                    if let PathPart::Ident(name) = &path.parts[0] {
-                        name.clone()
-                    } else {
-                        return Err(HoneError::TypeMismatch {
-                            src: self.source.clone(),
-                            span: (call.location.offset, call.location.length).into(),
-                            expected: "function name".to_string(),
-                            found: "path expression".to_string(),
-                            help: "only simple function names are supported".to_string(),
-                        });
-                    }
-                } else {
-                    return Err(HoneError::TypeMismatch {
-                        src: self.source.clone(),
-                        span: (call.location.offset, call.location.length).into(),
-                        expected: "function name".to_string(),
-                        found: "path expression".to_string(),
-                        help: "method calls are not supported".to_string(),
-                    });
-                }
-            }
-            _ => {
-                return Err(HoneError::TypeMismatch {
-                    src: self.source.clone(),
-                    span: (call.location.offset, call.location.length).into(),
-                    expected: "function name".to_string(),
-                    found: "expression".to_string(),
-                    help: "only named function calls are supported".to_string(),
-                });
-            }
+            Expr::Path(path) if path.parts.len() == 1 => match &path.parts[0] {
+                PathPart::Ident(name) => name.clone(),
+                PathPart::Index(_) => return self.eval_callee_call(call, piped),
+            },
+            _ => return self.eval_callee_call(call, piped),
         };
 
-        // Evaluate arguments
-        let args: Vec<Value> = call
-            .args
-            .iter()
-            .map(|a| self.eval_expr(a))
-            .collect::<HoneResult<_>>()?;
+        // A let or parameter holding a function shadows fns and builtins
+        if let Some(Value::Function(func)) = self.scopes.get(&func_name).cloned() {
+            let args = self.eval_args(call, piped)?;
+            return self.call_function(&func, args, &call.location);
+        }
+
+        // `try` evaluates its arguments itself; a piped `try` is handled
+        // where the pipe is, so the left side is attempted too
+        if piped.is_none() && self.is_try_call(call) {
+            return self.eval_try(call, None);
+        }
+
+        let args = self.eval_args(call, piped)?;
 
         // Check user-defined functions first
         if let Some(user_fn) = self.user_functions.get(&func_name).cloned() {
@@ -1213,12 +2523,48 @@ impl Evaluator {
             return result;
         }
 
-        // Gate env/file behind --allow-env
-        if !self.allow_env && (func_name == "env" || func_name == "file") {
-            let help = if func_name == "env" {
-                "env() reads environment variables, making output non-deterministic\n  = in CI/CD, prefer: --set key=\"$VALUE\"\n  = for local development: hone compile --allow-env <file>".to_string()
-            } else {
-                "file() reads external files, making output non-deterministic\n  = for local development: hone compile --allow-env <file>".to_string()
+        if builtins::is_higher_order(&func_name) {
+            let source = self.source.clone();
+            let location = call.location.clone();
+            return builtins::call_higher_order(
+                &func_name,
+                args,
+                &mut |func, args| self.call_function(func, args, &location),
+                &call.location,
+                &source,
+            );
+        }
+
+        if budget::is_budget_helper(&func_name) {
+            let Some((policy, format)) = self.policy.clone() else {
+                return Err(HoneError::undefined_variable(
+                    self.source.clone(),
+                    &call.location,
+                    &func_name,
+                    format!(
+                        "{}() measures the compiled output, so it can only be used in a policy condition",
+                        func_name
+                    ),
+                ));
+            };
+            self.budget_policies.insert(policy);
+            let output = self.scopes.get("output").unwrap_or(&Value::Null);
+            return budget::call_budget_helper(
+                &func_name,
+                args,
+                output,
+                format,
+                &call.location,
+                &self.source,
+            );
+        }
+
+        // Gate env/file/now behind --allow-env
+        if !self.allow_env && matches!(func_name.as_str(), "env" | "file" | "now") {
+            let help = match func_name.as_str() {
+                "env" => "env() reads environment variables, making output non-deterministic\n  = in CI/CD, prefer: --set key=\"$VALUE\"\n  = for local development: hone compile --allow-env <file>".to_string(),
+                "file" => "file() reads external files, making output non-deterministic\n  = for local development: hone compile --allow-env <file>".to_string(),
+                _ => "now() reads the clock, making output non-deterministic\n  = for reproducible builds, pass the time in: --set built_at=\"$(date +%s)\"\n  = to embed the build time: hone compile --allow-env <file>".to_string(),
             };
             return Err(HoneError::EnvNotAllowed {
                 src: self.source.clone(),
@@ -1228,10 +2574,109 @@ impl Evaluator {
             });
         }
 
+        if func_name == "now" {
+            self.external_inputs.push(ExternalInput {
+                kind: ExternalInputKind::Clock,
+                name: "now()".to_string(),
+                location: call.location.clone(),
+            });
+        }
+        if let Some(Value::String(name)) = args.first() {
+            let kind = match func_name.as_str() {
+                "env" => Some(ExternalInputKind::Env),
+                "file" => Some(ExternalInputKind::File),
+                _ => None,
+            };
+            if let Some(kind) = kind {
+                self.external_inputs.push(ExternalInput {
+                    kind,
+                    name: name.clone(),
+                    location: call.location.clone(),
+                });
+            }
+        }
+
         // Call built-in function
         builtins::call_builtin(&func_name, args, &call.location, &self.source)
     }
 
+    /// Whether `call` is `try(...)`, which no `let`, parameter or `fn`
+    /// named `try` shadows
+    fn is_try_call(&self, call: &CallExpr) -> bool {
+        let name = match call.func.as_ref() {
+            Expr::Ident(name, _) => name,
+            Expr::Path(path) => match path.parts.as_slice() {
+                [PathPart::Ident(name)] => name,
+                _ => return false,
+            },
+            _ => return false,
+        };
+        name == "try"
+            && !matches!(self.scopes.get(name), Some(Value::Function(_)))
+            && !self.user_functions.contains_key(name)
+    }
+
+    /// `try(expr, fallback)`: the value of `expr`, or of `fallback` when
+    /// evaluating `expr` fails on a bad value. `piped` is the left side of
+    /// `expr |> try(fallback)`, which is attempted in place of `expr`.
+    fn eval_try(&mut self, call: &CallExpr, piped: Option<&Expr>) -> HoneResult<Value> {
+        let args: Vec<&Expr> = piped.into_iter().chain(&call.args).collect();
+        let [attempt, fallback] = args[..] else {
+            return Err(builtins::arity_error(
+                "try",
+                "2",
+                args.len(),
+                &call.location,
+                &self.source,
+            ));
+        };
+
+        // A failure unwinds past the pops of whatever it interrupted
+        let scopes = self.scopes.depth();
+        let (path, depth) = (self.current_path.len(), self.depth);
+        let (self_frames, computed_keys) = (self.self_frames.len(), self.computed_keys.len());
+        let in_output = self.in_output;
+        match self.eval_expr(attempt) {
+            Err(e) if e.is_recoverable() => {
+                self.scopes.truncate(scopes);
+                self.current_path.truncate(path);
+                self.depth = depth;
+                self.self_frames.truncate(self_frames);
+                self.computed_keys.truncate(computed_keys);
+                self.in_output = in_output;
+                self.eval_expr(fallback)
+            }
+            result => result,
+        }
+    }
+
+    /// Evaluate the arguments of a call, after the piped value if any
+    fn eval_args(&mut self, call: &CallExpr, piped: Option<Value>) -> HoneResult<Vec<Value>> {
+        let mut args = Vec::with_capacity(call.args.len() + 1);
+        args.extend(piped);
+        for arg in &call.args {
+            args.push(self.eval_expr(arg)?);
+        }
+        Ok(args)
+    }
+
+    /// Call whatever the callee expression evaluates to, e.g. `lib.double(2)`
+    /// or `((x) => x + 1)(2)`
+    fn eval_callee_call(&mut self, call: &CallExpr, piped: Option<Value>) -> HoneResult<Value> {
+        let callee = self.eval_expr(&call.func)?;
+        let Value::Function(func) = callee else {
+            return Err(HoneError::TypeMismatch {
+                src: self.source.clone(),
+                span: (call.location.offset, call.location.length).into(),
+                expected: "function".to_string(),
+                found: callee.type_name().to_string(),
+                help: "only functions can be called".to_string(),
+            });
+        };
+        let args = self.eval_args(call, piped)?;
+        self.call_function(&func, args, &call.location)
+    }
+
     /// Evaluate an index expression
     fn eval_index(&mut self, idx: &IndexExpr) -> HoneResult<Value> {
         let base = self.eval_expr(&idx.base)?;
@@ -1276,6 +2721,36 @@ impl Evaluator {
         }
     }
 
+    /// Evaluate the first arm with a pattern equal to the subject; patterns
+    /// after it are never evaluated
+    fn eval_match(&mut self, m: &MatchExpr) -> HoneResult<Value> {
+        let subject = self.eval_expr(&m.subject)?;
+        for arm in &m.arms {
+            if arm.is_wildcard() {
+                return self.eval_expr(&arm.value);
+            }
+            for pattern in &arm.patterns {
+                if self.eval_expr(pattern)?.equals(&subject) {
+                    return self.eval_expr(&arm.value);
+                }
+            }
+        }
+
+        let value = match &subject {
+            Value::String(s) => format!("\"{}\"", s),
+            other => other.to_string(),
+        };
+        Err(HoneError::NoMatchArm {
+            src: self.source.clone(),
+            span: (m.subject.location().offset, m.subject.location().length).into(),
+            help: format!(
+                "add an arm for {}, or end with `_ => ...` to cover every other value",
+                value
+            ),
+            value,
+        })
+    }
+
     /// Define a variable in the current scope (for external use)
     pub fn define(&mut self, name: impl Into<String>, value: Value) {
         self.scopes.define(name, value);
@@ -1308,20 +2783,16 @@ impl Evaluator {
         when: &WhenBlock,
         target: &mut IndexMap<String, Value>,
     ) -> HoneResult<()> {
-        let condition = self.eval_expr(&when.condition)?;
+        let condition = self.detached(|e| e.eval_expr(&when.condition))?;
         if condition.is_truthy() {
-            for item in &when.body {
-                self.eval_body_item(item, target)?;
-            }
+            self.eval_items(&when.body, target, true)?;
         } else if let Some(ref else_branch) = when.else_branch {
             match else_branch {
                 ElseBranch::ElseWhen(else_when) => {
                     self.eval_when_body(else_when, target)?;
                 }
                 ElseBranch::Else(else_body, _) => {
-                    for item in else_body {
-                        self.eval_body_item(item, target)?;
-                    }
+                    self.eval_items(else_body, target, true)?;
                 }
             }
         }
@@ -1333,18 +2804,14 @@ impl Evaluator {
         let condition = self.eval_expr(&when.condition)?;
         if condition.is_truthy() {
             let mut obj = IndexMap::new();
-            for item in &when.body {
-                self.eval_body_item(item, &mut obj)?;
-            }
+            self.eval_items(&when.body, &mut obj, false)?;
             Ok(Value::Object(obj))
         } else if let Some(ref else_branch) = when.else_branch {
             match else_branch {
                 ElseBranch::ElseWhen(else_when) => self.eval_when_expr(else_when),
                 ElseBranch::Else(else_body, _) => {
                     let mut obj = IndexMap::new();
-                    for item in else_body {
-                        self.eval_body_item(item, &mut obj)?;
-                    }
+                    self.eval_items(else_body, &mut obj, false)?;
                     Ok(Value::Object(obj))
                 }
             }
@@ -1400,13 +2867,47 @@ impl Evaluator {
             Value::Bool(b) => b.to_string(),
             Value::Int(n) => n.to_string(),
             Value::Float(f) => f.to_string(),
+            Value::Duration(_) | Value::Size(_) => value.to_string(),
             Value::String(s) => format!("\"{}\"", s),
             Value::Array(arr) => format!("[...] (length {})", arr.len()),
             Value::Object(obj) => format!("{{...}} ({} keys)", obj.len()),
+            Value::Function(_) => value.to_string(),
         })
     }
 }
 
+/// "duration" or "size" for a value of that kind
+fn quantity_kind(value: &Value) -> Option<&'static str> {
+    match value {
+        Value::Duration(_) => Some("duration"),
+        Value::Size(_) => Some("size"),
+        _ => None,
+    }
+}
+
+/// Convert a provided arg to the type an `expect` declares, or `None` when it
+/// cannot be. `--set` infers numbers only when they print back unchanged, so
+/// turning them into strings recovers the text that was passed.
+fn coerce_arg(value: &Value, type_name: &str) -> Option<Value> {
+    match (type_name, value) {
+        ("string", Value::String(_)) => Some(value.clone()),
+        ("string", Value::Int(_) | Value::Float(_) | Value::Bool(_) | Value::Null) => {
+            Some(Value::String(value.to_string()))
+        }
+        ("int", Value::Int(_)) => Some(value.clone()),
+        ("int", Value::String(s)) => s.parse().ok().map(Value::Int),
+        ("float", Value::Float(_) | Value::Int(_)) => Some(value.clone()),
+        ("float", Value::String(s)) => s.parse().ok().map(Value::Float),
+        ("bool", Value::Bool(_)) => Some(value.clone()),
+        ("duration", Value::Duration(_)) => Some(value.clone()),
+        ("duration", Value::String(s)) => crate::lexer::duration::parse(s).map(Value::Duration),
+        ("size", Value::Size(_)) => Some(value.clone()),
+        ("size", Value::String(s)) => crate::lexer::size::parse(s).map(Value::Size),
+        ("string" | "int" | "float" | "bool" | "duration" | "size", _) => None,
+        _ => Some(value.clone()), // any and unknown types pass through
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1458,6 +2959,69 @@ mod tests {
         assert_eq!(result.get_path(&["val"]), Some(&Value::Int(1)));
     }
 
+    #[test]
+    fn test_match_expr() {
+        let source = "let env = \"qa\"\nlet pick = (e) => match e {\n  \"prod\" => 5\n  \"staging\", \"qa\" => 2\n  _ => 1\n}\na: pick(env)\nb: pick(\"prod\")\nc: pick(\"dev\")\nd: match 2 { 1 => \"one\", 1 + 1 => \"two\" }\n";
+        let result = eval(source).unwrap();
+        assert_eq!(result.get_path(&["a"]), Some(&Value::Int(2)));
+        assert_eq!(result.get_path(&["b"]), Some(&Value::Int(5)));
+        assert_eq!(result.get_path(&["c"]), Some(&Value::Int(1)));
+        assert_eq!(result.get_path(&["d"]), Some(&Value::String("two".into())));
+    }
+
+    #[test]
+    fn test_block_spread_keeps_operators() {
+        let source = "let port = 8080
+let probes = block {
+  liveness { path: \"/healthz\"\n    port: port }
+  ports +: [port]
+  mode !: { tcp: true }
+}
+let port = 1
+web {
+  liveness { delay: 5 }
+  ports: [80]
+  mode { http: true }
+  ...probes
+}
+";
+        let result = eval(source).unwrap();
+        assert_eq!(
+            result.get_path(&["web", "liveness", "delay"]),
+            Some(&Value::Int(5))
+        );
+        assert_eq!(
+            result.get_path(&["web", "liveness", "port"]),
+            Some(&Value::Int(8080))
+        );
+        assert_eq!(
+            result.get_path(&["web", "ports"]),
+            Some(&Value::Array(vec![Value::Int(80), Value::Int(8080)]))
+        );
+        assert_eq!(result.get_path(&["web", "mode", "http"]), None);
+        assert_eq!(
+            result.get_path(&["web", "mode", "tcp"]),
+            Some(&Value::Bool(true))
+        );
+    }
+
+    #[test]
+    fn test_block_is_not_a_value() {
+        let err = eval("let b = block { a: 1 }\nx: b()").unwrap_err();
+        assert!(matches!(err, HoneError::TypeMismatch { ref found, .. } if found == "block"));
+        assert!(eval("let b = block { a: 1 }\nx: b").is_err());
+        assert!(eval("let b = block { a: 1 }\nx: [...b]").is_err());
+    }
+
+    #[test]
+    fn test_match_without_matching_arm() {
+        let err = eval("let env = \"dev\"\nx: match env { \"prod\" => 1 }").unwrap_err();
+        assert!(matches!(err, HoneError::NoMatchArm { ref value, .. } if value == "\"dev\""));
+
+        let result = eval("x: try(match 3 { 1 => 1 }, 0)").unwrap();
+        assert_eq!(result.get_path(&["x"]), Some(&Value::Int(0)));
+    }
+
     #[test]
     fn test_when_block() {
         let result = eval("let env = \"prod\"\nwhen env == \"prod\" { replicas: 3 }").unwrap();
@@ -1607,6 +3171,101 @@ mod tests {
         assert_eq!(result.get_path(&["x"]), Some(&Value::Int(42)));
     }
 
+    #[test]
+    fn test_let_destructuring() {
+        let result = eval(
+            "let server = { host: \"db\", port: 5432 }\n\
+             let { host, port, user } = server\n\
+             let [first, second, ...rest] = [1, 2, 3, 4]\n\
+             url: \"${host}:${port}\"\n\
+             user: user\n\
+             inner {\n\
+               let [only] = [first]\n\
+               value: only + second\n\
+             }\n\
+             rest: rest",
+        )
+        .unwrap();
+        assert_eq!(
+            result.get_path(&["url"]),
+            Some(&Value::String("db:5432".into()))
+        );
+        assert_eq!(result.get_path(&["user"]), Some(&Value::Null));
+        assert_eq!(result.get_path(&["inner", "value"]), Some(&Value::Int(3)));
+        assert_eq!(
+            result.get_path(&["rest"]),
+            Some(&Value::Array(vec![Value::Int(3), Value::Int(4)]))
+        );
+    }
+
+    #[test]
+    fn test_let_destructuring_mismatch() {
+        let err = eval("let { a } = [1]\nx: a").unwrap_err();
+        assert!(
+            matches!(err, HoneError::TypeMismatch { ref expected, .. } if expected == "object")
+        );
+        let err = eval("let [a, b] = [1]\nx: a").unwrap_err();
+        assert!(matches!(err, HoneError::TypeMismatch { ref found, .. } if found == "1 element"));
+    }
+
+    #[test]
+    fn test_try_falls_back_on_bad_values() {
+        let result = eval(
+            "let o = { a: [1, 2] }\n\
+             missing: try(o.x.y, \"none\")\n\
+             index: try(o.a[5], 0)\n\
+             parse: try(to_int(\"abc\"), -1)\n\
+             divide: try(1 / 0, 0)\n\
+             ok: try(o.a[1], 0)\n\
+             piped: o.a[9] + 1 |> try(7)\n\
+             looped: [for i in [0, 3] { try(o.a[i], null) }]\n\
+             after: [for x in [1] { x }]",
+        )
+        .unwrap();
+        assert_eq!(
+            result.get_path(&["missing"]),
+            Some(&Value::String("none".into()))
+        );
+        assert_eq!(result.get_path(&["index"]), Some(&Value::Int(0)));
+        assert_eq!(result.get_path(&["parse"]), Some(&Value::Int(-1)));
+        assert_eq!(result.get_path(&["divide"]), Some(&Value::Int(0)));
+        assert_eq!(result.get_path(&["ok"]), Some(&Value::Int(2)));
+        assert_eq!(result.get_path(&["piped"]), Some(&Value::Int(7)));
+        assert_eq!(
+            result.get_path(&["looped"]),
+            Some(&Value::Array(vec![Value::Int(1), Value::Null]))
+        );
+        assert_eq!(
+            result.get_path(&["after"]),
+            Some(&Value::Array(vec![Value::Int(1)]))
+        );
+    }
+
+    #[test]
+    fn test_try_keeps_source_errors() {
+        // An undefined variable is a mistake, not a bad value
+        let err = eval("x: try(missing, 1)").unwrap_err();
+        assert!(
+            matches!(err, HoneError::UndefinedVariable { .. }),
+            "{:?}",
+            err
+        );
+        // The fallback is only evaluated when needed
+        assert_eq!(
+            eval("x: try(1, missing)").unwrap().get_path(&["x"]),
+            Some(&Value::Int(1))
+        );
+        let err = eval("x: try(1)").unwrap_err();
+        assert!(matches!(err, HoneError::TypeMismatch { .. }), "{:?}", err);
+        // A `fn` named try shadows the builtin
+        assert_eq!(
+            eval("fn try(a, b) { a + b }\nx: try(1, 2)")
+                .unwrap()
+                .get_path(&["x"]),
+            Some(&Value::Int(3))
+        );
+    }
+
     #[test]
     fn test_spread_in_array() {
         let result = eval("let a = [1, 2]\narr: [...a, 3]").unwrap();
@@ -1886,6 +3545,36 @@ config {
         assert_eq!(locations["a.b.c"].line, 3);
     }
 
+    // ── Scope snapshot tests ─────────────────────────────────────────────
+
+    #[test]
+    fn test_snapshots_record_scope_per_body_item() {
+        let source = "let env = \"prod\"\nserver {\n  let port = 8080\n  url: \"http://x:${port}\"\n}\nitems: [for i in [1, 2] { i }]\n";
+        let mut lexer = Lexer::new(source, None);
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens, source, None);
+        let ast = parser.parse().unwrap();
+        let mut evaluator = Evaluator::new(source);
+        evaluator.enable_snapshots();
+        evaluator.evaluate(&ast).unwrap();
+        let snapshots = evaluator.take_snapshots();
+
+        let offset = offset_at(source, 4, 5).unwrap();
+        let found = snapshots_at(&snapshots, offset);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, "server");
+        assert_eq!(found[0].key_path().as_deref(), Some("server.url"));
+        assert_eq!(found[0].depth, 2);
+        let names: Vec<&str> = found[0].bindings.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["env", "port"]);
+        assert_eq!(found[0].bindings[1].1, Value::Int(8080));
+
+        // Not recorded unless enabled
+        let mut evaluator = Evaluator::new(source);
+        evaluator.evaluate(&ast).unwrap();
+        assert!(evaluator.take_snapshots().is_empty());
+    }
+
     // --- Group 1: env/file allow_env and recursion tests ---
 
     fn eval_with_env(source: &str) -> HoneResult<Value> {
@@ -1914,6 +3603,184 @@ config {
         assert!(matches!(err, HoneError::EnvNotAllowed { .. }));
     }
 
+    #[test]
+    fn test_env_and_file_reads_are_recorded() {
+        let source = "a: env(\"PATH\")\nb: env(\"HONE_SURELY_UNSET\", \"x\")\nc: upper(\"y\")\n";
+        let mut lexer = Lexer::new(source, None);
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens, source, None);
+        let ast = parser.parse().unwrap();
+        let mut evaluator = Evaluator::new(source);
+        evaluator.set_allow_env(true);
+        evaluator.evaluate(&ast).unwrap();
+
+        let inputs = evaluator.take_external_inputs();
+        let names: Vec<_> = inputs.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["PATH", "HONE_SURELY_UNSET"]);
+        assert_eq!(inputs[1].kind, ExternalInputKind::Env);
+        assert_eq!(inputs[1].location.line, 2);
+    }
+
+    #[test]
+    fn test_now_requires_allow_env() {
+        let err = eval("built: now()").unwrap_err();
+        assert!(
+            matches!(err, HoneError::EnvNotAllowed { ref func_name, .. } if func_name == "now")
+        );
+
+        // Formatting and parsing are deterministic, so they need no flag
+        let result = eval(
+            "day: format_timestamp(86400, \"%F\")\nts: parse_timestamp(\"1970-01-02T00:00:00Z\")\n",
+        )
+        .unwrap();
+        assert_eq!(
+            result.get_path(&["day"]),
+            Some(&Value::String("1970-01-02".into()))
+        );
+        assert_eq!(result.get_path(&["ts"]), Some(&Value::Int(86400)));
+
+        let result = eval_with_env("built: format_timestamp(now())").unwrap();
+        let Some(Value::String(built)) = result.get_path(&["built"]) else {
+            panic!("expected a formatted timestamp");
+        };
+        assert!(built.ends_with('Z') && built.len() == 20, "{}", built);
+    }
+
+    #[test]
+    fn test_duration_literals_and_arithmetic() {
+        let result = eval(
+            "let timeout = 2h30m
+             total: timeout + 90s
+             half: timeout / 2
+             longer: timeout > 2h
+             seconds: to_seconds(timeout)
+             millis: to_millis(1500ms * 2)
+             text: \"${-5m}\"
+",
+        )
+        .unwrap();
+        assert_eq!(
+            result.get_path(&["total"]),
+            Some(&Value::Duration(9_090_000))
+        );
+        assert_eq!(
+            result.get_path(&["half"]),
+            Some(&Value::Duration(4_500_000))
+        );
+        assert_eq!(result.get_path(&["longer"]), Some(&Value::Bool(true)));
+        assert_eq!(result.get_path(&["seconds"]), Some(&Value::Int(9000)));
+        assert_eq!(result.get_path(&["millis"]), Some(&Value::Int(3000)));
+        assert_eq!(
+            result.get_path(&["text"]),
+            Some(&Value::String("-5m".into()))
+        );
+
+        // Mixing durations and plain numbers is almost always a unit bug
+        assert!(eval("x: 5m + 30").is_err());
+        let err = format!("{:?}", eval("x: to_seconds(1500ms)").unwrap_err());
+        assert!(err.contains("whole number of seconds"), "{}", err);
+        assert!(eval("x: 5min").is_err());
+    }
+
+    #[test]
+    fn test_size_literals_and_arithmetic() {
+        let result = eval(
+            "let request = 512Mi
+             limit: request * 2
+             total: request + 512MiB
+             slice: 1Gi / 4
+             bigger: 1Gi > 1000MB
+             bytes: to_bytes(2Ki)
+             mebibytes: to_mebibytes(1.5Gi)
+             text: \"${100MB}\"
+",
+        )
+        .unwrap();
+        assert_eq!(result.get_path(&["limit"]), Some(&Value::Size(1 << 30)));
+        assert_eq!(result.get_path(&["total"]), Some(&Value::Size(1 << 30)));
+        assert_eq!(result.get_path(&["slice"]), Some(&Value::Size(256 << 20)));
+        assert_eq!(result.get_path(&["bigger"]), Some(&Value::Bool(true)));
+        assert_eq!(result.get_path(&["bytes"]), Some(&Value::Int(2048)));
+        assert_eq!(result.get_path(&["mebibytes"]), Some(&Value::Int(1536)));
+        assert_eq!(
+            result.get_path(&["text"]),
+            Some(&Value::String("100M".into()))
+        );
+
+        assert!(eval("x: 512Mi + 1").is_err());
+        assert!(eval("x: 512Mi + 5m").is_err());
+        let err = format!("{:?}", eval("x: to_mebibytes(100MB)").unwrap_err());
+        assert!(err.contains("whole number of mebibytes"), "{}", err);
+        assert!(eval("x: 5Gb").is_err());
+    }
+
+    #[test]
+    fn test_computed_number_keys() {
+        let result = eval(
+            "nums {
+               [-1]: \"neg\"
+               [1.5]: \"half\"
+               [2.0]: \"two\"
+             }
+             byname {
+               for x in [\"a\", \"b\"] {
+                 [x + \"!\"]: true
+               }
+             }
+",
+        )
+        .unwrap();
+        let Some(Value::Object(nums)) = result.get_path(&["nums"]) else {
+            panic!("expected an object");
+        };
+        assert_eq!(nums.keys().collect::<Vec<_>>(), vec!["-1", "1.5", "2"]);
+        assert_eq!(result.get_path(&["byname", "b!"]), Some(&Value::Bool(true)));
+        assert!(eval("x {\n  [1 / 0.0]: 1\n}").is_err());
+    }
+
+    #[test]
+    fn test_key_collision_modes() {
+        let source = "x {\n  [1]: \"int\"\n  [1.0]: \"float\"\n  y: { [1]: \"inner\" }\n}\n";
+        let result = eval(source).unwrap();
+        assert_eq!(
+            result.get_path(&["x", "1"]),
+            Some(&Value::String("float".into()))
+        );
+
+        let collide = |source: &str| {
+            let tokens = Lexer::new(source, None).tokenize().unwrap();
+            let ast = Parser::new(tokens, source, None).parse().unwrap();
+            let mut evaluator = Evaluator::new(source);
+            evaluator.set_key_collision(KeyCollision::Error);
+            evaluator.evaluate(&ast)
+        };
+        match collide(source).unwrap_err() {
+            HoneError::KeyCollision {
+                key,
+                first_span,
+                help,
+                ..
+            } => {
+                assert_eq!(key, "1");
+                assert!(first_span.is_some());
+                assert!(help.contains("[1] and [1.0]"), "{}", help);
+            }
+            e => panic!("expected KeyCollision, got {:?}", e),
+        }
+
+        // A `for` loop that gives a key twice collides with itself
+        let looped = "x {\n  for n in [1, 2, 1] {\n    [n]: n\n  }\n}\n";
+        assert!(matches!(
+            collide(looped),
+            Err(HoneError::KeyCollision {
+                first_span: None,
+                ..
+            })
+        ));
+        // Keys in different objects, and literal keys, never collide
+        assert!(collide("a { [1]: 1 }\nb { [1]: 1 }\nc: 1\nc: 2\n").is_ok());
+    }
+
     #[test]
     fn test_env_works_when_allowed() {
         let result = eval_with_env(r#"val: env("PATH")"#);
@@ -1934,7 +3801,7 @@ config {
         // Directly test the depth check by pre-setting depth near the limit
         // and evaluating a simple expression
         use crate::lexer::token::SourceLocation;
-        use crate::parser::ast::{BinaryExpr, BinaryOp, Expr};
+        use crate::parser::ast::Expr;
 
         let source = "test";
         let mut evaluator = Evaluator::new(source);
@@ -2193,6 +4060,185 @@ b: x
         assert_eq!(result.get_path(&["b"]), Some(&Value::Int(100)));
     }
 
+    #[test]
+    fn test_lambda_closure_and_higher_order() {
+        let source = r#"
+let factor = 3
+let scale = (x) => x * factor
+let lib = { twice: (f, x) => f(f(x)) }
+fn inc(n) { n + 1 }
+a: scale(2)
+b: lib.twice(scale, 1)
+c: lib.twice(inc, 5)
+d: ((x, y) => x + y)(1, 2)
+"#;
+        let result = eval(source).unwrap();
+        assert_eq!(result.get_path(&["a"]), Some(&Value::Int(6)));
+        assert_eq!(result.get_path(&["b"]), Some(&Value::Int(9)));
+        assert_eq!(result.get_path(&["c"]), Some(&Value::Int(7)));
+        assert_eq!(result.get_path(&["d"]), Some(&Value::Int(3)));
+    }
+
+    #[test]
+    fn test_lambda_sees_captured_bindings_not_caller_scope() {
+        let source = r#"
+let n = 1
+let f = (x) => x + n
+let g = (n) => f(0)
+out: g(100)
+"#;
+        let result = eval(source).unwrap();
+        assert_eq!(result.get_path(&["out"]), Some(&Value::Int(1)));
+    }
+
+    #[test]
+    fn test_map_filter_reduce_call_lambdas() {
+        let source = r#"
+let step = 10
+let ports = [80, 443, 8080]
+doubled: map(ports, (p) => p + step)
+low: filter(ports, (p) => p < 1024)
+total: reduce(ports, 0, (acc, p) => acc + p)
+"#;
+        let result = eval(source).unwrap();
+        assert_eq!(
+            result.get_path(&["doubled"]),
+            Some(&Value::Array(vec![
+                Value::Int(90),
+                Value::Int(453),
+                Value::Int(8090)
+            ]))
+        );
+        assert_eq!(
+            result.get_path(&["low"]),
+            Some(&Value::Array(vec![Value::Int(80), Value::Int(443)]))
+        );
+        assert_eq!(result.get_path(&["total"]), Some(&Value::Int(8603)));
+    }
+
+    #[test]
+    fn test_self_reads_assigned_and_later_keys() {
+        let source = r#"
+url: "http://${self.host}:${self.port}"
+host: "db.internal"
+port: 8080
+port: self.port + 1
+server {
+  name: "api"
+  label: "${self.name}-${self.replicas}"
+  replicas: 3
+}
+"#;
+        let result = eval(source).unwrap();
+        assert_eq!(
+            result.get_path(&["url"]),
+            Some(&Value::String("http://db.internal:8080".into()))
+        );
+        assert_eq!(result.get_path(&["port"]), Some(&Value::Int(8081)));
+        assert_eq!(
+            result.get_path(&["server", "label"]),
+            Some(&Value::String("api-3".into()))
+        );
+        let Value::Object(obj) = &result else {
+            panic!("expected an object");
+        };
+        let keys: Vec<_> = obj.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["url", "host", "port", "server"]);
+    }
+
+    #[test]
+    fn test_self_in_when_sees_enclosing_object() {
+        let source = r#"
+when true {
+  url: "http://host:${self.port}"
+}
+port: 80
+"#;
+        let result = eval(source).unwrap();
+        assert_eq!(
+            result.get_path(&["url"]),
+            Some(&Value::String("http://host:80".into()))
+        );
+    }
+
+    #[test]
+    fn test_self_cycle_and_errors() {
+        let err = eval("a: self.b\nb: self.a\n").unwrap_err();
+        assert!(matches!(
+            &err,
+            HoneError::CircularDependency { cycle, .. } if cycle == "a -> b -> a"
+        ));
+        assert!(matches!(
+            eval("port: self.port\n"),
+            Err(HoneError::CircularDependency { .. })
+        ));
+        assert!(eval("a: self.missing\n").is_err());
+        assert!(eval("let x = self.a\na: 1\n").is_err());
+        // `self` is the innermost object
+        assert!(eval("port: 1\nserver { url: self.port }\n").is_err());
+    }
+
+    #[test]
+    fn test_group_by() {
+        let source = r#"
+let pods = [
+  { name: "a", tier: "web", replicas: 2 },
+  { name: "b", tier: "db", replicas: 1 },
+  { name: "c", tier: "web", replicas: 3 },
+]
+by_tier: group_by(pods, "tier")
+by_size: group_by(pods, (p) => p.replicas > 1)
+"#;
+        let result = eval(source).unwrap();
+        let names = |path: &[&str]| match result.get_path(path) {
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|p| p.get_path(&["name"]).unwrap().to_string())
+                .collect::<Vec<_>>(),
+            other => panic!("expected array, got {:?}", other),
+        };
+        assert_eq!(names(&["by_tier", "web"]), ["a", "c"]);
+        assert_eq!(names(&["by_tier", "db"]), ["b"]);
+        assert_eq!(names(&["by_size", "true"]), ["a", "c"]);
+        assert_eq!(names(&["by_size", "false"]), ["b"]);
+        assert!(eval("out: group_by([1, 2], \"tier\")\n").is_err());
+    }
+
+    #[test]
+    fn test_update() {
+        let source = r#"
+let base = { spec: { replicas: 2, containers: [{ name: "api", args: ["--port=80"] }] } }
+scaled: update(base, "spec.replicas", (n) => n * 2)
+debug: update(base, ["spec", "containers", 0, "args"], (args) => args + ["--debug"])
+labelled: update(base, "metadata.labels", (labels) => labels ?? { app: "api" })
+"#;
+        let result = eval(source).unwrap();
+        assert_eq!(
+            result.get_path(&["scaled", "spec", "replicas"]),
+            Some(&Value::Int(4))
+        );
+        let args = result
+            .get_path(&["debug", "spec", "containers"])
+            .and_then(|c| match c {
+                Value::Array(items) => items[0].get_path(&["args"]),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(args.to_string(), "[--port=80, --debug]");
+        assert_eq!(
+            result.get_path(&["labelled", "metadata", "labels", "app"]),
+            Some(&Value::String("api".into()))
+        );
+        assert!(eval("out: update({ a: 1 }, \"a\", 2)\n").is_err());
+    }
+
+    #[test]
+    fn test_function_in_output_is_error() {
+        let err = eval("let f = (x) => x\nout: { g: f }\n").unwrap_err();
+        assert!(err.to_string().contains("type mismatch"));
+        assert!(eval("let f = (x) => x\nout: f(1, 2)\n").is_err());
+    }
+
     #[test]
     fn test_when_no_match_produces_no_keys() {
         let result = eval("when false {\n  x: 1\n  y: 2\n}").unwrap();
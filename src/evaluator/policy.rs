@@ -0,0 +1,193 @@
+//! Timing, time limits and caching for policy conditions
+//!
+//! Each policy's condition is timed, and with a timeout its evaluation stops
+//! once the deadline passes, including inside the functions it calls. Calls
+//! in a condition that do not read `output` (directly, through a function,
+//! or with a budget helper) give the same value whenever their inputs are
+//! the same, so they are kept in a [`PolicyCache`] keyed by their text and
+//! the values of every name they read. A compile that re-checks the same
+//! policies, for another document or after an edit in `hone watch` or
+//! `hone serve`, then reuses them. Calls that read `env()`, `file()` or
+//! `now()` are never cached.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hasher;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::lexer::token::SourceLocation;
+use crate::parser::ast::{CallExpr, Expr};
+use crate::parser::visit::{walk_expr, Visit};
+
+use super::budget;
+use super::function::referenced_names;
+use super::value::Value;
+
+/// Values kept before the cache starts over
+const MAX_ENTRIES: usize = 4096;
+
+/// How long one policy's condition took to check
+#[derive(Debug, Clone)]
+pub struct PolicyTiming {
+    pub name: String,
+    pub location: SourceLocation,
+    pub elapsed: Duration,
+    /// Calls answered from the cache
+    pub cached_calls: usize,
+}
+
+/// Values of output-independent calls in policy conditions, shared by every
+/// clone, so one cache can serve many compiles
+#[derive(Debug, Clone, Default)]
+pub struct PolicyCache(Arc<Mutex<HashMap<u64, Value>>>);
+
+impl PolicyCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The cache every compiler in this process starts with
+    pub fn global() -> Self {
+        static GLOBAL: OnceLock<PolicyCache> = OnceLock::new();
+        GLOBAL.get_or_init(PolicyCache::new).clone()
+    }
+
+    pub fn get(&self, key: u64) -> Option<Value> {
+        self.0.lock().ok()?.get(&key).cloned()
+    }
+
+    pub fn insert(&self, key: u64, value: Value) {
+        if let Ok(mut entries) = self.0.lock() {
+            if entries.len() >= MAX_ENTRIES {
+                entries.clear();
+            }
+            entries.insert(key, value);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().map(|entries| entries.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// When the policy being checked must finish
+#[derive(Debug, Clone)]
+pub struct Deadline {
+    pub at: Instant,
+    pub timeout: Duration,
+    pub location: SourceLocation,
+}
+
+/// Names whose value can change while their inputs stay the same
+pub fn is_impure(name: &str) -> bool {
+    matches!(name, "output" | "env" | "file" | "now") || budget::is_budget_helper(name)
+}
+
+/// The outermost calls in `condition` that may be cached, by address: none
+/// of the names they mention is impure. Calls to user functions are checked
+/// again when they are evaluated, since their bodies may read `output`.
+pub fn cacheable_calls(condition: &Expr) -> HashSet<usize> {
+    let mut finder = CacheableCalls::default();
+    finder.visit_expr(condition);
+    finder.calls
+}
+
+#[derive(Default)]
+struct CacheableCalls {
+    calls: HashSet<usize>,
+}
+
+impl Visit for CacheableCalls {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Expr::Call(call) = expr {
+            if !referenced_names(expr).iter().any(|name| is_impure(name)) {
+                self.calls.insert(call_address(call));
+                return;
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Identity of a call node while its policy is checked
+pub fn call_address(call: &CallExpr) -> usize {
+    call as *const CallExpr as usize
+}
+
+/// Hashes what a cached call depends on
+pub struct KeyHasher(std::collections::hash_map::DefaultHasher);
+
+impl KeyHasher {
+    pub fn new() -> Self {
+        Self(std::collections::hash_map::DefaultHasher::new())
+    }
+
+    /// Add a value by its debug form, which covers every part of it
+    pub fn add(&mut self, item: &impl fmt::Debug) {
+        use fmt::Write;
+        let _ = write!(self, "{:?}", item);
+        self.0.write_u8(0);
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0.finish()
+    }
+}
+
+impl Default for KeyHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Write for KeyHasher {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::ast::PreambleItem;
+    use crate::parser::Parser;
+
+    fn condition(source: &str) -> Expr {
+        let tokens = Lexer::new(source, None).tokenize().unwrap();
+        let file = Parser::new(tokens, source, None).parse().unwrap();
+        match file.preamble.into_iter().next() {
+            Some(PreambleItem::Policy(policy)) => policy.condition,
+            _ => panic!("expected a policy"),
+        }
+    }
+
+    #[test]
+    fn test_cacheable_calls_skip_output() {
+        let expr = condition(
+            "policy p deny when len(output.items) > max(limits) && len(keys(output)) > min(names) { \"m\" }",
+        );
+        // max(limits) and min(names); the calls reading output are not
+        assert_eq!(cacheable_calls(&expr).len(), 2);
+        let expr = condition("policy p warn when count() > len(env(\"X\")) { \"m\" }");
+        assert!(cacheable_calls(&expr).is_empty());
+    }
+
+    #[test]
+    fn test_cache_starts_over_when_full() {
+        let cache = PolicyCache::new();
+        for i in 0..MAX_ENTRIES as u64 {
+            cache.insert(i, Value::Int(i as i64));
+        }
+        assert_eq!(cache.len(), MAX_ENTRIES);
+        assert_eq!(cache.get(7), Some(Value::Int(7)));
+        cache.insert(u64::MAX, Value::Null);
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,280 @@
+//! Human-oriented value printer
+//!
+//! Used where a value is read by a person rather than a tool (`hone eval`,
+//! LSP hover). Nested objects are indented blocks, scalar values line up after
+//! the longest key of their object, and long arrays are cut off with a count of
+//! the hidden items. Colors are ANSI escapes and are off by default.
+
+use super::Value;
+use crate::style::{
+    CYAN as KEY, DIM, GREEN as STRING, MAGENTA as LITERAL, RESET, YELLOW as NUMBER,
+};
+
+/// Options for [`pretty_print`]
+#[derive(Debug, Clone)]
+pub struct PrettyOptions {
+    /// Emit ANSI colors
+    pub color: bool,
+    /// Show at most this many items of an array; `None` shows all
+    pub max_items: Option<usize>,
+    /// Spaces per nesting level
+    pub indent: usize,
+}
+
+impl Default for PrettyOptions {
+    fn default() -> Self {
+        Self {
+            color: false,
+            max_items: Some(10),
+            indent: 2,
+        }
+    }
+}
+
+impl PrettyOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_max_items(mut self, max_items: Option<usize>) -> Self {
+        self.max_items = max_items;
+        self
+    }
+}
+
+/// Render a value for display. The result has no trailing newline.
+pub fn pretty_print(value: &Value, options: &PrettyOptions) -> String {
+    let printer = Printer { options };
+    let mut out = String::new();
+    match value {
+        Value::Object(obj) if !obj.is_empty() => printer.object(obj, 0, &mut out),
+        Value::Array(arr) if !printer.is_inline(arr) => printer.block_array(arr, 0, &mut out),
+        _ => out.push_str(&printer.inline(value)),
+    }
+    out.truncate(out.trim_end().len());
+    out
+}
+
+struct Printer<'a> {
+    options: &'a PrettyOptions,
+}
+
+impl Printer<'_> {
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.options.color {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Split an array into the shown items and the number hidden
+    fn visible<'v>(&self, arr: &'v [Value]) -> (&'v [Value], usize) {
+        match self.options.max_items {
+            Some(max) if arr.len() > max => (&arr[..max], arr.len() - max),
+            _ => (arr, 0),
+        }
+    }
+
+    fn more(&self, hidden: usize) -> String {
+        self.paint(DIM, &format!("… {} more", hidden))
+    }
+
+    /// Arrays of scalars fit on one line
+    fn is_inline(&self, arr: &[Value]) -> bool {
+        arr.iter().all(is_scalar_or_empty)
+    }
+
+    fn key(&self, key: &str) -> String {
+        let is_ident = key
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+            && key
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+        let text = if is_ident {
+            key.to_string()
+        } else {
+            quote(key)
+        };
+        self.paint(KEY, &text)
+    }
+
+    fn inline(&self, value: &Value) -> String {
+        match value {
+            Value::Null => self.paint(LITERAL, "null"),
+            Value::Bool(b) => self.paint(LITERAL, &b.to_string()),
+            Value::Int(_) | Value::Float(_) | Value::Duration(_) | Value::Size(_) => {
+                self.paint(NUMBER, &value.to_string())
+            }
+            Value::String(s) => self.paint(STRING, &quote(s)),
+            Value::Function(_) => self.paint(LITERAL, &value.to_string()),
+            Value::Array(arr) => {
+                let (shown, hidden) = self.visible(arr);
+                let mut items: Vec<String> = shown.iter().map(|v| self.inline(v)).collect();
+                if hidden > 0 {
+                    items.push(self.more(hidden));
+                }
+                format!("[{}]", items.join(", "))
+            }
+            Value::Object(obj) if obj.is_empty() => "{}".to_string(),
+            Value::Object(obj) => {
+                let items: Vec<String> = obj
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", self.key(k), self.inline(v)))
+                    .collect();
+                format!("{{ {} }}", items.join(", "))
+            }
+        }
+    }
+
+    fn object(&self, obj: &indexmap::IndexMap<String, Value>, depth: usize, out: &mut String) {
+        let pad = " ".repeat(depth * self.options.indent);
+        let width = obj
+            .iter()
+            .filter(|(_, v)| self.fits_line(v))
+            .map(|(k, _)| self.key(k).chars().count())
+            .max()
+            .unwrap_or(0);
+
+        for (key, value) in obj {
+            let key = self.key(key);
+            if self.fits_line(value) {
+                let gap = " ".repeat(width - key.chars().count() + 1);
+                out.push_str(&format!("{}{}:{}{}\n", pad, key, gap, self.inline(value)));
+                continue;
+            }
+            out.push_str(&format!("{}{}:\n", pad, key));
+            match value {
+                Value::Object(child) => self.object(child, depth + 1, out),
+                Value::Array(arr) => self.block_array(arr, depth + 1, out),
+                _ => unreachable!("scalars fit on one line"),
+            }
+        }
+    }
+
+    fn block_array(&self, arr: &[Value], depth: usize, out: &mut String) {
+        let pad = " ".repeat(depth * self.options.indent);
+        let (shown, hidden) = self.visible(arr);
+        for item in shown {
+            match item {
+                Value::Object(obj) if !obj.is_empty() => {
+                    // First key shares the dash line, the rest line up under it
+                    let mut nested = String::new();
+                    self.object(obj, depth + 1, &mut nested);
+                    let inner = " ".repeat((depth + 1) * self.options.indent);
+                    let nested = nested.strip_prefix(&inner).unwrap_or(&nested);
+                    out.push_str(&format!("{}- {}", pad, nested));
+                }
+                Value::Array(child) if !self.is_inline(child) => {
+                    out.push_str(&format!("{}-\n", pad));
+                    self.block_array(child, depth + 1, out);
+                }
+                _ => out.push_str(&format!("{}- {}\n", pad, self.inline(item))),
+            }
+        }
+        if hidden > 0 {
+            out.push_str(&format!("{}{}\n", pad, self.more(hidden)));
+        }
+    }
+
+    /// Values printed on their key's line
+    fn fits_line(&self, value: &Value) -> bool {
+        match value {
+            Value::Array(arr) => self.is_inline(arr),
+            _ => is_scalar_or_empty(value),
+        }
+    }
+}
+
+fn is_scalar_or_empty(value: &Value) -> bool {
+    match value {
+        Value::Array(arr) => arr.is_empty(),
+        Value::Object(obj) => obj.is_empty(),
+        _ => true,
+    }
+}
+
+fn quote(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_else(|_| format!("\"{}\"", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    fn obj(pairs: Vec<(&str, Value)>) -> Value {
+        Value::Object(
+            pairs
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect::<IndexMap<_, _>>(),
+        )
+    }
+
+    #[test]
+    fn test_pretty_aligns_scalars_and_nests_objects() {
+        let value = obj(vec![
+            ("name", Value::String("api".into())),
+            ("replicas", Value::Int(3)),
+            (
+                "server",
+                obj(vec![
+                    ("host", Value::String("localhost".into())),
+                    ("port", Value::Int(8080)),
+                ]),
+            ),
+            ("tags", Value::Array(vec![Value::String("a".into())])),
+        ]);
+        assert_eq!(
+            pretty_print(&value, &PrettyOptions::new()),
+            "name:     \"api\"\nreplicas: 3\nserver:\n  host: \"localhost\"\n  port: 8080\ntags:     [\"a\"]"
+        );
+    }
+
+    #[test]
+    fn test_pretty_truncates_long_arrays() {
+        let value = Value::Array((1..=5).map(Value::Int).collect());
+        let options = PrettyOptions::new().with_max_items(Some(2));
+        assert_eq!(pretty_print(&value, &options), "[1, 2, … 3 more]");
+        assert_eq!(
+            pretty_print(&value, &PrettyOptions::new().with_max_items(None)),
+            "[1, 2, 3, 4, 5]"
+        );
+    }
+
+    #[test]
+    fn test_pretty_array_of_objects() {
+        let value = obj(vec![(
+            "ports",
+            Value::Array(vec![
+                obj(vec![
+                    ("name", Value::String("http".into())),
+                    ("port", Value::Int(80)),
+                ]),
+                obj(vec![
+                    ("name", Value::String("tls".into())),
+                    ("port", Value::Int(443)),
+                ]),
+            ]),
+        )]);
+        assert_eq!(
+            pretty_print(&value, &PrettyOptions::new()),
+            "ports:\n  - name: \"http\"\n    port: 80\n  - name: \"tls\"\n    port: 443"
+        );
+    }
+
+    #[test]
+    fn test_pretty_color() {
+        let value = obj(vec![("on", Value::Bool(true))]);
+        let colored = pretty_print(&value, &PrettyOptions::new().with_color(true));
+        assert_eq!(colored, "\x1b[36mon\x1b[0m: \x1b[35mtrue\x1b[0m");
+    }
+}
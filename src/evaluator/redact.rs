@@ -0,0 +1,136 @@
+//! Redaction of `@sensitive` values
+//!
+//! Every scalar an `@sensitive` expression evaluates to is remembered, and
+//! text shown outside the output (diffs, `hone debug`, error messages) has
+//! each occurrence replaced with `***`. Matching is by value, so a sensitive
+//! string stays hidden after it is copied to other keys or interpolated
+//! into a longer one. Booleans and null are not recorded; hiding every
+//! `true` would hide nothing useful.
+
+use crate::errors::HoneError;
+
+use super::Value;
+
+/// What a sensitive value is shown as
+pub const MASK: &str = "***";
+
+/// Masks the values recorded from `@sensitive` expressions
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    /// Sensitive texts, longest first so a value containing another is
+    /// masked whole
+    values: Vec<String>,
+}
+
+impl Redactor {
+    /// Record the scalars in `value` as sensitive
+    pub fn add(&mut self, value: &Value) {
+        match value {
+            Value::String(s) if !s.is_empty() => self.insert(s.clone()),
+            Value::Int(_) | Value::Float(_) | Value::Duration(_) | Value::Size(_) => {
+                self.insert(value.to_string())
+            }
+            Value::Array(items) => items.iter().for_each(|item| self.add(item)),
+            Value::Object(obj) => obj.values().for_each(|v| self.add(v)),
+            _ => {}
+        }
+    }
+
+    /// Record everything `other` masks
+    pub fn extend(&mut self, other: &Redactor) {
+        for value in &other.values {
+            self.insert(value.clone());
+        }
+    }
+
+    fn insert(&mut self, text: String) {
+        if let Err(i) = self
+            .values
+            .binary_search_by(|v| text.len().cmp(&v.len()).then_with(|| v.cmp(&text)))
+        {
+            self.values.insert(i, text);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// `text` with every sensitive value replaced by `***`
+    pub fn text(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for value in &self.values {
+            if text.contains(value.as_str()) {
+                text = text.replace(value.as_str(), MASK);
+            }
+        }
+        text
+    }
+
+    /// `value` with sensitive strings masked inside strings, and other
+    /// sensitive scalars replaced by the string `***`
+    pub fn value(&self, value: &Value) -> Value {
+        if self.is_empty() {
+            return value.clone();
+        }
+        match value {
+            Value::String(s) => Value::String(self.text(s)),
+            Value::Int(_) | Value::Float(_) | Value::Duration(_) | Value::Size(_)
+                if self.values.contains(&value.to_string()) =>
+            {
+                Value::String(MASK.to_string())
+            }
+            Value::Array(items) => Value::Array(items.iter().map(|v| self.value(v)).collect()),
+            Value::Object(obj) => Value::Object(
+                obj.iter()
+                    .map(|(k, v)| (self.text(k), self.value(v)))
+                    .collect(),
+            ),
+            _ => value.clone(),
+        }
+    }
+
+    /// `error` with sensitive values masked in the text it shows
+    pub fn error(&self, error: HoneError) -> HoneError {
+        if self.is_empty() {
+            return error;
+        }
+        error.redact(&|text| self.text(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    #[test]
+    fn test_masks_by_value() {
+        let mut redactor = Redactor::default();
+        redactor.add(&Value::String("hunter2".into()));
+        redactor.add(&Value::String("hunter".into()));
+        redactor.add(&Value::Int(4242));
+        redactor.add(&Value::Bool(true));
+
+        assert_eq!(
+            redactor.text("postgres://app:hunter2@db, then hunter"),
+            "postgres://app:***@db, then ***"
+        );
+        assert_eq!(
+            redactor.text("value 4242 is too large"),
+            "value *** is too large"
+        );
+
+        let mut obj = IndexMap::new();
+        obj.insert("pin".to_string(), Value::Int(4242));
+        obj.insert("port".to_string(), Value::Int(42));
+        obj.insert("debug".to_string(), Value::Bool(true));
+        let redacted = redactor.value(&Value::Object(obj));
+        assert_eq!(
+            redacted.get_path(&["pin"]),
+            Some(&Value::String("***".into()))
+        );
+        assert_eq!(redacted.get_path(&["port"]), Some(&Value::Int(42)));
+        assert_eq!(redacted.get_path(&["debug"]), Some(&Value::Bool(true)));
+    }
+}
@@ -130,6 +130,11 @@ impl ScopeStack {
         }
     }
 
+    /// Pop scopes until `depth` are left, never the global scope
+    pub fn truncate(&mut self, depth: usize) {
+        self.scopes.truncate(depth.max(1));
+    }
+
     /// Get the current (topmost) scope
     pub fn current(&self) -> &Scope {
         self.scopes
@@ -177,6 +182,11 @@ impl ScopeStack {
         None
     }
 
+    /// Number of scopes on the stack (1 when only the global scope exists)
+    pub fn depth(&self) -> usize {
+        self.scopes.len()
+    }
+
     /// Get the global scope
     pub fn global(&self) -> &Scope {
         self.scopes
@@ -210,6 +220,24 @@ impl ScopeStack {
 
         names
     }
+
+    /// Get all visible bindings and imports, sorted by name. Inner scopes
+    /// shadow outer ones.
+    pub fn visible_bindings(&self) -> Vec<(String, Value)> {
+        let mut visible: HashMap<&str, &Value> = HashMap::new();
+        for scope in &self.scopes {
+            for (name, value) in scope.imports.iter().chain(scope.bindings.iter()) {
+                visible.insert(name, value);
+            }
+        }
+
+        let mut bindings: Vec<(String, Value)> = visible
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
 }
 
 #[cfg(test)]
@@ -287,4 +315,21 @@ mod tests {
         let mut stack = ScopeStack::new();
         assert!(stack.pop().is_none()); // Can't pop global
     }
+
+    #[test]
+    fn test_scope_stack_visible_bindings() {
+        let mut stack = ScopeStack::new();
+        stack.define("x", Value::Int(1));
+        stack.define("name", Value::String("api".into()));
+        stack.push();
+        stack.define("x", Value::Int(2));
+
+        assert_eq!(
+            stack.visible_bindings(),
+            vec![
+                ("name".to_string(), Value::String("api".into())),
+                ("x".to_string(), Value::Int(2)),
+            ]
+        );
+    }
 }
@@ -0,0 +1,142 @@
+//! `self` references
+//!
+//! `self` is the object being built: `url: "http://host:${self.port}"` reads
+//! the `port` key of the same object. A key that is already assigned reads
+//! its current value. A key that is not is late-bound: its next plain
+//! `key: value` in the same body is evaluated early, and used again when the
+//! evaluation reaches it. References that come back to a key still being
+//! evaluated are a cycle.
+//!
+//! Each body that mentions `self` gets a [`SelfFrame`]. `when` bodies and
+//! variant cases add to the enclosing object, so their frames are
+//! transparent: keys they do not assign are looked up in the enclosing body.
+
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+
+use crate::parser::ast::{BodyItem, Key, KeyValue};
+
+use super::function::body_referenced_names;
+use super::value::Value;
+
+/// Name of the reference
+pub const SELF: &str = "self";
+
+/// Whether any item of a body reads `self`
+pub fn mentions_self(items: &[BodyItem]) -> bool {
+    body_referenced_names(items).contains(SELF)
+}
+
+/// State of one body that reads `self`
+#[derive(Debug)]
+pub struct SelfFrame {
+    /// The object as assigned before the current item
+    pub object: IndexMap<String, Value>,
+    /// Plain `key: value` items of the body, with their index
+    items: Vec<(usize, String, KeyValue)>,
+    /// Index of the item being evaluated
+    pub position: usize,
+    /// Values of later items evaluated early, by index
+    early: HashMap<usize, Value>,
+    /// Keys being evaluated, in the order they were entered
+    resolving: Vec<String>,
+    /// Whether keys this body does not assign come from the enclosing body
+    pub transparent: bool,
+    /// Length of the evaluator's current path inside this body
+    pub depth: usize,
+}
+
+impl SelfFrame {
+    pub fn new(items: &[BodyItem], transparent: bool, depth: usize) -> Self {
+        let items = items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| match item {
+                BodyItem::KeyValue(kv) => match &kv.key {
+                    Key::Ident(key) | Key::String(key) => Some((i, key.clone(), kv.clone())),
+                    Key::Computed(_) => None,
+                },
+                _ => None,
+            })
+            .collect();
+        Self {
+            object: IndexMap::new(),
+            items,
+            position: 0,
+            early: HashMap::new(),
+            resolving: Vec::new(),
+            transparent,
+            depth,
+        }
+    }
+
+    /// The next item after the current one that assigns `key`
+    pub fn later_item(&self, key: &str) -> Option<(usize, &KeyValue)> {
+        self.items
+            .iter()
+            .find(|(i, k, _)| *i > self.position && k == key)
+            .map(|(i, _, kv)| (*i, kv))
+    }
+
+    /// A value already evaluated early for item `index`
+    pub fn early_value(&self, index: usize) -> Option<&Value> {
+        self.early.get(&index)
+    }
+
+    pub fn set_early(&mut self, index: usize, value: Value) {
+        self.early.insert(index, value);
+    }
+
+    /// Take the early value of item `index` when the evaluation reaches it
+    pub fn take_early(&mut self, index: usize) -> Option<Value> {
+        self.early.remove(&index)
+    }
+
+    pub fn enter(&mut self, key: &str) {
+        self.resolving.push(key.to_string());
+    }
+
+    pub fn leave(&mut self) {
+        self.resolving.pop();
+    }
+
+    /// The cycle `key` closes if it is still being evaluated:
+    /// `a -> b -> a`
+    pub fn cycle(&self, key: &str) -> Option<String> {
+        let start = self.resolving.iter().position(|k| k == key)?;
+        let mut chain: Vec<&str> = self.resolving[start..].iter().map(String::as_str).collect();
+        chain.push(key);
+        Some(chain.join(" -> "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn body(source: &str) -> Vec<BodyItem> {
+        let tokens = Lexer::new(source, None).tokenize().unwrap();
+        Parser::new(tokens, source, None).parse().unwrap().body
+    }
+
+    #[test]
+    fn test_frame_later_items_and_cycles() {
+        let items = body("a: self.b\nb: 1\nc: { d: self.e }\nb: 2\n");
+        assert!(mentions_self(&items));
+        assert!(!mentions_self(&body("a: 1\nb: a.c\n")));
+
+        let mut frame = SelfFrame::new(&items, false, 0);
+        assert_eq!(frame.later_item("b").map(|(i, _)| i), Some(1));
+        frame.position = 1;
+        assert_eq!(frame.later_item("b").map(|(i, _)| i), Some(3));
+        assert!(frame.later_item("a").is_none());
+
+        frame.enter("a");
+        frame.enter("b");
+        assert_eq!(frame.cycle("a").as_deref(), Some("a -> b -> a"));
+        assert_eq!(frame.cycle("c"), None);
+    }
+}
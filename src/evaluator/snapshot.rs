@@ -0,0 +1,147 @@
+//! Scope snapshots for `hone debug`
+//!
+//! When enabled, the evaluator records the variables visible at each body
+//! item before evaluating it. Snapshots are looked up by source position to
+//! answer "what was in scope here?" without sprinkling `trace()` calls.
+
+use crate::lexer::token::SourceLocation;
+
+use super::redact::Redactor;
+use super::value::Value;
+
+/// Variables in scope at one body item
+#[derive(Debug, Clone)]
+pub struct ScopeSnapshot {
+    /// Location of the body item (spans the whole item)
+    pub location: SourceLocation,
+    /// Dot-path of the object the item contributes to ("" at top level)
+    pub path: String,
+    /// The item's own key, for key-values and blocks with a literal key
+    pub key: Option<String>,
+    /// Scope nesting depth (1 at top level); used for step-over
+    pub depth: usize,
+    /// Visible bindings, sorted by name; inner scopes shadow outer ones
+    pub bindings: Vec<(String, Value)>,
+}
+
+impl ScopeSnapshot {
+    /// Full dot-path of the item's key (e.g. `server.port`), if it has one
+    pub fn key_path(&self) -> Option<String> {
+        let key = self.key.as_ref()?;
+        if self.path.is_empty() {
+            Some(key.clone())
+        } else {
+            Some(format!("{}.{}", self.path, key))
+        }
+    }
+
+    /// This snapshot with `@sensitive` values masked in its bindings
+    pub fn redacted(&self, redactor: &Redactor) -> ScopeSnapshot {
+        ScopeSnapshot {
+            bindings: self
+                .bindings
+                .iter()
+                .map(|(name, value)| (name.clone(), redactor.value(value)))
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Snapshots for the innermost body item containing `offset`.
+///
+/// An item inside a `for` body is recorded once per iteration, so more than
+/// one snapshot can match. When no item contains the offset (a blank line or
+/// a comment), the nearest item starting before it is used.
+pub fn snapshots_at(snapshots: &[ScopeSnapshot], offset: usize) -> Vec<&ScopeSnapshot> {
+    let contains = |s: &&ScopeSnapshot| {
+        s.location.offset <= offset && offset < s.location.offset + s.location.length.max(1)
+    };
+
+    let best = snapshots
+        .iter()
+        .filter(contains)
+        .min_by_key(|s| s.location.length)
+        .or_else(|| {
+            snapshots
+                .iter()
+                .filter(|s| s.location.offset <= offset)
+                .max_by_key(|s| s.location.offset)
+        });
+
+    match best {
+        Some(best) => snapshots
+            .iter()
+            .filter(|s| s.location.offset == best.location.offset)
+            .filter(|s| s.location.length == best.location.length)
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Convert a 1-indexed line and column to a byte offset in `source`.
+/// Columns past the end of the line clamp to the line end.
+pub fn offset_at(source: &str, line: usize, column: usize) -> Option<usize> {
+    if line == 0 {
+        return None;
+    }
+    let mut start = 0;
+    for _ in 1..line {
+        start += source[start..].find('\n')? + 1;
+    }
+    let line_len = source[start..].find('\n').unwrap_or(source.len() - start);
+    let col_offset = source[start..start + line_len]
+        .char_indices()
+        .nth(column.saturating_sub(1))
+        .map(|(i, _)| i)
+        .unwrap_or(line_len);
+    Some(start + col_offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(offset: usize, length: usize, path: &str) -> ScopeSnapshot {
+        ScopeSnapshot {
+            location: SourceLocation {
+                file: None,
+                line: 1,
+                column: 1,
+                offset,
+                length,
+            },
+            path: path.to_string(),
+            key: None,
+            depth: 1,
+            bindings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_snapshots_at_prefers_innermost() {
+        let snapshots = vec![
+            snapshot(0, 40, ""),
+            snapshot(10, 5, "server"),
+            snapshot(10, 5, "server"),
+            snapshot(50, 5, ""),
+        ];
+        let found = snapshots_at(&snapshots, 12);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].path, "server");
+
+        // Between items: the nearest preceding one
+        assert_eq!(snapshots_at(&snapshots, 45)[0].location.offset, 10);
+        assert!(snapshots_at(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn test_offset_at() {
+        let source = "a: 1\nbb: 2\n";
+        assert_eq!(offset_at(source, 1, 1), Some(0));
+        assert_eq!(offset_at(source, 2, 2), Some(6));
+        assert_eq!(offset_at(source, 2, 99), Some(10));
+        assert_eq!(offset_at(source, 9, 1), None);
+        assert_eq!(offset_at(source, 0, 1), None);
+    }
+}
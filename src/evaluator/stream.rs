@@ -0,0 +1,142 @@
+//! Which top-level arrays `--stream` can defer
+//!
+//! A deferred `key: for ...` holds `null` while the rest of the document is
+//! evaluated, and its elements are produced only when it is written. That is
+//! only the same output when nothing else in the document writes the key or
+//! reads it through `self`. Spreads, `include`, top-level `for` blocks and
+//! computed keys can write any key, so they rule every array out; a `when`
+//! block does only when its body could.
+
+use crate::parser::ast::{
+    AssignOp, BodyItem, ElseBranch, Expr, File, ForLoop, Key, PathPart, WhenBlock,
+};
+use crate::parser::visit::{walk_expr, Visit};
+
+use super::selfref::SELF;
+
+/// The key and loop of a top-level `key: for ...` item, whatever else the
+/// document does with the key
+pub fn candidate(item: &BodyItem) -> Option<(&String, &ForLoop)> {
+    let BodyItem::KeyValue(kv) = item else {
+        return None;
+    };
+    let (Key::Ident(key) | Key::String(key)) = &kv.key else {
+        return None;
+    };
+    match &kv.value {
+        Expr::For(for_loop) if matches!(kv.op, AssignOp::Colon) => Some((key, for_loop)),
+        _ => None,
+    }
+}
+
+/// What else in `file` could write or read the key of `item`, a
+/// [`candidate`], described for an error message
+pub fn conflict(file: &File, item: &BodyItem, key: &str) -> Option<&'static str> {
+    let write = file
+        .body
+        .iter()
+        .filter(|other| !std::ptr::eq(*other, item))
+        .find_map(|other| writes(other, key));
+    write.or_else(|| {
+        let mut reads = SelfReads { key, found: false };
+        reads.visit_file(file);
+        reads.found.then_some("a `self` reference")
+    })
+}
+
+/// The first top-level `key: for ...` of `file` that streaming can't defer,
+/// with what stops it
+pub fn first_conflict(file: &File) -> Option<(String, &'static str)> {
+    file.body.iter().find_map(|item| {
+        let (key, _) = candidate(item)?;
+        conflict(file, item, key).map(|what| (key.clone(), what))
+    })
+}
+
+fn writes(item: &BodyItem, key: &str) -> Option<&'static str> {
+    match item {
+        BodyItem::KeyValue(kv) => match &kv.key {
+            Key::Ident(k) | Key::String(k) if k == key => Some("another assignment"),
+            Key::Computed(_) => Some("a computed key"),
+            _ => None,
+        },
+        BodyItem::Block(block) if block.name == key => Some("another assignment"),
+        BodyItem::Spread(_) => Some("a spread"),
+        BodyItem::Include(_) => Some("an `include`"),
+        BodyItem::For(_) => Some("a `for` block"),
+        BodyItem::When(when) if when_writes(when, key) => Some("a `when` block"),
+        _ => None,
+    }
+}
+
+fn when_writes(when: &WhenBlock, key: &str) -> bool {
+    when.body.iter().any(|item| writes(item, key).is_some())
+        || match &when.else_branch {
+            Some(ElseBranch::ElseWhen(next)) => when_writes(next, key),
+            Some(ElseBranch::Else(body, _)) => body.iter().any(|item| writes(item, key).is_some()),
+            None => false,
+        }
+}
+
+/// Finds `self`, `self.<key>` and `self[...]` anywhere in a file. `self`
+/// in a nested object is a different object, but telling them apart isn't
+/// worth it: the cost of a false match is only that the file isn't streamed.
+struct SelfReads<'a> {
+    key: &'a str,
+    found: bool,
+}
+
+impl Visit for SelfReads<'_> {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Ident(name, _) if name == SELF => self.found = true,
+            Expr::Path(path) if matches!(path.parts.first(), Some(PathPart::Ident(n)) if n == SELF) => {
+                match path.parts.get(1) {
+                    Some(PathPart::Ident(key)) if key != self.key => {}
+                    _ => self.found = true,
+                }
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn first(source: &str) -> Option<(String, &'static str)> {
+        let tokens = Lexer::new(source, None).tokenize().unwrap();
+        let file = Parser::new(tokens, source, None).parse().unwrap();
+        first_conflict(&file)
+    }
+
+    #[test]
+    fn test_first_conflict() {
+        let items = "items: for i in [1, 2] { i }\n";
+        for (rest, expected) in [
+            ("...{ items: [] }\n", Some("a spread")),
+            ("when true { items +: [5] }\n", Some("a `when` block")),
+            (
+                "when false { a: 1 } else { items: [] }\n",
+                Some("a `when` block"),
+            ),
+            ("count: len(self.items)\n", Some("a `self` reference")),
+            ("all: self\n", Some("a `self` reference")),
+            ("items +: [3]\n", Some("another assignment")),
+            // Touching other keys is fine
+            ("when true { other: 1 }\nn: 2\nm: self.n\n", None),
+        ] {
+            let conflict = first(&format!("{}{}", items, rest));
+            assert_eq!(
+                conflict,
+                expected.map(|what| ("items".to_string(), what)),
+                "{}",
+                rest
+            );
+        }
+    }
+}
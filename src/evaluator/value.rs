@@ -5,6 +5,10 @@
 
 use indexmap::IndexMap;
 use std::fmt;
+use std::sync::Arc;
+
+use super::function::Function;
+use crate::lexer::{duration, size};
 
 /// A runtime value in Hone
 #[derive(Debug, Clone, PartialEq)]
@@ -17,12 +21,19 @@ pub enum Value {
     Int(i64),
     /// Floating point (64-bit)
     Float(f64),
+    /// Duration in milliseconds; written out as a literal like `2h30m`
+    Duration(i64),
+    /// Size in bytes; written out as a quantity like `512Mi`
+    Size(i64),
     /// String
     String(String),
     /// Array of values
     Array(Vec<Value>),
     /// Object (ordered map of string keys to values)
     Object(IndexMap<String, Value>),
+    /// Function: a lambda or a named `fn`. Functions exist only during
+    /// evaluation and cannot be written to output.
+    Function(Arc<Function>),
 }
 
 impl Value {
@@ -33,9 +44,13 @@ impl Value {
             Value::Bool(_) => "bool",
             Value::Int(_) => "int",
             Value::Float(_) => "float",
+            Value::Duration(_) => "duration",
+            Value::Size(_) => "size",
             Value::String(_) => "string",
             Value::Array(_) => "array",
             Value::Object(_) => "object",
+            Value::Function(func) if func.block().is_some() => "block",
+            Value::Function(_) => "function",
         }
     }
 
@@ -51,9 +66,12 @@ impl Value {
             Value::Bool(b) => *b,
             Value::Int(n) => *n != 0,
             Value::Float(n) => *n != 0.0,
+            Value::Duration(ms) => *ms != 0,
+            Value::Size(bytes) => *bytes != 0,
             Value::String(s) => !s.is_empty(),
             Value::Array(a) => !a.is_empty(),
             Value::Object(o) => !o.is_empty(),
+            Value::Function(_) => true,
         }
     }
 
@@ -62,6 +80,85 @@ impl Value {
         matches!(self, Value::Object(o) if o.is_empty())
     }
 
+    /// Copy of this value with object keys sorted at every level: number
+    /// keys by value, then the rest alphabetically (see [`super::keys::compare`]).
+    /// Array element order is kept.
+    pub fn sorted_keys(&self) -> Value {
+        match self {
+            Value::Object(obj) => {
+                let mut sorted: IndexMap<String, Value> = obj
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.sorted_keys()))
+                    .collect();
+                sorted.sort_by(|a, _, b, _| super::keys::compare(a, b));
+                Value::Object(sorted)
+            }
+            Value::Array(arr) => Value::Array(arr.iter().map(Value::sorted_keys).collect()),
+            other => other.clone(),
+        }
+    }
+
+    /// Copy of this value without the object keys whose value is null, at
+    /// every level. Nulls inside arrays are kept, since dropping them would
+    /// shift the elements after them.
+    pub fn without_nulls(&self) -> Value {
+        match self {
+            Value::Object(obj) => Value::Object(
+                obj.iter()
+                    .filter(|(_, v)| !v.is_null())
+                    .map(|(k, v)| (k.clone(), v.without_nulls()))
+                    .collect(),
+            ),
+            Value::Array(arr) => Value::Array(arr.iter().map(Value::without_nulls).collect()),
+            other => other.clone(),
+        }
+    }
+
+    /// Approximate size in bytes of this value written as compact JSON.
+    /// String escapes are not counted, and functions (which never reach the
+    /// output) count as nothing.
+    pub fn approx_size(&self) -> usize {
+        fn commas(n: usize) -> usize {
+            n.saturating_sub(1)
+        }
+        match self {
+            Value::Null => 4,
+            Value::Bool(true) => 4,
+            Value::Bool(false) => 5,
+            Value::Int(_) | Value::Float(_) => self.to_string().len(),
+            // Written as quoted literals like "2h30m" and "512Mi"
+            Value::Duration(_) | Value::Size(_) => self.to_string().len() + 2,
+            Value::String(s) => s.len() + 2,
+            Value::Array(arr) => {
+                2 + commas(arr.len()) + arr.iter().map(Value::approx_size).sum::<usize>()
+            }
+            Value::Object(obj) => {
+                2 + commas(obj.len())
+                    + obj
+                        .iter()
+                        .map(|(k, v)| k.len() + 3 + v.approx_size())
+                        .sum::<usize>()
+            }
+            Value::Function(_) => 0,
+        }
+    }
+
+    /// Dot-path of the first function inside this value (`""` for the value
+    /// itself), since output cannot contain functions
+    pub fn function_path(&self) -> Option<String> {
+        match self {
+            Value::Function(_) => Some(String::new()),
+            Value::Object(obj) => obj
+                .iter()
+                .find_map(|(key, v)| v.function_path().map(|rest| join_path(key, &rest))),
+            Value::Array(arr) => arr.iter().enumerate().find_map(|(i, v)| {
+                v.function_path()
+                    .map(|rest| join_path(&i.to_string(), &rest))
+            }),
+            _ => None,
+        }
+    }
+
     /// Try to get as boolean
     pub fn as_bool(&self) -> Option<bool> {
         match self {
@@ -212,6 +309,8 @@ impl Value {
             Value::Float(n) => serde_json::Number::from_f64(*n)
                 .map(serde_json::Value::Number)
                 .unwrap_or(serde_json::Value::Null),
+            Value::Duration(ms) => serde_json::Value::String(duration::format(*ms)),
+            Value::Size(bytes) => serde_json::Value::String(size::format(*bytes)),
             Value::String(s) => serde_json::Value::String(s.clone()),
             Value::Array(arr) => {
                 serde_json::Value::Array(arr.iter().map(|v| v.to_serde_json()).collect())
@@ -223,6 +322,8 @@ impl Value {
                     .collect();
                 serde_json::Value::Object(map)
             }
+            // Output is checked for functions before it gets here
+            Value::Function(_) => serde_json::Value::Null,
         }
     }
 
@@ -253,6 +354,14 @@ impl Value {
     }
 }
 
+fn join_path(head: &str, rest: &str) -> String {
+    if rest.is_empty() {
+        head.to_string()
+    } else {
+        format!("{}.{}", head, rest)
+    }
+}
+
 impl PartialOrd for Value {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         match (self, other) {
@@ -262,6 +371,9 @@ impl PartialOrd for Value {
             (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
             (Value::Int(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
             (Value::Float(a), Value::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (Value::Duration(a), Value::Duration(b)) | (Value::Size(a), Value::Size(b)) => {
+                a.partial_cmp(b)
+            }
             (Value::String(a), Value::String(b)) => a.partial_cmp(b),
             _ => None,
         }
@@ -281,6 +393,8 @@ impl fmt::Display for Value {
                     write!(f, "{}", n)
                 }
             }
+            Value::Duration(ms) => write!(f, "{}", duration::format(*ms)),
+            Value::Size(bytes) => write!(f, "{}", size::format(*bytes)),
             Value::String(s) => write!(f, "{}", s),
             Value::Array(arr) => {
                 write!(f, "[")?;
@@ -302,6 +416,7 @@ impl fmt::Display for Value {
                 }
                 write!(f, "}}")
             }
+            Value::Function(func) => write!(f, "{}", func.signature()),
         }
     }
 }
@@ -363,12 +478,29 @@ mod tests {
         assert_eq!(Value::Null.type_name(), "null");
         assert_eq!(Value::Bool(true).type_name(), "bool");
         assert_eq!(Value::Int(42).type_name(), "int");
-        assert_eq!(Value::Float(3.14).type_name(), "float");
+        assert_eq!(Value::Float(3.25).type_name(), "float");
         assert_eq!(Value::String("hello".into()).type_name(), "string");
         assert_eq!(Value::Array(vec![]).type_name(), "array");
         assert_eq!(Value::Object(IndexMap::new()).type_name(), "object");
     }
 
+    #[test]
+    fn test_approx_size_matches_compact_json() {
+        let mut obj = IndexMap::new();
+        obj.insert("name".to_string(), Value::String("api".into()));
+        obj.insert(
+            "ports".to_string(),
+            Value::Array(vec![Value::Int(80), Value::Int(443)]),
+        );
+        obj.insert("ratio".to_string(), Value::Float(0.5));
+        obj.insert("on".to_string(), Value::Bool(false));
+        obj.insert("none".to_string(), Value::Null);
+        obj.insert("timeout".to_string(), Value::Duration(30_000));
+        let value = Value::Object(obj);
+        let json = crate::emitter::emit(&value, crate::emitter::OutputFormat::Json).unwrap();
+        assert_eq!(value.approx_size(), json.len(), "{}", json);
+    }
+
     #[test]
     fn test_truthiness() {
         assert!(!Value::Null.is_truthy());
@@ -420,7 +552,7 @@ mod tests {
         assert_eq!(format!("{}", Value::Null), "null");
         assert_eq!(format!("{}", Value::Bool(true)), "true");
         assert_eq!(format!("{}", Value::Int(42)), "42");
-        assert_eq!(format!("{}", Value::Float(3.14)), "3.14");
+        assert_eq!(format!("{}", Value::Float(3.25)), "3.25");
         assert_eq!(format!("{}", Value::String("hello".into())), "hello");
     }
 
@@ -428,7 +560,7 @@ mod tests {
     fn test_from_conversions() {
         let _: Value = true.into();
         let _: Value = 42i64.into();
-        let _: Value = 3.14f64.into();
+        let _: Value = 3.25f64.into();
         let _: Value = "hello".into();
         let _: Value = vec![1i64, 2, 3].into();
     }
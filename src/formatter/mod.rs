@@ -8,6 +8,8 @@
 //! - Idempotent (formatting twice produces the same result)
 
 use crate::errors::HoneResult;
+use crate::lexer::number::{self, Number};
+use crate::lexer::token::SourceLocation;
 use crate::lexer::{Comment, Lexer};
 use crate::parser::ast::*;
 use crate::parser::Parser;
@@ -21,13 +23,20 @@ pub fn format_source(source: &str) -> HoneResult<String> {
     let mut parser = Parser::new(tokens, source, None);
     let ast = parser.parse()?;
 
-    let mut formatter = Formatter::new(comments);
-    formatter.format_file(&ast);
-    Ok(formatter.finish())
+    Ok(format_file(&ast, source, comments))
+}
+
+/// Format a parsed file, placing the `comments` lexed from its `source`
+pub fn format_file(ast: &File, source: &str, comments: Vec<Comment>) -> String {
+    let mut formatter = Formatter::new(source, comments);
+    formatter.format_file(ast);
+    formatter.finish()
 }
 
 /// Formatter state
-struct Formatter {
+struct Formatter<'a> {
+    /// Source the AST was parsed from, for literals kept as written
+    source: &'a str,
     /// Output buffer
     output: String,
     /// Current indentation level
@@ -40,9 +49,10 @@ struct Formatter {
     current_line: usize,
 }
 
-impl Formatter {
-    fn new(comments: Vec<Comment>) -> Self {
+impl<'a> Formatter<'a> {
+    fn new(source: &'a str, comments: Vec<Comment>) -> Self {
         Self {
+            source,
             output: String::new(),
             indent: 0,
             comments,
@@ -70,7 +80,7 @@ impl Formatter {
             if comment.is_block {
                 self.output.push_str(&format!("/* {} */", comment.text));
             } else {
-                self.output.push_str(&format!("# {}", comment.text));
+                self.output.push_str(&line_comment(&comment.text));
             }
             self.output.push('\n');
         }
@@ -89,7 +99,7 @@ impl Formatter {
             if comment.is_block {
                 self.output.push_str(&format!("/* {} */", comment.text));
             } else {
-                self.output.push_str(&format!("# {}", comment.text));
+                self.output.push_str(&line_comment(&comment.text));
             }
             self.output.push('\n');
             self.current_line = comment.line + 1;
@@ -106,7 +116,8 @@ impl Formatter {
                 if comment.is_block {
                     self.output.push_str(&format!(" /* {} */", comment.text));
                 } else {
-                    self.output.push_str(&format!(" # {}", comment.text));
+                    self.output.push(' ');
+                    self.output.push_str(&line_comment(&comment.text));
                 }
             }
         }
@@ -176,6 +187,7 @@ impl Formatter {
             PreambleItem::Import(i) => i.location.line,
             PreambleItem::Schema(s) => s.location.line,
             PreambleItem::TypeAlias(t) => t.location.line,
+            PreambleItem::Enum(e) => e.location.line,
             PreambleItem::Use(u) => u.location.line,
             PreambleItem::Variant(v) => v.location.line,
             PreambleItem::Expect(e) => e.location.line,
@@ -194,6 +206,8 @@ impl Formatter {
             BodyItem::Assert(a) => a.location.line,
             BodyItem::Let(l) => l.location.line,
             BodyItem::Spread(s) => s.location.line,
+            BodyItem::Use(u) => u.location.line,
+            BodyItem::Include(i) => i.location.line,
         }
     }
 
@@ -203,12 +217,31 @@ impl Formatter {
         std::mem::discriminant(current) != std::mem::discriminant(next)
     }
 
+    fn format_let_target(&mut self, binding: &LetBinding) {
+        match &binding.pattern {
+            None => self.output.push_str(&binding.name),
+            Some(LetPattern::Object(names)) if names.is_empty() => self.output.push_str("{}"),
+            Some(LetPattern::Object(names)) => {
+                self.output.push_str("{ ");
+                self.output.push_str(&names.join(", "));
+                self.output.push_str(" }");
+            }
+            Some(LetPattern::Array { names, rest }) => {
+                let mut parts: Vec<String> = names.clone();
+                parts.extend(rest.iter().map(|r| format!("...{}", r)));
+                self.output.push('[');
+                self.output.push_str(&parts.join(", "));
+                self.output.push(']');
+            }
+        }
+    }
+
     fn format_preamble_item(&mut self, item: &PreambleItem) {
         match item {
             PreambleItem::Let(binding) => {
                 self.write_indent();
                 self.output.push_str("let ");
-                self.output.push_str(&binding.name);
+                self.format_let_target(binding);
                 self.output.push_str(" = ");
                 self.format_expr(&binding.value);
                 self.emit_inline_comment(binding.location.line);
@@ -242,6 +275,9 @@ impl Formatter {
                             if i > 0 {
                                 self.output.push_str(", ");
                             }
+                            if name.is_schema {
+                                self.output.push_str("schema ");
+                            }
                             self.output.push_str(&name.name);
                             if let Some(ref alias) = name.alias {
                                 self.output.push_str(" as ");
@@ -290,8 +326,21 @@ impl Formatter {
                 self.emit_inline_comment(alias.location.line);
                 self.output.push('\n');
             }
+            PreambleItem::Enum(def) => {
+                self.write_indent();
+                self.output.push_str("enum ");
+                self.output.push_str(&def.name);
+                self.output.push_str(" { ");
+                self.output.push_str(&def.values.join(", "));
+                self.output.push_str(" }");
+                self.emit_inline_comment(def.location.line);
+                self.output.push('\n');
+            }
             PreambleItem::Use(use_stmt) => {
                 self.write_indent();
+                if use_stmt.partial {
+                    self.output.push_str("partial ");
+                }
                 self.output.push_str("use ");
                 self.output.push_str(&use_stmt.schema_name);
                 self.emit_inline_comment(use_stmt.location.line);
@@ -301,6 +350,10 @@ impl Formatter {
                 self.write_indent();
                 self.output.push_str("variant ");
                 self.output.push_str(&variant.name);
+                if let Some(ref enum_name) = variant.enum_name {
+                    self.output.push_str(": ");
+                    self.output.push_str(enum_name);
+                }
                 self.output.push_str(" {\n");
                 self.indent += 1;
                 for (i, case) in variant.cases.iter().enumerate() {
@@ -398,32 +451,40 @@ impl Formatter {
 
     fn format_type_constraint(&mut self, constraint: &TypeConstraint) {
         self.output.push_str(&constraint.name);
-        if !constraint.args.is_empty() {
-            self.output.push('(');
-            for (i, arg) in constraint.args.iter().enumerate() {
-                if i > 0 {
-                    self.output.push_str(", ");
-                }
-                self.format_expr(arg);
+        self.format_type_args(&constraint.args, &constraint.named_args);
+    }
+
+    fn format_type_args(&mut self, args: &[Expr], named_args: &[NamedTypeArg]) {
+        if args.is_empty() && named_args.is_empty() {
+            return;
+        }
+        self.output.push('(');
+        for (i, arg) in args.iter().enumerate() {
+            if i > 0 {
+                self.output.push_str(", ");
             }
-            self.output.push(')');
+            self.format_expr(arg);
         }
+        for (i, arg) in named_args.iter().enumerate() {
+            if i > 0 || !args.is_empty() {
+                self.output.push_str(", ");
+            }
+            self.output.push_str(&arg.name);
+            self.output.push_str(": ");
+            self.format_expr(&arg.value);
+        }
+        self.output.push(')');
     }
 
     fn format_type_expr(&mut self, expr: &TypeExpr) {
         match expr {
-            TypeExpr::Named { name, args } => {
+            TypeExpr::Named {
+                name,
+                args,
+                named_args,
+            } => {
                 self.output.push_str(name);
-                if !args.is_empty() {
-                    self.output.push('(');
-                    for (i, arg) in args.iter().enumerate() {
-                        if i > 0 {
-                            self.output.push_str(", ");
-                        }
-                        self.format_expr(arg);
-                    }
-                    self.output.push(')');
-                }
+                self.format_type_args(args, named_args);
             }
             TypeExpr::Array(inner) => {
                 self.output.push_str("array<");
@@ -442,6 +503,11 @@ impl Formatter {
                     self.format_type_expr(t);
                 }
             }
+            TypeExpr::StringLiteral(s) => {
+                self.output.push('"');
+                self.output.push_str(&escape_string(s));
+                self.output.push('"');
+            }
         }
     }
 
@@ -471,6 +537,9 @@ impl Formatter {
         match item {
             BodyItem::KeyValue(kv) => {
                 self.write_indent();
+                if kv.is_final {
+                    self.output.push_str("final ");
+                }
                 self.format_key(&kv.key);
                 match kv.op {
                     AssignOp::Colon => self.output.push_str(": "),
@@ -483,6 +552,9 @@ impl Formatter {
             }
             BodyItem::Block(block) => {
                 self.write_indent();
+                if block.is_final {
+                    self.output.push_str("final ");
+                }
                 self.output.push_str(&block.name);
                 self.output.push_str(" {\n");
                 self.indent += 1;
@@ -515,7 +587,7 @@ impl Formatter {
             BodyItem::Let(binding) => {
                 self.write_indent();
                 self.output.push_str("let ");
-                self.output.push_str(&binding.name);
+                self.format_let_target(binding);
                 self.output.push_str(" = ");
                 self.format_expr(&binding.value);
                 self.emit_inline_comment(binding.location.line);
@@ -528,6 +600,20 @@ impl Formatter {
                 self.emit_inline_comment(spread.location.line);
                 self.output.push('\n');
             }
+            BodyItem::Use(use_stmt) => {
+                self.write_indent();
+                self.output.push_str("use ");
+                self.output.push_str(&use_stmt.schema_name);
+                self.emit_inline_comment(use_stmt.location.line);
+                self.output.push('\n');
+            }
+            BodyItem::Include(include) => {
+                self.write_indent();
+                self.output.push_str("include ");
+                self.format_string_expr(&include.path);
+                self.emit_inline_comment(include.location.line);
+                self.output.push('\n');
+            }
         }
     }
 
@@ -547,22 +633,39 @@ impl Formatter {
         }
     }
 
+    /// A number literal's text as written, when it has a radix prefix or
+    /// separators and still reads as `value`
+    fn spelling(&self, loc: &SourceLocation, value: Number) -> Option<&'a str> {
+        let text = self.source.get(loc.offset..loc.offset + loc.length)?;
+        (number::is_spelled(text) && number::parse(text) == Some(value)).then_some(text)
+    }
+
     fn format_expr(&mut self, expr: &Expr) {
         match expr {
             Expr::Null(_) => self.output.push_str("null"),
             Expr::Bool(b, _) => {
                 self.output.push_str(if *b { "true" } else { "false" });
             }
-            Expr::Integer(n, _) => {
-                self.output.push_str(&n.to_string());
-            }
-            Expr::Float(n, _) => {
-                let s = format!("{}", n);
-                self.output.push_str(&s);
-                // Ensure there's a decimal point
-                if !s.contains('.') && !s.contains('e') && !s.contains('E') {
-                    self.output.push_str(".0");
+            Expr::Integer(n, loc) => match self.spelling(loc, Number::Int(*n)) {
+                Some(text) => self.output.push_str(text),
+                None => self.output.push_str(&n.to_string()),
+            },
+            Expr::Float(n, loc) => match self.spelling(loc, Number::Float(*n)) {
+                Some(text) => self.output.push_str(text),
+                None => {
+                    let s = format!("{}", n);
+                    self.output.push_str(&s);
+                    // Ensure there's a decimal point
+                    if !s.contains('.') && !s.contains('e') && !s.contains('E') {
+                        self.output.push_str(".0");
+                    }
                 }
+            },
+            Expr::Duration(ms, _) => {
+                self.output.push_str(&crate::lexer::duration::format(*ms));
+            }
+            Expr::Size(bytes, _) => {
+                self.output.push_str(&crate::lexer::size::format(*bytes));
             }
             Expr::String(s) => {
                 self.format_string_expr(s);
@@ -593,6 +696,13 @@ impl Formatter {
             Expr::Object(obj) => {
                 self.format_inline_object(obj);
             }
+            Expr::Block(obj) => {
+                self.output.push_str("block ");
+                self.format_inline_object(obj);
+            }
+            Expr::Binary(bin) if bin.op == BinaryOp::Pipe => {
+                self.format_pipeline(bin);
+            }
             Expr::Binary(bin) => {
                 self.format_expr(&bin.left);
                 let op_str = match bin.op {
@@ -610,6 +720,7 @@ impl Formatter {
                     BinaryOp::And => " && ",
                     BinaryOp::Or => " || ",
                     BinaryOp::NullCoalesce => " ?? ",
+                    BinaryOp::Pipe => " |> ",
                 };
                 self.output.push_str(op_str);
                 self.format_expr(&bin.right);
@@ -658,10 +769,55 @@ impl Formatter {
             Expr::For(for_loop) => {
                 self.format_for_loop(for_loop);
             }
+            Expr::Lambda(lambda) => {
+                self.output.push('(');
+                self.output.push_str(&lambda.params.join(", "));
+                self.output.push_str(") => ");
+                self.format_expr(&lambda.body);
+            }
             Expr::When(when) => {
                 self.format_when_inline(when);
             }
+            Expr::Match(m) => self.format_match(m),
+        }
+    }
+
+    /// Format a `match` with one arm per line
+    fn format_match(&mut self, m: &MatchExpr) {
+        self.output.push_str("match ");
+        self.format_expr(&m.subject);
+        self.output.push_str(" {");
+        // A comment belongs to the last arm that starts on its line; one
+        // after an arm sharing the `match` line ends the whole expression
+        let mut lines: Vec<usize> = m.arms.iter().map(|arm| arm.location.line).collect();
+        lines.push(usize::MAX);
+        if lines[0] != m.location.line {
+            self.emit_inline_comment(m.location.line);
+        }
+        self.indent += 1;
+        for (i, arm) in m.arms.iter().enumerate() {
+            self.output.push('\n');
+            self.write_indent();
+            if arm.is_wildcard() {
+                self.output.push('_');
+            }
+            for (i, pattern) in arm.patterns.iter().enumerate() {
+                if i > 0 {
+                    self.output.push_str(", ");
+                }
+                self.format_expr(pattern);
+            }
+            self.output.push_str(" => ");
+            self.format_expr(&arm.value);
+            let line = arm.location.line;
+            if lines[i + 1] > line && (line != m.location.line || i + 1 < m.arms.len()) {
+                self.emit_inline_comment(line);
+            }
         }
+        self.indent -= 1;
+        self.output.push('\n');
+        self.write_indent();
+        self.output.push('}');
     }
 
     fn format_string_expr(&mut self, s: &StringExpr) {
@@ -687,11 +843,65 @@ impl Formatter {
                     self.format_expr(expr);
                     self.output.push('}');
                 }
+                StringPart::Formatted(expr, spec) => {
+                    self.output.push_str("${");
+                    self.format_expr(expr);
+                    self.output.push(':');
+                    self.output.push_str(&spec.to_string());
+                    self.output.push('}');
+                }
             }
         }
         self.output.push('"');
     }
 
+    /// Format a `|>` chain. A chain written over several lines keeps one
+    /// stage per line, indented under its first line.
+    fn format_pipeline(&mut self, bin: &BinaryExpr) {
+        let mut stages = vec![bin.right.as_ref()];
+        let mut head = bin.left.as_ref();
+        while let Expr::Binary(inner) = head {
+            if inner.op != BinaryOp::Pipe {
+                break;
+            }
+            stages.push(inner.right.as_ref());
+            head = inner.left.as_ref();
+        }
+        stages.reverse();
+        // Broken over lines if a line break comes before any `|>`
+        let mut previous = head;
+        let mut multiline = false;
+        for stage in &stages {
+            let end = previous.location().offset + previous.location().length;
+            let gap = self.source.get(end..stage.location().offset).unwrap_or("");
+            multiline |= gap.contains('\n');
+            previous = stage;
+        }
+
+        self.format_expr(head);
+        if !multiline {
+            for stage in stages {
+                self.output.push_str(" |> ");
+                self.format_expr(stage);
+            }
+            return;
+        }
+        self.emit_inline_comment(head.location().line);
+        self.indent += 1;
+        let last = stages.len() - 1;
+        for (i, stage) in stages.into_iter().enumerate() {
+            self.output.push('\n');
+            self.write_indent();
+            self.output.push_str("|> ");
+            self.format_expr(stage);
+            // The last stage's comment ends the whole line
+            if i < last {
+                self.emit_inline_comment(stage.location().line);
+            }
+        }
+        self.indent -= 1;
+    }
+
     fn format_array(&mut self, arr: &ArrayExpr) {
         if arr.elements.is_empty() {
             self.output.push_str("[]");
@@ -749,6 +959,8 @@ impl Formatter {
                 | Expr::Bool(_, _)
                 | Expr::Integer(_, _)
                 | Expr::Float(_, _)
+                | Expr::Duration(_, _)
+                | Expr::Size(_, _)
                 | Expr::Ident(_, _)
         ) || matches!(expr, Expr::String(s) if s.parts.len() == 1 && matches!(&s.parts[0], StringPart::Literal(t) if t.len() < 30))
             || matches!(expr, Expr::Unary(u) if matches!(u.op, UnaryOp::Neg) && matches!(u.operand.as_ref(), Expr::Integer(_, _) | Expr::Float(_, _)))
@@ -835,9 +1047,18 @@ impl Formatter {
                 self.output.push_str(v);
                 self.output.push(')');
             }
+            ForBinding::Indexed(x, i) => {
+                self.output.push_str(x);
+                self.output.push_str(", ");
+                self.output.push_str(i);
+            }
         }
         self.output.push_str(" in ");
         self.format_expr(&for_loop.iterable);
+        if let Some(filter) = &for_loop.filter {
+            self.output.push_str(" where ");
+            self.format_expr(filter);
+        }
         self.output.push(' ');
 
         match &for_loop.body {
@@ -915,6 +1136,9 @@ impl Formatter {
     fn format_body_item_inline(&mut self, item: &BodyItem) {
         match item {
             BodyItem::KeyValue(kv) => {
+                if kv.is_final {
+                    self.output.push_str("final ");
+                }
                 self.format_key(&kv.key);
                 match kv.op {
                     AssignOp::Colon => self.output.push_str(": "),
@@ -935,10 +1159,25 @@ impl Formatter {
     }
 }
 
-/// Escape a string for output in double quotes
-fn escape_string(s: &str) -> String {
+/// A `#` comment with the `text` the lexer kept. A `##` doc comment's text
+/// starts with the second `#`, which stays attached to the first.
+fn line_comment(text: &str) -> String {
+    if text.starts_with('#') {
+        format!("#{}", text)
+    } else {
+        format!("# {}", text)
+    }
+}
+
+/// Escape a string for output in double quotes.
+///
+/// Equivalent escapes normalize to one spelling: printable characters are
+/// written as themselves (`\x41` and `\u{41}` become `A`), other control
+/// characters as `\u{..}`, and a literal `${` as `\${` so it stays literal.
+pub(crate) fn escape_string(s: &str) -> String {
     let mut result = String::with_capacity(s.len());
-    for ch in s.chars() {
+    let mut chars = s.chars().peekable();
+    while let Some(ch) = chars.next() {
         match ch {
             '"' => result.push_str("\\\""),
             '\\' => result.push_str("\\\\"),
@@ -946,6 +1185,8 @@ fn escape_string(s: &str) -> String {
             '\r' => result.push_str("\\r"),
             '\t' => result.push_str("\\t"),
             '\0' => result.push_str("\\0"),
+            '$' if chars.peek() == Some(&'{') => result.push_str("\\$"),
+            c if c.is_control() => result.push_str(&format!("\\u{{{:x}}}", c as u32)),
             c => result.push(c),
         }
     }
@@ -963,6 +1204,48 @@ mod tests {
         assert_eq!(formatted, "name: \"hello\"\nport: 8080\n");
     }
 
+    #[test]
+    fn test_format_keeps_doc_comments() {
+        let source = "## Public port\nport: 8080 ## inline\n# plain\nhost: \"a\"\n";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(formatted, source);
+    }
+
+    #[test]
+    fn test_format_keeps_format_specs() {
+        let source = "a: \"${ratio:.2} ${port:05} ${x ? 1 : 2:>4}\"\n";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(formatted, source);
+    }
+
+    #[test]
+    fn test_format_keeps_named_type_args() {
+        let source = "type Label = string(1,63,pattern:\"^[a-z]+$\")\n\nschema S {\n  name: string( pattern: \"^[a-z]+$\" )\n}\n";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "type Label = string(1, 63, pattern: \"^[a-z]+$\")\n\nschema S {\n  name: string(pattern: \"^[a-z]+$\")\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_number_spelling() {
+        let source = "mode:0o644\nmask: -0b1010\nbig: 1_000_000\nrate: 1_000.5\nplain: 1.50\n";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "mode: 0o644\nmask: -0b1010\nbig: 1_000_000\nrate: 1_000.5\nplain: 1.5\n"
+        );
+    }
+
+    #[test]
+    fn test_format_normalizes_escapes() {
+        let source = "a: \"\\x41\\u{42} \\u{1F600}\"\nb: \"\\${literal} \\u{1b}[0m\"\n";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(formatted, "a: \"AB 😀\"\nb: \"\\${literal} \\u{1b}[0m\"\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
     #[test]
     fn test_format_let_bindings() {
         let source = "let   x  =  42\nlet y = \"hello\"\nvalue: x\n";
@@ -970,6 +1253,38 @@ mod tests {
         assert_eq!(formatted, "let x = 42\nlet y = \"hello\"\n\nvalue: x\n");
     }
 
+    #[test]
+    fn test_format_let_destructuring() {
+        let source = "let {host,port}=server\nlet [ first , ...rest ] = items\nvalue: [host, port, first, rest]\n";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "let { host, port } = server\nlet [first, ...rest] = items\n\nvalue: [host, port, first, rest]\n"
+        );
+    }
+
+    #[test]
+    fn test_format_match() {
+        let source = "server {\n  replicas: match env { \"prod\"=>5,\"staging\" ,\"qa\"=>2 # small\n_=>1 }\n}\n";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "server {\n  replicas: match env {\n    \"prod\" => 5\n    \"staging\", \"qa\" => 2 # small\n    _ => 1\n  }\n}\n"
+        );
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_format_block_value() {
+        let source = "let probes = block{ports+:[80]}\nlet checks = block {\nliveness{path:\"/healthz\"}\n}\n";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "let probes = block {\n  ports +: [80]\n}\nlet checks = block {\n  liveness {\n    path: \"/healthz\"\n  }\n}\n"
+        );
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
     #[test]
     fn test_format_block() {
         let source = "server{host:\"localhost\"\nport:8080}";
@@ -1048,6 +1363,30 @@ when env == "production" {
         assert!(formatted.contains("import { port, host } from \"./config.hone\""));
     }
 
+    #[test]
+    fn test_format_named_schema_import() {
+        let source =
+            "import { schema  Server as S } from \"./schemas.hone\"\n\nuse config.Server\nval: 1";
+        let formatted = format_source(source).unwrap();
+        assert!(formatted.contains("import { schema Server as S } from \"./schemas.hone\""));
+        assert!(formatted.contains("use config.Server"));
+    }
+
+    #[test]
+    fn test_format_use_in_when() {
+        let source = "when env==\"prod\"{\nuse   ProdServer\n}";
+        let formatted = format_source(source).unwrap();
+        assert!(formatted.contains("  use ProdServer\n"));
+    }
+
+    #[test]
+    fn test_format_include() {
+        let source = "web{\ninclude    \"./probes.hone\"  # shared\n}";
+        let formatted = format_source(source).unwrap();
+        assert!(formatted.contains("  include \"./probes.hone\" # shared\n"));
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
     #[test]
     fn test_format_when() {
         let source = "let env=\"prod\"\nwhen env==\"prod\"{replicas:3}";
@@ -1106,6 +1445,20 @@ when env == "production" {
         assert!(formatted.contains("config !: { b: 2 }"));
     }
 
+    #[test]
+    fn test_format_pipeline() {
+        assert_eq!(
+            format_source("x: xs|>sort( )|>len()").unwrap(),
+            "x: xs |> sort() |> len()\n"
+        );
+        let source = "x: xs\n  |> filter((v) => v > 1) # big\n  |> sort()\n";
+        assert_eq!(
+            format_source("x: xs\n|> filter((v) => v > 1)   # big\n      |> sort()").unwrap(),
+            source
+        );
+        assert_eq!(format_source(source).unwrap(), source);
+    }
+
     #[test]
     fn test_format_empty_source() {
         let formatted = format_source("").unwrap();
@@ -0,0 +1,234 @@
+//! Compiled values written back as Hone source for `hone freeze`
+//!
+//! A frozen file is a library evaluated with fixed inputs: every `let`,
+//! `when`, `for` and `args.*` reference is resolved, and what remains is the
+//! value as plain key-value pairs. Nested objects are written as blocks,
+//! durations and sizes as literals, and `##` doc comments are carried over
+//! for keys outside arrays. The result is run through the formatter and
+//! compiled once more to check it reads back as the same value.
+
+use std::path::Path;
+
+use crate::compiler::Compiler;
+use crate::errors::{HoneError, HoneResult};
+use crate::evaluator::{DocMap, Value};
+use crate::formatter::{escape_string, format_source};
+use crate::lexer::token::TokenKind;
+use crate::lexer::{duration, size};
+
+/// Hone source for `value`, a compiled file's output, with `header` as
+/// `#` comment lines at the top and the `docs` of its keys above them
+pub fn freeze(value: &Value, docs: &DocMap, header: &[String]) -> HoneResult<String> {
+    let Value::Object(obj) = value else {
+        return Err(HoneError::compilation_error(format!(
+            "cannot freeze a {}: a Hone file's output is an object",
+            value.type_name()
+        )));
+    };
+
+    let mut body = String::new();
+    Writer { docs }.body(obj, Some(""), 0, &mut body)?;
+    let body = format_source(&body)?;
+    let reread = Compiler::new(Path::new(".")).compile_source(&body)?;
+    if reread != *value {
+        return Err(HoneError::compilation_error(
+            "frozen source does not read back as the compiled value".to_string(),
+        ));
+    }
+
+    let mut source = String::new();
+    for line in header {
+        source.push_str(format!("# {}", line).trim_end());
+        source.push('\n');
+    }
+    if !header.is_empty() {
+        source.push('\n');
+    }
+    source.push_str(&body);
+    Ok(source)
+}
+
+struct Writer<'a> {
+    docs: &'a DocMap,
+}
+
+impl Writer<'_> {
+    /// Write the keys of `obj` one per line. `path` is the object's dot-path,
+    /// or `None` inside an array, where keys get no doc comments.
+    fn body(
+        &self,
+        obj: &indexmap::IndexMap<String, Value>,
+        path: Option<&str>,
+        depth: usize,
+        out: &mut String,
+    ) -> HoneResult<()> {
+        let pad = "  ".repeat(depth);
+        for (key, value) in obj {
+            let key_path = path.map(|p| {
+                if p.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", p, key)
+                }
+            });
+            if let Some(doc) = key_path.as_ref().and_then(|p| self.docs.get(p)) {
+                for line in doc.lines() {
+                    out.push_str(format!("{}## {}", pad, line).trim_end());
+                    out.push('\n');
+                }
+            }
+            match value {
+                Value::Object(child) if !child.is_empty() && is_ident(key) => {
+                    out.push_str(&format!("{}{} {{\n", pad, key));
+                    self.body(child, key_path.as_deref(), depth + 1, out)?;
+                    out.push_str(&format!("{}}}\n", pad));
+                }
+                _ => {
+                    out.push_str(&format!("{}{}: ", pad, format_key(key)));
+                    self.expr(value, key_path.as_deref(), depth, out)?;
+                    out.push('\n');
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn expr(
+        &self,
+        value: &Value,
+        path: Option<&str>,
+        depth: usize,
+        out: &mut String,
+    ) -> HoneResult<()> {
+        match value {
+            Value::Null => out.push_str("null"),
+            Value::Bool(b) => out.push_str(&b.to_string()),
+            Value::Int(n) => out.push_str(&n.to_string()),
+            Value::Float(n) if n.is_finite() => out.push_str(&format!("{:?}", n)),
+            Value::Float(n) => {
+                return Err(HoneError::compilation_error(format!(
+                    "cannot freeze {}: Hone has no literal for it",
+                    n
+                )))
+            }
+            Value::Duration(ms) => out.push_str(&duration::format(*ms)),
+            Value::Size(bytes) => out.push_str(&size::format(*bytes)),
+            Value::String(s) => out.push_str(&quote(s)),
+            Value::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    self.expr(item, None, depth + 1, out)?;
+                }
+                out.push(']');
+            }
+            Value::Object(obj) if obj.is_empty() => out.push_str("{}"),
+            Value::Object(obj) => {
+                out.push_str("{\n");
+                self.body(obj, path, depth + 1, out)?;
+                out.push_str(&format!("{}}}", "  ".repeat(depth)));
+            }
+            Value::Function(_) => {
+                return Err(HoneError::compilation_error(format!(
+                    "cannot freeze a {}: functions are not output values",
+                    value.type_name()
+                )))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `key` can be written bare
+fn is_ident(key: &str) -> bool {
+    key.chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        && TokenKind::keyword_from_str(key).is_none()
+}
+
+fn format_key(key: &str) -> String {
+    if is_ident(key) {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", escape_string(s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(source: &str) -> (Value, DocMap) {
+        let mut compiler = Compiler::new(Path::new("."));
+        let value = compiler.compile_source(source).unwrap();
+        (value, compiler.doc_comments().clone())
+    }
+
+    #[test]
+    fn test_freeze_resolves_expressions() {
+        let (value, docs) = compile(
+            r#"
+let replicas = 2 * 3
+let regions = ["eu", "us"]
+
+## The service name
+name: "api-${regions[0]}"
+server {
+  ## Seconds before a request is dropped
+  timeout: 30s
+  limits { memory: 512Mi }
+}
+zones: [for r in regions { { name: r, primary: r == "eu" } }]
+replicas: replicas
+"type": "Deployment"
+"content type": "literal \${x}\n"
+ratio: 0.1 + 0.2
+"#,
+        );
+        let frozen = freeze(&value, &docs, &["Frozen from lib.hone".to_string()]).unwrap();
+        assert_eq!(
+            frozen,
+            r#"# Frozen from lib.hone
+
+## The service name
+name: "api-eu"
+
+server {
+  ## Seconds before a request is dropped
+  timeout: 30s
+
+  limits {
+    memory: 512Mi
+  }
+}
+
+zones: [
+  { name: "eu", primary: true },
+  { name: "us", primary: false },
+]
+replicas: 6
+"type": "Deployment"
+"content type": "literal \${x}\n"
+ratio: 0.30000000000000004
+"#
+        );
+    }
+
+    #[test]
+    fn test_freeze_rejects_non_finite_floats() {
+        let mut obj = indexmap::IndexMap::new();
+        obj.insert("x".to_string(), Value::Float(f64::NAN));
+        let err = freeze(&Value::Object(obj), &DocMap::new(), &[]).unwrap_err();
+        assert!(err.to_string().contains("no literal"), "{}", err);
+    }
+}
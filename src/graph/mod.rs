@@ -7,6 +7,7 @@ use std::path::{Path, PathBuf};
 
 use crate::errors::{HoneError, HoneResult};
 use crate::resolver::ImportResolver;
+use crate::style::{self, Style};
 
 /// Output format for graph visualization
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -49,10 +50,21 @@ struct GraphEdge {
 enum EdgeKind {
     Import,
     From,
+    Include,
 }
 
 /// Generate a dependency graph for a file and all its imports
 pub fn generate_graph(path: impl AsRef<Path>, format: GraphFormat) -> HoneResult<String> {
+    generate_graph_with(path, format, &Style::plain())
+}
+
+/// Generate a dependency graph, coloring and wrapping the text format by
+/// `style` (DOT and JSON are for tools and are never styled)
+pub fn generate_graph_with(
+    path: impl AsRef<Path>,
+    format: GraphFormat,
+    style: &Style,
+) -> HoneResult<String> {
     let path = path.as_ref();
     let canonical = path.canonicalize().map_err(|e| {
         HoneError::io_error(format!("failed to resolve path {}: {}", path.display(), e))
@@ -94,12 +106,20 @@ pub fn generate_graph(path: impl AsRef<Path>, format: GraphFormat) -> HoneResult
                 kind: EdgeKind::Import,
             });
         }
+
+        for include in &resolved.include_paths {
+            edges.push(GraphEdge {
+                from: resolved.path.clone(),
+                to: include.clone(),
+                kind: EdgeKind::Include,
+            });
+        }
     }
 
     match format {
         GraphFormat::Dot => Ok(format_dot(&nodes, &edges, &canonical)),
         GraphFormat::Json => Ok(format_json(&nodes, &edges, root_dir)),
-        GraphFormat::Text => Ok(format_text(&nodes, &edges, &canonical, root_dir)),
+        GraphFormat::Text => Ok(format_text(&nodes, &edges, &canonical, root_dir, style)),
     }
 }
 
@@ -142,6 +162,7 @@ fn format_dot(nodes: &[GraphNode], edges: &[GraphEdge], root: &PathBuf) -> Strin
         let style = match edge.kind {
             EdgeKind::Import => "",
             EdgeKind::From => " [style=dashed, label=\"from\"]",
+            EdgeKind::Include => " [style=dotted, label=\"include\"]",
         };
         out.push_str(&format!("  {} -> {}{};\n", from_id, to_id, style));
     }
@@ -175,6 +196,7 @@ fn format_json(nodes: &[GraphNode], edges: &[GraphEdge], root: &Path) -> String
         let kind = match edge.kind {
             EdgeKind::Import => "import",
             EdgeKind::From => "from",
+            EdgeKind::Include => "include",
         };
         json.push_str(&format!(
             "    {{\"from\": \"{}\", \"to\": \"{}\", \"kind\": \"{}\"}}",
@@ -198,6 +220,7 @@ fn format_text(
     edges: &[GraphEdge],
     root: &PathBuf,
     root_dir: &Path,
+    style: &Style,
 ) -> String {
     // Build adjacency list
     let mut children: HashMap<PathBuf, Vec<(PathBuf, EdgeKind)>> = HashMap::new();
@@ -215,11 +238,21 @@ fn format_text(
 
     let mut visited = std::collections::HashSet::new();
     visited.insert(root.clone());
-    print_tree(&mut out, root, &children, "", true, &mut visited, root_dir);
+    print_tree(
+        &mut out,
+        root,
+        &children,
+        "",
+        true,
+        &mut visited,
+        root_dir,
+        style,
+    );
 
     out
 }
 
+#[allow(clippy::too_many_arguments)]
 fn print_tree(
     out: &mut String,
     node: &PathBuf,
@@ -228,6 +261,7 @@ fn print_tree(
     _is_root: bool,
     visited: &mut std::collections::HashSet<PathBuf>,
     root_dir: &Path,
+    style: &Style,
 ) {
     if let Some(deps) = children.get(node) {
         for (i, (dep, kind)) in deps.iter().enumerate() {
@@ -237,6 +271,7 @@ fn print_tree(
             let kind_label = match kind {
                 EdgeKind::Import => "",
                 EdgeKind::From => " (from)",
+                EdgeKind::Include => " (include)",
             };
 
             let circular = if visited.contains(dep) {
@@ -245,15 +280,29 @@ fn print_tree(
                 ""
             };
 
-            out.push_str(&format!(
-                "{}{}{}{}{}\n",
-                prefix, connector, label, kind_label, circular
-            ));
+            let line = format!(
+                "{}{}{}{}",
+                style.paint(style::DIM, &format!("{}{}", prefix, connector)),
+                label,
+                style.paint(style::DIM, kind_label),
+                style.paint(style::YELLOW, circular)
+            );
+            out.push_str(&style.wrap(&line, prefix.len() + connector.len()));
+            out.push('\n');
 
             if !visited.contains(dep) {
                 visited.insert(dep.clone());
                 let new_prefix = format!("{}{}", prefix, if is_last { "    " } else { "|   " });
-                print_tree(out, dep, children, &new_prefix, false, visited, root_dir);
+                print_tree(
+                    out,
+                    dep,
+                    children,
+                    &new_prefix,
+                    false,
+                    visited,
+                    root_dir,
+                    style,
+                );
             }
         }
     }
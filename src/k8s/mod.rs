@@ -0,0 +1,739 @@
+//! Validation of emitted Kubernetes manifests
+//!
+//! Backs `hone compile --validate k8s@1.29`. Every emitted document with an
+//! `apiVersion` and `kind` is checked in two steps:
+//!
+//! 1. Built-in resources are checked against a bundled table of API versions,
+//!    which catches typos and versions removed in the target release even
+//!    with no network access.
+//! 2. If a JSON schema for the resource is available (kubeconform's
+//!    `kubernetes-json-schema` layout), the document is validated against it.
+//!    Schemas are read from the offline cache at `~/.cache/hone/k8s-schemas/`
+//!    and downloaded there with `curl` on first use unless disabled.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value as Json;
+
+use crate::errors::{HoneError, HoneResult};
+use crate::evaluator::Value;
+
+/// Where missing schemas are downloaded from
+pub const SCHEMA_BASE_URL: &str =
+    "https://raw.githubusercontent.com/yannh/kubernetes-json-schema/master";
+
+/// A validation target like `k8s@1.29`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct K8sVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl K8sVersion {
+    /// Parse `k8s@1.29`, `kubernetes@1.29.3`, or a bare `1.29`
+    pub fn parse_target(target: &str) -> HoneResult<Self> {
+        let version = match target.split_once('@') {
+            Some(("k8s" | "kubernetes", v)) => v,
+            Some((other, _)) => {
+                return Err(HoneError::io_error(format!(
+                    "unknown validation target '{}': only k8s@<version> is supported",
+                    other
+                )))
+            }
+            None => target,
+        };
+        let invalid = || {
+            HoneError::io_error(format!(
+                "invalid Kubernetes version '{}': expected e.g. k8s@1.29",
+                target
+            ))
+        };
+
+        let parts: Vec<&str> = version.trim_start_matches('v').split('.').collect();
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err(invalid());
+        }
+        let num = |s: &str| s.parse::<u32>().map_err(|_| invalid());
+        Ok(Self {
+            major: num(parts[0])?,
+            minor: num(parts[1])?,
+            patch: parts.get(2).map(|p| num(p)).transpose()?.unwrap_or(0),
+        })
+    }
+
+    /// Directory name used by kubernetes-json-schema (`v1.29.0-standalone-strict`)
+    pub fn schema_dir_name(&self) -> String {
+        format!(
+            "v{}.{}.{}-standalone-strict",
+            self.major, self.minor, self.patch
+        )
+    }
+}
+
+impl fmt::Display for K8sVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// A single validation failure
+#[derive(Debug, Clone, PartialEq)]
+pub struct K8sViolation {
+    /// Document name (`---name`), or `main` / `doc N`
+    pub document: String,
+    /// Dotted path within the document, empty for the root
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for K8sViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}: {}", self.document, self.message)
+        } else {
+            write!(f, "{}: {}: {}", self.document, self.path, self.message)
+        }
+    }
+}
+
+/// Result of validating a set of documents
+#[derive(Debug, Default)]
+pub struct K8sReport {
+    pub violations: Vec<K8sViolation>,
+    /// Resources that had no schema available (only apiVersion/kind checked)
+    pub unchecked: Vec<String>,
+}
+
+/// Validates documents against one Kubernetes release
+pub struct K8sValidator {
+    version: K8sVersion,
+    schema_dir: Option<PathBuf>,
+    download: bool,
+}
+
+impl K8sValidator {
+    /// Create a validator using the default schema cache, downloading missing
+    /// schemas when `download` is set
+    pub fn new(version: K8sVersion, download: bool) -> Self {
+        let schema_dir = crate::cache::cache_root()
+            .map(|root| root.join("k8s-schemas").join(version.schema_dir_name()));
+        Self {
+            version,
+            schema_dir,
+            download,
+        }
+    }
+
+    /// Use a specific schema directory (no downloads)
+    pub fn with_schema_dir(version: K8sVersion, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            version,
+            schema_dir: Some(dir.into()),
+            download: false,
+        }
+    }
+
+    /// Validate named documents (as produced by `compile_multi`)
+    pub fn validate(&self, documents: &[(Option<String>, Value)]) -> K8sReport {
+        let mut report = K8sReport::default();
+        for (i, (name, value)) in documents.iter().enumerate() {
+            if name.is_none() && value.is_empty_object() {
+                continue;
+            }
+            let document = match name {
+                Some(n) => n.clone(),
+                None if i == 0 => "main".to_string(),
+                None => format!("doc {}", i),
+            };
+            self.validate_document(&document, value, &mut report);
+        }
+        report
+    }
+
+    fn validate_document(&self, document: &str, value: &Value, report: &mut K8sReport) {
+        let json = value.to_serde_json();
+        let (api_version, kind) = match (
+            json.get("apiVersion").and_then(Json::as_str),
+            json.get("kind").and_then(Json::as_str),
+        ) {
+            (Some(a), Some(k)) => (a, k),
+            // Not a Kubernetes object
+            _ => return,
+        };
+        let violation = |path: &str, message: String| K8sViolation {
+            document: document.to_string(),
+            path: path.to_string(),
+            message,
+        };
+
+        if let Err(message) = check_api_version(kind, api_version, self.version.minor) {
+            report.violations.push(violation("apiVersion", message));
+            return;
+        }
+
+        // Only built-in resources have published schemas worth fetching
+        let builtin = BUILTIN_RESOURCES
+            .iter()
+            .any(|(k, a, _, _)| *k == kind && *a == api_version);
+        match self.load_schema(kind, api_version, self.download && builtin) {
+            Some(schema) => {
+                let mut errors = Vec::new();
+                validate_schema(&json, &schema, "", &mut errors);
+                report
+                    .violations
+                    .extend(errors.into_iter().map(|(p, m)| violation(&p, m)));
+            }
+            None => {
+                let resource = format!("{} ({})", kind, api_version);
+                if !report.unchecked.contains(&resource) {
+                    report.unchecked.push(resource);
+                }
+            }
+        }
+    }
+
+    fn load_schema(&self, kind: &str, api_version: &str, download_missing: bool) -> Option<Json> {
+        let dir = self.schema_dir.as_ref()?;
+        let file_name = schema_file_name(kind, api_version);
+        let path = dir.join(&file_name);
+
+        if !path.is_file() && download_missing {
+            let url = format!(
+                "{}/{}/{}",
+                SCHEMA_BASE_URL,
+                self.version.schema_dir_name(),
+                file_name
+            );
+            download(&url, &path);
+        }
+
+        let content = std::fs::read_to_string(&path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+}
+
+/// Fetch `url` into `path` with curl; failures leave no file behind
+fn download(url: &str, path: &Path) {
+    let Some(parent) = path.parent() else {
+        return;
+    };
+    if std::fs::create_dir_all(parent).is_err() {
+        return;
+    }
+    let tmp = path.with_extension("download");
+    let ok = std::process::Command::new("curl")
+        .args(["-fsSL", "--max-time", "30", "-o"])
+        .arg(&tmp)
+        .arg(url)
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false);
+    if ok {
+        let _ = std::fs::rename(&tmp, path);
+    } else {
+        let _ = std::fs::remove_file(&tmp);
+    }
+}
+
+/// kubernetes-json-schema file name: `deployment-apps-v1.json`, `pod-v1.json`
+pub fn schema_file_name(kind: &str, api_version: &str) -> String {
+    let kind = kind.to_lowercase();
+    match api_version.split_once('/') {
+        Some((group, version)) => {
+            let group = group.split('.').next().unwrap_or(group);
+            format!("{}-{}-{}.json", kind, group, version)
+        }
+        None => format!("{}-{}.json", kind, api_version),
+    }
+}
+
+/// (kind, apiVersion, first minor release, first minor release where removed)
+const BUILTIN_RESOURCES: &[(&str, &str, u32, Option<u32>)] = &[
+    ("Binding", "v1", 0, None),
+    ("ConfigMap", "v1", 2, None),
+    ("Endpoints", "v1", 0, None),
+    ("Event", "v1", 0, None),
+    ("LimitRange", "v1", 0, None),
+    ("Namespace", "v1", 0, None),
+    ("Node", "v1", 0, None),
+    ("PersistentVolume", "v1", 0, None),
+    ("PersistentVolumeClaim", "v1", 0, None),
+    ("Pod", "v1", 0, None),
+    ("PodTemplate", "v1", 0, None),
+    ("ReplicationController", "v1", 0, None),
+    ("ResourceQuota", "v1", 0, None),
+    ("Secret", "v1", 0, None),
+    ("Service", "v1", 0, None),
+    ("ServiceAccount", "v1", 0, None),
+    ("Deployment", "apps/v1", 9, None),
+    ("Deployment", "apps/v1beta1", 6, Some(16)),
+    ("Deployment", "apps/v1beta2", 8, Some(16)),
+    ("Deployment", "extensions/v1beta1", 2, Some(16)),
+    ("DaemonSet", "apps/v1", 9, None),
+    ("DaemonSet", "apps/v1beta2", 8, Some(16)),
+    ("DaemonSet", "extensions/v1beta1", 2, Some(16)),
+    ("ReplicaSet", "apps/v1", 9, None),
+    ("ReplicaSet", "apps/v1beta2", 8, Some(16)),
+    ("ReplicaSet", "extensions/v1beta1", 2, Some(16)),
+    ("StatefulSet", "apps/v1", 9, None),
+    ("StatefulSet", "apps/v1beta1", 5, Some(16)),
+    ("StatefulSet", "apps/v1beta2", 8, Some(16)),
+    ("ControllerRevision", "apps/v1", 9, None),
+    ("Job", "batch/v1", 2, None),
+    ("CronJob", "batch/v1", 21, None),
+    ("CronJob", "batch/v1beta1", 8, Some(25)),
+    ("HorizontalPodAutoscaler", "autoscaling/v1", 2, None),
+    ("HorizontalPodAutoscaler", "autoscaling/v2", 23, None),
+    (
+        "HorizontalPodAutoscaler",
+        "autoscaling/v2beta1",
+        8,
+        Some(25),
+    ),
+    (
+        "HorizontalPodAutoscaler",
+        "autoscaling/v2beta2",
+        12,
+        Some(26),
+    ),
+    ("Ingress", "networking.k8s.io/v1", 19, None),
+    ("Ingress", "networking.k8s.io/v1beta1", 14, Some(22)),
+    ("Ingress", "extensions/v1beta1", 1, Some(22)),
+    ("IngressClass", "networking.k8s.io/v1", 19, None),
+    ("NetworkPolicy", "networking.k8s.io/v1", 7, None),
+    ("NetworkPolicy", "extensions/v1beta1", 3, Some(16)),
+    ("PodDisruptionBudget", "policy/v1", 21, None),
+    ("PodDisruptionBudget", "policy/v1beta1", 5, Some(25)),
+    ("PodSecurityPolicy", "policy/v1beta1", 10, Some(25)),
+    ("ClusterRole", "rbac.authorization.k8s.io/v1", 8, None),
+    (
+        "ClusterRoleBinding",
+        "rbac.authorization.k8s.io/v1",
+        8,
+        None,
+    ),
+    ("Role", "rbac.authorization.k8s.io/v1", 8, None),
+    ("RoleBinding", "rbac.authorization.k8s.io/v1", 8, None),
+    (
+        "ClusterRole",
+        "rbac.authorization.k8s.io/v1beta1",
+        6,
+        Some(22),
+    ),
+    (
+        "ClusterRoleBinding",
+        "rbac.authorization.k8s.io/v1beta1",
+        6,
+        Some(22),
+    ),
+    ("Role", "rbac.authorization.k8s.io/v1beta1", 6, Some(22)),
+    (
+        "RoleBinding",
+        "rbac.authorization.k8s.io/v1beta1",
+        6,
+        Some(22),
+    ),
+    ("StorageClass", "storage.k8s.io/v1", 6, None),
+    ("CSIDriver", "storage.k8s.io/v1", 18, None),
+    ("VolumeAttachment", "storage.k8s.io/v1", 13, None),
+    (
+        "CustomResourceDefinition",
+        "apiextensions.k8s.io/v1",
+        16,
+        None,
+    ),
+    (
+        "CustomResourceDefinition",
+        "apiextensions.k8s.io/v1beta1",
+        7,
+        Some(22),
+    ),
+    ("PriorityClass", "scheduling.k8s.io/v1", 14, None),
+    ("Lease", "coordination.k8s.io/v1", 14, None),
+    ("RuntimeClass", "node.k8s.io/v1", 20, None),
+    ("EndpointSlice", "discovery.k8s.io/v1", 21, None),
+    ("EndpointSlice", "discovery.k8s.io/v1beta1", 17, Some(25)),
+    (
+        "MutatingWebhookConfiguration",
+        "admissionregistration.k8s.io/v1",
+        16,
+        None,
+    ),
+    (
+        "ValidatingWebhookConfiguration",
+        "admissionregistration.k8s.io/v1",
+        16,
+        None,
+    ),
+    (
+        "ValidatingAdmissionPolicy",
+        "admissionregistration.k8s.io/v1",
+        30,
+        None,
+    ),
+    (
+        "ValidatingAdmissionPolicyBinding",
+        "admissionregistration.k8s.io/v1",
+        30,
+        None,
+    ),
+    (
+        "CertificateSigningRequest",
+        "certificates.k8s.io/v1",
+        19,
+        None,
+    ),
+    ("APIService", "apiregistration.k8s.io/v1", 10, None),
+];
+
+/// Check an apiVersion/kind pair against the bundled table of built-in
+/// resources. Resources from groups Kubernetes doesn't ship (CRDs) pass.
+pub fn check_api_version(kind: &str, api_version: &str, minor: u32) -> Result<(), String> {
+    let group = api_version.rsplit_once('/').map(|(g, _)| g).unwrap_or("");
+    let builtin_group = BUILTIN_RESOURCES
+        .iter()
+        .any(|(_, a, _, _)| a.rsplit_once('/').map(|(g, _)| g).unwrap_or("") == group)
+        && !(group.is_empty() && api_version != "v1");
+    let versions: Vec<_> = BUILTIN_RESOURCES
+        .iter()
+        .filter(|(k, _, _, _)| *k == kind)
+        .collect();
+
+    if !builtin_group && versions.is_empty() {
+        return Ok(());
+    }
+
+    let current: Vec<&str> = versions
+        .iter()
+        .filter(|(_, _, since, removed)| *since <= minor && removed.is_none_or(|r| minor < r))
+        .map(|(_, a, _, _)| *a)
+        .collect();
+    let expected = || {
+        if current.is_empty() {
+            String::new()
+        } else {
+            format!(" (use {})", current.join(" or "))
+        }
+    };
+
+    match versions.iter().find(|(_, a, _, _)| *a == api_version) {
+        Some((_, _, _, Some(removed))) if minor >= *removed => Err(format!(
+            "{} {} was removed in Kubernetes 1.{}{}",
+            api_version,
+            kind,
+            removed,
+            expected()
+        )),
+        Some((_, _, since, _)) if minor < *since => Err(format!(
+            "{} {} is not available until Kubernetes 1.{}{}",
+            api_version,
+            kind,
+            since,
+            expected()
+        )),
+        Some(_) => Ok(()),
+        None if versions.is_empty() => Err(format!(
+            "unknown kind '{}' in built-in API group '{}'",
+            kind,
+            if group.is_empty() { "core" } else { group }
+        )),
+        None => Err(format!(
+            "unknown apiVersion '{}' for kind {}{}",
+            api_version,
+            kind,
+            expected()
+        )),
+    }
+}
+
+/// Validate `value` against a (standalone, `$ref`-free) JSON schema,
+/// collecting `(path, message)` pairs
+pub fn validate_schema(
+    value: &Json,
+    schema: &Json,
+    path: &str,
+    errors: &mut Vec<(String, String)>,
+) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+
+    if schema.get("x-kubernetes-int-or-string") == Some(&Json::Bool(true)) {
+        if !(value.is_string() || value.is_i64() || value.is_u64()) {
+            errors.push((
+                path.to_string(),
+                format!("expected int or string, found {}", json_type(value)),
+            ));
+        }
+        return;
+    }
+
+    if let Some(types) = schema.get("type") {
+        let allowed: Vec<&str> = match types {
+            Json::String(t) => vec![t.as_str()],
+            Json::Array(ts) => ts.iter().filter_map(Json::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| type_matches(value, t)) {
+            errors.push((
+                path.to_string(),
+                format!(
+                    "expected {}, found {}",
+                    allowed.join(" or "),
+                    json_type(value)
+                ),
+            ));
+            return;
+        }
+    }
+
+    if let Some(Json::Array(options)) = schema.get("enum") {
+        if !options.contains(value) {
+            let names: Vec<String> = options.iter().map(|o| o.to_string()).collect();
+            errors.push((
+                path.to_string(),
+                format!("{} is not one of: {}", value, names.join(", ")),
+            ));
+        }
+    }
+
+    for key in ["oneOf", "anyOf"] {
+        if let Some(Json::Array(options)) = schema.get(key) {
+            let matches = options.iter().any(|option| {
+                let mut sub = Vec::new();
+                validate_schema(value, option, path, &mut sub);
+                sub.is_empty()
+            });
+            if !matches {
+                errors.push((
+                    path.to_string(),
+                    format!("does not match any allowed shape ({})", key),
+                ));
+            }
+        }
+    }
+    if let Some(Json::Array(all)) = schema.get("allOf") {
+        for sub in all {
+            validate_schema(value, sub, path, errors);
+        }
+    }
+
+    match value {
+        Json::Object(obj) => {
+            let properties = schema.get("properties").and_then(Json::as_object);
+            if let Some(Json::Array(required)) = schema.get("required") {
+                for field in required.iter().filter_map(Json::as_str) {
+                    if !obj.contains_key(field) {
+                        errors.push((join(path, field), "required field is missing".to_string()));
+                    }
+                }
+            }
+            for (key, field_value) in obj {
+                let field_path = join(path, key);
+                match properties.and_then(|p| p.get(key)) {
+                    Some(field_schema) => {
+                        validate_schema(field_value, field_schema, &field_path, errors)
+                    }
+                    None => match schema.get("additionalProperties") {
+                        Some(Json::Bool(false)) => {
+                            errors.push((field_path, "unknown field".to_string()))
+                        }
+                        Some(extra @ Json::Object(_)) => {
+                            validate_schema(field_value, extra, &field_path, errors)
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        }
+        Json::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_schema(item, item_schema, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn type_matches(value: &Json, ty: &str) -> bool {
+    match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn json_type(value: &Json) -> &'static str {
+    match value {
+        Json::Null => "null",
+        Json::Bool(_) => "boolean",
+        Json::Number(n) if n.is_f64() => "number",
+        Json::Number(_) => "integer",
+        Json::String(_) => "string",
+        Json::Array(_) => "array",
+        Json::Object(_) => "object",
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn value(json: Json) -> Value {
+        fn convert(json: &Json) -> Value {
+            match json {
+                Json::Null => Value::Null,
+                Json::Bool(b) => Value::Bool(*b),
+                Json::Number(n) => n
+                    .as_i64()
+                    .map(Value::Int)
+                    .unwrap_or_else(|| Value::Float(n.as_f64().unwrap())),
+                Json::String(s) => Value::String(s.clone()),
+                Json::Array(a) => Value::Array(a.iter().map(convert).collect()),
+                Json::Object(o) => Value::Object(
+                    o.iter()
+                        .map(|(k, v)| (k.clone(), convert(v)))
+                        .collect::<IndexMap<_, _>>(),
+                ),
+            }
+        }
+        convert(&json)
+    }
+
+    #[test]
+    fn test_parse_target() {
+        let v = K8sVersion::parse_target("k8s@1.29").unwrap();
+        assert_eq!((v.major, v.minor, v.patch), (1, 29, 0));
+        assert_eq!(v.schema_dir_name(), "v1.29.0-standalone-strict");
+        assert_eq!(
+            K8sVersion::parse_target("kubernetes@v1.28.4")
+                .unwrap()
+                .patch,
+            4
+        );
+        assert!(K8sVersion::parse_target("openshift@4.14").is_err());
+        assert!(K8sVersion::parse_target("k8s@latest").is_err());
+    }
+
+    #[test]
+    fn test_schema_file_name() {
+        assert_eq!(
+            schema_file_name("Deployment", "apps/v1"),
+            "deployment-apps-v1.json"
+        );
+        assert_eq!(schema_file_name("Pod", "v1"), "pod-v1.json");
+        assert_eq!(
+            schema_file_name("Ingress", "networking.k8s.io/v1"),
+            "ingress-networking-v1.json"
+        );
+    }
+
+    #[test]
+    fn test_check_api_version() {
+        assert!(check_api_version("Deployment", "apps/v1", 29).is_ok());
+        assert!(check_api_version("Widget", "example.com/v1", 29).is_ok());
+
+        let err = check_api_version("Deployment", "app/v1", 29).unwrap_err();
+        assert!(err.contains("unknown apiVersion 'app/v1'"), "{}", err);
+        assert!(err.contains("use apps/v1"), "{}", err);
+
+        let err = check_api_version("CronJob", "batch/v1beta1", 29).unwrap_err();
+        assert!(err.contains("removed in Kubernetes 1.25"), "{}", err);
+        assert!(check_api_version("CronJob", "batch/v1beta1", 24).is_ok());
+
+        let err = check_api_version("Deploymnt", "apps/v1", 29).unwrap_err();
+        assert!(err.contains("unknown kind 'Deploymnt'"), "{}", err);
+    }
+
+    #[test]
+    fn test_validate_against_cached_schema() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("service-v1.json"),
+            json!({
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["metadata"],
+                "properties": {
+                    "apiVersion": {"type": "string"},
+                    "kind": {"type": "string"},
+                    "metadata": {"type": "object"},
+                    "spec": {
+                        "type": "object",
+                        "properties": {
+                            "type": {"type": "string", "enum": ["ClusterIP", "NodePort"]},
+                            "ports": {
+                                "type": "array",
+                                "items": {
+                                    "type": "object",
+                                    "properties": {
+                                        "port": {"type": "integer"},
+                                        "targetPort": {"x-kubernetes-int-or-string": true}
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let validator = K8sValidator::with_schema_dir(
+            K8sVersion::parse_target("k8s@1.29").unwrap(),
+            dir.path(),
+        );
+        let docs = vec![
+            (
+                Some("svc".to_string()),
+                value(json!({
+                    "apiVersion": "v1",
+                    "kind": "Service",
+                    "spec": {
+                        "type": "Nodeport",
+                        "ports": [{"port": "80", "targetPort": "http"}]
+                    },
+                    "extra": true
+                })),
+            ),
+            (
+                Some("cm".to_string()),
+                value(json!({"apiVersion": "v1", "kind": "ConfigMap"})),
+            ),
+            (None, value(json!({"not": "k8s"}))),
+        ];
+
+        let report = validator.validate(&docs);
+        let messages: Vec<String> = report.violations.iter().map(|v| v.to_string()).collect();
+        assert!(messages.contains(&"svc: metadata: required field is missing".to_string()));
+        assert!(messages.contains(&"svc: extra: unknown field".to_string()));
+        assert!(messages
+            .iter()
+            .any(|m| m.starts_with("svc: spec.type: \"Nodeport\" is not one of")));
+        assert!(messages
+            .contains(&"svc: spec.ports[0].port: expected integer, found string".to_string()));
+        assert_eq!(report.violations.len(), 4, "{:?}", messages);
+        assert_eq!(report.unchecked, vec!["ConfigMap (v1)"]);
+    }
+}
@@ -0,0 +1,137 @@
+//! Duration literals: `1500ms`, `30s`, `5m`, `2h30m`, `7d`
+//!
+//! A literal is one or more `<digits><unit>` parts with units from largest
+//! to smallest, each at most once. Durations are held as milliseconds and
+//! written back in the same form, largest unit first, so `90m` is `1h30m`.
+
+/// Units from largest to smallest, with their length in milliseconds
+const UNITS: &[(&str, i64)] = &[
+    ("d", 86_400_000),
+    ("h", 3_600_000),
+    ("m", 60_000),
+    ("s", 1_000),
+    ("ms", 1),
+];
+
+/// The unit at the start of `text`, as an index into [`UNITS`]
+fn unit_at(text: &str) -> Option<usize> {
+    // `ms` before `m`
+    if text.starts_with("ms") {
+        return Some(4);
+    }
+    UNITS[..4]
+        .iter()
+        .position(|(unit, _)| text.starts_with(unit))
+}
+
+/// Scan a duration literal at the start of `text`, which starts with a
+/// digit. `None` when it is a plain number; otherwise the literal's length
+/// and its value in milliseconds, or why it is not a valid duration.
+pub fn scan(text: &str) -> Option<(usize, Result<i64, String>)> {
+    let digits = text.bytes().take_while(u8::is_ascii_digit).count();
+    unit_at(&text[digits..])?;
+
+    let mut pos = 0;
+    let mut parts = Vec::new();
+    loop {
+        let digits = text[pos..].bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            break;
+        }
+        let Some(unit) = unit_at(&text[pos + digits..]) else {
+            break;
+        };
+        parts.push((&text[pos..pos + digits], unit));
+        pos += digits + UNITS[unit].0.len();
+    }
+    // `5min` or `2h30` is not a duration either way
+    let rest = &text[pos..];
+    if rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+        let end = rest
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        return Some((
+            pos + end,
+            Err(format!(
+                "'{}' is not a duration: use ms, s, m, h and d, as in 2h30m",
+                &text[..pos + end]
+            )),
+        ));
+    }
+    if parts.windows(2).any(|w| w[0].1 >= w[1].1) {
+        return Some((
+            pos,
+            Err(format!(
+                "'{}' is not a duration: give each unit once, largest first, as in 2h30m",
+                &text[..pos]
+            )),
+        ));
+    }
+
+    let millis = parts.iter().try_fold(0i64, |total, (number, unit)| {
+        let n: i64 = number.parse().ok()?;
+        n.checked_mul(UNITS[*unit].1)?.checked_add(total)
+    });
+    Some((
+        pos,
+        millis.ok_or_else(|| format!("duration '{}' is too long", &text[..pos])),
+    ))
+}
+
+/// The value of `text` when all of it is a duration literal
+pub fn parse(text: &str) -> Option<i64> {
+    match scan(text) {
+        Some((len, Ok(millis))) if len == text.len() => Some(millis),
+        _ => None,
+    }
+}
+
+/// A duration as a literal, largest unit first: `1h30m`, `1s500ms`, `0s`
+pub fn format(millis: i64) -> String {
+    if millis == 0 {
+        return "0s".to_string();
+    }
+    let mut out = String::new();
+    if millis < 0 {
+        out.push('-');
+    }
+    let mut rest = millis.unsigned_abs();
+    for (unit, length) in UNITS {
+        let length = *length as u64;
+        if rest >= length {
+            out.push_str(&format!("{}{}", rest / length, unit));
+            rest %= length;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_and_format() {
+        assert_eq!(scan("5m"), Some((2, Ok(300_000))));
+        assert_eq!(scan("2h30m)"), Some((5, Ok(9_000_000))));
+        assert_eq!(scan("1500ms "), Some((6, Ok(1_500))));
+        assert_eq!(scan("1d2h3m4s5ms"), Some((11, Ok(93_784_005))));
+        assert_eq!(scan("42"), None);
+        assert_eq!(parse("90s"), Some(90_000));
+        assert_eq!(parse("90s "), None);
+        assert_eq!(scan("1e5"), None);
+        assert!(scan("5min").unwrap().1.unwrap_err().contains("'5min'"));
+        assert!(scan("30m2h")
+            .unwrap()
+            .1
+            .unwrap_err()
+            .contains("largest first"));
+        assert!(scan("9999999999999999d").unwrap().1.is_err());
+
+        assert_eq!(format(9_000_000), "2h30m");
+        assert_eq!(format(1_500), "1s500ms");
+        assert_eq!(format(0), "0s");
+        assert_eq!(format(-300_000), "-5m");
+        assert_eq!(format(5_400_000), "1h30m");
+    }
+}
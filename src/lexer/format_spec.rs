@@ -0,0 +1,344 @@
+//! Format specs in string interpolations: `${ratio:.2}`, `${port:05}`
+//!
+//! A spec follows the last top-level `:` of an interpolation and reads
+//! `[[fill]align][+][0][width][,][.precision][type]`, after Python's format
+//! mini-language. Align is `<`, `>` or `^`; `+` signs positive numbers, `0`
+//! pads numbers with zeros after the sign and `,` groups thousands. The type
+//! is `d` for ints, `f`, `e` and `%` for fixed, exponent and percent, `x`,
+//! `X`, `o` and `b` for ints in hex, octal and binary, or `s` for the value
+//! as text. Numbers align right and everything else left. A spec is checked
+//! when it is lexed; whether it suits the value is known only when the
+//! string is evaluated.
+
+use std::fmt;
+
+/// Largest width or precision a spec may ask for
+pub const MAX_WIDTH: usize = 1000;
+
+/// The spec grammar, for messages
+const GRAMMAR: &str = "[[fill]align][+][0][width][,][.precision][type]";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+fn align_of(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    }
+}
+
+/// A parsed format spec, kept with its text so it is written back as is
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatSpec {
+    text: String,
+    fill: char,
+    align: Option<Align>,
+    sign: bool,
+    zero: bool,
+    width: usize,
+    thousands: bool,
+    precision: Option<usize>,
+    kind: Option<char>,
+}
+
+impl fmt::Display for FormatSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Parse the text of a spec, without its `:`, or say why it is not one
+pub fn parse(text: &str) -> Result<FormatSpec, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut spec = FormatSpec {
+        text: text.to_string(),
+        fill: ' ',
+        align: None,
+        sign: false,
+        zero: false,
+        width: 0,
+        thousands: false,
+        precision: None,
+        kind: None,
+    };
+    let mut pos = 0;
+
+    if let Some(align) = chars.get(1).copied().and_then(align_of) {
+        spec.fill = chars[0];
+        spec.align = Some(align);
+        pos = 2;
+    } else if let Some(align) = chars.first().copied().and_then(align_of) {
+        spec.align = Some(align);
+        pos = 1;
+    }
+    if chars.get(pos) == Some(&'+') {
+        spec.sign = true;
+        pos += 1;
+    }
+    if chars.get(pos) == Some(&'0') {
+        spec.zero = true;
+        pos += 1;
+    }
+    if let Some(width) = digits(&chars, &mut pos, text)? {
+        spec.width = width;
+    }
+    if chars.get(pos) == Some(&',') {
+        spec.thousands = true;
+        pos += 1;
+    }
+    if chars.get(pos) == Some(&'.') {
+        pos += 1;
+        match digits(&chars, &mut pos, text)? {
+            Some(precision) => spec.precision = Some(precision),
+            None => {
+                return Err(format!(
+                    "'{}' needs digits after '.', as in .2 for two decimals",
+                    text
+                ))
+            }
+        }
+    }
+    if let Some(&c) = chars.get(pos) {
+        if "dfexXob%s".contains(c) {
+            spec.kind = Some(c);
+            pos += 1;
+        }
+    }
+    if let Some(c) = chars.get(pos) {
+        return Err(format!(
+            "unexpected '{}' in format spec '{}': a spec reads {}",
+            c, text, GRAMMAR
+        ));
+    }
+
+    match spec.kind {
+        Some(kind @ ('d' | 'x' | 'X' | 'o' | 'b')) if spec.precision.is_some() => {
+            Err(format!("'{}' formats ints, which take no precision", kind))
+        }
+        Some(kind @ ('x' | 'X' | 'o' | 'b')) if spec.thousands => Err(format!(
+            "',' groups decimal digits and does not apply to '{}'",
+            kind
+        )),
+        Some('s') if spec.sign || spec.zero || spec.thousands => {
+            Err("'s' formats text, which takes no '+', '0' or ','".to_string())
+        }
+        _ => Ok(spec),
+    }
+}
+
+/// Digits at `pos`, as a width or precision
+fn digits(chars: &[char], pos: &mut usize, text: &str) -> Result<Option<usize>, String> {
+    let start = *pos;
+    while chars.get(*pos).is_some_and(char::is_ascii_digit) {
+        *pos += 1;
+    }
+    if *pos == start {
+        return Ok(None);
+    }
+    let n: String = chars[start..*pos].iter().collect();
+    match n.parse::<usize>() {
+        Ok(n) if n <= MAX_WIDTH => Ok(Some(n)),
+        _ => Err(format!(
+            "'{}' asks for {} characters; at most {} are allowed",
+            text, n, MAX_WIDTH
+        )),
+    }
+}
+
+impl FormatSpec {
+    /// Format an int, or name the kind of value the spec needs instead
+    pub fn format_int(&self, n: i64) -> Result<String, &'static str> {
+        let digits = match self.kind {
+            None | Some('d') if self.precision.is_none() => {
+                group(n.unsigned_abs().to_string(), self.thousands)
+            }
+            Some('x') => format!("{:x}", n.unsigned_abs()),
+            Some('X') => format!("{:X}", n.unsigned_abs()),
+            Some('o') => format!("{:o}", n.unsigned_abs()),
+            Some('b') => format!("{:b}", n.unsigned_abs()),
+            Some('s') => return Ok(self.format_text(&n.to_string())),
+            _ => return self.format_float(n as f64),
+        };
+        Ok(self.pad_number(n < 0, digits))
+    }
+
+    /// Format a float, or name the kind of value the spec needs instead
+    pub fn format_float(&self, n: f64) -> Result<String, &'static str> {
+        let abs = n.abs();
+        let digits = match (self.kind, self.precision) {
+            (Some('d' | 'x' | 'X' | 'o' | 'b'), _) => return Err("int"),
+            (Some('s'), _) => return Ok(self.format_text(&float_text(n))),
+            (Some('e'), precision) => exponent(abs, precision.unwrap_or(6)),
+            (Some('%'), precision) => {
+                let percent = format!("{:.*}", precision.unwrap_or(6), abs * 100.0);
+                format!("{}%", group(percent, self.thousands))
+            }
+            (Some('f'), precision) => group(
+                format!("{:.*}", precision.unwrap_or(6), abs),
+                self.thousands,
+            ),
+            (_, Some(precision)) => group(format!("{:.*}", precision, abs), self.thousands),
+            (_, None) => group(float_text(abs), self.thousands),
+        };
+        Ok(self.pad_number(n.is_sign_negative() && !n.is_nan(), digits))
+    }
+
+    /// Format a value as text, or name the kind of value the spec needs
+    /// instead. `text` is the value's usual interpolated form; `is_string`
+    /// says whether precision truncates it.
+    pub fn format_value(&self, text: &str, is_string: bool) -> Result<String, &'static str> {
+        if self.sign || self.zero || self.thousands {
+            return Err("number");
+        }
+        match self.kind {
+            None | Some('s') if self.precision.is_none() || is_string => Ok(self.format_text(text)),
+            None | Some('s') => Err("string"),
+            _ => Err("number"),
+        }
+    }
+
+    fn format_text(&self, text: &str) -> String {
+        let text: String = match self.precision {
+            Some(precision) => text.chars().take(precision).collect(),
+            None => text.to_string(),
+        };
+        self.pad(String::new(), text, Align::Left)
+    }
+
+    fn pad_number(&self, negative: bool, digits: String) -> String {
+        let sign = if negative {
+            "-"
+        } else if self.sign {
+            "+"
+        } else {
+            ""
+        };
+        if self.zero && self.align.is_none() {
+            let len = sign.len() + digits.chars().count();
+            let zeros = "0".repeat(self.width.saturating_sub(len));
+            return format!("{}{}{}", sign, zeros, digits);
+        }
+        self.pad(sign.to_string(), digits, Align::Right)
+    }
+
+    fn pad(&self, sign: String, body: String, default: Align) -> String {
+        let len = sign.chars().count() + body.chars().count();
+        let fill = self.width.saturating_sub(len);
+        let (before, after) = match self.align.unwrap_or(default) {
+            Align::Left => (0, fill),
+            Align::Right => (fill, 0),
+            Align::Center => (fill / 2, fill - fill / 2),
+        };
+        let fill = self.fill.to_string();
+        format!(
+            "{}{}{}{}",
+            fill.repeat(before),
+            sign,
+            body,
+            fill.repeat(after)
+        )
+    }
+}
+
+/// A float as interpolation writes it, with `.0` on whole numbers
+fn float_text(n: f64) -> String {
+    if n.fract() == 0.0 {
+        format!("{}.0", n)
+    } else {
+        n.to_string()
+    }
+}
+
+/// `n` as `1.50e+03`, with two exponent digits at least
+fn exponent(n: f64, precision: usize) -> String {
+    let text = format!("{:.*e}", precision, n);
+    match text.split_once('e') {
+        Some((mantissa, exp)) => {
+            let exp: i32 = exp.parse().unwrap_or(0);
+            let sign = if exp < 0 { '-' } else { '+' };
+            format!("{}e{}{:02}", mantissa, sign, exp.abs())
+        }
+        None => text,
+    }
+}
+
+/// Separate the whole part of `number` into groups of three with `,`
+fn group(number: String, thousands: bool) -> String {
+    if !thousands {
+        return number;
+    }
+    let end = number
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(number.len());
+    let (whole, rest) = number.split_at(end);
+    let mut grouped = String::new();
+    for (i, c) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped.push_str(rest);
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(spec: &str, n: i64) -> String {
+        parse(spec).unwrap().format_int(n).unwrap()
+    }
+
+    fn float(spec: &str, n: f64) -> String {
+        parse(spec).unwrap().format_float(n).unwrap()
+    }
+
+    #[test]
+    fn test_format_numbers() {
+        assert_eq!(int("05", 42), "00042");
+        assert_eq!(int("05", -42), "-0042");
+        assert_eq!(int("+d", 7), "+7");
+        assert_eq!(int(",", 1234567), "1,234,567");
+        assert_eq!(int("x", 255), "ff");
+        assert_eq!(int("#>6X", 255), "####FF");
+        assert_eq!(int("08b", 5), "00000101");
+        assert_eq!(int(".2", 3), "3.00");
+        assert_eq!(float(".2", 0.125), "0.12");
+        assert_eq!(float(".2", 2.0 / 3.0), "0.67");
+        assert_eq!(float(",.1f", -12345.67), "-12,345.7");
+        assert_eq!(float(".1%", 0.256), "25.6%");
+        assert_eq!(float(".2e", 1500.0), "1.50e+03");
+        assert_eq!(float("e", 0.00012), "1.200000e-04");
+        assert_eq!(float("", 2.0), "2.0");
+        assert_eq!(float("^9.1", 2.5), "   2.5   ");
+    }
+
+    #[test]
+    fn test_format_text() {
+        let spec = parse("<6").unwrap();
+        assert_eq!(spec.format_value("ab", true).unwrap(), "ab    ");
+        let spec = parse("*^7").unwrap();
+        assert_eq!(spec.format_value("ab", true).unwrap(), "**ab***");
+        let spec = parse(".3").unwrap();
+        assert_eq!(spec.format_value("abcdef", true).unwrap(), "abc");
+        assert_eq!(spec.format_value("30s", false), Err("string"));
+        assert_eq!(parse("05").unwrap().format_value("ab", true), Err("number"));
+        assert_eq!(parse("x").unwrap().format_float(1.5), Err("int"));
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_specs() {
+        for spec in ["5q", ".", ".2x", ",b", "+s", "5000"] {
+            assert!(parse(spec).is_err(), "{}", spec);
+        }
+        assert_eq!(parse(">10,.2f").unwrap().to_string(), ">10,.2f");
+    }
+}
@@ -2,13 +2,23 @@
 //!
 //! Converts source text into a stream of [`token::Token`]s for the parser.
 
+pub mod duration;
+pub mod format_spec;
+pub mod number;
+pub mod size;
 pub mod token;
+pub mod triple;
 
 use std::path::PathBuf;
 
+use crate::cancel::CancelToken;
 use crate::errors::{HoneError, HoneResult};
+use number::Number;
 use token::{SourceLocation, Token, TokenKind};
 
+/// Tokens lexed between checks of the cancel token
+const CANCEL_CHECK_INTERVAL: usize = 1024;
+
 /// A collected comment with its location
 #[derive(Debug, Clone)]
 pub struct Comment {
@@ -22,6 +32,13 @@ pub struct Comment {
     pub is_block: bool,
 }
 
+/// What is open at the top level of an interpolation
+#[derive(Debug, Default)]
+struct Nesting {
+    groups: usize,
+    ternaries: usize,
+}
+
 /// Lexer for Hone configuration language
 pub struct Lexer<'a> {
     /// Source code being lexed
@@ -48,8 +65,18 @@ pub struct Lexer<'a> {
     interpolation_brace_stack: Vec<usize>,
     /// Track whether each interpolation level is inside a triple-quoted string
     triple_string_interpolation: Vec<bool>,
+    /// Open parens and brackets, and ternary `?`s awaiting their `:`, at the
+    /// top level of each interpolation; any other `:` there starts a format spec
+    interpolation_nesting: Vec<Nesting>,
+    /// Offsets of interpolated triple-quoted strings, with whether each
+    /// chomps, for stripping once all their segments are lexed
+    interpolated_triples: Vec<(usize, bool)>,
     /// Collected comments (for formatter use)
     comments: Vec<Comment>,
+    /// Recoverable errors, in source order
+    errors: Vec<HoneError>,
+    /// Stops tokenizing once cancelled
+    cancel: Option<CancelToken>,
 }
 
 impl<'a> Lexer<'a> {
@@ -68,10 +95,20 @@ impl<'a> Lexer<'a> {
             interpolation_depth: 0,
             interpolation_brace_stack: Vec::new(),
             triple_string_interpolation: Vec::new(),
+            interpolation_nesting: Vec::new(),
+            interpolated_triples: Vec::new(),
             comments: Vec::new(),
+            errors: Vec::new(),
+            cancel: None,
         }
     }
 
+    /// Stop with [`HoneError::Cancelled`] once `token` is cancelled
+    pub fn with_cancel(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
     /// Get the source code
     pub fn source(&self) -> &str {
         self.source
@@ -87,23 +124,109 @@ impl<'a> Lexer<'a> {
         std::mem::take(&mut self.comments)
     }
 
-    /// Tokenize the entire source and return all tokens
+    /// Errors recorded so far by [`Lexer::next_token`]
+    pub fn errors(&self) -> &[HoneError] {
+        &self.errors
+    }
+
+    /// Tokenize the entire source and return all tokens, failing on the first
+    /// lexical error
     pub fn tokenize(&mut self) -> HoneResult<Vec<Token>> {
+        let (tokens, errors) = self.tokenize_recovering();
+        match errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(tokens),
+        }
+    }
+
+    /// Tokenize the entire source, continuing past lexical errors.
+    ///
+    /// Malformed input becomes a [`TokenKind::Error`] token and every problem
+    /// is returned, so editors can report all of them and keep highlighting
+    /// the rest of the file.
+    pub fn tokenize_recovering(&mut self) -> (Vec<Token>, Vec<HoneError>) {
         let mut tokens = Vec::new();
 
         loop {
-            let token = self.next_token()?;
+            if tokens.len() % CANCEL_CHECK_INTERVAL == 0 {
+                if let Some(Err(error)) = self.cancel.as_ref().map(CancelToken::check) {
+                    self.errors.push(error);
+                    break;
+                }
+            }
+            let token = match self.next_token() {
+                Ok(token) => token,
+                Err(error) => {
+                    self.errors.push(error);
+                    self.make_token(TokenKind::Error)
+                }
+            };
             let is_eof = token.kind == TokenKind::Eof;
             tokens.push(token);
             if is_eof {
                 break;
             }
         }
+        self.strip_interpolated_triples(&mut tokens);
+
+        (tokens, std::mem::take(&mut self.errors))
+    }
+
+    /// Strip the indentation of each interpolated triple-quoted string
+    /// across its `StringStart`, `StringMiddle` and `StringEnd` segments
+    fn strip_interpolated_triples(&mut self, tokens: &mut [Token]) {
+        for (offset, chomp) in std::mem::take(&mut self.interpolated_triples) {
+            let start = tokens.partition_point(|t| t.location.offset < offset);
+            let mut indices = vec![start];
+            let mut depth = 0;
+            for (i, token) in tokens.iter().enumerate().skip(start + 1) {
+                match token.kind {
+                    TokenKind::StringStart(_) => depth += 1,
+                    TokenKind::StringMiddle(_) if depth == 0 => indices.push(i),
+                    TokenKind::StringEnd(_) if depth == 0 => {
+                        indices.push(i);
+                        break;
+                    }
+                    TokenKind::StringEnd(_) => depth -= 1,
+                    _ => {}
+                }
+            }
 
-        Ok(tokens)
+            let mut segments: Vec<String> = indices
+                .iter()
+                .filter_map(|&i| match &tokens[i].kind {
+                    TokenKind::StringStart(s)
+                    | TokenKind::StringMiddle(s)
+                    | TokenKind::StringEnd(s) => Some(s.clone()),
+                    _ => None,
+                })
+                .collect();
+            // An unterminated string has no end to strip towards
+            if segments.len() != indices.len()
+                || !matches!(
+                    tokens[indices[indices.len() - 1]].kind,
+                    TokenKind::StringEnd(_)
+                )
+            {
+                continue;
+            }
+            triple::strip(&mut segments, chomp);
+            for (&i, segment) in indices.iter().zip(segments) {
+                match &mut tokens[i].kind {
+                    TokenKind::StringStart(s)
+                    | TokenKind::StringMiddle(s)
+                    | TokenKind::StringEnd(s) => *s = segment,
+                    _ => {}
+                }
+            }
+        }
     }
 
-    /// Get the next token
+    /// Get the next token.
+    ///
+    /// Unterminated strings and invalid escapes don't fail: the first yields a
+    /// [`TokenKind::Error`] token, the second drops the escape, and both are
+    /// recorded in [`Lexer::errors`].
     pub fn next_token(&mut self) -> HoneResult<Token> {
         self.skip_whitespace_and_comments();
 
@@ -182,21 +305,31 @@ impl<'a> Lexer<'a> {
                     }
                     '[' => {
                         self.advance();
+                        self.open_group();
                         Ok(self.make_token(TokenKind::LeftBracket))
                     }
                     ']' => {
                         self.advance();
+                        self.close_group();
                         Ok(self.make_token(TokenKind::RightBracket))
                     }
                     '(' => {
                         self.advance();
+                        self.open_group();
                         Ok(self.make_token(TokenKind::LeftParen))
                     }
                     ')' => {
                         self.advance();
+                        self.close_group();
                         Ok(self.make_token(TokenKind::RightParen))
                     }
                     ':' => {
+                        if let Some(nesting) = self.interpolation_top_level() {
+                            if nesting.ternaries == 0 {
+                                return self.lex_format_spec();
+                            }
+                            nesting.ternaries -= 1;
+                        }
                         self.advance();
                         Ok(self.make_token(TokenKind::Colon))
                     }
@@ -250,6 +383,9 @@ impl<'a> Lexer<'a> {
                         if self.peek_char() == Some('=') {
                             self.advance();
                             Ok(self.make_token(TokenKind::EqEq))
+                        } else if self.peek_char() == Some('>') {
+                            self.advance();
+                            Ok(self.make_token(TokenKind::FatArrow))
                         } else {
                             Ok(self.make_token(TokenKind::Eq))
                         }
@@ -286,11 +422,22 @@ impl<'a> Lexer<'a> {
                         if self.peek_char() == Some('|') {
                             self.advance();
                             Ok(self.make_token(TokenKind::Or))
+                        } else if self.peek_char() == Some('>') {
+                            self.advance();
+                            Ok(self.make_token(TokenKind::Pipeline))
                         } else {
                             Ok(self.make_token(TokenKind::Pipe))
                         }
                     }
                     '?' => {
+                        // The two `?`s of `??` are not ternaries
+                        let coalesce = self.source[..self.position].ends_with('?')
+                            || self.peek_char_at(1) == Some('?');
+                        if !coalesce {
+                            if let Some(nesting) = self.interpolation_top_level() {
+                                nesting.ternaries += 1;
+                            }
+                        }
                         self.advance();
                         Ok(self.make_token(TokenKind::Question))
                     }
@@ -308,6 +455,62 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Count a `(` or `[` in the innermost interpolation
+    fn open_group(&mut self) {
+        if let Some(nesting) = self.interpolation_nesting.last_mut() {
+            nesting.groups += 1;
+        }
+    }
+
+    /// Count a `)` or `]` in the innermost interpolation
+    fn close_group(&mut self) {
+        if let Some(nesting) = self.interpolation_nesting.last_mut() {
+            nesting.groups = nesting.groups.saturating_sub(1);
+        }
+    }
+
+    /// The innermost interpolation, when nothing is open inside it
+    fn interpolation_top_level(&mut self) -> Option<&mut Nesting> {
+        if self.interpolation_brace_stack.last() != Some(&0) {
+            return None;
+        }
+        self.interpolation_nesting
+            .last_mut()
+            .filter(|nesting| nesting.groups == 0)
+    }
+
+    /// Lex the format spec from a `:` up to the `}` closing its interpolation
+    fn lex_format_spec(&mut self) -> HoneResult<Token> {
+        self.advance(); // :
+        let start = self.position;
+        while let Some(ch) = self.peek_char() {
+            if matches!(ch, '}' | '"' | '\n') {
+                break;
+            }
+            self.advance();
+        }
+        let text = &self.source[start..self.position];
+        if self.peek_char() != Some('}') {
+            return Err(HoneError::unexpected_token(
+                self.source.to_string(),
+                &self.token_location(),
+                "'}' after the format spec",
+                text,
+                "a format spec runs from the last ':' of an interpolation to its '}', as in ${ratio:.2}",
+            ));
+        }
+        let spec = format_spec::parse(text).map_err(|help| {
+            HoneError::unexpected_token(
+                self.source.to_string(),
+                &self.token_location(),
+                "format spec",
+                text,
+                help,
+            )
+        })?;
+        Ok(self.make_token(TokenKind::FormatSpec(spec)))
+    }
+
     /// Peek at the current character without consuming
     fn peek_char(&mut self) -> Option<char> {
         self.chars.peek().map(|(_, c)| *c)
@@ -425,77 +628,60 @@ impl<'a> Lexer<'a> {
         Ok(self.make_token(kind))
     }
 
-    /// Lex a number (integer or float)
+    /// Lex a number (integer, float, duration or size)
     fn lex_number(&mut self) -> HoneResult<Token> {
         let start = self.position;
-        let mut is_float = false;
 
-        // Integer part
-        while let Some(ch) = self.peek_char() {
-            if ch.is_ascii_digit() {
+        if let Some((len, millis)) = duration::scan(&self.source[start..]) {
+            for _ in 0..len {
                 self.advance();
-            } else {
-                break;
-            }
-        }
-
-        // Decimal part
-        if self.peek_char() == Some('.') {
-            if let Some(next) = self.peek_char_at(1) {
-                if next.is_ascii_digit() {
-                    is_float = true;
-                    self.advance(); // .
-                    while let Some(ch) = self.peek_char() {
-                        if ch.is_ascii_digit() {
-                            self.advance();
-                        } else {
-                            break;
-                        }
-                    }
-                }
             }
+            let text = &self.source[start..self.position];
+            let millis = millis.map_err(|help| {
+                HoneError::unexpected_token(
+                    self.source.to_string(),
+                    &self.token_location(),
+                    "duration",
+                    text,
+                    help,
+                )
+            })?;
+            return Ok(self.make_token(TokenKind::Duration(millis)));
         }
-
-        // Exponent part
-        if let Some('e' | 'E') = self.peek_char() {
-            is_float = true;
-            self.advance();
-            if let Some('+' | '-') = self.peek_char() {
+        if let Some((len, bytes)) = size::scan(&self.source[start..]) {
+            for _ in 0..len {
                 self.advance();
             }
-            while let Some(ch) = self.peek_char() {
-                if ch.is_ascii_digit() {
-                    self.advance();
-                } else {
-                    break;
-                }
-            }
-        }
-
-        let text = &self.source[start..self.position];
-
-        let kind = if is_float {
-            let value: f64 = text.parse().map_err(|_| {
+            let text = &self.source[start..self.position];
+            let bytes = bytes.map_err(|help| {
                 HoneError::unexpected_token(
                     self.source.to_string(),
-                    &self.current_location(),
-                    "valid number",
+                    &self.token_location(),
+                    "size",
                     text,
-                    "invalid float literal",
+                    help,
                 )
             })?;
-            TokenKind::Float(value)
-        } else {
-            let value: i64 = text.parse().map_err(|_| {
-                HoneError::unexpected_token(
+            return Ok(self.make_token(TokenKind::Size(bytes)));
+        }
+
+        let (len, value) = number::scan(&self.source[start..]);
+        for _ in 0..len {
+            self.advance();
+        }
+        let text = &self.source[start..self.position];
+        let kind = match value {
+            Ok(Number::Int(n)) => TokenKind::Integer(n),
+            Ok(Number::Float(f)) => TokenKind::Float(f),
+            Err(help) => {
+                return Err(HoneError::unexpected_token(
                     self.source.to_string(),
-                    &self.current_location(),
+                    &self.token_location(),
                     "valid number",
                     text,
-                    "invalid integer literal",
-                )
-            })?;
-            TokenKind::Integer(value)
+                    help,
+                ))
+            }
         };
 
         Ok(self.make_token(kind))
@@ -518,10 +704,7 @@ impl<'a> Lexer<'a> {
         loop {
             match self.peek_char() {
                 None | Some('\n') => {
-                    return Err(HoneError::unterminated_string(
-                        self.source.to_string(),
-                        &self.token_location(),
-                    ));
+                    return Ok(self.unterminated_string());
                 }
                 Some('"') => {
                     self.advance();
@@ -529,8 +712,7 @@ impl<'a> Lexer<'a> {
                 }
                 Some('\\') => {
                     self.advance();
-                    let escaped = self.lex_escape_sequence()?;
-                    value.push(escaped);
+                    self.push_escape(&mut value);
                 }
                 Some('$') if self.peek_char_at(1) == Some('{') => {
                     // Start of interpolation
@@ -540,6 +722,7 @@ impl<'a> Lexer<'a> {
                     self.interpolation_depth += 1;
                     self.interpolation_brace_stack.push(0);
                     self.triple_string_interpolation.push(false);
+                    self.interpolation_nesting.push(Nesting::default());
 
                     if value.is_empty() {
                         return Ok(self.make_token(TokenKind::StringStart(String::new())));
@@ -563,6 +746,7 @@ impl<'a> Lexer<'a> {
         self.interpolation_depth -= 1;
         self.interpolation_brace_stack.pop();
         self.triple_string_interpolation.pop();
+        self.interpolation_nesting.pop();
 
         self.token_start = self.position;
         self.token_start_line = self.line;
@@ -573,10 +757,7 @@ impl<'a> Lexer<'a> {
         loop {
             match self.peek_char() {
                 None | Some('\n') => {
-                    return Err(HoneError::unterminated_string(
-                        self.source.to_string(),
-                        &self.token_location(),
-                    ));
+                    return Ok(self.unterminated_string());
                 }
                 Some('"') => {
                     self.advance();
@@ -584,8 +765,7 @@ impl<'a> Lexer<'a> {
                 }
                 Some('\\') => {
                     self.advance();
-                    let escaped = self.lex_escape_sequence()?;
-                    value.push(escaped);
+                    self.push_escape(&mut value);
                 }
                 Some('$') if self.peek_char_at(1) == Some('{') => {
                     // Another interpolation
@@ -594,6 +774,7 @@ impl<'a> Lexer<'a> {
                     self.interpolation_depth += 1;
                     self.interpolation_brace_stack.push(0);
                     self.triple_string_interpolation.push(false);
+                    self.interpolation_nesting.push(Nesting::default());
 
                     return Ok(self.make_token(TokenKind::StringMiddle(value)));
                 }
@@ -611,6 +792,7 @@ impl<'a> Lexer<'a> {
         self.interpolation_depth -= 1;
         self.interpolation_brace_stack.pop();
         self.triple_string_interpolation.pop();
+        self.interpolation_nesting.pop();
 
         self.token_start = self.position;
         self.token_start_line = self.line;
@@ -622,10 +804,7 @@ impl<'a> Lexer<'a> {
         loop {
             match self.peek_char() {
                 None => {
-                    return Err(HoneError::unterminated_string(
-                        self.source.to_string(),
-                        &self.token_location(),
-                    ));
+                    return Ok(self.unterminated_string());
                 }
                 Some('"') => {
                     self.advance();
@@ -641,8 +820,7 @@ impl<'a> Lexer<'a> {
                 Some('\\') => {
                     consecutive_quotes = 0;
                     self.advance();
-                    let escaped = self.lex_escape_sequence()?;
-                    value.push(escaped);
+                    self.push_escape(&mut value);
                 }
                 Some('$') if self.peek_char_at(1) == Some('{') => {
                     // Another interpolation
@@ -651,6 +829,7 @@ impl<'a> Lexer<'a> {
                     self.interpolation_depth += 1;
                     self.interpolation_brace_stack.push(0);
                     self.triple_string_interpolation.push(true);
+                    self.interpolation_nesting.push(Nesting::default());
 
                     return Ok(self.make_token(TokenKind::StringMiddle(value)));
                 }
@@ -679,10 +858,7 @@ impl<'a> Lexer<'a> {
         loop {
             match self.peek_char() {
                 None | Some('\n') => {
-                    return Err(HoneError::unterminated_string(
-                        self.source.to_string(),
-                        &self.token_location(),
-                    ));
+                    return Ok(self.unterminated_string());
                 }
                 Some('\'') => {
                     self.advance();
@@ -721,18 +897,29 @@ impl<'a> Lexer<'a> {
         let mut consecutive_quotes = 0;
         let quote_char = if interpolate { '"' } else { '\'' };
 
+        // `"""-` at the end of a line chomps the trailing newlines
+        let line_break = |lexer: &Self, at: usize| match lexer.peek_char_at(at) {
+            Some('\n') => true,
+            Some('\r') => lexer.peek_char_at(at + 1) == Some('\n'),
+            _ => false,
+        };
+        let chomp = self.peek_char() == Some('-') && line_break(self, 1);
+        if chomp {
+            self.advance();
+        }
+
         // Skip initial newline if present
-        if self.peek_char() == Some('\n') {
+        if line_break(self, 0) {
+            if self.peek_char() == Some('\r') {
+                self.advance();
+            }
             self.advance();
         }
 
         loop {
             match self.peek_char() {
                 None => {
-                    return Err(HoneError::unterminated_string(
-                        self.source.to_string(),
-                        &self.token_location(),
-                    ));
+                    return Ok(self.unterminated_string());
                 }
                 Some(ch) if ch == quote_char => {
                     self.advance();
@@ -748,8 +935,7 @@ impl<'a> Lexer<'a> {
                 Some('\\') if interpolate => {
                     consecutive_quotes = 0;
                     self.advance();
-                    let escaped = self.lex_escape_sequence()?;
-                    value.push(escaped);
+                    self.push_escape(&mut value);
                 }
                 Some('$') if interpolate && self.peek_char_at(1) == Some('{') => {
                     // Start of interpolation in triple-quoted string
@@ -758,6 +944,8 @@ impl<'a> Lexer<'a> {
                     self.interpolation_depth += 1;
                     self.interpolation_brace_stack.push(0);
                     self.triple_string_interpolation.push(true);
+                    self.interpolation_nesting.push(Nesting::default());
+                    self.interpolated_triples.push((self.token_start, chomp));
 
                     if value.is_empty() {
                         return Ok(self.make_token(TokenKind::StringStart(String::new())));
@@ -773,33 +961,30 @@ impl<'a> Lexer<'a> {
             }
         }
 
-        // Strip leading indentation based on closing """ position
-        let value = self.strip_triple_string_indent(&value);
+        let mut segments = [value];
+        triple::strip(&mut segments, chomp);
+        let [value] = segments;
 
         Ok(self.make_token(TokenKind::TripleString(value)))
     }
 
-    /// Strip leading indentation from triple-quoted string
-    fn strip_triple_string_indent(&self, s: &str) -> String {
-        // Find minimum indentation (excluding empty lines)
-        let min_indent = s
-            .lines()
-            .filter(|line| !line.trim().is_empty())
-            .map(|line| line.len() - line.trim_start().len())
-            .min()
-            .unwrap_or(0);
-
-        // Strip that much indentation from each line
-        s.lines()
-            .map(|line| {
-                if line.len() >= min_indent {
-                    &line[min_indent..]
-                } else {
-                    line.trim_start()
-                }
-            })
-            .collect::<Vec<_>>()
-            .join("\n")
+    /// Record an unterminated string and return an error token for it. The
+    /// newline (if any) is left for the next token.
+    fn unterminated_string(&mut self) -> Token {
+        self.errors.push(HoneError::unterminated_string(
+            self.source.to_string(),
+            &self.token_location(),
+        ));
+        self.make_token(TokenKind::Error)
+    }
+
+    /// Lex an escape sequence into `value`, recording invalid ones. The
+    /// characters after a bad escape are lexed as ordinary string content.
+    fn push_escape(&mut self, value: &mut String) {
+        match self.lex_escape_sequence() {
+            Ok(ch) => value.push(ch),
+            Err(error) => self.errors.push(error),
+        }
     }
 
     /// Lex an escape sequence
@@ -845,6 +1030,37 @@ impl<'a> Lexer<'a> {
                 self.advance();
                 Ok('}')
             }
+            Some('x') => {
+                // Byte escape \xNN, limited to ASCII so strings stay valid UTF-8
+                self.advance();
+                let mut hex = String::new();
+                for _ in 0..2 {
+                    match self.peek_char() {
+                        Some(ch) if ch.is_ascii_hexdigit() => {
+                            hex.push(ch);
+                            self.advance();
+                        }
+                        _ => {
+                            return Err(HoneError::invalid_escape_sequence(
+                                self.source.to_string(),
+                                &self.current_location(),
+                                format!("\\x{}", hex),
+                                "expected two hex digits after \\x",
+                            ));
+                        }
+                    }
+                }
+                let byte = u8::from_str_radix(&hex, 16).unwrap_or(0xFF);
+                if !byte.is_ascii() {
+                    return Err(HoneError::invalid_escape_sequence(
+                        self.source.to_string(),
+                        &self.current_location(),
+                        format!("\\x{}", hex),
+                        "\\x escapes must be at most \\x7F; use \\u{...} for other characters",
+                    ));
+                }
+                Ok(byte as char)
+            }
             Some('u') => {
                 // Unicode escape \u{XXXX}
                 self.advance();
@@ -875,6 +1091,15 @@ impl<'a> Lexer<'a> {
                     self.advance();
                 }
 
+                if hex.is_empty() || hex.len() > 6 {
+                    return Err(HoneError::invalid_escape_sequence(
+                        self.source.to_string(),
+                        &self.current_location(),
+                        format!("\\u{{{}}}", hex),
+                        "unicode escapes take 1 to 6 hex digits",
+                    ));
+                }
+
                 if self.peek_char() != Some('}') {
                     return Err(HoneError::invalid_escape_sequence(
                         self.source.to_string(),
@@ -1022,7 +1247,7 @@ mod tests {
             lex("-17"),
             vec![TokenKind::Minus, TokenKind::Integer(17), TokenKind::Eof]
         );
-        assert_eq!(lex("3.14"), vec![TokenKind::Float(3.14), TokenKind::Eof]);
+        assert_eq!(lex("3.25"), vec![TokenKind::Float(3.25), TokenKind::Eof]);
         assert_eq!(
             lex("-2.5"),
             vec![TokenKind::Minus, TokenKind::Float(2.5), TokenKind::Eof]
@@ -1032,6 +1257,16 @@ mod tests {
             lex("1.5e-3"),
             vec![TokenKind::Float(1.5e-3), TokenKind::Eof]
         );
+        assert_eq!(
+            lex("0o755 0xFF 1_000"),
+            vec![
+                TokenKind::Integer(493),
+                TokenKind::Integer(255),
+                TokenKind::Integer(1000),
+                TokenKind::Eof
+            ]
+        );
+        assert!(Lexer::new("x: 1__0", None).tokenize().is_err());
     }
 
     #[test]
@@ -1059,6 +1294,29 @@ mod tests {
             lex("\"tab\\there\""),
             vec![TokenKind::String("tab\there".to_string()), TokenKind::Eof]
         );
+        assert_eq!(
+            lex(r#""\u{1F600} \x41\x7e \u{e9}""#),
+            vec![TokenKind::String("😀 A~ é".to_string()), TokenKind::Eof]
+        );
+    }
+
+    #[test]
+    fn test_invalid_escapes() {
+        for source in [
+            r#""\x4""#,
+            r#""\xFF""#,
+            r#""\u{}""#,
+            r#""\u{1234567}""#,
+            r#""\q""#,
+        ] {
+            let result = Lexer::new(source, None).tokenize();
+            assert!(
+                matches!(result, Err(HoneError::InvalidEscapeSequence { .. })),
+                "{}: {:?}",
+                source,
+                result
+            );
+        }
     }
 
     #[test]
@@ -1157,6 +1415,26 @@ mod tests {
             ]
         );
 
+        assert_eq!(
+            lex("| || |>"),
+            vec![
+                TokenKind::Pipe,
+                TokenKind::Or,
+                TokenKind::Pipeline,
+                TokenKind::Eof,
+            ]
+        );
+
+        assert_eq!(
+            lex("= => =="),
+            vec![
+                TokenKind::Eq,
+                TokenKind::FatArrow,
+                TokenKind::EqEq,
+                TokenKind::Eof,
+            ]
+        );
+
         assert_eq!(
             lex("&& || !"),
             vec![
@@ -1244,6 +1522,26 @@ server {
         }
     }
 
+    #[test]
+    fn test_recovers_from_multiple_lexical_errors() {
+        let source = "a: \"open\nb: \"bad \\q escape\"\nc: 1";
+        let mut lexer = Lexer::new(source, None);
+        let (tokens, errors) = lexer.tokenize_recovering();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], HoneError::UnterminatedString { .. }));
+        assert!(matches!(errors[1], HoneError::InvalidEscapeSequence { .. }));
+
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(kinds[2], TokenKind::Error);
+        // Lexing continues after both errors
+        assert!(kinds.contains(&TokenKind::String("bad q escape".to_string())));
+        assert!(kinds.contains(&TokenKind::Integer(1)));
+
+        // tokenize() still fails on the first error
+        let result = Lexer::new(source, None).tokenize();
+        assert!(matches!(result, Err(HoneError::UnterminatedString { .. })));
+    }
+
     #[test]
     fn test_source_locations() {
         let mut lexer = Lexer::new("name: 42", None);
@@ -1307,6 +1605,54 @@ server {
         );
     }
 
+    #[test]
+    fn test_interpolation_format_spec() {
+        let spec = |text| TokenKind::FormatSpec(format_spec::parse(text).unwrap());
+        assert_eq!(
+            lex("\"${ratio:.2} ${a ? b : c} ${f(x, y):>5}\""),
+            vec![
+                TokenKind::StringStart(String::new()),
+                TokenKind::Ident("ratio".to_string()),
+                spec(".2"),
+                TokenKind::StringMiddle(" ".to_string()),
+                TokenKind::Ident("a".to_string()),
+                TokenKind::Question,
+                TokenKind::Ident("b".to_string()),
+                TokenKind::Colon,
+                TokenKind::Ident("c".to_string()),
+                TokenKind::StringMiddle(" ".to_string()),
+                TokenKind::Ident("f".to_string()),
+                TokenKind::LeftParen,
+                TokenKind::Ident("x".to_string()),
+                TokenKind::Comma,
+                TokenKind::Ident("y".to_string()),
+                TokenKind::RightParen,
+                spec(">5"),
+                TokenKind::StringEnd(String::new()),
+                TokenKind::Eof,
+            ]
+        );
+        // `??` is not a ternary, and `#` in a spec is a fill, not a comment
+        assert_eq!(
+            lex("\"${x ?? 0:#^4}\""),
+            vec![
+                TokenKind::StringStart(String::new()),
+                TokenKind::Ident("x".to_string()),
+                TokenKind::Question,
+                TokenKind::Question,
+                TokenKind::Integer(0),
+                spec("#^4"),
+                TokenKind::StringEnd(String::new()),
+                TokenKind::Eof,
+            ]
+        );
+        // Colons inside braces belong to the object
+        assert!(lex("\"${ {a: 1} }\"").contains(&TokenKind::Colon));
+
+        let mut lexer = Lexer::new("\"${x:.q}\"", None);
+        assert!(lexer.tokenize().is_err());
+    }
+
     #[test]
     fn test_triple_string_multiple_interpolations() {
         let src = "\"\"\"a ${x} b ${y} c\"\"\"";
@@ -1334,6 +1680,24 @@ server {
         }
     }
 
+    #[test]
+    fn test_triple_string_strips_indent_across_interpolations() {
+        let src = "\"\"\"-\n    name: ${x}\n      id: ${f(\"${y}\")}\n    \"\"\"";
+        let tokens = lex(src);
+        assert_eq!(tokens[0], TokenKind::StringStart("name: ".to_string()));
+        assert_eq!(tokens[2], TokenKind::StringMiddle("\n  id: ".to_string()));
+        assert_eq!(
+            tokens[tokens.len() - 2],
+            TokenKind::StringEnd(String::new())
+        );
+
+        let tokens = lex("'''\n  a\n    b\n  '''");
+        assert_eq!(tokens[0], TokenKind::TripleString("a\n  b\n".to_string()));
+        // `-` only chomps at the end of the opening line
+        let tokens = lex("\"\"\"-a\"\"\"");
+        assert_eq!(tokens[0], TokenKind::TripleString("-a".to_string()));
+    }
+
     #[test]
     fn test_single_triple_string_no_interpolation() {
         // Single-quoted triple strings never support interpolation
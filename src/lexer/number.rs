@@ -0,0 +1,190 @@
+//! Number literals: `42`, `1_000_000`, `0xFF`, `0o755`, `0b1010`, `3.14`, `1e10`
+//!
+//! `_` may separate digits anywhere in a literal, one at a time and only
+//! between two digits: `1_000`, `0xFF_FF` and `1_000.000_1` are valid, `1_`,
+//! `1__000` and `0x_FF` are errors. The prefixes `0x`, `0o` and `0b` write an
+//! integer in hex, octal or binary; they have no fraction or exponent.
+
+/// The value of a number literal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+/// Scan a number literal at the start of `text`, which starts with a digit.
+/// Returns the literal's length and its value, or why it is not a valid
+/// number.
+pub fn scan(text: &str) -> (usize, Result<Number, String>) {
+    let radix = match text.get(..2) {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        let len = 2 + word_len(&text[2..]);
+        return (len, radix_int(&text[..len], radix));
+    }
+
+    let mut end = digits_len(text);
+    let mut is_float = false;
+    let rest = &text[end..];
+    if rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+        is_float = true;
+        end += 1 + digits_len(&rest[1..]);
+    }
+    if text[end..].starts_with(['e', 'E']) {
+        is_float = true;
+        end += 1;
+        if text[end..].starts_with(['+', '-']) {
+            end += 1;
+        }
+        end += digits_len(&text[end..]);
+    }
+
+    // `42abc` or `1_000ms` is not a number either way
+    let trailing = word_len(&text[end..]);
+    if trailing > 0 {
+        let len = end + trailing;
+        let hint = if text[..end].contains('_') {
+            "; durations and sizes take no separators"
+        } else {
+            ""
+        };
+        return (
+            len,
+            Err(format!("'{}' is not a number{}", &text[..len], hint)),
+        );
+    }
+
+    let literal = &text[..end];
+    let mut parts = literal.split(['.', 'e', 'E', '+', '-']);
+    if parts.any(|part| !separated(part, 10)) {
+        return (end, Err(misplaced(literal)));
+    }
+    let digits = literal.replace('_', "");
+    let value = if is_float {
+        digits
+            .parse()
+            .map(Number::Float)
+            .map_err(|_| "invalid float literal".to_string())
+    } else {
+        digits
+            .parse()
+            .map(Number::Int)
+            .map_err(|_| format!("'{}' is too large for a 64-bit integer", literal))
+    };
+    (end, value)
+}
+
+/// The value of `text` when all of it is a number literal
+pub fn parse(text: &str) -> Option<Number> {
+    if !text.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    match scan(text) {
+        (len, Ok(number)) if len == text.len() => Some(number),
+        _ => None,
+    }
+}
+
+/// Whether `text` uses a radix prefix or separators, so it reads differently
+/// from the plain decimal form of its value
+pub fn is_spelled(text: &str) -> bool {
+    text.contains('_') || matches!(text.get(..2), Some("0x" | "0o" | "0b"))
+}
+
+/// A `0x`, `0o` or `0b` literal
+fn radix_int(literal: &str, radix: u32) -> Result<Number, String> {
+    let body = &literal[2..];
+    let name = match radix {
+        16 => "hex",
+        8 => "octal",
+        _ => "binary",
+    };
+    if body.is_empty() || !body.chars().all(|c| c == '_' || c.is_digit(radix)) {
+        return Err(format!(
+            "'{}' is not a {} number: use the digits {}",
+            literal,
+            name,
+            match radix {
+                16 => "0-9 and a-f",
+                8 => "0-7",
+                _ => "0 and 1",
+            }
+        ));
+    }
+    if !separated(body, radix) {
+        return Err(misplaced(literal));
+    }
+    i64::from_str_radix(&body.replace('_', ""), radix)
+        .map(Number::Int)
+        .map_err(|_| format!("'{}' is too large for a 64-bit integer", literal))
+}
+
+/// Whether every `_` in `part` sits between two digits
+fn separated(part: &str, radix: u32) -> bool {
+    let chars: Vec<char> = part.chars().collect();
+    chars.iter().enumerate().all(|(i, c)| {
+        *c != '_'
+            || (i > 0
+                && chars[i - 1].is_digit(radix)
+                && chars.get(i + 1).is_some_and(|c| c.is_digit(radix)))
+    })
+}
+
+fn misplaced(literal: &str) -> String {
+    format!(
+        "'{}' is not a number: put each `_` between two digits, as in 1_000_000",
+        literal
+    )
+}
+
+/// Length of the decimal digits and separators at the start of `text`
+fn digits_len(text: &str) -> usize {
+    text.bytes()
+        .take_while(|b| b.is_ascii_digit() || *b == b'_')
+        .count()
+}
+
+/// Length of the identifier characters at the start of `text`
+fn word_len(text: &str) -> usize {
+    text.find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or(text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_radix_and_separators() {
+        assert_eq!(scan("1_000_000"), (9, Ok(Number::Int(1_000_000))));
+        assert_eq!(scan("0xFF)"), (4, Ok(Number::Int(255))));
+        assert_eq!(scan("0xdead_BEEF"), (11, Ok(Number::Int(0xdead_beef))));
+        assert_eq!(scan("0o755 "), (5, Ok(Number::Int(0o755))));
+        assert_eq!(scan("0b1010"), (6, Ok(Number::Int(10))));
+        assert_eq!(scan("1_000.5e1_0"), (11, Ok(Number::Float(1000.5e10))));
+        assert_eq!(scan("3.25"), (4, Ok(Number::Float(3.25))));
+        assert_eq!(scan("1..5"), (1, Ok(Number::Int(1))));
+
+        for bad in ["1_", "1__000", "1_.5", "0x_FF", "1e_5"] {
+            let err = scan(bad).1.unwrap_err();
+            assert!(err.contains("between two digits"), "{}: {}", bad, err);
+        }
+        assert!(scan("0o789").1.unwrap_err().contains("0-7"));
+        assert!(scan("0x").1.unwrap_err().contains("hex"));
+        assert!(scan("0b102").1.unwrap_err().contains("0 and 1"));
+        assert!(scan("0x1_0000_0000_0000_0000")
+            .1
+            .unwrap_err()
+            .contains("too large"));
+        assert!(scan("1_000ms").1.unwrap_err().contains("separators"));
+        assert_eq!(scan("42abc").1.unwrap_err(), "'42abc' is not a number");
+
+        assert_eq!(parse("0o644"), Some(Number::Int(420)));
+        assert_eq!(parse("0o644 "), None);
+        assert!(is_spelled("0o644") && is_spelled("1_000") && !is_spelled("644"));
+    }
+}
@@ -0,0 +1,163 @@
+//! Size literals: `512Mi`, `2Gi`, `100MB`, `1.5G`
+//!
+//! A literal is a number and one unit: binary (`Ki`, `Mi`, `Gi`, `Ti`, `Pi`,
+//! optionally with a `B`), decimal (`k`, `M`, `G`, `T`, `P`, optionally with
+//! a `B`, plus `KB`) or plain bytes (`B`). A fraction is allowed when the
+//! result is a whole number of bytes. Sizes are held as bytes and written
+//! back as Kubernetes quantities, in the largest unit that divides them
+//! exactly: `1024Mi` is `1Gi` and `100MB` is `100M`.
+
+/// Units with their length in bytes, longer names before their prefixes
+const UNITS: &[(&str, i64)] = &[
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+    ("TiB", 1 << 40),
+    ("PiB", 1 << 50),
+    ("Ki", 1 << 10),
+    ("Mi", 1 << 20),
+    ("Gi", 1 << 30),
+    ("Ti", 1 << 40),
+    ("Pi", 1 << 50),
+    ("kB", 1_000),
+    ("KB", 1_000),
+    ("MB", 1_000_000),
+    ("GB", 1_000_000_000),
+    ("TB", 1_000_000_000_000),
+    ("PB", 1_000_000_000_000_000),
+    ("k", 1_000),
+    ("M", 1_000_000),
+    ("G", 1_000_000_000),
+    ("T", 1_000_000_000_000),
+    ("P", 1_000_000_000_000_000),
+    ("B", 1),
+];
+
+/// Units a size is written back in, largest first
+const OUTPUT_UNITS: &[(&str, i64)] = &[
+    ("Pi", 1 << 50),
+    ("P", 1_000_000_000_000_000),
+    ("Ti", 1 << 40),
+    ("T", 1_000_000_000_000),
+    ("Gi", 1 << 30),
+    ("G", 1_000_000_000),
+    ("Mi", 1 << 20),
+    ("M", 1_000_000),
+    ("Ki", 1 << 10),
+    ("k", 1_000),
+];
+
+/// Scan a size literal at the start of `text`, which starts with a digit.
+/// `None` when it is a plain number; otherwise the literal's length and its
+/// value in bytes, or why it is not a valid size.
+pub fn scan(text: &str) -> Option<(usize, Result<i64, String>)> {
+    let whole = text.bytes().take_while(u8::is_ascii_digit).count();
+    let mut end = whole;
+    let rest = &text[whole..];
+    if rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+        end += 1 + rest[1..].bytes().take_while(u8::is_ascii_digit).count();
+    }
+    let (unit, length) = UNITS
+        .iter()
+        .find(|(unit, _)| text[end..].starts_with(unit))?;
+    let number = &text[..end];
+    let pos = end + unit.len();
+
+    // `5Gb` or `2Mix` is not a size either way
+    let rest = &text[pos..];
+    if rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+        let end = rest
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        return Some((
+            pos + end,
+            Err(format!(
+                "'{}' is not a size: use B, k, M, G, T, P or Ki, Mi, Gi, Ti, Pi, as in 512Mi",
+                &text[..pos + end]
+            )),
+        ));
+    }
+
+    let bytes =
+        bytes(number, *length).map_err(|reason| format!("size '{}' is {}", &text[..pos], reason));
+    Some((pos, bytes))
+}
+
+/// `number` (digits with an optional fraction) times `length`, exactly
+fn bytes(number: &str, length: i64) -> Result<i64, &'static str> {
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let scale = 10i128
+        .checked_pow(fraction.len() as u32)
+        .ok_or("too precise")?;
+    let digits: i128 = format!("{}{}", whole, fraction)
+        .parse()
+        .map_err(|_| "too large")?;
+    let scaled = digits.checked_mul(length as i128).ok_or("too large")?;
+    if scaled % scale != 0 {
+        return Err("not a whole number of bytes");
+    }
+    i64::try_from(scaled / scale).map_err(|_| "too large")
+}
+
+/// The value of `text` when all of it is a size literal or a number of bytes
+pub fn parse(text: &str) -> Option<i64> {
+    if !text.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    match scan(text) {
+        Some((len, Ok(bytes))) if len == text.len() => Some(bytes),
+        None => text.parse().ok(),
+        _ => None,
+    }
+}
+
+/// A size as a Kubernetes quantity, in the largest unit that divides it:
+/// `1Gi`, `100M`, `1500`
+pub fn format(bytes: i64) -> String {
+    let sign = if bytes < 0 { "-" } else { "" };
+    let magnitude = bytes.unsigned_abs();
+    for (unit, length) in OUTPUT_UNITS {
+        let length = *length as u64;
+        if magnitude != 0 && magnitude.is_multiple_of(length) {
+            return format!("{}{}{}", sign, magnitude / length, unit);
+        }
+    }
+    format!("{}{}", sign, magnitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_and_format() {
+        assert_eq!(scan("512Mi"), Some((5, Ok(512 << 20))));
+        assert_eq!(scan("2GiB)"), Some((4, Ok(2 << 30))));
+        assert_eq!(scan("100MB "), Some((5, Ok(100_000_000))));
+        assert_eq!(scan("1.5Gi"), Some((5, Ok(1_610_612_736))));
+        assert_eq!(scan("64k"), Some((3, Ok(64_000))));
+        assert_eq!(scan("42"), None);
+        assert_eq!(scan("1.5"), None);
+        assert_eq!(scan("1e5"), None);
+        assert!(scan("5Gb").unwrap().1.unwrap_err().contains("'5Gb'"));
+        assert!(scan("0.1B")
+            .unwrap()
+            .1
+            .unwrap_err()
+            .contains("'0.1B' is not a whole number"));
+        assert!(scan("99999999PiB").unwrap().1.is_err());
+
+        assert_eq!(parse("256Mi"), Some(256 << 20));
+        assert_eq!(parse("1500"), Some(1500));
+        assert_eq!(parse("lots"), None);
+        assert_eq!(parse("5Mi "), None);
+
+        assert_eq!(format(1 << 30), "1Gi");
+        assert_eq!(format(1536 << 20), "1536Mi");
+        assert_eq!(format(100_000_000), "100M");
+        assert_eq!(format(1_024_000_000), "1024M");
+        assert_eq!(format(1500), "1500");
+        assert_eq!(format(0), "0");
+        assert_eq!(format(-(512 << 20)), "-512Mi");
+    }
+}
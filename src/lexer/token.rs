@@ -86,12 +86,18 @@ pub enum TokenKind {
     // Literals
     Integer(i64),
     Float(f64),
+    /// Duration in milliseconds: `5m`, `2h30m`, `1500ms`
+    Duration(i64),
+    /// Size in bytes: `512Mi`, `2Gi`, `100MB`
+    Size(i64),
     String(String),
 
     // String interpolation parts (for "text ${expr} more text")
     StringStart(String),  // "text ${
     StringMiddle(String), // } middle ${
     StringEnd(String),    // } end"
+    /// Format spec ending an interpolation: the `:.2` of `${ratio:.2}`
+    FormatSpec(super::format_spec::FormatSpec),
 
     // Triple-quoted strings
     TripleString(String),
@@ -133,10 +139,14 @@ pub enum TokenKind {
     Question,  // ?
     Eq,        // =
     Pipe,      // |
+    Pipeline,  // |>
+    FatArrow,  // =>
 
     // Special
     Newline,
     Eof,
+    /// Malformed input the lexer skipped past; the error is in `Lexer::errors`
+    Error,
 }
 
 impl TokenKind {
@@ -232,10 +242,13 @@ impl fmt::Display for TokenKind {
             TokenKind::False => write!(f, "false"),
             TokenKind::Integer(n) => write!(f, "{}", n),
             TokenKind::Float(n) => write!(f, "{}", n),
+            TokenKind::Duration(ms) => write!(f, "{}", super::duration::format(*ms)),
+            TokenKind::Size(bytes) => write!(f, "{}", super::size::format(*bytes)),
             TokenKind::String(s) => write!(f, "\"{}\"", s),
             TokenKind::StringStart(s) => write!(f, "\"{}${{", s),
             TokenKind::StringMiddle(s) => write!(f, "}}{}${{", s),
             TokenKind::StringEnd(s) => write!(f, "}}{}\"", s),
+            TokenKind::FormatSpec(spec) => write!(f, ":{}", spec),
             TokenKind::TripleString(s) => write!(f, "\"\"\"{}\"\"\"", s),
             TokenKind::Ident(s) => write!(f, "{}", s),
             TokenKind::LeftBrace => write!(f, "{{"),
@@ -268,9 +281,12 @@ impl fmt::Display for TokenKind {
             TokenKind::Not => write!(f, "!"),
             TokenKind::Question => write!(f, "?"),
             TokenKind::Eq => write!(f, "="),
+            TokenKind::FatArrow => write!(f, "=>"),
             TokenKind::Pipe => write!(f, "|"),
+            TokenKind::Pipeline => write!(f, "|>"),
             TokenKind::Newline => write!(f, "<newline>"),
             TokenKind::Eof => write!(f, "<eof>"),
+            TokenKind::Error => write!(f, "<error>"),
         }
     }
 }
@@ -0,0 +1,112 @@
+//! Triple-quoted strings: indentation stripping and chomping
+//!
+//! The indentation common to every non-blank line is removed, so a string
+//! can be indented with the code around it. The line holding only the
+//! closing quotes adds nothing, which leaves the string ending in a newline
+//! when the quotes sit on their own line. Opening with `"""-` (or `'''-`)
+//! followed by a line break chomps: trailing newlines are removed.
+//!
+//! A string with interpolations is handled as its literal segments, with
+//! each `${...}` counting as content: it ends a line's indentation and is
+//! never stripped.
+
+/// Strip the common indentation from the literal `segments` of a
+/// triple-quoted string, which have an interpolation between each pair,
+/// drop the closing line, and chomp the trailing newlines if asked.
+pub fn strip(segments: &mut [String], chomp: bool) {
+    let indent = common_indent(segments);
+    let mut at_line_start = true;
+    for segment in segments.iter_mut() {
+        let mut stripped = String::with_capacity(segment.len());
+        let mut skipped = 0;
+        let mut chars = segment.chars().peekable();
+        while let Some(ch) = chars.next() {
+            if ch == '\r' && chars.peek() == Some(&'\n') {
+                continue;
+            }
+            if ch == '\n' {
+                at_line_start = true;
+                skipped = 0;
+                stripped.push(ch);
+                continue;
+            }
+            if at_line_start && skipped < indent && (ch == ' ' || ch == '\t') {
+                skipped += 1;
+                continue;
+            }
+            at_line_start = false;
+            stripped.push(ch);
+        }
+        *segment = stripped;
+        // The interpolation after this segment is content
+        at_line_start = false;
+    }
+
+    if let Some(last) = segments.last_mut() {
+        if let Some(newline) = last.rfind('\n') {
+            if last[newline + 1..].trim().is_empty() {
+                last.truncate(newline + 1);
+            }
+        }
+        if chomp {
+            let kept = last.trim_end_matches('\n').len();
+            last.truncate(kept);
+        }
+    }
+}
+
+/// The indentation shared by every line with content. Blank lines,
+/// including the closing line, don't count.
+fn common_indent(segments: &[String]) -> usize {
+    let mut indent: Option<usize> = None;
+    let mut measuring = Some(0);
+    for (i, segment) in segments.iter().enumerate() {
+        for ch in segment.chars() {
+            match (ch, measuring) {
+                ('\n', _) => measuring = Some(0),
+                (' ' | '\t', Some(n)) => measuring = Some(n + 1),
+                ('\r', _) => {}
+                (_, Some(n)) => {
+                    indent = Some(indent.map_or(n, |i| i.min(n)));
+                    measuring = None;
+                }
+                _ => {}
+            }
+        }
+        if i + 1 < segments.len() {
+            if let Some(n) = measuring.take() {
+                indent = Some(indent.map_or(n, |i| i.min(n)));
+            }
+        }
+    }
+    indent.unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stripped(segments: &[&str], chomp: bool) -> Vec<String> {
+        let mut segments: Vec<String> = segments.iter().map(|s| s.to_string()).collect();
+        strip(&mut segments, chomp);
+        segments
+    }
+
+    #[test]
+    fn test_strip_indent_and_chomp() {
+        assert_eq!(
+            stripped(&["  #!/bin/sh\n    echo hi\n\n  exit 0\n  "], false),
+            ["#!/bin/sh\n  echo hi\n\nexit 0\n"]
+        );
+        assert_eq!(stripped(&["  a\n  b\n      "], true), ["a\nb"]);
+        assert_eq!(stripped(&["a\r\nb\r\n"], false), ["a\nb\n"]);
+        assert_eq!(stripped(&["hello world "], false), ["hello world "]);
+
+        // Interpolations are content, and keep their surrounding text
+        assert_eq!(
+            stripped(&["    name: ", "\n    tags:\n      - ", "\n    "], false),
+            ["name: ", "\ntags:\n  - ", "\n"]
+        );
+        assert_eq!(stripped(&["    a\n  ", " b\n    "], true), ["  a\n", " b"]);
+    }
+}
@@ -20,40 +20,69 @@
 //! ```
 
 pub mod cache;
+pub mod cancel;
+pub mod codemod;
 pub mod compiler;
+pub mod conflicts;
+pub mod dap;
 pub mod differ;
+pub mod doctor;
 pub mod emitter;
 pub mod errors;
 pub mod evaluator;
 pub mod formatter;
+pub mod freeze;
 pub mod graph;
 pub mod importer;
+pub mod k8s;
 pub mod lexer;
 #[cfg(feature = "lsp")]
 pub mod lsp;
+pub mod manifest;
+pub mod optimizer;
+pub mod overlay;
 pub mod parser;
 pub mod resolver;
+pub mod serve;
+pub mod stats;
+pub mod style;
 pub mod typechecker;
 pub mod typeprovider;
+pub mod unused;
+#[cfg(feature = "self-update")]
+pub mod updater;
+pub mod worker;
 
+pub use cancel::CancelToken;
 pub use compiler::{
-    build_args_object, compile_file, compile_file_with_args, infer_value, validate_against_schema,
-    CompiledFile, Compiler,
+    build_args_object, collect_inputs, compile_file, compile_file_with_args, infer_value,
+    validate_against_schema, CompileInputs, CompiledFile, Compiler, ExpectedArg, VariantChoice,
 };
+pub use differ::vcs::VcsProvider;
 pub use differ::{
-    blame_diff, compile_at_ref, diff_values, diff_with_moves, format_blame_text, format_diff_json,
-    format_diff_text, parse_arg_string, BlameInfo, DiffEntry, DiffKind,
+    blame_diff, blame_diff_with, compile_at_ref, diff_values, diff_with_moves, diff_with_options,
+    format_blame_text, format_blame_text_with, format_diff_json, format_diff_text,
+    format_diff_text_with, parse_arg_string, redact_entries, source_at_ref, source_at_ref_with,
+    BlameInfo, DiffEntry, DiffKind, DiffOptions,
 };
 pub use emitter::{
-    emit, emit_multi, DotenvEmitter, Emitter, JsonEmitter, OutputFormat, TomlEmitter, YamlEmitter,
+    emit, emit_multi, emit_multi_with, emit_to_writer, emit_with, register_emitter, write_output,
+    write_output_with, CsvEmitter, DotenvArrays, DotenvEmitter, EmitOptions, Emitter, EnvVar,
+    HclEmitter, JsonEmitter, OutputFormat, PropertiesEmitter, StreamEmitter, TomlEmitter,
+    WriteOptions, XmlEmitter, YamlEmitter,
+};
+pub use errors::{Catalog, HoneError, HoneResult, Localized, Warning};
+pub use evaluator::keys::KeyCollision;
+pub use evaluator::{
+    pretty_print, Evaluator, PolicyCache, PolicyTiming, PrettyOptions, Redactor, Value,
 };
-pub use errors::{HoneError, HoneResult, Warning};
-pub use evaluator::{Evaluator, Value};
 pub use formatter::format_source;
 pub use lexer::token::{SourceLocation, Token, TokenKind};
 pub use lexer::{Comment, Lexer};
+pub use optimizer::optimize;
 pub use parser::ast;
 pub use parser::Parser;
 pub use resolver::{ImportResolver, ResolvedFile, VirtualResolver};
+pub use style::{ColorChoice, Style};
 pub use typechecker::{Type, TypeChecker, TypeEnv, TypeRegistry};
 pub use typeprovider::generate_from_file as typegen;
@@ -0,0 +1,285 @@
+//! Incremental re-parsing
+//!
+//! Editors send small edits to large documents. Instead of parsing the whole
+//! document again, the top-level items around the edited bytes are re-lexed
+//! and re-parsed, the items before them are kept as they are, and the items
+//! after them are kept with their locations shifted. Whenever the edit could
+//! change how the rest of the file parses — it touches several documents,
+//! opens a block comment, or does not re-parse cleanly on its own — the
+//! caller falls back to a full parse, so the result is always the same AST.
+
+use std::path::PathBuf;
+
+use crate::cancel::CancelToken;
+use crate::lexer::token::SourceLocation;
+use crate::lexer::Lexer;
+use crate::parser::ast::{BodyItem, File, PreambleItem};
+use crate::parser::shift::{Offset, Shift};
+use crate::parser::Parser;
+
+/// Bytes edited since the last parse, in the coordinates of the parsed text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dirty {
+    /// First edited byte
+    pub start: usize,
+    /// End of the edited bytes
+    pub end: usize,
+    /// Bytes added (negative when removed) since the last parse
+    pub delta: isize,
+}
+
+impl Dirty {
+    /// Add an edit replacing `start..end` of the current text with `inserted`
+    /// bytes
+    pub fn record(dirty: Option<Dirty>, start: usize, end: usize, inserted: usize) -> Dirty {
+        let edit_delta = inserted as isize - (end - start) as isize;
+        let Some(dirty) = dirty else {
+            return Dirty {
+                start,
+                end,
+                delta: edit_delta,
+            };
+        };
+        // Map a position in the current text back to the parsed text
+        let parsed = |pos: usize| {
+            if pos <= dirty.start {
+                pos
+            } else if pos as isize >= dirty.end as isize + dirty.delta {
+                (pos as isize - dirty.delta) as usize
+            } else {
+                dirty.end
+            }
+        };
+        Dirty {
+            start: dirty.start.min(parsed(start)),
+            end: dirty.end.max(parsed(end)),
+            delta: dirty.delta + edit_delta,
+        }
+    }
+}
+
+/// Location of a top-level item
+enum Item<'a> {
+    Preamble(&'a PreambleItem),
+    Body(&'a BodyItem),
+}
+
+impl Item<'_> {
+    fn location(&self) -> &SourceLocation {
+        match self {
+            Item::Preamble(item) => match item {
+                PreambleItem::Let(i) => &i.location,
+                PreambleItem::From(i) => &i.location,
+                PreambleItem::Import(i) => &i.location,
+                PreambleItem::Schema(i) => &i.location,
+                PreambleItem::TypeAlias(i) => &i.location,
+                PreambleItem::Enum(i) => &i.location,
+                PreambleItem::Use(i) => &i.location,
+                PreambleItem::Variant(i) => &i.location,
+                PreambleItem::Expect(i) => &i.location,
+                PreambleItem::Secret(i) => &i.location,
+                PreambleItem::Policy(i) => &i.location,
+                PreambleItem::FnDef(i) => &i.location,
+            },
+            Item::Body(item) => match item {
+                BodyItem::KeyValue(i) => &i.location,
+                BodyItem::Block(i) => &i.location,
+                BodyItem::When(i) => &i.location,
+                BodyItem::For(i) => &i.location,
+                BodyItem::Assert(i) => &i.location,
+                BodyItem::Let(i) => &i.location,
+                BodyItem::Spread(i) => &i.location,
+                BodyItem::Use(i) => &i.location,
+                BodyItem::Include(i) => &i.location,
+            },
+        }
+    }
+}
+
+/// Re-parse `text` after the edits in `dirty` to `old`, the AST of the text
+/// before them. `None` when only a full parse gives the right result.
+pub fn reparse(
+    old: &File,
+    dirty: Dirty,
+    text: &str,
+    path: Option<PathBuf>,
+    cancel: &CancelToken,
+) -> Option<File> {
+    if !old.documents.is_empty() {
+        return None;
+    }
+    let items: Vec<Item> = old
+        .preamble
+        .iter()
+        .map(Item::Preamble)
+        .chain(old.body.iter().map(Item::Body))
+        .collect();
+    let locations: Vec<&SourceLocation> = items.iter().map(Item::location).collect();
+    // Items must start lines of their own for the region to end between them
+    if locations.is_empty()
+        || locations[1..].iter().any(|loc| loc.column != 1)
+        || locations.windows(2).any(|w| w[0].line >= w[1].line)
+    {
+        return None;
+    }
+
+    // Re-parse from the item containing the first edited byte through the
+    // item after the one containing the last, which holds any doc comment
+    let first = locations.iter().rposition(|loc| loc.offset <= dirty.start);
+    let last = locations
+        .iter()
+        .rposition(|loc| loc.offset <= dirty.end)
+        .unwrap_or(0);
+    let kept = (last + 2).min(items.len());
+
+    let (start, start_line) = match first {
+        Some(i) => {
+            let line_start = text[..locations[i].offset].rfind('\n').map_or(0, |n| n + 1);
+            (line_start, locations[i].line)
+        }
+        None => (0, 1),
+    };
+    let first = first.unwrap_or(0);
+    let old_len = (text.len() as isize - dirty.delta) as usize;
+    let old_end = locations.get(kept).map_or(old_len, |loc| loc.offset);
+    let end = (old_end as isize + dirty.delta) as usize;
+    let region = text.get(start..end)?;
+    if region.contains("/*") {
+        return None;
+    }
+
+    let mut tokens = Lexer::new(region, path.clone())
+        .with_cancel(cancel.clone())
+        .tokenize()
+        .ok()?;
+    let moved = Offset {
+        lines: start_line as isize - 1,
+        bytes: start as isize,
+    };
+    tokens.shift(moved);
+    let in_body = first > old.preamble.len();
+    let (preamble, body) = Parser::new(tokens, text, path)
+        .with_cancel(cancel.clone())
+        .parse_region(in_body)
+        .ok()?;
+
+    // Items after the region, moved to their new lines and bytes
+    let lines = start_line + region.matches('\n').count();
+    let by = Offset {
+        lines: locations
+            .get(kept)
+            .map_or(0, |loc| lines as isize - loc.line as isize),
+        bytes: dirty.delta,
+    };
+    let split = |at: usize| at.min(old.preamble.len());
+    let mut after_preamble = old.preamble[split(kept)..].to_vec();
+    let mut after_body = old.body[kept.max(old.preamble.len()) - old.preamble.len()..].to_vec();
+    // A body item in the region would make later preamble items body items
+    if (!body.is_empty() || in_body) && !after_preamble.is_empty() {
+        return None;
+    }
+    after_preamble.shift(by);
+    after_body.shift(by);
+
+    let before_body = first.saturating_sub(old.preamble.len());
+    let mut file = File {
+        preamble: old.preamble[..split(first)].to_vec(),
+        body: old.body[..before_body].to_vec(),
+        documents: Vec::new(),
+        location: old.location.clone(),
+    };
+    file.preamble.extend(preamble);
+    file.preamble.extend(after_preamble);
+    file.body.extend(body);
+    file.body.extend(after_body);
+    file.location.length = (old.location.length as isize + dirty.delta) as usize;
+    Some(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> File {
+        let tokens = Lexer::new(source, None).tokenize().unwrap();
+        Parser::new(tokens, source, None).parse().unwrap()
+    }
+
+    /// Apply edits one after another and check the incremental result
+    /// against a full parse of the final text
+    fn check(source: &str, edits: &[(usize, usize, &str)]) -> bool {
+        let old = parse(source);
+        let mut text = source.to_string();
+        let mut dirty = None;
+        for (start, end, insert) in edits {
+            text.replace_range(start..end, insert);
+            dirty = Some(Dirty::record(dirty, *start, *end, insert.len()));
+        }
+        let Some(file) = reparse(&old, dirty.unwrap(), &text, None, &CancelToken::new()) else {
+            return false;
+        };
+        let full = parse(&text);
+        assert_eq!(file.preamble, full.preamble, "{}", text);
+        assert_eq!(file.body, full.body, "{}", text);
+        true
+    }
+
+    const SOURCE: &str = "let a = 1\nlet b = 2\n\n# port\nport: a + 1\nserver {\n  host: \"x\"\n}\nname: \"n\"\nlast: [1, 2]\n";
+
+    #[test]
+    fn test_reparse_matches_full_parse() {
+        let at = |s: &str| SOURCE.find(s).unwrap();
+        // Change a value inside a block
+        assert!(check(
+            SOURCE,
+            &[(at("\"x\""), at("\"x\"") + 3, "\"longer\"\n  tls: true")]
+        ));
+        // Insert a line between items
+        assert!(check(SOURCE, &[(at("name"), at("name"), "extra: 1\n")]));
+        // Edit the first and the last item
+        assert!(check(SOURCE, &[(0, 9, "let a = 100")]));
+        assert!(check(SOURCE, &[(at("[1, 2]"), at("[1, 2]") + 6, "[]")]));
+        // Delete an item
+        assert!(check(SOURCE, &[(at("name"), at("last"), "")]));
+        // Two edits far apart
+        assert!(check(
+            SOURCE,
+            &[(at("a + 1"), at("a + 1") + 1, "b"), (8, 9, "10")]
+        ));
+        // A preamble item turned into a body item
+        assert!(check(SOURCE, &[(0, 9, "a: 1")]));
+    }
+
+    #[test]
+    fn test_reparse_falls_back() {
+        let at = |s: &str| SOURCE.find(s).unwrap();
+        // An unclosed block swallows the rest of the file
+        assert!(!check(SOURCE, &[(at("}"), at("}") + 1, "")]));
+        // A block comment may hide later items
+        assert!(!check(SOURCE, &[(at("name"), at("name"), "/* ")]));
+        // Later preamble items would become body items
+        assert!(!check(
+            "let a = 1\nlet b = 2\nlet c = 3\nx: c\n",
+            &[(0, 9, "a: 1")]
+        ));
+    }
+
+    #[test]
+    fn test_record_merges_edits() {
+        let dirty = Dirty::record(None, 10, 12, 5);
+        assert_eq!(
+            dirty,
+            Dirty {
+                start: 10,
+                end: 12,
+                delta: 3
+            }
+        );
+        // After the first edit, in current coordinates
+        let dirty = Dirty::record(Some(dirty), 20, 20, 2);
+        assert_eq!((dirty.start, dirty.end, dirty.delta), (10, 17, 5));
+        // Before it
+        let dirty = Dirty::record(Some(dirty), 5, 6, 0);
+        assert_eq!((dirty.start, dirty.end, dirty.delta), (5, 17, 4));
+    }
+}
@@ -0,0 +1,536 @@
+//! Workspace index
+//!
+//! Cross-file features — workspace symbols, definitions of imported names,
+//! completions after `alias.`, and fields of imported schemas — need to know
+//! what every `.hone` file in the workspace defines. The index keeps a
+//! [`Summary`] of each file: its top-level names, its schemas, and the files
+//! it imports. Summaries are stored as JSON under `~/.cache/hone/lsp-index/`,
+//! keyed by a hash of the Hone version and the file's content, so a restart
+//! reads unchanged files back instead of lexing and parsing them all again.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{cache_root, CacheKey};
+use crate::cancel::CancelToken;
+use crate::lexer::token::SourceLocation;
+use crate::lexer::Lexer;
+use crate::parser::ast::{
+    BodyItem, File, ImportKind, Key, PreambleItem, SchemaDefinition, StringPart,
+};
+use crate::parser::Parser;
+
+/// Longest item text kept as a symbol's detail
+const MAX_DETAIL_LEN: usize = 80;
+
+/// Directories never scanned for `.hone` files
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules"];
+
+/// What a file defines at the top level
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolKind {
+    Let,
+    Fn,
+    Schema,
+    Type,
+    Enum,
+    /// An output key
+    Key,
+}
+
+/// A top-level name and where it is defined
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Line of the name, 0-based
+    pub line: u32,
+    /// Column of the name, 0-based
+    pub column: u32,
+    /// First line of the definition, e.g. `fn add(a, b) {`
+    pub detail: String,
+}
+
+/// A schema field, with its type as shown in completions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldSummary {
+    pub name: String,
+    pub type_name: String,
+    pub optional: bool,
+}
+
+/// A schema and the fields it declares itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchemaSummary {
+    pub name: String,
+    pub extends: Option<String>,
+    pub fields: Vec<FieldSummary>,
+}
+
+impl SchemaSummary {
+    pub fn new(schema: &SchemaDefinition) -> Self {
+        Self {
+            name: schema.name.clone(),
+            extends: schema.extends.clone(),
+            fields: schema
+                .fields
+                .iter()
+                .map(|field| FieldSummary {
+                    name: field.name.clone(),
+                    type_name: super::format_type_constraint(&field.constraint),
+                    optional: field.optional,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// An `import` of another file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportSummary {
+    /// The path as written
+    pub path: String,
+    /// `import "path" [as alias]`: the name the file's exports are under,
+    /// the alias or else the file's stem
+    pub namespace: Option<String>,
+    /// `import { ... } from "path"`
+    pub names: Vec<ImportedName>,
+}
+
+/// A name in `import { name as local, schema Name } from "path"`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportedName {
+    pub name: String,
+    /// The name in the importing file
+    pub local: String,
+    pub is_schema: bool,
+}
+
+/// What cross-file features need to know about a file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub symbols: Vec<Symbol>,
+    pub schemas: Vec<SchemaSummary>,
+    pub imports: Vec<ImportSummary>,
+}
+
+impl Summary {
+    /// Summarize a parsed file
+    pub fn new(ast: &File, source: &str) -> Self {
+        let mut summary = Summary::default();
+        let mut symbol = |name: &str, kind, location: &SourceLocation| {
+            summary
+                .symbols
+                .push(make_symbol(source, name, kind, location));
+        };
+        for item in &ast.preamble {
+            match item {
+                PreambleItem::Let(i) => {
+                    for name in i.names() {
+                        symbol(name, SymbolKind::Let, &i.location)
+                    }
+                }
+                PreambleItem::FnDef(i) => symbol(&i.name, SymbolKind::Fn, &i.location),
+                PreambleItem::Schema(i) => symbol(&i.name, SymbolKind::Schema, &i.location),
+                PreambleItem::TypeAlias(i) => symbol(&i.name, SymbolKind::Type, &i.location),
+                PreambleItem::Enum(i) => symbol(&i.name, SymbolKind::Enum, &i.location),
+                _ => {}
+            }
+        }
+        for item in &ast.body {
+            match item {
+                BodyItem::Let(i) => {
+                    for name in i.names() {
+                        symbol(name, SymbolKind::Let, &i.location)
+                    }
+                }
+                BodyItem::KeyValue(kv) => match &kv.key {
+                    Key::Ident(name) | Key::String(name) => {
+                        symbol(name, SymbolKind::Key, &kv.location)
+                    }
+                    Key::Computed(_) => {}
+                },
+                BodyItem::Block(i) => symbol(&i.name, SymbolKind::Key, &i.location),
+                _ => {}
+            }
+        }
+
+        for item in &ast.preamble {
+            if let PreambleItem::Schema(schema) = item {
+                summary.schemas.push(SchemaSummary::new(schema));
+            }
+        }
+        summary.imports = imports(ast);
+        summary
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+
+    /// A schema with the fields of the schemas it extends first
+    pub fn flattened_schema(&self, name: &str) -> Option<SchemaSummary> {
+        let mut chain = Vec::new();
+        let mut next = Some(name);
+        while let Some(name) = next {
+            // Stop at cycles and at parents defined elsewhere
+            if chain.iter().any(|s: &&SchemaSummary| s.name == name) {
+                break;
+            }
+            let Some(schema) = self.schemas.iter().find(|s| s.name == name) else {
+                break;
+            };
+            chain.push(schema);
+            next = schema.extends.as_deref();
+        }
+        let schema = chain.first()?;
+        let mut fields: Vec<FieldSummary> = Vec::new();
+        for parent in chain.iter().rev() {
+            for field in &parent.fields {
+                if !fields.iter().any(|f| f.name == field.name) {
+                    fields.push(field.clone());
+                }
+            }
+        }
+        Some(SchemaSummary {
+            name: schema.name.clone(),
+            extends: None,
+            fields,
+        })
+    }
+}
+
+/// The imports of a parsed file
+pub fn imports(ast: &File) -> Vec<ImportSummary> {
+    let mut imports = Vec::new();
+    for item in &ast.preamble {
+        let PreambleItem::Import(import) = item else {
+            continue;
+        };
+        let path = match &import.kind {
+            ImportKind::Whole { path, .. } | ImportKind::Named { path, .. } => path,
+        };
+        // Import paths must be literal; compiling rejects interpolations
+        let path: String = path
+            .parts
+            .iter()
+            .filter_map(|part| match part {
+                StringPart::Literal(s) => Some(s.as_str()),
+                StringPart::Interpolation(_) | StringPart::Formatted(..) => None,
+            })
+            .collect();
+        let (namespace, names) = match &import.kind {
+            ImportKind::Whole { alias, .. } => {
+                let stem = Path::new(&path)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned());
+                (alias.clone().or(stem), Vec::new())
+            }
+            ImportKind::Named { names, .. } => {
+                let names = names
+                    .iter()
+                    .map(|n| ImportedName {
+                        name: n.name.clone(),
+                        local: n.alias.clone().unwrap_or_else(|| n.name.clone()),
+                        is_schema: n.is_schema,
+                    })
+                    .collect();
+                (None, names)
+            }
+        };
+        imports.push(ImportSummary {
+            path,
+            namespace,
+            names,
+        });
+    }
+    imports
+}
+
+/// Symbol for `name`, defined by the item at `location`
+fn make_symbol(source: &str, name: &str, kind: SymbolKind, location: &SourceLocation) -> Symbol {
+    let start = location.offset.min(source.len());
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |n| start + n);
+    let item_line = source.get(start..line_end).unwrap_or("");
+    // The location points at the keyword; find the name after it
+    let name_start = find_word(item_line, name).unwrap_or(0);
+    let column = location.column.saturating_sub(1) + item_line[..name_start].chars().count();
+
+    let mut detail = item_line.trim().to_string();
+    if detail.chars().count() > MAX_DETAIL_LEN {
+        detail = detail.chars().take(MAX_DETAIL_LEN - 1).collect();
+        detail.push('…');
+    }
+    Symbol {
+        name: name.to_string(),
+        kind,
+        line: location.line.saturating_sub(1) as u32,
+        column: column as u32,
+        detail,
+    }
+}
+
+/// Byte offset of `word` in `text` where it is not part of a longer word
+fn find_word(text: &str, word: &str) -> Option<usize> {
+    let is_word = |c: Option<char>| c.is_some_and(super::is_word_char);
+    text.match_indices(word).map(|(i, _)| i).find(|&i| {
+        !is_word(text[..i].chars().next_back()) && !is_word(text[i + word.len()..].chars().next())
+    })
+}
+
+/// Summaries of the files in a workspace
+#[derive(Debug)]
+pub struct WorkspaceIndex {
+    /// Summaries by canonical path
+    files: DashMap<PathBuf, Arc<Summary>>,
+    /// Where summaries are stored; `None` keeps them in memory only
+    store: Option<PathBuf>,
+}
+
+impl WorkspaceIndex {
+    /// An index stored in the default cache directory
+    pub fn new() -> Self {
+        Self::with_store(cache_root().map(|root| root.join("lsp-index")))
+    }
+
+    /// An index stored in `store` (for testing)
+    pub fn with_store(store: Option<PathBuf>) -> Self {
+        Self {
+            files: DashMap::new(),
+            store,
+        }
+    }
+
+    /// Index every `.hone` file under `root`. Returns the number of files
+    /// indexed and how many of them were read from the store.
+    pub fn scan(&self, root: &Path, cancel: &CancelToken) -> (usize, usize) {
+        let mut files = Vec::new();
+        collect_hone_files(root, &mut files);
+        let (mut indexed, mut stored) = (0, 0);
+        for path in files {
+            if cancel.is_cancelled() {
+                break;
+            }
+            let Ok(content) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            match self.update(&path, &content) {
+                Some(true) => (indexed, stored) = (indexed + 1, stored + 1),
+                Some(false) => indexed += 1,
+                None => {}
+            }
+        }
+        (indexed, stored)
+    }
+
+    /// Index `path` with `content`. Returns whether the summary came from the
+    /// store, or `None` when the content does not parse, in which case the
+    /// last summary is kept.
+    pub fn update(&self, path: &Path, content: &str) -> Option<bool> {
+        let path = crate::resolver::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let hash = CacheKey::hash_string(&format!("{}\n{}", env!("CARGO_PKG_VERSION"), content));
+        if let Some(summary) = self.load(&hash) {
+            self.files.insert(path, Arc::new(summary));
+            return Some(true);
+        }
+
+        let tokens = Lexer::new(content, Some(path.clone())).tokenize().ok()?;
+        let ast = Parser::new(tokens, content, Some(path.clone()))
+            .parse()
+            .ok()?;
+        let summary = Summary::new(&ast, content);
+        self.save(&hash, &summary);
+        self.files.insert(path, Arc::new(summary));
+        Some(false)
+    }
+
+    /// Summary of `path`, indexing it from disk if it is not indexed yet
+    pub fn get(&self, path: &Path) -> Option<Arc<Summary>> {
+        let path = crate::resolver::canonicalize(path).ok()?;
+        if let Some(summary) = self.files.get(&path) {
+            return Some(Arc::clone(&summary));
+        }
+        let content = std::fs::read_to_string(&path).ok()?;
+        self.update(&path, &content)?;
+        self.files.get(&path).map(|s| Arc::clone(&s))
+    }
+
+    /// Symbols whose name contains `query`, ignoring case
+    pub fn search(&self, query: &str) -> Vec<(PathBuf, Symbol)> {
+        let query = query.to_lowercase();
+        let mut found: Vec<(PathBuf, Symbol)> = self
+            .files
+            .iter()
+            .flat_map(|entry| {
+                let path = entry.key().clone();
+                entry
+                    .value()
+                    .symbols
+                    .iter()
+                    .filter(|s| s.name.to_lowercase().contains(&query))
+                    .map(|s| (path.clone(), s.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        found.sort_by(|a, b| (&a.0, a.1.line).cmp(&(&b.0, b.1.line)));
+        found
+    }
+
+    fn entry_path(&self, hash: &str) -> Option<PathBuf> {
+        let (prefix, _) = hash.split_at(2.min(hash.len()));
+        Some(
+            self.store
+                .as_ref()?
+                .join(prefix)
+                .join(format!("{}.json", hash)),
+        )
+    }
+
+    fn load(&self, hash: &str) -> Option<Summary> {
+        let content = std::fs::read_to_string(self.entry_path(hash)?).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Store a summary; failures only cost a re-parse next time
+    fn save(&self, hash: &str, summary: &Summary) {
+        let Some(path) = self.entry_path(hash) else {
+            return;
+        };
+        let Ok(content) = serde_json::to_string(summary) else {
+            return;
+        };
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        // Write to a temp file then rename, so readers never see half a file
+        let tmp_path = path.with_extension("tmp");
+        if std::fs::write(&tmp_path, content).is_ok() {
+            let _ = std::fs::rename(&tmp_path, &path);
+        }
+    }
+}
+
+impl Default for WorkspaceIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolve an import path written in `from`
+pub fn resolve_import(from: &Path, import: &str) -> PathBuf {
+    let path = Path::new(import);
+    match from.parent() {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// `.hone` files under `dir`, skipping hidden and build directories
+fn collect_hone_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries.filter_map(|e| e.ok()).collect();
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_ref()) {
+                collect_hone_files(&path, files);
+            }
+        } else if path.extension().is_some_and(|ext| ext == "hone") {
+            files.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIB: &str = "import \"./base.hone\" as base\nimport { schema Port as P } from \"./types.hone\"\n\nschema Server extends Named {\n  port: int\n  tls?: bool\n}\nschema Named {\n  name: string\n}\nlet region = \"eu\"\nfn double(x) {\n  x * 2\n}\n\nservice {\n  name: \"api\"\n}\nreplicas: 3\n";
+
+    #[test]
+    fn test_summary_of_file() {
+        let tokens = Lexer::new(LIB, None).tokenize().unwrap();
+        let ast = Parser::new(tokens, LIB, None).parse().unwrap();
+        let summary = Summary::new(&ast, LIB);
+
+        let names: Vec<(&str, SymbolKind, u32, u32)> = summary
+            .symbols
+            .iter()
+            .map(|s| (s.name.as_str(), s.kind, s.line, s.column))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("Server", SymbolKind::Schema, 3, 7),
+                ("Named", SymbolKind::Schema, 7, 7),
+                ("region", SymbolKind::Let, 10, 4),
+                ("double", SymbolKind::Fn, 11, 3),
+                ("service", SymbolKind::Key, 15, 0),
+                ("replicas", SymbolKind::Key, 18, 0),
+            ]
+        );
+        assert_eq!(summary.symbol("double").unwrap().detail, "fn double(x) {");
+        assert_eq!(
+            summary.imports[1].names,
+            vec![ImportedName {
+                name: "Port".to_string(),
+                local: "P".to_string(),
+                is_schema: true
+            }]
+        );
+        assert_eq!(summary.imports[0].namespace.as_deref(), Some("base"));
+
+        let server = summary.flattened_schema("Server").unwrap();
+        let fields: Vec<&str> = server.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(fields, vec!["name", "port", "tls"]);
+        assert!(server.fields[2].optional);
+    }
+
+    #[test]
+    fn test_index_reads_unchanged_files_from_store() {
+        let workspace = tempfile::tempdir().unwrap();
+        let store = tempfile::tempdir().unwrap();
+        std::fs::create_dir(workspace.path().join("apps")).unwrap();
+        std::fs::create_dir(workspace.path().join(".git")).unwrap();
+        std::fs::write(workspace.path().join("lib.hone"), LIB).unwrap();
+        std::fs::write(workspace.path().join("apps/a.hone"), "let regionName = 1\n").unwrap();
+        std::fs::write(workspace.path().join(".git/x.hone"), "let hidden = 1\n").unwrap();
+        std::fs::write(workspace.path().join("broken.hone"), "let = \n").unwrap();
+
+        let cancel = CancelToken::new();
+        let index = WorkspaceIndex::with_store(Some(store.path().to_path_buf()));
+        assert_eq!(index.scan(workspace.path(), &cancel), (2, 0));
+
+        // A new server reads both summaries back
+        let index = WorkspaceIndex::with_store(Some(store.path().to_path_buf()));
+        assert_eq!(index.scan(workspace.path(), &cancel), (2, 2));
+        let found: Vec<String> = index
+            .search("REGION")
+            .into_iter()
+            .map(|(path, s)| format!("{}:{}", path.file_name().unwrap().to_string_lossy(), s.name))
+            .collect();
+        assert_eq!(found, vec!["a.hone:regionName", "lib.hone:region"]);
+
+        // Edited content is parsed again; content that fails to parse keeps
+        // the last summary
+        let a = workspace.path().join("apps/a.hone");
+        assert_eq!(index.update(&a, "let zone = 2\n"), Some(false));
+        assert_eq!(index.update(&a, "let zone = \n"), None);
+        assert!(index.get(&a).unwrap().symbol("zone").is_some());
+        assert!(index.search("regionName").is_empty());
+    }
+}
@@ -1,5 +1,6 @@
 #![allow(unused_assignments)]
 
+use std::io::IsTerminal;
 use std::path::PathBuf;
 use std::process::ExitCode;
 
@@ -14,8 +15,85 @@ use clap::{Parser, Subcommand};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Diagnostic output format: human (default) or json (one object per line on stderr)
+    #[arg(long, global = true, default_value = "human", value_parser = ["human", "json"])]
+    error_format: String,
+
+    /// Color human output: auto (terminals, unless NO_COLOR is set), always, never
+    #[arg(long, global = true, default_value = "auto", value_parser = ["auto", "always", "never"])]
+    color: String,
+
+    /// Language of diagnostics: en (default), ja, or the path of a TOML message catalog
+    #[arg(long, global = true, default_value = "en")]
+    lang: String,
+}
+
+/// Set once from `--error-format`; read wherever diagnostics are printed
+static JSON_DIAGNOSTICS: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+
+fn json_diagnostics() -> bool {
+    JSON_DIAGNOSTICS.get().copied().unwrap_or(false)
+}
+
+thread_local! {
+    /// Diagnostics of the work request being run, which go into its response
+    /// instead of to stderr
+    static CAPTURED: std::cell::RefCell<Option<String>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Print a diagnostic to stderr, or keep it for the worker's response
+fn print_diagnostic(text: &str) {
+    let kept = CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+        Some(output) => {
+            output.push_str(text);
+            output.push('\n');
+            true
+        }
+        None => false,
+    });
+    if !kept {
+        eprintln!("{}", text);
+    }
+}
+
+/// Set once from `--color`; read wherever human output is printed
+static COLOR: std::sync::OnceLock<hone::ColorChoice> = std::sync::OnceLock::new();
+
+/// Set once from `--lang`; applied wherever errors are printed
+static CATALOG: std::sync::OnceLock<hone::Catalog> = std::sync::OnceLock::new();
+
+/// Style for human output written to stdout
+fn stdout_style() -> hone::Style {
+    let choice = COLOR.get().copied().unwrap_or_default();
+    hone::Style::detect(choice, std::io::stdout().is_terminal())
 }
 
+/// Style for diagnostics and other human output written to stderr
+fn stderr_style() -> hone::Style {
+    let choice = COLOR.get().copied().unwrap_or_default();
+    hone::Style::detect(choice, std::io::stderr().is_terminal())
+}
+
+/// Render miette reports (errors and warnings) with `style`
+fn set_report_hook(style: hone::Style) {
+    miette::set_hook(Box::new(move |_| {
+        let mut options = miette::MietteHandlerOpts::new()
+            .terminal_links(true)
+            .unicode(true)
+            .context_lines(2)
+            .tab_width(4)
+            .color(style.color);
+        if let Some(width) = style.width {
+            options = options.width(width);
+        }
+        Box::new(options.build())
+    }))
+    .ok();
+}
+
+// Parsed once per run, so the size of the `Compile` variant doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Compile Hone source to JSON or YAML
@@ -51,6 +129,10 @@ enum Commands {
         #[arg(long)]
         strict: bool,
 
+        /// Treat warnings with this code as errors (format: warn:CODE, repeatable)
+        #[arg(long = "deny", value_parser = parse_deny)]
+        deny: Vec<String>,
+
         /// Suppress warnings
         #[arg(long)]
         quiet: bool,
@@ -59,7 +141,12 @@ enum Commands {
         #[arg(long)]
         output_dir: Option<PathBuf>,
 
-        /// Allow env() and file() builtins (non-deterministic)
+        /// File name template for --output-dir documents, e.g.
+        /// {document}-{variant.env}.{ext} (also {index} and {args.NAME})
+        #[arg(long, value_name = "TEMPLATE", requires = "output_dir")]
+        output_name: Option<String>,
+
+        /// Allow env(), file() and now() builtins (non-deterministic)
         #[arg(long)]
         allow_env: bool,
 
@@ -78,12 +165,135 @@ enum Commands {
         /// Skip all policy checks
         #[arg(long)]
         ignore_policy: bool,
+
+        /// Prompt for unset `expect args.*` values and variant selections (TTY only)
+        #[arg(short, long)]
+        interactive: bool,
+
+        /// Permission bits for written files, in octal (e.g. 0600)
+        #[arg(long, value_parser = parse_file_mode)]
+        mode: Option<u32>,
+
+        /// Fail instead of overwriting existing output files
+        #[arg(long)]
+        no_clobber: bool,
+
+        /// Add a "generated by hone, do not edit" header comment (YAML, TOML, .env)
+        #[arg(long)]
+        stamp: bool,
+
+        /// Don't run post_compile hooks from hone.toml
+        #[arg(long)]
+        no_hooks: bool,
+
+        /// Validate emitted Kubernetes documents against a release (e.g. k8s@1.29)
+        #[arg(long, value_name = "TARGET")]
+        validate: Option<String>,
+
+        /// With --validate: only use cached schemas, never download
+        #[arg(long, requires = "validate")]
+        offline: bool,
+
+        /// Output key order: source (as written and merged) or alpha (sorted)
+        #[arg(long, default_value = "source", value_parser = ["source", "alpha"])]
+        stable_order: String,
+
+        /// When two computed keys in one object give the same key: override
+        /// (the later one wins) or error
+        #[arg(long, default_value = "override", value_parser = ["override", "error"])]
+        key_collision: String,
+
+        /// Spaces per indentation level (json-pretty, yaml, hcl, xml)
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=8))]
+        indent: Option<u8>,
+
+        /// Double-quote every string value in YAML output
+        #[arg(long)]
+        quote_strings: bool,
+
+        /// Leave out keys whose value is null instead of writing null
+        #[arg(long)]
+        omit_nulls: bool,
+
+        /// In jsonc output, note above each key the file and line that set it
+        #[arg(long)]
+        key_origins: bool,
+
+        /// In jsonc output, end the last array element and object entry with a comma
+        #[arg(long)]
+        trailing_commas: bool,
+
+        /// Separator between nested keys in dotenv output (default: __)
+        #[arg(long, value_name = "SEP")]
+        env_separator: Option<String>,
+
+        /// How dotenv output writes arrays: join, indexed, or json
+        #[arg(long, value_name = "STYLE", value_parser = ["join", "indexed", "json"])]
+        env_arrays: Option<String>,
+
+        /// List the environment variables and files read by env()/file() (to stderr)
+        #[arg(long)]
+        explain_inputs: bool,
+
+        /// Print peak heap use and the largest values built by lets, `for`
+        /// loops and calls, with where they were built (to stderr)
+        #[arg(long)]
+        report_memory: bool,
+
+        /// Print how long each policy took to check, slowest first, and how
+        /// many of its calls came from the policy cache (to stderr)
+        #[arg(long)]
+        profile: bool,
+
+        /// Fail when a policy's condition takes longer than this (e.g. 500ms, 2s)
+        #[arg(long, value_name = "DURATION", value_parser = parse_policy_timeout)]
+        policy_timeout: Option<std::time::Duration>,
+
+        /// With dotenv output, list each variable with the Hone path and
+        /// expression that set it (to stderr)
+        #[arg(long)]
+        report_mapping: bool,
+
+        /// Write top-level `key: for ...` arrays element by element instead of
+        /// building them in memory (json and yaml only)
+        #[arg(long)]
+        stream: bool,
+
+        /// Compile twice with different hash seeds, and fail if the outputs
+        /// differ
+        #[arg(long)]
+        audit_determinism: bool,
+
+        /// Fold constants and prune `when` branches that are constant for
+        /// the selected variants before evaluating
+        #[arg(long)]
+        optimize: bool,
+
+        /// Write a JSON description of the build (input and output hashes,
+        /// args, variants, compiler version) to this file
+        #[arg(long, value_name = "PATH")]
+        emit_derivation: Option<PathBuf>,
+
+        /// Write <output>.map.json next to the output, mapping each output
+        /// key (and its line in yaml and json) to the source that set it
+        #[arg(long, requires = "output")]
+        source_map: bool,
     },
 
     /// Validate source without emitting output
     Check {
-        /// Source file to check
-        file: PathBuf,
+        /// Source file to check; with --all, the directory to search
+        #[arg(required_unless_present = "all")]
+        file: Option<PathBuf>,
+
+        /// Check every .hone file under the directory (default: the
+        /// hone.toml directory, or the current one), in dependency order
+        #[arg(long, conflicts_with = "schema")]
+        all: bool,
+
+        /// With --all: files to check at once (default: one per CPU)
+        #[arg(long, requires = "all")]
+        jobs: Option<usize>,
 
         /// Inject variable (required if file uses args.*)
         #[arg(long = "set", value_parser = parse_key_value)]
@@ -93,7 +303,15 @@ enum Commands {
         #[arg(long)]
         schema: Option<String>,
 
-        /// Allow env() and file() builtins (non-deterministic)
+        /// Treat warnings as errors
+        #[arg(long)]
+        strict: bool,
+
+        /// Treat warnings with this code as errors (format: warn:CODE, repeatable)
+        #[arg(long = "deny", value_parser = parse_deny)]
+        deny: Vec<String>,
+
+        /// Allow env(), file() and now() builtins (non-deterministic)
         #[arg(long)]
         allow_env: bool,
 
@@ -102,6 +320,73 @@ enum Commands {
         variants: Vec<(String, String)>,
     },
 
+    /// Compile named targets from hone.toml
+    Build {
+        /// Target names from [targets.<name>] in hone.toml
+        #[arg(required_unless_present = "all_targets")]
+        targets: Vec<String>,
+
+        /// Build every target in the manifest
+        #[arg(long, conflicts_with = "targets")]
+        all_targets: bool,
+
+        /// Print output to stdout, don't write files
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Treat warnings as errors
+        #[arg(long)]
+        strict: bool,
+
+        /// Suppress warnings
+        #[arg(long)]
+        quiet: bool,
+
+        /// Disable build cache
+        #[arg(long)]
+        no_cache: bool,
+
+        /// Don't run post_compile hooks from hone.toml
+        #[arg(long)]
+        no_hooks: bool,
+    },
+
+    /// Rebuild manifest targets whenever the project changes
+    Serve {
+        /// Target names from [targets.<name>] in hone.toml
+        #[arg(required_unless_present = "all_targets")]
+        targets: Vec<String>,
+
+        /// Serve every target in the manifest
+        #[arg(long, conflicts_with = "targets")]
+        all_targets: bool,
+
+        /// POST changed output as JSON to this http:// URL (repeatable)
+        #[arg(long = "webhook", value_name = "URL")]
+        webhooks: Vec<String>,
+
+        /// Send `@sensitive` values to webhooks in the output content
+        /// instead of masking them as ***
+        #[arg(long, requires = "webhooks")]
+        webhook_sensitive: bool,
+
+        /// Milliseconds between checks for changed files
+        #[arg(long, default_value = "500", value_name = "MS")]
+        interval: u64,
+
+        /// Build and notify once, then exit
+        #[arg(long)]
+        once: bool,
+
+        /// Suppress warnings and progress lines
+        #[arg(long)]
+        quiet: bool,
+
+        /// Don't run post_compile hooks from hone.toml
+        #[arg(long)]
+        no_hooks: bool,
+    },
+
     /// Format source files
     Fmt {
         /// Files to format
@@ -120,7 +405,21 @@ enum Commands {
         write: bool,
     },
 
-    /// Compare compilation outputs (different args or git refs)
+    /// Rewrite deprecated syntax for a newer edition
+    Upgrade {
+        /// Files or directories to upgrade (default: current directory)
+        paths: Vec<PathBuf>,
+
+        /// Edition to upgrade to
+        #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(hone::codemod::EDITIONS.iter().copied()))]
+        to: String,
+
+        /// Print the changes as a diff instead of writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Compare compilation outputs (different args or revisions)
     Diff {
         /// Source file
         file: PathBuf,
@@ -133,11 +432,12 @@ enum Commands {
         #[arg(long)]
         right: Option<String>,
 
-        /// Compare against a git ref (branch, tag, or commit)
+        /// Compare against a revision: git ref, jj revision, or dir:PATH for a directory snapshot
+        /// holding an earlier copy of the file's directory
         #[arg(long)]
         base: Option<String>,
 
-        /// Compare current file vs version at a git ref
+        /// Alias for --base
         #[arg(long)]
         since: Option<String>,
 
@@ -145,15 +445,39 @@ enum Commands {
         #[arg(long)]
         detect_moves: bool,
 
-        /// Annotate diffs with git blame information
+        /// Annotate diffs with the last change to the file (git or jj)
         #[arg(long)]
         blame: bool,
 
+        /// Match array elements by this object field instead of by position
+        #[arg(long, value_name = "FIELD")]
+        array_key: Option<String>,
+
         /// Output format: text (default), json
         #[arg(long, default_value = "text")]
         format: String,
     },
 
+    /// Summarize how every build target's output changed since a revision
+    ReleaseNotes {
+        /// Project directory (the one holding hone.toml, or below it)
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+
+        /// Revision to compare from: git ref, jj revision, or dir:PATH for a directory snapshot
+        /// holding an earlier copy of the project
+        #[arg(long, value_name = "REF")]
+        since: String,
+
+        /// Revision to compare to (default: the working tree)
+        #[arg(long, value_name = "REF")]
+        until: Option<String>,
+
+        /// Output format: text (default), markdown
+        #[arg(long, default_value = "text")]
+        format: String,
+    },
+
     /// Convert YAML/JSON to Hone source
     Import {
         /// YAML or JSON file to convert
@@ -172,6 +496,28 @@ enum Commands {
         split_docs: bool,
     },
 
+    /// Evaluate a file with fixed inputs and write the result as plain Hone
+    Freeze {
+        /// Source file to freeze
+        file: PathBuf,
+
+        /// Output Hone file
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Inject variable (required if file uses args.*)
+        #[arg(long = "set", value_parser = parse_key_value)]
+        set: Vec<(String, String)>,
+
+        /// Allow env(), file() and now() builtins (non-deterministic)
+        #[arg(long)]
+        allow_env: bool,
+
+        /// Select variant case (can be used multiple times, format: name=case)
+        #[arg(long = "variant", value_parser = parse_key_value)]
+        variants: Vec<(String, String)>,
+    },
+
     /// Start Language Server Protocol server
     Lsp {
         /// Use stdio transport (default)
@@ -183,6 +529,13 @@ enum Commands {
         socket: Option<u16>,
     },
 
+    /// Start a Debug Adapter Protocol server for stepping through evaluation
+    Dap {
+        /// Use stdio transport (default)
+        #[arg(long)]
+        stdio: bool,
+    },
+
     /// Internal: Lex a file and print tokens (for debugging)
     #[command(hide = true)]
     Lex {
@@ -218,20 +571,143 @@ enum Commands {
         output: Option<PathBuf>,
     },
 
+    /// Report complexity metrics per file
+    Stats {
+        /// Files or directories to analyze (default: current directory)
+        paths: Vec<PathBuf>,
+
+        /// Output format: text (default), json
+        #[arg(short, long, default_value = "text")]
+        format: String,
+    },
+
+    /// Show the variables in scope at a source position
+    Debug {
+        /// Source file to evaluate
+        file: PathBuf,
+
+        /// Position to inspect (format: line:col, or just line)
+        #[arg(long, value_parser = parse_line_col)]
+        at: (usize, usize),
+
+        /// Inject variable (required if file uses args.*)
+        #[arg(long = "set", value_parser = parse_key_value)]
+        set: Vec<(String, String)>,
+
+        /// Allow env(), file() and now() builtins (non-deterministic)
+        #[arg(long)]
+        allow_env: bool,
+
+        /// Select variant case (can be used multiple times, format: name=case)
+        #[arg(long = "variant", value_parser = parse_key_value)]
+        variants: Vec<(String, String)>,
+    },
+
+    /// List output keys written more than once, and which write wins
+    Conflicts {
+        /// Source file to compile
+        file: PathBuf,
+
+        /// Output format: text (default), json
+        #[arg(short, long, default_value = "text", value_parser = ["text", "json"])]
+        format: String,
+
+        /// Inject variable (required if file uses args.*)
+        #[arg(long = "set", value_parser = parse_key_value)]
+        set: Vec<(String, String)>,
+
+        /// Allow env(), file() and now() builtins (non-deterministic)
+        #[arg(long)]
+        allow_env: bool,
+
+        /// Select variant case (can be used multiple times, format: name=case)
+        #[arg(long = "variant", value_parser = parse_key_value)]
+        variants: Vec<(String, String)>,
+    },
+
+    /// Report files, lets, functions and schemas no entry point uses
+    Unused {
+        /// Workspace directory (default: the hone.toml root, or the current directory)
+        dir: Option<PathBuf>,
+
+        /// Entry point to search from (can be used multiple times; default:
+        /// hone.toml targets, or files with output nothing else imports)
+        #[arg(long = "entry")]
+        entries: Vec<PathBuf>,
+
+        /// Output format: text (default), json
+        #[arg(short, long, default_value = "text", value_parser = ["text", "json"])]
+        format: String,
+    },
+
+    /// Check the cache, git, editor setup, hone.toml and workspace imports,
+    /// and print version details for bug reports
+    Doctor {
+        /// Output format: text (default), json
+        #[arg(short, long, default_value = "text", value_parser = ["text", "json"])]
+        format: String,
+    },
+
     /// Manage the build cache
     Cache {
         #[command(subcommand)]
         action: CacheAction,
     },
 
-    /// Generate Hone schema definitions from JSON Schema
+    /// Create and manage environment overlays
+    Overlay {
+        #[command(subcommand)]
+        action: OverlayAction,
+    },
+
+    /// Generate Hone schema definitions from JSON Schema, OpenAPI, CRDs, or protobuf
     Typegen {
         /// JSON Schema file to convert
-        file: PathBuf,
+        #[arg(
+            required_unless_present_any = ["openapi", "crd", "proto"],
+            conflicts_with_all = ["openapi", "crd", "proto"]
+        )]
+        file: Option<PathBuf>,
+
+        /// OpenAPI document (JSON or YAML) to extract schemas from
+        #[arg(long, conflicts_with_all = ["crd", "proto"])]
+        openapi: Option<PathBuf>,
+
+        /// JSON pointer to a single OpenAPI schema (e.g. '#/components/schemas/Config')
+        #[arg(long)]
+        path: Option<String>,
+
+        /// Kubernetes CustomResourceDefinition file (JSON or YAML)
+        #[arg(long, conflicts_with = "proto")]
+        crd: Option<PathBuf>,
+
+        /// Compiled protobuf descriptor set (protoc --descriptor_set_out)
+        #[arg(long)]
+        proto: Option<PathBuf>,
+
+        /// Fully-qualified protobuf message to generate (can be used multiple times)
+        #[arg(long = "message")]
+        messages: Vec<String>,
 
         /// Output file (default: stdout)
         #[arg(short, long)]
         output: Option<PathBuf>,
+
+        /// Check that the output file is up to date instead of writing it (fail if not)
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Replace this binary with the newest signed release
+    #[cfg(feature = "self-update")]
+    SelfUpdate {
+        /// Only report whether a newer release is available
+        #[arg(long)]
+        check: bool,
+
+        /// Release channel: stable, or prerelease to include release candidates
+        #[arg(long, default_value = "stable", value_parser = ["stable", "prerelease"])]
+        channel: String,
     },
 
     /// Internal: Evaluate inline source (for debugging/testing)
@@ -240,9 +716,13 @@ enum Commands {
         /// Hone source code to evaluate
         source: String,
 
-        /// Output format: json, yaml
-        #[arg(short, long, default_value = "json")]
+        /// Output format: pretty, or any compile format (json, yaml, ...)
+        #[arg(short, long, default_value = "pretty")]
         format: String,
+
+        /// Show at most N items per array in pretty output (0 shows all)
+        #[arg(long, default_value_t = 10)]
+        max_items: usize,
     },
 }
 
@@ -256,6 +736,79 @@ enum CacheAction {
     },
 }
 
+#[derive(Subcommand)]
+enum OverlayAction {
+    /// Create a new environment overlay that inherits from an existing file
+    New {
+        /// Name of the new environment (e.g. staging)
+        name: String,
+
+        /// Existing file the overlay inherits from
+        #[arg(long)]
+        from: PathBuf,
+
+        /// Keys to copy into the overlay, comma-separated (dotted paths allowed)
+        #[arg(long, value_delimiter = ',')]
+        keys: Option<Vec<String>>,
+
+        /// Add the environment as a new case of this variant
+        #[arg(long)]
+        variant: Option<String>,
+
+        /// Output file (default: <NAME>.hone next to the --from file)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Parse an octal file mode like 0600
+fn parse_file_mode(s: &str) -> Result<u32, String> {
+    hone::emitter::parse_mode(s)
+        .ok_or_else(|| format!("invalid file mode '{}': expected octal like 0600", s))
+}
+
+/// Parse a `--policy-timeout` duration like 500ms or 2s
+fn parse_policy_timeout(s: &str) -> Result<std::time::Duration, String> {
+    match hone::lexer::duration::parse(s) {
+        Some(ms) if ms > 0 => Ok(std::time::Duration::from_millis(ms as u64)),
+        _ => Err(format!(
+            "invalid timeout '{}': expected a duration above zero like 500ms or 2s",
+            s
+        )),
+    }
+}
+
+/// Parse a line:col position (column defaults to 1)
+fn parse_line_col(s: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("invalid position '{}': expected line:col", s);
+    let (line, col) = s.split_once(':').unwrap_or((s, "1"));
+    let line: usize = line.parse().map_err(|_| invalid())?;
+    let col: usize = col.parse().map_err(|_| invalid())?;
+    if line == 0 || col == 0 {
+        return Err(invalid());
+    }
+    Ok((line, col))
+}
+
+/// Parse a `--deny warn:CODE` value into the warning code
+fn parse_deny(s: &str) -> Result<String, String> {
+    match s.strip_prefix("warn:") {
+        Some(code) if !code.is_empty() => Ok(code.to_string()),
+        _ => Err(format!("invalid --deny '{}': expected warn:CODE", s)),
+    }
+}
+
+/// Resolve a `--format` name, including registered custom formats
+fn parse_output_format(s: &str) -> hone::HoneResult<hone::OutputFormat> {
+    hone::OutputFormat::parse(s).ok_or_else(|| {
+        hone::HoneError::io_error(format!(
+            "unknown output format '{}'. Use: {}",
+            s,
+            hone::OutputFormat::names().join(", ")
+        ))
+    })
+}
+
 /// Parse a key=value pair
 fn parse_key_value(s: &str) -> Result<(String, String), String> {
     let pos = s
@@ -265,22 +818,47 @@ fn parse_key_value(s: &str) -> Result<(String, String), String> {
 }
 
 fn main() -> ExitCode {
-    // Set up miette for nice error output
-    miette::set_hook(Box::new(|_| {
-        Box::new(
-            miette::MietteHandlerOpts::new()
-                .terminal_links(true)
-                .unicode(true)
-                .context_lines(2)
-                .tab_width(4)
-                .build(),
-        )
-    }))
-    .ok();
+    // Bazel and Buck2 start workers with the flag after the startup arguments
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--persistent_worker") {
+        set_report_hook(stderr_style());
+        return cmd_worker(
+            args.into_iter()
+                .filter(|a| a != "--persistent_worker")
+                .collect(),
+        );
+    }
 
     let cli = Cli::parse();
+    JSON_DIAGNOSTICS.set(cli.error_format == "json").ok();
+    COLOR
+        .set(hone::ColorChoice::parse(&cli.color).unwrap_or_default())
+        .ok();
+    set_report_hook(stderr_style());
+    match hone::Catalog::load(&cli.lang) {
+        Ok(catalog) => {
+            CATALOG.set(catalog).ok();
+        }
+        Err(e) => {
+            let exit_code = exit_code(&e);
+            report_error(e);
+            return ExitCode::from(exit_code);
+        }
+    }
+
+    match run(cli.command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let exit_code = exit_code(&e);
+            report_error(e);
+            ExitCode::from(exit_code)
+        }
+    }
+}
 
-    let result = match cli.command {
+/// Run a parsed command
+fn run(command: Commands) -> hone::HoneResult<()> {
+    match command {
         Commands::Compile {
             file,
             output,
@@ -290,13 +868,41 @@ fn main() -> ExitCode {
             set_string,
             dry_run,
             strict,
+            deny,
             quiet,
             output_dir,
+            output_name,
             allow_env,
             variants,
             no_cache,
             secrets_mode,
             ignore_policy,
+            interactive,
+            mode,
+            no_clobber,
+            stamp,
+            no_hooks,
+            validate,
+            offline,
+            stable_order,
+            key_collision,
+            indent,
+            quote_strings,
+            omit_nulls,
+            key_origins,
+            trailing_commas,
+            env_separator,
+            env_arrays,
+            explain_inputs,
+            report_memory,
+            profile,
+            policy_timeout,
+            report_mapping,
+            stream,
+            audit_determinism,
+            optimize,
+            emit_derivation,
+            source_map,
         } => cmd_compile(
             file,
             output,
@@ -306,27 +912,121 @@ fn main() -> ExitCode {
             set_string,
             dry_run,
             strict,
+            deny,
             quiet,
             output_dir,
+            output_name,
             allow_env,
             variants,
             no_cache,
             secrets_mode,
             ignore_policy,
+            interactive,
+            hone::WriteOptions::new()
+                .with_mode(mode)
+                .with_no_clobber(no_clobber),
+            stamp,
+            no_hooks,
+            validate,
+            offline,
+            stable_order == "alpha",
+            hone::KeyCollision::parse(&key_collision).unwrap_or_default(),
+            hone::EmitOptions::new()
+                .with_indent(indent.map(usize::from))
+                .with_quote_strings(quote_strings)
+                .with_omit_nulls(omit_nulls)
+                .with_key_origins(key_origins)
+                .with_trailing_commas(trailing_commas)
+                .with_env_separator(env_separator)
+                .with_env_arrays(
+                    env_arrays
+                        .as_deref()
+                        .and_then(hone::DotenvArrays::parse)
+                        .unwrap_or_default(),
+                ),
+            explain_inputs,
+            report_memory,
+            profile,
+            policy_timeout,
+            report_mapping,
+            stream,
+            audit_determinism,
+            optimize,
+            emit_derivation,
+            source_map,
         ),
         Commands::Check {
             file,
+            all: true,
+            jobs,
+            set,
+            strict,
+            deny,
+            allow_env,
+            variants,
+            ..
+        } => cmd_check_all(file, jobs, set, strict, deny, allow_env, variants),
+        Commands::Check {
+            file,
+            set,
+            schema,
+            strict,
+            deny,
+            allow_env,
+            variants,
+            ..
+        } => cmd_check(
+            file.unwrap_or_default(),
             set,
             schema,
+            strict,
+            deny,
             allow_env,
             variants,
-        } => cmd_check(file, set, schema, allow_env, variants),
+        ),
+        Commands::Build {
+            targets,
+            all_targets,
+            dry_run,
+            strict,
+            quiet,
+            no_cache,
+            no_hooks,
+        } => cmd_build(
+            targets,
+            all_targets,
+            dry_run,
+            strict,
+            quiet,
+            no_cache,
+            no_hooks,
+        ),
+        Commands::Serve {
+            targets,
+            all_targets,
+            webhooks,
+            webhook_sensitive,
+            interval,
+            once,
+            quiet,
+            no_hooks,
+        } => cmd_serve(
+            targets,
+            all_targets,
+            webhooks,
+            webhook_sensitive,
+            interval,
+            once,
+            quiet,
+            no_hooks,
+        ),
         Commands::Fmt {
             files,
             check,
             diff,
             write,
         } => cmd_fmt(files, check, diff, write),
+        Commands::Upgrade { paths, to, dry_run } => cmd_upgrade(paths, to, dry_run),
         Commands::Diff {
             file,
             left,
@@ -335,39 +1035,210 @@ fn main() -> ExitCode {
             since,
             detect_moves,
             blame,
+            array_key,
+            format,
+        } => cmd_diff(
+            file,
+            left,
+            right,
+            base,
+            since,
+            detect_moves,
+            blame,
+            array_key,
+            format,
+        ),
+        Commands::ReleaseNotes {
+            dir,
+            since,
+            until,
             format,
-        } => cmd_diff(file, left, right, base, since, detect_moves, blame, format),
+        } => cmd_release_notes(dir, since, until, format),
         Commands::Import {
             file,
             output,
             extract_vars,
             split_docs,
         } => cmd_import(file, output, extract_vars, split_docs),
+        Commands::Freeze {
+            file,
+            output,
+            set,
+            allow_env,
+            variants,
+        } => cmd_freeze(file, output, set, allow_env, variants),
         Commands::Graph {
             file,
             format,
             output,
         } => cmd_graph(file, format, output),
+        Commands::Stats { paths, format } => cmd_stats(paths, format),
+        Commands::Debug {
+            file,
+            at,
+            set,
+            allow_env,
+            variants,
+        } => cmd_debug(file, at, set, allow_env, variants),
+        Commands::Conflicts {
+            file,
+            format,
+            set,
+            allow_env,
+            variants,
+        } => cmd_conflicts(file, format, set, allow_env, variants),
+        Commands::Unused {
+            dir,
+            entries,
+            format,
+        } => cmd_unused(dir, entries, format),
+        Commands::Doctor { format } => cmd_doctor(format),
         Commands::Cache { action } => cmd_cache(action),
+        Commands::Overlay { action } => cmd_overlay(action),
         Commands::Lsp { stdio, socket } => cmd_lsp(stdio, socket),
+        Commands::Dap { stdio: _ } => hone::dap::run_stdio(),
         Commands::Lex { file } => cmd_lex(file),
         Commands::Parse { file } => cmd_parse(file),
         Commands::Resolve { file } => cmd_resolve(file),
-        Commands::Typegen { file, output } => cmd_typegen(file, output),
-        Commands::Eval { source, format } => cmd_eval(source, format),
+        Commands::Typegen {
+            file,
+            openapi,
+            path,
+            crd,
+            proto,
+            messages,
+            output,
+            check,
+        } => cmd_typegen(file, openapi, path, crd, proto, messages, output, check),
+        #[cfg(feature = "self-update")]
+        Commands::SelfUpdate { check, channel } => cmd_self_update(check, channel),
+        Commands::Eval {
+            source,
+            format,
+            max_items,
+        } => cmd_eval(source, format, max_items),
+    }
+}
+
+/// Process exit code for an error
+fn exit_code(e: &hone::HoneError) -> u8 {
+    match e {
+        // I/O errors
+        hone::HoneError::IoError { .. } => 3,
+        // All compilation errors
+        _ => 1,
+    }
+}
+
+/// Print an error in the `--error-format` style
+fn report_error(e: hone::HoneError) {
+    eprintln!("{}", format_error(e, json_diagnostics()));
+}
+
+fn format_error(e: hone::HoneError, json: bool) -> String {
+    let e = CATALOG.get_or_init(hone::Catalog::default).localize(e);
+    if json {
+        hone::errors::diagnostic_json(&e).to_string()
+    } else {
+        format!("{:?}", miette::Report::new(e))
+    }
+}
+
+/// `--persistent_worker`: answer build tool work requests on stdin until it
+/// closes. Each request's arguments follow the startup arguments, so a rule
+/// can start the worker as `hone compile --persistent_worker`.
+fn cmd_worker(startup: Vec<String>) -> ExitCode {
+    // Sandboxed inputs are symlinks; imports must resolve beside them
+    hone::resolver::set_preserve_symlinks(true);
+    let cwd = match std::env::current_dir() {
+        Ok(cwd) => cwd,
+        Err(e) => {
+            report_error(hone::HoneError::io_error(format!(
+                "failed to get cwd: {}",
+                e
+            )));
+            return ExitCode::from(3);
+        }
     };
 
+    let result = hone::worker::run(std::io::stdin().lock(), std::io::stdout(), |request| {
+        work(&startup, &cwd, request)
+    });
     match result {
         Ok(()) => ExitCode::SUCCESS,
         Err(e) => {
-            let exit_code = match &e {
-                // I/O errors
-                hone::HoneError::IoError { .. } => ExitCode::from(3),
-                // All compilation errors
-                _ => ExitCode::from(1),
-            };
-            eprintln!("{:?}", miette::Report::new(e));
-            exit_code
+            report_error(e);
+            ExitCode::from(3)
+        }
+    }
+}
+
+/// Run one work request in-process. Stdout carries the protocol, so only
+/// commands that write their results to files are accepted.
+fn work(
+    startup: &[String],
+    cwd: &std::path::Path,
+    request: &hone::worker::WorkRequest,
+) -> hone::worker::WorkResponse {
+    use hone::worker::WorkResponse;
+
+    let dir = request.working_dir(cwd);
+    let arguments = std::env::set_current_dir(&dir)
+        .map_err(|e| hone::HoneError::io_error(format!("failed to enter {}: {}", dir.display(), e)))
+        .and_then(|()| hone::worker::expand_arguments(&request.arguments, &dir));
+    let arguments = match arguments {
+        Ok(arguments) => arguments,
+        Err(e) => return WorkResponse::new(exit_code(&e).into(), format_error(e, false)),
+    };
+
+    let args = std::iter::once("hone")
+        .chain(startup.iter().map(String::as_str))
+        .chain(arguments.iter().map(String::as_str));
+    let mut cli = match Cli::try_parse_from(args) {
+        Ok(cli) => cli,
+        Err(e) => return WorkResponse::new(e.exit_code(), e.render().to_string()),
+    };
+
+    let reads_stdin =
+        |file: &PathBuf| file.to_str() == Some("-") || file.to_str() == Some("/dev/stdin");
+    let refused = match &cli.command {
+        Commands::Compile { file, .. }
+        | Commands::Check {
+            file: Some(file), ..
+        } if reads_stdin(file) => Some("the worker cannot read source from stdin"),
+        Commands::Compile {
+            output,
+            output_dir,
+            dry_run,
+            ..
+        } if *dry_run || (output.is_none() && output_dir.is_none()) => {
+            Some("the worker needs -o or --output-dir, since stdout carries its responses")
+        }
+        Commands::Compile { .. } | Commands::Check { .. } => None,
+        _ => Some("the worker runs compile and check only"),
+    };
+    if let Some(reason) = refused {
+        return WorkResponse::new(2, format!("error: {}\n", reason));
+    }
+
+    // Hooks would print into the protocol stream, and the build tool
+    // should decide what runs after a compile anyway
+    if let Commands::Compile { no_hooks, .. } = &mut cli.command {
+        *no_hooks = true;
+    }
+
+    let json = cli.error_format == "json";
+    CAPTURED.with(|captured| *captured.borrow_mut() = Some(String::new()));
+    let result = run(cli.command);
+    let mut output = CAPTURED
+        .with(|captured| captured.borrow_mut().take())
+        .unwrap_or_default();
+    match result {
+        Ok(()) => WorkResponse::new(0, output),
+        Err(e) => {
+            let code = exit_code(&e);
+            output.push_str(&format_error(e, json));
+            WorkResponse::new(code.into(), output)
         }
     }
 }
@@ -382,30 +1253,66 @@ fn cmd_compile(
     set_string: Vec<(String, String)>,
     dry_run: bool,
     strict: bool,
+    deny: Vec<String>,
     quiet: bool,
     output_dir: Option<PathBuf>,
+    output_name: Option<String>,
     allow_env: bool,
     variants: Vec<(String, String)>,
     no_cache: bool,
     secrets_mode: String,
     ignore_policy: bool,
+    interactive: bool,
+    write_options: hone::WriteOptions,
+    stamp: bool,
+    no_hooks: bool,
+    validate: Option<String>,
+    offline: bool,
+    alpha_order: bool,
+    key_collision: hone::KeyCollision,
+    emit_options: hone::EmitOptions,
+    explain_inputs: bool,
+    report_memory: bool,
+    profile: bool,
+    policy_timeout: Option<std::time::Duration>,
+    report_mapping: bool,
+    stream: bool,
+    audit_determinism: bool,
+    optimize: bool,
+    emit_derivation: Option<PathBuf>,
+    source_map: bool,
 ) -> hone::HoneResult<()> {
+    let validator = validate
+        .map(|target| {
+            hone::k8s::K8sVersion::parse_target(&target)
+                .map(|version| hone::k8s::K8sValidator::new(version, !offline))
+        })
+        .transpose()?;
+    let (mut set, mut set_string, mut variants) = (set, set_string, variants);
+    let is_stdin = file.to_str() == Some("-") || file.to_str() == Some("/dev/stdin");
+
+    // Ask for anything left unset before the cache key is computed
+    if interactive {
+        if is_stdin {
+            return Err(hone::HoneError::io_error(
+                "--interactive cannot be used when reading source from stdin".to_string(),
+            ));
+        }
+        if std::io::stdin().is_terminal() {
+            prompt_compile_inputs(&file, &mut set, &set_file, &mut set_string, &mut variants)?;
+        } else if !quiet {
+            eprintln!("warning: --interactive ignored: stdin is not a terminal");
+        }
+    }
+
     // Determine output format
     let output_format = if let Some(ref fmt) = format {
-        hone::OutputFormat::parse(fmt).ok_or_else(|| {
-            hone::HoneError::io_error(format!(
-                "unknown output format '{}'. Use: json, yaml, toml, dotenv",
-                fmt
-            ))
-        })?
+        parse_output_format(fmt)?
     } else if let Some(ref out) = output {
-        match out.extension().and_then(|e| e.to_str()) {
-            Some("yaml") | Some("yml") => hone::OutputFormat::Yaml,
-            Some("json") => hone::OutputFormat::JsonPretty,
-            Some("toml") => hone::OutputFormat::Toml,
-            Some("env") => hone::OutputFormat::Dotenv,
-            _ => hone::OutputFormat::JsonPretty,
-        }
+        out.extension()
+            .and_then(|e| e.to_str())
+            .and_then(hone::OutputFormat::from_extension)
+            .unwrap_or(hone::OutputFormat::JsonPretty)
     } else if output_dir.is_some() {
         // Default to YAML for multi-file output (common for K8s)
         hone::OutputFormat::Yaml
@@ -413,15 +1320,96 @@ fn cmd_compile(
         hone::OutputFormat::JsonPretty
     };
 
-    // If output_dir is specified, do multi-file output (no caching for multi-file)
-    if let Some(ref dir) = output_dir {
+    if stream {
+        let conflict = if is_stdin {
+            Some("reading source from stdin")
+        } else if output_dir.is_some() {
+            Some("--output-dir")
+        } else if validator.is_some() {
+            Some("--validate")
+        } else if alpha_order {
+            Some("--stable-order alpha")
+        } else if emit_options != hone::EmitOptions::default() {
+            Some("output style options")
+        } else if emit_derivation.is_some() {
+            Some("--emit-derivation")
+        } else if source_map {
+            Some("--source-map")
+        } else if secrets_mode != "placeholder" {
+            Some("--secrets-mode")
+        } else {
+            None
+        };
+        if let Some(conflict) = conflict {
+            return Err(hone::HoneError::io_error(format!(
+                "--stream cannot be combined with {}",
+                conflict
+            )));
+        }
+    }
+
+    if emit_derivation.is_some() && is_stdin {
+        return Err(hone::HoneError::io_error(
+            "--emit-derivation cannot be used when reading source from stdin".to_string(),
+        ));
+    }
+
+    if report_mapping {
+        if output_format != hone::OutputFormat::Dotenv {
+            return Err(hone::HoneError::io_error(
+                "--report-mapping lists dotenv variables; use it with --format dotenv".to_string(),
+            ));
+        }
+        if output_dir.is_some() {
+            return Err(hone::HoneError::io_error(
+                "--report-mapping cannot be used with --output-dir".to_string(),
+            ));
+        }
+    }
+
+    if source_map && (output_dir.is_some() || output_format == hone::OutputFormat::Ndjson) {
+        return Err(hone::HoneError::io_error(
+            "--source-map maps a single output file; it cannot be used with --output-dir or ndjson"
+                .to_string(),
+        ));
+    }
+
+    if audit_determinism {
+        if is_stdin {
+            return Err(hone::HoneError::io_error(
+                "--audit-determinism cannot be used when reading source from stdin".to_string(),
+            ));
+        }
+        audit_compile_determinism(
+            &file,
+            output_format,
+            &set,
+            &set_file,
+            &set_string,
+            allow_env,
+            &variants,
+            ignore_policy,
+            &secrets_mode,
+            alpha_order,
+        )?;
+        if !quiet {
+            eprintln!("determinism audit passed: two compilations produced identical output");
+        }
+    }
+
+    // If output_dir is specified, do multi-file output (no caching for multi-file);
+    // NDJSON puts every document of the file on its own line of a single output
+    if output_dir.is_some() || (output_format == hone::OutputFormat::Ndjson && !is_stdin) {
         return cmd_compile_multi(
             &file,
-            dir,
+            output_dir.as_deref(),
+            output_name.as_deref(),
+            output.as_deref(),
             output_format,
             dry_run,
             quiet,
             strict,
+            &deny,
             &set,
             &set_file,
             &set_string,
@@ -429,18 +1417,27 @@ fn cmd_compile(
             &variants,
             &secrets_mode,
             ignore_policy,
+            &write_options,
+            stamp,
+            no_hooks,
+            validator.as_ref(),
+            alpha_order,
+            key_collision,
+            &emit_options,
+            explain_inputs,
+            report_memory,
+            profile,
+            policy_timeout,
+            emit_derivation.as_deref(),
         );
     }
 
-    // Check for stdin
-    let is_stdin = file.to_str() == Some("-") || file.to_str() == Some("/dev/stdin");
-
     // Set up base_dir early (needed for import resolution during cache hashing)
     let base_dir = if is_stdin {
         std::env::current_dir()
             .map_err(|e| hone::HoneError::io_error(format!("failed to get cwd: {}", e)))?
     } else {
-        let canonical = file.canonicalize().map_err(|e| {
+        let canonical = hone::resolver::canonicalize(&file).map_err(|e| {
             hone::HoneError::io_error(format!("failed to resolve path {}: {}", file.display(), e))
         })?;
         canonical
@@ -449,27 +1446,34 @@ fn cmd_compile(
             .to_path_buf()
     };
 
-    // Try cache for non-stdin, non-env builds
-    let use_cache = !no_cache && !is_stdin && !allow_env;
+    // Try cache for non-stdin, non-env builds (validation needs the values;
+    // a cached build may hide key collisions)
+    let use_cache = !no_cache
+        && !is_stdin
+        && !allow_env
+        && key_collision == hone::KeyCollision::Override
+        && validator.is_none()
+        && !explain_inputs
+        && !report_memory
+        && !profile
+        && !report_mapping
+        && !stream
+        && emit_derivation.is_none()
+        && !source_map;
     let cache = if use_cache {
         hone::cache::BuildCache::new()
     } else {
         None
     };
 
-    // Compute cache key if caching is enabled
-    let format_str = match output_format {
-        hone::OutputFormat::Json => "json",
-        hone::OutputFormat::JsonPretty => "json-pretty",
-        hone::OutputFormat::Yaml => "yaml",
-        hone::OutputFormat::Toml => "toml",
-        hone::OutputFormat::Dotenv => "dotenv",
-    };
+    // Compute cache key if caching is enabled (sorted or restyled output is
+    // cached apart)
+    let format_str = output_style_key(output_format, alpha_order, &emit_options);
 
     // Collect source hashes from ALL files in the import closure (not just root)
     let source_hashes: Vec<String> = if use_cache && !is_stdin {
         let mut resolver = hone::ImportResolver::new(&base_dir);
-        let canonical = file.canonicalize().map_err(|e| {
+        let canonical = hone::resolver::canonicalize(&file).map_err(|e| {
             hone::HoneError::io_error(format!("failed to resolve path {}: {}", file.display(), e))
         })?;
         match resolver.resolve(&canonical) {
@@ -523,7 +1527,7 @@ fn cmd_compile(
                 &source_hashes,
                 &variant_map,
                 args_hash.as_deref(),
-                format_str,
+                &format_str,
                 env!("CARGO_PKG_VERSION"),
             ))
         } else {
@@ -536,17 +1540,21 @@ fn cmd_compile(
     // Check cache
     if let (Some(ref cache), Some(ref key)) = (&cache, &cache_key) {
         if let Some(cached) = cache.get(key) {
+            let result = apply_stamp(cached.output, stamp, output_format, &file);
             if dry_run || output.is_none() {
-                println!("{}", cached.output);
+                println!("{}", result);
             } else if let Some(out_path) = output.as_ref() {
-                std::fs::write(out_path, &cached.output).map_err(|e| {
-                    hone::HoneError::io_error(format!(
-                        "failed to write {}: {}",
-                        out_path.display(),
-                        e
-                    ))
-                })?;
+                hone::write_output(out_path, &result, &write_options)?;
                 eprintln!("Wrote {}", out_path.display());
+                if !no_hooks {
+                    run_post_compile_hooks(
+                        &file,
+                        &base_dir,
+                        std::slice::from_ref(out_path),
+                        None,
+                        output_format,
+                    )?;
+                }
             }
             return Ok(());
         }
@@ -555,8 +1563,17 @@ fn cmd_compile(
     let mut compiler = hone::Compiler::new(&base_dir);
     compiler.set_allow_env(allow_env);
     compiler.set_ignore_policies(ignore_policy);
+    compiler.set_optimize(optimize);
+    compiler.set_output_format(output_format);
+    compiler.set_key_collision(key_collision);
+    compiler.set_policy_timeout(policy_timeout);
+    if report_memory {
+        compiler.enable_value_sizes();
+        heap::start();
+    }
     if !variants.is_empty() {
-        let variant_map: std::collections::HashMap<String, String> = variants.into_iter().collect();
+        let variant_map: std::collections::HashMap<String, String> =
+            variants.iter().cloned().collect();
         compiler.set_variants(variant_map);
     }
     if has_args(&set, &set_file, &set_string) {
@@ -564,37 +1581,52 @@ fn cmd_compile(
         compiler.set_args(args);
     }
 
+    if stream {
+        return cmd_compile_stream(
+            &mut compiler,
+            &file,
+            &base_dir,
+            output,
+            output_format,
+            dry_run,
+            strict,
+            &deny,
+            quiet,
+            &write_options,
+            stamp,
+            no_hooks,
+            explain_inputs,
+            report_memory,
+            profile,
+        );
+    }
+
+    let mut stdin_source = None;
     let value = if is_stdin {
         use std::io::Read;
         let mut source = String::new();
         std::io::stdin()
             .read_to_string(&mut source)
             .map_err(|e| hone::HoneError::io_error(format!("failed to read stdin: {}", e)))?;
-        compiler.compile_source(&source)?
+        let value = compiler.compile_source(&source)?;
+        stdin_source = Some(source);
+        value
     } else {
-        let canonical = file.canonicalize().map_err(|e| {
+        let canonical = hone::resolver::canonicalize(&file).map_err(|e| {
             hone::HoneError::io_error(format!("failed to resolve path {}: {}", file.display(), e))
         })?;
         compiler.compile(&canonical)?
     };
 
-    // Handle warnings
-    let warnings = compiler.warnings();
-    if !warnings.is_empty() {
-        if strict {
-            for w in warnings {
-                eprintln!("warning{}: {}", format_warning_location(w), w.message);
-            }
-            return Err(hone::HoneError::compilation_error(format!(
-                "{} warning(s) treated as errors (--strict)",
-                warnings.len()
-            )));
-        }
-        if !quiet {
-            for w in warnings {
-                eprintln!("warning{}: {}", format_warning_location(w), w.message);
-            }
-        }
+    report_warnings(compiler.warnings(), strict, &deny, quiet)?;
+    if explain_inputs {
+        print_external_inputs(compiler.external_inputs(), compiler.redactor());
+    }
+    if report_memory {
+        print_memory_report(&compiler);
+    }
+    if profile {
+        print_policy_profile(&compiler);
     }
 
     // Handle secrets mode
@@ -627,74 +1659,872 @@ fn cmd_compile(
         }
     };
 
-    let result = hone::emit(&value, output_format)?;
+    if let Some(ref validator) = validator {
+        validate_k8s(validator, &[(None, value.clone())], quiet)?;
+    }
+
+    let value = if alpha_order {
+        value.sorted_keys()
+    } else {
+        value
+    };
+
+    let emit_options = emit_options.with_doc_comments(compiler.doc_comments().clone());
+    let emit_options = if emit_options.key_origins {
+        let origins = key_origins(compiler.key_locations(), &base_dir);
+        emit_options.with_origins(origins)
+    } else {
+        emit_options
+    };
+    let result = hone::emit_with(&value, output_format, &emit_options)?;
+    if report_mapping {
+        let vars = emit_options.dotenv_emitter().variables(&value)?;
+        print_env_mapping(&vars, &compiler, stdin_source.as_deref());
+    }
 
     // Store in cache
     if let (Some(ref cache), Some(ref key)) = (&cache, &cache_key) {
-        let cached = hone::cache::CachedResult::new(result.clone(), format_str, file.to_str());
+        let cached = hone::cache::CachedResult::new(result.clone(), &format_str, file.to_str());
         // Ignore cache write failures
         let _ = cache.put(key, &cached);
     }
 
+    let mut map = source_map.then(|| {
+        hone::compiler::sourcemap::SourceMap::new(
+            &value,
+            output_format,
+            &emit_options,
+            &result,
+            compiler.key_locations(),
+        )
+    });
+
+    let unstamped_lines = result.lines().count();
+    let result = apply_stamp(result, stamp, output_format, &file);
+    if let Some(map) = map.as_mut() {
+        map.offset_lines(result.lines().count() - unstamped_lines);
+    }
+    let derivation = match emit_derivation {
+        Some(ref path) => {
+            let mut derivation =
+                hone::compiler::derivation::Derivation::new(&compiler, &file, format_str)
+                    .with_args(hone::build_args_object(&set, &set_file, &set_string)?)
+                    .with_variants(&variants);
+            derivation.add_output(output.as_deref().filter(|_| !dry_run), &result);
+            Some((path, derivation))
+        }
+        None => None,
+    };
     if dry_run || output.is_none() {
         println!("{}", result);
     } else if let Some(out_path) = output {
-        std::fs::write(&out_path, &result).map_err(|e| {
-            hone::HoneError::io_error(format!("failed to write {}: {}", out_path.display(), e))
-        })?;
+        hone::write_output(&out_path, &result, &write_options)?;
         eprintln!("Wrote {}", out_path.display());
+        if let Some(map) = map {
+            let mut map_path = out_path.clone().into_os_string();
+            map_path.push(".map.json");
+            let map_path = PathBuf::from(map_path);
+            hone::write_output(&map_path, &map.to_json(&base_dir)?, &write_options)?;
+            if !quiet {
+                eprintln!("Wrote {}", map_path.display());
+            }
+        }
+        if !no_hooks {
+            run_post_compile_hooks(&file, &base_dir, &[out_path], None, output_format)?;
+        }
+    }
+    if let Some((path, derivation)) = derivation {
+        write_derivation(path, &derivation, &write_options, quiet)?;
     }
 
     Ok(())
 }
 
-fn has_args(
-    set: &[(String, String)],
-    set_file: &[(String, String)],
-    set_string: &[(String, String)],
-) -> bool {
-    !set.is_empty() || !set_file.is_empty() || !set_string.is_empty()
+/// Cache-key name of the output format plus any options that change the
+/// emitted text
+fn output_style_key(
+    format: hone::OutputFormat,
+    alpha_order: bool,
+    emit_options: &hone::EmitOptions,
+) -> String {
+    let mut key = format.name().to_string();
+    if alpha_order {
+        key.push_str("+alpha");
+    }
+    if let Some(indent) = emit_options.indent {
+        key.push_str(&format!("+indent{}", indent));
+    }
+    if emit_options.quote_strings {
+        key.push_str("+quoted");
+    }
+    if emit_options.omit_nulls {
+        key.push_str("+nonulls");
+    }
+    if emit_options.key_origins {
+        key.push_str("+origins");
+    }
+    if emit_options.trailing_commas {
+        key.push_str("+trailingcommas");
+    }
+    if let Some(ref separator) = emit_options.env_separator {
+        key.push_str(&format!("+envsep{:?}", separator));
+    }
+    if emit_options.env_arrays != hone::DotenvArrays::default() {
+        key.push_str(&format!("+envarrays{:?}", emit_options.env_arrays));
+    }
+    key
+}
+
+/// `file:line` of each output key, with paths relative to `base_dir`, for
+/// `--key-origins`
+fn key_origins(
+    locations: &hone::evaluator::LocationMap,
+    base_dir: &std::path::Path,
+) -> hone::evaluator::DocMap {
+    locations
+        .iter()
+        .map(|(path, location)| {
+            let file = match &location.file {
+                Some(file) => file
+                    .strip_prefix(base_dir)
+                    .unwrap_or(file)
+                    .display()
+                    .to_string(),
+                None => "<stdin>".to_string(),
+            };
+            (path.clone(), format!("{}:{}", file, location.line))
+        })
+        .collect()
 }
 
-/// Format a warning's file:line location for display
-fn format_warning_location(w: &hone::Warning) -> String {
-    match (&w.file, w.line) {
-        (Some(f), line) if line > 0 => format!("[{}:{}]", f.display(), line),
-        (Some(f), _) => format!("[{}]", f.display()),
-        _ => String::new(),
+/// Write the `--emit-derivation` file
+fn write_derivation(
+    path: &std::path::Path,
+    derivation: &hone::compiler::derivation::Derivation,
+    write_options: &hone::WriteOptions,
+    quiet: bool,
+) -> hone::HoneResult<()> {
+    hone::write_output(path, &derivation.to_json()?, write_options)?;
+    if !quiet {
+        eprintln!("Wrote {}", path.display());
     }
+    Ok(())
 }
 
-/// Find all secret placeholders in a value tree, returning their paths
-fn find_secret_placeholders(value: &hone::Value, prefix: &str) -> Vec<String> {
-    let mut found = Vec::new();
-    match value {
-        hone::Value::String(s) if s.starts_with("<SECRET:") && s.ends_with('>') => {
-            found.push(if prefix.is_empty() {
-                s.clone()
-            } else {
-                format!("{} ({})", prefix, s)
-            });
-        }
-        hone::Value::Object(obj) => {
-            for (k, v) in obj {
-                let path = if prefix.is_empty() {
-                    k.clone()
-                } else {
-                    format!("{}.{}", prefix, k)
-                };
-                found.extend(find_secret_placeholders(v, &path));
+/// `compile --stream`: top-level `key: for ...` arrays are written as each
+/// element is evaluated, so memory is bounded by the largest element rather
+/// than the whole output
+#[allow(clippy::too_many_arguments)]
+fn cmd_compile_stream(
+    compiler: &mut hone::Compiler,
+    file: &std::path::Path,
+    base_dir: &std::path::Path,
+    output: Option<PathBuf>,
+    format: hone::OutputFormat,
+    dry_run: bool,
+    strict: bool,
+    deny: &[String],
+    quiet: bool,
+    write_options: &hone::WriteOptions,
+    stamp: bool,
+    no_hooks: bool,
+    explain_inputs: bool,
+    report_memory: bool,
+    profile: bool,
+) -> hone::HoneResult<()> {
+    let canonical = hone::resolver::canonicalize(file).map_err(|e| {
+        hone::HoneError::io_error(format!("failed to resolve path {}: {}", file.display(), e))
+    })?;
+    let mut compile = |out: &mut dyn std::io::Write| -> hone::HoneResult<()> {
+        let header = apply_stamp(String::new(), stamp, format, file);
+        out.write_all(header.as_bytes())
+            .map_err(|e| hone::HoneError::io_error(format!("failed to write output: {}", e)))?;
+        let mut emitter = hone::StreamEmitter::new(format, out)?;
+        compiler.compile_stream(&canonical, &mut emitter)?;
+        emitter.finish()?;
+        report_warnings(compiler.warnings(), strict, deny, quiet)
+    };
+
+    match output {
+        Some(out_path) if !dry_run => {
+            hone::write_output_with(&out_path, write_options, compile)?;
+            eprintln!("Wrote {}", out_path.display());
+            if !no_hooks {
+                run_post_compile_hooks(file, base_dir, &[out_path], None, format)?;
             }
         }
-        hone::Value::Array(arr) => {
-            for (i, v) in arr.iter().enumerate() {
-                let path = format!("{}[{}]", prefix, i);
-                found.extend(find_secret_placeholders(v, &path));
-            }
+        _ => {
+            let stdout = std::io::stdout();
+            compile(&mut std::io::BufWriter::new(stdout.lock()))?;
         }
-        _ => {}
     }
-    found
+    if explain_inputs {
+        print_external_inputs(compiler.external_inputs(), compiler.redactor());
+    }
+    if report_memory {
+        print_memory_report(compiler);
+    }
+    if profile {
+        print_policy_profile(compiler);
+    }
+    Ok(())
+}
+
+/// `compile --audit-determinism`: compile and emit every document twice with
+/// fresh compilers and fail on any difference. Each compiler's hash maps get
+/// new random seeds, so output that leaks hash iteration order shows up here
+/// instead of as spurious cache misses and GitOps diffs.
+#[allow(clippy::too_many_arguments)]
+fn audit_compile_determinism(
+    file: &std::path::Path,
+    format: hone::OutputFormat,
+    set: &[(String, String)],
+    set_file: &[(String, String)],
+    set_string: &[(String, String)],
+    allow_env: bool,
+    variants: &[(String, String)],
+    ignore_policy: bool,
+    secrets_mode: &str,
+    alpha_order: bool,
+) -> hone::HoneResult<()> {
+    let canonical = hone::resolver::canonicalize(file).map_err(|e| {
+        hone::HoneError::io_error(format!("failed to resolve path {}: {}", file.display(), e))
+    })?;
+    let base_dir = canonical
+        .parent()
+        .unwrap_or(std::path::Path::new("."))
+        .to_path_buf();
+
+    let run = || -> hone::HoneResult<String> {
+        let mut compiler = hone::Compiler::new(&base_dir);
+        compiler.set_allow_env(allow_env);
+        compiler.set_ignore_policies(ignore_policy);
+        if !variants.is_empty() {
+            compiler.set_variants(variants.iter().cloned().collect());
+        }
+        if has_args(set, set_file, set_string) {
+            compiler.set_args(hone::build_args_object(set, set_file, set_string)?);
+        }
+        let documents = compiler
+            .compile_multi(&canonical)?
+            .into_iter()
+            .map(|(name, value)| {
+                let value = apply_secrets_mode(&value, secrets_mode)?;
+                let value = if alpha_order {
+                    value.sorted_keys()
+                } else {
+                    value
+                };
+                Ok((name, value))
+            })
+            .collect::<hone::HoneResult<Vec<_>>>()?;
+        hone::emit_multi(&documents, format)
+    };
+
+    let (first, second) = (run()?, run()?);
+    if first == second {
+        return Ok(());
+    }
+    let (line, a, b) = first
+        .lines()
+        .chain(std::iter::repeat("<end of output>"))
+        .zip(second.lines().chain(std::iter::repeat("<end of output>")))
+        .take(first.lines().count().max(second.lines().count()) + 1)
+        .enumerate()
+        .map(|(i, (a, b))| (i + 1, a, b))
+        .find(|(_, a, b)| a != b)
+        .unwrap_or((1, "<whitespace only>", "<whitespace only>"));
+    Err(hone::HoneError::compilation_error(format!(
+        "output is not deterministic: two compilations differ at line {}\n  first:  {}\n  second: {}",
+        line, a, b
+    )))
+}
+
+/// Print `--explain-inputs`: every env var and file read, where it was first
+/// read. A name built from a `@sensitive` value is masked.
+fn print_external_inputs(inputs: &[hone::evaluator::ExternalInput], redactor: &hone::Redactor) {
+    if inputs.is_empty() {
+        eprintln!("no environment variables or files were read");
+        return;
+    }
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let names: Vec<String> = inputs.iter().map(|i| redactor.text(&i.name)).collect();
+    let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+    eprintln!("inputs read during compilation:");
+    for (input, name) in inputs.iter().zip(&names) {
+        let kind = match input.kind {
+            hone::evaluator::ExternalInputKind::Env => "env ",
+            hone::evaluator::ExternalInputKind::File => "file",
+            hone::evaluator::ExternalInputKind::Clock => "time",
+        };
+        let file = match input.location.file {
+            Some(ref path) => path
+                .strip_prefix(&cwd)
+                .unwrap_or(path)
+                .display()
+                .to_string(),
+            None => "<stdin>".to_string(),
+        };
+        eprintln!(
+            "  {} {:width$}  {}:{}:{}",
+            kind,
+            name,
+            file,
+            input.location.line,
+            input.location.column,
+            width = width
+        );
+    }
+}
+
+/// Sites listed by `compile --report-memory`
+const REPORTED_VALUES: usize = 10;
+
+/// Print the `--report-memory` summary: how far the heap grew while
+/// compiling, and the largest values lets, `for` loops and calls built
+fn print_memory_report(compiler: &hone::Compiler) {
+    eprintln!(
+        "peak heap growth during compilation: {}",
+        format_bytes(heap::peak())
+    );
+    let largest = compiler
+        .value_sizes()
+        .map(|sizes| sizes.largest(REPORTED_VALUES))
+        .unwrap_or_default();
+    if largest.is_empty() {
+        eprintln!("no lets, `for` loops or calls were evaluated");
+        return;
+    }
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let sizes: Vec<String> = largest.iter().map(|s| format_bytes(s.bytes)).collect();
+    let size_width = sizes.iter().map(|s| s.len()).max().unwrap_or(0);
+    let what_width = largest.iter().map(|s| s.what.len()).max().unwrap_or(0);
+    eprintln!("largest values (approximate size as JSON):");
+    for (site, size) in largest.iter().zip(&sizes) {
+        let file = match site.location.file {
+            Some(ref path) => path
+                .strip_prefix(&cwd)
+                .unwrap_or(path)
+                .display()
+                .to_string(),
+            None => "<stdin>".to_string(),
+        };
+        let times = if site.evaluations > 1 {
+            format!("  (evaluated {} times)", site.evaluations)
+        } else {
+            String::new()
+        };
+        eprintln!(
+            "  {:>size_width$}  {:what_width$}  {}:{}:{}{}",
+            size,
+            site.what,
+            file,
+            site.location.line,
+            site.location.column,
+            times,
+            size_width = size_width,
+            what_width = what_width
+        );
+    }
+}
+
+/// Print the `--profile` summary: how long each policy took to check, summed
+/// over every document it was checked against, slowest first
+fn print_policy_profile(compiler: &hone::Compiler) {
+    let mut policies: Vec<(&hone::PolicyTiming, std::time::Duration, usize, usize)> = Vec::new();
+    for timing in compiler.policy_timings() {
+        let same = |(seen, ..): &&mut (&hone::PolicyTiming, _, _, _)| {
+            seen.name == timing.name
+                && seen.location.file == timing.location.file
+                && seen.location.line == timing.location.line
+        };
+        match policies.iter_mut().find(same) {
+            Some((_, elapsed, checks, cached)) => {
+                *elapsed += timing.elapsed;
+                *checks += 1;
+                *cached += timing.cached_calls;
+            }
+            None => policies.push((timing, timing.elapsed, 1, timing.cached_calls)),
+        }
+    }
+    if policies.is_empty() {
+        eprintln!("no policies were checked");
+        return;
+    }
+    policies.sort_by_key(|p| std::cmp::Reverse(p.1));
+
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let times: Vec<String> = policies
+        .iter()
+        .map(|(_, elapsed, ..)| format!("{:.3}ms", elapsed.as_secs_f64() * 1000.0))
+        .collect();
+    let time_width = times.iter().map(|t| t.len()).max().unwrap_or(0);
+    let name_width = policies
+        .iter()
+        .map(|(t, ..)| t.name.len())
+        .max()
+        .unwrap_or(0);
+    eprintln!("policy timings (slowest first):");
+    for ((timing, _, checks, cached), time) in policies.iter().zip(&times) {
+        let file = match timing.location.file {
+            Some(ref path) => path
+                .strip_prefix(&cwd)
+                .unwrap_or(path)
+                .display()
+                .to_string(),
+            None => "<stdin>".to_string(),
+        };
+        let mut notes = Vec::new();
+        if *checks > 1 {
+            notes.push(format!("checked {} times", checks));
+        }
+        if *cached > 0 {
+            notes.push(format!("{} cached calls", cached));
+        }
+        let notes = if notes.is_empty() {
+            String::new()
+        } else {
+            format!("  ({})", notes.join(", "))
+        };
+        eprintln!(
+            "  {:>time_width$}  {:name_width$}  {}:{}{}",
+            time,
+            timing.name,
+            file,
+            timing.location.line,
+            notes,
+            time_width = time_width,
+            name_width = name_width
+        );
+    }
+}
+
+/// Print the `--report-mapping` table: each dotenv variable, the output
+/// path it comes from, and the assignment that set it
+fn print_env_mapping(vars: &[hone::EnvVar], compiler: &hone::Compiler, stdin_source: Option<&str>) {
+    if vars.is_empty() {
+        eprintln!("no dotenv variables were written");
+        return;
+    }
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let sources: std::collections::HashMap<&std::path::Path, &str> = compiler
+        .source_files()
+        .map(|f| (f.path.as_path(), f.source.as_str()))
+        .collect();
+    let rows: Vec<(&str, &str, String, String)> = vars
+        .iter()
+        .map(|var| {
+            // Keys inside arrays share one location per path, so those are
+            // traced to the array; a key with no location of its own (set by
+            // a spread, say) is traced to its parent
+            let mut path: Vec<&str> = var
+                .path
+                .split('.')
+                .take_while(|s| s.parse::<usize>().is_err())
+                .collect();
+            let location = loop {
+                if let Some(location) = compiler.key_locations().get(&path.join(".")) {
+                    break Some(location);
+                }
+                if path.pop().is_none() {
+                    break None;
+                }
+            };
+            let Some(location) = location else {
+                return (
+                    var.name.as_str(),
+                    var.path.as_str(),
+                    "-".to_string(),
+                    String::new(),
+                );
+            };
+            let (file, source) = match location.file {
+                Some(ref path) => (
+                    path.strip_prefix(&cwd)
+                        .unwrap_or(path)
+                        .display()
+                        .to_string(),
+                    sources.get(path.as_path()).copied(),
+                ),
+                None => ("<stdin>".to_string(), stdin_source),
+            };
+            let expression = source
+                .and_then(|s| s.get(location.offset..location.offset + location.length))
+                .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+                .unwrap_or_default();
+            (
+                var.name.as_str(),
+                var.path.as_str(),
+                format!("{}:{}", file, location.line),
+                compiler.redactor().text(&shorten(&expression, 60)),
+            )
+        })
+        .collect();
+    let name_width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0);
+    let path_width = rows.iter().map(|r| r.1.len()).max().unwrap_or(0);
+    let file_width = rows.iter().map(|r| r.2.len()).max().unwrap_or(0);
+    eprintln!("dotenv variables and where they are set:");
+    for (name, path, file, expression) in &rows {
+        eprintln!(
+            "  {:name_width$}  {:path_width$}  {:file_width$}  {}",
+            name,
+            path,
+            file,
+            expression,
+            name_width = name_width,
+            path_width = path_width,
+            file_width = file_width
+        );
+    }
+}
+
+/// `text` cut to at most `max` characters, ending in `…` when cut
+fn shorten(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(max - 1).collect();
+    cut.push('…');
+    cut
+}
+
+/// A byte count in binary units: `812 B`, `1.5 KiB`, `24.0 MiB`
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Heap accounting for `compile --report-memory`. Once started, the global
+/// allocator tracks bytes allocated minus bytes freed, and the highest that
+/// total reached; before that it only forwards to the system allocator.
+mod heap {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+
+    static COUNTING: AtomicBool = AtomicBool::new(false);
+    static CURRENT: AtomicIsize = AtomicIsize::new(0);
+    static PEAK: AtomicIsize = AtomicIsize::new(0);
+
+    pub struct CountingAllocator;
+
+    fn note(delta: isize) {
+        if COUNTING.load(Ordering::Relaxed) {
+            let now = CURRENT.fetch_add(delta, Ordering::Relaxed) + delta;
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                note(layout.size() as isize);
+            }
+            ptr
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc_zeroed(layout);
+            if !ptr.is_null() {
+                note(layout.size() as isize);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            note(-(layout.size() as isize));
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new_ptr = System.realloc(ptr, layout, new_size);
+            if !new_ptr.is_null() {
+                note(new_size as isize - layout.size() as isize);
+            }
+            new_ptr
+        }
+    }
+
+    /// Start counting, from zero
+    pub fn start() {
+        CURRENT.store(0, Ordering::Relaxed);
+        PEAK.store(0, Ordering::Relaxed);
+        COUNTING.store(true, Ordering::Relaxed);
+    }
+
+    /// Highest net growth of the heap since `start`, in bytes
+    pub fn peak() -> usize {
+        PEAK.load(Ordering::Relaxed).max(0) as usize
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: heap::CountingAllocator = heap::CountingAllocator;
+
+/// Validate documents for `--validate`, failing with every violation found
+fn validate_k8s(
+    validator: &hone::k8s::K8sValidator,
+    documents: &[(Option<String>, hone::Value)],
+    quiet: bool,
+) -> hone::HoneResult<()> {
+    let report = validator.validate(documents);
+    if !quiet {
+        for resource in &report.unchecked {
+            eprintln!(
+                "warning: no schema available for {}; only apiVersion and kind were checked",
+                resource
+            );
+        }
+    }
+    if report.violations.is_empty() {
+        return Ok(());
+    }
+    for violation in &report.violations {
+        eprintln!("error: {}", violation);
+    }
+    Err(hone::HoneError::compilation_error(format!(
+        "{} Kubernetes validation error(s)",
+        report.violations.len()
+    )))
+}
+
+/// Run the `post_compile` hooks of the nearest hone.toml, if any, after
+/// output files were written. Paths are made absolute since hooks run from
+/// the manifest's directory.
+fn run_post_compile_hooks(
+    file: &std::path::Path,
+    base_dir: &std::path::Path,
+    outputs: &[PathBuf],
+    output_dir: Option<&std::path::Path>,
+    format: hone::OutputFormat,
+) -> hone::HoneResult<()> {
+    let Some(manifest) = hone::manifest::Manifest::discover(base_dir)? else {
+        return Ok(());
+    };
+    if manifest.hooks.post_compile.is_empty() {
+        return Ok(());
+    }
+
+    let absolute = |p: &std::path::Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+    let ctx = hone::manifest::HookContext {
+        file: absolute(file),
+        outputs: outputs.iter().map(|p| absolute(p)).collect(),
+        output_dir: output_dir.map(absolute),
+        format: match format {
+            hone::OutputFormat::JsonPretty => "json",
+            format => format.name(),
+        }
+        .to_string(),
+    };
+    manifest.run_post_compile(&ctx)
+}
+
+/// Prepend the `--stamp` header, if requested and the format has comments
+fn apply_stamp(
+    output: String,
+    stamp: bool,
+    format: hone::OutputFormat,
+    file: &std::path::Path,
+) -> String {
+    let source = if file.to_str() == Some("-") || file.to_str() == Some("/dev/stdin") {
+        "<stdin>".to_string()
+    } else {
+        file.display().to_string()
+    };
+    match hone::emitter::stamp_comment(format, &source) {
+        Some(header) if stamp => format!("{}{}", header, output),
+        _ => output,
+    }
+}
+
+/// Prompt on the terminal for every `expect args.*` and variant that wasn't
+/// given on the command line. Answers are appended to the `--set`,
+/// `--set-string`, and `--variant` lists, so caching sees them like flags.
+fn prompt_compile_inputs(
+    file: &std::path::Path,
+    set: &mut Vec<(String, String)>,
+    set_file: &[(String, String)],
+    set_string: &mut Vec<(String, String)>,
+    variants: &mut Vec<(String, String)>,
+) -> hone::HoneResult<()> {
+    let inputs = hone::collect_inputs(file)?;
+
+    let is_set = |key: &str| {
+        set.iter()
+            .chain(set_file)
+            .chain(set_string.iter())
+            .any(|(k, _)| k == key || key.starts_with(&format!("{}.", k)))
+    };
+    let missing_args: Vec<_> = inputs.args.iter().filter(|a| !is_set(&a.key)).collect();
+
+    for arg in missing_args {
+        if let Some(ref description) = arg.description {
+            eprintln!("# {}", description);
+        }
+        let prompt = match arg.default {
+            Some(ref default) => format!("args.{} ({}) [{}]: ", arg.key, arg.type_name, default),
+            None => format!("args.{} ({}): ", arg.key, arg.type_name),
+        };
+        loop {
+            let answer = read_prompt(&prompt)?;
+            if answer.is_empty() {
+                if arg.default.is_some() {
+                    break;
+                }
+                eprintln!("a value is required");
+                continue;
+            }
+            // Strings skip type inference so `--set`-style "123" stays a string
+            if arg.type_name == "string" {
+                set_string.push((arg.key.clone(), answer));
+            } else {
+                set.push((arg.key.clone(), answer));
+            }
+            break;
+        }
+    }
+
+    for variant in &inputs.variants {
+        if variants.iter().any(|(name, _)| name == &variant.name) {
+            continue;
+        }
+        eprintln!("variant {}:", variant.name);
+        for (i, case) in variant.cases.iter().enumerate() {
+            let marker = if variant.default.as_ref() == Some(case) {
+                " (default)"
+            } else {
+                ""
+            };
+            eprintln!("  {:>2}) {}{}", i + 1, case, marker);
+        }
+        let prompt = match variant.default {
+            Some(ref default) => format!("{} [{}]: ", variant.name, default),
+            None => format!("{}: ", variant.name),
+        };
+        loop {
+            let answer = read_prompt(&prompt)?;
+            let case = match answer.parse::<usize>() {
+                Ok(n) if n >= 1 && n <= variant.cases.len() => Some(variant.cases[n - 1].clone()),
+                _ if answer.is_empty() => variant.default.clone(),
+                _ => variant.cases.iter().find(|c| **c == answer).cloned(),
+            };
+            match case {
+                Some(case) => {
+                    variants.push((variant.name.clone(), case));
+                    break;
+                }
+                None => eprintln!("expected one of: {}", variant.cases.join(", ")),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print a prompt to stderr and read one trimmed line from stdin
+fn read_prompt(prompt: &str) -> hone::HoneResult<String> {
+    use std::io::Write;
+
+    eprint!("{}", prompt);
+    std::io::stderr().flush().ok();
+
+    let mut line = String::new();
+    let read = std::io::stdin()
+        .read_line(&mut line)
+        .map_err(|e| hone::HoneError::io_error(format!("failed to read stdin: {}", e)))?;
+    if read == 0 {
+        return Err(hone::HoneError::io_error(
+            "unexpected end of input while prompting".to_string(),
+        ));
+    }
+    Ok(line.trim().to_string())
+}
+
+fn has_args(
+    set: &[(String, String)],
+    set_file: &[(String, String)],
+    set_string: &[(String, String)],
+) -> bool {
+    !set.is_empty() || !set_file.is_empty() || !set_string.is_empty()
+}
+
+/// Print warnings, then fail if `--strict` is set or any warning's code is
+/// listed in `--deny warn:CODE`
+fn report_warnings(
+    warnings: &[hone::Warning],
+    strict: bool,
+    deny: &[String],
+    quiet: bool,
+) -> hone::HoneResult<()> {
+    let denied = warnings
+        .iter()
+        .filter(|w| strict || deny.contains(&w.code))
+        .count();
+
+    for w in warnings {
+        let w = if strict || deny.contains(&w.code) {
+            w.clone().with_severity(miette::Severity::Error)
+        } else if quiet {
+            continue;
+        } else {
+            w.clone()
+        };
+        if json_diagnostics() {
+            print_diagnostic(&w.to_json().to_string());
+        } else {
+            print_diagnostic(&format!("{:?}", miette::Report::new(w)));
+        }
+    }
+
+    if denied > 0 {
+        let flag = if strict { "--strict" } else { "--deny" };
+        return Err(hone::HoneError::compilation_error(format!(
+            "{} warning(s) treated as errors ({})",
+            denied, flag
+        )));
+    }
+    Ok(())
+}
+
+/// Find all secret placeholders in a value tree, returning their paths
+fn find_secret_placeholders(value: &hone::Value, prefix: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    match value {
+        hone::Value::String(s) if s.starts_with("<SECRET:") && s.ends_with('>') => {
+            found.push(if prefix.is_empty() {
+                s.clone()
+            } else {
+                format!("{} ({})", prefix, s)
+            });
+        }
+        hone::Value::Object(obj) => {
+            for (k, v) in obj {
+                let path = if prefix.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{}.{}", prefix, k)
+                };
+                found.extend(find_secret_placeholders(v, &path));
+            }
+        }
+        hone::Value::Array(arr) => {
+            for (i, v) in arr.iter().enumerate() {
+                let path = format!("{}[{}]", prefix, i);
+                found.extend(find_secret_placeholders(v, &path));
+            }
+        }
+        _ => {}
+    }
+    found
 }
 
 /// Resolve env:-prefixed secrets from environment variables
@@ -752,20 +2582,230 @@ fn cmd_graph(file: PathBuf, format: String, output: Option<PathBuf>) -> hone::Ho
         ))
     })?;
 
-    let result = hone::graph::generate_graph(&file, graph_format)?;
-
     if let Some(out_path) = output {
-        std::fs::write(&out_path, &result).map_err(|e| {
-            hone::HoneError::io_error(format!("failed to write {}: {}", out_path.display(), e))
-        })?;
+        let result = hone::graph::generate_graph(&file, graph_format)?;
+        hone::write_output(&out_path, &result, &hone::WriteOptions::new())?;
         eprintln!("Wrote {}", out_path.display());
     } else {
+        let result = hone::graph::generate_graph_with(&file, graph_format, &stdout_style())?;
         print!("{}", result);
     }
 
     Ok(())
 }
 
+fn cmd_stats(paths: Vec<PathBuf>, format: String) -> hone::HoneResult<()> {
+    if format != "text" && format != "json" {
+        return Err(hone::HoneError::io_error(format!(
+            "unknown stats format '{}'. Use: text, json",
+            format
+        )));
+    }
+    let paths = if paths.is_empty() {
+        vec![PathBuf::from(".")]
+    } else {
+        paths
+    };
+    let mut files = Vec::new();
+    for path in &paths {
+        if path.is_dir() {
+            collect_hone_files(path, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    files.sort();
+
+    let stats = hone::stats::collect_stats(&files)?;
+    let cwd = std::env::current_dir().unwrap_or_default();
+    if format == "json" {
+        print!("{}", hone::stats::format_stats_json(&stats, &cwd));
+    } else {
+        print!(
+            "{}",
+            hone::stats::format_stats_text_with(&stats, &cwd, &stdout_style())
+        );
+    }
+    Ok(())
+}
+
+fn cmd_doctor(format: String) -> hone::HoneResult<()> {
+    let cwd = std::env::current_dir()
+        .map_err(|e| hone::HoneError::io_error(format!("failed to get cwd: {}", e)))?;
+    let report = hone::doctor::diagnose(&cwd);
+    if format == "json" {
+        print!("{}", report.to_json());
+    } else {
+        print!("{}", report.format_text(&stdout_style()));
+    }
+    match report.failed() {
+        0 => Ok(()),
+        n => Err(hone::HoneError::io_error(format!(
+            "{} doctor check(s) failed",
+            n
+        ))),
+    }
+}
+
+fn cmd_conflicts(
+    file: PathBuf,
+    format: String,
+    set: Vec<(String, String)>,
+    allow_env: bool,
+    variants: Vec<(String, String)>,
+) -> hone::HoneResult<()> {
+    let canonical = hone::resolver::canonicalize(&file).map_err(|e| {
+        hone::HoneError::io_error(format!("failed to resolve path {}: {}", file.display(), e))
+    })?;
+    let base_dir = canonical
+        .parent()
+        .unwrap_or(std::path::Path::new("."))
+        .to_path_buf();
+    let mut compiler = hone::Compiler::new(&base_dir);
+    compiler.set_allow_env(allow_env);
+    compiler.enable_writes();
+    if !variants.is_empty() {
+        compiler.set_variants(variants.into_iter().collect());
+    }
+    if !set.is_empty() {
+        compiler.set_args(hone::build_args_object(&set, &[], &[])?);
+    }
+
+    let output = compiler.compile(&canonical)?;
+    let conflicts: Vec<_> = hone::conflicts::find_conflicts(&compiler.writes(&canonical), &output)
+        .iter()
+        .map(|c| c.redacted(compiler.redactor()))
+        .collect();
+    let cwd = std::env::current_dir().unwrap_or(base_dir);
+    if format == "json" {
+        print!(
+            "{}",
+            hone::conflicts::format_conflicts_json(&conflicts, &cwd)
+        );
+    } else {
+        print!(
+            "{}",
+            hone::conflicts::format_conflicts_text_with(&conflicts, &cwd, &stdout_style())
+        );
+    }
+    Ok(())
+}
+
+fn cmd_unused(dir: Option<PathBuf>, entries: Vec<PathBuf>, format: String) -> hone::HoneResult<()> {
+    let cwd = std::env::current_dir()
+        .map_err(|e| hone::HoneError::io_error(format!("failed to get cwd: {}", e)))?;
+    let manifest = hone::manifest::Manifest::discover(dir.as_deref().unwrap_or(&cwd))?;
+    let root = match (dir, &manifest) {
+        (Some(dir), _) => dir,
+        (None, Some(manifest)) => manifest.root.clone(),
+        (None, None) => cwd.clone(),
+    };
+    let entries = match manifest {
+        Some(manifest) if entries.is_empty() => manifest
+            .targets
+            .values()
+            .map(|target| manifest.root.join(&target.entry))
+            .collect(),
+        _ => entries,
+    };
+
+    let report = hone::unused::find_unused(&root, &entries)?;
+    if report.entries.is_empty() {
+        eprintln!(
+            "warning: no entry points found under {}; pass --entry or add targets to hone.toml",
+            root.display()
+        );
+    }
+    if format == "json" {
+        print!("{}", hone::unused::format_unused_json(&report, &cwd));
+    } else {
+        print!(
+            "{}",
+            hone::unused::format_unused_text_with(&report, &cwd, &stdout_style())
+        );
+    }
+    Ok(())
+}
+
+fn cmd_debug(
+    file: PathBuf,
+    at: (usize, usize),
+    set: Vec<(String, String)>,
+    allow_env: bool,
+    variants: Vec<(String, String)>,
+) -> hone::HoneResult<()> {
+    let canonical = hone::resolver::canonicalize(&file).map_err(|e| {
+        hone::HoneError::io_error(format!("failed to resolve path {}: {}", file.display(), e))
+    })?;
+    let source = std::fs::read_to_string(&canonical).map_err(|e| {
+        hone::HoneError::io_error(format!("failed to read {}: {}", file.display(), e))
+    })?;
+    let (line, column) = at;
+    let offset = hone::evaluator::offset_at(&source, line, column).ok_or_else(|| {
+        hone::HoneError::io_error(format!("{} has no line {}", file.display(), line))
+    })?;
+
+    let base_dir = canonical
+        .parent()
+        .unwrap_or(std::path::Path::new("."))
+        .to_path_buf();
+    let mut compiler = hone::Compiler::new(&base_dir);
+    compiler.set_allow_env(allow_env);
+    compiler.enable_snapshots();
+    if !variants.is_empty() {
+        compiler.set_variants(variants.into_iter().collect());
+    }
+    if !set.is_empty() {
+        compiler.set_args(hone::build_args_object(&set, &[], &[])?);
+    }
+
+    // Show what was recorded even if evaluation fails later in the file
+    let result = compiler.compile(&canonical);
+
+    let file_snapshots: Vec<hone::evaluator::ScopeSnapshot> = compiler
+        .snapshots()
+        .iter()
+        .filter(|s| s.location.file.as_deref() == Some(canonical.as_path()))
+        .map(|s| s.redacted(compiler.redactor()))
+        .collect();
+    let found = hone::evaluator::snapshots_at(&file_snapshots, offset);
+
+    if found.is_empty() {
+        eprintln!(
+            "no body item evaluated at {}:{}:{}",
+            file.display(),
+            line,
+            column
+        );
+    }
+    for (i, snapshot) in found.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let mut header = format!(
+            "{}:{}:{}",
+            file.display(),
+            snapshot.location.line,
+            snapshot.location.column
+        );
+        if !snapshot.path.is_empty() {
+            header.push_str(&format!(" in {}", snapshot.path));
+        }
+        if found.len() > 1 {
+            header.push_str(&format!(" (iteration {} of {})", i + 1, found.len()));
+        }
+        println!("{}", header);
+        if snapshot.bindings.is_empty() {
+            println!("  (no variables in scope)");
+        }
+        for (name, value) in &snapshot.bindings {
+            println!("  {} = {}", name, value.to_serde_json());
+        }
+    }
+
+    result.map(|_| ())
+}
+
 fn cmd_cache(action: CacheAction) -> hone::HoneResult<()> {
     match action {
         CacheAction::Clean { older_than } => {
@@ -791,14 +2831,148 @@ fn cmd_cache(action: CacheAction) -> hone::HoneResult<()> {
     }
 }
 
+fn cmd_overlay(action: OverlayAction) -> hone::HoneResult<()> {
+    match action {
+        OverlayAction::New {
+            name,
+            from,
+            keys,
+            variant,
+            output,
+        } => {
+            let base_value = hone::compile_file(&from)?;
+            let canonical_from = from.canonicalize().map_err(|e| {
+                hone::HoneError::io_error(format!(
+                    "failed to resolve path {}: {}",
+                    from.display(),
+                    e
+                ))
+            })?;
+            let from_dir = canonical_from.parent().unwrap_or(std::path::Path::new("."));
+
+            let out_path = output.unwrap_or_else(|| from_dir.join(format!("{}.hone", name)));
+            if out_path.exists() {
+                return Err(hone::HoneError::io_error(format!(
+                    "{} already exists",
+                    out_path.display()
+                )));
+            }
+
+            let available = hone::overlay::top_level_keys(&base_value);
+            let keys = match keys {
+                Some(keys) => keys,
+                None if std::io::stdin().is_terminal() => prompt_overlay_keys(&available)?,
+                None => Vec::new(),
+            };
+            let selected = hone::overlay::select_keys(&base_value, &keys)?;
+
+            // `from` paths resolve relative to the overlay's own directory
+            let out_dir = match out_path.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            let out_dir = out_dir.canonicalize().map_err(|e| {
+                hone::HoneError::io_error(format!(
+                    "failed to resolve path {}: {}",
+                    out_dir.display(),
+                    e
+                ))
+            })?;
+            let from_rel = hone::overlay::relative_from_path(&out_dir, &canonical_from);
+            let source = hone::overlay::render_overlay(&name, &from_rel, &selected)?;
+
+            // Work out the variant's new case first, so a bad --variant leaves
+            // nothing behind
+            let variant_update = match variant {
+                Some(ref variant) => {
+                    let variant_file = hone::overlay::find_variant_file(&canonical_from, variant)?
+                        .ok_or_else(|| {
+                            hone::HoneError::compilation_error(format!(
+                                "variant '{}' is not defined in {} or the files it inherits from",
+                                variant,
+                                from.display()
+                            ))
+                        })?;
+                    let template = canonical_from.file_stem().and_then(|s| s.to_str());
+                    let variant_source = std::fs::read_to_string(&variant_file).map_err(|e| {
+                        hone::HoneError::io_error(format!(
+                            "failed to read {}: {}",
+                            variant_file.display(),
+                            e
+                        ))
+                    })?;
+                    let updated =
+                        hone::overlay::add_variant_case(&variant_source, variant, &name, template)?;
+                    Some((variant, variant_file, updated))
+                }
+                None => None,
+            };
+
+            // Write the overlay before the case that points at it. It may have
+            // appeared while keys were being chosen, so never replace one.
+            let options = hone::WriteOptions::new().with_no_clobber(true);
+            hone::write_output(&out_path, &source, &options)?;
+            eprintln!("Wrote {}", out_path.display());
+
+            if let Some((variant, variant_file, updated)) = variant_update {
+                if let Err(e) =
+                    hone::write_output(&variant_file, &updated, &hone::WriteOptions::new())
+                {
+                    // Leave neither half of the overlay behind
+                    let _ = std::fs::remove_file(&out_path);
+                    return Err(e);
+                }
+                eprintln!(
+                    "Added case '{}' to variant '{}' in {}",
+                    name,
+                    variant,
+                    variant_file.display()
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Ask which top-level keys to copy into a new overlay
+fn prompt_overlay_keys(available: &[String]) -> hone::HoneResult<Vec<String>> {
+    eprintln!("Keys in the base output:");
+    for (i, key) in available.iter().enumerate() {
+        eprintln!("  {:>2}) {}", i + 1, key);
+    }
+    let line = read_prompt(
+        "Copy which keys? (numbers or names, comma-separated; 'all'; empty for none): ",
+    )?;
+    if line == "all" {
+        return Ok(available.to_vec());
+    }
+
+    line.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| match s.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= available.len() => Ok(available[n - 1].clone()),
+            Ok(n) => Err(hone::HoneError::io_error(format!(
+                "no key numbered {} (expected 1-{})",
+                n,
+                available.len()
+            ))),
+            Err(_) => Ok(s.to_string()),
+        })
+        .collect()
+}
+
 #[allow(clippy::too_many_arguments)]
 fn cmd_compile_multi(
     file: &std::path::Path,
-    output_dir: &std::path::Path,
+    output_dir: Option<&std::path::Path>,
+    output_name: Option<&str>,
+    output: Option<&std::path::Path>,
     format: hone::OutputFormat,
     dry_run: bool,
     quiet: bool,
     strict: bool,
+    deny: &[String],
     set: &[(String, String)],
     set_file: &[(String, String)],
     set_string: &[(String, String)],
@@ -806,8 +2980,20 @@ fn cmd_compile_multi(
     variants: &[(String, String)],
     secrets_mode: &str,
     ignore_policy: bool,
+    write_options: &hone::WriteOptions,
+    stamp: bool,
+    no_hooks: bool,
+    validator: Option<&hone::k8s::K8sValidator>,
+    alpha_order: bool,
+    key_collision: hone::KeyCollision,
+    emit_options: &hone::EmitOptions,
+    explain_inputs: bool,
+    report_memory: bool,
+    profile: bool,
+    policy_timeout: Option<std::time::Duration>,
+    emit_derivation: Option<&std::path::Path>,
 ) -> hone::HoneResult<()> {
-    let canonical = file.canonicalize().map_err(|e| {
+    let canonical = hone::resolver::canonicalize(file).map_err(|e| {
         hone::HoneError::io_error(format!("failed to resolve path {}: {}", file.display(), e))
     })?;
     let base_dir = canonical
@@ -819,6 +3005,13 @@ fn cmd_compile_multi(
     let mut compiler = hone::Compiler::new(&base_dir);
     compiler.set_allow_env(allow_env);
     compiler.set_ignore_policies(ignore_policy);
+    compiler.set_output_format(format);
+    compiler.set_key_collision(key_collision);
+    compiler.set_policy_timeout(policy_timeout);
+    if report_memory {
+        compiler.enable_value_sizes();
+        heap::start();
+    }
     if !variants.is_empty() {
         let variant_map: std::collections::HashMap<String, String> =
             variants.iter().cloned().collect();
@@ -832,98 +3025,429 @@ fn cmd_compile_multi(
     // Compile with full import resolution, variants, args, policies, etc.
     let documents = compiler.compile_multi(&canonical)?;
 
-    // Handle warnings
-    let warnings = compiler.warnings();
-    if !warnings.is_empty() {
-        if strict {
-            for w in warnings {
-                eprintln!("warning{}: {}", format_warning_location(w), w.message);
+    report_warnings(compiler.warnings(), strict, deny, quiet)?;
+    if explain_inputs {
+        print_external_inputs(compiler.external_inputs(), compiler.redactor());
+    }
+    if report_memory {
+        print_memory_report(&compiler);
+    }
+    if profile {
+        print_policy_profile(&compiler);
+    }
+
+    // Validate secrets mode prerequisites
+    if secrets_mode == "env" && !allow_env {
+        return Err(hone::HoneError::io_error(
+            "--secrets-mode=env requires --allow-env flag".to_string(),
+        ));
+    }
+
+    // Apply secrets mode to each document
+    let documents: Vec<(Option<String>, hone::Value)> = documents
+        .into_iter()
+        .map(|(name, value)| {
+            let value = apply_secrets_mode(&value, secrets_mode)?;
+            let value = if alpha_order {
+                value.sorted_keys()
+            } else {
+                value
+            };
+            Ok((name, value))
+        })
+        .collect::<hone::HoneResult<Vec<_>>>()?;
+
+    if let Some(validator) = validator {
+        validate_k8s(validator, &documents, quiet)?;
+    }
+
+    let mut derivation = match emit_derivation {
+        Some(_) => Some(
+            hone::compiler::derivation::Derivation::new(
+                &compiler,
+                file,
+                output_style_key(format, alpha_order, emit_options),
+            )
+            .with_args(hone::build_args_object(set, set_file, set_string)?)
+            .with_variants(variants),
+        ),
+        None => None,
+    };
+    let finish = |derivation: Option<hone::compiler::derivation::Derivation>| match (
+        emit_derivation,
+        derivation,
+    ) {
+        (Some(path), Some(derivation)) => write_derivation(path, &derivation, write_options, quiet),
+        _ => Ok(()),
+    };
+
+    let ext = format.extension();
+
+    let Some(output_dir) = output_dir else {
+        // Without a directory every document goes to one output, one line each
+        let documents: Vec<_> = documents
+            .into_iter()
+            .filter(|(name, value)| name.is_some() || !value.is_empty_object())
+            .collect();
+        let result = apply_stamp(
+            hone::emit_multi_with(&documents, format, emit_options)?,
+            stamp,
+            format,
+            file,
+        );
+        if let Some(ref mut derivation) = derivation {
+            derivation.add_output(output.filter(|_| !dry_run), &result);
+        }
+        match output {
+            Some(out_path) if !dry_run => {
+                hone::write_output(out_path, &result, write_options)?;
+                if !quiet {
+                    eprintln!("Wrote {}", out_path.display());
+                }
+                if !no_hooks {
+                    run_post_compile_hooks(
+                        file,
+                        &base_dir,
+                        &[out_path.to_path_buf()],
+                        None,
+                        format,
+                    )?;
+                }
+            }
+            _ => print!("{}", result),
+        }
+        return finish(derivation);
+    };
+
+    let names = hone::serve::document_file_names(
+        &documents,
+        &ext,
+        output_name,
+        &hone::serve::NameValues {
+            variants: variants.iter().cloned().collect(),
+            args: hone::build_args_object(set, set_file, set_string)?,
+        },
+    )?;
+
+    if dry_run {
+        // Print all documents with separators
+        let mut first = true;
+        for (i, _) in &names {
+            let (name, value) = &documents[*i];
+            if !first {
+                println!("---");
+            }
+            first = false;
+            let result = apply_stamp(
+                hone::emit_with(value, format, emit_options)?,
+                stamp,
+                format,
+                file,
+            );
+            if let Some(doc_name) = name {
+                println!("# {}", doc_name);
+            }
+            if let Some(ref mut derivation) = derivation {
+                derivation.add_output(None, &result);
+            }
+            println!("{}", result);
+        }
+        finish(derivation)?;
+    } else {
+        // Create output directory
+        std::fs::create_dir_all(output_dir).map_err(|e| {
+            hone::HoneError::io_error(format!(
+                "failed to create directory {}: {}",
+                output_dir.display(),
+                e
+            ))
+        })?;
+
+        let mut written = Vec::new();
+        for (i, filename) in &names {
+            let value = &documents[*i].1;
+            let out_path = output_dir.join(filename);
+            if let Some(parent) = out_path.parent().filter(|p| *p != output_dir) {
+                std::fs::create_dir_all(parent).map_err(|e| {
+                    hone::HoneError::io_error(format!(
+                        "failed to create directory {}: {}",
+                        parent.display(),
+                        e
+                    ))
+                })?;
+            }
+            let result = apply_stamp(
+                hone::emit_with(value, format, emit_options)?,
+                stamp,
+                format,
+                file,
+            );
+
+            hone::write_output(&out_path, &result, write_options)?;
+            if !quiet {
+                eprintln!("Wrote {}", out_path.display());
+            }
+            if let Some(ref mut derivation) = derivation {
+                derivation.add_output(Some(&out_path), &result);
+            }
+            written.push(out_path);
+        }
+
+        if !no_hooks {
+            run_post_compile_hooks(file, &base_dir, &written, Some(output_dir), format)?;
+        }
+        finish(derivation)?;
+    }
+
+    Ok(())
+}
+
+/// Compile each named target from the nearest hone.toml, in order
+fn cmd_build(
+    targets: Vec<String>,
+    all_targets: bool,
+    dry_run: bool,
+    strict: bool,
+    quiet: bool,
+    no_cache: bool,
+    no_hooks: bool,
+) -> hone::HoneResult<()> {
+    let manifest = discover_manifest()?;
+
+    let names: Vec<String> = if all_targets {
+        manifest.targets.keys().cloned().collect()
+    } else {
+        targets
+    };
+    if names.is_empty() && !quiet {
+        eprintln!(
+            "warning: no targets defined in {}",
+            manifest.root.join(hone::manifest::MANIFEST_FILE).display()
+        );
+    }
+
+    let to_pairs = |map: &std::collections::BTreeMap<String, String>| {
+        map.iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<Vec<_>>()
+    };
+    for name in &names {
+        let target = manifest.target(name)?;
+        if !quiet {
+            eprintln!("Building {}", name);
+        }
+        cmd_compile(
+            manifest.root.join(&target.entry),
+            target.output.as_ref().map(|p| manifest.root.join(p)),
+            target.format.clone(),
+            to_pairs(&target.args),
+            Vec::new(),
+            Vec::new(),
+            dry_run,
+            strict,
+            Vec::new(),
+            quiet,
+            target.output_dir.as_ref().map(|p| manifest.root.join(p)),
+            target.output_name.clone(),
+            target.allow_env,
+            to_pairs(&target.variants),
+            no_cache,
+            "placeholder".to_string(),
+            false,
+            false,
+            hone::WriteOptions::new(),
+            false,
+            no_hooks,
+            None,
+            false,
+            false,
+            hone::KeyCollision::default(),
+            hone::EmitOptions::new(),
+            false,
+            false,
+            false,
+            None,
+            false,
+            false,
+            false,
+            false,
+            None,
+            false,
+        )?;
+    }
+    Ok(())
+}
+
+/// Load the nearest hone.toml at or above the current directory
+fn discover_manifest() -> hone::HoneResult<hone::manifest::Manifest> {
+    let cwd = std::env::current_dir()
+        .map_err(|e| hone::HoneError::io_error(format!("failed to get cwd: {}", e)))?;
+    hone::manifest::Manifest::discover(&cwd)?.ok_or_else(|| {
+        hone::HoneError::io_error(format!(
+            "no {} found in {} or any parent directory",
+            hone::manifest::MANIFEST_FILE,
+            cwd.display()
+        ))
+    })
+}
+
+/// Rebuild targets from the nearest hone.toml whenever a source file or the
+/// manifest changes, POSTing changed output to webhooks
+#[allow(clippy::too_many_arguments)]
+fn cmd_serve(
+    targets: Vec<String>,
+    all_targets: bool,
+    webhooks: Vec<String>,
+    webhook_sensitive: bool,
+    interval: u64,
+    once: bool,
+    quiet: bool,
+    no_hooks: bool,
+) -> hone::HoneResult<()> {
+    let webhooks = webhooks
+        .iter()
+        .map(|url| hone::serve::Webhook::parse(url))
+        .collect::<hone::HoneResult<Vec<_>>>()?;
+    let mut manifest = discover_manifest()?;
+    let select = |manifest: &hone::manifest::Manifest| -> hone::HoneResult<Vec<String>> {
+        if all_targets {
+            return Ok(manifest.targets.keys().cloned().collect());
+        }
+        for name in &targets {
+            manifest.target(name)?;
+        }
+        Ok(targets.clone())
+    };
+    let mut names = select(&manifest)?;
+
+    let mut watcher = hone::serve::Watcher::new(&manifest.root);
+    let mut builds = std::collections::HashMap::new();
+    loop {
+        for name in &names {
+            let result = serve_target(
+                &manifest,
+                name,
+                &mut builds,
+                &webhooks,
+                webhook_sensitive,
+                quiet,
+                no_hooks,
+            );
+            match result {
+                Err(e) if once => return Err(e),
+                Err(e) => report_error(e),
+                Ok(()) => {}
             }
-            return Err(hone::HoneError::compilation_error(format!(
-                "{} warning(s) treated as errors (--strict)",
-                warnings.len()
-            )));
         }
+        if once {
+            return Ok(());
+        }
+        watcher.set_outputs(
+            builds
+                .values()
+                .flat_map(|build| build.files.iter().map(|file| file.path.clone())),
+        );
+
+        if !quiet {
+            eprintln!("Watching {} for changes", manifest.root.display());
+        }
+        let changed = loop {
+            std::thread::sleep(std::time::Duration::from_millis(interval));
+            let changed = watcher.poll();
+            if !changed.is_empty() {
+                break changed;
+            }
+        };
         if !quiet {
-            for w in warnings {
-                eprintln!("warning{}: {}", format_warning_location(w), w.message);
+            for path in &changed {
+                eprintln!("Changed {}", path.display());
             }
         }
-    }
 
-    // Validate secrets mode prerequisites
-    if secrets_mode == "env" && !allow_env {
-        return Err(hone::HoneError::io_error(
-            "--secrets-mode=env requires --allow-env flag".to_string(),
-        ));
+        // Pick up edits to the manifest itself; keep serving the old one if
+        // the new one is broken
+        let manifest_path = manifest.root.join(hone::manifest::MANIFEST_FILE);
+        if changed.contains(&manifest_path) {
+            let reloaded = hone::manifest::Manifest::load(&manifest_path)
+                .and_then(|m| select(&m).map(|names| (m, names)));
+            match reloaded {
+                Ok((m, n)) => (manifest, names) = (m, n),
+                Err(e) => report_error(e),
+            }
+        }
     }
+}
 
-    // Apply secrets mode to each document
-    let documents: Vec<(Option<String>, hone::Value)> = documents
-        .into_iter()
-        .map(|(name, value)| {
-            let value = apply_secrets_mode(&value, secrets_mode)?;
-            Ok((name, value))
-        })
-        .collect::<hone::HoneResult<Vec<_>>>()?;
-
-    let ext = match format {
-        hone::OutputFormat::Yaml => "yaml",
-        hone::OutputFormat::Toml => "toml",
-        hone::OutputFormat::Dotenv => "env",
-        _ => "json",
-    };
+/// Render one target for `hone serve`; write the files that changed, then run
+/// hooks and notify webhooks
+fn serve_target(
+    manifest: &hone::manifest::Manifest,
+    name: &str,
+    builds: &mut std::collections::HashMap<String, hone::serve::RenderedTarget>,
+    webhooks: &[hone::serve::Webhook],
+    webhook_sensitive: bool,
+    quiet: bool,
+    no_hooks: bool,
+) -> hone::HoneResult<()> {
+    let rendered = hone::serve::render_target(manifest, name)?;
+    report_warnings(&rendered.warnings, false, &[], quiet)?;
 
-    if dry_run {
-        // Print all documents with separators
-        let mut first = true;
-        for (name, value) in documents.iter() {
-            if name.is_none() && value.is_empty_object() {
-                continue;
-            }
-            if !first {
-                println!("---");
-            }
-            first = false;
-            let result = hone::emit(value, format)?;
-            if let Some(doc_name) = name {
-                println!("# {}", doc_name);
-            }
-            println!("{}", result);
+    let previous = builds.get(name);
+    let changed = hone::serve::changed_files(previous, &rendered);
+    if changed.is_empty() {
+        if !quiet {
+            eprintln!("{} is unchanged", name);
         }
-    } else {
-        // Create output directory
-        std::fs::create_dir_all(output_dir).map_err(|e| {
-            hone::HoneError::io_error(format!(
-                "failed to create directory {}: {}",
-                output_dir.display(),
-                e
-            ))
-        })?;
+        return Ok(());
+    }
+    for file in &changed {
+        if let Some(dir) = file.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                hone::HoneError::io_error(format!(
+                    "failed to create directory {}: {}",
+                    dir.display(),
+                    e
+                ))
+            })?;
+        }
+        hone::write_output(&file.path, &file.text, &hone::WriteOptions::new())?;
+        if !quiet {
+            eprintln!("Wrote {}", file.path.display());
+        }
+    }
 
-        for (i, (name, value)) in documents.iter().enumerate() {
-            if name.is_none() && value.is_empty_object() {
-                continue;
+    if !no_hooks && !manifest.hooks.post_compile.is_empty() {
+        let target = manifest.target(name)?;
+        let ctx = hone::manifest::HookContext {
+            file: manifest.root.join(&target.entry),
+            outputs: changed.iter().map(|f| f.path.clone()).collect(),
+            output_dir: target.output_dir.as_ref().map(|d| manifest.root.join(d)),
+            format: match rendered.format {
+                hone::OutputFormat::JsonPretty => "json",
+                format => format.name(),
             }
+            .to_string(),
+        };
+        manifest.run_post_compile(&ctx)?;
+    }
 
-            let filename = match name {
-                Some(n) => format!("{}.{}", n, ext),
-                None if i == 0 => format!("main.{}", ext),
-                None => format!("doc{}.{}", i, ext),
-            };
-
-            let out_path = output_dir.join(&filename);
-            let result = hone::emit(value, format)?;
-
-            std::fs::write(&out_path, &result).map_err(|e| {
-                hone::HoneError::io_error(format!("failed to write {}: {}", out_path.display(), e))
-            })?;
-            if !quiet {
-                eprintln!("Wrote {}", out_path.display());
+    if !webhooks.is_empty() {
+        let payload = hone::serve::webhook_payload(name, previous, &rendered, webhook_sensitive);
+        for webhook in webhooks {
+            // A failing endpoint shouldn't stop the others or the build
+            match webhook.post(&payload) {
+                Ok(status) if (200..300).contains(&status) => {
+                    if !quiet {
+                        eprintln!("Notified {} ({})", webhook.url, status);
+                    }
+                }
+                Ok(status) => eprintln!("warning: webhook {} returned {}", webhook.url, status),
+                Err(e) => eprintln!("warning: {}", e),
             }
         }
     }
 
+    builds.insert(name.to_string(), rendered);
     Ok(())
 }
 
@@ -931,6 +3455,8 @@ fn cmd_check(
     file: PathBuf,
     set: Vec<(String, String)>,
     schema: Option<String>,
+    strict: bool,
+    deny: Vec<String>,
     allow_env: bool,
     variants: Vec<(String, String)>,
 ) -> hone::HoneResult<()> {
@@ -941,7 +3467,7 @@ fn cmd_check(
         std::env::current_dir()
             .map_err(|e| hone::HoneError::io_error(format!("failed to get cwd: {}", e)))?
     } else {
-        let canonical = file.canonicalize().map_err(|e| {
+        let canonical = hone::resolver::canonicalize(&file).map_err(|e| {
             hone::HoneError::io_error(format!("failed to resolve path {}: {}", file.display(), e))
         })?;
         canonical
@@ -971,17 +3497,16 @@ fn cmd_check(
             .map_err(|e| hone::HoneError::io_error(format!("failed to read stdin: {}", e)))?;
         compiler.compile_source(&source)?
     } else {
-        let canonical = file.canonicalize().map_err(|e| {
+        let canonical = hone::resolver::canonicalize(&file).map_err(|e| {
             hone::HoneError::io_error(format!("failed to resolve path {}: {}", file.display(), e))
         })?;
         compiler.compile(&canonical)?
     };
+    report_warnings(compiler.warnings(), strict, &deny, false)?;
 
     // If --schema is provided, validate against it explicitly
     if let Some(ref schema_name) = schema {
-        if !is_stdin {
-            hone::validate_against_schema(&file, &value, schema_name)?;
-        }
+        compiler.check_schema(&value, schema_name)?;
     }
 
     if is_stdin {
@@ -992,6 +3517,123 @@ fn cmd_check(
     Ok(())
 }
 
+/// `hone check --all`: check every file of the workspace in dependency
+/// order, reporting a failed file once and skipping the files built on it
+fn cmd_check_all(
+    root: Option<PathBuf>,
+    jobs: Option<usize>,
+    set: Vec<(String, String)>,
+    strict: bool,
+    deny: Vec<String>,
+    allow_env: bool,
+    variants: Vec<(String, String)>,
+) -> hone::HoneResult<()> {
+    let cwd = std::env::current_dir()
+        .map_err(|e| hone::HoneError::io_error(format!("failed to get cwd: {}", e)))?;
+    let root = match root {
+        Some(root) => root,
+        None => hone::manifest::Manifest::discover(&cwd)?
+            .map(|manifest| manifest.root)
+            .unwrap_or(cwd),
+    };
+    let root = hone::resolver::canonicalize(&root).map_err(|e| {
+        hone::HoneError::io_error(format!("failed to resolve path {}: {}", root.display(), e))
+    })?;
+    let files = hone::compiler::workspace::discover(&root)?;
+    if files.is_empty() {
+        eprintln!("No .hone files found under {}", root.display());
+        return Ok(());
+    }
+
+    let args = if set.is_empty() {
+        None
+    } else {
+        Some(hone::build_args_object(&set, &[], &[])?)
+    };
+    let variants: std::collections::HashMap<String, String> = variants.into_iter().collect();
+    let jobs = jobs.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+
+    let checked = hone::compiler::workspace::check_in_order(&files, jobs, |file| {
+        let mut compiler = hone::Compiler::new(file.parent().unwrap_or(&root));
+        compiler.set_allow_env(allow_env);
+        if !variants.is_empty() {
+            compiler.set_variants(variants.clone());
+        }
+        if let Some(ref args) = args {
+            compiler.set_args(args.clone());
+        }
+        let result = compiler.compile(file).map(|_| ());
+        let warnings = compiler.warnings().to_vec();
+        let denied = warnings
+            .iter()
+            .filter(|w| strict || deny.contains(&w.code))
+            .count();
+        let result = result.and_then(|()| match denied {
+            0 => Ok(()),
+            _ => Err(hone::HoneError::compilation_error(format!(
+                "{} warning(s) treated as errors ({})",
+                denied,
+                if strict { "--strict" } else { "--deny" }
+            ))),
+        });
+        (warnings, result)
+    });
+
+    let relative = |path: &std::path::Path| {
+        path.strip_prefix(&root)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+    let (mut ok, mut failed, mut skipped) = (0, 0, 0);
+    for file in checked {
+        // Warnings that fail the file are shown by the summary error
+        let _ = report_warnings(&file.warnings, strict, &deny, false);
+        match file.outcome {
+            hone::compiler::workspace::Outcome::Ok => {
+                ok += 1;
+                eprintln!("{}: OK", relative(&file.path));
+            }
+            hone::compiler::workspace::Outcome::Failed(e) => {
+                failed += 1;
+                eprintln!("{}: FAILED", relative(&file.path));
+                report_error(e);
+            }
+            hone::compiler::workspace::Outcome::Skipped(upstream) => {
+                skipped += 1;
+                let upstream: Vec<String> = upstream.iter().map(|p| relative(p)).collect();
+                eprintln!(
+                    "{}: skipped due to upstream error in {}",
+                    relative(&file.path),
+                    upstream.join(", ")
+                );
+            }
+        }
+    }
+
+    let total = ok + failed + skipped;
+    eprintln!(
+        "\nChecked {} file{}: {} ok, {} failed, {} skipped",
+        total,
+        if total == 1 { "" } else { "s" },
+        ok,
+        failed,
+        skipped
+    );
+    if failed + skipped > 0 {
+        return Err(hone::HoneError::compilation_error(format!(
+            "{} of {} files did not pass",
+            failed + skipped,
+            total
+        )));
+    }
+    Ok(())
+}
+
 fn cmd_fmt(files: Vec<PathBuf>, check: bool, diff: bool, write: bool) -> hone::HoneResult<()> {
     // Collect .hone files from arguments
     let mut all_files = Vec::new();
@@ -1033,9 +3675,7 @@ fn cmd_fmt(files: Vec<PathBuf>, check: bool, diff: bool, write: bool) -> hone::H
                 }
             } else {
                 // write mode
-                std::fs::write(file, &formatted).map_err(|e| {
-                    hone::HoneError::io_error(format!("failed to write {}: {}", file.display(), e))
-                })?;
+                hone::write_output(file, &formatted, &hone::WriteOptions::new())?;
                 eprintln!("Formatted {}", file.display());
             }
         } else {
@@ -1053,6 +3693,87 @@ fn cmd_fmt(files: Vec<PathBuf>, check: bool, diff: bool, write: bool) -> hone::H
     Ok(())
 }
 
+fn cmd_upgrade(paths: Vec<PathBuf>, to: String, dry_run: bool) -> hone::HoneResult<()> {
+    let paths = if paths.is_empty() {
+        vec![PathBuf::from(".")]
+    } else {
+        paths
+    };
+    let mut all_files = Vec::new();
+    for path in &paths {
+        if path.is_dir() {
+            collect_hone_files(path, &mut all_files)?;
+        } else {
+            all_files.push(path.clone());
+        }
+    }
+    all_files.sort();
+
+    let (mut upgraded, mut rewrites, mut manual, mut failed) = (0, 0, 0, 0);
+    for file in &all_files {
+        let source = std::fs::read_to_string(file).map_err(|e| {
+            hone::HoneError::io_error(format!("failed to read {}: {}", file.display(), e))
+        })?;
+        // Keep going so one broken file does not hide the rest
+        let upgrade = match hone::codemod::upgrade_source(&source, Some(file.clone()), &to) {
+            Ok(upgrade) => upgrade,
+            Err(e) => {
+                eprintln!("error: {}: {}", file.display(), e.message());
+                failed += 1;
+                continue;
+            }
+        };
+
+        for change in &upgrade.changes {
+            let site = format!("{}:{}:{}", file.display(), change.line, change.column);
+            match &change.manual {
+                None => eprintln!("{}: {}", site, change.migration),
+                Some(reason) => {
+                    eprintln!(
+                        "{}: {}: left for review: {}",
+                        site, change.migration, reason
+                    )
+                }
+            }
+        }
+        rewrites += upgrade.rewritten();
+        manual += upgrade.changes.len() - upgrade.rewritten();
+        if upgrade.source == source {
+            continue;
+        }
+        upgraded += 1;
+
+        if dry_run {
+            println!("--- {}", file.display());
+            println!("+++ {}", file.display());
+            for change in simple_diff(&source, &upgrade.source) {
+                println!("{}", change);
+            }
+        } else {
+            hone::write_output(file, &upgrade.source, &hone::WriteOptions::new())?;
+        }
+    }
+
+    eprintln!(
+        "{} {} file{} to edition {} ({} rewrite{}, {} left for review)",
+        if dry_run { "Would upgrade" } else { "Upgraded" },
+        upgraded,
+        if upgraded == 1 { "" } else { "s" },
+        to,
+        rewrites,
+        if rewrites == 1 { "" } else { "s" },
+        manual
+    );
+    if failed > 0 {
+        return Err(hone::HoneError::io_error(format!(
+            "{} file{} could not be parsed",
+            failed,
+            if failed == 1 { "" } else { "s" }
+        )));
+    }
+    Ok(())
+}
+
 /// Recursively collect all .hone files in a directory
 fn collect_hone_files(dir: &PathBuf, files: &mut Vec<PathBuf>) -> hone::HoneResult<()> {
     let entries = std::fs::read_dir(dir).map_err(|e| {
@@ -1108,6 +3829,26 @@ fn simple_diff(original: &str, formatted: &str) -> Vec<String> {
     changes
 }
 
+fn cmd_release_notes(
+    dir: PathBuf,
+    since: String,
+    until: Option<String>,
+    format: String,
+) -> hone::HoneResult<()> {
+    let notes = hone::differ::release::release_notes(&dir, &since, until.as_deref())?;
+    match format.as_str() {
+        "text" => print!("{}", notes.format_text()),
+        "markdown" | "md" => print!("{}", notes.format_markdown()),
+        other => {
+            return Err(hone::HoneError::io_error(format!(
+                "unknown release notes format '{}'. Use: text, markdown",
+                other
+            )))
+        }
+    }
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments)]
 fn cmd_diff(
     file: PathBuf,
@@ -1117,76 +3858,49 @@ fn cmd_diff(
     since: Option<String>,
     detect_moves: bool,
     blame: bool,
+    array_key: Option<String>,
     format: String,
 ) -> hone::HoneResult<()> {
-    let (left_value, right_value) = if let Some(ref git_ref) = since {
-        // Since mode: compile current file vs version at git ref
-        let canonical = file.canonicalize().map_err(|e| {
-            hone::HoneError::io_error(format!("failed to resolve path {}: {}", file.display(), e))
-        })?;
-        let old_value = hone::compile_at_ref(&canonical, git_ref)?;
-        let new_value = hone::compile_file(&file)?;
-        (old_value, new_value)
-    } else if let Some(ref git_ref) = base {
-        // Git mode: compare current file vs file at git ref
-        let old_source = std::process::Command::new("git")
-            .args(["show", &format!("{}:{}", git_ref, file.display())])
-            .output()
-            .map_err(|e| hone::HoneError::io_error(format!("failed to run git: {}", e)))?;
-
-        if !old_source.status.success() {
-            let stderr = String::from_utf8_lossy(&old_source.stderr);
-            return Err(hone::HoneError::io_error(format!(
-                "git show failed: {}",
-                stderr.trim()
-            )));
-        }
-
-        let old_src = String::from_utf8_lossy(&old_source.stdout).to_string();
-
-        // Compile old version via in-memory eval (no file imports)
-        let mut lexer = hone::Lexer::new(&old_src, Some(file.clone()));
-        let tokens = lexer.tokenize()?;
-        let mut parser = hone::Parser::new(tokens, &old_src, Some(file.clone()));
-        let ast = parser.parse()?;
-        let mut evaluator = hone::Evaluator::new(&old_src);
-        let old_value = evaluator.evaluate(&ast)?;
-
-        // Compile current version
-        let new_value = hone::compile_file(&file)?;
-
-        (old_value, new_value)
-    } else if left.is_some() || right.is_some() {
-        // Args mode: compare same file with two different arg sets
-        let left_args = hone::parse_arg_string(left.as_deref().unwrap_or(""));
-        let right_args = hone::parse_arg_string(right.as_deref().unwrap_or(""));
-
-        let left_value = if left_args.is_empty() {
-            hone::compile_file(&file)?
-        } else {
-            let args = hone::build_args_object(&left_args, &[], &[])?;
-            hone::compile_file_with_args(&file, args)?
-        };
+    let ((left_value, left_redactor), (right_value, right_redactor)) =
+        if let Some(rev) = base.as_ref().or(since.as_ref()) {
+            // Revision mode: compile the file as it was at `rev` vs as it is now
+            let old_source = hone::source_at_ref(&file, rev)?;
+            let old = compile_for_diff(&file, Some(&old_source), None)?;
+            let new = compile_for_diff(&file, None, None)?;
+            (old, new)
+        } else if left.is_some() || right.is_some() {
+            // Args mode: compare same file with two different arg sets
+            let left_args = hone::parse_arg_string(left.as_deref().unwrap_or(""));
+            let right_args = hone::parse_arg_string(right.as_deref().unwrap_or(""));
+
+            let left_args = if left_args.is_empty() {
+                None
+            } else {
+                Some(hone::build_args_object(&left_args, &[], &[])?)
+            };
+            let right_args = if right_args.is_empty() {
+                None
+            } else {
+                Some(hone::build_args_object(&right_args, &[], &[])?)
+            };
 
-        let right_value = if right_args.is_empty() {
-            hone::compile_file(&file)?
+            (
+                compile_for_diff(&file, None, left_args)?,
+                compile_for_diff(&file, None, right_args)?,
+            )
         } else {
-            let args = hone::build_args_object(&right_args, &[], &[])?;
-            hone::compile_file_with_args(&file, args)?
+            return Err(hone::HoneError::io_error(
+                "must specify either --base, --since, or --left/--right args".to_string(),
+            ));
         };
 
-        (left_value, right_value)
-    } else {
-        return Err(hone::HoneError::io_error(
-            "must specify either --base, --since, or --left/--right args".to_string(),
-        ));
-    };
-
-    let entries = if detect_moves {
-        hone::diff_with_moves(&left_value, &right_value)
-    } else {
-        hone::diff_values(&left_value, &right_value)
-    };
+    let options = hone::DiffOptions::new()
+        .with_array_key(array_key)
+        .with_detect_moves(detect_moves);
+    let entries = hone::diff_with_options(&left_value, &right_value, &options);
+    let mut redactor = left_redactor;
+    redactor.extend(&right_redactor);
+    let entries = hone::redact_entries(&entries, &redactor);
 
     if entries.is_empty() {
         eprintln!("No differences found");
@@ -1195,11 +3909,11 @@ fn cmd_diff(
 
     let output = if blame {
         let blamed = hone::blame_diff(&entries, &file);
-        hone::format_blame_text(&blamed)
+        hone::format_blame_text_with(&blamed, &stdout_style())
     } else if format == "json" {
         hone::format_diff_json(&entries)
     } else {
-        hone::format_diff_text(&entries)
+        hone::format_diff_text_with(&entries, &stdout_style())
     };
 
     print!("{}", output);
@@ -1208,6 +3922,27 @@ fn cmd_diff(
     std::process::exit(1);
 }
 
+/// Compile `file` for `hone diff` (or `source` standing in for it), with the
+/// masks for its `@sensitive` values
+fn compile_for_diff(
+    file: &std::path::Path,
+    source: Option<&str>,
+    args: Option<hone::Value>,
+) -> hone::HoneResult<(hone::Value, hone::Redactor)> {
+    let canonical = hone::resolver::canonicalize(file).map_err(|e| {
+        hone::HoneError::io_error(format!("failed to resolve path {}: {}", file.display(), e))
+    })?;
+    let mut compiler = hone::Compiler::new(canonical.parent().unwrap_or(std::path::Path::new(".")));
+    if let Some(args) = args {
+        compiler.set_args(args);
+    }
+    let value = match source {
+        Some(source) => compiler.compile_source(source)?,
+        None => compiler.compile(&canonical)?,
+    };
+    Ok((value, compiler.redactor().clone()))
+}
+
 fn cmd_import(
     file: PathBuf,
     output: Option<PathBuf>,
@@ -1224,9 +3959,7 @@ fn cmd_import(
 
     // Output
     if let Some(out_path) = output {
-        std::fs::write(&out_path, &hone_source).map_err(|e| {
-            hone::HoneError::io_error(format!("failed to write {}: {}", out_path.display(), e))
-        })?;
+        hone::write_output(&out_path, &hone_source, &hone::WriteOptions::new())?;
         eprintln!("Wrote {}", out_path.display());
     } else {
         println!("{}", hone_source);
@@ -1235,6 +3968,56 @@ fn cmd_import(
     Ok(())
 }
 
+fn cmd_freeze(
+    file: PathBuf,
+    output: Option<PathBuf>,
+    set: Vec<(String, String)>,
+    allow_env: bool,
+    variants: Vec<(String, String)>,
+) -> hone::HoneResult<()> {
+    let canonical = hone::resolver::canonicalize(&file).map_err(|e| {
+        hone::HoneError::io_error(format!("failed to resolve path {}: {}", file.display(), e))
+    })?;
+    let base_dir = canonical
+        .parent()
+        .unwrap_or(std::path::Path::new("."))
+        .to_path_buf();
+    let mut compiler = hone::Compiler::new(&base_dir);
+    compiler.set_allow_env(allow_env);
+    if !variants.is_empty() {
+        compiler.set_variants(variants.iter().cloned().collect());
+    }
+    if !set.is_empty() {
+        compiler.set_args(hone::build_args_object(&set, &[], &[])?);
+    }
+    let value = compiler.compile(&canonical)?;
+
+    // The inputs go in the header, so a sensitive one is masked there
+    let mut header = vec![format!("Frozen by `hone freeze` from {}", file.display())];
+    let inputs: Vec<String> = set
+        .iter()
+        .map(|(k, v)| format!("--set {}={}", k, v))
+        .chain(
+            variants
+                .iter()
+                .map(|(k, v)| format!("--variant {}={}", k, v)),
+        )
+        .collect();
+    if !inputs.is_empty() {
+        header.push(compiler.redactor().text(&inputs.join(" ")));
+    }
+    header.push("Edit the source and freeze it again rather than changing this file".to_string());
+    let frozen = hone::freeze::freeze(&value, compiler.doc_comments(), &header)?;
+
+    if let Some(out_path) = output {
+        hone::write_output(&out_path, &frozen, &hone::WriteOptions::new())?;
+        eprintln!("Wrote {}", out_path.display());
+    } else {
+        print!("{}", frozen);
+    }
+    Ok(())
+}
+
 fn cmd_lsp(_stdio: bool, socket: Option<u16>) -> hone::HoneResult<()> {
     // Only stdio is supported for now
     if socket.is_some() {
@@ -1249,6 +4032,38 @@ fn cmd_lsp(_stdio: bool, socket: Option<u16>) -> hone::HoneResult<()> {
     Ok(())
 }
 
+#[cfg(feature = "self-update")]
+fn cmd_self_update(check: bool, channel: String) -> hone::HoneResult<()> {
+    use hone::updater::{self, Channel, Version};
+
+    let channel = Channel::parse(&channel).unwrap_or(Channel::Stable);
+    let current = Version::current();
+    let release = match updater::latest_release(channel)? {
+        Some(release) if release.version > current => release,
+        _ => {
+            println!("hone {} is up to date", current);
+            return Ok(());
+        }
+    };
+    if check {
+        println!(
+            "hone {} is available (installed: {})",
+            release.version, current
+        );
+        return Ok(());
+    }
+
+    eprintln!("Downloading hone {}...", release.tag);
+    let exe = updater::install(&release)?;
+    println!(
+        "Updated {} from {} to {}",
+        exe.display(),
+        current,
+        release.version
+    );
+    Ok(())
+}
+
 fn cmd_lex(file: PathBuf) -> hone::HoneResult<()> {
     let source = std::fs::read_to_string(&file).map_err(|e| {
         hone::HoneError::io_error(format!("failed to read {}: {}", file.display(), e))
@@ -1301,6 +4116,9 @@ fn cmd_resolve(file: PathBuf) -> hone::HoneResult<()> {
         for import in &f.import_paths {
             println!("      import: {}", import.display());
         }
+        for include in &f.include_paths {
+            println!("      include: {}", include.display());
+        }
     }
 
     Ok(())
@@ -1355,26 +4173,120 @@ fn cmd_parse(file: PathBuf) -> hone::HoneResult<()> {
     Ok(())
 }
 
-fn cmd_typegen(file: PathBuf, output: Option<PathBuf>) -> hone::HoneResult<()> {
-    let result =
-        hone::typeprovider::generate_from_file(&file).map_err(hone::HoneError::io_error)?;
+#[allow(clippy::too_many_arguments)]
+fn cmd_typegen(
+    file: Option<PathBuf>,
+    openapi: Option<PathBuf>,
+    path: Option<String>,
+    crd: Option<PathBuf>,
+    proto: Option<PathBuf>,
+    messages: Vec<String>,
+    output: Option<PathBuf>,
+    check: bool,
+) -> hone::HoneResult<()> {
+    use hone::typeprovider::{self, DriftStatus};
+
+    if path.is_some() && openapi.is_none() {
+        return Err(hone::HoneError::io_error(
+            "--path can only be used with --openapi",
+        ));
+    }
+    if !messages.is_empty() && proto.is_none() {
+        return Err(hone::HoneError::io_error(
+            "--message can only be used with --proto",
+        ));
+    }
+
+    let (result, source) = match (file, openapi, crd, proto) {
+        (_, Some(openapi), _, _) => {
+            let source = match &path {
+                Some(pointer) => format!("{}{}", openapi.display(), pointer),
+                None => openapi.display().to_string(),
+            };
+            (
+                typeprovider::generate_from_openapi_file(&openapi, path.as_deref()),
+                source,
+            )
+        }
+        (_, _, Some(crd), _) => (
+            typeprovider::generate_from_crd_file(&crd),
+            crd.display().to_string(),
+        ),
+        (_, _, _, Some(proto)) => {
+            let source = if messages.is_empty() {
+                proto.display().to_string()
+            } else {
+                format!("{}#{}", proto.display(), messages.join("+"))
+            };
+            (
+                typeprovider::generate_from_descriptor_file(&proto, &messages),
+                source,
+            )
+        }
+        (Some(file), _, _, _) => (
+            typeprovider::generate_from_file(&file),
+            file.display().to_string(),
+        ),
+        (None, None, None, None) => unreachable!("clap requires a typegen input"),
+    };
+    let result = result.map_err(hone::HoneError::io_error)?;
+
+    if check {
+        let path = output.ok_or_else(|| {
+            hone::HoneError::io_error("--check requires --output (the generated file to check)")
+        })?;
+        let existing = std::fs::read_to_string(&path).map_err(|e| {
+            hone::HoneError::io_error(format!("failed to read {}: {}", path.display(), e))
+        })?;
+
+        let problem = match typeprovider::check_generated(&existing, &result) {
+            DriftStatus::UpToDate => return Ok(()),
+            DriftStatus::Drifted {
+                existing,
+                regenerated,
+            } => {
+                eprintln!("--- {}", path.display());
+                eprintln!("+++ {}", source);
+                for change in simple_diff(&existing, &regenerated) {
+                    eprintln!("{}", change);
+                }
+                format!("{} is out of date with {}", path.display(), source)
+            }
+            DriftStatus::ManuallyEdited => format!(
+                "{} has manual edits inside its generated region",
+                path.display()
+            ),
+            DriftStatus::Missing => {
+                format!("{} has no hone typegen region", path.display())
+            }
+        };
+        return Err(hone::HoneError::io_error(format!(
+            "{} (rerun `hone typegen` to regenerate)",
+            problem
+        )));
+    }
+
+    let region = typeprovider::wrap_generated(&result, &source);
 
     match output {
         Some(path) => {
-            std::fs::write(&path, &result).map_err(|e| {
-                hone::HoneError::io_error(format!("failed to write {}: {}", path.display(), e))
-            })?;
+            // Keep any hand-written content around an existing generated region
+            let content = match std::fs::read_to_string(&path) {
+                Ok(existing) => typeprovider::splice_generated(&existing, &region),
+                Err(_) => region,
+            };
+            hone::write_output(&path, &content, &hone::WriteOptions::new())?;
             eprintln!("Wrote {}", path.display());
         }
         None => {
-            print!("{}", result);
+            print!("{}", region);
         }
     }
 
     Ok(())
 }
 
-fn cmd_eval(source: String, format: String) -> hone::HoneResult<()> {
+fn cmd_eval(source: String, format: String, max_items: usize) -> hone::HoneResult<()> {
     // Lex
     let mut lexer = hone::Lexer::new(&source, None);
     let tokens = lexer.tokenize()?;
@@ -1387,13 +4299,16 @@ fn cmd_eval(source: String, format: String) -> hone::HoneResult<()> {
     let mut evaluator = hone::Evaluator::new(&source);
     let value = evaluator.evaluate(&ast)?;
 
+    if format == "pretty" {
+        let options = hone::PrettyOptions::new()
+            .with_color(stdout_style().color)
+            .with_max_items((max_items > 0).then_some(max_items));
+        println!("{}", hone::pretty_print(&value, &options));
+        return Ok(());
+    }
+
     // Determine output format
-    let output_format = hone::OutputFormat::parse(&format).ok_or_else(|| {
-        hone::HoneError::io_error(format!(
-            "unknown output format '{}'. Use: json, yaml, toml, dotenv",
-            format
-        ))
-    })?;
+    let output_format = parse_output_format(&format)?;
 
     // Emit
     let result = hone::emit(&value, output_format)?;
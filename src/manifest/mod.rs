@@ -0,0 +1,412 @@
+//! Project manifest (`hone.toml`)
+//!
+//! The manifest is discovered by walking up from the compiled file's
+//! directory, like `Cargo.toml`. It configures CLI hooks and named build
+//! targets for `hone build`:
+//!
+//! ```toml
+//! [hooks]
+//! post_compile = ["kubeconform -strict {output}"]
+//!
+//! [targets.prod-us-east]
+//! entry = "app.hone"
+//! variants = { env = "prod", region = "us-east" }
+//! output = "deploy/us-east.yaml"
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Deserialize;
+
+use crate::errors::{HoneError, HoneResult};
+
+/// File name of the project manifest
+pub const MANIFEST_FILE: &str = "hone.toml";
+
+/// Parsed `hone.toml`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// `[targets.<name>]` sections, by name
+    #[serde(default)]
+    pub targets: BTreeMap<String, Target>,
+    /// Directory containing the manifest; hooks run from here
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+/// The `[hooks]` section
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    /// Shell commands run after output is written successfully
+    #[serde(default)]
+    pub post_compile: Vec<String>,
+}
+
+/// A named build target: what `hone build <name>` compiles and where it goes.
+/// Paths are relative to the manifest's directory.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Target {
+    /// Source file to compile
+    pub entry: PathBuf,
+    /// Variant selections (name -> case)
+    #[serde(default)]
+    pub variants: BTreeMap<String, String>,
+    /// Args, with the same type inference as `--set`
+    #[serde(default)]
+    pub args: BTreeMap<String, String>,
+    /// Output format; defaults to the output file's extension
+    pub format: Option<String>,
+    /// Output file
+    pub output: Option<PathBuf>,
+    /// Directory for one file per `---name` document
+    pub output_dir: Option<PathBuf>,
+    /// File name template for the documents in `output_dir`, such as
+    /// `{document}-{variant.env}.{ext}`
+    pub output_name: Option<String>,
+    /// Allow env() and file()
+    #[serde(default)]
+    pub allow_env: bool,
+}
+
+/// Values substituted into hook commands
+#[derive(Debug, Clone, Default)]
+pub struct HookContext {
+    /// `{file}`: the compiled source file
+    pub file: PathBuf,
+    /// `{output}`: a written output file
+    pub outputs: Vec<PathBuf>,
+    /// `{output_dir}`: the `--output-dir` directory, if any
+    pub output_dir: Option<PathBuf>,
+    /// `{format}`: output format name (json, yaml, toml, dotenv)
+    pub format: String,
+}
+
+impl Manifest {
+    /// Parse a manifest from TOML source
+    pub fn parse(source: &str, root: impl Into<PathBuf>) -> HoneResult<Self> {
+        let mut manifest: Manifest = toml::from_str(source)
+            .map_err(|e| HoneError::io_error(format!("invalid {}: {}", MANIFEST_FILE, e)))?;
+        manifest.root = root.into();
+        for (name, target) in &manifest.targets {
+            if target.output.is_some() == target.output_dir.is_some() {
+                return Err(HoneError::io_error(format!(
+                    "invalid {}: target '{}' needs exactly one of `output` or `output_dir`",
+                    MANIFEST_FILE, name
+                )));
+            }
+            if target.output_name.is_some() && target.output_dir.is_none() {
+                return Err(HoneError::io_error(format!(
+                    "invalid {}: target '{}' sets `output_name` without `output_dir`",
+                    MANIFEST_FILE, name
+                )));
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Look up a build target by name
+    pub fn target(&self, name: &str) -> HoneResult<&Target> {
+        self.targets.get(name).ok_or_else(|| {
+            let known: Vec<_> = self.targets.keys().map(String::as_str).collect();
+            HoneError::io_error(format!(
+                "no target '{}' in {}{}",
+                name,
+                self.root.join(MANIFEST_FILE).display(),
+                if known.is_empty() {
+                    String::new()
+                } else {
+                    format!(" (targets: {})", known.join(", "))
+                }
+            ))
+        })
+    }
+
+    /// Load a manifest file
+    pub fn load(path: &Path) -> HoneResult<Self> {
+        let source = std::fs::read_to_string(path).map_err(|e| {
+            HoneError::io_error(format!("failed to read {}: {}", path.display(), e))
+        })?;
+        let root = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        Self::parse(&source, root)
+    }
+
+    /// Find and load the nearest `hone.toml` at or above `start`
+    pub fn discover(start: &Path) -> HoneResult<Option<Self>> {
+        match find_manifest(start) {
+            Some(path) => Self::load(&path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Run the `post_compile` hooks. A hook that uses `{output}` runs once per
+    /// output file; any other hook runs once. The first failure stops the rest.
+    pub fn run_post_compile(&self, ctx: &HookContext) -> HoneResult<()> {
+        for hook in &self.hooks.post_compile {
+            if hook.contains("{output}") {
+                for output in &ctx.outputs {
+                    self.run_hook(&expand_hook(hook, ctx, Some(output))?)?;
+                }
+            } else {
+                self.run_hook(&expand_hook(hook, ctx, None)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn run_hook(&self, command: &str) -> HoneResult<()> {
+        let status = shell(command)
+            .current_dir(&self.root)
+            .status()
+            .map_err(|e| HoneError::io_error(format!("failed to run hook `{}`: {}", command, e)))?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            let code = status
+                .code()
+                .map(|c| c.to_string())
+                .unwrap_or_else(|| "signal".to_string());
+            Err(HoneError::compilation_error(format!(
+                "post_compile hook failed (exit {}): {}",
+                code, command
+            )))
+        }
+    }
+}
+
+/// Walk up from `start` looking for `hone.toml`
+pub fn find_manifest(start: &Path) -> Option<PathBuf> {
+    let mut dir = Some(start);
+    while let Some(d) = dir {
+        let candidate = d.join(MANIFEST_FILE);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        dir = d.parent();
+    }
+    None
+}
+
+/// Substitute `{file}`, `{output}`, `{output_dir}`, and `{format}` in a hook
+/// command. Paths are shell-quoted. A placeholder this compile has no value
+/// for, such as `{output_dir}` after `-o`, is an error rather than being
+/// passed to the shell as is; other braces are left for the shell.
+pub fn expand_hook(command: &str, ctx: &HookContext, output: Option<&Path>) -> HoneResult<String> {
+    let mut expanded = String::new();
+    let mut rest = command;
+    while let Some(open) = rest.find('{') {
+        expanded.push_str(&rest[..open]);
+        let after = &rest[open..];
+        let placeholder = ["{file}", "{format}", "{output}", "{output_dir}"]
+            .into_iter()
+            .find(|p| after.starts_with(p));
+        let Some(placeholder) = placeholder else {
+            expanded.push('{');
+            rest = &after[1..];
+            continue;
+        };
+        let value = match placeholder {
+            "{file}" => Some(shell_quote(&ctx.file.display().to_string())),
+            "{format}" => Some(ctx.format.clone()),
+            "{output}" => output.map(|o| shell_quote(&o.display().to_string())),
+            _ => ctx
+                .output_dir
+                .as_ref()
+                .map(|d| shell_quote(&d.display().to_string())),
+        };
+        let value = value.ok_or_else(|| {
+            HoneError::compilation_error(format!(
+                "post_compile hook `{}` uses {}, which has no value here{}",
+                command,
+                placeholder,
+                if placeholder == "{output_dir}" {
+                    "; it is set only with --output-dir or a target's `output_dir`"
+                } else {
+                    ""
+                }
+            ))
+        })?;
+        expanded.push_str(&value);
+        rest = &after[placeholder.len()..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn shell_quote(s: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "/._-+:=@,".contains(c);
+    if !s.is_empty() && s.chars().all(safe) {
+        s.to_string()
+    } else if cfg!(windows) {
+        format!("\"{}\"", s)
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = Manifest::parse(
+            "[hooks]\npost_compile = [\"kubeconform -strict {output}\"]\n",
+            "/project",
+        )
+        .unwrap();
+        assert_eq!(
+            manifest.hooks.post_compile,
+            vec!["kubeconform -strict {output}"]
+        );
+        assert_eq!(manifest.root, PathBuf::from("/project"));
+
+        assert!(Manifest::parse("", ".")
+            .unwrap()
+            .hooks
+            .post_compile
+            .is_empty());
+        let err = Manifest::parse("[hooks]\npre_compile = []\n", ".").unwrap_err();
+        assert!(err.to_string().contains("pre_compile"));
+    }
+
+    #[test]
+    fn test_parse_targets() {
+        let manifest = Manifest::parse(
+            r#"
+[targets.prod-us-east]
+entry = "app.hone"
+variants = { env = "prod", region = "us-east" }
+args = { replicas = "3" }
+output = "deploy/us-east.yaml"
+
+[targets.all-docs]
+entry = "k8s.hone"
+format = "yaml"
+output_dir = "manifests"
+"#,
+            "/project",
+        )
+        .unwrap();
+        let names: Vec<_> = manifest.targets.keys().collect();
+        assert_eq!(names, ["all-docs", "prod-us-east"]);
+
+        let target = manifest.target("prod-us-east").unwrap();
+        assert_eq!(target.entry, PathBuf::from("app.hone"));
+        assert_eq!(target.variants["region"], "us-east");
+        assert_eq!(target.args["replicas"], "3");
+        assert!(target.format.is_none());
+
+        let err = manifest.target("staging").unwrap_err();
+        assert!(err.to_string().contains("targets: all-docs, prod-us-east"));
+
+        let err = Manifest::parse(
+            "[targets.x]
+entry = \"a.hone\"\n",
+            ".",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("exactly one of"));
+
+        let err = Manifest::parse(
+            "[targets.x]\nentry = \"a.hone\"\noutput = \"a.yaml\"\noutput_name = \"{document}.{ext}\"\n",
+            ".",
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("`output_name` without `output_dir`"));
+    }
+
+    #[test]
+    fn test_expand_hook() {
+        let ctx = HookContext {
+            file: PathBuf::from("app.hone"),
+            outputs: vec![],
+            output_dir: Some(PathBuf::from("out dir")),
+            format: "yaml".to_string(),
+        };
+        assert_eq!(
+            expand_hook(
+                "check {file} {output} --format {format}",
+                &ctx,
+                Some(Path::new("out/app.yaml"))
+            )
+            .unwrap(),
+            "check app.hone out/app.yaml --format yaml"
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            expand_hook("ls {output_dir}", &ctx, None).unwrap(),
+            "ls 'out dir'"
+        );
+        assert_eq!(
+            expand_hook("awk '{print}' {file}", &ctx, None).unwrap(),
+            "awk '{print}' app.hone"
+        );
+
+        let ctx = HookContext {
+            output_dir: None,
+            ..ctx
+        };
+        let err = expand_hook("yamllint {output_dir}", &ctx, None).unwrap_err();
+        assert!(err.to_string().contains("{output_dir}"), "{}", err);
+        assert!(err.to_string().contains("--output-dir"), "{}", err);
+    }
+
+    #[test]
+    fn test_discover_walks_up() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join(MANIFEST_FILE), "[hooks]\n").unwrap();
+        let nested = dir.path().join("envs/prod");
+        std::fs::create_dir_all(&nested).unwrap();
+
+        let manifest = Manifest::discover(&nested).unwrap().unwrap();
+        assert_eq!(manifest.root, dir.path());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_post_compile_per_output_and_failure() {
+        let dir = TempDir::new().unwrap();
+        let manifest = Manifest::parse(
+            "[hooks]\npost_compile = [\"echo {output} >> hooks.log\", \"echo done >> hooks.log\"]\n",
+            dir.path(),
+        )
+        .unwrap();
+        let ctx = HookContext {
+            outputs: vec![PathBuf::from("a.yaml"), PathBuf::from("b.yaml")],
+            ..Default::default()
+        };
+        manifest.run_post_compile(&ctx).unwrap();
+        let log = std::fs::read_to_string(dir.path().join("hooks.log")).unwrap();
+        assert_eq!(log, "a.yaml\nb.yaml\ndone\n");
+
+        let failing =
+            Manifest::parse("[hooks]\npost_compile = [\"exit 7\"]\n", dir.path()).unwrap();
+        let err = failing.run_post_compile(&ctx).unwrap_err();
+        assert!(err.to_string().contains("exit 7"));
+    }
+}
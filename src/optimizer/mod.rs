@@ -0,0 +1,670 @@
+//! Constant folding and dead-branch elimination
+//!
+//! An optional pass between parsing and evaluation. It folds operators,
+//! ternaries, `match`es and interpolations whose operands are literals, substitutes
+//! `let` bindings holding scalar literals, and prunes `when` branches whose
+//! conditions become constant. Variant lets are known once the variant
+//! selections are, so `when replicas > 1` inside a file with
+//! `variant env { ... }` resolves at this stage.
+//!
+//! Folding uses the evaluator itself, so a folded value is always what
+//! evaluation would have produced. Anything that fails to fold (a division by
+//! zero, a type error) is left for the evaluator to report at its location.
+//! `fn` bodies are left alone, since they resolve names at the call site;
+//! lambdas capture theirs where they are written, so their bodies are folded.
+
+use std::collections::HashMap;
+
+use crate::evaluator::{Evaluator, Value};
+use crate::lexer::token::SourceLocation;
+use crate::parser::ast::*;
+
+/// Fold constants and prune dead `when` branches in `file`, given the
+/// `--variant` selections it will be evaluated with
+pub fn optimize(file: &mut File, variants: &HashMap<String, String>) {
+    let mut optimizer = Optimizer {
+        scopes: vec![HashMap::new()],
+        variants,
+        folder: Evaluator::new(""),
+    };
+    optimizer.optimize_file(file);
+}
+
+/// A `when` chain after pruning
+enum Pruned {
+    /// No branch can run
+    Never,
+    /// This branch always runs
+    Always(Vec<BodyItem>),
+    /// The condition is only known at evaluation time
+    Chain(Box<WhenBlock>),
+}
+
+struct Optimizer<'a> {
+    /// Known bindings per scope; `None` shadows an outer constant
+    scopes: Vec<HashMap<String, Option<Value>>>,
+    variants: &'a HashMap<String, String>,
+    /// Evaluates literal-only expressions
+    folder: Evaluator,
+}
+
+impl Optimizer<'_> {
+    fn optimize_file(&mut self, file: &mut File) {
+        for item in &mut file.preamble {
+            self.preamble_item(item);
+        }
+        // Variant cases run after the whole preamble; their lets are visible
+        // in the enclosing scope
+        for item in &mut file.preamble {
+            if let PreambleItem::Variant(variant) = item {
+                self.variant(variant);
+            }
+        }
+        self.body_items(&mut file.body);
+
+        for doc in &mut file.documents {
+            self.scopes.push(HashMap::new());
+            for item in &mut doc.preamble {
+                self.preamble_item(item);
+            }
+            self.body_items(&mut doc.body);
+            self.scopes.pop();
+        }
+    }
+
+    fn preamble_item(&mut self, item: &mut PreambleItem) {
+        match item {
+            PreambleItem::Let(binding) => self.let_binding(binding),
+            PreambleItem::Secret(secret) => self.shadow(&secret.name),
+            PreambleItem::Enum(def) => self.shadow(&def.name),
+            PreambleItem::Expect(_) => self.shadow("args"),
+            _ => {}
+        }
+    }
+
+    fn variant(&mut self, variant: &mut VariantDefinition) {
+        let selected = match self.variants.get(&variant.name) {
+            Some(name) => variant.cases.iter().position(|c| &c.name == name),
+            None => variant.cases.iter().position(|c| c.is_default),
+        };
+        match selected {
+            Some(index) => self.body_items(&mut variant.cases[index].body),
+            None => {
+                // Which case runs is decided at evaluation (or it errors)
+                let mut names = Vec::new();
+                for case in &variant.cases {
+                    leaked_lets(&case.body, &mut names);
+                }
+                for name in names {
+                    self.shadow(&name);
+                }
+            }
+        }
+    }
+
+    fn let_binding(&mut self, binding: &mut LetBinding) {
+        self.expr(&mut binding.value);
+        if binding.pattern.is_some() {
+            // Destructured names are never folded; they only shadow
+            for name in binding.names() {
+                self.define(name, None);
+            }
+            return;
+        }
+        let value = literal_value(&binding.value);
+        self.define(&binding.name, value);
+    }
+
+    fn define(&mut self, name: &str, value: Option<Value>) {
+        self.scopes
+            .last_mut()
+            .expect("optimizer scope")
+            .insert(name.to_string(), value);
+    }
+
+    fn shadow(&mut self, name: &str) {
+        self.define(name, None);
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Value> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(value) = scope.get(name) {
+                return value.as_ref();
+            }
+        }
+        None
+    }
+
+    /// Optimize body items evaluated in the current scope
+    fn body_items(&mut self, items: &mut Vec<BodyItem>) {
+        let mut result = Vec::with_capacity(items.len());
+        for mut item in std::mem::take(items) {
+            match item {
+                BodyItem::When(when) => match self.when(when, false) {
+                    Pruned::Never => {}
+                    Pruned::Always(body) => result.extend(body),
+                    Pruned::Chain(when) => result.push(BodyItem::When(*when)),
+                },
+                _ => {
+                    self.body_item(&mut item);
+                    result.push(item);
+                }
+            }
+        }
+        *items = result;
+    }
+
+    /// Optimize body items in a scope of their own
+    fn scoped_body_items(&mut self, items: &mut Vec<BodyItem>) {
+        self.scopes.push(HashMap::new());
+        self.body_items(items);
+        self.scopes.pop();
+    }
+
+    fn body_item(&mut self, item: &mut BodyItem) {
+        match item {
+            BodyItem::KeyValue(kv) => {
+                if let Key::Computed(expr) = &mut kv.key {
+                    self.expr(expr);
+                }
+                self.expr(&mut kv.value);
+            }
+            BodyItem::Block(block) => self.scoped_body_items(&mut block.items),
+            BodyItem::Let(binding) => self.let_binding(binding),
+            BodyItem::For(for_loop) => self.for_loop(for_loop),
+            BodyItem::Spread(spread) => self.expr(&mut spread.expr),
+            // Pruned by body_items, which may replace it with its branch
+            BodyItem::When(_) => {}
+            // Assertion messages show the values of the names they mention
+            BodyItem::Assert(_) | BodyItem::Use(_) => {}
+            // The included file's lets may shadow any known name from here on
+            BodyItem::Include(_) => {
+                let known: Vec<String> =
+                    self.scopes.iter().flat_map(|s| s.keys().cloned()).collect();
+                let scope = self.scopes.last_mut().expect("a scope is always open");
+                for name in known {
+                    scope.insert(name, None);
+                }
+            }
+        }
+    }
+
+    /// Prune a `when` chain. Body context runs branch items in the enclosing
+    /// scope (their lets leak); array context only evaluates `key: value`
+    /// items, and ignores lets.
+    fn when(&mut self, mut when: WhenBlock, array: bool) -> Pruned {
+        self.expr(&mut when.condition);
+        if let Some(condition) = literal_value(&when.condition) {
+            if condition.is_truthy() {
+                self.branch(&mut when.body, array, false);
+                return Pruned::Always(when.body);
+            }
+            return match when.else_branch {
+                None => Pruned::Never,
+                Some(ElseBranch::Else(mut body, _)) => {
+                    self.branch(&mut body, array, false);
+                    Pruned::Always(body)
+                }
+                Some(ElseBranch::ElseWhen(next)) => self.when(*next, array),
+            };
+        }
+
+        self.branch(&mut when.body, array, true);
+        when.else_branch = match when.else_branch.take() {
+            None => None,
+            Some(ElseBranch::Else(mut body, loc)) => {
+                self.branch(&mut body, array, true);
+                Some(ElseBranch::Else(body, loc))
+            }
+            Some(ElseBranch::ElseWhen(next)) => {
+                let loc = next.location.clone();
+                self.scopes.push(HashMap::new());
+                let pruned = self.when(*next, array);
+                self.scopes.pop();
+                match pruned {
+                    Pruned::Never => None,
+                    Pruned::Always(body) => Some(ElseBranch::Else(body, loc)),
+                    Pruned::Chain(next) => Some(ElseBranch::ElseWhen(next)),
+                }
+            }
+        };
+        if !array {
+            // Whether these lets ran is only known at evaluation time
+            let mut leaked = Vec::new();
+            leaked_lets(&when.body, &mut leaked);
+            collect_branch_lets(&when.else_branch, &mut leaked);
+            for name in leaked {
+                self.shadow(&name);
+            }
+        }
+        Pruned::Chain(Box::new(when))
+    }
+
+    /// Optimize the items of one `when` branch. A branch that may not run is
+    /// optimized in a scope of its own; its lets are shadowed afterwards.
+    fn branch(&mut self, body: &mut Vec<BodyItem>, array: bool, conditional: bool) {
+        if array {
+            for item in body.iter_mut() {
+                if let BodyItem::KeyValue(kv) = item {
+                    self.expr(&mut kv.value);
+                }
+            }
+        } else if conditional {
+            self.scoped_body_items(body);
+        } else {
+            self.body_items(body);
+        }
+    }
+
+    fn for_loop(&mut self, for_loop: &mut ForLoop) {
+        self.expr(&mut for_loop.iterable);
+        self.scopes.push(HashMap::new());
+        match &for_loop.binding {
+            ForBinding::Single(name) => self.shadow(name),
+            ForBinding::Pair(a, b) | ForBinding::Indexed(a, b) => {
+                self.shadow(a);
+                self.shadow(b);
+            }
+        }
+        if let Some(filter) = &mut for_loop.filter {
+            self.expr(filter);
+        }
+        match &mut for_loop.body {
+            ForBody::Object(items) => self.body_items(items),
+            ForBody::Expr(expr) => self.expr(expr),
+            ForBody::Block(items, expr) => {
+                self.body_items(items);
+                self.expr(expr);
+            }
+        }
+        self.scopes.pop();
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Ident(name, loc) => {
+                if let Some(value) = self.lookup(name) {
+                    if let Some(literal) = literal_expr(value, loc) {
+                        *expr = literal;
+                    }
+                }
+                return;
+            }
+            Expr::String(s) => {
+                if s.as_literal().is_some() {
+                    return;
+                }
+                for part in &mut s.parts {
+                    if let StringPart::Interpolation(e) | StringPart::Formatted(e, _) = part {
+                        self.expr(e);
+                    }
+                }
+            }
+            Expr::Path(path) => {
+                for part in &mut path.parts {
+                    if let PathPart::Index(e) = part {
+                        self.expr(e);
+                    }
+                }
+                return;
+            }
+            Expr::Array(array) => {
+                self.array_elements(&mut array.elements);
+                return;
+            }
+            Expr::Object(obj) | Expr::Block(obj) => {
+                self.scoped_body_items(&mut obj.items);
+                return;
+            }
+            Expr::Binary(b) => {
+                self.expr(&mut b.left);
+                self.expr(&mut b.right);
+            }
+            Expr::Unary(u) => self.expr(&mut u.operand),
+            Expr::Call(call) => {
+                for arg in &mut call.args {
+                    self.expr(arg);
+                }
+                return;
+            }
+            Expr::Index(idx) => {
+                self.expr(&mut idx.base);
+                self.expr(&mut idx.index);
+                return;
+            }
+            Expr::Conditional(cond) => {
+                self.expr(&mut cond.condition);
+                if let Some(condition) = literal_value(&cond.condition) {
+                    let mut taken = if condition.is_truthy() {
+                        std::mem::replace(&mut *cond.then_branch, Expr::Null(cond.location.clone()))
+                    } else {
+                        std::mem::replace(&mut *cond.else_branch, Expr::Null(cond.location.clone()))
+                    };
+                    self.expr(&mut taken);
+                    *expr = taken;
+                    return;
+                }
+                self.expr(&mut cond.then_branch);
+                self.expr(&mut cond.else_branch);
+                return;
+            }
+            Expr::Match(m) => {
+                self.expr(&mut m.subject);
+                for arm in &mut m.arms {
+                    for pattern in &mut arm.patterns {
+                        self.expr(pattern);
+                    }
+                    self.expr(&mut arm.value);
+                }
+                if let Some(index) = literal_match_arm(m) {
+                    *expr = m.arms.swap_remove(index).value;
+                }
+                return;
+            }
+            Expr::Annotated(ann) => {
+                self.expr(&mut ann.expr);
+                return;
+            }
+            Expr::Paren(inner, _) => {
+                self.expr(inner);
+                if literal_value(inner).is_some() {
+                    let placeholder = Expr::Null(inner.location().clone());
+                    *expr = std::mem::replace(&mut **inner, placeholder);
+                }
+                return;
+            }
+            Expr::For(for_loop) => {
+                self.for_loop(for_loop);
+                return;
+            }
+            Expr::Lambda(lambda) => {
+                self.scopes.push(HashMap::new());
+                for param in &lambda.params {
+                    self.shadow(param);
+                }
+                self.expr(&mut lambda.body);
+                self.scopes.pop();
+                return;
+            }
+            Expr::When(when) => {
+                let location = when.location.clone();
+                *expr = match self.when((**when).clone(), false) {
+                    Pruned::Never => Expr::Null(location),
+                    Pruned::Always(body) => Expr::When(Box::new(WhenBlock {
+                        condition: Expr::Bool(true, location.clone()),
+                        body,
+                        else_branch: None,
+                        location,
+                    })),
+                    Pruned::Chain(when) => Expr::When(when),
+                };
+                return;
+            }
+            Expr::Null(_)
+            | Expr::Bool(..)
+            | Expr::Integer(..)
+            | Expr::Float(..)
+            | Expr::Duration(..)
+            | Expr::Size(..) => return,
+        }
+        self.fold(expr);
+    }
+
+    fn array_elements(&mut self, elements: &mut Vec<ArrayElement>) {
+        let mut result = Vec::with_capacity(elements.len());
+        for element in std::mem::take(elements) {
+            match element {
+                ArrayElement::Expr(mut e) => {
+                    self.expr(&mut e);
+                    result.push(ArrayElement::Expr(e));
+                }
+                ArrayElement::Spread(mut e) => {
+                    self.expr(&mut e);
+                    result.push(ArrayElement::Spread(e));
+                }
+                ArrayElement::For(mut for_loop) => {
+                    self.for_loop(&mut for_loop);
+                    result.push(ArrayElement::For(for_loop));
+                }
+                ArrayElement::When(when) => match self.when(when, true) {
+                    Pruned::Never => {}
+                    Pruned::Always(body) => {
+                        result.extend(body.into_iter().filter_map(|item| match item {
+                            BodyItem::KeyValue(kv) => Some(ArrayElement::Expr(kv.value)),
+                            _ => None,
+                        }))
+                    }
+                    Pruned::Chain(when) => result.push(ArrayElement::When(*when)),
+                },
+            }
+        }
+        *elements = result;
+    }
+
+    /// Replace an operator or interpolation whose operands are all literals
+    /// with its value
+    fn fold(&mut self, expr: &mut Expr) {
+        let foldable = match expr {
+            Expr::Binary(b) => {
+                literal_value(&b.left).is_some() && literal_value(&b.right).is_some()
+            }
+            Expr::Unary(u) => literal_value(&u.operand).is_some(),
+            Expr::String(s) => s.parts.iter().all(|part| match part {
+                StringPart::Literal(_) => true,
+                StringPart::Interpolation(e) | StringPart::Formatted(e, _) => {
+                    literal_value(e).is_some()
+                }
+            }),
+            _ => false,
+        };
+        if !foldable {
+            return;
+        }
+        if let Ok(value) = self.folder.eval_expr(expr) {
+            if let Some(literal) = literal_expr(&value, expr.location()) {
+                *expr = literal;
+            }
+        }
+    }
+}
+
+/// The value of a literal expression
+fn literal_value(expr: &Expr) -> Option<Value> {
+    match expr {
+        Expr::Null(_) => Some(Value::Null),
+        Expr::Bool(b, _) => Some(Value::Bool(*b)),
+        Expr::Integer(n, _) => Some(Value::Int(*n)),
+        Expr::Float(n, _) => Some(Value::Float(*n)),
+        Expr::Duration(ms, _) => Some(Value::Duration(*ms)),
+        Expr::Size(bytes, _) => Some(Value::Size(*bytes)),
+        Expr::String(s) => s.as_literal().map(Value::String),
+        _ => None,
+    }
+}
+
+/// The arm a `match` on a literal always takes, when every pattern up to it
+/// is a literal too
+fn literal_match_arm(m: &MatchExpr) -> Option<usize> {
+    let subject = literal_value(&m.subject)?;
+    for (index, arm) in m.arms.iter().enumerate() {
+        if arm.is_wildcard() {
+            return Some(index);
+        }
+        for pattern in &arm.patterns {
+            if literal_value(pattern)?.equals(&subject) {
+                return Some(index);
+            }
+        }
+    }
+    None
+}
+
+/// A literal expression for a scalar value
+fn literal_expr(value: &Value, loc: &SourceLocation) -> Option<Expr> {
+    let loc = loc.clone();
+    match value {
+        Value::Null => Some(Expr::Null(loc)),
+        Value::Bool(b) => Some(Expr::Bool(*b, loc)),
+        Value::Int(n) => Some(Expr::Integer(*n, loc)),
+        Value::Float(n) => Some(Expr::Float(*n, loc)),
+        Value::Duration(ms) => Some(Expr::Duration(*ms, loc)),
+        Value::Size(bytes) => Some(Expr::Size(*bytes, loc)),
+        Value::String(s) => Some(Expr::String(StringExpr {
+            parts: vec![StringPart::Literal(s.clone())],
+            location: loc,
+        })),
+        Value::Array(_) | Value::Object(_) | Value::Function(_) => None,
+    }
+}
+
+/// Names of lets that body items define in their enclosing scope, including
+/// those inside `when` branches (which do not open a scope)
+fn leaked_lets(items: &[BodyItem], names: &mut Vec<String>) {
+    for item in items {
+        match item {
+            BodyItem::Let(binding) => names.extend(binding.names().into_iter().map(String::from)),
+            BodyItem::When(when) => {
+                leaked_lets(&when.body, names);
+                collect_branch_lets(&when.else_branch, names);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_branch_lets(branch: &Option<ElseBranch>, names: &mut Vec<String>) {
+    match branch {
+        Some(ElseBranch::ElseWhen(when)) => {
+            leaked_lets(&when.body, names);
+            collect_branch_lets(&when.else_branch, names);
+        }
+        Some(ElseBranch::Else(body, _)) => leaked_lets(body, names),
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn optimized(source: &str, variants: &[(&str, &str)]) -> File {
+        let tokens = Lexer::new(source, None).tokenize().unwrap();
+        let mut file = Parser::new(tokens, source, None).parse().unwrap();
+        let variants = variants
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        optimize(&mut file, &variants);
+        file
+    }
+
+    /// Keys and values of the top-level `key: value` items
+    fn entries(file: &File) -> Vec<(String, String)> {
+        file.body
+            .iter()
+            .filter_map(|item| match item {
+                BodyItem::KeyValue(kv) => Some((
+                    match &kv.key {
+                        Key::Ident(k) | Key::String(k) => k.clone(),
+                        Key::Computed(e) => e.display(),
+                    },
+                    kv.value.display(),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_folds_literals_and_constant_lets() {
+        let file = optimized(
+            "let base = 8000\nlet name = \"api\"\nport: base + 80\nlabel: \"${name}-${base / 2}\"\nneg: -(2 * 3)\npick: base > 1 ? \"big\" : other\nlen: len(name)\n",
+            &[],
+        );
+        assert_eq!(
+            entries(&file),
+            vec![
+                ("port".into(), "8080".into()),
+                ("label".into(), "\"api-4000\"".into()),
+                ("neg".into(), "-6".into()),
+                ("pick".into(), "\"big\"".into()),
+                ("len".into(), "len(\"api\")".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_leaves_failing_folds_for_the_evaluator() {
+        let file = optimized("a: 1 / 0\nb: \"x\" - 1\n", &[]);
+        assert_eq!(
+            entries(&file),
+            vec![
+                ("a".into(), "1 / 0".into()),
+                ("b".into(), "\"x\" - 1".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_folds_match_on_a_literal() {
+        let file = optimized(
+            "let env = \"qa\"\na: match env {\n  \"prod\" => 5\n  \"staging\", \"qa\" => 2\n  _ => 1\n}\nb: match env { \"prod\" => 5 }\nc: match env { other => 3, _ => 1 }\n",
+            &[],
+        );
+        let values: Vec<String> = entries(&file).into_iter().map(|(_, v)| v).collect();
+        // No arm matches `b`, and `c` depends on a name known only at evaluation
+        assert_eq!(values, vec!["2", "<expr>", "<expr>"]);
+    }
+
+    #[test]
+    fn test_prunes_when_branches_for_selected_variant() {
+        let source = "variant env {\n  default dev {\n    let replicas = 1\n  }\n  prod {\n    let replicas = 5\n  }\n}\nwhen replicas > 1 {\n  ha: true\n} else when replicas == 1 {\n  single: true\n}\nn: replicas\n";
+        let prod = optimized(source, &[("env", "prod")]);
+        assert_eq!(
+            entries(&prod),
+            vec![("ha".into(), "true".into()), ("n".into(), "5".into())]
+        );
+        let dev = optimized(source, &[]);
+        assert_eq!(
+            entries(&dev),
+            vec![("single".into(), "true".into()), ("n".into(), "1".into())]
+        );
+    }
+
+    #[test]
+    fn test_unknown_conditions_shadow_their_lets() {
+        let file = optimized(
+            "let mode = \"a\"\nwhen args.flag {\n  let mode = \"b\"\n}\nm: mode\nitems: [for mode in [1] { mode }]\nblock {\n  let mode = other\n  inner: mode\n}\nafter: mode\n",
+            &[],
+        );
+        assert!(matches!(file.body[0], BodyItem::When(_)));
+        let entries = entries(&file);
+        assert_eq!(entries[0], ("m".into(), "mode".into()));
+        let BodyItem::Block(block) = &file.body[3] else {
+            panic!("expected block");
+        };
+        let BodyItem::KeyValue(inner) = &block.items[1] else {
+            panic!("expected key");
+        };
+        assert_eq!(inner.value.display(), "mode");
+        assert_eq!(entries[2], ("after".into(), "mode".into()));
+    }
+
+    #[test]
+    fn test_constant_false_when_without_else_is_removed() {
+        let file = optimized(
+            "let debug = false\nwhen debug {\n  level: \"trace\"\n}\nlist: [1, when debug { x: 2 }]\n",
+            &[],
+        );
+        assert_eq!(file.body.len(), 1);
+        let BodyItem::KeyValue(list) = &file.body[0] else {
+            panic!("expected key");
+        };
+        let Expr::Array(array) = &list.value else {
+            panic!("expected array");
+        };
+        assert_eq!(array.elements.len(), 1);
+    }
+}
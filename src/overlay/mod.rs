@@ -0,0 +1,340 @@
+//! Environment overlay generator for Hone
+//!
+//! Backs `hone overlay new`: creates a new environment file that inherits from
+//! an existing one via `from`, pre-populated with the keys the user wants to
+//! override, and optionally registers the environment as a new variant case.
+
+use std::path::{Component, Path, PathBuf};
+
+use indexmap::IndexMap;
+
+use crate::emitter::{emit, OutputFormat};
+use crate::errors::{HoneError, HoneResult};
+use crate::evaluator::Value;
+use crate::importer::{import_yaml, ImportOptions};
+use crate::parser::ast::{File, PreambleItem, VariantDefinition};
+
+/// List the top-level keys of a compiled base file, in output order
+pub fn top_level_keys(value: &Value) -> Vec<String> {
+    match value {
+        Value::Object(obj) => obj.keys().cloned().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Build an object containing only the given keys of `value`.
+///
+/// Keys may be dotted paths (`database.host`) to copy a single nested field.
+pub fn select_keys(value: &Value, keys: &[String]) -> HoneResult<Value> {
+    let mut selected = Value::Object(IndexMap::new());
+
+    for key in keys {
+        let path: Vec<&str> = key.split('.').collect();
+        let mut current = value;
+        for segment in &path {
+            current = match current {
+                Value::Object(obj) => obj.get(*segment),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                HoneError::compilation_error(format!("key '{}' not found in base output", key))
+            })?;
+        }
+        insert_path(&mut selected, &path, current.clone());
+    }
+
+    Ok(selected)
+}
+
+/// Insert `value` at `path` in `target`, creating intermediate objects
+fn insert_path(target: &mut Value, path: &[&str], value: Value) {
+    let Value::Object(obj) = target else {
+        return;
+    };
+    if path.len() == 1 {
+        obj.insert(path[0].to_string(), value);
+        return;
+    }
+    let child = obj
+        .entry(path[0].to_string())
+        .or_insert_with(|| Value::Object(IndexMap::new()));
+    if !matches!(child, Value::Object(_)) {
+        *child = Value::Object(IndexMap::new());
+    }
+    insert_path(child, &path[1..], value);
+}
+
+/// Render the source of a new overlay file.
+///
+/// `from_path` is written verbatim into the `from` statement, so it should
+/// already be relative to the overlay's directory.
+pub fn render_overlay(name: &str, from_path: &str, selected: &Value) -> HoneResult<String> {
+    let mut output = String::new();
+    output.push_str(&format!("# {} environment overlay\n", name));
+    output.push_str(&format!("from \"{}\"\n", from_path));
+
+    let is_empty = matches!(selected, Value::Object(obj) if obj.is_empty());
+    if !is_empty {
+        let yaml = emit(selected, OutputFormat::Yaml)?;
+        let body = import_yaml(&yaml, &ImportOptions::new())?;
+        output.push('\n');
+        output.push_str(body.trim_end());
+        output.push('\n');
+    }
+
+    Ok(output)
+}
+
+/// Compute the path of `target` relative to the directory `base`, in the
+/// `./file.hone` / `../dir/file.hone` form used by `from` and `import`.
+pub fn relative_from_path(base: &Path, target: &Path) -> String {
+    let base: Vec<Component> = base.components().collect();
+    let target: Vec<Component> = target.components().collect();
+    let common = base
+        .iter()
+        .zip(target.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut rel = PathBuf::new();
+    for _ in common..base.len() {
+        rel.push("..");
+    }
+    for component in &target[common..] {
+        rel.push(component.as_os_str());
+    }
+
+    let rel = rel.to_string_lossy().replace('\\', "/");
+    if rel.starts_with("../") {
+        rel
+    } else {
+        format!("./{}", rel)
+    }
+}
+
+/// Find the file that defines `variant`, starting at `path` and following its
+/// `from` chain. Returns `None` if no file in the chain defines it.
+pub fn find_variant_file(path: &Path, variant: &str) -> HoneResult<Option<PathBuf>> {
+    let mut current = path.to_path_buf();
+    let mut seen = Vec::new();
+
+    loop {
+        if seen.contains(&current) {
+            return Ok(None);
+        }
+        seen.push(current.clone());
+
+        let source = std::fs::read_to_string(&current).map_err(|e| {
+            HoneError::io_error(format!("failed to read {}: {}", current.display(), e))
+        })?;
+        let ast = parse(&source, &current)?;
+
+        if find_variant(&ast, variant).is_some() {
+            return Ok(Some(current));
+        }
+
+        let parent = ast.preamble.iter().find_map(|item| match item {
+            PreambleItem::From(from) => Some(from.parts_as_string()),
+            _ => None,
+        });
+        match parent {
+            Some(from) => {
+                let dir = current.parent().unwrap_or(Path::new("."));
+                current = dir.join(from);
+            }
+            None => return Ok(None),
+        }
+    }
+}
+
+/// Add a case named `case` to `variant` in `source`, returning the new source.
+///
+/// The new case copies the body of `template` if that case exists, otherwise
+/// the default case (or the first case), so every binding the rest of the
+/// file relies on stays defined.
+pub fn add_variant_case(
+    source: &str,
+    variant: &str,
+    case: &str,
+    template: Option<&str>,
+) -> HoneResult<String> {
+    let ast = parse(source, Path::new("<variant>"))?;
+    let def = find_variant(&ast, variant).ok_or_else(|| {
+        HoneError::compilation_error(format!("variant '{}' is not defined", variant))
+    })?;
+
+    if def.cases.iter().any(|c| c.name == case) {
+        return Err(HoneError::compilation_error(format!(
+            "variant '{}' already has a case '{}'",
+            variant, case
+        )));
+    }
+
+    let source_case = template
+        .and_then(|t| def.cases.iter().find(|c| c.name == t))
+        .or_else(|| def.cases.iter().find(|c| c.is_default))
+        .or_else(|| def.cases.first());
+
+    // The variant's span ends at its closing brace; insert on the line before it
+    let close = def.location.offset + def.location.length - 1;
+    let insert_at = source[..close].rfind('\n').map(|i| i + 1).unwrap_or(close);
+    let closing_line_is_blank = source[insert_at..close].trim().is_empty();
+
+    let new_case = match source_case {
+        Some(c) => {
+            let start = c.location.offset;
+            let text = source[start..start + c.location.length].trim_end();
+            let text = text
+                .strip_prefix("default")
+                .filter(|_| c.is_default)
+                .map(str::trim_start)
+                .unwrap_or(text);
+            let rest = text.strip_prefix(c.name.as_str()).unwrap_or(text);
+            let line_start = source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+            let indent = &source[line_start..start];
+            format!("{}{}{}\n", indent, case, rest)
+        }
+        None => format!("  {} {{\n  }}\n", case),
+    };
+
+    let mut output = String::with_capacity(source.len() + new_case.len());
+    if closing_line_is_blank {
+        output.push_str(&source[..insert_at]);
+        output.push_str(&new_case);
+        output.push_str(&source[insert_at..]);
+    } else {
+        // Single-line variant (`variant env { a { } }`): break before the brace
+        output.push_str(&source[..close]);
+        output.push('\n');
+        output.push_str(&new_case);
+        output.push_str(&source[close..]);
+    }
+    Ok(output)
+}
+
+fn find_variant<'a>(ast: &'a File, name: &str) -> Option<&'a VariantDefinition> {
+    ast.preamble.iter().find_map(|item| match item {
+        PreambleItem::Variant(v) if v.name == name => Some(v),
+        _ => None,
+    })
+}
+
+fn parse(source: &str, path: &Path) -> HoneResult<File> {
+    let mut lexer = crate::Lexer::new(source, Some(path.to_path_buf()));
+    let tokens = lexer.tokenize()?;
+    let mut parser = crate::Parser::new(tokens, source, Some(path.to_path_buf()));
+    parser.parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn obj(pairs: Vec<(&str, Value)>) -> Value {
+        Value::Object(pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    #[test]
+    fn test_select_top_level_and_dotted_keys() {
+        let base = obj(vec![
+            ("name", Value::from("api")),
+            ("replicas", Value::from(3i64)),
+            (
+                "database",
+                obj(vec![
+                    ("host", Value::from("db.prod")),
+                    ("port", Value::from(5432i64)),
+                ]),
+            ),
+        ]);
+
+        let selected = select_keys(
+            &base,
+            &["replicas".to_string(), "database.host".to_string()],
+        )
+        .unwrap();
+        assert_eq!(
+            selected,
+            obj(vec![
+                ("replicas", Value::from(3i64)),
+                ("database", obj(vec![("host", Value::from("db.prod"))])),
+            ])
+        );
+
+        let err = select_keys(&base, &["database.user".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("database.user"));
+    }
+
+    #[test]
+    fn test_render_overlay() {
+        let selected = obj(vec![
+            ("replicas", Value::from(3i64)),
+            ("database", obj(vec![("host", Value::from("db.prod"))])),
+        ]);
+        let source = render_overlay("staging", "./prod.hone", &selected).unwrap();
+
+        assert!(source.starts_with("# staging environment overlay\nfrom \"./prod.hone\"\n"));
+        assert!(source.contains("replicas: 3"));
+        assert!(source.contains("database {"));
+        assert!(source.contains("host: \"db.prod\""));
+
+        let empty = render_overlay("qa", "./prod.hone", &obj(vec![])).unwrap();
+        assert_eq!(empty, "# qa environment overlay\nfrom \"./prod.hone\"\n");
+    }
+
+    #[test]
+    fn test_relative_from_path() {
+        assert_eq!(
+            relative_from_path(Path::new("/a/envs"), Path::new("/a/envs/prod.hone")),
+            "./prod.hone"
+        );
+        assert_eq!(
+            relative_from_path(Path::new("/a/envs/new"), Path::new("/a/base/prod.hone")),
+            "../../base/prod.hone"
+        );
+    }
+
+    #[test]
+    fn test_add_variant_case_copies_default() {
+        let source = "variant env {\n  default dev {\n    let replicas = 1\n  }\n  production {\n    let replicas = 5\n  }\n}\n\nreplicas: replicas\n";
+        let updated = add_variant_case(source, "env", "staging", None).unwrap();
+        assert_eq!(
+            updated,
+            "variant env {\n  default dev {\n    let replicas = 1\n  }\n  production {\n    let replicas = 5\n  }\n  staging {\n    let replicas = 1\n  }\n}\n\nreplicas: replicas\n"
+        );
+
+        let from_prod = add_variant_case(source, "env", "staging", Some("production")).unwrap();
+        assert!(from_prod.contains("  staging {\n    let replicas = 5\n  }\n}"));
+        // The result must still parse
+        parse(&from_prod, Path::new("test.hone")).unwrap();
+    }
+
+    #[test]
+    fn test_add_variant_case_errors() {
+        let source = "variant env {\n  default dev {\n  }\n}\n";
+        let err = add_variant_case(source, "region", "eu", None).unwrap_err();
+        assert!(err.to_string().contains("variant 'region' is not defined"));
+        let err = add_variant_case(source, "env", "dev", None).unwrap_err();
+        assert!(err.to_string().contains("already has a case 'dev'"));
+    }
+
+    #[test]
+    fn test_find_variant_file_follows_from_chain() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("base.hone"),
+            "variant env {\n  default dev {\n  }\n}\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("prod.hone"), "from \"./base.hone\"\nx: 1\n").unwrap();
+
+        let found = find_variant_file(&dir.path().join("prod.hone"), "env").unwrap();
+        assert_eq!(found, Some(dir.path().join("./base.hone")));
+        assert_eq!(
+            find_variant_file(&dir.path().join("prod.hone"), "region").unwrap(),
+            None
+        );
+    }
+}
@@ -3,6 +3,7 @@
 //! The AST represents the parsed structure of a Hone source file.
 //! Each node carries source location information for error reporting.
 
+use crate::lexer::format_spec::FormatSpec;
 use crate::lexer::token::SourceLocation;
 
 /// A complete Hone file, potentially containing multiple documents
@@ -44,6 +45,8 @@ pub enum PreambleItem {
     Schema(SchemaDefinition),
     /// `type Name = base_type & constraint1 & constraint2`
     TypeAlias(TypeAliasDefinition),
+    /// `enum Name { a, b, c }`
+    Enum(EnumDefinition),
     /// `use schema_name`
     Use(UseStatement),
     /// `variant name { ... }`
@@ -75,16 +78,49 @@ pub enum BodyItem {
     Let(LetBinding),
     /// Spread: `...expr`
     Spread(SpreadExpr),
+    /// `use schema_name` inside a top-level `when` or variant case
+    Use(UseStatement),
+    /// `include "path"`: another file's body items spliced into this block
+    Include(IncludeStatement),
 }
 
-/// Let binding: `let name = expr`
+/// Let binding: `let name = expr`, or a destructuring
+/// `let { a, b } = expr` / `let [first, ...rest] = expr`
 #[derive(Debug, Clone, PartialEq)]
 pub struct LetBinding {
+    /// Bound name; empty when `pattern` is set
     pub name: String,
+    pub pattern: Option<LetPattern>,
     pub value: Expr,
     pub location: SourceLocation,
 }
 
+impl LetBinding {
+    /// Every name this binding introduces, in source order
+    pub fn names(&self) -> Vec<&str> {
+        match &self.pattern {
+            None => vec![self.name.as_str()],
+            Some(LetPattern::Object(names)) => names.iter().map(String::as_str).collect(),
+            Some(LetPattern::Array { names, rest }) => names
+                .iter()
+                .chain(rest.iter())
+                .map(String::as_str)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LetPattern {
+    /// Object keys bound to same-named variables: `let { host, port } = ...`
+    Object(Vec<String>),
+    /// Leading elements, plus the remainder: `let [first, ...rest] = ...`
+    Array {
+        names: Vec<String>,
+        rest: Option<String>,
+    },
+}
+
 /// From statement: `from "path" [as alias]`
 #[derive(Debug, Clone, PartialEq)]
 pub struct FromStatement {
@@ -101,12 +137,21 @@ impl FromStatement {
             .iter()
             .filter_map(|p| match p {
                 StringPart::Literal(s) => Some(s.as_str()),
-                StringPart::Interpolation(_) => None,
+                StringPart::Interpolation(_) | StringPart::Formatted(..) => None,
             })
             .collect()
     }
 }
 
+/// Include statement: `include "path"`
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncludeStatement {
+    pub path: StringExpr,
+    /// Canonical path of the included file, set by the resolver
+    pub resolved: Option<std::path::PathBuf>,
+    pub location: SourceLocation,
+}
+
 /// Import statement variants
 #[derive(Debug, Clone, PartialEq)]
 pub struct ImportStatement {
@@ -121,7 +166,7 @@ pub enum ImportKind {
         path: StringExpr,
         alias: Option<String>,
     },
-    /// `import { a, b as c } from "path"`
+    /// `import { a, b as c, schema S } from "path"`
     Named {
         names: Vec<ImportName>,
         path: StringExpr,
@@ -132,6 +177,8 @@ pub enum ImportKind {
 pub struct ImportName {
     pub name: String,
     pub alias: Option<String>,
+    /// `schema Name`: imports a schema definition instead of a value
+    pub is_schema: bool,
     pub location: SourceLocation,
 }
 
@@ -166,20 +213,41 @@ pub struct TypeAliasDefinition {
 /// Type expression: can be a simple type, union, or intersection with constraints
 #[derive(Debug, Clone, PartialEq)]
 pub enum TypeExpr {
-    /// Named type with optional args (e.g., "int", "int(1, 65535)", "Port")
-    Named { name: String, args: Vec<Expr> },
+    /// Named type with optional args (e.g., "int", "int(1, 65535)", "Port",
+    /// `string(pattern: "^[a-z]+$")`)
+    Named {
+        name: String,
+        args: Vec<Expr>,
+        named_args: Vec<NamedTypeArg>,
+    },
     /// Array type (e.g., "array<string>")
     Array(Box<TypeExpr>),
     /// Optional type (e.g., "int?")
     Optional(Box<TypeExpr>),
     /// Union type (e.g., "int | string")
     Union(Vec<TypeExpr>),
+    /// String literal type (e.g., `"tcp"` in `"tcp" | "udp"`)
+    StringLiteral(String),
 }
 
-/// Use statement: `use schema_name`
+/// Use statement: `use schema_name` or `use alias.SchemaName`, optionally
+/// `partial use` in a base file that leaves required fields to its children
 #[derive(Debug, Clone, PartialEq)]
 pub struct UseStatement {
+    /// Schema name, dotted (`alias.Name`) when namespaced by a whole import
     pub schema_name: String,
+    /// Required fields may be missing here; files built `from` this one must
+    /// supply them
+    pub partial: bool,
+    pub location: SourceLocation,
+}
+
+/// Enum definition: `enum Env { dev, staging, prod }`, a closed set of
+/// string values
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumDefinition {
+    pub name: String,
+    pub values: Vec<String>,
     pub location: SourceLocation,
 }
 
@@ -187,6 +255,8 @@ pub struct UseStatement {
 #[derive(Debug, Clone, PartialEq)]
 pub struct VariantDefinition {
     pub name: String,
+    /// Enum the case names are drawn from: `variant env: Env { ... }`
+    pub enum_name: Option<String>,
     pub cases: Vec<VariantCase>,
     pub location: SourceLocation,
 }
@@ -205,7 +275,7 @@ pub struct VariantCase {
 pub struct ExpectDeclaration {
     /// The full dotted path, e.g. ["args", "env"]
     pub path: Vec<String>,
-    /// The expected type name (string, int, bool, float)
+    /// The expected type name (string, int, bool, float, or an enum)
     pub type_name: String,
     /// Optional default value
     pub default: Option<Expr>,
@@ -261,6 +331,10 @@ pub struct KeyValue {
     pub key: Key,
     pub op: AssignOp,
     pub value: Expr,
+    /// Declared `final`: no other assignment may set this key
+    pub is_final: bool,
+    /// Text of the `##` doc comment lines directly above the key
+    pub doc: Option<String>,
     pub location: SourceLocation,
 }
 
@@ -291,6 +365,10 @@ pub enum AssignOp {
 pub struct Block {
     pub name: String,
     pub items: Vec<BodyItem>,
+    /// Declared `final`: no other assignment may set this key or below it
+    pub is_final: bool,
+    /// Text of the `##` doc comment lines directly above the block
+    pub doc: Option<String>,
     pub location: SourceLocation,
 }
 
@@ -317,6 +395,8 @@ pub enum ElseBranch {
 pub struct ForLoop {
     pub binding: ForBinding,
     pub iterable: Expr,
+    /// `where` clause: iterations where this is falsy produce nothing
+    pub filter: Option<Box<Expr>>,
     pub body: ForBody,
     pub location: SourceLocation,
 }
@@ -327,6 +407,8 @@ pub enum ForBinding {
     Single(String),
     /// Destructuring: `for (k, v) in ...`
     Pair(String, String),
+    /// Element and position: `for x, i in ...`
+    Indexed(String, String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -366,6 +448,10 @@ pub enum Expr {
     Integer(i64, SourceLocation),
     /// Float literal
     Float(f64, SourceLocation),
+    /// Duration literal, in milliseconds: `5m`, `2h30m`
+    Duration(i64, SourceLocation),
+    /// Size literal, in bytes: `512Mi`, `100MB`
+    Size(i64, SourceLocation),
     /// String literal (may contain interpolations)
     String(StringExpr),
     /// Identifier
@@ -394,6 +480,13 @@ pub enum Expr {
     For(Box<ForLoop>),
     /// When expression (in array/object context)
     When(Box<WhenBlock>),
+    /// Match expression: `match env { "prod" => 3, _ => 1 }`
+    Match(Box<MatchExpr>),
+    /// Reusable body: `block { key +: value }`. Spliced with `...name`, its
+    /// items merge with their own operators; the location starts at `block`.
+    Block(ObjectExpr),
+    /// Anonymous function: `(x, y) => expr`
+    Lambda(LambdaExpr),
 }
 
 impl Expr {
@@ -404,6 +497,8 @@ impl Expr {
             Expr::Bool(_, loc) => loc,
             Expr::Integer(_, loc) => loc,
             Expr::Float(_, loc) => loc,
+            Expr::Duration(_, loc) => loc,
+            Expr::Size(_, loc) => loc,
             Expr::String(s) => &s.location,
             Expr::Ident(_, loc) => loc,
             Expr::Path(p) => &p.location,
@@ -418,6 +513,9 @@ impl Expr {
             Expr::Paren(_, loc) => loc,
             Expr::For(f) => &f.location,
             Expr::When(w) => &w.location,
+            Expr::Match(m) => &m.location,
+            Expr::Block(b) => &b.location,
+            Expr::Lambda(l) => &l.location,
         }
     }
 
@@ -428,6 +526,8 @@ impl Expr {
             Expr::Bool(b, _) => b.to_string(),
             Expr::Integer(n, _) => n.to_string(),
             Expr::Float(f, _) => f.to_string(),
+            Expr::Duration(ms, _) => crate::lexer::duration::format(*ms),
+            Expr::Size(bytes, _) => crate::lexer::size::format(*bytes),
             Expr::String(s) => {
                 let mut result = String::from("\"");
                 for part in &s.parts {
@@ -438,6 +538,13 @@ impl Expr {
                             result.push_str(&expr.display());
                             result.push('}');
                         }
+                        StringPart::Formatted(expr, spec) => {
+                            result.push_str("${");
+                            result.push_str(&expr.display());
+                            result.push(':');
+                            result.push_str(&spec.to_string());
+                            result.push('}');
+                        }
                     }
                 }
                 result.push('"');
@@ -516,6 +623,7 @@ impl Expr {
                 c.then_branch.collect_variables_inner(vars);
                 c.else_branch.collect_variables_inner(vars);
             }
+            Expr::Match(m) => m.subject.collect_variables_inner(vars),
             _ => {}
         }
     }
@@ -546,6 +654,8 @@ pub enum StringPart {
     Literal(String),
     /// Interpolated expression: `${expr}`
     Interpolation(Expr),
+    /// Interpolated expression with a format spec: `${expr:.2}`
+    Formatted(Expr, FormatSpec),
 }
 
 /// Path expression: `a.b.c`
@@ -618,6 +728,8 @@ pub enum BinaryOp {
     Or,
     // Null coalescing
     NullCoalesce,
+    // Pipeline: the left value becomes the first argument of the right call
+    Pipe,
 }
 
 impl std::fmt::Display for BinaryOp {
@@ -637,6 +749,7 @@ impl std::fmt::Display for BinaryOp {
             BinaryOp::And => write!(f, "&&"),
             BinaryOp::Or => write!(f, "||"),
             BinaryOp::NullCoalesce => write!(f, "??"),
+            BinaryOp::Pipe => write!(f, "|>"),
         }
     }
 }
@@ -658,9 +771,10 @@ impl BinaryOp {
             BinaryOp::And => 2,
             BinaryOp::Eq | BinaryOp::NotEq => 3,
             BinaryOp::Lt | BinaryOp::Gt | BinaryOp::LtEq | BinaryOp::GtEq => 4,
-            BinaryOp::NullCoalesce => 5,
-            BinaryOp::Add | BinaryOp::Sub => 6,
-            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 7,
+            BinaryOp::Pipe => 5,
+            BinaryOp::NullCoalesce => 6,
+            BinaryOp::Add | BinaryOp::Sub => 7,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 8,
         }
     }
 }
@@ -679,6 +793,14 @@ pub enum UnaryOp {
     Neg,
 }
 
+/// Anonymous function
+#[derive(Debug, Clone, PartialEq)]
+pub struct LambdaExpr {
+    pub params: Vec<String>,
+    pub body: Box<Expr>,
+    pub location: SourceLocation,
+}
+
 /// Function call
 #[derive(Debug, Clone, PartialEq)]
 pub struct CallExpr {
@@ -704,6 +826,30 @@ pub struct ConditionalExpr {
     pub location: SourceLocation,
 }
 
+/// Match expression: `match subject { "a", "b" => x, _ => y }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchExpr {
+    pub subject: Box<Expr>,
+    pub arms: Vec<MatchArm>,
+    pub location: SourceLocation,
+}
+
+/// One arm of a match: the values it covers and the result
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    /// Values compared with the subject; empty for the `_` arm, which
+    /// covers every value
+    pub patterns: Vec<Expr>,
+    pub value: Expr,
+    pub location: SourceLocation,
+}
+
+impl MatchArm {
+    pub fn is_wildcard(&self) -> bool {
+        self.patterns.is_empty()
+    }
+}
+
 /// Type-annotated expression: `value @type(args)`
 #[derive(Debug, Clone, PartialEq)]
 pub struct AnnotatedExpr {
@@ -717,5 +863,16 @@ pub struct AnnotatedExpr {
 pub struct TypeConstraint {
     pub name: String,
     pub args: Vec<Expr>,
+    /// `name: value` arguments, after the positional ones
+    pub named_args: Vec<NamedTypeArg>,
+    pub location: SourceLocation,
+}
+
+/// A `name: value` argument to a type: `pattern: "^[a-z]+$"` in
+/// `string(pattern: "^[a-z]+$")`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedTypeArg {
+    pub name: String,
+    pub value: Expr,
     pub location: SourceLocation,
 }
@@ -4,7 +4,10 @@
 //! from a token stream. The parser is LL(1) with one token lookahead.
 
 pub mod ast;
+pub mod shift;
+pub mod visit;
 
+use crate::cancel::CancelToken;
 use crate::errors::{HoneError, HoneResult};
 use crate::lexer::token::{SourceLocation, Token, TokenKind};
 use ast::*;
@@ -23,6 +26,8 @@ pub struct Parser {
     source: String,
     /// Current recursion depth
     depth: usize,
+    /// Stops parsing once cancelled
+    cancel: Option<CancelToken>,
 }
 
 impl Parser {
@@ -33,9 +38,16 @@ impl Parser {
             pos: 0,
             source: source.into(),
             depth: 0,
+            cancel: None,
         }
     }
 
+    /// Stop with [`HoneError::Cancelled`] once `token` is cancelled
+    pub fn with_cancel(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
     /// Parse the entire file
     pub fn parse(&mut self) -> HoneResult<File> {
         let start_loc = self.current_location();
@@ -47,7 +59,7 @@ impl Parser {
         self.skip_newlines();
 
         // Parse preamble and body of main document
-        self.parse_document_content(&mut preamble, &mut body)?;
+        self.parse_document_content(&mut preamble, &mut body, false)?;
 
         // Parse additional documents
         while self.check(&TokenKind::DocSeparator) {
@@ -90,7 +102,7 @@ impl Parser {
         let mut preamble = Vec::new();
         let mut body = Vec::new();
 
-        self.parse_document_content(&mut preamble, &mut body)?;
+        self.parse_document_content(&mut preamble, &mut body, false)?;
 
         let end_loc = if body.is_empty() && preamble.is_empty() {
             start_loc.clone()
@@ -106,15 +118,33 @@ impl Parser {
         })
     }
 
+    /// Parse the top-level items of part of a file, as [`Parser::parse`]
+    /// would after the items before it; `in_body` says whether one of those
+    /// was a body item. Used to re-parse only the edited items of a document.
+    pub fn parse_region(
+        &mut self,
+        in_body: bool,
+    ) -> HoneResult<(Vec<PreambleItem>, Vec<BodyItem>)> {
+        let mut preamble = Vec::new();
+        let mut body = Vec::new();
+        self.skip_newlines();
+        self.parse_document_content(&mut preamble, &mut body, in_body)?;
+        self.skip_newlines();
+        if !self.is_at_end() {
+            return Err(self.error_unexpected("end of file"));
+        }
+        Ok((preamble, body))
+    }
+
     /// Parse the content of a document (preamble and body items)
     fn parse_document_content(
         &mut self,
         preamble: &mut Vec<PreambleItem>,
         body: &mut Vec<BodyItem>,
+        mut in_body: bool,
     ) -> HoneResult<()> {
         // First parse preamble items (let, from, import, schema, use)
         // Then switch to body items once we see body content
-        let mut in_body = false;
 
         while !self.is_at_end() && !self.check(&TokenKind::DocSeparator) {
             self.skip_newlines();
@@ -137,6 +167,13 @@ impl Parser {
         Ok(())
     }
 
+    fn check_cancel(&self) -> HoneResult<()> {
+        match &self.cancel {
+            Some(token) => token.check(),
+            None => Ok(()),
+        }
+    }
+
     /// Check if current position starts a preamble item
     fn is_preamble_item(&self) -> bool {
         match &self.current().kind {
@@ -147,6 +184,9 @@ impl Parser {
             | TokenKind::Use
             | TokenKind::Secret
             | TokenKind::Fn => true,
+            // `enum` is contextual, so `enum: [...]` stays an ordinary key
+            TokenKind::Ident(id) if id == "enum" => self.peek_is(&TokenKind::Ident(String::new())),
+            TokenKind::Ident(id) if id == "partial" => self.peek_is(&TokenKind::Use),
             // These are preamble items only if NOT followed by `:` (which would mean key usage)
             TokenKind::Schema
             | TokenKind::Type
@@ -163,6 +203,7 @@ impl Parser {
 
     /// Parse a preamble item
     fn parse_preamble_item(&mut self) -> HoneResult<PreambleItem> {
+        self.check_cancel()?;
         match &self.current().kind {
             TokenKind::Let => Ok(PreambleItem::Let(self.parse_let()?)),
             TokenKind::From => Ok(PreambleItem::From(self.parse_from()?)),
@@ -170,17 +211,20 @@ impl Parser {
             TokenKind::Schema => Ok(PreambleItem::Schema(self.parse_schema()?)),
             TokenKind::Type => Ok(PreambleItem::TypeAlias(self.parse_type_alias()?)),
             TokenKind::Use => Ok(PreambleItem::Use(self.parse_use()?)),
+            TokenKind::Ident(id) if id == "partial" => Ok(PreambleItem::Use(self.parse_use()?)),
             TokenKind::Variant => Ok(PreambleItem::Variant(self.parse_variant()?)),
             TokenKind::Expect => Ok(PreambleItem::Expect(self.parse_expect()?)),
             TokenKind::Secret => Ok(PreambleItem::Secret(self.parse_secret()?)),
             TokenKind::Policy => Ok(PreambleItem::Policy(self.parse_policy()?)),
             TokenKind::Fn => Ok(PreambleItem::FnDef(self.parse_fn_def()?)),
-            _ => Err(self.error_unexpected("preamble item (let, from, import, schema, type, use, variant, expect, secret, policy, fn)")),
+            TokenKind::Ident(id) if id == "enum" => Ok(PreambleItem::Enum(self.parse_enum()?)),
+            _ => Err(self.error_unexpected("preamble item (let, from, import, schema, type, enum, use, variant, expect, secret, policy, fn)")),
         }
     }
 
     /// Parse a body item
     fn parse_body_item(&mut self) -> HoneResult<BodyItem> {
+        self.check_cancel()?;
         // Check for stray comma (common mistake in block syntax)
         if self.check(&TokenKind::Comma) {
             let loc = self.current_location();
@@ -197,6 +241,15 @@ impl Parser {
             TokenKind::When => Ok(BodyItem::When(self.parse_when()?)),
             TokenKind::For => Ok(BodyItem::For(self.parse_for()?)),
             TokenKind::Assert => Ok(BodyItem::Assert(self.parse_assert()?)),
+            TokenKind::Use => Ok(BodyItem::Use(self.parse_use()?)),
+            // `include` is only a keyword before a path, so it stays usable as a key
+            TokenKind::Ident(id)
+                if id == "include"
+                    && (self.peek_is(&TokenKind::String(String::new()))
+                        || self.peek_is(&TokenKind::StringStart(String::new()))) =>
+            {
+                Ok(BodyItem::Include(self.parse_include()?))
+            }
             TokenKind::Dot if self.peek_is(&TokenKind::Dot) => {
                 // Spread: `...expr`
                 Ok(BodyItem::Spread(self.parse_spread()?))
@@ -208,23 +261,78 @@ impl Parser {
         }
     }
 
-    /// Parse let binding: `let name = expr`
+    /// Parse let binding: `let name = expr`, `let { a, b } = expr` or
+    /// `let [first, ...rest] = expr`
     fn parse_let(&mut self) -> HoneResult<LetBinding> {
         let start_loc = self.current_location();
         self.expect(&TokenKind::Let)?;
 
-        let name = self.expect_ident("variable name")?;
+        let (name, pattern) = if self.check(&TokenKind::LeftBrace) {
+            (String::new(), Some(self.parse_object_pattern()?))
+        } else if self.check(&TokenKind::LeftBracket) {
+            (String::new(), Some(self.parse_array_pattern()?))
+        } else {
+            (self.expect_ident("variable name")?, None)
+        };
         self.expect(&TokenKind::Eq)?;
         let value = self.parse_expr()?;
 
         let end_loc = value.location().clone();
         Ok(LetBinding {
             name,
+            pattern,
             value,
             location: start_loc.span_to(&end_loc),
         })
     }
 
+    /// Parse `{ a, b }` after `let`
+    fn parse_object_pattern(&mut self) -> HoneResult<LetPattern> {
+        self.expect(&TokenKind::LeftBrace)?;
+        let mut names = Vec::new();
+        self.skip_newlines();
+        while !self.check(&TokenKind::RightBrace) {
+            names.push(self.expect_ident("binding name")?);
+            self.skip_newlines();
+            if !self.check(&TokenKind::RightBrace) {
+                self.expect(&TokenKind::Comma)?;
+                self.skip_newlines();
+            }
+        }
+        self.expect(&TokenKind::RightBrace)?;
+        Ok(LetPattern::Object(names))
+    }
+
+    /// Parse `[a, b, ...rest]` after `let`; the rest binding must come last
+    fn parse_array_pattern(&mut self) -> HoneResult<LetPattern> {
+        self.expect(&TokenKind::LeftBracket)?;
+        let mut names = Vec::new();
+        let mut rest = None;
+        self.skip_newlines();
+        while !self.check(&TokenKind::RightBracket) {
+            if self.check(&TokenKind::Dot) {
+                self.expect(&TokenKind::Dot)?;
+                self.expect(&TokenKind::Dot)?;
+                self.expect(&TokenKind::Dot)?;
+                rest = Some(self.expect_ident("rest binding name")?);
+                self.skip_newlines();
+                if self.check(&TokenKind::Comma) {
+                    self.advance();
+                    self.skip_newlines();
+                }
+                break;
+            }
+            names.push(self.expect_ident("binding name")?);
+            self.skip_newlines();
+            if !self.check(&TokenKind::RightBracket) {
+                self.expect(&TokenKind::Comma)?;
+                self.skip_newlines();
+            }
+        }
+        self.expect(&TokenKind::RightBracket)?;
+        Ok(LetPattern::Array { names, rest })
+    }
+
     /// Parse from statement: `from "path" [as alias]`
     fn parse_from(&mut self) -> HoneResult<FromStatement> {
         let start_loc = self.current_location();
@@ -258,6 +366,10 @@ impl Parser {
 
             while !self.check(&TokenKind::RightBrace) {
                 let name_loc = self.current_location();
+                let is_schema = self.check(&TokenKind::Schema);
+                if is_schema {
+                    self.advance();
+                }
                 let name = self.expect_ident("import name")?;
                 let alias = if self.check(&TokenKind::As) {
                     self.advance();
@@ -269,6 +381,7 @@ impl Parser {
                 names.push(ImportName {
                     name,
                     alias,
+                    is_schema,
                     location: name_loc.span_to(&name_end),
                 });
 
@@ -398,31 +511,70 @@ impl Parser {
         let start_loc = self.current_location();
         let name = self.expect_ident("type name")?;
 
-        let args = if self.check(&TokenKind::LeftParen) {
-            self.advance();
-            let mut args = Vec::new();
-
-            while !self.check(&TokenKind::RightParen) {
-                args.push(self.parse_expr()?);
-                if !self.check(&TokenKind::RightParen) {
-                    self.expect(&TokenKind::Comma)?;
-                }
-            }
-
-            self.expect(&TokenKind::RightParen)?;
-            args
+        let (args, named_args) = if self.check(&TokenKind::LeftParen) {
+            self.parse_type_args()?
         } else {
-            Vec::new()
+            (Vec::new(), Vec::new())
         };
 
         let end_loc = self.previous_location();
         Ok(TypeConstraint {
             name,
             args,
+            named_args,
             location: start_loc.span_to(&end_loc),
         })
     }
 
+    /// Parse the arguments of a type, `(1, 63, pattern: "^[a-z]+$")`:
+    /// positional ones first, then `name: value` ones
+    fn parse_type_args(&mut self) -> HoneResult<(Vec<Expr>, Vec<NamedTypeArg>)> {
+        self.expect(&TokenKind::LeftParen)?;
+        let mut args = Vec::new();
+        let mut named_args: Vec<NamedTypeArg> = Vec::new();
+
+        while !self.check(&TokenKind::RightParen) {
+            let start_loc = self.current_location();
+            if matches!(self.current().kind, TokenKind::Ident(_)) && self.peek_is(&TokenKind::Colon)
+            {
+                let name = self.expect_ident("argument name")?;
+                if named_args.iter().any(|arg| arg.name == name) {
+                    return Err(HoneError::unexpected_token(
+                        self.source.clone(),
+                        &start_loc,
+                        "each named argument once",
+                        format!("'{}' given twice", name),
+                        "remove one of them",
+                    ));
+                }
+                self.advance();
+                let value = self.parse_expr()?;
+                let end_loc = self.previous_location();
+                named_args.push(NamedTypeArg {
+                    name,
+                    value,
+                    location: start_loc.span_to(&end_loc),
+                });
+            } else if !named_args.is_empty() {
+                return Err(HoneError::unexpected_token(
+                    self.source.clone(),
+                    &start_loc,
+                    "a named argument",
+                    format!("{}", self.current().kind),
+                    "positional arguments come before named ones",
+                ));
+            } else {
+                args.push(self.parse_expr()?);
+            }
+            if !self.check(&TokenKind::RightParen) {
+                self.expect(&TokenKind::Comma)?;
+            }
+        }
+
+        self.expect(&TokenKind::RightParen)?;
+        Ok((args, named_args))
+    }
+
     /// Parse type alias: `type Name = base_type & constraint1 & constraint2`
     fn parse_type_alias(&mut self) -> HoneResult<TypeAliasDefinition> {
         let start_loc = self.current_location();
@@ -441,6 +593,54 @@ impl Parser {
         })
     }
 
+    /// Parse enum definition: `enum Name { a, b, c }` (commas or newlines)
+    fn parse_enum(&mut self) -> HoneResult<EnumDefinition> {
+        let start_loc = self.current_location();
+        self.advance(); // consume contextual "enum"
+
+        let name = self.expect_ident("enum name")?;
+        self.expect(&TokenKind::LeftBrace)?;
+        self.skip_newlines();
+
+        let mut values: Vec<String> = Vec::new();
+        while !self.check(&TokenKind::RightBrace) {
+            let value_loc = self.current_location();
+            let value = self.expect_ident("enum value")?;
+            if values.contains(&value) {
+                return Err(HoneError::unexpected_token(
+                    &self.source,
+                    &value_loc,
+                    "a new enum value",
+                    &value,
+                    format!("'{}' is already a value of enum {}", value, name),
+                ));
+            }
+            values.push(value);
+            if self.check(&TokenKind::Comma) {
+                self.advance();
+            }
+            self.skip_newlines();
+        }
+        self.expect(&TokenKind::RightBrace)?;
+
+        if values.is_empty() {
+            return Err(HoneError::unexpected_token(
+                &self.source,
+                &self.previous_location(),
+                "enum value",
+                "}",
+                format!("enum {} needs at least one value", name),
+            ));
+        }
+
+        let end_loc = self.previous_location();
+        Ok(EnumDefinition {
+            name,
+            values,
+            location: start_loc.span_to(&end_loc),
+        })
+    }
+
     /// Parse type expression: handles unions, optionals, and arrays
     fn parse_type_expr(&mut self) -> HoneResult<TypeExpr> {
         let mut expr = self.parse_type_primary()?;
@@ -464,8 +664,14 @@ impl Parser {
         Ok(expr)
     }
 
-    /// Parse primary type expression: name, name(args), or array<T>
+    /// Parse primary type expression: name, name(args), array<T>, or "literal"
     fn parse_type_primary(&mut self) -> HoneResult<TypeExpr> {
+        if let TokenKind::String(s) = &self.current().kind {
+            let literal = s.clone();
+            self.advance();
+            return Ok(TypeExpr::StringLiteral(literal));
+        }
+
         let name = self.expect_ident("type name")?;
 
         // Check for array<T> syntax
@@ -476,44 +682,73 @@ impl Parser {
             Ok(TypeExpr::Array(Box::new(elem_type)))
         } else if self.check(&TokenKind::LeftParen) {
             // Parse name(args) syntax like int(1, 65535)
-            self.advance();
-            let mut args = Vec::new();
-            while !self.check(&TokenKind::RightParen) {
-                args.push(self.parse_expr()?);
-                if !self.check(&TokenKind::RightParen) {
-                    self.expect(&TokenKind::Comma)?;
-                }
-            }
-            self.expect(&TokenKind::RightParen)?;
-            Ok(TypeExpr::Named { name, args })
+            let (args, named_args) = self.parse_type_args()?;
+            Ok(TypeExpr::Named {
+                name,
+                args,
+                named_args,
+            })
         } else {
             Ok(TypeExpr::Named {
                 name,
                 args: Vec::new(),
+                named_args: Vec::new(),
             })
         }
     }
 
-    /// Parse use statement: `use schema_name`
+    /// Parse use statement: `[partial] use schema_name`
     fn parse_use(&mut self) -> HoneResult<UseStatement> {
         let start_loc = self.current_location();
+        let partial = matches!(&self.current().kind, TokenKind::Ident(id) if id == "partial");
+        if partial {
+            self.advance();
+        }
         self.expect(&TokenKind::Use)?;
 
-        let schema_name = self.expect_ident("schema name")?;
+        let mut schema_name = self.expect_ident("schema name")?;
+        // Namespaced schema from a whole import: `use config.Server`
+        if self.check(&TokenKind::Dot) {
+            self.advance();
+            schema_name.push('.');
+            schema_name.push_str(&self.expect_ident("schema name")?);
+        }
 
         let end_loc = self.previous_location();
         Ok(UseStatement {
             schema_name,
+            partial,
+            location: start_loc.span_to(&end_loc),
+        })
+    }
+
+    /// Parse include statement: `include "path"`
+    fn parse_include(&mut self) -> HoneResult<IncludeStatement> {
+        let start_loc = self.current_location();
+        self.advance();
+
+        let path = self.parse_string_expr()?;
+
+        let end_loc = self.previous_location();
+        Ok(IncludeStatement {
+            path,
+            resolved: None,
             location: start_loc.span_to(&end_loc),
         })
     }
 
-    /// Parse variant definition: `variant name { [default] case_name { ... } ... }`
+    /// Parse variant definition: `variant name [: Enum] { [default] case_name { ... } ... }`
     fn parse_variant(&mut self) -> HoneResult<VariantDefinition> {
         let start_loc = self.current_location();
         self.expect(&TokenKind::Variant)?;
 
         let name = self.expect_ident("variant name")?;
+        let enum_name = if self.check(&TokenKind::Colon) {
+            self.advance();
+            Some(self.expect_ident("enum name")?)
+        } else {
+            None
+        };
         self.expect(&TokenKind::LeftBrace)?;
         self.skip_newlines();
 
@@ -559,6 +794,7 @@ impl Parser {
 
         Ok(VariantDefinition {
             name,
+            enum_name,
             cases,
             location: start_loc.span_to(&end_loc),
         })
@@ -789,14 +1025,28 @@ impl Parser {
             self.expect(&TokenKind::RightParen)?;
             ForBinding::Pair(first, second)
         } else {
-            // Single: `for x in ...`
+            // Single: `for x in ...`, or with a position: `for x, i in ...`
             let name = self.expect_ident("loop variable")?;
-            ForBinding::Single(name)
+            if self.check(&TokenKind::Comma) {
+                self.advance();
+                let index = self.expect_ident("index variable")?;
+                ForBinding::Indexed(name, index)
+            } else {
+                ForBinding::Single(name)
+            }
         };
 
         self.expect(&TokenKind::In)?;
         let iterable = self.parse_expr()?;
 
+        // Optional filter: `where` is contextual, so it stays usable as a key
+        let filter = if matches!(&self.current().kind, TokenKind::Ident(w) if w == "where") {
+            self.advance();
+            Some(Box::new(self.parse_expr()?))
+        } else {
+            None
+        };
+
         self.expect(&TokenKind::LeftBrace)?;
         self.skip_newlines();
 
@@ -832,6 +1082,7 @@ impl Parser {
         Ok(ForLoop {
             binding,
             iterable,
+            filter,
             body,
             location: start_loc.span_to(&end_loc),
         })
@@ -844,17 +1095,14 @@ impl Parser {
             TokenKind::Ident(_) | TokenKind::String(_) => {
                 // Could be a key-value or block
                 // Look ahead for `:`, `+:`, `!:`, or `{`
-                if self.pos + 1 < self.tokens.len() {
-                    matches!(
+                self.pos + 1 < self.tokens.len()
+                    && matches!(
                         self.tokens[self.pos + 1].kind,
                         TokenKind::Colon
                             | TokenKind::ColonPlus
                             | TokenKind::ColonBang
                             | TokenKind::LeftBrace
                     )
-                } else {
-                    false
-                }
             }
             TokenKind::StringStart(_) => {
                 // Interpolated string key: `"${expr}": value`
@@ -875,10 +1123,42 @@ impl Parser {
                     true // fallback: assume it's a key (old behavior)
                 }
             }
+            TokenKind::LeftBracket => {
+                // Computed key `[expr]: value`, not an array expression
+                self.find_matching_bracket(self.pos).is_some_and(|end| {
+                    matches!(
+                        self.tokens.get(end + 1).map(|t| &t.kind),
+                        Some(
+                            TokenKind::Colon
+                                | TokenKind::ColonPlus
+                                | TokenKind::ColonBang
+                                | TokenKind::LeftBrace
+                        )
+                    )
+                })
+            }
             _ => false,
         }
     }
 
+    /// Find the position of the `]` matching a `[` at `start_pos`
+    fn find_matching_bracket(&self, start_pos: usize) -> Option<usize> {
+        let mut depth = 0usize;
+        for (pos, token) in self.tokens.iter().enumerate().skip(start_pos) {
+            match token.kind {
+                TokenKind::LeftBracket => depth += 1,
+                TokenKind::RightBracket => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(pos);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
     /// Find the position of the StringEnd token matching a StringStart at `start_pos`.
     /// Handles nested interpolated strings.
     fn find_matching_string_end(&self, start_pos: usize) -> Option<usize> {
@@ -939,9 +1219,20 @@ impl Parser {
         })
     }
 
-    /// Parse key-value pair or block
+    /// Parse key-value pair or block, either optionally prefixed with `final`
     fn parse_key_value_or_block(&mut self) -> HoneResult<BodyItem> {
         let start_loc = self.current_location();
+        let doc = self.doc_comment(&start_loc);
+
+        // `final` is contextual, so `final: true` stays an ordinary key
+        let is_final = matches!(&self.current().kind, TokenKind::Ident(id) if id == "final")
+            && (self.peek_is(&TokenKind::Ident(String::new()))
+                || self.peek_is(&TokenKind::String(String::new()))
+                || self.peek_is(&TokenKind::StringStart(String::new()))
+                || self.peek_is(&TokenKind::LeftBracket));
+        if is_final {
+            self.advance();
+        }
 
         // Parse the key
         let key = self.parse_key()?;
@@ -965,6 +1256,8 @@ impl Parser {
                 return Ok(BodyItem::Block(Block {
                     name,
                     items,
+                    is_final,
+                    doc,
                     location: start_loc.span_to(&end_loc),
                 }));
             }
@@ -981,10 +1274,35 @@ impl Parser {
             key,
             op,
             value,
+            is_final,
+            doc,
             location: start_loc.span_to(&end_loc),
         }))
     }
 
+    /// The `##` comment lines directly above the line starting at `loc`,
+    /// joined with newlines. Comments aren't tokens, so they are read from
+    /// the source; a blank line or any other line ends the doc comment.
+    fn doc_comment(&self, loc: &SourceLocation) -> Option<String> {
+        let before = self.source.get(..loc.offset)?;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        if !before[line_start..].trim().is_empty() {
+            return None;
+        }
+
+        let mut lines: Vec<&str> = before[..line_start.saturating_sub(1)]
+            .split('\n')
+            .rev()
+            .map_while(|line| line.trim().strip_prefix("##"))
+            .map(|text| text.strip_prefix(' ').unwrap_or(text).trim_end())
+            .collect();
+        if line_start == 0 || lines.is_empty() {
+            return None;
+        }
+        lines.reverse();
+        Some(lines.join("\n"))
+    }
+
     /// Parse a key
     fn parse_key(&mut self) -> HoneResult<Key> {
         match &self.current().kind {
@@ -1155,7 +1473,7 @@ impl Parser {
 
     /// Parse comparison expression: `a < b`, `a > b`, etc.
     fn parse_comparison(&mut self) -> HoneResult<Expr> {
-        let mut left = self.parse_null_coalesce()?;
+        let mut left = self.parse_pipeline()?;
 
         while matches!(
             self.current().kind,
@@ -1170,7 +1488,7 @@ impl Parser {
                 _ => unreachable!(),
             };
             self.advance();
-            let right = self.parse_null_coalesce()?;
+            let right = self.parse_pipeline()?;
             let end_loc = right.location().clone();
 
             left = Expr::Binary(BinaryExpr {
@@ -1184,6 +1502,39 @@ impl Parser {
         Ok(left)
     }
 
+    /// Parse a pipeline: `values |> filter(f) |> sort()`. A line starting
+    /// with `|>` continues the expression on the line before.
+    fn parse_pipeline(&mut self) -> HoneResult<Expr> {
+        let mut left = self.parse_null_coalesce()?;
+
+        while self.check(&TokenKind::Pipeline) || self.newlines_then(&TokenKind::Pipeline) {
+            self.skip_newlines();
+            let start_loc = left.location().clone();
+            self.advance();
+            self.skip_newlines();
+            let right = self.parse_null_coalesce()?;
+            if !matches!(right, Expr::Call(_)) {
+                return Err(HoneError::unexpected_token(
+                    self.source.clone(),
+                    right.location(),
+                    "function call",
+                    "expression",
+                    "the right side of |> must be a call, which gets the left value as its first argument: `values |> sort()`",
+                ));
+            }
+            let end_loc = right.location().clone();
+
+            left = Expr::Binary(BinaryExpr {
+                left: Box::new(left),
+                op: BinaryOp::Pipe,
+                right: Box::new(right),
+                location: start_loc.span_to(&end_loc),
+            });
+        }
+
+        Ok(left)
+    }
+
     /// Parse null coalescing: `a ?? b`
     fn parse_null_coalesce(&mut self) -> HoneResult<Expr> {
         let mut left = self.parse_additive()?;
@@ -1405,9 +1756,30 @@ impl Parser {
                 self.advance();
                 Ok(Expr::Float(n, start_loc))
             }
+            TokenKind::Duration(ms) => {
+                let ms = *ms;
+                self.advance();
+                Ok(Expr::Duration(ms, start_loc))
+            }
+            TokenKind::Size(bytes) => {
+                let bytes = *bytes;
+                self.advance();
+                Ok(Expr::Size(bytes, start_loc))
+            }
             TokenKind::String(_) | TokenKind::StringStart(_) | TokenKind::TripleString(_) => {
                 Ok(Expr::String(self.parse_string_expr()?))
             }
+            TokenKind::Ident(name) if name == "match" && self.at_match() => {
+                Ok(Expr::Match(Box::new(self.parse_match()?)))
+            }
+            TokenKind::Ident(name) if name == "block" && self.peek_is(&TokenKind::LeftBrace) => {
+                self.advance();
+                let Expr::Object(mut obj) = self.parse_object()? else {
+                    unreachable!("parse_object returns an object");
+                };
+                obj.location = start_loc.span_to(&obj.location);
+                Ok(Expr::Block(obj))
+            }
             TokenKind::Ident(name) => {
                 let name = name.clone();
                 self.advance();
@@ -1421,6 +1793,7 @@ impl Parser {
                 // Object literal
                 self.parse_object()
             }
+            TokenKind::LeftParen if self.at_lambda() => self.parse_lambda(),
             TokenKind::LeftParen => {
                 // Parenthesized expression
                 self.advance();
@@ -1443,6 +1816,159 @@ impl Parser {
         }
     }
 
+    /// Whether the `match` at the current position starts a match expression:
+    /// a subject follows on the same line, then `{`. Anywhere else `match` is
+    /// an ordinary name, so it stays usable as a key or variable.
+    fn at_match(&self) -> bool {
+        let mut depth = 0usize;
+        for (i, token) in self.tokens.iter().enumerate().skip(self.pos + 1) {
+            match token.kind {
+                TokenKind::LeftBrace if depth == 0 => return i > self.pos + 1,
+                TokenKind::LeftParen | TokenKind::LeftBracket | TokenKind::LeftBrace => depth += 1,
+                TokenKind::RightParen | TokenKind::RightBracket | TokenKind::RightBrace => {
+                    if depth == 0 {
+                        return false;
+                    }
+                    depth -= 1;
+                }
+                // `match.x`, `match == y`, `match, ...`: a name, not a subject
+                TokenKind::Dot
+                | TokenKind::Comma
+                | TokenKind::Colon
+                | TokenKind::Question
+                | TokenKind::EqEq
+                | TokenKind::NotEq
+                | TokenKind::Pipeline
+                    if i == self.pos + 1 =>
+                {
+                    return false
+                }
+                TokenKind::Newline | TokenKind::Eof if depth == 0 => return false,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Parse a match expression:
+    /// `match subject { "a", "b" => x, "c" => y, _ => z }`
+    fn parse_match(&mut self) -> HoneResult<MatchExpr> {
+        let start_loc = self.current_location();
+        self.advance(); // `match`
+        let subject = self.parse_expr()?;
+        self.expect(&TokenKind::LeftBrace)?;
+        self.skip_newlines();
+
+        let mut arms: Vec<MatchArm> = Vec::new();
+        while !self.check(&TokenKind::RightBrace) {
+            let arm_loc = self.current_location();
+            if arms.last().is_some_and(MatchArm::is_wildcard) {
+                return Err(HoneError::unexpected_token(
+                    self.source.clone(),
+                    &arm_loc,
+                    "'}'",
+                    format!("{}", self.current().kind),
+                    "the `_` arm matches every value, so it must be the last arm",
+                ));
+            }
+            let mut patterns = Vec::new();
+            let wildcard = matches!(&self.current().kind, TokenKind::Ident(name) if name == "_")
+                && self.peek_is(&TokenKind::FatArrow);
+            if wildcard {
+                self.advance();
+            } else {
+                loop {
+                    patterns.push(self.parse_expr()?);
+                    if !self.check(&TokenKind::Comma) {
+                        break;
+                    }
+                    self.advance();
+                    self.skip_newlines();
+                }
+            }
+            self.expect(&TokenKind::FatArrow)?;
+            self.skip_newlines();
+            let value = self.parse_expr()?;
+            let arm_end = value.location().clone();
+            arms.push(MatchArm {
+                patterns,
+                value,
+                location: arm_loc.span_to(&arm_end),
+            });
+
+            // Arms are separated by newlines or commas
+            self.skip_newlines();
+            if self.check(&TokenKind::Comma) {
+                self.advance();
+                self.skip_newlines();
+            } else if !self.check(&TokenKind::RightBrace) && !self.previous_is_newline() {
+                return Err(self.error_unexpected("',' or a new line between match arms"));
+            }
+        }
+        self.expect(&TokenKind::RightBrace)?;
+
+        let end_loc = self.previous_location();
+        Ok(MatchExpr {
+            subject: Box::new(subject),
+            arms,
+            location: start_loc.span_to(&end_loc),
+        })
+    }
+
+    /// Whether the `(` at the current position opens a lambda's parameter
+    /// list: `()` or `(a, b)` followed by `=>`
+    fn at_lambda(&self) -> bool {
+        let mut pos = self.pos + 1;
+        let kind = |pos: usize| self.tokens.get(pos).map(|t| &t.kind);
+        if !matches!(kind(pos), Some(TokenKind::RightParen)) {
+            loop {
+                if !matches!(kind(pos), Some(TokenKind::Ident(_))) {
+                    return false;
+                }
+                pos += 1;
+                match kind(pos) {
+                    Some(TokenKind::Comma) => pos += 1,
+                    Some(TokenKind::RightParen) => break,
+                    _ => return false,
+                }
+            }
+        }
+        matches!(kind(pos + 1), Some(TokenKind::FatArrow))
+    }
+
+    /// Parse a lambda: `(x, y) => expr`
+    fn parse_lambda(&mut self) -> HoneResult<Expr> {
+        let start_loc = self.current_location();
+        self.expect(&TokenKind::LeftParen)?;
+        let mut params: Vec<String> = Vec::new();
+        while !self.check(&TokenKind::RightParen) {
+            let param_loc = self.current_location();
+            let param = self.expect_ident("parameter name")?;
+            if params.contains(&param) {
+                return Err(HoneError::unexpected_token(
+                    self.source.clone(),
+                    &param_loc,
+                    "a distinct parameter name",
+                    param.clone(),
+                    format!("parameter '{}' is declared twice", param),
+                ));
+            }
+            params.push(param);
+            if !self.check(&TokenKind::RightParen) {
+                self.expect(&TokenKind::Comma)?;
+            }
+        }
+        self.expect(&TokenKind::RightParen)?;
+        self.expect(&TokenKind::FatArrow)?;
+        let body = self.parse_expr()?;
+        let end_loc = body.location().clone();
+        Ok(Expr::Lambda(LambdaExpr {
+            params,
+            body: Box::new(body),
+            location: start_loc.span_to(&end_loc),
+        }))
+    }
+
     /// Parse a string expression (possibly with interpolations)
     fn parse_string_expr(&mut self) -> HoneResult<StringExpr> {
         let start_loc = self.current_location();
@@ -1462,8 +1988,7 @@ impl Parser {
                 self.advance();
 
                 // Parse interpolated expression
-                let expr = self.parse_expr()?;
-                parts.push(StringPart::Interpolation(expr));
+                parts.push(self.parse_interpolation()?);
 
                 // Continue parsing string parts
                 loop {
@@ -1471,8 +1996,7 @@ impl Parser {
                         TokenKind::StringMiddle(s) => {
                             parts.push(StringPart::Literal(s.clone()));
                             self.advance();
-                            let expr = self.parse_expr()?;
-                            parts.push(StringPart::Interpolation(expr));
+                            parts.push(self.parse_interpolation()?);
                         }
                         TokenKind::StringEnd(s) => {
                             parts.push(StringPart::Literal(s.clone()));
@@ -1497,6 +2021,17 @@ impl Parser {
         })
     }
 
+    /// Parse the expression of an interpolation and its format spec, if any
+    fn parse_interpolation(&mut self) -> HoneResult<StringPart> {
+        let expr = self.parse_expr()?;
+        if let TokenKind::FormatSpec(spec) = &self.current().kind {
+            let spec = spec.clone();
+            self.advance();
+            return Ok(StringPart::Formatted(expr, spec));
+        }
+        Ok(StringPart::Interpolation(expr))
+    }
+
     /// Parse array literal
     fn parse_array(&mut self) -> HoneResult<Expr> {
         let start_loc = self.current_location();
@@ -1597,6 +2132,11 @@ impl Parser {
         }
     }
 
+    /// Whether the token before the current one is a line break
+    fn previous_is_newline(&self) -> bool {
+        self.pos > 0 && matches!(self.tokens[self.pos - 1].kind, TokenKind::Newline)
+    }
+
     /// Check if we're at the end of input
     fn is_at_end(&self) -> bool {
         matches!(self.current().kind, TokenKind::Eof)
@@ -1623,6 +2163,16 @@ impl Parser {
         }
     }
 
+    /// Whether the current token is a newline and the first token after the
+    /// newlines is `kind`
+    fn newlines_then(&self, kind: &TokenKind) -> bool {
+        self.check(&TokenKind::Newline)
+            && self.tokens[self.pos..]
+                .iter()
+                .find(|t| t.kind != TokenKind::Newline)
+                .is_some_and(|t| std::mem::discriminant(&t.kind) == std::mem::discriminant(kind))
+    }
+
     /// Skip newline tokens
     fn skip_newlines(&mut self) {
         while self.check(&TokenKind::Newline) {
@@ -1683,6 +2233,53 @@ mod tests {
         assert!(file.documents.is_empty());
     }
 
+    #[test]
+    fn test_doc_comments_attach_to_keys() {
+        let file = parse(
+            "## Service name\n##\n##   indented\nname: \"api\"\n## detached\n\nport: 80\n# plain\nserver {\n  ## Bind address\n  host: \"x\"\n}\n",
+        )
+        .unwrap();
+        let docs: Vec<Option<&str>> = file
+            .body
+            .iter()
+            .map(|item| match item {
+                BodyItem::KeyValue(kv) => kv.doc.as_deref(),
+                BodyItem::Block(block) => block.doc.as_deref(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(docs, vec![Some("Service name\n\n  indented"), None, None]);
+        let BodyItem::Block(block) = &file.body[2] else {
+            panic!("expected block");
+        };
+        let BodyItem::KeyValue(host) = &block.items[0] else {
+            panic!("expected key");
+        };
+        assert_eq!(host.doc.as_deref(), Some("Bind address"));
+    }
+
+    #[test]
+    fn test_enum_declaration() {
+        let file = parse(
+            "enum Env { dev, staging,\n  prod }\nvariant tier: Env {\n  default dev {\n    a: 1\n  }\n}\nenum: [1]\n",
+        )
+        .unwrap();
+        let PreambleItem::Enum(def) = &file.preamble[0] else {
+            panic!("expected enum");
+        };
+        assert_eq!(def.name, "Env");
+        assert_eq!(def.values, vec!["dev", "staging", "prod"]);
+        let PreambleItem::Variant(variant) = &file.preamble[1] else {
+            panic!("expected variant");
+        };
+        assert_eq!(variant.enum_name.as_deref(), Some("Env"));
+        // `enum` followed by a colon is still an ordinary key
+        assert_eq!(file.body.len(), 1);
+
+        assert!(parse("enum Env { dev, dev }").is_err());
+        assert!(parse("enum Env { }").is_err());
+    }
+
     #[test]
     fn test_simple_key_value() {
         let file = parse("name: \"hello\"").unwrap();
@@ -1710,6 +2307,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_let_destructuring() {
+        let file =
+            parse("let { host, port } = server\nlet [first, ...rest] = items\nlet [a, b,] = pair")
+                .unwrap();
+        let patterns: Vec<_> = file
+            .preamble
+            .iter()
+            .map(|item| match item {
+                PreambleItem::Let(binding) => binding.pattern.clone(),
+                _ => panic!("expected let binding"),
+            })
+            .collect();
+        assert_eq!(
+            patterns,
+            vec![
+                Some(LetPattern::Object(vec!["host".into(), "port".into()])),
+                Some(LetPattern::Array {
+                    names: vec!["first".into()],
+                    rest: Some("rest".into()),
+                }),
+                Some(LetPattern::Array {
+                    names: vec!["a".into(), "b".into()],
+                    rest: None,
+                }),
+            ]
+        );
+        // The rest binding has to come last
+        assert!(parse("let [...rest, last] = items").is_err());
+    }
+
     #[test]
     fn test_from_statement() {
         let file = parse("from \"./base.hone\"").unwrap();
@@ -1817,6 +2445,57 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_match_expr() {
+        let file = parse(
+            "x: match env {\n  \"prod\" => 5\n  \"staging\", \"qa\" => 2,\n  _ => 1\n}\ny: match env { \"a\" => 1, _ => 2 }",
+        )
+        .unwrap();
+        let BodyItem::KeyValue(kv) = &file.body[0] else {
+            panic!("expected key-value");
+        };
+        let Expr::Match(m) = &kv.value else {
+            panic!("expected match");
+        };
+        let arities: Vec<usize> = m.arms.iter().map(|arm| arm.patterns.len()).collect();
+        assert_eq!(arities, vec![1, 2, 0]);
+        assert!(m.arms[2].is_wildcard());
+        assert!(
+            matches!(&file.body[1], BodyItem::KeyValue(kv) if matches!(kv.value, Expr::Match(_)))
+        );
+
+        // `match` is still an ordinary name everywhere else
+        let file = parse(
+            "match: 1
+x: match.key
+y: match == 1 ? 1 : 2",
+        )
+        .unwrap();
+        assert_eq!(file.body.len(), 3);
+
+        // Nothing after the catch-all arm could ever run
+        assert!(parse("x: match env { _ => 1, \"prod\" => 2 }").is_err());
+        assert!(parse("x: match env { \"a\" => 1 \"b\" => 2 }").is_err());
+    }
+
+    #[test]
+    fn test_block_value() {
+        let file = parse("let probes = block {\n  ports +: [80]\n}\nblock: 1\nx: block").unwrap();
+        let PreambleItem::Let(binding) = &file.preamble[0] else {
+            panic!("expected let");
+        };
+        let Expr::Block(block) = &binding.value else {
+            panic!("expected block");
+        };
+        assert!(matches!(&block.items[0], BodyItem::KeyValue(kv) if kv.op == AssignOp::Append));
+        assert_eq!(block.location.column, 14);
+
+        // Without a `{` after it, `block` is an ordinary name
+        assert!(
+            matches!(&file.body[1], BodyItem::KeyValue(kv) if matches!(kv.value, Expr::Ident(..)))
+        );
+    }
+
     #[test]
     fn test_function_call() {
         let file = parse("x: len(items)").unwrap();
@@ -2008,6 +2687,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_import_named_schema() {
+        let file = parse("import { schema Server as S, port } from \"./lib.hone\"").unwrap();
+        if let PreambleItem::Import(import) = &file.preamble[0] {
+            if let ImportKind::Named { names, .. } = &import.kind {
+                assert!(names[0].is_schema);
+                assert_eq!(names[0].name, "Server");
+                assert_eq!(names[0].alias, Some("S".to_string()));
+                assert!(!names[1].is_schema);
+            } else {
+                panic!("expected named import");
+            }
+        } else {
+            panic!("expected import");
+        }
+    }
+
     #[test]
     fn test_unary_not() {
         let file = parse("enabled: !disabled").unwrap();
@@ -2080,6 +2776,64 @@ mod tests {
         let file = parse("use MySchema").unwrap();
         if let PreambleItem::Use(u) = &file.preamble[0] {
             assert_eq!(u.schema_name, "MySchema");
+            assert!(!u.partial);
+        } else {
+            panic!("expected use statement");
+        }
+    }
+
+    #[test]
+    fn test_final_keys() {
+        let file = parse("final replicas: 3\nfinal db {\n  host: \"a\"\n}\nfinal: true").unwrap();
+        assert!(matches!(&file.body[0], BodyItem::KeyValue(kv) if kv.is_final));
+        assert!(matches!(&file.body[1], BodyItem::Block(b) if b.is_final && b.name == "db"));
+        assert!(
+            matches!(&file.body[2], BodyItem::KeyValue(kv) if !kv.is_final && kv.key == Key::Ident("final".into()))
+        );
+    }
+
+    #[test]
+    fn test_partial_use_statement() {
+        let file = parse(
+            "partial use MySchema
+partial: true",
+        )
+        .unwrap();
+        assert!(matches!(&file.preamble[0], PreambleItem::Use(u) if u.partial));
+        assert_eq!(file.body.len(), 1);
+    }
+
+    #[test]
+    fn test_use_in_when_body() {
+        let file = parse("when env == \"prod\" {\n  use ProdServer\n}").unwrap();
+        if let BodyItem::When(when) = &file.body[0] {
+            assert!(matches!(&when.body[0], BodyItem::Use(u) if u.schema_name == "ProdServer"));
+        } else {
+            panic!("expected when");
+        }
+    }
+
+    #[test]
+    fn test_include_in_block() {
+        let file = parse("web {\n  include \"./probes.hone\"\n}\ninclude: true").unwrap();
+        if let BodyItem::Block(block) = &file.body[0] {
+            assert!(matches!(
+                &block.items[0],
+                BodyItem::Include(i) if i.path.parts == vec![StringPart::Literal("./probes.hone".into())]
+                    && i.resolved.is_none()
+            ));
+        } else {
+            panic!("expected block");
+        }
+        // `include` followed by a colon is still a key
+        assert!(matches!(&file.body[1], BodyItem::KeyValue(_)));
+    }
+
+    #[test]
+    fn test_use_namespaced_schema() {
+        let file = parse("use config.Server").unwrap();
+        if let PreambleItem::Use(u) = &file.preamble[0] {
+            assert_eq!(u.schema_name, "config.Server");
         } else {
             panic!("expected use statement");
         }
@@ -2172,7 +2926,7 @@ kind: "Service"
         assert_eq!(file.preamble.len(), 1);
         if let PreambleItem::TypeAlias(alias) = &file.preamble[0] {
             assert_eq!(alias.name, "Port");
-            if let TypeExpr::Named { name, args } = &alias.base_type {
+            if let TypeExpr::Named { name, args, .. } = &alias.base_type {
                 assert_eq!(name, "int");
                 assert!(args.is_empty());
             } else {
@@ -2191,7 +2945,7 @@ kind: "Service"
         assert_eq!(file.preamble.len(), 1);
         if let PreambleItem::TypeAlias(alias) = &file.preamble[0] {
             assert_eq!(alias.name, "Port");
-            if let TypeExpr::Named { name, args } = &alias.base_type {
+            if let TypeExpr::Named { name, args, .. } = &alias.base_type {
                 assert_eq!(name, "int");
                 assert_eq!(args.len(), 2);
                 // args[0] should be 1, args[1] should be 65535
@@ -2230,7 +2984,7 @@ kind: "Service"
         let file = parse(source).unwrap();
         if let PreambleItem::TypeAlias(alias) = &file.preamble[0] {
             if let TypeExpr::Array(inner) = &alias.base_type {
-                if let TypeExpr::Named { name, args } = inner.as_ref() {
+                if let TypeExpr::Named { name, args, .. } = inner.as_ref() {
                     assert_eq!(name, "int");
                     assert!(args.is_empty());
                 } else {
@@ -2258,4 +3012,54 @@ kind: "Service"
             panic!("expected type alias");
         }
     }
+
+    #[test]
+    fn test_type_alias_string_literal_union() {
+        let source = r#"type Protocol = "TCP" | "UDP""#;
+        let file = parse(source).unwrap();
+        if let PreambleItem::TypeAlias(alias) = &file.preamble[0] {
+            if let TypeExpr::Union(types) = &alias.base_type {
+                assert_eq!(
+                    types,
+                    &vec![
+                        TypeExpr::StringLiteral("TCP".to_string()),
+                        TypeExpr::StringLiteral("UDP".to_string()),
+                    ]
+                );
+            } else {
+                panic!("expected union type");
+            }
+        } else {
+            panic!("expected type alias");
+        }
+    }
+
+    #[test]
+    fn test_lambda_expression() {
+        let file = parse(
+            "let add = (a, b) => a + b
+let k = () => 1
+x: (1 + 2) * 3
+",
+        )
+        .unwrap();
+        let PreambleItem::Let(binding) = &file.preamble[0] else {
+            panic!("expected let");
+        };
+        let Expr::Lambda(lambda) = &binding.value else {
+            panic!("expected lambda");
+        };
+        assert_eq!(lambda.params, vec!["a", "b"]);
+        assert!(matches!(lambda.body.as_ref(), Expr::Binary(_)));
+        let PreambleItem::Let(binding) = &file.preamble[1] else {
+            panic!("expected let");
+        };
+        assert!(matches!(&binding.value, Expr::Lambda(l) if l.params.is_empty()));
+        let BodyItem::KeyValue(kv) = &file.body[0] else {
+            panic!("expected key");
+        };
+        assert!(matches!(&kv.value, Expr::Binary(_)));
+
+        assert!(parse("let f = (x, x) => x\n").is_err());
+    }
 }
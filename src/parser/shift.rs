@@ -0,0 +1,284 @@
+//! Moving AST locations
+//!
+//! After an edit, the items that follow the edited part of a file keep their
+//! structure but start on other lines and byte offsets. [`Shift`] moves every
+//! location in them, so they can be reused instead of re-parsed. Columns are
+//! left alone: shifted items start on a line of their own.
+
+use super::ast::*;
+use crate::lexer::token::{SourceLocation, Token};
+
+/// Lines and bytes to move locations by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Offset {
+    pub lines: isize,
+    pub bytes: isize,
+}
+
+/// Something holding source locations
+pub trait Shift {
+    fn shift(&mut self, by: Offset);
+}
+
+impl Shift for SourceLocation {
+    fn shift(&mut self, by: Offset) {
+        self.line = self.line.saturating_add_signed(by.lines);
+        self.offset = self.offset.saturating_add_signed(by.bytes);
+    }
+}
+
+impl<T: Shift> Shift for Vec<T> {
+    fn shift(&mut self, by: Offset) {
+        for item in self {
+            item.shift(by);
+        }
+    }
+}
+
+impl<T: Shift> Shift for Option<T> {
+    fn shift(&mut self, by: Offset) {
+        if let Some(item) = self {
+            item.shift(by);
+        }
+    }
+}
+
+impl<T: Shift> Shift for Box<T> {
+    fn shift(&mut self, by: Offset) {
+        (**self).shift(by);
+    }
+}
+
+/// Shift the listed fields of a struct
+macro_rules! shift_fields {
+    ($($ty:ty => $($field:ident),+;)+) => {
+        $(
+            impl Shift for $ty {
+                fn shift(&mut self, by: Offset) {
+                    $(self.$field.shift(by);)+
+                }
+            }
+        )+
+    };
+}
+
+shift_fields! {
+    Token => location;
+    LetBinding => value, location;
+    FromStatement => path, location;
+    ImportStatement => kind, location;
+    ImportName => location;
+    SchemaDefinition => fields, location;
+    SchemaField => constraint, default, location;
+    TypeAliasDefinition => base_type, location;
+    UseStatement => location;
+    IncludeStatement => path, location;
+    EnumDefinition => location;
+    VariantDefinition => cases, location;
+    VariantCase => body, location;
+    ExpectDeclaration => default, location;
+    PolicyDeclaration => condition, location;
+    SecretDeclaration => location;
+    FnDefinition => body, location;
+    KeyValue => key, value, location;
+    Block => items, location;
+    WhenBlock => condition, body, else_branch, location;
+    ForLoop => iterable, filter, body, location;
+    AssertStatement => condition, message, location;
+    SpreadExpr => expr, location;
+    StringExpr => parts, location;
+    PathExpr => parts, location;
+    ArrayExpr => elements, location;
+    ObjectExpr => items, location;
+    BinaryExpr => left, right, location;
+    UnaryExpr => operand, location;
+    LambdaExpr => body, location;
+    CallExpr => func, args, location;
+    IndexExpr => base, index, location;
+    ConditionalExpr => condition, then_branch, else_branch, location;
+    MatchExpr => subject, arms, location;
+    MatchArm => patterns, value, location;
+    AnnotatedExpr => expr, constraint, location;
+    TypeConstraint => args, named_args, location;
+    NamedTypeArg => value, location;
+}
+
+impl Shift for PreambleItem {
+    fn shift(&mut self, by: Offset) {
+        match self {
+            PreambleItem::Let(item) => item.shift(by),
+            PreambleItem::From(item) => item.shift(by),
+            PreambleItem::Import(item) => item.shift(by),
+            PreambleItem::Schema(item) => item.shift(by),
+            PreambleItem::TypeAlias(item) => item.shift(by),
+            PreambleItem::Enum(item) => item.shift(by),
+            PreambleItem::Use(item) => item.shift(by),
+            PreambleItem::Variant(item) => item.shift(by),
+            PreambleItem::Expect(item) => item.shift(by),
+            PreambleItem::Secret(item) => item.shift(by),
+            PreambleItem::Policy(item) => item.shift(by),
+            PreambleItem::FnDef(item) => item.shift(by),
+        }
+    }
+}
+
+impl Shift for BodyItem {
+    fn shift(&mut self, by: Offset) {
+        match self {
+            BodyItem::KeyValue(item) => item.shift(by),
+            BodyItem::Block(item) => item.shift(by),
+            BodyItem::When(item) => item.shift(by),
+            BodyItem::For(item) => item.shift(by),
+            BodyItem::Assert(item) => item.shift(by),
+            BodyItem::Let(item) => item.shift(by),
+            BodyItem::Spread(item) => item.shift(by),
+            BodyItem::Use(item) => item.shift(by),
+            BodyItem::Include(item) => item.shift(by),
+        }
+    }
+}
+
+impl Shift for ImportKind {
+    fn shift(&mut self, by: Offset) {
+        match self {
+            ImportKind::Whole { path, .. } => path.shift(by),
+            ImportKind::Named { names, path } => {
+                names.shift(by);
+                path.shift(by);
+            }
+        }
+    }
+}
+
+impl Shift for TypeExpr {
+    fn shift(&mut self, by: Offset) {
+        match self {
+            TypeExpr::Named {
+                args, named_args, ..
+            } => {
+                args.shift(by);
+                named_args.shift(by);
+            }
+            TypeExpr::Array(inner) | TypeExpr::Optional(inner) => inner.shift(by),
+            TypeExpr::Union(types) => types.shift(by),
+            TypeExpr::StringLiteral(_) => {}
+        }
+    }
+}
+
+impl Shift for Key {
+    fn shift(&mut self, by: Offset) {
+        if let Key::Computed(expr) = self {
+            expr.shift(by);
+        }
+    }
+}
+
+impl Shift for ElseBranch {
+    fn shift(&mut self, by: Offset) {
+        match self {
+            ElseBranch::ElseWhen(when) => when.shift(by),
+            ElseBranch::Else(body, location) => {
+                body.shift(by);
+                location.shift(by);
+            }
+        }
+    }
+}
+
+impl Shift for ForBody {
+    fn shift(&mut self, by: Offset) {
+        match self {
+            ForBody::Object(items) => items.shift(by),
+            ForBody::Expr(expr) => expr.shift(by),
+            ForBody::Block(items, expr) => {
+                items.shift(by);
+                expr.shift(by);
+            }
+        }
+    }
+}
+
+impl Shift for Expr {
+    fn shift(&mut self, by: Offset) {
+        match self {
+            Expr::Null(location)
+            | Expr::Bool(_, location)
+            | Expr::Integer(_, location)
+            | Expr::Float(_, location)
+            | Expr::Duration(_, location)
+            | Expr::Size(_, location)
+            | Expr::Ident(_, location) => location.shift(by),
+            Expr::String(expr) => expr.shift(by),
+            Expr::Path(expr) => expr.shift(by),
+            Expr::Array(expr) => expr.shift(by),
+            Expr::Object(expr) => expr.shift(by),
+            Expr::Binary(expr) => expr.shift(by),
+            Expr::Unary(expr) => expr.shift(by),
+            Expr::Call(expr) => expr.shift(by),
+            Expr::Index(expr) => expr.shift(by),
+            Expr::Conditional(expr) => expr.shift(by),
+            Expr::Annotated(expr) => expr.shift(by),
+            Expr::Paren(expr, location) => {
+                expr.shift(by);
+                location.shift(by);
+            }
+            Expr::For(expr) => expr.shift(by),
+            Expr::When(expr) => expr.shift(by),
+            Expr::Match(expr) => expr.shift(by),
+            Expr::Block(expr) => expr.shift(by),
+            Expr::Lambda(expr) => expr.shift(by),
+        }
+    }
+}
+
+impl Shift for StringPart {
+    fn shift(&mut self, by: Offset) {
+        if let StringPart::Interpolation(expr) | StringPart::Formatted(expr, _) = self {
+            expr.shift(by);
+        }
+    }
+}
+
+impl Shift for PathPart {
+    fn shift(&mut self, by: Offset) {
+        if let PathPart::Index(expr) = self {
+            expr.shift(by);
+        }
+    }
+}
+
+impl Shift for ArrayElement {
+    fn shift(&mut self, by: Offset) {
+        match self {
+            ArrayElement::Expr(expr) | ArrayElement::Spread(expr) => expr.shift(by),
+            ArrayElement::For(for_loop) => for_loop.shift(by),
+            ArrayElement::When(when) => when.shift(by),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> File {
+        let tokens = Lexer::new(source, None).tokenize().unwrap();
+        Parser::new(tokens, source, None).parse().unwrap()
+    }
+
+    #[test]
+    fn test_shift_matches_parse_after_inserted_lines() {
+        let source =
+            "let a = 1\nserver {\n  port: a + 1\n  tags: [for t in [\"x\"] { \"${t}\" }]\n}\n";
+        let mut shifted = parse(source);
+        shifted.preamble.shift(Offset { lines: 2, bytes: 2 });
+        shifted.body.shift(Offset { lines: 2, bytes: 2 });
+
+        let moved = parse(&format!("\n\n{}", source));
+        assert_eq!(shifted.preamble, moved.preamble);
+        assert_eq!(shifted.body, moved.body);
+    }
+}
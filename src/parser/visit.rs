@@ -0,0 +1,467 @@
+//! Walking the AST
+//!
+//! [`Visit`] walks a parsed file by reference and [`VisitMut`] by mutable
+//! reference, so linters, codemods and documentation generators can find
+//! the nodes they care about without matching on every variant. Each
+//! `visit_*` method defaults to the `walk_*` function of the same name,
+//! which visits the node's children in source order; override a method to
+//! act on that kind of node, and call the `walk_*` function from it to keep
+//! descending.
+//!
+//! Nodes added in later releases get `visit_*` methods with the same
+//! default, and new variants are walked by the existing `walk_*` functions,
+//! so a visitor keeps compiling and still sees every expression.
+//!
+//! ```
+//! use hone::ast::Expr;
+//! use hone::parser::visit::{walk_expr, Visit};
+//! use hone::{Lexer, Parser};
+//!
+//! /// Names of the functions a file calls
+//! #[derive(Default)]
+//! struct Calls(Vec<String>);
+//!
+//! impl Visit for Calls {
+//!     fn visit_expr(&mut self, expr: &Expr) {
+//!         if let Expr::Call(call) = expr {
+//!             if let Expr::Ident(name, _) = &*call.func {
+//!                 self.0.push(name.clone());
+//!             }
+//!         }
+//!         walk_expr(self, expr);
+//!     }
+//! }
+//!
+//! let source = "name: upper(trim(\" api \"))\n";
+//! let tokens = Lexer::new(source, None).tokenize().unwrap();
+//! let file = Parser::new(tokens, source, None).parse().unwrap();
+//! let mut calls = Calls::default();
+//! calls.visit_file(&file);
+//! assert_eq!(calls.0, ["upper", "trim"]);
+//! ```
+
+use super::ast::*;
+
+/// Define a visitor trait and its walk functions, once for `&` and once
+/// for `&mut`, from one description of the tree
+macro_rules! visitor {
+    (
+        $(#[$doc:meta])*
+        trait $Visit:ident [$($m:tt)?] {
+            file: $visit_file:ident, $walk_file:ident;
+            document: $visit_document:ident, $walk_document:ident;
+            preamble_item: $visit_preamble_item:ident, $walk_preamble_item:ident;
+            body_item: $visit_body_item:ident, $walk_body_item:ident;
+            let_binding: $visit_let:ident, $walk_let:ident;
+            key_value: $visit_key_value:ident, $walk_key_value:ident;
+            when: $visit_when:ident, $walk_when:ident;
+            for_loop: $visit_for:ident, $walk_for:ident;
+            expr: $visit_expr:ident, $walk_expr:ident;
+            string: $visit_string:ident, $walk_string:ident;
+            type_expr: $visit_type_expr:ident, $walk_type_expr:ident;
+        }
+    ) => {
+        $(#[$doc])*
+        pub trait $Visit {
+            fn $visit_file(&mut self, file: &$($m)? File) {
+                $walk_file(self, file);
+            }
+
+            /// A named document after a `---` separator
+            fn $visit_document(&mut self, document: &$($m)? Document) {
+                $walk_document(self, document);
+            }
+
+            fn $visit_preamble_item(&mut self, item: &$($m)? PreambleItem) {
+                $walk_preamble_item(self, item);
+            }
+
+            fn $visit_body_item(&mut self, item: &$($m)? BodyItem) {
+                $walk_body_item(self, item);
+            }
+
+            /// A `let`, in the preamble or a body
+            fn $visit_let(&mut self, binding: &$($m)? LetBinding) {
+                $walk_let(self, binding);
+            }
+
+            fn $visit_key_value(&mut self, kv: &$($m)? KeyValue) {
+                $walk_key_value(self, kv);
+            }
+
+            /// A `when` block, in a body, an array or as an expression
+            fn $visit_when(&mut self, when: &$($m)? WhenBlock) {
+                $walk_when(self, when);
+            }
+
+            /// A `for` loop, in a body, an array or as an expression
+            fn $visit_for(&mut self, for_loop: &$($m)? ForLoop) {
+                $walk_for(self, for_loop);
+            }
+
+            fn $visit_expr(&mut self, expr: &$($m)? Expr) {
+                $walk_expr(self, expr);
+            }
+
+            /// A string, as an expression or the path of a `from`, `import`
+            /// or `include`
+            fn $visit_string(&mut self, string: &$($m)? StringExpr) {
+                $walk_string(self, string);
+            }
+
+            /// The type of a `type` alias
+            fn $visit_type_expr(&mut self, ty: &$($m)? TypeExpr) {
+                $walk_type_expr(self, ty);
+            }
+        }
+
+        pub fn $walk_file<V: $Visit + ?Sized>(v: &mut V, file: &$($m)? File) {
+            for item in &$($m)? file.preamble {
+                v.$visit_preamble_item(item);
+            }
+            for item in &$($m)? file.body {
+                v.$visit_body_item(item);
+            }
+            for document in &$($m)? file.documents {
+                v.$visit_document(document);
+            }
+        }
+
+        pub fn $walk_document<V: $Visit + ?Sized>(v: &mut V, document: &$($m)? Document) {
+            for item in &$($m)? document.preamble {
+                v.$visit_preamble_item(item);
+            }
+            for item in &$($m)? document.body {
+                v.$visit_body_item(item);
+            }
+        }
+
+        pub fn $walk_preamble_item<V: $Visit + ?Sized>(v: &mut V, item: &$($m)? PreambleItem) {
+            match item {
+                PreambleItem::Let(binding) => v.$visit_let(binding),
+                PreambleItem::From(from) => v.$visit_string(&$($m)? from.path),
+                PreambleItem::Import(import) => match &$($m)? import.kind {
+                    ImportKind::Whole { path, .. } | ImportKind::Named { path, .. } => {
+                        v.$visit_string(path)
+                    }
+                },
+                PreambleItem::Schema(schema) => {
+                    for field in &$($m)? schema.fields {
+                        for arg in &$($m)? field.constraint.args {
+                            v.$visit_expr(arg);
+                        }
+                        for arg in &$($m)? field.constraint.named_args {
+                            v.$visit_expr(&$($m)? arg.value);
+                        }
+                        if let Some(default) = &$($m)? field.default {
+                            v.$visit_expr(default);
+                        }
+                    }
+                }
+                PreambleItem::TypeAlias(alias) => v.$visit_type_expr(&$($m)? alias.base_type),
+                PreambleItem::Variant(variant) => {
+                    for case in &$($m)? variant.cases {
+                        for item in &$($m)? case.body {
+                            v.$visit_body_item(item);
+                        }
+                    }
+                }
+                PreambleItem::Expect(expect) => {
+                    if let Some(default) = &$($m)? expect.default {
+                        v.$visit_expr(default);
+                    }
+                }
+                PreambleItem::Policy(policy) => v.$visit_expr(&$($m)? policy.condition),
+                PreambleItem::FnDef(def) => v.$visit_expr(&$($m)? def.body),
+                PreambleItem::Enum(_) | PreambleItem::Use(_) | PreambleItem::Secret(_) => {}
+            }
+        }
+
+        pub fn $walk_body_item<V: $Visit + ?Sized>(v: &mut V, item: &$($m)? BodyItem) {
+            match item {
+                BodyItem::KeyValue(kv) => v.$visit_key_value(kv),
+                BodyItem::Block(block) => {
+                    for item in &$($m)? block.items {
+                        v.$visit_body_item(item);
+                    }
+                }
+                BodyItem::When(when) => v.$visit_when(when),
+                BodyItem::For(for_loop) => v.$visit_for(for_loop),
+                BodyItem::Assert(assert) => {
+                    v.$visit_expr(&$($m)? assert.condition);
+                    if let Some(message) = &$($m)? assert.message {
+                        v.$visit_expr(message);
+                    }
+                }
+                BodyItem::Let(binding) => v.$visit_let(binding),
+                BodyItem::Spread(spread) => v.$visit_expr(&$($m)? spread.expr),
+                BodyItem::Include(include) => v.$visit_string(&$($m)? include.path),
+                BodyItem::Use(_) => {}
+            }
+        }
+
+        pub fn $walk_let<V: $Visit + ?Sized>(v: &mut V, binding: &$($m)? LetBinding) {
+            v.$visit_expr(&$($m)? binding.value);
+        }
+
+        pub fn $walk_key_value<V: $Visit + ?Sized>(v: &mut V, kv: &$($m)? KeyValue) {
+            if let Key::Computed(key) = &$($m)? kv.key {
+                v.$visit_expr(key);
+            }
+            v.$visit_expr(&$($m)? kv.value);
+        }
+
+        pub fn $walk_when<V: $Visit + ?Sized>(v: &mut V, when: &$($m)? WhenBlock) {
+            v.$visit_expr(&$($m)? when.condition);
+            for item in &$($m)? when.body {
+                v.$visit_body_item(item);
+            }
+            match &$($m)? when.else_branch {
+                Some(ElseBranch::ElseWhen(when)) => v.$visit_when(when),
+                Some(ElseBranch::Else(items, _)) => {
+                    for item in items {
+                        v.$visit_body_item(item);
+                    }
+                }
+                None => {}
+            }
+        }
+
+        pub fn $walk_for<V: $Visit + ?Sized>(v: &mut V, for_loop: &$($m)? ForLoop) {
+            v.$visit_expr(&$($m)? for_loop.iterable);
+            if let Some(filter) = &$($m)? for_loop.filter {
+                v.$visit_expr(filter);
+            }
+            match &$($m)? for_loop.body {
+                ForBody::Object(items) => {
+                    for item in items {
+                        v.$visit_body_item(item);
+                    }
+                }
+                ForBody::Expr(expr) => v.$visit_expr(expr),
+                ForBody::Block(items, expr) => {
+                    for item in items {
+                        v.$visit_body_item(item);
+                    }
+                    v.$visit_expr(expr);
+                }
+            }
+        }
+
+        pub fn $walk_expr<V: $Visit + ?Sized>(v: &mut V, expr: &$($m)? Expr) {
+            match expr {
+                Expr::Null(_)
+                | Expr::Bool(..)
+                | Expr::Integer(..)
+                | Expr::Float(..)
+                | Expr::Duration(..)
+                | Expr::Size(..)
+                | Expr::Ident(..) => {}
+                Expr::String(string) => v.$visit_string(string),
+                Expr::Path(path) => {
+                    for part in &$($m)? path.parts {
+                        if let PathPart::Index(index) = part {
+                            v.$visit_expr(index);
+                        }
+                    }
+                }
+                Expr::Array(array) => {
+                    for element in &$($m)? array.elements {
+                        match element {
+                            ArrayElement::Expr(expr) | ArrayElement::Spread(expr) => {
+                                v.$visit_expr(expr)
+                            }
+                            ArrayElement::For(for_loop) => v.$visit_for(for_loop),
+                            ArrayElement::When(when) => v.$visit_when(when),
+                        }
+                    }
+                }
+                Expr::Object(object) | Expr::Block(object) => {
+                    for item in &$($m)? object.items {
+                        v.$visit_body_item(item);
+                    }
+                }
+                Expr::Binary(binary) => {
+                    v.$visit_expr(&$($m)? binary.left);
+                    v.$visit_expr(&$($m)? binary.right);
+                }
+                Expr::Unary(unary) => v.$visit_expr(&$($m)? unary.operand),
+                Expr::Call(call) => {
+                    v.$visit_expr(&$($m)? call.func);
+                    for arg in &$($m)? call.args {
+                        v.$visit_expr(arg);
+                    }
+                }
+                Expr::Index(index) => {
+                    v.$visit_expr(&$($m)? index.base);
+                    v.$visit_expr(&$($m)? index.index);
+                }
+                Expr::Conditional(cond) => {
+                    v.$visit_expr(&$($m)? cond.condition);
+                    v.$visit_expr(&$($m)? cond.then_branch);
+                    v.$visit_expr(&$($m)? cond.else_branch);
+                }
+                Expr::Annotated(annotated) => {
+                    v.$visit_expr(&$($m)? annotated.expr);
+                    for arg in &$($m)? annotated.constraint.args {
+                        v.$visit_expr(arg);
+                    }
+                    for arg in &$($m)? annotated.constraint.named_args {
+                        v.$visit_expr(&$($m)? arg.value);
+                    }
+                }
+                Expr::Paren(inner, _) => v.$visit_expr(inner),
+                Expr::For(for_loop) => v.$visit_for(for_loop),
+                Expr::When(when) => v.$visit_when(when),
+                Expr::Match(m) => {
+                    v.$visit_expr(&$($m)? m.subject);
+                    for arm in &$($m)? m.arms {
+                        for pattern in &$($m)? arm.patterns {
+                            v.$visit_expr(pattern);
+                        }
+                        v.$visit_expr(&$($m)? arm.value);
+                    }
+                }
+                Expr::Lambda(lambda) => v.$visit_expr(&$($m)? lambda.body),
+            }
+        }
+
+        pub fn $walk_string<V: $Visit + ?Sized>(v: &mut V, string: &$($m)? StringExpr) {
+            for part in &$($m)? string.parts {
+                if let StringPart::Interpolation(expr) | StringPart::Formatted(expr, _) = part {
+                    v.$visit_expr(expr);
+                }
+            }
+        }
+
+        pub fn $walk_type_expr<V: $Visit + ?Sized>(v: &mut V, ty: &$($m)? TypeExpr) {
+            match ty {
+                TypeExpr::Named { args, named_args, .. } => {
+                    for arg in args {
+                        v.$visit_expr(arg);
+                    }
+                    for arg in named_args {
+                        v.$visit_expr(&$($m)? arg.value);
+                    }
+                }
+                TypeExpr::Array(inner) | TypeExpr::Optional(inner) => v.$visit_type_expr(inner),
+                TypeExpr::Union(types) => {
+                    for ty in types {
+                        v.$visit_type_expr(ty);
+                    }
+                }
+                TypeExpr::StringLiteral(_) => {}
+            }
+        }
+    };
+}
+
+visitor! {
+    /// Walks a file by reference; see the [module docs](self)
+    trait Visit [] {
+        file: visit_file, walk_file;
+        document: visit_document, walk_document;
+        preamble_item: visit_preamble_item, walk_preamble_item;
+        body_item: visit_body_item, walk_body_item;
+        let_binding: visit_let, walk_let;
+        key_value: visit_key_value, walk_key_value;
+        when: visit_when, walk_when;
+        for_loop: visit_for, walk_for;
+        expr: visit_expr, walk_expr;
+        string: visit_string, walk_string;
+        type_expr: visit_type_expr, walk_type_expr;
+    }
+}
+
+visitor! {
+    /// Walks a file by mutable reference, for rewriting it in place; see the
+    /// [module docs](self)
+    trait VisitMut [mut] {
+        file: visit_file_mut, walk_file_mut;
+        document: visit_document_mut, walk_document_mut;
+        preamble_item: visit_preamble_item_mut, walk_preamble_item_mut;
+        body_item: visit_body_item_mut, walk_body_item_mut;
+        let_binding: visit_let_mut, walk_let_mut;
+        key_value: visit_key_value_mut, walk_key_value_mut;
+        when: visit_when_mut, walk_when_mut;
+        for_loop: visit_for_mut, walk_for_mut;
+        expr: visit_expr_mut, walk_expr_mut;
+        string: visit_string_mut, walk_string_mut;
+        type_expr: visit_type_expr_mut, walk_type_expr_mut;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> File {
+        let tokens = Lexer::new(source, None).tokenize().unwrap();
+        Parser::new(tokens, source, None).parse().unwrap()
+    }
+
+    /// Every identifier, in the order visited
+    #[derive(Default)]
+    struct Idents(Vec<String>);
+
+    impl Visit for Idents {
+        fn visit_expr(&mut self, expr: &Expr) {
+            if let Expr::Ident(name, _) = expr {
+                self.0.push(name.clone());
+            }
+            walk_expr(self, expr);
+        }
+    }
+
+    #[test]
+    fn test_visit_reaches_every_expression() {
+        let file = parse(
+            r#"
+type Port = int(a, 65535)
+schema S { port: int(b) = c }
+fn double(x) { x * d }
+let e = [for i in f where g { "${h:>3}" }]
+server {
+  [k]: when l { m: n } else { o: p }
+  ...q
+}
+assert r : s
+---web
+t: match u { v => (w) => y }
+"#,
+        );
+        let mut idents = Idents::default();
+        idents.visit_file(&file);
+        let expected: Vec<String> = "a b c x d f g h k l n p q r s u v y"
+            .split(' ')
+            .map(String::from)
+            .collect();
+        assert_eq!(idents.0, expected);
+    }
+
+    /// Renames one variable everywhere it is read
+    struct Rename(&'static str, &'static str);
+
+    impl VisitMut for Rename {
+        fn visit_expr_mut(&mut self, expr: &mut Expr) {
+            if let Expr::Ident(name, _) = expr {
+                if name == self.0 {
+                    *name = self.1.to_string();
+                }
+            }
+            walk_expr_mut(self, expr);
+        }
+    }
+
+    #[test]
+    fn test_visit_mut_rewrites_in_place() {
+        let mut file = parse("let a = 1\nx: a + 1\ny: \"${a}\"\nz { w: [a] }\n");
+        Rename("a", "b").visit_file_mut(&mut file);
+        assert_eq!(
+            file,
+            parse("let a = 1\nx: b + 1\ny: \"${b}\"\nz { w: [b] }\n")
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Data files: `.json`, `.yaml`/`.yml`, `.toml` and `.csv` imports
+//!
+//! A data file is parsed into a value when it is resolved, so
+//! `import "./regions.json" as regions` binds `regions` to the file's
+//! contents. Data files are tracked inputs like any imported `.hone` file,
+//! which keeps them deterministic without `--allow-env`.
+//!
+//! A CSV file becomes an array of objects keyed by its header row, with
+//! every field a string; convert with `to_int()` and friends where needed.
+
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+
+use crate::errors::{HoneError, HoneResult};
+use crate::evaluator::Value;
+use crate::lexer::token::SourceLocation;
+use crate::parser::ast::File;
+
+/// Key the `toml` crate uses to carry a datetime through serde
+const TOML_DATETIME: &str = "$__toml_private_datetime";
+
+/// Whether `path` names a data file rather than Hone source
+pub fn is_data_file(path: &Path) -> bool {
+    format_of(path).is_some()
+}
+
+fn format_of(path: &Path) -> Option<&'static str> {
+    match path.extension()?.to_str()? {
+        "json" => Some("JSON"),
+        "yaml" | "yml" => Some("YAML"),
+        "toml" => Some("TOML"),
+        "csv" => Some("CSV"),
+        _ => None,
+    }
+}
+
+/// Parse the data file at `path`, whose contents are `source`
+pub fn parse(path: &Path, source: &str) -> HoneResult<Value> {
+    let format = format_of(path).unwrap_or("JSON");
+    let parsed = match format {
+        "YAML" => serde_yaml::from_str(source).map_err(|e| e.to_string()),
+        "TOML" => return parse_toml(source).map_err(|e| invalid(path, format, e)),
+        "CSV" => return parse_csv(source).map_err(|e| invalid(path, format, e)),
+        _ => serde_json::from_str(source).map_err(|e| e.to_string()),
+    };
+    parsed
+        .map(Value::from_serde_json)
+        .map_err(|e| invalid(path, format, e))
+}
+
+/// Parse a TOML document, with datetimes as their text
+pub(crate) fn parse_toml(source: &str) -> Result<Value, String> {
+    toml::from_str(source)
+        .map(|value| Value::from_serde_json(toml_datetimes(value)))
+        .map_err(|e| e.to_string())
+}
+
+fn invalid(path: &Path, format: &str, reason: String) -> HoneError {
+    HoneError::io_error(format!(
+        "invalid {} in {}: {}",
+        format,
+        path.display(),
+        reason.trim_end()
+    ))
+}
+
+/// The empty AST a data file stands in the import graph with
+pub fn empty_ast(path: &Path) -> File {
+    File {
+        preamble: Vec::new(),
+        body: Vec::new(),
+        documents: Vec::new(),
+        location: SourceLocation::new(Some(PathBuf::from(path)), 1, 1, 0, 0),
+    }
+}
+
+/// Replace the tables the `toml` crate makes of datetimes with their text
+fn toml_datetimes(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(mut obj) => {
+            if obj.len() == 1 {
+                if let Some(serde_json::Value::String(s)) = obj.get(TOML_DATETIME) {
+                    return serde_json::Value::String(s.clone());
+                }
+            }
+            for v in obj.values_mut() {
+                *v = toml_datetimes(v.take());
+            }
+            serde_json::Value::Object(obj)
+        }
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.into_iter().map(toml_datetimes).collect())
+        }
+        other => other,
+    }
+}
+
+/// An RFC 4180 CSV file as an array of objects keyed by the header row
+fn parse_csv(source: &str) -> Result<Value, String> {
+    let mut rows = csv_records(source)?.into_iter();
+    let Some((_, header)) = rows.next() else {
+        return Ok(Value::Array(Vec::new()));
+    };
+    for (i, name) in header.iter().enumerate() {
+        if header[..i].contains(name) {
+            return Err(format!("column '{}' appears twice in the header", name));
+        }
+    }
+
+    let mut records = Vec::new();
+    for (line, fields) in rows {
+        if fields.len() != header.len() {
+            return Err(format!(
+                "line {} has {} of the header's {} fields",
+                line,
+                fields.len(),
+                header.len()
+            ));
+        }
+        let record: IndexMap<String, Value> = header
+            .iter()
+            .cloned()
+            .zip(fields.into_iter().map(Value::String))
+            .collect();
+        records.push(Value::Object(record));
+    }
+    Ok(Value::Array(records))
+}
+
+/// The records of a CSV file with the line each starts on. Blank lines
+/// are skipped.
+fn csv_records(source: &str) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let mut records = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut line = 1;
+
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        loop {
+            match chars.next() {
+                Some('"') if field.is_empty() && !quoted => {
+                    quoted = true;
+                    loop {
+                        match chars.next() {
+                            Some('"') if chars.peek() == Some(&'"') => {
+                                chars.next();
+                                field.push('"');
+                            }
+                            Some('"') => break,
+                            Some(c) => {
+                                if c == '\n' {
+                                    line += 1;
+                                }
+                                field.push(c);
+                            }
+                            None => return Err(format!("line {} has an unclosed quote", start)),
+                        }
+                    }
+                    if !matches!(chars.peek(), None | Some(',' | '\r' | '\n')) {
+                        return Err(format!(
+                            "line {} has text after a closing quote; write a quote inside a field as \"\"",
+                            line
+                        ));
+                    }
+                }
+                Some(',') => {
+                    fields.push(std::mem::take(&mut field));
+                    quoted = false;
+                }
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some('\n') | None => {
+                    line += 1;
+                    break;
+                }
+                Some(c) => field.push(c),
+            }
+        }
+        if fields.is_empty() && field.is_empty() && !quoted {
+            continue;
+        }
+        fields.push(field);
+        records.push((start, fields));
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_as(name: &str, source: &str) -> HoneResult<Value> {
+        parse(Path::new(name), source)
+    }
+
+    #[test]
+    fn test_parse_data_formats() {
+        let json = parse_as("a.json", r#"{"b": 2, "a": [1, 2.5, null]}"#).unwrap();
+        assert_eq!(json.to_string(), r#"{b: 2, a: [1, 2.5, null]}"#);
+
+        let yaml = parse_as("a.yml", "name: api\nports:\n  - 80\n  - 443\n").unwrap();
+        assert_eq!(yaml.get_path(&["name"]), Some(&Value::String("api".into())));
+        assert_eq!(
+            yaml.get_path(&["ports"]),
+            Some(&Value::Array(vec![Value::Int(80), Value::Int(443)]))
+        );
+
+        let toml = parse_as(
+            "a.toml",
+            "zone = \"b\"\nat = 1979-05-27T07:32:00Z\n[limits]\ncpu = 2\n",
+        )
+        .unwrap();
+        let Value::Object(obj) = &toml else {
+            panic!("expected an object")
+        };
+        assert_eq!(obj.keys().collect::<Vec<_>>(), ["zone", "at", "limits"]);
+        assert_eq!(obj["at"], Value::String("1979-05-27T07:32:00Z".into()));
+
+        let err = parse_as("bad.json", "{").unwrap_err().to_string();
+        assert!(err.contains("invalid JSON in bad.json"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let csv = parse_as(
+            "a.csv",
+            "name,notes\r\napi,\"says \"\"hi\"\", twice\"\n\nweb,\"two\nlines\"\n",
+        )
+        .unwrap();
+        let Value::Array(rows) = csv else {
+            panic!("expected an array")
+        };
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[0].get_path(&["notes"]),
+            Some(&Value::String("says \"hi\", twice".into()))
+        );
+        assert_eq!(
+            rows[1].get_path(&["notes"]),
+            Some(&Value::String("two\nlines".into()))
+        );
+        assert_eq!(parse_as("a.csv", "").unwrap(), Value::Array(Vec::new()));
+        assert_eq!(
+            parse_as("a.csv", "a,b\n1,\n").unwrap(),
+            parse_as("a.json", r#"[{"a": "1", "b": ""}]"#).unwrap()
+        );
+
+        for (source, reason) in [
+            ("a,b\n1,2,3\n", "line 2 has 3 of the header's 2 fields"),
+            ("a,a\n", "column 'a' appears twice"),
+            ("a\n\"open\n", "line 2 has an unclosed quote"),
+            ("a\n\"x\"y\n", "text after a closing quote"),
+        ] {
+            let err = parse_as("a.csv", source).unwrap_err().to_string();
+            assert!(err.contains(reason), "{}: {}", source, err);
+        }
+    }
+}
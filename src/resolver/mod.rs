@@ -8,14 +8,20 @@
 
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use crate::errors::{HoneError, HoneResult};
+use crate::evaluator::Value;
+use crate::lexer::token::SourceLocation;
 use crate::lexer::Lexer;
 use crate::parser::ast::{
-    File, FromStatement, ImportKind, ImportStatement, PreambleItem, StringPart,
+    BodyItem, ElseBranch, File, FromStatement, ImportKind, ImportStatement, IncludeStatement,
+    PreambleItem, StringPart, WhenBlock,
 };
 use crate::parser::Parser;
 
+pub mod data;
+
 /// Normalize a path by resolving `.` and `..` components
 fn normalize_path(path: &Path) -> PathBuf {
     let mut components = Vec::new();
@@ -40,6 +46,224 @@ fn normalize_path(path: &Path) -> PathBuf {
     components.iter().collect()
 }
 
+/// Whether source paths keep their symlinks; see [`set_preserve_symlinks`]
+static PRESERVE_SYMLINKS: AtomicBool = AtomicBool::new(false);
+
+/// Resolve source paths without following symlinks. Build sandboxes lay
+/// inputs out as symlinks into the source tree; following them would
+/// resolve imports next to the real file rather than inside the sandbox.
+pub fn set_preserve_symlinks(preserve: bool) {
+    PRESERVE_SYMLINKS.store(preserve, Ordering::Relaxed);
+}
+
+/// Absolute path of an existing file: canonical, or with `.` and `..`
+/// removed lexically while symlinks are preserved
+pub fn canonicalize(path: &Path) -> std::io::Result<PathBuf> {
+    absolute_path(path, PRESERVE_SYMLINKS.load(Ordering::Relaxed))
+}
+
+fn absolute_path(path: &Path, preserve_symlinks: bool) -> std::io::Result<PathBuf> {
+    if !preserve_symlinks {
+        return path.canonicalize();
+    }
+    let absolute = normalize_path(&std::env::current_dir()?.join(path));
+    std::fs::metadata(&absolute)?;
+    Ok(absolute)
+}
+
+/// How a file depends on another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edge {
+    From,
+    Import,
+    Include,
+}
+
+impl Edge {
+    fn keyword(self) -> &'static str {
+        match self {
+            Edge::From => "from",
+            Edge::Import => "import",
+            Edge::Include => "include",
+        }
+    }
+}
+
+/// A `from`, `import` or `include` statement and the file it names
+struct Dependency {
+    path: PathBuf,
+    edge: Edge,
+    location: SourceLocation,
+}
+
+/// A file's dependencies, as `ResolvedFile` lists them and statement by
+/// statement
+struct Dependencies {
+    from_path: Option<PathBuf>,
+    import_paths: Vec<PathBuf>,
+    include_paths: Vec<PathBuf>,
+    statements: Vec<Dependency>,
+}
+
+impl Dependencies {
+    fn new() -> Self {
+        Self {
+            from_path: None,
+            import_paths: Vec::new(),
+            include_paths: Vec::new(),
+            statements: Vec::new(),
+        }
+    }
+}
+
+/// The error for `closing`, a statement in `source` that names the first
+/// file of `cycle`: the files being resolved, each with the edge that
+/// reached it.
+///
+/// A cycle of only imports or only `from`s is a circular import. One that
+/// mixes them means a base depends on a file built `from` it, which gets
+/// its own error naming the base and the overlay.
+fn cycle_error(cycle: &[(PathBuf, Option<Edge>)], closing: &Dependency, source: &str) -> HoneError {
+    let edges: Vec<Edge> = cycle
+        .iter()
+        .skip(1)
+        .filter_map(|(_, edge)| *edge)
+        .chain(std::iter::once(closing.edge))
+        .collect();
+    let span = (closing.location.offset, closing.location.length).into();
+    let mixed = edges.contains(&Edge::From) && edges.contains(&Edge::Import);
+
+    if !mixed {
+        let mut parts: Vec<String> = cycle.iter().map(|(p, _)| p.display().to_string()).collect();
+        parts.push(closing.path.display().to_string());
+        return HoneError::CircularImport {
+            src: source.to_string(),
+            span,
+            chain: parts.join(" -> "),
+        };
+    }
+
+    let mut chain = cycle[0].0.display().to_string();
+    for (path, edge) in cycle[1..]
+        .iter()
+        .map(|(path, _)| path)
+        .chain(std::iter::once(&closing.path))
+        .zip(&edges)
+        .map(|(path, edge)| (path, *edge))
+    {
+        chain.push_str(&format!(" --{}--> {}", edge.keyword(), path.display()));
+    }
+
+    // Name the first `from` in the cycle: its base is what must not depend
+    // on the overlay
+    let files: Vec<&Path> = cycle
+        .iter()
+        .map(|(path, _)| path.as_path())
+        .chain(std::iter::once(closing.path.as_path()))
+        .collect();
+    let at = edges.iter().position(|edge| *edge == Edge::From).unwrap();
+    let name = |path: &Path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string())
+    };
+    let (overlay, base) = (name(files[at]), name(files[at + 1]));
+    HoneError::CircularFrom {
+        src: source.to_string(),
+        span,
+        chain,
+        help: format!(
+            "{} is built `from` {}, so {} cannot depend on {}, directly or through \
+             other files; move what both need into a third file that each imports",
+            overlay, base, base, overlay
+        ),
+    }
+}
+
+/// The `include` statements of `ast` in statement position: file and
+/// document bodies, blocks, `when` branches and variant cases. An
+/// `include` inside an expression is left unresolved, which the
+/// evaluator reports.
+fn include_statements(ast: &mut File) -> Vec<&mut IncludeStatement> {
+    fn visit<'a>(items: &'a mut [BodyItem], out: &mut Vec<&'a mut IncludeStatement>) {
+        for item in items {
+            match item {
+                BodyItem::Include(include) => out.push(include),
+                BodyItem::Block(block) => visit(&mut block.items, out),
+                BodyItem::When(when) => visit_when(when, out),
+                _ => {}
+            }
+        }
+    }
+    fn visit_when<'a>(when: &'a mut WhenBlock, out: &mut Vec<&'a mut IncludeStatement>) {
+        visit(&mut when.body, out);
+        match &mut when.else_branch {
+            Some(ElseBranch::ElseWhen(next)) => visit_when(next, out),
+            Some(ElseBranch::Else(body, _)) => visit(body, out),
+            None => {}
+        }
+    }
+    fn visit_preamble<'a>(
+        preamble: &'a mut [PreambleItem],
+        out: &mut Vec<&'a mut IncludeStatement>,
+    ) {
+        for item in preamble {
+            if let PreambleItem::Variant(variant) = item {
+                for case in &mut variant.cases {
+                    visit(&mut case.body, out);
+                }
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    visit_preamble(&mut ast.preamble, &mut out);
+    visit(&mut ast.body, &mut out);
+    for doc in &mut ast.documents {
+        visit_preamble(&mut doc.preamble, &mut out);
+        visit(&mut doc.body, &mut out);
+    }
+    out
+}
+
+/// Check that `fragment`, named by the `include` statement `dependency` in
+/// `source`, holds only body items and `let`s
+fn check_fragment(
+    fragment: &ResolvedFile,
+    dependency: &Dependency,
+    source: &str,
+) -> HoneResult<()> {
+    let found = if fragment.data.is_some() {
+        Some("data rather than Hone source")
+    } else if !fragment.ast.documents.is_empty() {
+        Some("documents")
+    } else {
+        fragment.ast.preamble.iter().find_map(|item| match item {
+            PreambleItem::Let(_) => None,
+            PreambleItem::From(_) => Some("a `from`"),
+            PreambleItem::Import(_) => Some("an `import`"),
+            PreambleItem::Schema(_) => Some("a `schema`"),
+            PreambleItem::TypeAlias(_) => Some("a `type`"),
+            PreambleItem::Enum(_) => Some("an `enum`"),
+            PreambleItem::Use(_) => Some("a `use`"),
+            PreambleItem::Variant(_) => Some("a `variant`"),
+            PreambleItem::Expect(_) => Some("an `expect`"),
+            PreambleItem::Secret(_) => Some("a `secret`"),
+            PreambleItem::Policy(_) => Some("a `policy`"),
+            PreambleItem::FnDef(_) => Some("a `fn`"),
+        })
+    };
+    match found {
+        Some(found) => Err(HoneError::InvalidFragment {
+            src: source.to_string(),
+            span: (dependency.location.offset, dependency.location.length).into(),
+            path: dependency.path.display().to_string(),
+            found: found.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
 /// A resolved and parsed file with its dependencies
 #[derive(Debug)]
 pub struct ResolvedFile {
@@ -53,14 +277,20 @@ pub struct ResolvedFile {
     pub from_path: Option<PathBuf>,
     /// Files this file imports
     pub import_paths: Vec<PathBuf>,
+    /// Fragments this file splices in (via `include`)
+    pub include_paths: Vec<PathBuf>,
+    /// The parsed contents of a data file (`.json`, `.yaml`, `.toml`,
+    /// `.csv`), whose AST is empty
+    pub data: Option<Value>,
 }
 
 /// Import resolver that handles file loading and circular import detection
 pub struct ImportResolver {
     /// Cache of already-resolved files
     cache: HashMap<PathBuf, ResolvedFile>,
-    /// Stack of files currently being resolved (for cycle detection)
-    resolution_stack: Vec<PathBuf>,
+    /// Stack of files currently being resolved, each with the edge that
+    /// reached it (for cycle detection)
+    resolution_stack: Vec<(PathBuf, Option<Edge>)>,
     /// Base directory for resolving paths (if not absolute)
     base_dir: PathBuf,
 }
@@ -78,62 +308,99 @@ impl ImportResolver {
     /// Resolve a file and all its dependencies
     pub fn resolve(&mut self, path: impl AsRef<Path>) -> HoneResult<&ResolvedFile> {
         let path = self.canonicalize_path(path.as_ref())?;
-
-        // Check if already resolved
-        if self.cache.contains_key(&path) {
-            return Ok(self.cache.get(&path).unwrap());
-        }
-
-        // Check for circular import
-        if self.resolution_stack.contains(&path) {
-            let cycle = self.format_cycle(&path);
+        if self.resolution_stack.iter().any(|(p, _)| *p == path) {
             return Err(HoneError::CircularImport {
                 src: String::new(),
                 span: (0, 0).into(),
-                chain: cycle,
+                chain: self.format_cycle(&path),
             });
         }
+        self.resolve_reached(path, None)
+    }
 
-        // Push onto resolution stack
-        self.resolution_stack.push(path.clone());
-
-        // Read and parse the file
-        let source = std::fs::read_to_string(&path).map_err(|e| {
-            HoneError::io_error(format!("failed to read {}: {}", path.display(), e))
-        })?;
-
-        let mut lexer = Lexer::new(&source, Some(path.clone()));
-        let tokens = lexer.tokenize()?;
-        let mut parser = Parser::new(tokens, &source, Some(path.clone()));
-        let ast = parser.parse()?;
-
-        // Extract dependencies
-        let (from_path, import_paths) = self.extract_dependencies(&ast, &path)?;
-
-        // Recursively resolve dependencies
-        if let Some(ref from) = from_path {
-            self.resolve(from)?;
+    /// Resolve a dependency of the file on top of the resolution stack,
+    /// whose source is `source`
+    fn resolve_dependency(&mut self, dependency: &Dependency, source: &str) -> HoneResult<()> {
+        if let Some(start) = self
+            .resolution_stack
+            .iter()
+            .position(|(p, _)| *p == dependency.path)
+        {
+            return Err(cycle_error(
+                &self.resolution_stack[start..],
+                dependency,
+                source,
+            ));
         }
-        for import in &import_paths {
-            self.resolve(import)?;
+        let resolved = self.resolve_reached(dependency.path.clone(), Some(dependency.edge))?;
+        if dependency.edge == Edge::Include {
+            check_fragment(resolved, dependency, source)?;
         }
+        Ok(())
+    }
 
-        // Pop from resolution stack
+    /// Resolve a canonical path that is not being resolved already
+    fn resolve_reached(&mut self, path: PathBuf, edge: Option<Edge>) -> HoneResult<&ResolvedFile> {
+        // Check if already resolved
+        if self.cache.contains_key(&path) {
+            return Ok(self.cache.get(&path).unwrap());
+        }
+
+        // Push onto resolution stack, popping even on error so the resolver
+        // stays usable for other entry points
+        self.resolution_stack.push((path.clone(), edge));
+        let parsed = self.parse_with_dependencies(&path);
         self.resolution_stack.pop();
+        let (source, ast, dependencies, data) = parsed?;
 
         // Cache the resolved file
         let resolved = ResolvedFile {
             path: path.clone(),
             ast,
             source,
-            from_path,
-            import_paths,
+            from_path: dependencies.from_path,
+            import_paths: dependencies.import_paths,
+            include_paths: dependencies.include_paths,
+            data,
         };
 
         self.cache.insert(path.clone(), resolved);
         Ok(self.cache.get(&path).unwrap())
     }
 
+    /// Read and parse a file, then resolve its dependencies
+    fn parse_with_dependencies(
+        &mut self,
+        path: &Path,
+    ) -> HoneResult<(String, File, Dependencies, Option<Value>)> {
+        let source = std::fs::read_to_string(path).map_err(|e| {
+            HoneError::io_error(format!("failed to read {}: {}", path.display(), e))
+        })?;
+        if data::is_data_file(path) {
+            let value = data::parse(path, &source)?;
+            return Ok((
+                source,
+                data::empty_ast(path),
+                Dependencies::new(),
+                Some(value),
+            ));
+        }
+
+        let mut lexer = Lexer::new(&source, Some(path.to_path_buf()));
+        let tokens = lexer.tokenize()?;
+        let mut parser = Parser::new(tokens, &source, Some(path.to_path_buf()));
+        let mut ast = parser.parse()?;
+
+        // Extract and recursively resolve dependencies
+        let mut dependencies = self.extract_dependencies(&ast, path)?;
+        self.extract_includes(&mut ast, path, &mut dependencies)?;
+        for dependency in &dependencies.statements {
+            self.resolve_dependency(dependency, &source)?;
+        }
+
+        Ok((source, ast, dependencies, None))
+    }
+
     /// Resolve a file from source string (for testing or embedded sources)
     pub fn resolve_source(
         &mut self,
@@ -152,18 +419,21 @@ impl ImportResolver {
         let mut lexer = Lexer::new(&source, Some(path.clone()));
         let tokens = lexer.tokenize()?;
         let mut parser = Parser::new(tokens, &source, Some(path.clone()));
-        let ast = parser.parse()?;
+        let mut ast = parser.parse()?;
 
         // Extract dependencies (but don't resolve them - caller is responsible)
-        let (from_path, import_paths) = self.extract_dependencies(&ast, &path)?;
+        let mut dependencies = self.extract_dependencies(&ast, &path)?;
+        self.extract_includes(&mut ast, &path, &mut dependencies)?;
 
         // Cache the resolved file
         let resolved = ResolvedFile {
             path: path.clone(),
             ast,
             source,
-            from_path,
-            import_paths,
+            from_path: dependencies.from_path,
+            import_paths: dependencies.import_paths,
+            include_paths: dependencies.include_paths,
+            data: None,
         };
 
         self.cache.insert(path.clone(), resolved);
@@ -175,6 +445,11 @@ impl ImportResolver {
         self.cache.get(path)
     }
 
+    /// Canonical path of the file `import`, a statement of `file`, names
+    pub fn import_path(&self, import: &ImportStatement, file: &Path) -> HoneResult<PathBuf> {
+        self.resolve_import_path_from_import(import, file.parent().unwrap_or(Path::new(".")))
+    }
+
     /// Get all resolved files
     pub fn files(&self) -> impl Iterator<Item = &ResolvedFile> {
         self.cache.values()
@@ -214,19 +489,17 @@ impl ImportResolver {
         for import in &resolved.import_paths {
             self.visit_topological(import, visited, result)?;
         }
+        for include in &resolved.include_paths {
+            self.visit_topological(include, visited, result)?;
+        }
 
         result.push(resolved);
         Ok(())
     }
 
     /// Extract from and import paths from AST
-    fn extract_dependencies(
-        &self,
-        ast: &File,
-        current_file: &Path,
-    ) -> HoneResult<(Option<PathBuf>, Vec<PathBuf>)> {
-        let mut from_path = None;
-        let mut import_paths = Vec::new();
+    fn extract_dependencies(&self, ast: &File, current_file: &Path) -> HoneResult<Dependencies> {
+        let mut deps = Dependencies::new();
 
         let parent_dir = current_file.parent().unwrap_or(Path::new("."));
 
@@ -235,7 +508,7 @@ impl ImportResolver {
             match item {
                 PreambleItem::From(from) => {
                     let path = self.resolve_import_path(from, parent_dir)?;
-                    if from_path.is_some() {
+                    if deps.from_path.is_some() {
                         // This should be caught by the parser, but double-check
                         return Err(HoneError::MultipleFrom {
                             src: String::new(),
@@ -243,11 +516,21 @@ impl ImportResolver {
                             first_span: (0, 0).into(),
                         });
                     }
-                    from_path = Some(path);
+                    deps.from_path = Some(path.clone());
+                    deps.statements.push(Dependency {
+                        path,
+                        edge: Edge::From,
+                        location: from.location.clone(),
+                    });
                 }
                 PreambleItem::Import(import) => {
                     let path = self.resolve_import_path_from_import(import, parent_dir)?;
-                    import_paths.push(path);
+                    deps.import_paths.push(path.clone());
+                    deps.statements.push(Dependency {
+                        path,
+                        edge: Edge::Import,
+                        location: import.location.clone(),
+                    });
                 }
                 _ => {}
             }
@@ -261,22 +544,59 @@ impl ImportResolver {
                         let path = self.resolve_import_path(from, parent_dir)?;
                         // Each document can have its own `from`
                         // We track all of them as dependencies
-                        if !import_paths.contains(&path) && from_path.as_ref() != Some(&path) {
-                            import_paths.push(path);
+                        if !deps.import_paths.contains(&path)
+                            && deps.from_path.as_ref() != Some(&path)
+                        {
+                            deps.import_paths.push(path.clone());
                         }
+                        deps.statements.push(Dependency {
+                            path,
+                            edge: Edge::From,
+                            location: from.location.clone(),
+                        });
                     }
                     PreambleItem::Import(import) => {
                         let path = self.resolve_import_path_from_import(import, parent_dir)?;
-                        if !import_paths.contains(&path) {
-                            import_paths.push(path);
+                        if !deps.import_paths.contains(&path) {
+                            deps.import_paths.push(path.clone());
                         }
+                        deps.statements.push(Dependency {
+                            path,
+                            edge: Edge::Import,
+                            location: import.location.clone(),
+                        });
                     }
                     _ => {}
                 }
             }
         }
 
-        Ok((from_path, import_paths))
+        Ok(deps)
+    }
+
+    /// Resolve the path of each `include` in `ast`, recording it on the
+    /// statement and as a dependency
+    fn extract_includes(
+        &self,
+        ast: &mut File,
+        current_file: &Path,
+        deps: &mut Dependencies,
+    ) -> HoneResult<()> {
+        let parent_dir = current_file.parent().unwrap_or(Path::new("."));
+        for include in include_statements(ast) {
+            let path_str = self.string_expr_to_string(&include.path)?;
+            let path = self.resolve_path_string(&path_str, parent_dir, &include.location)?;
+            include.resolved = Some(path.clone());
+            if !deps.include_paths.contains(&path) {
+                deps.include_paths.push(path.clone());
+            }
+            deps.statements.push(Dependency {
+                path,
+                edge: Edge::Include,
+                location: include.location.clone(),
+            });
+        }
+        Ok(())
     }
 
     /// Resolve a path from a `from` statement
@@ -305,7 +625,7 @@ impl ImportResolver {
         for part in &expr.parts {
             match part {
                 StringPart::Literal(s) => result.push_str(s),
-                StringPart::Interpolation(_) => {
+                StringPart::Interpolation(_) | StringPart::Formatted(..) => {
                     return Err(HoneError::unexpected_token(
                         String::new(),
                         &expr.location,
@@ -337,7 +657,7 @@ impl ImportResolver {
         let resolved = parent_dir.join(path);
 
         // Try to canonicalize, but if file doesn't exist, return normalized path
-        match resolved.canonicalize() {
+        match canonicalize(&resolved) {
             Ok(canonical) => Ok(canonical),
             Err(_) => {
                 // File doesn't exist
@@ -353,12 +673,12 @@ impl ImportResolver {
     /// Canonicalize a path, handling errors appropriately
     fn canonicalize_path(&self, path: &Path) -> HoneResult<PathBuf> {
         if path.is_absolute() {
-            path.canonicalize().map_err(|e| {
+            canonicalize(path).map_err(|e| {
                 HoneError::io_error(format!("failed to resolve path {}: {}", path.display(), e))
             })
         } else {
             let full = self.base_dir.join(path);
-            full.canonicalize().map_err(|e| {
+            canonicalize(&full).map_err(|e| {
                 HoneError::io_error(format!("failed to resolve path {}: {}", full.display(), e))
             })
         }
@@ -369,6 +689,7 @@ impl ImportResolver {
         let mut cycle_parts: Vec<String> = self
             .resolution_stack
             .iter()
+            .map(|(p, _)| p)
             .skip_while(|p| *p != target)
             .map(|p| p.display().to_string())
             .collect();
@@ -415,7 +736,7 @@ impl TestFixture {
 pub struct VirtualResolver {
     files: HashMap<PathBuf, String>,
     cache: HashMap<PathBuf, ResolvedFile>,
-    resolution_stack: Vec<PathBuf>,
+    resolution_stack: Vec<(PathBuf, Option<Edge>)>,
 }
 
 impl VirtualResolver {
@@ -446,21 +767,43 @@ impl VirtualResolver {
     /// Resolve a virtual file
     pub fn resolve(&mut self, path: impl AsRef<Path>) -> HoneResult<&ResolvedFile> {
         let path = normalize_path(path.as_ref());
-
-        // Check if already resolved
-        if self.cache.contains_key(&path) {
-            return Ok(self.cache.get(&path).unwrap());
-        }
-
-        // Check for circular import
-        if self.resolution_stack.contains(&path) {
-            let cycle = self.format_cycle(&path);
+        if self.resolution_stack.iter().any(|(p, _)| *p == path) {
             return Err(HoneError::CircularImport {
                 src: String::new(),
                 span: (0, 0).into(),
-                chain: cycle,
+                chain: self.format_cycle(&path),
             });
         }
+        self.resolve_reached(path, None)
+    }
+
+    /// Resolve a dependency of the file on top of the resolution stack,
+    /// whose source is `source`
+    fn resolve_dependency(&mut self, dependency: &Dependency, source: &str) -> HoneResult<()> {
+        if let Some(start) = self
+            .resolution_stack
+            .iter()
+            .position(|(p, _)| *p == dependency.path)
+        {
+            return Err(cycle_error(
+                &self.resolution_stack[start..],
+                dependency,
+                source,
+            ));
+        }
+        let resolved = self.resolve_reached(dependency.path.clone(), Some(dependency.edge))?;
+        if dependency.edge == Edge::Include {
+            check_fragment(resolved, dependency, source)?;
+        }
+        Ok(())
+    }
+
+    /// Resolve a normalized path that is not being resolved already
+    fn resolve_reached(&mut self, path: PathBuf, edge: Option<Edge>) -> HoneResult<&ResolvedFile> {
+        // Check if already resolved
+        if self.cache.contains_key(&path) {
+            return Ok(self.cache.get(&path).unwrap());
+        }
 
         // Get the virtual file content
         let source = self
@@ -473,42 +816,51 @@ impl VirtualResolver {
             })?
             .clone();
 
-        // Push onto resolution stack
-        self.resolution_stack.push(path.clone());
-
-        // Parse the source
-        let mut lexer = Lexer::new(&source, Some(path.clone()));
-        let tokens = lexer.tokenize()?;
-        let mut parser = Parser::new(tokens, &source, Some(path.clone()));
-        let ast = parser.parse()?;
-
-        // Extract dependencies
-        let (from_path, import_paths) = self.extract_dependencies(&ast, &path)?;
-
-        // Recursively resolve dependencies
-        if let Some(ref from) = from_path {
-            self.resolve(from)?;
-        }
-        for import in &import_paths {
-            self.resolve(import)?;
-        }
-
-        // Pop from resolution stack
+        // Push onto resolution stack, popping even on error so the resolver
+        // stays usable for other entry points
+        self.resolution_stack.push((path.clone(), edge));
+        let parsed = self.parse_with_dependencies(&path, &source);
         self.resolution_stack.pop();
+        let (ast, dependencies, data) = parsed?;
 
         // Cache the resolved file
         let resolved = ResolvedFile {
             path: path.clone(),
             ast,
             source,
-            from_path,
-            import_paths,
+            from_path: dependencies.from_path,
+            import_paths: dependencies.import_paths,
+            include_paths: dependencies.include_paths,
+            data,
         };
 
         self.cache.insert(path.clone(), resolved);
         Ok(self.cache.get(&path).unwrap())
     }
 
+    /// Parse a virtual file, then resolve its dependencies
+    fn parse_with_dependencies(
+        &mut self,
+        path: &Path,
+        source: &str,
+    ) -> HoneResult<(File, Dependencies, Option<Value>)> {
+        if data::is_data_file(path) {
+            let value = data::parse(path, source)?;
+            return Ok((data::empty_ast(path), Dependencies::new(), Some(value)));
+        }
+        let mut lexer = Lexer::new(source, Some(path.to_path_buf()));
+        let tokens = lexer.tokenize()?;
+        let mut parser = Parser::new(tokens, source, Some(path.to_path_buf()));
+        let mut ast = parser.parse()?;
+
+        let mut dependencies = self.extract_dependencies(&ast, path)?;
+        self.extract_includes(&mut ast, path, &mut dependencies)?;
+        for dependency in &dependencies.statements {
+            self.resolve_dependency(dependency, source)?;
+        }
+        Ok((ast, dependencies, None))
+    }
+
     /// Get topologically sorted files
     pub fn topological_order(&self, root: &Path) -> HoneResult<Vec<&ResolvedFile>> {
         let mut visited = HashSet::new();
@@ -543,18 +895,16 @@ impl VirtualResolver {
         for import in &resolved.import_paths {
             self.visit_topological(import, visited, result)?;
         }
+        for include in &resolved.include_paths {
+            self.visit_topological(include, visited, result)?;
+        }
 
         result.push(resolved);
         Ok(())
     }
 
-    fn extract_dependencies(
-        &self,
-        ast: &File,
-        current_file: &Path,
-    ) -> HoneResult<(Option<PathBuf>, Vec<PathBuf>)> {
-        let mut from_path = None;
-        let mut import_paths = Vec::new();
+    fn extract_dependencies(&self, ast: &File, current_file: &Path) -> HoneResult<Dependencies> {
+        let mut deps = Dependencies::new();
 
         let parent_dir = current_file.parent().unwrap_or(Path::new(""));
 
@@ -562,11 +912,21 @@ impl VirtualResolver {
             match item {
                 PreambleItem::From(from) => {
                     let path = self.resolve_import_path(from, parent_dir)?;
-                    from_path = Some(path);
+                    deps.from_path = Some(path.clone());
+                    deps.statements.push(Dependency {
+                        path,
+                        edge: Edge::From,
+                        location: from.location.clone(),
+                    });
                 }
                 PreambleItem::Import(import) => {
                     let path = self.resolve_import_path_from_import(import, parent_dir)?;
-                    import_paths.push(path);
+                    deps.import_paths.push(path.clone());
+                    deps.statements.push(Dependency {
+                        path,
+                        edge: Edge::Import,
+                        location: import.location.clone(),
+                    });
                 }
                 _ => {}
             }
@@ -577,22 +937,57 @@ impl VirtualResolver {
                 match item {
                     PreambleItem::From(from) => {
                         let path = self.resolve_import_path(from, parent_dir)?;
-                        if !import_paths.contains(&path) && from_path.as_ref() != Some(&path) {
-                            import_paths.push(path);
+                        if !deps.import_paths.contains(&path)
+                            && deps.from_path.as_ref() != Some(&path)
+                        {
+                            deps.import_paths.push(path.clone());
                         }
+                        deps.statements.push(Dependency {
+                            path,
+                            edge: Edge::From,
+                            location: from.location.clone(),
+                        });
                     }
                     PreambleItem::Import(import) => {
                         let path = self.resolve_import_path_from_import(import, parent_dir)?;
-                        if !import_paths.contains(&path) {
-                            import_paths.push(path);
+                        if !deps.import_paths.contains(&path) {
+                            deps.import_paths.push(path.clone());
                         }
+                        deps.statements.push(Dependency {
+                            path,
+                            edge: Edge::Import,
+                            location: import.location.clone(),
+                        });
                     }
                     _ => {}
                 }
             }
         }
 
-        Ok((from_path, import_paths))
+        Ok(deps)
+    }
+
+    fn extract_includes(
+        &self,
+        ast: &mut File,
+        current_file: &Path,
+        deps: &mut Dependencies,
+    ) -> HoneResult<()> {
+        let parent_dir = current_file.parent().unwrap_or(Path::new("."));
+        for include in include_statements(ast) {
+            let path_str = self.string_expr_to_string(&include.path)?;
+            let path = self.resolve_path_string(&path_str, parent_dir)?;
+            include.resolved = Some(path.clone());
+            if !deps.include_paths.contains(&path) {
+                deps.include_paths.push(path.clone());
+            }
+            deps.statements.push(Dependency {
+                path,
+                edge: Edge::Include,
+                location: include.location.clone(),
+            });
+        }
+        Ok(())
     }
 
     fn resolve_import_path(&self, from: &FromStatement, parent_dir: &Path) -> HoneResult<PathBuf> {
@@ -618,7 +1013,7 @@ impl VirtualResolver {
         for part in &expr.parts {
             match part {
                 StringPart::Literal(s) => result.push_str(s),
-                StringPart::Interpolation(_) => {
+                StringPart::Interpolation(_) | StringPart::Formatted(..) => {
                     return Err(HoneError::unexpected_token(
                         String::new(),
                         &expr.location,
@@ -650,6 +1045,7 @@ impl VirtualResolver {
         let mut cycle_parts: Vec<String> = self
             .resolution_stack
             .iter()
+            .map(|(p, _)| p)
             .skip_while(|p| *p != target)
             .map(|p| p.display().to_string())
             .collect();
@@ -754,6 +1150,73 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_mixed_from_import_cycle() {
+        let mut resolver = VirtualResolver::new(HashMap::new());
+        resolver.add_file("/base.hone", "import \"./child.hone\" as c\nname: \"base\"");
+        resolver.add_file("/child.hone", "from \"./base.hone\"\nport: 1");
+        resolver.add_file("/other.hone", "x: 1");
+
+        match resolver.resolve("/child.hone").unwrap_err() {
+            HoneError::CircularFrom {
+                src,
+                span,
+                chain,
+                help,
+            } => {
+                assert_eq!(
+                    chain,
+                    "/child.hone --from--> /base.hone --import--> /child.hone"
+                );
+                // Points at the import in base.hone that closes the cycle
+                assert_eq!(
+                    &src[span.offset()..span.offset() + span.len()],
+                    "import \"./child.hone\" as c"
+                );
+                assert!(help.contains("child.hone is built `from` base.hone"));
+            }
+            e => panic!("expected CircularFrom, got {:?}", e),
+        }
+
+        // The failed resolution leaves nothing on the stack
+        assert!(resolver.resolve("/other.hone").is_ok());
+        assert!(matches!(
+            resolver.resolve("/base.hone"),
+            Err(HoneError::CircularFrom { .. })
+        ));
+    }
+
+    #[test]
+    fn test_mixed_cycle_from_file_resolver() {
+        let dir = std::env::temp_dir().join(format!("hone_from_cycle_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("base.hone"), "import \"./shared.hone\" as s\na: 1").unwrap();
+        std::fs::write(
+            dir.join("shared.hone"),
+            "import \"./child.hone\" as c\nb: 1",
+        )
+        .unwrap();
+        std::fs::write(dir.join("child.hone"), "from \"./base.hone\"\nc: 1").unwrap();
+
+        let mut resolver = ImportResolver::new(&dir);
+        let err = resolver.resolve("child.hone").unwrap_err();
+        std::fs::remove_dir_all(&dir).ok();
+        match err {
+            HoneError::CircularFrom { chain, .. } => {
+                let parts: Vec<&str> = chain.split(' ').collect();
+                assert_eq!(parts.len(), 7);
+                assert!(parts[0].ends_with("child.hone"));
+                assert_eq!(parts[1], "--from-->");
+                assert!(parts[2].ends_with("base.hone"));
+                assert_eq!(parts[3], "--import-->");
+                assert!(parts[4].ends_with("shared.hone"));
+                assert_eq!(parts[5], "--import-->");
+                assert!(parts[6].ends_with("child.hone"));
+            }
+            e => panic!("expected CircularFrom, got {:?}", e),
+        }
+    }
+
     #[test]
     fn test_relative_path_resolution() {
         let mut resolver = VirtualResolver::new(HashMap::new());
@@ -960,4 +1423,25 @@ port: 80
             .unwrap();
         assert_eq!(order.len(), 4);
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preserved_symlinks_stay_in_sandbox() {
+        let dir = std::env::temp_dir().join(format!("hone_sandbox_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::create_dir_all(dir.join("sandbox")).unwrap();
+        std::fs::write(dir.join("src/app.hone"), "a: 1").unwrap();
+        std::os::unix::fs::symlink(dir.join("src/app.hone"), dir.join("sandbox/app.hone")).unwrap();
+
+        let link = dir.join("sandbox/./app.hone");
+        let real = dir.join("src/app.hone").canonicalize().unwrap();
+        assert_eq!(absolute_path(&link, false).unwrap(), real);
+        assert_eq!(
+            absolute_path(&link, true).unwrap(),
+            dir.join("sandbox/app.hone")
+        );
+        assert!(absolute_path(&dir.join("sandbox/missing.hone"), true).is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
 }
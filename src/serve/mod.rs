@@ -0,0 +1,520 @@
+//! `hone serve`: rebuild manifest targets when the project changes
+//!
+//! The project is polled for changed `.hone` files, data files and
+//! `hone.toml`, leaving out the files it writes; every watched target is then
+//! re-rendered in memory. Only files whose text changed are written, and each
+//! changed target is reported to the configured webhooks with its new output
+//! and a structural diff against the previous build.
+
+mod watch;
+mod webhook;
+
+pub use watch::Watcher;
+pub use webhook::Webhook;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::compiler::{build_args_object, Compiler};
+use crate::differ::{diff_values, format_diff_json, redact_entries};
+use crate::emitter::{emit, emit_multi, OutputFormat};
+use crate::errors::{HoneError, HoneResult, Warning};
+use crate::evaluator::{Redactor, Value};
+use crate::manifest::{Manifest, Target};
+
+/// One output file of a target, rendered but not yet written
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedFile {
+    /// Absolute output path
+    pub path: PathBuf,
+    /// The document written to `path`
+    pub value: Value,
+    /// Emitted text
+    pub text: String,
+}
+
+/// A compiled target
+#[derive(Debug, Clone)]
+pub struct RenderedTarget {
+    pub format: OutputFormat,
+    pub files: Vec<RenderedFile>,
+    pub warnings: Vec<Warning>,
+    /// Masks for the target's `@sensitive` values
+    pub redactor: Redactor,
+}
+
+/// Output format of a target: its `format`, else the output file's
+/// extension, else YAML for `output_dir` and pretty JSON otherwise
+pub fn target_format(target: &Target) -> HoneResult<OutputFormat> {
+    if let Some(name) = &target.format {
+        return OutputFormat::parse(name).ok_or_else(|| {
+            HoneError::io_error(format!(
+                "unknown output format '{}'. Use: {}",
+                name,
+                OutputFormat::names().join(", ")
+            ))
+        });
+    }
+    Ok(match &target.output {
+        Some(output) => output
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(OutputFormat::from_extension)
+            .unwrap_or(OutputFormat::JsonPretty),
+        None => OutputFormat::Yaml,
+    })
+}
+
+/// File name of the `index`th document of a multi-document compile written
+/// to an output directory
+pub fn document_file_name(name: Option<&str>, index: usize, extension: &str) -> String {
+    match name {
+        Some(n) => format!("{}.{}", n, extension),
+        None if index == 0 => format!("main.{}", extension),
+        None => format!("doc{}.{}", index, extension),
+    }
+}
+
+/// The args object of a target's `args`
+fn target_args(target: &Target) -> HoneResult<Value> {
+    let set: Vec<_> = target
+        .args
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    build_args_object(&set, &[], &[])
+}
+
+/// Values for the placeholders of an output name template besides the
+/// document's own: the selected variant cases and the args
+#[derive(Debug, Clone)]
+pub struct NameValues {
+    pub variants: HashMap<String, String>,
+    /// The args object, as built from `--set` or a target's `args`
+    pub args: Value,
+}
+
+/// File name of a document from an output name template such as
+/// `{document}-{variant.env}.{ext}`. The placeholders are `{document}` (the
+/// document's name, or `main`/`docN` for an unnamed one), `{index}`,
+/// `{ext}`, `{variant.NAME}` and `{args.NAME}`. A template may name
+/// subdirectories, but no placeholder may give a path.
+pub fn template_file_name(
+    template: &str,
+    name: Option<&str>,
+    index: usize,
+    extension: &str,
+    values: &NameValues,
+) -> HoneResult<String> {
+    let invalid = |reason: String| {
+        HoneError::io_error(format!("invalid output name '{}': {}", template, reason))
+    };
+    let mut file_name = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find(['{', '}']) {
+        file_name.push_str(&rest[..open]);
+        if rest[open..].starts_with('}') {
+            return Err(invalid("'}' without a matching '{'".to_string()));
+        }
+        let Some(close) = rest[open..].find('}') else {
+            return Err(invalid("'{' without a matching '}'".to_string()));
+        };
+        let placeholder = &rest[open + 1..open + close];
+        let value = match placeholder.split_once('.') {
+            None if placeholder == "document" => {
+                let default = document_file_name(name, index, extension);
+                default
+                    .strip_suffix(&format!(".{}", extension))
+                    .unwrap_or(&default)
+                    .to_string()
+            }
+            None if placeholder == "index" => index.to_string(),
+            None if placeholder == "ext" => extension.to_string(),
+            Some(("variant", variant)) => {
+                values.variants.get(variant).cloned().ok_or_else(|| {
+                    invalid(format!(
+                        "variant '{}' is not selected; select it with --variant {}=CASE",
+                        variant, variant
+                    ))
+                })?
+            }
+            Some(("args", arg)) => {
+                let path: Vec<&str> = arg.split('.').collect();
+                match values.args.get_path(&path) {
+                    Some(Value::String(s)) => s.clone(),
+                    Some(
+                        value @ (Value::Int(_)
+                        | Value::Float(_)
+                        | Value::Bool(_)
+                        | Value::Duration(_)
+                        | Value::Size(_)),
+                    ) => value.to_string(),
+                    Some(other) => {
+                        return Err(invalid(format!(
+                            "args.{} is {}, not a string, number or bool",
+                            arg,
+                            other.type_name()
+                        )))
+                    }
+                    None => {
+                        return Err(invalid(format!(
+                            "args.{} is not set; set it with --set {}=VALUE",
+                            arg, arg
+                        )))
+                    }
+                }
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "unknown placeholder {{{}}}; use {{document}}, {{index}}, {{ext}}, \
+                     {{variant.NAME}} or {{args.NAME}}",
+                    placeholder
+                )))
+            }
+        };
+        if value.is_empty() || value == ".." || value.contains(['/', '\\']) {
+            return Err(invalid(format!(
+                "{{{}}} is '{}', which is not a file name",
+                placeholder, value
+            )));
+        }
+        file_name.push_str(&value);
+        rest = &rest[open + close + 1..];
+    }
+    file_name.push_str(rest);
+    Ok(file_name)
+}
+
+/// File names for the documents of a multi-document compile written to an
+/// output directory, by index, leaving out an empty unnamed document. Two
+/// documents given the same name are an error rather than one overwriting
+/// the other.
+pub fn document_file_names(
+    documents: &[(Option<String>, Value)],
+    extension: &str,
+    template: Option<&str>,
+    values: &NameValues,
+) -> HoneResult<Vec<(usize, String)>> {
+    let mut names: Vec<(usize, String)> = Vec::new();
+    for (i, (name, value)) in documents.iter().enumerate() {
+        if name.is_none() && value.is_empty_object() {
+            continue;
+        }
+        let file_name = match template {
+            Some(template) => template_file_name(template, name.as_deref(), i, extension, values)?,
+            None => document_file_name(name.as_deref(), i, extension),
+        };
+        if names.iter().any(|(_, n)| *n == file_name) {
+            return Err(HoneError::io_error(format!(
+                "two documents would be written to {}; put {{document}} or {{index}} in the output name",
+                file_name
+            )));
+        }
+        names.push((i, file_name));
+    }
+    Ok(names)
+}
+
+/// Compile the target `name` and emit its output files, without writing them
+pub fn render_target(manifest: &Manifest, name: &str) -> HoneResult<RenderedTarget> {
+    let target = manifest.target(name)?;
+    let format = target_format(target)?;
+    let entry = manifest.root.join(&target.entry);
+    let canonical = crate::resolver::canonicalize(&entry).map_err(|e| {
+        HoneError::io_error(format!("failed to resolve path {}: {}", entry.display(), e))
+    })?;
+
+    let mut compiler = Compiler::new(canonical.parent().unwrap_or(Path::new(".")));
+    compiler.set_allow_env(target.allow_env);
+    compiler.set_output_format(format);
+    compiler.set_variants(
+        target
+            .variants
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<HashMap<_, _>>(),
+    );
+    if !target.args.is_empty() {
+        compiler.set_args(target_args(target)?);
+    }
+    let documents = compiler.compile_multi(&canonical)?;
+
+    let files = match (&target.output, &target.output_dir) {
+        (Some(output), _) => {
+            let documents: Vec<_> = documents
+                .into_iter()
+                .filter(|(name, value)| name.is_some() || !value.is_empty_object())
+                .collect();
+            let (value, text) = match documents.as_slice() {
+                [(None, value)] => (value.clone(), emit(value, format)?),
+                _ => (
+                    Value::Array(documents.iter().map(|(_, v)| v.clone()).collect()),
+                    emit_multi(&documents, format)?,
+                ),
+            };
+            vec![RenderedFile {
+                path: manifest.root.join(output),
+                value,
+                text,
+            }]
+        }
+        (None, Some(dir)) => {
+            let dir = manifest.root.join(dir);
+            let values = NameValues {
+                variants: target
+                    .variants
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect(),
+                args: target_args(target)?,
+            };
+            let names = document_file_names(
+                &documents,
+                &format.extension(),
+                target.output_name.as_deref(),
+                &values,
+            )?;
+            let mut files = Vec::new();
+            for (i, file_name) in names {
+                let value = documents[i].1.clone();
+                files.push(RenderedFile {
+                    path: dir.join(file_name),
+                    text: emit(&value, format)?,
+                    value,
+                });
+            }
+            files
+        }
+        (None, None) => Vec::new(),
+    };
+
+    Ok(RenderedTarget {
+        format,
+        files,
+        warnings: compiler.warnings().to_vec(),
+        redactor: compiler.redactor().clone(),
+    })
+}
+
+/// Files of `current` that are new or whose text differs from `previous`
+pub fn changed_files<'a>(
+    previous: Option<&RenderedTarget>,
+    current: &'a RenderedTarget,
+) -> Vec<&'a RenderedFile> {
+    current
+        .files
+        .iter()
+        .filter(|file| {
+            previous
+                .and_then(|p| p.files.iter().find(|f| f.path == file.path))
+                .is_none_or(|f| f.text != file.text)
+        })
+        .collect()
+}
+
+/// JSON body POSTed to webhooks when a target's output changes. `diff` is
+/// `null` for a file's first build. `@sensitive` values of either build are
+/// masked in the diff, and in the content unless `sensitive` is set.
+pub fn webhook_payload(
+    target: &str,
+    previous: Option<&RenderedTarget>,
+    current: &RenderedTarget,
+    sensitive: bool,
+) -> String {
+    let mut redactor = current.redactor.clone();
+    if let Some(previous) = previous {
+        redactor.extend(&previous.redactor);
+    }
+    let files: Vec<serde_json::Value> = changed_files(previous, current)
+        .into_iter()
+        .map(|file| {
+            let before = previous.and_then(|p| p.files.iter().find(|f| f.path == file.path));
+            let diff = match before {
+                Some(before) => file_diff(&before.value, &file.value, &redactor),
+                None => serde_json::Value::Null,
+            };
+            let content = if sensitive {
+                file.text.clone()
+            } else {
+                redactor.text(&file.text)
+            };
+            serde_json::json!({
+                "path": file.path.display().to_string(),
+                "content": content,
+                "diff": diff,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "target": target,
+        "format": current.format.name(),
+        "files": files,
+    })
+    .to_string()
+}
+
+fn file_diff(before: &Value, after: &Value, redactor: &Redactor) -> serde_json::Value {
+    let entries = redact_entries(&diff_values(before, after), redactor);
+    serde_json::from_str(&format_diff_json(&entries)).unwrap_or(serde_json::Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    fn rendered(files: &[(&str, i64)]) -> RenderedTarget {
+        RenderedTarget {
+            format: OutputFormat::Json,
+            files: files
+                .iter()
+                .map(|(path, port)| {
+                    let mut obj = IndexMap::new();
+                    obj.insert("port".to_string(), Value::Int(*port));
+                    let value = Value::Object(obj);
+                    RenderedFile {
+                        path: PathBuf::from(path),
+                        text: emit(&value, OutputFormat::Json).unwrap(),
+                        value,
+                    }
+                })
+                .collect(),
+            warnings: Vec::new(),
+            redactor: Redactor::default(),
+        }
+    }
+
+    #[test]
+    fn test_changed_files() {
+        let before = rendered(&[("a.json", 80), ("b.json", 90)]);
+        let after = rendered(&[("a.json", 80), ("b.json", 91), ("c.json", 1)]);
+        let changed: Vec<_> = changed_files(Some(&before), &after)
+            .into_iter()
+            .map(|f| f.path.display().to_string())
+            .collect();
+        assert_eq!(changed, ["b.json", "c.json"]);
+        assert_eq!(changed_files(None, &after).len(), 3);
+        assert!(changed_files(Some(&after), &after).is_empty());
+    }
+
+    #[test]
+    fn test_webhook_payload_has_output_and_diff() {
+        let before = rendered(&[("a.json", 80)]);
+        let after = rendered(&[("a.json", 8080)]);
+        let payload: serde_json::Value =
+            serde_json::from_str(&webhook_payload("prod", Some(&before), &after, false)).unwrap();
+        assert_eq!(payload["target"], "prod");
+        assert_eq!(payload["format"], "json");
+        let file = &payload["files"][0];
+        assert_eq!(file["content"], r#"{"port":8080}"#);
+        assert_eq!(file["diff"][0]["path"], "port");
+        assert_eq!(file["diff"][0]["right"], 8080);
+
+        let first: serde_json::Value =
+            serde_json::from_str(&webhook_payload("prod", None, &after, false)).unwrap();
+        assert!(first["files"][0]["diff"].is_null());
+    }
+
+    #[test]
+    fn test_webhook_payload_masks_sensitive_values() {
+        let mut before = rendered(&[("a.json", 80)]);
+        let mut after = rendered(&[("a.json", 8080)]);
+        // The old value was sensitive in the previous build, the new one in
+        // this one
+        before.redactor.add(&Value::Int(80));
+        after.redactor.add(&Value::Int(8080));
+
+        let payload: serde_json::Value =
+            serde_json::from_str(&webhook_payload("prod", Some(&before), &after, false)).unwrap();
+        let file = &payload["files"][0];
+        assert_eq!(file["content"], r#"{"port":***}"#);
+        assert_eq!(file["diff"][0]["left"], "***");
+        assert_eq!(file["diff"][0]["right"], "***");
+
+        let payload: serde_json::Value =
+            serde_json::from_str(&webhook_payload("prod", Some(&before), &after, true)).unwrap();
+        let file = &payload["files"][0];
+        assert_eq!(file["content"], r#"{"port":8080}"#);
+        assert_eq!(file["diff"][0]["right"], "***");
+    }
+
+    #[test]
+    fn test_document_file_name() {
+        assert_eq!(document_file_name(Some("svc"), 3, "yaml"), "svc.yaml");
+        assert_eq!(document_file_name(None, 0, "yaml"), "main.yaml");
+        assert_eq!(document_file_name(None, 2, "json"), "doc2.json");
+    }
+
+    #[test]
+    fn test_template_file_name() {
+        let mut args = IndexMap::new();
+        args.insert("region".to_string(), Value::String("us-east".to_string()));
+        args.insert("replicas".to_string(), Value::Int(3));
+        args.insert("tags".to_string(), Value::Array(vec![]));
+        let values = NameValues {
+            variants: HashMap::from([("env".to_string(), "prod".to_string())]),
+            args: Value::Object(args),
+        };
+        let name = |template: &str, document: Option<&str>| {
+            template_file_name(template, document, 1, "yaml", &values)
+        };
+        assert_eq!(
+            name("{document}-{variant.env}.{ext}", Some("svc")).unwrap(),
+            "svc-prod.yaml"
+        );
+        assert_eq!(
+            name("{args.region}/{document}-{args.replicas}.{ext}", None).unwrap(),
+            "us-east/doc1-3.yaml"
+        );
+        assert_eq!(name("{index}.json", None).unwrap(), "1.json");
+
+        for (template, expected) in [
+            (
+                "{document}-{variant.tier}",
+                "variant 'tier' is not selected",
+            ),
+            ("{args.zone}", "args.zone is not set"),
+            ("{args.tags}", "args.tags is array"),
+            ("{name}", "unknown placeholder {name}"),
+            ("{document", "without a matching '}'"),
+        ] {
+            let err = name(template, Some("svc")).unwrap_err().to_string();
+            assert!(err.contains(expected), "{}: {}", template, err);
+        }
+        let err = name("{document}", Some("../escape"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("not a file name"), "{}", err);
+    }
+
+    #[test]
+    fn test_document_file_names_rejects_collisions() {
+        let documents = vec![
+            (Some("a".to_string()), Value::Int(1)),
+            (Some("b".to_string()), Value::Int(2)),
+        ];
+        let values = NameValues {
+            variants: HashMap::from([("env".to_string(), "prod".to_string())]),
+            args: Value::Object(IndexMap::new()),
+        };
+        let names = document_file_names(
+            &documents,
+            "yaml",
+            Some("{document}-{variant.env}.{ext}"),
+            &values,
+        )
+        .unwrap();
+        assert_eq!(
+            names,
+            [
+                (0, "a-prod.yaml".to_string()),
+                (1, "b-prod.yaml".to_string())
+            ]
+        );
+        let err = document_file_names(&documents, "yaml", Some("{variant.env}.{ext}"), &values)
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("two documents would be written to prod.yaml"));
+    }
+}
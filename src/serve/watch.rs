@@ -0,0 +1,160 @@
+//! Polling file watcher
+//!
+//! Modification times of the project's `.hone` files, the data files they
+//! can import (`.json`, `.yaml`, `.toml`, `.csv`) and `hone.toml` are
+//! compared on each poll. Files the build writes itself are set with
+//! [`Watcher::set_outputs`] and skipped, so a rebuild doesn't trigger
+//! another. Polling needs nothing from the platform and sees edits made over
+//! network filesystems and by editors that replace files.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::manifest::MANIFEST_FILE;
+use crate::resolver::data::is_data_file;
+
+/// Watches the source files below a project root
+#[derive(Debug)]
+pub struct Watcher {
+    root: PathBuf,
+    stamps: BTreeMap<PathBuf, SystemTime>,
+    outputs: BTreeSet<PathBuf>,
+}
+
+impl Watcher {
+    /// Watch `root`, taking its current state as the baseline
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        let stamps = scan(&root);
+        Self {
+            root,
+            stamps,
+            outputs: BTreeSet::new(),
+        }
+    }
+
+    /// Stop watching `outputs`, the files the build writes; replaces the
+    /// previous set
+    pub fn set_outputs(&mut self, outputs: impl IntoIterator<Item = PathBuf>) {
+        self.outputs = outputs.into_iter().collect();
+        self.stamps.retain(|path, _| !self.outputs.contains(path));
+    }
+
+    /// Files added, modified, or removed since the last poll, sorted
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut stamps = scan(&self.root);
+        stamps.retain(|path, _| !self.outputs.contains(path));
+        let mut changed: Vec<PathBuf> = stamps
+            .iter()
+            .filter(|(path, stamp)| self.stamps.get(*path) != Some(stamp))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(
+            self.stamps
+                .keys()
+                .filter(|path| !stamps.contains_key(*path))
+                .cloned(),
+        );
+        changed.sort();
+        self.stamps = stamps;
+        changed
+    }
+}
+
+/// Modification times of watched files below `dir`. Hidden directories
+/// (`.git`, `.hone-cache`) are skipped.
+fn scan(dir: &Path) -> BTreeMap<PathBuf, SystemTime> {
+    let mut stamps = BTreeMap::new();
+    scan_into(dir, &mut stamps);
+    stamps
+}
+
+fn scan_into(dir: &Path, stamps: &mut BTreeMap<PathBuf, SystemTime>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if meta.is_dir() {
+            let hidden = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with('.'));
+            if !hidden {
+                scan_into(&path, stamps);
+            }
+        } else if is_watched(&path) {
+            if let Ok(modified) = meta.modified() {
+                stamps.insert(path, modified);
+            }
+        }
+    }
+}
+
+fn is_watched(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "hone")
+        || is_data_file(path)
+        || path.file_name().is_some_and(|n| n == MANIFEST_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_poll_reports_changes() {
+        let dir = std::env::temp_dir().join(format!("hone_watch_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(dir.join("main.hone"), "a: 1").unwrap();
+        std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let mut watcher = Watcher::new(&dir);
+        assert!(watcher.poll().is_empty());
+
+        std::fs::write(dir.join("lib/extra.hone"), "b: 2").unwrap();
+        std::fs::write(dir.join("notes.txt"), "still ignored").unwrap();
+        assert_eq!(watcher.poll(), [dir.join("lib/extra.hone")]);
+
+        let main = std::fs::File::options()
+            .write(true)
+            .open(dir.join("main.hone"))
+            .unwrap();
+        main.set_modified(SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+        std::fs::remove_file(dir.join("lib/extra.hone")).unwrap();
+        assert_eq!(
+            watcher.poll(),
+            [dir.join("lib/extra.hone"), dir.join("main.hone")]
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_poll_reports_data_files_but_not_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        std::fs::write(dir.join("main.hone"), "import \"./regions.yaml\" as r").unwrap();
+        std::fs::write(dir.join("regions.yaml"), "- eu").unwrap();
+
+        let mut watcher = Watcher::new(dir);
+        watcher.set_outputs([dir.join("out.json")]);
+        std::fs::write(dir.join("out.json"), "{}").unwrap();
+        assert!(watcher.poll().is_empty());
+
+        let regions = std::fs::File::options()
+            .write(true)
+            .open(dir.join("regions.yaml"))
+            .unwrap();
+        regions
+            .set_modified(SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+        std::fs::write(dir.join("out.json"), "{\"a\": 1}").unwrap();
+        assert_eq!(watcher.poll(), [dir.join("regions.yaml")]);
+    }
+}
@@ -0,0 +1,153 @@
+//! Webhook delivery
+//!
+//! A webhook is an `http://` URL that build results are POSTed to as JSON.
+//! Requests are plain HTTP/1.1 over a TCP connection; put a relay in front
+//! of endpoints that need TLS.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::errors::{HoneError, HoneResult};
+
+/// How long to wait for a webhook to connect, accept the body, and answer
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A parsed webhook URL
+#[derive(Debug, Clone, PartialEq)]
+pub struct Webhook {
+    pub url: String,
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Webhook {
+    /// Parse an `http://host[:port][/path]` URL
+    pub fn parse(url: &str) -> HoneResult<Self> {
+        let invalid =
+            |why: &str| HoneError::io_error(format!("invalid webhook '{}': {}", url, why));
+        let rest = match url.split_once("://") {
+            Some(("http", rest)) => rest,
+            Some(("https", _)) => {
+                return Err(invalid(
+                    "https is not supported; use an http relay for TLS endpoints",
+                ))
+            }
+            _ => return Err(invalid("expected an http:// URL")),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse::<u16>().map_err(|_| invalid("bad port"))?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(invalid("missing host"));
+        }
+        Ok(Self {
+            url: url.to_string(),
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// POST a JSON `body` and return the response status code
+    pub fn post(&self, body: &str) -> HoneResult<u16> {
+        let failed =
+            |e: std::io::Error| HoneError::io_error(format!("webhook {} failed: {}", self.url, e));
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(failed)?
+            .next()
+            .ok_or_else(|| HoneError::io_error(format!("webhook {}: host not found", self.url)))?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT).map_err(failed)?;
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(failed)?;
+        stream.set_write_timeout(Some(TIMEOUT)).map_err(failed)?;
+
+        stream
+            .write_all(self.request(body).as_bytes())
+            .map_err(failed)?;
+
+        let mut status_line = String::new();
+        BufReader::new(&stream)
+            .read_line(&mut status_line)
+            .map_err(failed)?;
+        status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| {
+                HoneError::io_error(format!(
+                    "webhook {}: malformed response '{}'",
+                    self.url,
+                    status_line.trim_end()
+                ))
+            })
+    }
+
+    fn request(&self, body: &str) -> String {
+        let host = if self.port == 80 {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        };
+        format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: hone/{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.path,
+            host,
+            env!("CARGO_PKG_VERSION"),
+            body.len(),
+            body
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_parse_urls() {
+        let hook = Webhook::parse("http://localhost:9000/hooks/hone").unwrap();
+        assert_eq!(
+            (hook.host.as_str(), hook.port, hook.path.as_str()),
+            ("localhost", 9000, "/hooks/hone")
+        );
+        let hook = Webhook::parse("http://example.com").unwrap();
+        assert_eq!((hook.port, hook.path.as_str()), (80, "/"));
+        assert!(Webhook::parse("https://example.com/").is_err());
+        assert!(Webhook::parse("example.com/hook").is_err());
+        assert!(Webhook::parse("http://:80/").is_err());
+    }
+
+    #[test]
+    fn test_post_sends_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with("{\"ok\":true}") {
+                let n = conn.read(&mut buf).unwrap();
+                request.push_str(std::str::from_utf8(&buf[..n]).unwrap());
+            }
+            conn.write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            request
+        });
+
+        let hook = Webhook::parse(&format!("http://127.0.0.1:{}/push", port)).unwrap();
+        assert_eq!(hook.post("{\"ok\":true}").unwrap(), 202);
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /push HTTP/1.1\r\n"));
+        assert!(request.contains("Content-Type: application/json\r\n"));
+        assert!(request.contains("Content-Length: 11\r\n"));
+    }
+}
@@ -0,0 +1,506 @@
+//! Complexity metrics for `hone stats`
+//!
+//! Everything is read off the syntax tree: nothing is evaluated, imported
+//! files are not followed and nothing leaves the machine. The numbers are
+//! meant to be tracked over time, so they count what the source says, not
+//! what a particular set of args or variant cases would output.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::errors::{HoneError, HoneResult};
+use crate::evaluator::builtins::is_builtin;
+use crate::lexer::Lexer;
+use crate::parser::ast::*;
+use crate::parser::Parser;
+use crate::resolver::canonicalize;
+use crate::style::{self, Style};
+
+/// Metrics for one file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FileStats {
+    pub path: PathBuf,
+    pub lines: usize,
+    /// Keys assigned, at any depth and in any branch
+    pub output_keys: usize,
+    /// Deepest nesting of blocks and objects; a top-level key is depth 1
+    pub max_depth: usize,
+    pub variants: usize,
+    pub variant_cases: usize,
+    /// `when`, `else when` and `else` branches
+    pub when_branches: usize,
+    /// Paths of the files this one imports or is built `from`, as written
+    pub imports: Vec<String>,
+    /// Number of the other analyzed files that import this one
+    pub imported_by: usize,
+    /// Calls per builtin, leaving out names the file defines itself
+    pub builtins: BTreeMap<String, usize>,
+}
+
+/// Metrics for a single source; `imported_by` is left at 0
+pub fn file_stats(source: &str, path: &Path) -> HoneResult<FileStats> {
+    let tokens = Lexer::new(source, Some(path.to_path_buf())).tokenize()?;
+    let ast = Parser::new(tokens, source, Some(path.to_path_buf())).parse()?;
+
+    let mut counter = Counter {
+        stats: FileStats {
+            path: path.to_path_buf(),
+            lines: source.lines().count(),
+            ..FileStats::default()
+        },
+        defined: HashSet::new(),
+    };
+    let documents = std::iter::once((&ast.preamble, &ast.body))
+        .chain(ast.documents.iter().map(|doc| (&doc.preamble, &doc.body)));
+    for (preamble, _) in documents.clone() {
+        counter.define(preamble);
+    }
+    for (preamble, body) in documents {
+        counter.preamble(preamble);
+        counter.items(body, 0);
+    }
+    Ok(counter.stats)
+}
+
+/// Metrics for each of `files`, with `imported_by` counted among them
+pub fn collect_stats(files: &[PathBuf]) -> HoneResult<Vec<FileStats>> {
+    let mut all = Vec::new();
+    for file in files {
+        let source = std::fs::read_to_string(file).map_err(|e| {
+            HoneError::io_error(format!("failed to read {}: {}", file.display(), e))
+        })?;
+        all.push(file_stats(&source, file)?);
+    }
+
+    let canonical: Vec<Option<PathBuf>> = all.iter().map(|s| canonicalize(&s.path).ok()).collect();
+    let mut imported_by = vec![0; all.len()];
+    for stats in &all {
+        let dir = stats.path.parent().unwrap_or(Path::new("."));
+        // A file imported twice by the same file counts once
+        let targets: HashSet<PathBuf> = stats
+            .imports
+            .iter()
+            .filter_map(|import| canonicalize(&dir.join(import)).ok())
+            .collect();
+        for (i, path) in canonical.iter().enumerate() {
+            if path.as_ref().is_some_and(|p| targets.contains(p)) {
+                imported_by[i] += 1;
+            }
+        }
+    }
+    for (stats, count) in all.iter_mut().zip(imported_by) {
+        stats.imported_by = count;
+    }
+    Ok(all)
+}
+
+/// Walks a file, adding up its metrics
+struct Counter {
+    stats: FileStats,
+    /// Top-level names, which hide builtins of the same name
+    defined: HashSet<String>,
+}
+
+impl Counter {
+    fn define(&mut self, preamble: &[PreambleItem]) {
+        for item in preamble {
+            match item {
+                PreambleItem::Let(binding) => {
+                    for name in binding.names() {
+                        self.defined.insert(name.to_string());
+                    }
+                }
+                PreambleItem::FnDef(def) => {
+                    self.defined.insert(def.name.clone());
+                }
+                PreambleItem::Secret(secret) => {
+                    self.defined.insert(secret.name.clone());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn preamble(&mut self, preamble: &[PreambleItem]) {
+        for item in preamble {
+            match item {
+                PreambleItem::Let(binding) => self.expr(&binding.value, 0),
+                PreambleItem::From(from) => self.import(&from.path),
+                PreambleItem::Import(import) => match &import.kind {
+                    ImportKind::Whole { path, .. } | ImportKind::Named { path, .. } => {
+                        self.import(path)
+                    }
+                },
+                PreambleItem::Schema(schema) => {
+                    for default in schema.fields.iter().filter_map(|f| f.default.as_ref()) {
+                        self.expr(default, 0);
+                    }
+                }
+                PreambleItem::Variant(variant) => {
+                    self.stats.variants += 1;
+                    self.stats.variant_cases += variant.cases.len();
+                    for case in &variant.cases {
+                        self.items(&case.body, 0);
+                    }
+                }
+                PreambleItem::Expect(expect) => {
+                    if let Some(default) = &expect.default {
+                        self.expr(default, 0);
+                    }
+                }
+                PreambleItem::Policy(policy) => self.expr(&policy.condition, 0),
+                PreambleItem::FnDef(def) => self.expr(&def.body, 0),
+                PreambleItem::TypeAlias(_)
+                | PreambleItem::Enum(_)
+                | PreambleItem::Use(_)
+                | PreambleItem::Secret(_) => {}
+            }
+        }
+    }
+
+    fn import(&mut self, path: &StringExpr) {
+        let text: Option<String> = path
+            .parts
+            .iter()
+            .map(|part| match part {
+                StringPart::Literal(s) => Some(s.as_str()),
+                StringPart::Interpolation(_) | StringPart::Formatted(..) => None,
+            })
+            .collect();
+        if let Some(text) = text {
+            self.stats.imports.push(text);
+        }
+    }
+
+    /// Items at `depth` levels of nesting
+    fn items(&mut self, items: &[BodyItem], depth: usize) {
+        for item in items {
+            match item {
+                BodyItem::KeyValue(kv) => {
+                    self.key(depth + 1);
+                    if let Key::Computed(key) = &kv.key {
+                        self.expr(key, depth);
+                    }
+                    self.expr(&kv.value, depth + 1);
+                }
+                BodyItem::Block(block) => {
+                    self.key(depth + 1);
+                    self.items(&block.items, depth + 1);
+                }
+                BodyItem::When(when) => self.when(when, depth),
+                BodyItem::For(for_loop) => self.for_loop(for_loop, depth),
+                BodyItem::Assert(assert) => {
+                    self.expr(&assert.condition, depth);
+                    if let Some(message) = &assert.message {
+                        self.expr(message, depth);
+                    }
+                }
+                BodyItem::Let(binding) => self.expr(&binding.value, depth),
+                BodyItem::Spread(spread) => self.expr(&spread.expr, depth),
+                BodyItem::Use(_) | BodyItem::Include(_) => {}
+            }
+        }
+    }
+
+    fn key(&mut self, depth: usize) {
+        self.stats.output_keys += 1;
+        self.stats.max_depth = self.stats.max_depth.max(depth);
+    }
+
+    fn when(&mut self, when: &WhenBlock, depth: usize) {
+        self.stats.when_branches += 1;
+        self.expr(&when.condition, depth);
+        self.items(&when.body, depth);
+        match &when.else_branch {
+            Some(ElseBranch::ElseWhen(next)) => self.when(next, depth),
+            Some(ElseBranch::Else(items, _)) => {
+                self.stats.when_branches += 1;
+                self.items(items, depth);
+            }
+            None => {}
+        }
+    }
+
+    fn for_loop(&mut self, for_loop: &ForLoop, depth: usize) {
+        self.expr(&for_loop.iterable, depth);
+        if let Some(filter) = &for_loop.filter {
+            self.expr(filter, depth);
+        }
+        match &for_loop.body {
+            ForBody::Object(items) => self.items(items, depth),
+            ForBody::Expr(expr) => self.expr(expr, depth),
+            ForBody::Block(items, expr) => {
+                self.items(items, depth);
+                self.expr(expr, depth);
+            }
+        }
+    }
+
+    /// An expression whose objects nest below `depth`
+    fn expr(&mut self, expr: &Expr, depth: usize) {
+        match expr {
+            Expr::Null(_)
+            | Expr::Bool(..)
+            | Expr::Integer(..)
+            | Expr::Float(..)
+            | Expr::Duration(..)
+            | Expr::Size(..)
+            | Expr::Ident(..) => {}
+            Expr::String(s) => self.string(s, depth),
+            Expr::Path(path) => {
+                for part in &path.parts {
+                    if let PathPart::Index(index) = part {
+                        self.expr(index, depth);
+                    }
+                }
+            }
+            Expr::Array(array) => {
+                for element in &array.elements {
+                    match element {
+                        ArrayElement::Expr(e) | ArrayElement::Spread(e) => self.expr(e, depth),
+                        ArrayElement::For(for_loop) => self.for_loop(for_loop, depth),
+                        ArrayElement::When(when) => self.when(when, depth),
+                    }
+                }
+            }
+            Expr::Object(object) | Expr::Block(object) => self.items(&object.items, depth),
+            Expr::Binary(binary) => {
+                self.expr(&binary.left, depth);
+                self.expr(&binary.right, depth);
+            }
+            Expr::Unary(unary) => self.expr(&unary.operand, depth),
+            Expr::Call(call) => {
+                if let Expr::Ident(name, _) = &*call.func {
+                    if is_builtin(name) && !self.defined.contains(name) {
+                        *self.stats.builtins.entry(name.clone()).or_default() += 1;
+                    }
+                }
+                self.expr(&call.func, depth);
+                for arg in &call.args {
+                    self.expr(arg, depth);
+                }
+            }
+            Expr::Index(index) => {
+                self.expr(&index.base, depth);
+                self.expr(&index.index, depth);
+            }
+            Expr::Conditional(cond) => {
+                self.expr(&cond.condition, depth);
+                self.expr(&cond.then_branch, depth);
+                self.expr(&cond.else_branch, depth);
+            }
+            Expr::Annotated(annotated) => {
+                self.expr(&annotated.expr, depth);
+                for arg in &annotated.constraint.args {
+                    self.expr(arg, depth);
+                }
+                for arg in &annotated.constraint.named_args {
+                    self.expr(&arg.value, depth);
+                }
+            }
+            Expr::Paren(inner, _) => self.expr(inner, depth),
+            Expr::For(for_loop) => self.for_loop(for_loop, depth),
+            Expr::When(when) => self.when(when, depth),
+            Expr::Match(m) => {
+                self.expr(&m.subject, depth);
+                for arm in &m.arms {
+                    for pattern in &arm.patterns {
+                        self.expr(pattern, depth);
+                    }
+                    self.expr(&arm.value, depth);
+                }
+            }
+            Expr::Lambda(lambda) => self.expr(&lambda.body, depth),
+        }
+    }
+
+    fn string(&mut self, s: &StringExpr, depth: usize) {
+        for part in &s.parts {
+            if let StringPart::Interpolation(expr) | StringPart::Formatted(expr, _) = part {
+                self.expr(expr, depth);
+            }
+        }
+    }
+}
+
+/// Format stats as a table, then each file's builtin calls; file paths are
+/// shown relative to `base`
+pub fn format_stats_text(stats: &[FileStats], base: &Path) -> String {
+    format_stats_text_with(stats, base, &Style::plain())
+}
+
+/// Format stats as text, colored by `style`
+pub fn format_stats_text_with(stats: &[FileStats], base: &Path, style: &Style) -> String {
+    if stats.is_empty() {
+        return "no .hone files found\n".to_string();
+    }
+    const HEADER: [&str; 8] = [
+        "lines", "keys", "depth", "variants", "cases", "when", "imports", "imported",
+    ];
+    let names: Vec<String> = stats.iter().map(|s| relative(&s.path, base)).collect();
+    let width = names
+        .iter()
+        .map(|n| n.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("file".len());
+
+    let mut output = format!("{:<width$}", "file", width = width);
+    for column in HEADER {
+        output.push_str(&format!("  {}", column));
+    }
+    output = output.trim_end().to_string() + "\n";
+    for (s, name) in stats.iter().zip(&names) {
+        let row = [
+            s.lines,
+            s.output_keys,
+            s.max_depth,
+            s.variants,
+            s.variant_cases,
+            s.when_branches,
+            s.imports.len(),
+            s.imported_by,
+        ];
+        let padding = " ".repeat(width - name.chars().count());
+        output.push_str(&format!("{}{}", style.paint(style::CYAN, name), padding));
+        for (value, column) in row.iter().zip(HEADER) {
+            output.push_str(&format!("  {:>w$}", value, w = column.len()));
+        }
+        output.push('\n');
+    }
+
+    if stats.iter().any(|s| !s.builtins.is_empty()) {
+        output.push_str("\nbuiltins\n");
+        for (s, name) in stats.iter().zip(&names) {
+            if s.builtins.is_empty() {
+                continue;
+            }
+            let mut calls: Vec<(&String, &usize)> = s.builtins.iter().collect();
+            // Most used first; ties stay in name order
+            calls.sort_by(|a, b| b.1.cmp(a.1));
+            let calls: Vec<String> = calls
+                .iter()
+                .map(|(builtin, count)| format!("{} {}", builtin, count))
+                .collect();
+            output.push_str(&format!(
+                "{}{}  {}\n",
+                style.paint(style::CYAN, name),
+                " ".repeat(width - name.chars().count()),
+                calls.join(", ")
+            ));
+        }
+    }
+    output
+}
+
+/// Format stats as JSON
+pub fn format_stats_json(stats: &[FileStats], base: &Path) -> String {
+    let json: Vec<serde_json::Value> = stats
+        .iter()
+        .map(|s| {
+            serde_json::json!({
+                "file": relative(&s.path, base),
+                "lines": s.lines,
+                "output_keys": s.output_keys,
+                "max_depth": s.max_depth,
+                "variants": s.variants,
+                "variant_cases": s.variant_cases,
+                "when_branches": s.when_branches,
+                "imports": s.imports,
+                "imported_by": s.imported_by,
+                "builtins": s.builtins,
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&json).unwrap_or_default() + "\n"
+}
+
+fn relative(file: &Path, base: &Path) -> String {
+    file.strip_prefix(base)
+        .or_else(|_| file.strip_prefix("."))
+        .unwrap_or(file)
+        .display()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_stats_counts_syntax() {
+        let source = r#"import { ports } from "./ports.hone"
+from "./base.hone"
+
+variant env {
+  default dev {
+    replicas: 1
+  }
+  prod {
+    replicas: 3
+  }
+}
+
+fn len(x) {
+  0
+}
+
+server {
+  port: ports.http
+  tls {
+    enabled: true
+    hosts: [for h in keys(ports) { upper(h) }]
+  }
+}
+
+when env == "prod" {
+  debug: false
+} else {
+  debug: to_str(len([]))
+  extra: { nested: { deep: keys({}) } }
+}
+"#;
+        let stats = file_stats(source, Path::new("app.hone")).unwrap();
+        assert_eq!(stats.lines, 30);
+        // replicas x2, server, port, tls, enabled, hosts, debug x2, extra,
+        // nested, deep
+        assert_eq!(stats.output_keys, 12);
+        assert_eq!(stats.max_depth, 3);
+        assert_eq!((stats.variants, stats.variant_cases), (1, 2));
+        assert_eq!(stats.when_branches, 2);
+        assert_eq!(stats.imports, vec!["./ports.hone", "./base.hone"]);
+        // `len` is the file's own function
+        let builtins: Vec<(&str, usize)> = stats
+            .builtins
+            .iter()
+            .map(|(k, v)| (k.as_str(), *v))
+            .collect();
+        assert_eq!(builtins, vec![("keys", 2), ("to_str", 1), ("upper", 1)]);
+    }
+
+    #[test]
+    fn test_collect_stats_counts_imported_by() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        };
+        let files = [
+            write("base.hone", "name: \"base\"\n"),
+            write("a.hone", "from \"./base.hone\"\nx: 1\n"),
+            write(
+                "b.hone",
+                "import \"./base.hone\" as b\nimport \"./a.hone\" as a\n\ny: b.name\n",
+            ),
+        ];
+        let stats = collect_stats(&files).unwrap();
+        let imported_by: Vec<usize> = stats.iter().map(|s| s.imported_by).collect();
+        assert_eq!(imported_by, vec![2, 1, 0]);
+        assert_eq!(stats[2].imports.len(), 2);
+
+        let text = format_stats_text(&stats, dir.path());
+        assert!(text.starts_with("file       lines  keys"), "{}", text);
+        assert!(text.contains("\nb.hone         4     1"), "{}", text);
+    }
+}
@@ -0,0 +1,210 @@
+//! Styling of human-readable output
+//!
+//! Decides once per run whether output is colored and how wide it may be,
+//! so diagnostics, warnings, diff text, graph text and `hone eval` agree.
+//! Color follows `--color` (auto, always, never); in auto mode it is on for
+//! terminals unless `NO_COLOR` is set or `TERM=dumb`, and `CLICOLOR_FORCE`
+//! turns it on for pipes. Width comes from `COLUMNS` when it is set.
+
+/// Reset all attributes
+pub const RESET: &str = "\x1b[0m";
+pub const RED: &str = "\x1b[31m";
+pub const GREEN: &str = "\x1b[32m";
+pub const YELLOW: &str = "\x1b[33m";
+pub const MAGENTA: &str = "\x1b[35m";
+pub const CYAN: &str = "\x1b[36m";
+pub const DIM: &str = "\x1b[2m";
+
+/// Value of `--color`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    /// Color terminals, honoring `NO_COLOR`, `CLICOLOR_FORCE` and `TERM=dumb`
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Parse `auto`, `always` or `never`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    /// Whether to color output going to a stream that is (or is not) a terminal
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        self.enabled_with(is_terminal, |name| std::env::var(name).ok())
+    }
+
+    fn enabled_with(self, is_terminal: bool, var: impl Fn(&str) -> Option<String>) -> bool {
+        let set = |name: &str| var(name).is_some_and(|v| !v.is_empty() && v != "0");
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto if set("NO_COLOR") => false,
+            ColorChoice::Auto if set("CLICOLOR_FORCE") => true,
+            ColorChoice::Auto => is_terminal && var("TERM").as_deref() != Some("dumb"),
+        }
+    }
+}
+
+/// How human-readable output is rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    /// Emit ANSI colors
+    pub color: bool,
+    /// Wrap lines to this many columns; `None` leaves them as they are
+    pub width: Option<usize>,
+}
+
+impl Style {
+    /// No color, no wrapping
+    pub fn plain() -> Self {
+        Self::default()
+    }
+
+    /// Style for a stream, from `--color` and the environment
+    pub fn detect(choice: ColorChoice, is_terminal: bool) -> Self {
+        Self {
+            color: choice.enabled(is_terminal),
+            // Piped output is read by scripts, so lines stay whole
+            width: if is_terminal { terminal_width() } else { None },
+        }
+    }
+
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_width(mut self, width: Option<usize>) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Wrap `text` in an ANSI color when color is on
+    pub fn paint(&self, code: &str, text: &str) -> String {
+        if self.color && !text.is_empty() {
+            format!("{}{}{}", code, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Break lines longer than the width at spaces, continuing them indented
+    /// by `indent` columns. Words longer than a line are left whole; escapes
+    /// do not count towards the width.
+    pub fn wrap(&self, text: &str, indent: usize) -> String {
+        let Some(width) = self.width else {
+            return text.to_string();
+        };
+        let mut out = String::with_capacity(text.len());
+        for line in text.split_inclusive('\n') {
+            let (line, newline) = match line.strip_suffix('\n') {
+                Some(line) => (line, "\n"),
+                None => (line, ""),
+            };
+            let mut column = 0;
+            for (i, word) in line.split(' ').enumerate() {
+                let len = visible_len(word);
+                if i > 0 {
+                    if column + 1 + len > width && column > indent {
+                        out.push('\n');
+                        out.push_str(&" ".repeat(indent));
+                        column = indent;
+                    } else {
+                        out.push(' ');
+                        column += 1;
+                    }
+                }
+                out.push_str(word);
+                column += len;
+            }
+            out.push_str(newline);
+        }
+        out
+    }
+}
+
+/// Terminal width from `COLUMNS`, if it is set to a usable number
+pub fn terminal_width() -> Option<usize> {
+    std::env::var("COLUMNS")
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&w| w >= 20)
+}
+
+/// Number of characters shown for `s`, skipping ANSI escapes
+fn visible_len(s: &str) -> usize {
+    let mut len = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            len += 1;
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_choice_env() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        assert!(ColorChoice::Auto.enabled_with(true, env(&[])));
+        assert!(!ColorChoice::Auto.enabled_with(false, env(&[])));
+        assert!(!ColorChoice::Auto.enabled_with(true, env(&[("NO_COLOR", "1")])));
+        assert!(ColorChoice::Auto.enabled_with(true, env(&[("NO_COLOR", "")])));
+        assert!(!ColorChoice::Auto.enabled_with(true, env(&[("TERM", "dumb")])));
+        assert!(ColorChoice::Auto.enabled_with(false, env(&[("CLICOLOR_FORCE", "1")])));
+        assert!(ColorChoice::Always.enabled_with(false, env(&[("NO_COLOR", "1")])));
+        assert!(!ColorChoice::Never.enabled_with(true, env(&[("CLICOLOR_FORCE", "1")])));
+    }
+
+    #[test]
+    fn test_detect_never_wraps_without_a_terminal() {
+        for choice in [ColorChoice::Auto, ColorChoice::Always, ColorChoice::Never] {
+            assert_eq!(Style::detect(choice, false).width, None);
+        }
+    }
+
+    #[test]
+    fn test_wrap() {
+        let style = Style::plain().with_width(Some(12));
+        assert_eq!(
+            style.wrap("+ a.b: one two three\nshort\n", 4),
+            "+ a.b: one\n    two\n    three\nshort\n"
+        );
+        assert_eq!(
+            Style::plain().wrap("+ a.b: one two three", 4),
+            "+ a.b: one two three"
+        );
+
+        let painted = style.with_color(true).paint(GREEN, "+ a.b:");
+        assert_eq!(
+            style.wrap(&format!("{} one", painted), 4),
+            format!("{} one", painted)
+        );
+    }
+}
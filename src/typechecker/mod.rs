@@ -11,8 +11,8 @@ use crate::errors::{HoneError, HoneResult};
 use crate::evaluator::{LocationMap, Value};
 use crate::lexer::token::SourceLocation;
 use crate::parser::ast::{
-    Expr, File, PreambleItem, SchemaDefinition, SchemaField, TypeAliasDefinition, TypeConstraint,
-    TypeExpr,
+    Expr, File, NamedTypeArg, PreambleItem, SchemaDefinition, SchemaField, TypeAliasDefinition,
+    TypeConstraint, TypeExpr,
 };
 
 use std::collections::{HashMap, HashSet};
@@ -53,6 +53,55 @@ fn extract_float(expr: &crate::parser::ast::Expr) -> Option<f64> {
     }
 }
 
+/// How the values a ranged type admits relate to another ranged type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeRelation {
+    /// No value is in both ranges
+    Disjoint,
+    /// Every value of the first range is in the second
+    Within,
+    /// Some values of the first range are outside the second
+    Overlapping,
+}
+
+/// The bounds of a ranged `int(min, max)` or `float(min, max)` type, with
+/// `Optional` unwrapped
+fn numeric_bounds(ty: &Type) -> Option<(Option<f64>, Option<f64>)> {
+    match ty {
+        Type::IntConstrained(c) => Some((c.min.map(|n| n as f64), c.max.map(|n| n as f64))),
+        Type::FloatConstrained(c) => Some((c.min, c.max)),
+        Type::Optional(inner) => numeric_bounds(inner),
+        _ => None,
+    }
+}
+
+/// Compare the range of `inner` with the range of `outer`, statically.
+/// `None` unless both are ranged numeric types.
+pub fn compare_ranges(inner: &Type, outer: &Type) -> Option<RangeRelation> {
+    let (inner_min, inner_max) = numeric_bounds(inner)?;
+    let (outer_min, outer_max) = numeric_bounds(outer)?;
+    let below = matches!((inner_max, outer_min), (Some(max), Some(min)) if max < min);
+    let above = matches!((inner_min, outer_max), (Some(min), Some(max)) if min > max);
+    if below || above {
+        return Some(RangeRelation::Disjoint);
+    }
+    let min_within = match (inner_min, outer_min) {
+        (_, None) => true,
+        (Some(inner), Some(outer)) => inner >= outer,
+        (None, Some(_)) => false,
+    };
+    let max_within = match (inner_max, outer_max) {
+        (_, None) => true,
+        (Some(inner), Some(outer)) => inner <= outer,
+        (None, Some(_)) => false,
+    };
+    Some(if min_within && max_within {
+        RangeRelation::Within
+    } else {
+        RangeRelation::Overlapping
+    })
+}
+
 /// Type checker for Hone files
 pub struct TypeChecker {
     /// Registry of defined schemas
@@ -65,6 +114,8 @@ pub struct TypeChecker {
     unchecked_paths: HashSet<String>,
     /// Cache of compiled regexes for string pattern constraints
     regex_cache: HashMap<String, regex::Regex>,
+    /// Let required fields be missing (checking a `partial use`)
+    partial: bool,
 }
 
 /// Compiled schema for type checking
@@ -95,6 +146,7 @@ impl TypeChecker {
             source,
             unchecked_paths: HashSet::new(),
             regex_cache: HashMap::new(),
+            partial: false,
         }
     }
 
@@ -103,9 +155,27 @@ impl TypeChecker {
         self.unchecked_paths = paths;
     }
 
+    /// Only check the fields that are present, as for a `partial use`
+    pub fn set_partial(&mut self, partial: bool) {
+        self.partial = partial;
+    }
+
     /// Collect and compile schema definitions and type aliases from a file.
     /// Regex patterns are pre-compiled and cached for O(1) lookups at check time.
     pub fn collect_schemas(&mut self, file: &File) -> HoneResult<()> {
+        // Enums are unions of their values' string literal types
+        for item in &file.preamble {
+            if let PreambleItem::Enum(def) = item {
+                let values = def
+                    .values
+                    .iter()
+                    .map(|v| Type::StringLiteral(v.clone()))
+                    .collect();
+                self.type_aliases
+                    .insert(def.name.clone(), Type::Union(values));
+            }
+        }
+
         // First pass: collect type aliases (they may be referenced by schemas)
         for item in &file.preamble {
             if let PreambleItem::TypeAlias(alias_def) = item {
@@ -129,6 +199,33 @@ impl TypeChecker {
         Ok(())
     }
 
+    /// Register schema `name` from an imported file under `local_name`
+    /// (an import alias or a namespaced `alias.Name`). Type aliases and
+    /// sibling schemas it references must already be collected.
+    /// Returns false if the file does not define the schema.
+    pub fn collect_schema_as(
+        &mut self,
+        file: &File,
+        name: &str,
+        local_name: &str,
+    ) -> HoneResult<bool> {
+        let def = file.preamble.iter().find_map(|item| match item {
+            PreambleItem::Schema(def) if def.name == name => Some(def),
+            _ => None,
+        });
+        let Some(def) = def else {
+            return Ok(false);
+        };
+
+        let mut schema = self.compile_schema(def)?;
+        for field in &schema.fields {
+            self.cache_regex_from_type(&field.field_type);
+        }
+        schema.name = local_name.to_string();
+        self.schemas.insert(local_name.to_string(), schema);
+        Ok(true)
+    }
+
     /// Pre-compile and cache any regex pattern found in a type.
     fn cache_regex_from_type(&mut self, ty: &Type) {
         if let Type::StringConstrained(c) = ty {
@@ -150,7 +247,14 @@ impl TypeChecker {
     /// Compile a type expression into a Type
     fn compile_type_expr(&self, expr: &TypeExpr) -> HoneResult<Type> {
         match expr {
-            TypeExpr::Named { name, args } => {
+            TypeExpr::Named {
+                name,
+                args,
+                named_args,
+            } => {
+                if name != "string" {
+                    self.reject_named_args(name, named_args)?;
+                }
                 // Check if it's a type alias first (with no args)
                 if args.is_empty() {
                     if let Some(t) = self.type_aliases.get(name) {
@@ -161,41 +265,10 @@ impl TypeChecker {
                 // Handle built-in types with optional args
                 match name.as_str() {
                     "string" => {
-                        if args.is_empty() {
+                        if args.is_empty() && named_args.is_empty() {
                             Ok(Type::String)
                         } else {
-                            let mut constraints = StringConstraints::default();
-                            let first_is_string = matches!(args.first(), Some(Expr::String(_)));
-
-                            if first_is_string {
-                                if let Some(Expr::String(s_expr)) = args.first() {
-                                    if let Some(pat) = s_expr.as_literal() {
-                                        if let Err(e) = regex::Regex::new(&pat) {
-                                            return Err(HoneError::TypeMismatch {
-                                                src: self.source.clone(),
-                                                span: (0, 0).into(),
-                                                expected: "valid regex pattern".to_string(),
-                                                found: format!("\"{}\"", pat),
-                                                help: format!("invalid regex: {}", e),
-                                            });
-                                        }
-                                        constraints.pattern = Some(pat);
-                                    }
-                                }
-                                if let Some(min) = args.get(1).and_then(extract_int) {
-                                    constraints.min_len = Some(min as usize);
-                                }
-                                if let Some(max) = args.get(2).and_then(extract_int) {
-                                    constraints.max_len = Some(max as usize);
-                                }
-                            } else {
-                                if let Some(min) = args.first().and_then(extract_int) {
-                                    constraints.min_len = Some(min as usize);
-                                }
-                                if let Some(max) = args.get(1).and_then(extract_int) {
-                                    constraints.max_len = Some(max as usize);
-                                }
-                            }
+                            let constraints = self.string_constraints(args, named_args)?;
                             Ok(Type::StringConstrained(constraints))
                         }
                     }
@@ -233,6 +306,8 @@ impl TypeChecker {
                     "object" => Ok(Type::Object(None)),
                     "array" => Ok(Type::Array(Box::new(Type::Any))),
                     "number" => Ok(Type::Number),
+                    "duration" => Ok(Type::Duration),
+                    "size" => Ok(Type::Size),
                     _ => {
                         // Schema reference
                         Ok(Type::Schema(name.clone()))
@@ -254,6 +329,7 @@ impl TypeChecker {
                     .collect::<HoneResult<Vec<_>>>()?;
                 Ok(Type::Union(compiled_types))
             }
+            TypeExpr::StringLiteral(s) => Ok(Type::StringLiteral(s.clone())),
         }
     }
 
@@ -298,55 +374,17 @@ impl TypeChecker {
     }
 
     /// Parse a type constraint into a Type
-    fn parse_type_constraint(&self, constraint: &TypeConstraint) -> HoneResult<Type> {
+    pub fn parse_type_constraint(&self, constraint: &TypeConstraint) -> HoneResult<Type> {
+        if constraint.name != "string" {
+            self.reject_named_args(&constraint.name, &constraint.named_args)?;
+        }
         let base_type = match constraint.name.as_str() {
             "string" => {
-                if constraint.args.is_empty() {
+                if constraint.args.is_empty() && constraint.named_args.is_empty() {
                     Type::String
                 } else {
-                    let mut constraints = StringConstraints::default();
-
-                    // Detect pattern: string("^[a-z]+$") - single string arg is a regex pattern
-                    let first_is_string = matches!(constraint.args.first(), Some(Expr::String(_)));
-
-                    if first_is_string {
-                        // string("pattern") or string("pattern", min, max)
-                        if let Some(Expr::String(s_expr)) = constraint.args.first() {
-                            // Only plain string literals (no interpolation) are valid patterns
-                            if let Some(pat) = s_expr.as_literal() {
-                                if let Err(e) = regex::Regex::new(&pat) {
-                                    return Err(HoneError::TypeMismatch {
-                                        src: self.source.clone(),
-                                        span: (
-                                            constraint.location.offset,
-                                            constraint.location.length,
-                                        )
-                                            .into(),
-                                        expected: "valid regex pattern".to_string(),
-                                        found: format!("\"{}\"", pat),
-                                        help: format!("invalid regex: {}", e),
-                                    });
-                                }
-                                constraints.pattern = Some(pat);
-                            }
-                        }
-                        // Optional min/max after pattern: string("pattern", min, max)
-                        if let Some(min) = constraint.args.get(1).and_then(extract_int) {
-                            constraints.min_len = Some(min as usize);
-                        }
-                        if let Some(max) = constraint.args.get(2).and_then(extract_int) {
-                            constraints.max_len = Some(max as usize);
-                        }
-                    } else {
-                        // string(min, max) for length constraints
-                        if let Some(min) = constraint.args.first().and_then(extract_int) {
-                            constraints.min_len = Some(min as usize);
-                        }
-                        if let Some(max) = constraint.args.get(1).and_then(extract_int) {
-                            constraints.max_len = Some(max as usize);
-                        }
-                    }
-
+                    let constraints =
+                        self.string_constraints(&constraint.args, &constraint.named_args)?;
                     Type::StringConstrained(constraints)
                 }
             }
@@ -383,6 +421,8 @@ impl TypeChecker {
             "bool" => Type::Bool,
             "null" => Type::Null,
             "any" => Type::Any,
+            "duration" => Type::Duration,
+            "size" => Type::Size,
             "array" => {
                 if constraint.args.is_empty() {
                     Type::Array(Box::new(Type::Any))
@@ -399,6 +439,106 @@ impl TypeChecker {
         Ok(base_type)
     }
 
+    /// Length and pattern constraints from the arguments of `string(...)`:
+    /// `string(min, max)`, `string("pattern", min, max)`, or
+    /// `string(min, max, pattern: "pattern")`
+    fn string_constraints(
+        &self,
+        args: &[Expr],
+        named_args: &[NamedTypeArg],
+    ) -> HoneResult<StringConstraints> {
+        let mut constraints = StringConstraints::default();
+
+        // A first positional string is the pattern, and min/max follow it
+        let lengths = match args.first() {
+            Some(Expr::String(s_expr)) => {
+                // Only plain string literals (no interpolation) are valid patterns
+                if let Some(pat) = s_expr.as_literal() {
+                    constraints.pattern = Some(self.check_pattern(pat, &s_expr.location)?);
+                }
+                &args[1..]
+            }
+            _ => args,
+        };
+        if let Some(min) = lengths.first().and_then(extract_int) {
+            constraints.min_len = Some(min as usize);
+        }
+        if let Some(max) = lengths.get(1).and_then(extract_int) {
+            constraints.max_len = Some(max as usize);
+        }
+
+        for arg in named_args {
+            let location = arg.value.location();
+            match arg.name.as_str() {
+                "pattern" if constraints.pattern.is_some() => {
+                    return Err(HoneError::TypeMismatch {
+                        src: self.source.clone(),
+                        span: (arg.location.offset, arg.location.length).into(),
+                        expected: "one pattern".to_string(),
+                        found: "a second pattern".to_string(),
+                        help: "string(...) was already given a pattern as its first argument"
+                            .to_string(),
+                    });
+                }
+                "pattern" => {
+                    let literal = match &arg.value {
+                        Expr::String(s_expr) => s_expr.as_literal(),
+                        _ => None,
+                    };
+                    let Some(pat) = literal else {
+                        return Err(HoneError::TypeMismatch {
+                            src: self.source.clone(),
+                            span: (location.offset, location.length).into(),
+                            expected: "a string literal".to_string(),
+                            found: "an expression".to_string(),
+                            help: "write the pattern as a plain string, without interpolation"
+                                .to_string(),
+                        });
+                    };
+                    constraints.pattern = Some(self.check_pattern(pat, location)?);
+                }
+                other => {
+                    return Err(HoneError::TypeMismatch {
+                        src: self.source.clone(),
+                        span: (arg.location.offset, arg.location.length).into(),
+                        expected: "pattern".to_string(),
+                        found: format!("'{}'", other),
+                        help: format!("string(...) takes no argument named '{}'", other),
+                    });
+                }
+            }
+        }
+        Ok(constraints)
+    }
+
+    /// `pattern` if it is a valid regex, else an error at `location`
+    fn check_pattern(&self, pattern: String, location: &SourceLocation) -> HoneResult<String> {
+        match regex::Regex::new(&pattern) {
+            Ok(_) => Ok(pattern),
+            Err(e) => Err(HoneError::TypeMismatch {
+                src: self.source.clone(),
+                span: (location.offset, location.length).into(),
+                expected: "valid regex pattern".to_string(),
+                found: format!("\"{}\"", pattern),
+                help: format!("invalid regex: {}", e),
+            }),
+        }
+    }
+
+    /// Named arguments are only taken by `string(...)`
+    fn reject_named_args(&self, type_name: &str, named_args: &[NamedTypeArg]) -> HoneResult<()> {
+        match named_args.first() {
+            Some(arg) => Err(HoneError::TypeMismatch {
+                src: self.source.clone(),
+                span: (arg.location.offset, arg.location.length).into(),
+                expected: "positional arguments".to_string(),
+                found: format!("'{}: ...'", arg.name),
+                help: format!("{} takes no named arguments", type_name),
+            }),
+            None => Ok(()),
+        }
+    }
+
     /// Check if a value matches the expected type
     pub fn check_type(
         &self,
@@ -433,6 +573,8 @@ impl TypeChecker {
             (Value::Bool(_), Type::Bool) => Ok(()),
             (Value::Int(_), Type::Int) => Ok(()),
             (Value::Float(_), Type::Float) => Ok(()),
+            (Value::Duration(_), Type::Duration) => Ok(()),
+            (Value::Size(_), Type::Size) => Ok(()),
             (Value::String(_), Type::String) => Ok(()),
 
             // Constrained integer type
@@ -705,7 +847,7 @@ impl TypeChecker {
                 Some(value) => {
                     self.check_type_at_path(value, &field.field_type, location, &field_path)?;
                 }
-                None if !field.optional => {
+                None if !field.optional && !self.partial => {
                     return Err(HoneError::MissingField {
                         src: self.source.clone(),
                         span: (location.offset, location.length).into(),
@@ -745,6 +887,15 @@ impl TypeChecker {
         self.schemas.get(name)
     }
 
+    /// The type of field `name` in schema `schema_name` or a schema it extends
+    pub fn field_type(&self, schema_name: &str, name: &str) -> Option<&Type> {
+        let schema = self.schemas.get(schema_name)?;
+        match schema.fields.iter().find(|field| field.name == name) {
+            Some(field) => Some(&field.field_type),
+            None => self.field_type(schema.extends.as_deref()?, name),
+        }
+    }
+
     /// Check a value against a type, collecting all errors instead of failing fast.
     /// Uses `location_map` to point errors at the value definition site.
     /// Falls back to `fallback_location` (typically the `use` statement) when no map entry exists.
@@ -796,6 +947,8 @@ impl TypeChecker {
             (Value::Bool(_), Type::Bool) => {}
             (Value::Int(_), Type::Int) => {}
             (Value::Float(_), Type::Float) => {}
+            (Value::Duration(_), Type::Duration) => {}
+            (Value::Size(_), Type::Size) => {}
             (Value::String(_), Type::String) => {}
 
             // Constrained integer type
@@ -1132,7 +1285,7 @@ impl TypeChecker {
                         errors,
                     );
                 }
-                None if !field.optional => {
+                None if !field.optional && !self.partial => {
                     // Missing fields always point to the use-statement / fallback
                     // (there's no definition site for something that's absent)
                     errors.push(HoneError::MissingField {
@@ -1221,7 +1374,7 @@ mod tests {
             .check_type(&Value::Int(42), &Type::Int, &loc())
             .is_ok());
         assert!(checker
-            .check_type(&Value::Float(3.14), &Type::Float, &loc())
+            .check_type(&Value::Float(3.25), &Type::Float, &loc())
             .is_ok());
         assert!(checker
             .check_type(&Value::String("hello".into()), &Type::String, &loc())
@@ -1281,7 +1434,7 @@ mod tests {
             .check_type(&Value::Int(42), &Type::Number, &loc())
             .is_ok());
         assert!(checker
-            .check_type(&Value::Float(3.14), &Type::Number, &loc())
+            .check_type(&Value::Float(3.25), &Type::Number, &loc())
             .is_ok());
         assert!(checker
             .check_type(&Value::String("42".into()), &Type::Number, &loc())
@@ -1388,6 +1541,49 @@ mod tests {
             .is_err());
     }
 
+    #[test]
+    fn test_compare_ranges() {
+        let int = |min, max| Type::IntConstrained(IntConstraints { min, max });
+        let port = int(Some(1024), Some(2048));
+        assert_eq!(
+            compare_ranges(&int(Some(1), Some(100)), &port),
+            Some(RangeRelation::Disjoint)
+        );
+        assert_eq!(
+            compare_ranges(&int(Some(4096), None), &port),
+            Some(RangeRelation::Disjoint)
+        );
+        assert_eq!(
+            compare_ranges(&int(Some(1), Some(65535)), &port),
+            Some(RangeRelation::Overlapping)
+        );
+        assert_eq!(
+            compare_ranges(&int(Some(1100), None), &port),
+            Some(RangeRelation::Overlapping)
+        );
+        assert_eq!(
+            compare_ranges(&int(Some(1100), Some(1200)), &port),
+            Some(RangeRelation::Within)
+        );
+        assert_eq!(
+            compare_ranges(&int(Some(0), None), &int(Some(0), None)),
+            Some(RangeRelation::Within)
+        );
+        let ratio = Type::FloatConstrained(FloatConstraints {
+            min: Some(0.0),
+            max: Some(1.0),
+        });
+        assert_eq!(
+            compare_ranges(&int(Some(0), Some(1)), &ratio),
+            Some(RangeRelation::Within)
+        );
+        assert_eq!(
+            compare_ranges(&int(Some(1), Some(5)), &Type::Optional(Box::new(port))),
+            Some(RangeRelation::Disjoint)
+        );
+        assert_eq!(compare_ranges(&int(Some(1), Some(5)), &Type::Int), None);
+    }
+
     #[test]
     fn test_schema_extends() {
         let mut checker = TypeChecker::new("test".into());
@@ -46,6 +46,10 @@ pub enum Type {
     FloatConstrained(FloatConstraints),
     /// Number type (int or float)
     Number,
+    /// Duration type (`5m`, `2h30m`)
+    Duration,
+    /// Size type (`512Mi`, `100MB`)
+    Size,
     /// String type
     String,
     /// String type with constraints
@@ -109,6 +113,8 @@ impl Type {
             "int" | "integer" => Some(Type::Int),
             "float" | "double" => Some(Type::Float),
             "number" => Some(Type::Number),
+            "duration" => Some(Type::Duration),
+            "size" => Some(Type::Size),
             "string" | "str" => Some(Type::String),
             "array" => Some(Type::Array(Box::new(Type::Any))),
             "object" => Some(Type::Object(None)),
@@ -126,6 +132,8 @@ impl Type {
             (Type::Bool, Type::Bool)
             | (Type::Int, Type::Int)
             | (Type::Float, Type::Float)
+            | (Type::Duration, Type::Duration)
+            | (Type::Size, Type::Size)
             | (Type::String, Type::String)
             | (Type::Null, Type::Null) => true,
 
@@ -186,6 +194,8 @@ impl fmt::Display for Type {
                 (None, None) => write!(f, "float"),
             },
             Type::Number => write!(f, "number"),
+            Type::Duration => write!(f, "duration"),
+            Type::Size => write!(f, "size"),
             Type::String => write!(f, "string"),
             Type::StringConstrained(c) => {
                 if let Some(ref pattern) = c.pattern {
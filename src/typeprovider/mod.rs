@@ -1,12 +1,27 @@
 //! Type provider: generates Hone schema definitions from external type sources.
 //!
-//! Currently supports JSON Schema → Hone schema conversion (`hone typegen`).
+//! Currently supports JSON Schema → Hone schema conversion (`hone typegen`),
+//! including schemas embedded in OpenAPI documents and Kubernetes CRDs, and
+//! message types from compiled protobuf descriptor sets.
 // Usage: `hone typegen schema.json -o types.hone`
+//        `hone typegen --openapi api.yaml --path '#/components/schemas/Config'`
+//        `hone typegen --crd crd.yaml`
+//        `hone typegen --proto config.pb --message acme.v1.Config`
+//        `hone typegen schema.json -o types.hone --check`
 
+use serde::Deserialize;
 use serde_json::Value;
-use std::collections::BTreeMap;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
 
+mod proto;
+
+pub use proto::{generate_from_descriptor_file, generate_from_descriptor_set};
+
+/// Prefixes of `$ref` pointers that name a reusable definition
+const DEF_PREFIXES: [&str; 3] = ["#/$defs/", "#/definitions/", "#/components/schemas/"];
+
 /// Generate Hone schema source from a JSON Schema file
 pub fn generate_from_file(path: &Path) -> Result<String, String> {
     let content = std::fs::read_to_string(path)
@@ -20,16 +35,294 @@ pub fn generate_from_file(path: &Path) -> Result<String, String> {
 
 /// Generate Hone schema source from a parsed JSON Schema value
 pub fn generate_from_schema(schema: &Value) -> Result<String, String> {
-    let mut generator = SchemaGenerator::new();
+    let mut generator = SchemaGenerator::new(schema);
     generator.process_root(schema)?;
     Ok(generator.output())
 }
 
+/// Generate Hone schema source from an OpenAPI (3.x or Swagger 2) document
+///
+/// With a `pointer` (e.g. `#/components/schemas/Config`), only that schema and
+/// the definitions it references are generated. Without one, every schema
+/// under `components.schemas` (or `definitions`) is generated.
+pub fn generate_from_openapi_file(path: &Path, pointer: Option<&str>) -> Result<String, String> {
+    let docs = read_documents(path)?;
+    let doc = docs
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("{} is empty", path.display()))?;
+    generate_from_openapi(&doc, pointer)
+}
+
+/// Generate Hone schema source from a parsed OpenAPI document
+pub fn generate_from_openapi(doc: &Value, pointer: Option<&str>) -> Result<String, String> {
+    let defs = doc
+        .pointer("/components/schemas")
+        .or_else(|| doc.get("definitions"))
+        .and_then(|d| d.as_object());
+
+    match pointer {
+        Some(pointer) => {
+            let ref_str = if pointer.starts_with('#') {
+                pointer.to_string()
+            } else {
+                format!("#{}", pointer)
+            };
+            generate_refs(doc, defs, &[ref_str])
+        }
+        None => {
+            defs.ok_or("no schemas found under components.schemas or definitions")?;
+            generate_refs(doc, defs, &[])
+        }
+    }
+}
+
+/// Generate the schemas named by `refs` and everything they reference, or
+/// every definition in `defs` when `refs` is empty
+fn generate_refs(
+    doc: &Value,
+    defs: Option<&serde_json::Map<String, Value>>,
+    refs: &[String],
+) -> Result<String, String> {
+    let mut generator = SchemaGenerator::new(doc);
+    if let Some(defs) = defs {
+        generator.reserve_def_names(defs);
+    }
+
+    if refs.is_empty() {
+        if let Some(defs) = defs {
+            generator.process_defs(defs)?;
+        }
+    }
+    for ref_str in refs {
+        if generator.resolve_ref(ref_str)? == "object" {
+            return Err(format!("{} does not name a schema", ref_str));
+        }
+    }
+
+    Ok(generator.output())
+}
+
+/// Generate Hone schema source from a file of Kubernetes CustomResourceDefinitions
+///
+/// Each served version of each CRD becomes a schema named after the resource
+/// kind (suffixed with the version when a CRD has more than one). `status`
+/// is omitted since users don't author it.
+pub fn generate_from_crd_file(path: &Path) -> Result<String, String> {
+    let docs = read_documents(path)?;
+    generate_from_crds(&docs)
+}
+
+/// Generate Hone schema source from parsed CustomResourceDefinition documents
+pub fn generate_from_crds(docs: &[Value]) -> Result<String, String> {
+    let mut out = String::new();
+    let mut found = false;
+
+    for doc in docs {
+        if doc.get("kind").and_then(|k| k.as_str()) != Some("CustomResourceDefinition") {
+            continue;
+        }
+        let kind = doc
+            .pointer("/spec/names/kind")
+            .and_then(|k| k.as_str())
+            .ok_or("CustomResourceDefinition is missing spec.names.kind")?;
+
+        // apiextensions.k8s.io/v1 keeps a schema per version; v1beta1 may
+        // have a single top-level `spec.validation` instead
+        let mut schemas: Vec<(String, &Value)> = Vec::new();
+        let versions = doc
+            .pointer("/spec/versions")
+            .and_then(|v| v.as_array())
+            .map(|v| v.as_slice())
+            .unwrap_or_default();
+        for version in versions {
+            if let Some(schema) = version.pointer("/schema/openAPIV3Schema") {
+                let name = version.get("name").and_then(|n| n.as_str()).unwrap_or("");
+                schemas.push((name.to_string(), schema));
+            }
+        }
+        if schemas.is_empty() {
+            if let Some(schema) = doc.pointer("/spec/validation/openAPIV3Schema") {
+                schemas.push((String::new(), schema));
+            }
+        }
+
+        let multiple = schemas.len() > 1;
+        for (version, schema) in schemas {
+            let mut schema = schema.clone();
+            if let Some(props) = schema.get_mut("properties").and_then(|p| p.as_object_mut()) {
+                props.remove("status");
+            }
+            let name = if multiple {
+                format!("{}{}", pascal_case(kind), pascal_case(&version))
+            } else {
+                pascal_case(kind)
+            };
+
+            let mut generator = SchemaGenerator::new(&schema);
+            generator.names.insert(name.clone());
+            generator.process_definition(&name, &schema)?;
+
+            if found {
+                out.push('\n');
+            }
+            out.push_str(&generator.output());
+            found = true;
+        }
+    }
+
+    if !found {
+        return Err("no CustomResourceDefinition with an openAPIV3Schema found".to_string());
+    }
+    Ok(out)
+}
+
+/// First line of a generated region (followed by source and checksum)
+const REGION_BEGIN: &str = "# --- BEGIN hone typegen";
+/// Last line of a generated region
+const REGION_END: &str = "# --- END hone typegen ---";
+
+/// A generated region found in an existing `.hone` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedRegion {
+    /// Description of the input the region was generated from
+    pub source: String,
+    /// Checksum recorded in the header when the region was written
+    pub checksum: String,
+    /// Generated source between the markers
+    pub body: String,
+    /// Byte range of the whole region (markers included) in the file
+    range: std::ops::Range<usize>,
+}
+
+/// Result of comparing an existing generated file against a fresh generation
+#[derive(Debug, Clone, PartialEq)]
+pub enum DriftStatus {
+    /// The generated region matches what would be generated now
+    UpToDate,
+    /// The region was not edited by hand, but the input has changed since
+    Drifted {
+        existing: String,
+        regenerated: String,
+    },
+    /// The region's contents no longer match its recorded checksum
+    ManuallyEdited,
+    /// The file has no generated region
+    Missing,
+}
+
+/// Wrap generated source in region markers recording its input and checksum
+pub fn wrap_generated(body: &str, source: &str) -> String {
+    format!(
+        "{} (source: {}, sha256: {}) DO NOT EDIT ---\n{}{}\n",
+        REGION_BEGIN,
+        source,
+        checksum(body),
+        body,
+        REGION_END
+    )
+}
+
+/// Replace the generated region of `existing`, keeping everything outside it.
+/// Files without a region are replaced entirely.
+pub fn splice_generated(existing: &str, region: &str) -> String {
+    match find_generated_region(existing) {
+        Some(found) => format!(
+            "{}{}{}",
+            &existing[..found.range.start],
+            region,
+            &existing[found.range.end..]
+        ),
+        None => region.to_string(),
+    }
+}
+
+/// Locate the generated region in a file
+pub fn find_generated_region(text: &str) -> Option<GeneratedRegion> {
+    let start = text.find(REGION_BEGIN)?;
+    let header_end = start + text[start..].find('\n')? + 1;
+    let header = &text[start..header_end];
+    let body_len = text[header_end..].find(REGION_END)?;
+    let body_end = header_end + body_len;
+    let mut end = body_end + REGION_END.len();
+    if text[end..].starts_with('\n') {
+        end += 1;
+    }
+
+    let field = |key: &str| -> Option<String> {
+        let rest = &header[header.find(key)? + key.len()..];
+        let len = rest.find([',', ')'])?;
+        Some(rest[..len].trim().to_string())
+    };
+
+    Some(GeneratedRegion {
+        source: field("source:")?,
+        checksum: field("sha256:")?,
+        body: text[header_end..body_end].to_string(),
+        range: start..end,
+    })
+}
+
+/// Compare the generated region of `existing` with freshly generated source
+pub fn check_generated(existing: &str, regenerated: &str) -> DriftStatus {
+    let Some(region) = find_generated_region(existing) else {
+        return DriftStatus::Missing;
+    };
+    if checksum(&region.body) != region.checksum {
+        DriftStatus::ManuallyEdited
+    } else if region.body != regenerated {
+        DriftStatus::Drifted {
+            existing: region.body,
+            regenerated: regenerated.to_string(),
+        }
+    } else {
+        DriftStatus::UpToDate
+    }
+}
+
+fn checksum(body: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(body.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Read every document from a JSON or (multi-document) YAML file
+fn read_documents(path: &Path) -> Result<Vec<Value>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+
+    if content.trim_start().starts_with('{') {
+        let doc = serde_json::from_str(&content)
+            .map_err(|e| format!("invalid JSON in {}: {}", path.display(), e))?;
+        return Ok(vec![doc]);
+    }
+
+    let mut docs = Vec::new();
+    for doc in serde_yaml::Deserializer::from_str(&content) {
+        let value = Value::deserialize(doc)
+            .map_err(|e| format!("invalid YAML in {}: {}", path.display(), e))?;
+        if !value.is_null() {
+            docs.push(value);
+        }
+    }
+    Ok(docs)
+}
+
 struct SchemaGenerator {
     /// Named schemas in definition order
     schemas: Vec<(String, SchemaInfo)>,
-    /// Type aliases for constrained primitives
+    /// Type aliases for constrained primitives, enums, and unions
     type_aliases: Vec<(String, String)>,
+    /// The root document, used to resolve local `$ref` pointers
+    root: Value,
+    /// Every schema/alias name handed out so far (keeps generated names unique)
+    names: HashSet<String>,
+    /// Names assigned to `$ref` targets outside `$defs`/`definitions`
+    ref_names: HashMap<String, String>,
+    /// Definitions already generated (or in progress), by Hone name
+    defined: HashSet<String>,
+    /// `$ref` pointers currently being resolved (guards against cycles)
+    ref_stack: Vec<String>,
 }
 
 struct SchemaInfo {
@@ -44,10 +337,15 @@ struct FieldInfo {
 }
 
 impl SchemaGenerator {
-    fn new() -> Self {
+    fn new(root: &Value) -> Self {
         Self {
             schemas: Vec::new(),
             type_aliases: Vec::new(),
+            root: root.clone(),
+            names: HashSet::new(),
+            ref_names: HashMap::new(),
+            defined: HashSet::new(),
+            ref_stack: Vec::new(),
         }
     }
 
@@ -86,11 +384,8 @@ impl SchemaGenerator {
         // Process $defs / definitions first (referenced schemas)
         if let Some(defs) = schema.get("$defs").or_else(|| schema.get("definitions")) {
             if let Some(obj) = defs.as_object() {
-                // Sort for deterministic output
-                let sorted: BTreeMap<_, _> = obj.iter().collect();
-                for (name, def_schema) in sorted {
-                    self.process_object_schema(&pascal_case(name), def_schema)?;
-                }
+                self.reserve_def_names(obj);
+                self.process_defs(obj)?;
             }
         }
 
@@ -103,12 +398,51 @@ impl SchemaGenerator {
 
         // Only process root as schema if it has properties
         if schema.get("properties").is_some() {
+            let title = self.claim_name(&title);
             self.process_object_schema(&title, schema)?;
         }
 
         Ok(())
     }
 
+    /// Reserve every definition name up front so generated nested names
+    /// never steal a name that a `$ref` points at
+    fn reserve_def_names(&mut self, defs: &serde_json::Map<String, Value>) {
+        for name in defs.keys() {
+            self.names.insert(pascal_case(name));
+        }
+    }
+
+    /// Generate every definition in a `$defs`-style map
+    fn process_defs(&mut self, defs: &serde_json::Map<String, Value>) -> Result<(), String> {
+        // Sort for deterministic output
+        let sorted: BTreeMap<_, _> = defs.iter().collect();
+        for (name, def_schema) in sorted {
+            let name = pascal_case(name);
+            if self.defined.insert(name.clone()) {
+                self.process_definition(&name, def_schema)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Process a named definition: objects become schemas, everything else
+    /// (enums, unions, constrained primitives) becomes a type alias
+    fn process_definition(&mut self, name: &str, schema: &Value) -> Result<(), String> {
+        if schema.get("properties").is_some() {
+            return self.process_object_schema(name, schema);
+        }
+
+        let type_str = match self.union_body(name, schema, "")? {
+            Some(body) => body,
+            None => strip_comment(&self.resolve_type(name, schema, "")?),
+        };
+        if type_str != name {
+            self.type_aliases.push((name.to_string(), type_str));
+        }
+        Ok(())
+    }
+
     fn process_object_schema(&mut self, name: &str, schema: &Value) -> Result<(), String> {
         let properties = match schema.get("properties") {
             Some(p) => match p.as_object() {
@@ -170,7 +504,7 @@ impl SchemaGenerator {
     ) -> Result<String, String> {
         // Handle $ref
         if let Some(ref_str) = schema.get("$ref").and_then(|r| r.as_str()) {
-            return Ok(self.resolve_ref(ref_str));
+            return self.resolve_ref(ref_str);
         }
 
         // Handle allOf (treat as the first schema for simplicity)
@@ -180,64 +514,19 @@ impl SchemaGenerator {
             }
         }
 
-        // Handle oneOf/anyOf as union (simplified: take the types)
-        if let Some(one_of) = schema.get("oneOf").or_else(|| schema.get("anyOf")) {
-            if let Some(arr) = one_of.as_array() {
-                let mut types = Vec::new();
-                for item in arr {
-                    types.push(self.resolve_type(field_name, item, parent_name)?);
-                }
-                // Deduplicate
-                types.dedup();
-                if types.len() == 1 {
-                    return Ok(types.into_iter().next().unwrap());
-                }
-                // Hone doesn't have union types in schemas, use the first non-null
-                let non_null: Vec<_> = types.into_iter().filter(|t| t != "null").collect();
-                return Ok(non_null
-                    .first()
-                    .cloned()
-                    .unwrap_or_else(|| "object".to_string()));
-            }
-        }
-
-        // Handle enum (string enum -> string, with comment)
-        if let Some(_enum_values) = schema.get("enum") {
-            let base_type = schema
-                .get("type")
-                .and_then(|t| {
-                    if let Some(s) = t.as_str() {
-                        return Some(s);
-                    }
-                    if let Some(arr) = t.as_array() {
-                        return arr.iter().filter_map(|v| v.as_str()).find(|s| *s != "null");
-                    }
-                    None
-                })
-                .unwrap_or("string");
-            return Ok(map_primitive_type(base_type));
+        // enum / const / oneOf / anyOf / multi-type: literal and type unions
+        // need a named alias, since schema fields take a single type name
+        if let Some(body) = self.union_body(field_name, schema, parent_name)? {
+            if !needs_alias(&body) {
+                return Ok(body);
+            }
+            let alias = self.claim_name(&format!("{}{}", parent_name, pascal_case(field_name)));
+            self.type_aliases.push((alias.clone(), body));
+            return Ok(alias);
         }
 
         // Get the type field, handling both "type": "string" and "type": ["string", "null"]
-        let type_val = schema.get("type");
-        let type_str = type_val.and_then(|t| {
-            // Direct string form: "type": "string"
-            if let Some(s) = t.as_str() {
-                return Some(s.to_string());
-            }
-            // Array form: "type": ["string", "null"] -- filter out "null", use remaining
-            if let Some(arr) = t.as_array() {
-                let non_null: Vec<&str> = arr
-                    .iter()
-                    .filter_map(|v| v.as_str())
-                    .filter(|s| *s != "null")
-                    .collect();
-                if non_null.len() == 1 {
-                    return Some(non_null[0].to_string());
-                }
-            }
-            None
-        });
+        let type_str = single_type(schema);
 
         match type_str.as_deref() {
             Some("string") => Ok(self.resolve_string_type(schema)),
@@ -249,9 +538,7 @@ impl SchemaGenerator {
             Some("object") => {
                 // Nested object - if it has properties, create a named sub-schema
                 if schema.get("properties").is_some() {
-                    let sub_name = format!("{}{}", parent_name, pascal_case(field_name));
-                    self.process_object_schema(&sub_name, schema)?;
-                    Ok(sub_name)
+                    self.nested_schema(field_name, schema, parent_name)
                 } else {
                     Ok("object".to_string())
                 }
@@ -260,9 +547,7 @@ impl SchemaGenerator {
             None => {
                 // No type specified - check for properties (implicit object)
                 if schema.get("properties").is_some() {
-                    let sub_name = format!("{}{}", parent_name, pascal_case(field_name));
-                    self.process_object_schema(&sub_name, schema)?;
-                    Ok(sub_name)
+                    self.nested_schema(field_name, schema, parent_name)
                 } else {
                     Ok("object".to_string())
                 }
@@ -270,13 +555,109 @@ impl SchemaGenerator {
         }
     }
 
+    /// Generate a sub-schema for a nested object, named `<Parent><Field>`
+    fn nested_schema(
+        &mut self,
+        field_name: &str,
+        schema: &Value,
+        parent_name: &str,
+    ) -> Result<String, String> {
+        let sub_name = self.claim_name(&format!("{}{}", parent_name, pascal_case(field_name)));
+        self.process_object_schema(&sub_name, schema)?;
+        Ok(sub_name)
+    }
+
+    /// Build the right-hand side of a union or literal type for `enum`,
+    /// `const`, `oneOf`/`anyOf`, and multi-valued `type` arrays.
+    ///
+    /// Returns `None` when the schema is none of those. `null` members are
+    /// dropped: optionality is expressed with `?` on the field instead.
+    fn union_body(
+        &mut self,
+        field_name: &str,
+        schema: &Value,
+        parent_name: &str,
+    ) -> Result<Option<String>, String> {
+        // Kubernetes structural schemas mark IntOrString with an extension
+        if schema
+            .get("x-kubernetes-int-or-string")
+            .and_then(|v| v.as_bool())
+            == Some(true)
+        {
+            return Ok(Some("int | string".to_string()));
+        }
+
+        let enum_values = match (schema.get("enum"), schema.get("const")) {
+            (Some(Value::Array(values)), _) => Some(values.clone()),
+            (_, Some(value)) => Some(vec![value.clone()]),
+            _ => None,
+        };
+
+        if let Some(values) = enum_values {
+            let non_null: Vec<&Value> = values.iter().filter(|v| !v.is_null()).collect();
+            if !non_null.is_empty() && non_null.iter().all(|v| v.is_string()) {
+                let literals: Vec<String> = non_null
+                    .iter()
+                    .filter_map(|v| v.as_str())
+                    .map(hone_string)
+                    .collect();
+                return Ok(Some(dedup(literals).join(" | ")));
+            }
+
+            // Non-string enums can't be expressed as literals; fall back to
+            // the declared type, or infer it from the values themselves
+            let base_type = single_type(schema).unwrap_or_else(|| {
+                let types: Vec<String> = non_null.iter().map(|v| json_value_type(v)).collect();
+                match dedup(types).as_slice() {
+                    [single] => single.clone(),
+                    _ => "any".to_string(),
+                }
+            });
+            return Ok(Some(map_primitive_type(&base_type)));
+        }
+
+        if let Some(one_of) = schema.get("oneOf").or_else(|| schema.get("anyOf")) {
+            if let Some(arr) = one_of.as_array() {
+                let mut types = Vec::new();
+                for (i, item) in arr.iter().enumerate() {
+                    // Give inline object variants distinct names
+                    let member_field = if arr.len() > 1 {
+                        format!("{}{}", field_name, i + 1)
+                    } else {
+                        field_name.to_string()
+                    };
+                    types.push(strip_comment(&self.resolve_type(
+                        &member_field,
+                        item,
+                        parent_name,
+                    )?));
+                }
+                return Ok(Some(join_union(types)));
+            }
+        }
+
+        if let Some(arr) = schema.get("type").and_then(|t| t.as_array()) {
+            let non_null: Vec<&str> = arr
+                .iter()
+                .filter_map(|v| v.as_str())
+                .filter(|s| *s != "null")
+                .collect();
+            if non_null.len() > 1 {
+                let types = non_null.iter().map(|t| map_primitive_type(t)).collect();
+                return Ok(Some(join_union(types)));
+            }
+        }
+
+        Ok(None)
+    }
+
     fn resolve_string_type(&self, schema: &Value) -> String {
         let min_len = schema.get("minLength").and_then(|v| v.as_u64());
         let max_len = schema.get("maxLength").and_then(|v| v.as_u64());
         let pattern = schema.get("pattern").and_then(|v| v.as_str());
 
         if let Some(pat) = pattern {
-            return format!("string(\"{}\")", pat);
+            return format!("string({})", hone_string(pat));
         }
 
         match (min_len, max_len) {
@@ -316,26 +697,173 @@ impl SchemaGenerator {
             let item_type = self.resolve_type(field_name, items, parent_name)?;
             // Hone's array type doesn't carry element type in the type constraint
             // but we can add it as a comment in the output
-            Ok(format!("array # {}", item_type))
+            Ok(format!("array # {}", strip_comment(&item_type)))
         } else {
             Ok("array".to_string())
         }
     }
 
-    fn resolve_ref(&self, ref_str: &str) -> String {
-        // Handle local $ref like "#/$defs/Foo" or "#/definitions/Foo"
-        if let Some(name) = ref_str
-            .strip_prefix("#/$defs/")
-            .or_else(|| ref_str.strip_prefix("#/definitions/"))
-        {
-            pascal_case(name)
-        } else {
+    fn resolve_ref(&mut self, ref_str: &str) -> Result<String, String> {
+        // Handle local $ref like "#/$defs/Foo" or "#/components/schemas/Foo",
+        // generating the definition the first time it is referenced
+        let def_name = DEF_PREFIXES
+            .iter()
+            .find_map(|prefix| ref_str.strip_prefix(prefix))
+            .filter(|name| !name.contains('/'));
+        if let Some(name) = def_name {
+            let name = pascal_case(&unescape_pointer(name));
+            if !self.defined.contains(&name) {
+                let target = self.root.pointer(&ref_str[1..]).cloned();
+                let Some(target) = target else {
+                    return Err(format!("unresolved $ref: {}", ref_str));
+                };
+                self.names.insert(name.clone());
+                self.defined.insert(name.clone());
+                self.process_definition(&name, &target)?;
+            }
+            return Ok(name);
+        }
+
+        // Any other local JSON pointer: resolve against the root document
+        let Some(pointer) = ref_str.strip_prefix('#') else {
             // Remote refs not supported - return as object
-            "object".to_string()
+            return Ok("object".to_string());
+        };
+        if let Some(name) = self.ref_names.get(ref_str) {
+            return Ok(name.clone());
+        }
+        if self.ref_stack.iter().any(|r| r == ref_str) {
+            // Self-referential pointer outside $defs: nothing to name it after
+            return Ok("object".to_string());
+        }
+        let Some(target) = self.root.pointer(pointer).cloned() else {
+            return Err(format!("unresolved $ref: {}", ref_str));
+        };
+
+        let segments: Vec<String> = pointer
+            .split('/')
+            .filter(|s| !s.is_empty() && *s != "properties")
+            .map(unescape_pointer)
+            .collect();
+        let field_name = segments.last().cloned().unwrap_or_else(|| "Root".into());
+        let parent_name: String = segments[..segments.len().saturating_sub(1)]
+            .iter()
+            .map(|s| pascal_case(s.trim_start_matches('$')))
+            .collect();
+
+        self.ref_stack.push(ref_str.to_string());
+        let resolved = self.resolve_type(&field_name, &target, &parent_name);
+        self.ref_stack.pop();
+        let resolved = resolved?;
+        self.ref_names.insert(ref_str.to_string(), resolved.clone());
+        Ok(resolved)
+    }
+
+    /// Reserve a schema/alias name, appending a numeric suffix on collision
+    fn claim_name(&mut self, base: &str) -> String {
+        let mut name = base.to_string();
+        let mut n = 2;
+        while self.names.contains(&name) {
+            name = format!("{}{}", base, n);
+            n += 1;
         }
+        self.names.insert(name.clone());
+        name
+    }
+}
+
+/// Get the single non-null `type` of a schema, if it declares exactly one
+fn single_type(schema: &Value) -> Option<String> {
+    let t = schema.get("type")?;
+    // Direct string form: "type": "string"
+    if let Some(s) = t.as_str() {
+        return Some(s.to_string());
+    }
+    // Array form: "type": ["string", "null"] -- filter out "null", use remaining
+    let non_null: Vec<&str> = t
+        .as_array()?
+        .iter()
+        .filter_map(|v| v.as_str())
+        .filter(|s| *s != "null")
+        .collect();
+    if non_null.len() == 1 {
+        Some(non_null[0].to_string())
+    } else {
+        None
+    }
+}
+
+/// JSON Schema type name for a literal JSON value
+fn json_value_type(value: &Value) -> String {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+    .to_string()
+}
+
+/// Join union members, dropping `null` and duplicates
+fn join_union(types: Vec<String>) -> String {
+    let members: Vec<String> = dedup(types).into_iter().filter(|t| t != "null").collect();
+    if members.is_empty() {
+        "object".to_string()
+    } else {
+        members.join(" | ")
     }
 }
 
+/// Order-preserving deduplication
+fn dedup(items: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    items
+        .into_iter()
+        .filter(|item| seen.insert(item.clone()))
+        .collect()
+}
+
+/// Whether a type string is only valid in a `type` alias (literals and unions)
+fn needs_alias(type_str: &str) -> bool {
+    type_str.starts_with('"') || type_str.contains(" | ")
+}
+
+/// Drop a trailing `# comment` (used for array element hints)
+fn strip_comment(type_str: &str) -> String {
+    match type_str.find(" # ") {
+        Some(idx) => type_str[..idx].to_string(),
+        None => type_str.to_string(),
+    }
+}
+
+/// Decode a JSON pointer segment (`~1` → `/`, `~0` → `~`)
+fn unescape_pointer(segment: &str) -> String {
+    segment.replace("~1", "/").replace("~0", "~")
+}
+
+/// Render a string as a double-quoted Hone string literal
+fn hone_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
 /// Convert a string to PascalCase for schema names
 fn pascal_case(s: &str) -> String {
     s.split(['_', '-', ' ', '.'])
@@ -475,12 +1003,13 @@ mod tests {
         });
 
         let result = generate_from_schema(&schema).unwrap();
-        // The pattern is emitted as-is from JSON Schema
+        // Backslashes are escaped so the pattern survives Hone string lexing
         assert!(
-            result.contains(r#"string("^[a-z]+@[a-z]+\.[a-z]+$")"#),
+            result.contains(r#"string("^[a-z]+@[a-z]+\\.[a-z]+$")"#),
             "got: {}",
             result
         );
+        assert_parses(&result);
     }
 
     #[test]
@@ -685,7 +1214,7 @@ mod tests {
     }
 
     #[test]
-    fn test_enum_becomes_base_type() {
+    fn test_enum_becomes_literal_union() {
         let schema = serde_json::json!({
             "type": "object",
             "title": "Status",
@@ -697,10 +1226,12 @@ mod tests {
 
         let result = generate_from_schema(&schema).unwrap();
         assert!(
-            result.contains("state: string"),
-            "enum should map to base type, got: {}",
+            result.contains(r#"type StatusState = "active" | "inactive" | "pending""#),
+            "enum should map to a literal union alias, got: {}",
             result
         );
+        assert!(result.contains("state: StatusState"), "got: {}", result);
+        assert_parses(&result);
     }
 
     #[test]
@@ -807,9 +1338,333 @@ mod tests {
 
         let result = generate_from_schema(&schema).unwrap();
         assert!(
-            result.contains("protocol?: string"),
-            "nullable enum should resolve to string, got: {}",
+            result.contains(r#"type PolicyProtocol = "TCP" | "UDP" | "SCTP""#),
+            "nullable enum should drop null from the union, got: {}",
+            result
+        );
+        assert!(
+            result.contains("protocol?: PolicyProtocol"),
+            "got: {}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_integer_enum_becomes_base_type() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "title": "Tls",
+            "properties": {
+                "version": { "enum": [12, 13] }
+            }
+        });
+
+        let result = generate_from_schema(&schema).unwrap();
+        assert!(result.contains("version?: int\n"), "got: {}", result);
+    }
+
+    #[test]
+    fn test_one_of_becomes_union_alias() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "title": "Container",
+            "properties": {
+                "port": { "oneOf": [{ "type": "integer" }, { "type": "string" }] },
+                "name": { "anyOf": [{ "type": "string" }, { "type": "null" }] }
+            },
+            "required": ["port"]
+        });
+
+        let result = generate_from_schema(&schema).unwrap();
+        assert!(
+            result.contains("type ContainerPort = int | string"),
+            "got: {}",
+            result
+        );
+        assert!(result.contains("port: ContainerPort"), "got: {}", result);
+        assert!(
+            result.contains("name?: string\n"),
+            "null-only alternative should collapse, got: {}",
+            result
+        );
+        assert_parses(&result);
+    }
+
+    #[test]
+    fn test_non_object_defs_become_aliases() {
+        let schema = serde_json::json!({
+            "title": "Service",
+            "$defs": {
+                "port": { "type": "integer", "minimum": 1, "maximum": 65535 },
+                "protocol": { "enum": ["TCP", "UDP"] },
+                "intOrString": { "anyOf": [{ "type": "integer" }, { "type": "string" }] }
+            },
+            "properties": {
+                "port": { "$ref": "#/$defs/port" },
+                "protocol": { "$ref": "#/$defs/protocol" },
+                "target": { "$ref": "#/$defs/intOrString" }
+            }
+        });
+
+        let result = generate_from_schema(&schema).unwrap();
+        assert!(
+            result.contains("type Port = int(1, 65535)"),
+            "got: {}",
             result
         );
+        assert!(
+            result.contains(r#"type Protocol = "TCP" | "UDP""#),
+            "got: {}",
+            result
+        );
+        assert!(
+            result.contains("type IntOrString = int | string"),
+            "got: {}",
+            result
+        );
+        assert!(result.contains("port?: Port"), "got: {}", result);
+        assert!(result.contains("target?: IntOrString"), "got: {}", result);
+        assert_parses(&result);
+    }
+
+    #[test]
+    fn test_ref_to_nested_pointer() {
+        let schema = serde_json::json!({
+            "title": "App",
+            "$defs": {
+                "Server": {
+                    "properties": {
+                        "tls": {
+                            "properties": { "cert": { "type": "string" } },
+                            "required": ["cert"]
+                        }
+                    }
+                }
+            },
+            "properties": {
+                "server": { "$ref": "#/$defs/Server" },
+                "tls": { "$ref": "#/$defs/Server/properties/tls" }
+            }
+        });
+
+        let result = generate_from_schema(&schema).unwrap();
+        assert!(result.contains("tls?: ServerTls"), "got: {}", result);
+        assert_eq!(
+            result.matches("schema ServerTls ").count(),
+            1,
+            "pointer target should be generated once, got: {}",
+            result
+        );
+        assert_parses(&result);
+    }
+
+    #[test]
+    fn test_unresolved_ref_is_an_error() {
+        let schema = serde_json::json!({
+            "title": "Broken",
+            "properties": {
+                "x": { "$ref": "#/properties/missing" }
+            }
+        });
+
+        let err = generate_from_schema(&schema).unwrap_err();
+        assert!(err.contains("#/properties/missing"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_nested_names_do_not_collide_with_defs() {
+        let schema = serde_json::json!({
+            "title": "Config",
+            "$defs": {
+                "ConfigServer": {
+                    "properties": { "id": { "type": "integer" } }
+                }
+            },
+            "properties": {
+                "server": {
+                    "properties": { "host": { "type": "string" } }
+                }
+            }
+        });
+
+        let result = generate_from_schema(&schema).unwrap();
+        assert!(result.contains("schema ConfigServer {"), "got: {}", result);
+        assert!(result.contains("schema ConfigServer2 {"), "got: {}", result);
+        assert!(result.contains("server?: ConfigServer2"), "got: {}", result);
+    }
+
+    #[test]
+    fn test_openapi_path_generates_referenced_schemas_only() {
+        let doc = serde_json::json!({
+            "openapi": "3.0.0",
+            "components": {
+                "schemas": {
+                    "Config": {
+                        "type": "object",
+                        "properties": {
+                            "db": { "$ref": "#/components/schemas/Database" }
+                        }
+                    },
+                    "Database": {
+                        "type": "object",
+                        "properties": { "host": { "type": "string" } },
+                        "required": ["host"]
+                    },
+                    "Unused": {
+                        "type": "object",
+                        "properties": { "x": { "type": "integer" } }
+                    }
+                }
+            }
+        });
+
+        let result = generate_from_openapi(&doc, Some("#/components/schemas/Config")).unwrap();
+        assert!(result.contains("schema Config {"), "got: {}", result);
+        assert!(result.contains("db?: Database"), "got: {}", result);
+        assert!(result.contains("schema Database {"), "got: {}", result);
+        assert!(!result.contains("Unused"), "got: {}", result);
+        assert!(
+            result.find("schema Database").unwrap() < result.find("schema Config").unwrap(),
+            "dependencies come first, got: {}",
+            result
+        );
+
+        let all = generate_from_openapi(&doc, None).unwrap();
+        assert!(all.contains("schema Unused {"), "got: {}", all);
+        assert_parses(&all);
+    }
+
+    #[test]
+    fn test_openapi_missing_path_is_an_error() {
+        let doc = serde_json::json!({ "components": { "schemas": {} } });
+        let err = generate_from_openapi(&doc, Some("#/components/schemas/Nope")).unwrap_err();
+        assert!(err.contains("Nope"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_swagger2_definitions() {
+        let doc = serde_json::json!({
+            "swagger": "2.0",
+            "definitions": {
+                "Pet": {
+                    "type": "object",
+                    "properties": { "name": { "type": "string" } }
+                }
+            }
+        });
+
+        let result = generate_from_openapi(&doc, None).unwrap();
+        assert!(result.contains("schema Pet {"), "got: {}", result);
+    }
+
+    #[test]
+    fn test_crd_versions_and_status() {
+        let crd = serde_json::json!({
+            "apiVersion": "apiextensions.k8s.io/v1",
+            "kind": "CustomResourceDefinition",
+            "spec": {
+                "names": { "kind": "Widget" },
+                "versions": [{
+                    "name": "v1",
+                    "schema": {
+                        "openAPIV3Schema": {
+                            "type": "object",
+                            "properties": {
+                                "spec": {
+                                    "type": "object",
+                                    "properties": {
+                                        "port": { "x-kubernetes-int-or-string": true }
+                                    }
+                                },
+                                "status": {
+                                    "type": "object",
+                                    "properties": { "ready": { "type": "boolean" } }
+                                }
+                            }
+                        }
+                    }
+                }]
+            }
+        });
+
+        let result = generate_from_crds(std::slice::from_ref(&crd)).unwrap();
+        assert!(result.contains("schema Widget {"), "got: {}", result);
+        assert!(result.contains("spec?: WidgetSpec"), "got: {}", result);
+        assert!(
+            result.contains("type WidgetSpecPort = int | string"),
+            "got: {}",
+            result
+        );
+        assert!(!result.contains("status"), "got: {}", result);
+        assert_parses(&result);
+
+        let mut two_versions = crd.clone();
+        let v1 = two_versions["spec"]["versions"][0].clone();
+        let mut v2 = v1.clone();
+        v2["name"] = serde_json::json!("v2");
+        two_versions["spec"]["versions"] = serde_json::json!([v1, v2]);
+        let result = generate_from_crds(&[two_versions]).unwrap();
+        assert!(result.contains("schema WidgetV1 {"), "got: {}", result);
+        assert!(result.contains("schema WidgetV2 {"), "got: {}", result);
+    }
+
+    #[test]
+    fn test_crd_input_without_crds_is_an_error() {
+        let doc = serde_json::json!({ "kind": "ConfigMap" });
+        assert!(generate_from_crds(&[doc]).is_err());
+    }
+
+    #[test]
+    fn test_generated_region_roundtrip() {
+        let body = "schema A {\n  x: int\n}\n";
+        let region = wrap_generated(body, "a.json");
+        assert!(region.starts_with("# --- BEGIN hone typegen (source: a.json, sha256: "));
+        assert_parses(&region);
+
+        let found = find_generated_region(&region).unwrap();
+        assert_eq!(found.source, "a.json");
+        assert_eq!(found.body, body);
+        assert_eq!(check_generated(&region, body), DriftStatus::UpToDate);
+    }
+
+    #[test]
+    fn test_splice_keeps_content_outside_region() {
+        let old = wrap_generated("schema A {\n  x: int\n}\n", "a.json");
+        let file = format!("# handwritten\n{}\ntype Extra = int\n", old);
+        let new_region = wrap_generated("schema A {\n  x: string\n}\n", "a.json");
+
+        let spliced = splice_generated(&file, &new_region);
+        assert!(spliced.starts_with("# handwritten\n"));
+        assert!(spliced.ends_with("\ntype Extra = int\n"));
+        assert!(spliced.contains("x: string"));
+        assert!(!spliced.contains("x: int"));
+
+        assert_eq!(splice_generated("no markers\n", &new_region), new_region);
+    }
+
+    #[test]
+    fn test_check_generated_detects_drift_and_edits() {
+        let body = "schema A {\n  x: int\n}\n";
+        let region = wrap_generated(body, "a.json");
+
+        let regenerated = "schema A {\n  x: string\n}\n";
+        assert_eq!(
+            check_generated(&region, regenerated),
+            DriftStatus::Drifted {
+                existing: body.to_string(),
+                regenerated: regenerated.to_string(),
+            }
+        );
+
+        let edited = region.replace("x: int", "x: int\n  y: bool");
+        assert_eq!(check_generated(&edited, body), DriftStatus::ManuallyEdited);
+        assert_eq!(check_generated("schema A {}\n", body), DriftStatus::Missing);
+    }
+
+    fn assert_parses(hone_source: &str) {
+        let mut lexer = crate::lexer::Lexer::new(hone_source, None);
+        let tokens = lexer.tokenize().expect("generated source should lex");
+        let mut parser = crate::parser::Parser::new(tokens, hone_source, None);
+        parser.parse().expect("generated source should parse");
     }
 }
@@ -0,0 +1,541 @@
+//! Protobuf descriptor sets → Hone schemas (`hone typegen --proto`).
+//!
+//! Messages and enums from a compiled `FileDescriptorSet` (as produced by
+//! `protoc --descriptor_set_out` or `buf build -o`) are translated into a
+//! JSON Schema `$defs` document following the proto3 JSON mapping, then run
+//! through the regular JSON Schema generator. Types are named after their
+//! local name (`Config.Tls` → `ConfigTls`) unless another type in the set
+//! would get the same name; then both are named after their full name
+//! (`acme.v1.Config.Tls` → `AcmeV1ConfigTls`).
+
+use prost::Message;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::{generate_refs, pascal_case};
+
+/// Generate Hone schema source from a compiled protobuf descriptor set file
+///
+/// `messages` selects fully-qualified message names (e.g. `acme.v1.Config`);
+/// only those and the types they reference are generated. When empty, every
+/// message and enum in the set is generated.
+pub fn generate_from_descriptor_file(path: &Path, messages: &[String]) -> Result<String, String> {
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    generate_from_descriptor_set(&bytes, messages).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Generate Hone schema source from an encoded `FileDescriptorSet`
+pub fn generate_from_descriptor_set(bytes: &[u8], messages: &[String]) -> Result<String, String> {
+    let set = FileDescriptorSet::decode(bytes)
+        .map_err(|e| format!("invalid protobuf descriptor set: {}", e))?;
+
+    let mut index = TypeIndex::default();
+    for file in &set.file {
+        let package = file.package.as_deref().unwrap_or("");
+        for message in &file.message_type {
+            index.add_message(package, "", message);
+        }
+        for enum_type in &file.enum_type {
+            index.add_enum(package, "", enum_type);
+        }
+    }
+    index.qualify_shared_names()?;
+
+    let mut defs = Map::new();
+    for file in &set.file {
+        let package = file.package.as_deref().unwrap_or("");
+        for message in &file.message_type {
+            index.message_defs(package, "", message, &mut defs)?;
+        }
+        for enum_type in &file.enum_type {
+            let key = index.key(package, enum_type.name());
+            insert_def(&mut defs, key, enum_schema(enum_type))?;
+        }
+    }
+
+    let refs = messages
+        .iter()
+        .map(|name| {
+            let full_name = format!(".{}", name.trim_start_matches('.'));
+            match index.types.get(&full_name) {
+                Some(ProtoType::Message(key)) => Ok(format!("#/$defs/{}", key)),
+                _ => Err(format!("message '{}' not found in descriptor set", name)),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let doc = json!({ "$defs": defs });
+    generate_refs(&doc, doc["$defs"].as_object(), &refs)
+}
+
+/// What a fully-qualified protobuf type name refers to
+enum ProtoType {
+    /// A message, by its `$defs` key
+    Message(String),
+    /// A map entry message; maps are emitted as plain objects
+    MapEntry,
+    /// An enum, by its `$defs` key
+    Enum(String),
+}
+
+#[derive(Default)]
+struct TypeIndex {
+    /// Fully-qualified name (`.pkg.Outer.Inner`) to type
+    types: HashMap<String, ProtoType>,
+}
+
+impl TypeIndex {
+    fn add_message(&mut self, package: &str, scope: &str, message: &DescriptorProto) {
+        let local = scoped(scope, message.name());
+        let is_map_entry = message.options.as_ref().and_then(|o| o.map_entry) == Some(true);
+        let entry = if is_map_entry {
+            ProtoType::MapEntry
+        } else {
+            ProtoType::Message(local.clone())
+        };
+        self.types.insert(full_name(package, &local), entry);
+
+        for nested in &message.nested_type {
+            self.add_message(package, &local, nested);
+        }
+        for enum_type in &message.enum_type {
+            self.add_enum(package, &local, enum_type);
+        }
+    }
+
+    fn add_enum(&mut self, package: &str, scope: &str, enum_type: &EnumDescriptorProto) {
+        let local = scoped(scope, enum_type.name());
+        self.types
+            .insert(full_name(package, &local), ProtoType::Enum(local.clone()));
+    }
+
+    /// Hone names of every message and enum, by the full names that get
+    /// each one
+    fn hone_names(&self) -> HashMap<String, Vec<&str>> {
+        let mut names: HashMap<String, Vec<&str>> = HashMap::new();
+        for (full_name, ty) in &self.types {
+            if let ProtoType::Message(key) | ProtoType::Enum(key) = ty {
+                names
+                    .entry(pascal_case(key))
+                    .or_default()
+                    .push(full_name.as_str());
+            }
+        }
+        names
+    }
+
+    /// Key types that would share a Hone name with another type by their
+    /// full name, so that neither replaces the other. Names that still
+    /// clash, like a nested `Config.Tls` beside a `ConfigTls` in the same
+    /// package, are an error.
+    fn qualify_shared_names(&mut self) -> Result<(), String> {
+        let shared: Vec<String> = self
+            .hone_names()
+            .into_values()
+            .filter(|full_names| full_names.len() > 1)
+            .flatten()
+            .map(str::to_string)
+            .collect();
+        for full_name in shared {
+            if let Some(ProtoType::Message(key) | ProtoType::Enum(key)) =
+                self.types.get_mut(&full_name)
+            {
+                *key = full_name.trim_start_matches('.').to_string();
+            }
+        }
+
+        let mut clashes: Vec<(String, Vec<&str>)> = self
+            .hone_names()
+            .into_iter()
+            .filter(|(_, full_names)| full_names.len() > 1)
+            .collect();
+        clashes.sort();
+        match clashes.first_mut() {
+            Some((name, full_names)) => {
+                full_names.sort();
+                Err(format!(
+                    "{} would share the name '{}'",
+                    full_names
+                        .iter()
+                        .map(|n| n.trim_start_matches('.'))
+                        .collect::<Vec<_>>()
+                        .join(" and "),
+                    name
+                ))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// The `$defs` key of a message or enum
+    fn key(&self, package: &str, local: &str) -> String {
+        match self.types.get(&full_name(package, local)) {
+            Some(ProtoType::Message(key)) | Some(ProtoType::Enum(key)) => key.clone(),
+            _ => local.to_string(),
+        }
+    }
+
+    /// Add JSON Schema definitions for a message and everything nested in it
+    fn message_defs(
+        &self,
+        package: &str,
+        scope: &str,
+        message: &DescriptorProto,
+        defs: &mut Map<String, Value>,
+    ) -> Result<(), String> {
+        let local = scoped(scope, message.name());
+        for nested in &message.nested_type {
+            self.message_defs(package, &local, nested, defs)?;
+        }
+        for enum_type in &message.enum_type {
+            let key = self.key(package, &scoped(&local, enum_type.name()));
+            insert_def(defs, key, enum_schema(enum_type))?;
+        }
+        if message.options.as_ref().and_then(|o| o.map_entry) == Some(true) {
+            return Ok(());
+        }
+
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for field in &message.field {
+            let name = field
+                .json_name
+                .clone()
+                .unwrap_or_else(|| field.name().into());
+            if field.label() == Label::Required {
+                required.push(Value::String(name.clone()));
+            }
+            properties.insert(name, self.field_schema(field));
+        }
+
+        // Protobuf JSON parsers reject unknown fields, so schemas are closed
+        insert_def(
+            defs,
+            self.key(package, &local),
+            json!({
+                "type": "object",
+                "properties": properties,
+                "required": required,
+                "additionalProperties": false,
+            }),
+        )
+    }
+
+    fn field_schema(&self, field: &FieldDescriptorProto) -> Value {
+        let type_name = field.type_name();
+        let element = match field.r#type() {
+            Type::Double | Type::Float => json!({ "type": "number" }),
+            Type::Int64
+            | Type::Uint64
+            | Type::Int32
+            | Type::Fixed64
+            | Type::Fixed32
+            | Type::Uint32
+            | Type::Sfixed32
+            | Type::Sfixed64
+            | Type::Sint32
+            | Type::Sint64 => json!({ "type": "integer" }),
+            Type::Bool => json!({ "type": "boolean" }),
+            // bytes are base64 strings in the JSON mapping
+            Type::String | Type::Bytes => json!({ "type": "string" }),
+            Type::Group => json!({ "type": "object" }),
+            Type::Message | Type::Enum => match well_known_type(type_name) {
+                Some(schema) => schema,
+                None => match self.types.get(type_name) {
+                    Some(ProtoType::Message(key)) | Some(ProtoType::Enum(key)) => {
+                        json!({ "$ref": format!("#/$defs/{}", key) })
+                    }
+                    // map<K, V> fields are repeated map entries: a plain object
+                    Some(ProtoType::MapEntry) => return json!({ "type": "object" }),
+                    None => json!({ "type": "object" }),
+                },
+            },
+        };
+
+        if field.label() == Label::Repeated {
+            json!({ "type": "array", "items": element })
+        } else {
+            element
+        }
+    }
+}
+
+/// Add a definition, unless another type already has its name, as the same
+/// message listed in two files of the set would
+fn insert_def(defs: &mut Map<String, Value>, key: String, schema: Value) -> Result<(), String> {
+    if defs.contains_key(&key) {
+        return Err(format!(
+            "two types in the descriptor set are named '{}'",
+            key
+        ));
+    }
+    defs.insert(key, schema);
+    Ok(())
+}
+
+/// JSON mapping of `google.protobuf` well-known types
+fn well_known_type(type_name: &str) -> Option<Value> {
+    let name = type_name.strip_prefix(".google.protobuf.")?;
+    let schema = match name {
+        "Timestamp" | "Duration" | "FieldMask" | "StringValue" | "BytesValue" => {
+            json!({ "type": "string" })
+        }
+        "Int32Value" | "UInt32Value" | "Int64Value" | "UInt64Value" => {
+            json!({ "type": "integer" })
+        }
+        "DoubleValue" | "FloatValue" => json!({ "type": "number" }),
+        "BoolValue" => json!({ "type": "boolean" }),
+        "Struct" | "Any" | "Empty" => json!({ "type": "object" }),
+        "ListValue" => json!({ "type": "array" }),
+        "Value" => json!({ "type": "any" }),
+        _ => return None,
+    };
+    Some(schema)
+}
+
+/// Enums serialize as their value names in the JSON mapping
+fn enum_schema(enum_type: &EnumDescriptorProto) -> Value {
+    let names: Vec<&str> = enum_type.value.iter().map(|v| v.name()).collect();
+    json!({ "enum": names })
+}
+
+/// A type's name qualified by its enclosing messages (`Outer.Inner`). Also
+/// used as its `$defs` key, which becomes `OuterInner` in Hone.
+fn scoped(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+fn full_name(package: &str, local: &str) -> String {
+    if package.is_empty() {
+        format!(".{}", local)
+    } else {
+        format!(".{}.{}", package, local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::{EnumValueDescriptorProto, FileDescriptorProto, MessageOptions};
+
+    fn field(name: &str, json_name: &str, ty: Type, label: Label) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.into()),
+            json_name: Some(json_name.into()),
+            r#type: Some(ty as i32),
+            label: Some(label as i32),
+            ..Default::default()
+        }
+    }
+
+    fn message_field(name: &str, type_name: &str, label: Label) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            type_name: Some(type_name.into()),
+            ..field(name, name, Type::Message, label)
+        }
+    }
+
+    fn descriptor_set() -> Vec<u8> {
+        let labels_entry = DescriptorProto {
+            name: Some("LabelsEntry".into()),
+            options: Some(MessageOptions {
+                map_entry: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let config = DescriptorProto {
+            name: Some("Config".into()),
+            field: vec![
+                field("listen_port", "listenPort", Type::Int32, Label::Optional),
+                field("hosts", "hosts", Type::String, Label::Repeated),
+                FieldDescriptorProto {
+                    type_name: Some(".acme.v1.Config.Mode".into()),
+                    ..field("mode", "mode", Type::Enum, Label::Optional)
+                },
+                message_field("tls", ".acme.v1.Tls", Label::Optional),
+                message_field("labels", ".acme.v1.Config.LabelsEntry", Label::Repeated),
+                message_field("timeout", ".google.protobuf.Duration", Label::Optional),
+            ],
+            nested_type: vec![labels_entry],
+            enum_type: vec![EnumDescriptorProto {
+                name: Some("Mode".into()),
+                value: vec![
+                    EnumValueDescriptorProto {
+                        name: Some("MODE_UNSPECIFIED".into()),
+                        number: Some(0),
+                        ..Default::default()
+                    },
+                    EnumValueDescriptorProto {
+                        name: Some("MODE_FAST".into()),
+                        number: Some(1),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let tls = DescriptorProto {
+            name: Some("Tls".into()),
+            field: vec![field("cert", "cert", Type::String, Label::Required)],
+            ..Default::default()
+        };
+        let unused = DescriptorProto {
+            name: Some("Unused".into()),
+            ..Default::default()
+        };
+
+        FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("acme/v1/config.proto".into()),
+                package: Some("acme.v1".into()),
+                message_type: vec![config, tls, unused],
+                ..Default::default()
+            }],
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn test_selected_message_and_dependencies() {
+        let result =
+            generate_from_descriptor_set(&descriptor_set(), &["acme.v1.Config".into()]).unwrap();
+
+        assert!(result.contains("schema Config {"), "got: {}", result);
+        assert!(result.contains("listenPort?: int"), "got: {}", result);
+        assert!(result.contains("hosts?: array # string"), "got: {}", result);
+        assert!(result.contains("mode?: ConfigMode"), "got: {}", result);
+        assert!(
+            result.contains(r#"type ConfigMode = "MODE_UNSPECIFIED" | "MODE_FAST""#),
+            "got: {}",
+            result
+        );
+        assert!(result.contains("tls?: Tls"), "got: {}", result);
+        assert!(result.contains("  cert: string"), "got: {}", result);
+        assert!(result.contains("labels?: object"), "got: {}", result);
+        assert!(result.contains("timeout?: string"), "got: {}", result);
+        assert!(!result.contains("Unused"), "got: {}", result);
+        assert!(!result.contains("LabelsEntry"), "got: {}", result);
+        assert!(
+            !result.contains("..."),
+            "proto schemas are closed: {}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_all_messages_without_selection() {
+        let result = generate_from_descriptor_set(&descriptor_set(), &[]).unwrap();
+        assert!(result.contains("schema Unused {"), "got: {}", result);
+        assert!(result.contains("schema Config {"), "got: {}", result);
+    }
+
+    #[test]
+    fn test_unknown_message_is_an_error() {
+        let err =
+            generate_from_descriptor_set(&descriptor_set(), &["acme.v1.Nope".into()]).unwrap_err();
+        assert!(err.contains("acme.v1.Nope"), "got: {}", err);
+    }
+
+    #[test]
+    fn test_same_name_in_two_packages() {
+        let duration = |package: &str, field_name: &str| FileDescriptorProto {
+            name: Some(format!("{}.proto", package)),
+            package: Some(package.into()),
+            message_type: vec![DescriptorProto {
+                name: Some("Duration".into()),
+                field: vec![field(field_name, field_name, Type::Int64, Label::Optional)],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut user = duration("acme.v1", "millis");
+        user.message_type.push(DescriptorProto {
+            name: Some("Job".into()),
+            field: vec![message_field("every", ".acme.v1.Duration", Label::Optional)],
+            ..Default::default()
+        });
+        let set = FileDescriptorSet {
+            file: vec![user, duration("google.protobuf", "seconds")],
+        }
+        .encode_to_vec();
+
+        let result = generate_from_descriptor_set(&set, &["acme.v1.Job".into()]).unwrap();
+        assert!(result.contains("every?: AcmeV1Duration"), "got: {}", result);
+        assert!(
+            result.contains("schema AcmeV1Duration {"),
+            "got: {}",
+            result
+        );
+        assert!(result.contains("millis?: int"), "got: {}", result);
+        assert!(!result.contains("seconds"), "got: {}", result);
+
+        let result = generate_from_descriptor_set(&set, &["acme.v1.Duration".into()]).unwrap();
+        assert!(result.contains("millis?: int"), "got: {}", result);
+        assert!(!result.contains("seconds"), "got: {}", result);
+        let result = generate_from_descriptor_set(&set, &[]).unwrap();
+        assert!(
+            result.contains("schema GoogleProtobufDuration {"),
+            "got: {}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_nested_and_top_level_names_that_clash() {
+        let message = |name: &str, nested: Vec<DescriptorProto>| DescriptorProto {
+            name: Some(name.into()),
+            nested_type: nested,
+            ..Default::default()
+        };
+        let file = |package: &str, messages: Vec<DescriptorProto>| FileDescriptorProto {
+            name: Some(format!("{}.proto", package)),
+            package: Some(package.into()),
+            message_type: messages,
+            ..Default::default()
+        };
+
+        // Across packages, both are qualified
+        let set = FileDescriptorSet {
+            file: vec![
+                file("a", vec![message("Config", vec![message("Tls", vec![])])]),
+                file("b", vec![message("ConfigTls", vec![])]),
+            ],
+        }
+        .encode_to_vec();
+        let result = generate_from_descriptor_set(&set, &[]).unwrap();
+        assert!(result.contains("schema AConfigTls {"), "got: {}", result);
+        assert!(result.contains("schema BConfigTls {"), "got: {}", result);
+
+        // In one package, qualifying cannot tell them apart
+        let set = FileDescriptorSet {
+            file: vec![file(
+                "a",
+                vec![
+                    message("Config", vec![message("Tls", vec![])]),
+                    message("ConfigTls", vec![]),
+                ],
+            )],
+        }
+        .encode_to_vec();
+        let err = generate_from_descriptor_set(&set, &[]).unwrap_err();
+        assert_eq!(
+            err,
+            "a.Config.Tls and a.ConfigTls would share the name 'AConfigTls'"
+        );
+    }
+
+    #[test]
+    fn test_invalid_descriptor_set() {
+        assert!(generate_from_descriptor_set(b"\xff\xff\xff", &[]).is_err());
+    }
+}
@@ -0,0 +1,734 @@
+//! Dead code across a workspace for `hone unused`
+//!
+//! Every `.hone` file under a directory is parsed and its imports, `from`
+//! bases and `include`s followed, starting from the entry points: the
+//! targets of `hone.toml`, or else every file with output that no other
+//! file depends on. A file no entry point reaches is unused as a whole. In
+//! the files that are reached, a top-level `let`, `fn` or `schema` is used
+//! when the file's body, policies or `use` statements refer to it, when
+//! another file imports it (by name, or as `alias.name` through a whole
+//! import), or when something used refers to it. Entry points themselves
+//! are not searched: their unused `let`s are already compile warnings.
+//!
+//! Nothing is evaluated, so every branch counts: a name referred to only
+//! under a `when` that is never true is still used. Local names that shadow
+//! a top-level one are counted as references to it too. Both err on the
+//! side of keeping a definition.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::compiler::workspace::discover;
+use crate::errors::{HoneError, HoneResult};
+use crate::lexer::token::SourceLocation;
+use crate::parser::ast::*;
+use crate::parser::visit::{walk_body_item, walk_expr, walk_preamble_item, walk_type_expr, Visit};
+use crate::resolver::{canonicalize, ImportResolver, ResolvedFile};
+use crate::style::{self, Style};
+
+/// What kind of definition is unused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DefKind {
+    Let,
+    Function,
+    Schema,
+}
+
+impl DefKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DefKind::Let => "let",
+            DefKind::Function => "fn",
+            DefKind::Schema => "schema",
+        }
+    }
+}
+
+/// A top-level definition nothing uses
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnusedDef {
+    pub kind: DefKind,
+    pub name: String,
+    pub location: SourceLocation,
+}
+
+/// What `hone unused` found
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Report {
+    /// The files the search started from
+    pub entries: Vec<PathBuf>,
+    /// Files no entry point reaches, sorted
+    pub files: Vec<PathBuf>,
+    /// Unused definitions in the files that are reached, by file and line
+    pub defs: Vec<UnusedDef>,
+}
+
+impl Report {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.defs.is_empty()
+    }
+}
+
+/// Find what no entry point uses among the `.hone` files under `root`.
+/// With no `entries`, every file with output that no other file imports,
+/// includes or is built `from` is one.
+pub fn find_unused(root: &Path, entries: &[PathBuf]) -> HoneResult<Report> {
+    let root = canonicalize(root).map_err(|e| {
+        HoneError::io_error(format!("failed to resolve path {}: {}", root.display(), e))
+    })?;
+    let files: Vec<PathBuf> = discover(&root)?
+        .iter()
+        .map(|f| canonicalize(f).unwrap_or_else(|_| f.clone()))
+        .collect();
+
+    let mut resolver = ImportResolver::new(&root);
+    for file in &files {
+        resolver.resolve(file)?;
+    }
+    let mut entries = entries
+        .iter()
+        .map(|entry| {
+            let path = canonicalize(entry).map_err(|e| {
+                HoneError::io_error(format!("failed to resolve path {}: {}", entry.display(), e))
+            })?;
+            resolver.resolve(&path)?;
+            Ok(path)
+        })
+        .collect::<HoneResult<Vec<_>>>()?;
+    if entries.is_empty() {
+        entries = default_entries(&resolver, &files);
+    }
+
+    let mut graph = Graph::new(&resolver);
+    let reached = graph.reach(&entries);
+    let unused_files = files
+        .iter()
+        .filter(|f| !reached.contains(*f))
+        .cloned()
+        .collect();
+
+    // Everything in an entry point is used; the rest of each reached file
+    // is used unless only its top-level definitions are
+    for file in &reached {
+        if entries.contains(file) {
+            graph.use_everything(file);
+        } else {
+            graph.use_roots(file);
+        }
+    }
+    graph.propagate();
+
+    let mut defs = Vec::new();
+    for file in files.iter().filter(|f| reached.contains(*f)) {
+        if entries.contains(file) {
+            continue;
+        }
+        for def in graph.defs(file) {
+            if !graph
+                .used
+                .contains(&(file.clone(), def.namespace(), def.name.clone()))
+            {
+                defs.push(UnusedDef {
+                    kind: def.kind,
+                    name: def.name.clone(),
+                    location: def.location.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(Report {
+        entries,
+        files: unused_files,
+        defs,
+    })
+}
+
+/// Files with output that no other file depends on
+fn default_entries(resolver: &ImportResolver, files: &[PathBuf]) -> Vec<PathBuf> {
+    let depended_on: HashSet<&PathBuf> = resolver.files().flat_map(dependencies).collect();
+    files
+        .iter()
+        .filter(|f| !depended_on.contains(f))
+        .filter(|f| {
+            resolver
+                .get(f)
+                .is_some_and(|r| !r.ast.body.is_empty() || !r.ast.documents.is_empty())
+        })
+        .cloned()
+        .collect()
+}
+
+fn dependencies(file: &ResolvedFile) -> impl Iterator<Item = &PathBuf> {
+    file.from_path
+        .iter()
+        .chain(&file.import_paths)
+        .chain(&file.include_paths)
+}
+
+/// Lets and functions share a namespace; schemas have their own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Namespace {
+    Value,
+    Type,
+}
+
+/// A top-level definition, with the names it refers to
+struct Def<'a> {
+    kind: DefKind,
+    name: String,
+    location: SourceLocation,
+    refs: &'a Refs,
+}
+
+impl Def<'_> {
+    fn namespace(&self) -> Namespace {
+        match self.kind {
+            DefKind::Let | DefKind::Function => Namespace::Value,
+            DefKind::Schema => Namespace::Type,
+        }
+    }
+}
+
+/// How a file names another: what a local name or alias stands for
+#[derive(Default)]
+struct Bindings {
+    /// Local name -> (file, name there), from `import { a as b }`
+    named: HashMap<String, Vec<(PathBuf, String)>>,
+    /// Alias -> file, from `import "path" [as alias]`
+    whole: HashMap<String, PathBuf>,
+    /// Files whose schemas are in scope by their bare names
+    schema_scope: Vec<PathBuf>,
+}
+
+/// One file's definitions and the references outside them
+struct FileInfo {
+    /// (kind, name, location, index into `refs`)
+    defs: Vec<(DefKind, String, SourceLocation, usize)>,
+    refs: Vec<Refs>,
+    /// References from everything but the top-level definitions
+    roots: Refs,
+    bindings: Bindings,
+}
+
+struct Graph<'a> {
+    resolver: &'a ImportResolver,
+    info: HashMap<PathBuf, FileInfo>,
+    used: HashSet<(PathBuf, Namespace, String)>,
+    /// References still to resolve, each in the file they were made in
+    pending: Vec<(PathBuf, Refs)>,
+}
+
+impl<'a> Graph<'a> {
+    fn new(resolver: &'a ImportResolver) -> Self {
+        let info = resolver
+            .files()
+            .map(|file| (file.path.clone(), file_info(resolver, file)))
+            .collect();
+        Self {
+            resolver,
+            info,
+            used: HashSet::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Files reachable from `entries`
+    fn reach(&self, entries: &[PathBuf]) -> BTreeSet<PathBuf> {
+        let mut reached = BTreeSet::new();
+        let mut stack: Vec<PathBuf> = entries.to_vec();
+        while let Some(path) = stack.pop() {
+            if !reached.insert(path.clone()) {
+                continue;
+            }
+            if let Some(file) = self.resolver.get(&path) {
+                stack.extend(dependencies(file).cloned());
+            }
+        }
+        reached
+    }
+
+    fn defs(&self, file: &Path) -> Vec<Def<'_>> {
+        let Some(info) = self.info.get(file) else {
+            return Vec::new();
+        };
+        info.defs
+            .iter()
+            .map(|(kind, name, location, refs)| Def {
+                kind: *kind,
+                name: name.clone(),
+                location: location.clone(),
+                refs: &info.refs[*refs],
+            })
+            .collect()
+    }
+
+    fn use_everything(&mut self, file: &Path) {
+        self.use_roots(file);
+        let defs: Vec<(Namespace, String)> = self
+            .defs(file)
+            .iter()
+            .map(|def| (def.namespace(), def.name.clone()))
+            .collect();
+        for (namespace, name) in defs {
+            self.mark(file, namespace, &name);
+        }
+    }
+
+    fn use_roots(&mut self, file: &Path) {
+        let Some(info) = self.info.get(file) else {
+            return;
+        };
+        self.pending.push((file.to_path_buf(), info.roots.clone()));
+        // A fragment's names are looked up where it is included
+        if let Some(resolved) = self.resolver.get(file) {
+            for fragment in &resolved.include_paths {
+                if let Some(included) = self.info.get(fragment) {
+                    self.pending
+                        .push((file.to_path_buf(), included.roots.clone()));
+                }
+            }
+        }
+    }
+
+    /// Mark a definition used, queueing what it refers to the first time
+    fn mark(&mut self, file: &Path, namespace: Namespace, name: &str) {
+        let key = (file.to_path_buf(), namespace, name.to_string());
+        if !self.used.insert(key) {
+            return;
+        }
+        let refs: Vec<Refs> = self
+            .defs(file)
+            .iter()
+            .filter(|def| def.namespace() == namespace && def.name == name)
+            .map(|def| def.refs.clone())
+            .collect();
+        for refs in refs {
+            self.pending.push((file.to_path_buf(), refs));
+        }
+    }
+
+    /// Resolve pending references until none are left
+    fn propagate(&mut self) {
+        while let Some((file, refs)) = self.pending.pop() {
+            let mut targets = Vec::new();
+            if let Some(info) = self.info.get(&file) {
+                let bindings = &info.bindings;
+                for name in &refs.names {
+                    targets.push((file.clone(), Namespace::Value, name.clone()));
+                    for (target, original) in bindings.named.get(name).into_iter().flatten() {
+                        targets.push((target.clone(), Namespace::Value, original.clone()));
+                    }
+                }
+                // A whole import used as a value may be read in any way
+                for alias in &refs.whole {
+                    if let Some(target) = bindings.whole.get(alias) {
+                        for def in self.defs(target) {
+                            if def.namespace() == Namespace::Value {
+                                targets.push((target.clone(), Namespace::Value, def.name));
+                            }
+                        }
+                    }
+                }
+                for (alias, member) in &refs.members {
+                    if let Some(target) = bindings.whole.get(alias) {
+                        targets.push((target.clone(), Namespace::Value, member.clone()));
+                    }
+                }
+                for name in &refs.types {
+                    match name.split_once('.') {
+                        Some((alias, schema)) => {
+                            if let Some(target) = bindings.whole.get(alias) {
+                                targets.push((target.clone(), Namespace::Type, schema.to_string()));
+                            }
+                        }
+                        None => {
+                            targets.push((file.clone(), Namespace::Type, name.clone()));
+                            for target in &bindings.schema_scope {
+                                targets.push((target.clone(), Namespace::Type, name.clone()));
+                            }
+                            for (target, original) in bindings.named.get(name).into_iter().flatten()
+                            {
+                                targets.push((target.clone(), Namespace::Type, original.clone()));
+                            }
+                        }
+                    }
+                }
+            }
+            for (target, namespace, name) in targets {
+                self.mark(&target, namespace, &name);
+            }
+        }
+    }
+}
+
+/// Definitions, references and bindings of one file
+fn file_info(resolver: &ImportResolver, file: &ResolvedFile) -> FileInfo {
+    let mut info = FileInfo {
+        defs: Vec::new(),
+        refs: Vec::new(),
+        roots: Refs::default(),
+        bindings: bindings(resolver, file),
+    };
+    for item in &file.ast.preamble {
+        let (kind, names, location) = match item {
+            PreambleItem::Let(binding) => (DefKind::Let, binding.names(), &binding.location),
+            PreambleItem::FnDef(def) => (DefKind::Function, vec![def.name.as_str()], &def.location),
+            PreambleItem::Schema(def) => (DefKind::Schema, vec![def.name.as_str()], &def.location),
+            _ => {
+                info.roots.visit_preamble_item(item);
+                continue;
+            }
+        };
+        let mut refs = Refs::default();
+        refs.visit_preamble_item(item);
+        for name in names {
+            info.defs
+                .push((kind, name.to_string(), location.clone(), info.refs.len()));
+        }
+        info.refs.push(refs);
+    }
+    for item in &file.ast.body {
+        info.roots.visit_body_item(item);
+    }
+    for document in &file.ast.documents {
+        info.roots.visit_document(document);
+    }
+    info.defs.sort_by_key(|(_, _, location, _)| location.offset);
+    info
+}
+
+fn bindings(resolver: &ImportResolver, file: &ResolvedFile) -> Bindings {
+    let mut bindings = Bindings::default();
+    let preambles = std::iter::once(&file.ast.preamble)
+        .chain(file.ast.documents.iter().map(|document| &document.preamble));
+    for item in preambles.flatten() {
+        let PreambleItem::Import(import) = item else {
+            continue;
+        };
+        let Ok(target) = resolver.import_path(import, &file.path) else {
+            continue;
+        };
+        match &import.kind {
+            ImportKind::Whole { alias, .. } => {
+                let alias = alias.clone().unwrap_or_else(|| {
+                    target
+                        .file_stem()
+                        .and_then(|s| s.to_str())
+                        .unwrap_or("imported")
+                        .to_string()
+                });
+                bindings.whole.insert(alias, target.clone());
+            }
+            ImportKind::Named { names, .. } => {
+                for name in names {
+                    let local = name.alias.as_ref().unwrap_or(&name.name);
+                    bindings
+                        .named
+                        .entry(local.clone())
+                        .or_default()
+                        .push((target.clone(), name.name.clone()));
+                }
+            }
+        }
+        bindings.schema_scope.push(target);
+    }
+    // Schemas a base uses partially are checked in the files built on it
+    let mut base = file.from_path.clone();
+    while let Some(path) = base {
+        if bindings.schema_scope.contains(&path) {
+            break;
+        }
+        bindings.schema_scope.push(path.clone());
+        base = resolver.get(&path).and_then(|r| {
+            bindings.schema_scope.extend(r.import_paths.iter().cloned());
+            r.from_path.clone()
+        });
+    }
+    bindings
+}
+
+/// Names an item refers to
+#[derive(Debug, Clone, Default)]
+struct Refs {
+    /// Every identifier read, including the first segment of a path
+    names: BTreeSet<String>,
+    /// Identifiers read as a whole rather than as `name.member`
+    whole: BTreeSet<String>,
+    /// `name.member` paths, by their first two segments
+    members: BTreeSet<(String, String)>,
+    /// Schema and type names, dotted when namespaced by a whole import
+    types: BTreeSet<String>,
+}
+
+impl Visit for Refs {
+    fn visit_preamble_item(&mut self, item: &PreambleItem) {
+        match item {
+            PreambleItem::Schema(def) => {
+                self.types.extend(def.extends.clone());
+                for field in &def.fields {
+                    self.types.insert(field.constraint.name.clone());
+                }
+            }
+            PreambleItem::Use(use_stmt) => {
+                self.types.insert(use_stmt.schema_name.clone());
+            }
+            PreambleItem::Expect(expect) => {
+                self.types.insert(expect.type_name.clone());
+            }
+            _ => {}
+        }
+        walk_preamble_item(self, item);
+    }
+
+    fn visit_body_item(&mut self, item: &BodyItem) {
+        if let BodyItem::Use(use_stmt) = item {
+            self.types.insert(use_stmt.schema_name.clone());
+        }
+        walk_body_item(self, item);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Ident(name, _) => {
+                self.names.insert(name.clone());
+                self.whole.insert(name.clone());
+            }
+            Expr::Path(path) => {
+                if let Some(PathPart::Ident(head)) = path.parts.first() {
+                    self.names.insert(head.clone());
+                    match path.parts.get(1) {
+                        Some(PathPart::Ident(member)) => {
+                            self.members.insert((head.clone(), member.clone()));
+                        }
+                        _ => {
+                            self.whole.insert(head.clone());
+                        }
+                    }
+                }
+            }
+            Expr::Annotated(annotated) => {
+                self.types.insert(annotated.constraint.name.clone());
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+
+    fn visit_type_expr(&mut self, ty: &TypeExpr) {
+        if let TypeExpr::Named { name, .. } = ty {
+            self.types.insert(name.clone());
+        }
+        walk_type_expr(self, ty);
+    }
+}
+
+/// Format a report as text; file paths are shown relative to `base`
+pub fn format_unused_text(report: &Report, base: &Path) -> String {
+    format_unused_text_with(report, base, &Style::plain())
+}
+
+/// Format a report as text, colored by `style`
+pub fn format_unused_text_with(report: &Report, base: &Path, style: &Style) -> String {
+    if report.is_empty() {
+        return format!(
+            "nothing unused (from {})\n",
+            entry_count(report.entries.len())
+        );
+    }
+    let mut output = String::new();
+    for file in &report.files {
+        output.push_str(&format!(
+            "{}  {}\n",
+            relative(file, base),
+            style.paint(style::DIM, "file")
+        ));
+    }
+    let sites: Vec<String> = report
+        .defs
+        .iter()
+        .map(|def| site(&def.location, base))
+        .collect();
+    let width = sites.iter().map(|s| s.chars().count()).max().unwrap_or(0);
+    for (def, site) in report.defs.iter().zip(&sites) {
+        output.push_str(&format!(
+            "{:<width$}  {:<6}  {}\n",
+            site,
+            def.kind.as_str(),
+            style.paint(style::CYAN, &def.name),
+            width = width
+        ));
+    }
+
+    let mut counts = Vec::new();
+    for (kind, one, many) in [
+        (DefKind::Let, "let", "lets"),
+        (DefKind::Function, "function", "functions"),
+        (DefKind::Schema, "schema", "schemas"),
+    ] {
+        let n = report.defs.iter().filter(|d| d.kind == kind).count();
+        if n > 0 {
+            counts.push(format!("{} {}", n, if n == 1 { one } else { many }));
+        }
+    }
+    if !report.files.is_empty() {
+        let n = report.files.len();
+        counts.push(format!("{} {}", n, if n == 1 { "file" } else { "files" }));
+    }
+    output.push_str(&format!(
+        "\nunused: {} (from {})\n",
+        counts.join(", "),
+        entry_count(report.entries.len())
+    ));
+    output
+}
+
+/// Format a report as JSON
+pub fn format_unused_json(report: &Report, base: &Path) -> String {
+    let defs: Vec<serde_json::Value> = report
+        .defs
+        .iter()
+        .map(|def| {
+            serde_json::json!({
+                "file": def.location.file.as_deref().map(|f| relative(f, base)),
+                "line": def.location.line,
+                "column": def.location.column,
+                "kind": def.kind.as_str(),
+                "name": def.name,
+            })
+        })
+        .collect();
+    let json = serde_json::json!({
+        "entries": report.entries.iter().map(|f| relative(f, base)).collect::<Vec<_>>(),
+        "files": report.files.iter().map(|f| relative(f, base)).collect::<Vec<_>>(),
+        "definitions": defs,
+    });
+    serde_json::to_string_pretty(&json).unwrap_or_default() + "\n"
+}
+
+fn entry_count(n: usize) -> String {
+    format!("{} entry point{}", n, if n == 1 { "" } else { "s" })
+}
+
+/// `file:line:column` of a definition
+fn site(location: &SourceLocation, base: &Path) -> String {
+    let file = location
+        .file
+        .as_deref()
+        .map(|f| relative(f, base))
+        .unwrap_or_else(|| "<stdin>".to_string());
+    format!("{}:{}:{}", file, location.line, location.column)
+}
+
+fn relative(file: &Path, base: &Path) -> String {
+    file.strip_prefix(base)
+        .unwrap_or(file)
+        .display()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, source) in files {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    fn unused_names(report: &Report) -> Vec<String> {
+        report
+            .defs
+            .iter()
+            .map(|d| format!("{} {}", d.kind.as_str(), d.name))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_unused_across_files() {
+        let dir = workspace(&[
+            (
+                "lib/net.hone",
+                r#"
+let base_port = 8000
+let port = base_port + 80
+let legacy_port = 9000
+let helper_only = 1
+fn double(x) { x * helper_only * 2 }
+fn unused_fn(x) { x }
+
+schema Service {
+  name: string
+  limits: Limits
+}
+schema Limits {
+  cpu: int
+}
+schema Old {
+  x: int
+}
+"#,
+            ),
+            ("lib/dead.hone", "let nothing = 1\n"),
+            (
+                "lib/meta.hone",
+                "let team = \"infra\"\nlet owner = \"ops\"\n",
+            ),
+            (
+                "app.hone",
+                r#"
+import { port, double } from "./lib/net.hone"
+import "./lib/meta.hone" as meta
+import { schema Service } from "./lib/net.hone"
+use Service
+
+let unused_here = 1
+name: "api"
+limits { cpu: double(port) }
+team: meta.team
+"#,
+            ),
+        ]);
+        let report = find_unused(dir.path(), &[]).unwrap();
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.files.len(), 1);
+        assert!(report.files[0].ends_with("lib/dead.hone"));
+        assert_eq!(
+            unused_names(&report),
+            ["let owner", "let legacy_port", "fn unused_fn", "schema Old"]
+        );
+        let text = format_unused_text(&report, &dir.path().canonicalize().unwrap());
+        assert!(text.contains("lib/dead.hone  file\n"), "{}", text);
+        assert!(
+            text.contains("lib/net.hone:4:1   let     legacy_port\n"),
+            "{}",
+            text
+        );
+        assert!(
+            text.ends_with("unused: 2 lets, 1 function, 1 schema, 1 file (from 1 entry point)\n"),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn test_whole_import_used_as_value_keeps_every_let() {
+        let dir = workspace(&[
+            ("lib.hone", "let a = 1\nlet b = 2\n"),
+            ("app.hone", "import \"./lib.hone\" as lib\nall: lib\n"),
+        ]);
+        let report = find_unused(dir.path(), &[]).unwrap();
+        assert!(report.is_empty(), "{:?}", report);
+
+        // An entry point given explicitly leaves the other file unreached
+        let entry = dir.path().join("lib.hone");
+        let report = find_unused(dir.path(), &[entry]).unwrap();
+        assert_eq!(report.files.len(), 1);
+        assert!(report.files[0].ends_with("app.hone"));
+    }
+}
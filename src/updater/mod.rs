@@ -0,0 +1,549 @@
+//! `hone self-update`: replace the running binary with the newest release
+//!
+//! Every release publishes one archive per target, a `SHA256SUMS` file with
+//! their digests, and `SHA256SUMS.sig`, an SSH signature over that file made
+//! with the release key. The public half is baked in at build time from
+//! `HONE_RELEASE_SIGNING_KEY`, so a binary built from source without it
+//! refuses to install anything it cannot verify. Downloads use `curl`,
+//! unpacking uses `tar` and the signature check uses `ssh-keygen -Y verify`.
+//!
+//! The whole module sits behind the `self-update` feature, which is on by
+//! default. Build with `--no-default-features --features cli` for a binary
+//! that has no way to reach the network to update itself.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde_json::Value as Json;
+use sha2::{Digest, Sha256};
+
+use crate::errors::{HoneError, HoneResult};
+
+/// The GitHub repository releases are published from
+pub const REPOSITORY: &str = "honelang/hone";
+
+/// Public key that signs `SHA256SUMS`, in `ssh-ed25519 AAAA...` form
+const RELEASE_KEY: Option<&str> = option_env!("HONE_RELEASE_SIGNING_KEY");
+
+/// `ssh-keygen -Y sign -n` namespace of release signatures
+const SIGNATURE_NAMESPACE: &str = "hone-release";
+
+/// Signer identity the release key is registered under
+const SIGNER: &str = "releases@honelang";
+
+const CHECKSUMS: &str = "SHA256SUMS";
+const CHECKSUMS_SIGNATURE: &str = "SHA256SUMS.sig";
+
+/// Which releases `self-update` considers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Stable,
+    /// Stable releases and release candidates
+    Prerelease,
+}
+
+impl Channel {
+    pub fn parse(name: &str) -> Option<Channel> {
+        match name {
+            "stable" => Some(Channel::Stable),
+            "prerelease" => Some(Channel::Prerelease),
+            _ => None,
+        }
+    }
+}
+
+/// A release version: `0.4.0` or `0.4.0-rc.1`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    pub pre: Option<String>,
+}
+
+impl Version {
+    /// Parse a version or tag name; a leading `v` is ignored
+    pub fn parse(s: &str) -> Option<Version> {
+        let s = s.strip_prefix('v').unwrap_or(s);
+        let (core, pre) = match s.split_once('-') {
+            Some((core, pre)) if !pre.is_empty() => (core, Some(pre.to_string())),
+            Some(_) => return None,
+            None => (s, None),
+        };
+        let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+        let version = Version {
+            major: parts.next()??,
+            minor: parts.next()??,
+            patch: parts.next()??,
+            pre,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(version)
+    }
+
+    /// The version of the running binary
+    pub fn current() -> Version {
+        Version::parse(env!("CARGO_PKG_VERSION")).expect("crate version is a valid version")
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                // A release candidate comes before its release
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(a), Some(b)) => compare_pre(a, b),
+            })
+    }
+}
+
+/// Order prerelease tags as semver does: dot-separated identifiers in turn,
+/// numbers by value and before words, so `rc.9` comes before `rc.10`
+fn compare_pre(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split('.');
+    let mut b_parts = b.split('.');
+    loop {
+        let ordering = match (a_parts.next(), b_parts.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => a.cmp(b),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre) = &self.pre {
+            write!(f, "-{}", pre)?;
+        }
+        Ok(())
+    }
+}
+
+/// A published release and its downloadable files
+#[derive(Debug, Clone, PartialEq)]
+pub struct Release {
+    pub tag: String,
+    pub version: Version,
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Asset {
+    pub name: String,
+    pub url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> HoneResult<&Asset> {
+        self.assets
+            .iter()
+            .find(|a| a.name == name)
+            .ok_or_else(|| HoneError::io_error(format!("release {} has no {}", self.tag, name)))
+    }
+}
+
+/// The newest release on `channel`, from the GitHub releases API response
+/// (newest first, but ordered by date rather than version)
+pub fn select_release(releases: &Json, channel: Channel) -> Option<Release> {
+    releases
+        .as_array()?
+        .iter()
+        .filter(|r| r["draft"].as_bool() != Some(true))
+        .filter(|r| channel == Channel::Prerelease || r["prerelease"].as_bool() != Some(true))
+        .filter_map(|r| {
+            let tag = r["tag_name"].as_str()?;
+            let version = Version::parse(tag)?;
+            let assets = r["assets"]
+                .as_array()?
+                .iter()
+                .filter_map(|a| {
+                    Some(Asset {
+                        name: a["name"].as_str()?.to_string(),
+                        url: a["browser_download_url"].as_str()?.to_string(),
+                    })
+                })
+                .collect();
+            Some(Release {
+                tag: tag.to_string(),
+                version,
+                assets,
+            })
+        })
+        .max_by(|a, b| a.version.cmp(&b.version))
+}
+
+/// The release target this binary was built for, if releases are
+/// published for it
+pub fn current_target() -> Option<&'static str> {
+    if cfg!(all(
+        target_arch = "x86_64",
+        target_os = "linux",
+        target_env = "musl"
+    )) {
+        Some("x86_64-unknown-linux-musl")
+    } else if cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+        Some("x86_64-unknown-linux-gnu")
+    } else if cfg!(all(
+        target_arch = "aarch64",
+        target_os = "linux",
+        target_env = "musl"
+    )) {
+        Some("aarch64-unknown-linux-musl")
+    } else if cfg!(all(target_arch = "aarch64", target_os = "linux")) {
+        Some("aarch64-unknown-linux-gnu")
+    } else if cfg!(all(target_arch = "x86_64", target_os = "macos")) {
+        Some("x86_64-apple-darwin")
+    } else if cfg!(all(target_arch = "aarch64", target_os = "macos")) {
+        Some("aarch64-apple-darwin")
+    } else if cfg!(all(target_arch = "x86_64", target_os = "windows")) {
+        Some("x86_64-pc-windows-msvc")
+    } else {
+        None
+    }
+}
+
+/// File name of the release archive for `target`
+pub fn archive_name(target: &str) -> String {
+    let extension = if target.contains("windows") {
+        "zip"
+    } else {
+        "tar.gz"
+    };
+    format!("hone-{}.{}", target, extension)
+}
+
+/// The digest `SHA256SUMS` lists for `file`, in `sha256sum` output format
+pub fn expected_digest(sums: &str, file: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let (digest, name) = line.split_once(char::is_whitespace)?;
+        let name = name.trim_start();
+        let name = name.strip_prefix('*').unwrap_or(name);
+        (name == file).then(|| digest.to_lowercase())
+    })
+}
+
+/// The newest release on `channel`, fetched from GitHub
+pub fn latest_release(channel: Channel) -> HoneResult<Option<Release>> {
+    let url = format!(
+        "https://api.github.com/repos/{}/releases?per_page=30",
+        REPOSITORY
+    );
+    let output = Command::new("curl")
+        .args([
+            "-fsSL",
+            "--max-time",
+            "30",
+            "-H",
+            "Accept: application/vnd.github+json",
+        ])
+        .arg(&url)
+        .output()
+        .map_err(|e| HoneError::io_error(format!("failed to run curl: {}", e)))?;
+    if !output.status.success() {
+        return Err(HoneError::io_error(format!(
+            "failed to fetch {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let releases: Json = serde_json::from_slice(&output.stdout)
+        .map_err(|e| HoneError::io_error(format!("unexpected response from {}: {}", url, e)))?;
+    Ok(select_release(&releases, channel))
+}
+
+/// Download `release` for this platform, verify it, and replace the running
+/// binary with it. Returns the path of the replaced binary.
+pub fn install(release: &Release) -> HoneResult<PathBuf> {
+    let key = RELEASE_KEY.ok_or_else(|| {
+        HoneError::io_error(
+            "this hone was built without a release signing key, so it cannot verify a \
+             download; install releases with scripts/install.sh instead"
+                .to_string(),
+        )
+    })?;
+    let target = current_target().ok_or_else(|| {
+        HoneError::io_error("no releases are published for this platform".to_string())
+    })?;
+    let archive = archive_name(target);
+
+    // Private and randomly named, so no other user can swap a verified file
+    let work = tempfile::Builder::new()
+        .prefix("hone_update_")
+        .tempdir()
+        .map_err(|e| {
+            HoneError::io_error(format!("failed to create a download directory: {}", e))
+        })?;
+    let work_dir = work.path();
+    for name in [archive.as_str(), CHECKSUMS, CHECKSUMS_SIGNATURE] {
+        download(&release.asset(name)?.url, &work_dir.join(name))?;
+    }
+    verify_signature(work_dir, key)?;
+
+    let sums = read_to_string(&work_dir.join(CHECKSUMS))?;
+    let expected = expected_digest(&sums, &archive)
+        .ok_or_else(|| HoneError::io_error(format!("{} does not list {}", CHECKSUMS, archive)))?;
+    let bytes = std::fs::read(work_dir.join(&archive)).map_err(|e| {
+        HoneError::io_error(format!("failed to read downloaded {}: {}", archive, e))
+    })?;
+    let actual = format!("{:x}", Sha256::digest(&bytes));
+    if actual != expected {
+        return Err(HoneError::io_error(format!(
+            "{} does not match its checksum (expected {}, got {})",
+            archive, expected, actual
+        )));
+    }
+
+    let unpacked = work_dir.join("unpacked");
+    unpack(&work_dir.join(&archive), &unpacked)?;
+    let binary = unpacked.join(if cfg!(windows) { "hone.exe" } else { "hone" });
+    if !binary.is_file() {
+        return Err(HoneError::io_error(format!(
+            "{} does not contain a hone binary",
+            archive
+        )));
+    }
+
+    let exe = std::env::current_exe()
+        .and_then(std::fs::canonicalize)
+        .map_err(|e| HoneError::io_error(format!("cannot locate the running binary: {}", e)))?;
+    replace_binary(&exe, &binary)?;
+    Ok(exe)
+}
+
+/// Atomically put `new` in place of the binary at `exe`. The copy is staged
+/// next to `exe`, so the final rename never crosses filesystems and a
+/// failed update leaves the old binary untouched.
+pub fn replace_binary(exe: &Path, new: &Path) -> HoneResult<()> {
+    let dir = exe.parent().unwrap_or(Path::new("."));
+    let file_name = exe
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "hone".to_string());
+    let staged = dir.join(format!(".{}.new", file_name));
+    let unwritable = |e: std::io::Error| {
+        HoneError::io_error(format!(
+            "cannot write to {}: {} (rerun with permission to modify it)",
+            dir.display(),
+            e
+        ))
+    };
+
+    std::fs::copy(new, &staged).map_err(unwritable)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let permissions = std::fs::Permissions::from_mode(0o755);
+        if let Err(e) = std::fs::set_permissions(&staged, permissions) {
+            let _ = std::fs::remove_file(&staged);
+            return Err(unwritable(e));
+        }
+    }
+    // A running executable cannot be overwritten on Windows, but it can be
+    // renamed out of the way
+    #[cfg(windows)]
+    let old = {
+        let old = dir.join(format!("{}.old", file_name));
+        let _ = std::fs::remove_file(&old);
+        if let Err(e) = std::fs::rename(exe, &old) {
+            let _ = std::fs::remove_file(&staged);
+            return Err(unwritable(e));
+        }
+        old
+    };
+    std::fs::rename(&staged, exe).map_err(|e| {
+        let _ = std::fs::remove_file(&staged);
+        #[cfg(windows)]
+        let _ = std::fs::rename(&old, exe);
+        unwritable(e)
+    })
+}
+
+/// Check `SHA256SUMS.sig` in `dir` against the release key
+fn verify_signature(dir: &Path, key: &str) -> HoneResult<()> {
+    let allowed_signers = dir.join("allowed_signers");
+    std::fs::write(&allowed_signers, format!("{} {}\n", SIGNER, key))
+        .map_err(|e| HoneError::io_error(format!("failed to write allowed signers: {}", e)))?;
+    let sums = std::fs::File::open(dir.join(CHECKSUMS))
+        .map_err(|e| HoneError::io_error(format!("failed to read {}: {}", CHECKSUMS, e)))?;
+    let output = Command::new("ssh-keygen")
+        .args(["-Y", "verify", "-f"])
+        .arg(&allowed_signers)
+        .args(["-I", SIGNER, "-n", SIGNATURE_NAMESPACE, "-s"])
+        .arg(dir.join(CHECKSUMS_SIGNATURE))
+        .stdin(sums)
+        .stdout(Stdio::null())
+        .output()
+        .map_err(|e| HoneError::io_error(format!("failed to run ssh-keygen: {}", e)))?;
+    if !output.status.success() {
+        return Err(HoneError::io_error(format!(
+            "{} is not signed by the hone release key: {}",
+            CHECKSUMS,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Fetch `url` into `path` with curl
+fn download(url: &str, path: &Path) -> HoneResult<()> {
+    let output = Command::new("curl")
+        .args(["-fsSL", "--max-time", "300", "-o"])
+        .arg(path)
+        .arg(url)
+        .output()
+        .map_err(|e| HoneError::io_error(format!("failed to run curl: {}", e)))?;
+    if !output.status.success() {
+        return Err(HoneError::io_error(format!(
+            "failed to download {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Extract `archive` into `dest`; `tar` reads both the `.tar.gz` and, on
+/// Windows, the `.zip` archives
+fn unpack(archive: &Path, dest: &Path) -> HoneResult<()> {
+    std::fs::create_dir_all(dest)
+        .map_err(|e| HoneError::io_error(format!("failed to create {}: {}", dest.display(), e)))?;
+    let output = Command::new("tar")
+        .arg("-xf")
+        .arg(archive)
+        .arg("-C")
+        .arg(dest)
+        .output()
+        .map_err(|e| HoneError::io_error(format!("failed to run tar: {}", e)))?;
+    if !output.status.success() {
+        return Err(HoneError::io_error(format!(
+            "failed to unpack {}: {}",
+            archive.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+fn read_to_string(path: &Path) -> HoneResult<String> {
+    std::fs::read_to_string(path)
+        .map_err(|e| HoneError::io_error(format!("failed to read {}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(s: &str) -> Version {
+        Version::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_version_ordering() {
+        assert!(version("v0.4.0") > version("0.3.9"));
+        assert!(version("0.10.0") > version("0.9.1"));
+        assert!(version("0.4.0") > version("0.4.0-rc.2"));
+        assert!(version("0.4.0-rc.2") > version("0.4.0-rc.1"));
+        assert!(version("1.0.0-rc.10") > version("1.0.0-rc.9"));
+        assert!(version("1.0.0-rc.1") > version("1.0.0-rc"));
+        assert!(version("1.0.0-beta") > version("1.0.0-2"));
+        assert!(version("1.0.0-beta.2") < version("1.0.0-rc.1"));
+        assert_eq!(version("v1.2.3-beta").to_string(), "1.2.3-beta");
+        assert_eq!(Version::parse("1.2"), None);
+        assert_eq!(Version::parse("1.2.3.4"), None);
+        assert_eq!(Version::parse("1.2.3-"), None);
+        assert!(Version::parse(env!("CARGO_PKG_VERSION")).is_some());
+    }
+
+    #[test]
+    fn test_select_release_by_channel() {
+        let releases = serde_json::json!([
+            { "tag_name": "v0.5.0-rc.1", "prerelease": true, "draft": false, "assets": [] },
+            { "tag_name": "v0.6.0", "prerelease": false, "draft": true, "assets": [] },
+            { "tag_name": "v0.3.2", "prerelease": false, "draft": false, "assets": [] },
+            {
+                "tag_name": "v0.4.0",
+                "prerelease": false,
+                "draft": false,
+                "assets": [{
+                    "name": "SHA256SUMS",
+                    "browser_download_url": "https://example.com/SHA256SUMS"
+                }]
+            },
+        ]);
+        let stable = select_release(&releases, Channel::Stable).unwrap();
+        assert_eq!(stable.tag, "v0.4.0");
+        assert_eq!(stable.assets[0].url, "https://example.com/SHA256SUMS");
+        assert!(stable
+            .asset("hone-x86_64-unknown-linux-gnu.tar.gz")
+            .is_err());
+
+        let pre = select_release(&releases, Channel::Prerelease).unwrap();
+        assert_eq!(pre.tag, "v0.5.0-rc.1");
+
+        assert_eq!(
+            select_release(&serde_json::json!([]), Channel::Stable),
+            None
+        );
+    }
+
+    #[test]
+    fn test_expected_digest() {
+        let sums =
+            "ab12  hone-x86_64-unknown-linux-musl.tar.gz\nCD34 *hone-x86_64-pc-windows-msvc.zip\n";
+        assert_eq!(
+            expected_digest(sums, "hone-x86_64-unknown-linux-musl.tar.gz").as_deref(),
+            Some("ab12")
+        );
+        assert_eq!(
+            expected_digest(sums, &archive_name("x86_64-pc-windows-msvc")).as_deref(),
+            Some("cd34")
+        );
+        assert_eq!(
+            expected_digest(sums, "hone-aarch64-apple-darwin.tar.gz"),
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_binary() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("hone");
+        let new = dir.path().join("downloaded");
+        std::fs::write(&exe, "old").unwrap();
+        std::fs::write(&new, "new").unwrap();
+
+        replace_binary(&exe, &new).unwrap();
+        assert_eq!(std::fs::read_to_string(&exe).unwrap(), "new");
+        let mode = std::fs::metadata(&exe).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+        assert!(!dir.path().join(".hone.new").exists());
+    }
+}
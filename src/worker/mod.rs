@@ -0,0 +1,341 @@
+//! Persistent worker protocol for Bazel and Buck2
+//!
+//! Started with `--persistent_worker`, one `hone` process serves many build
+//! actions: the build tool writes a `WorkRequest` per action to stdin and
+//! reads a `WorkResponse` for each from stdout. Messages are length-delimited
+//! protobuf, or one JSON object per line for rules that set
+//! `requires-worker-protocol: json`; the first request tells which.
+//!
+//! Arguments may name `@flagfile`s, read one argument per line. Under
+//! multiplex sandboxing each request carries a `sandbox_dir`, and its
+//! relative paths are resolved there.
+
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{HoneError, HoneResult};
+
+/// A file the action may read, with the digest the build tool computed
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Input {
+    #[prost(string, tag = "1")]
+    pub path: String,
+    /// Only sent in the protobuf protocol
+    #[prost(bytes = "vec", tag = "2")]
+    #[serde(skip)]
+    pub digest: Vec<u8>,
+}
+
+/// One action to run
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WorkRequest {
+    #[prost(string, repeated, tag = "1")]
+    pub arguments: Vec<String>,
+    #[prost(message, repeated, tag = "2")]
+    pub inputs: Vec<Input>,
+    /// 0 for singleplex workers
+    #[prost(int32, tag = "3")]
+    pub request_id: i32,
+    #[prost(bool, tag = "4")]
+    pub cancel: bool,
+    #[prost(int32, tag = "5")]
+    pub verbosity: i32,
+    /// Directory the action's inputs are laid out in, relative to the
+    /// worker's working directory; empty without sandboxing
+    #[prost(string, tag = "6")]
+    pub sandbox_dir: String,
+}
+
+/// Result of one action
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WorkResponse {
+    #[prost(int32, tag = "1")]
+    pub exit_code: i32,
+    /// Diagnostics shown to the user
+    #[prost(string, tag = "2")]
+    pub output: String,
+    #[prost(int32, tag = "3")]
+    pub request_id: i32,
+    #[prost(bool, tag = "4")]
+    pub was_cancelled: bool,
+}
+
+impl WorkRequest {
+    /// Directory the request's relative paths are resolved in: its sandbox
+    /// below `cwd`, or `cwd` itself
+    pub fn working_dir(&self, cwd: &Path) -> PathBuf {
+        if self.sandbox_dir.is_empty() {
+            cwd.to_path_buf()
+        } else {
+            cwd.join(&self.sandbox_dir)
+        }
+    }
+}
+
+impl WorkResponse {
+    pub fn new(exit_code: i32, output: impl Into<String>) -> Self {
+        Self {
+            exit_code,
+            output: output.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Wire format of a worker's messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Proto,
+    Json,
+}
+
+impl Protocol {
+    /// Tell the protocol from the first bytes of the first request. A JSON
+    /// request opens with `{"` or is `{}`; a protobuf one opens with a length
+    /// and a field key, and no `WorkRequest` field key is `"` or `}`.
+    pub fn detect(start: &[u8]) -> Self {
+        match start {
+            [b'{'] | [b'{', b'"' | b'}', ..] => Protocol::Json,
+            _ => Protocol::Proto,
+        }
+    }
+}
+
+/// Largest protobuf request accepted; a length past it means a corrupt or
+/// foreign stream rather than a real request
+pub const MAX_REQUEST_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Read the next request, or `None` once input ends
+pub fn read_request<R: BufRead>(
+    reader: &mut R,
+    protocol: Protocol,
+) -> HoneResult<Option<WorkRequest>> {
+    match protocol {
+        Protocol::Json => loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).map_err(read_failed)? == 0 {
+                return Ok(None);
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            return serde_json::from_str(&line)
+                .map(Some)
+                .map_err(|e| HoneError::io_error(format!("invalid worker request: {}", e)));
+        },
+        Protocol::Proto => {
+            let Some(len) = read_varint(reader)? else {
+                return Ok(None);
+            };
+            if len > MAX_REQUEST_BYTES {
+                return Err(HoneError::io_error(format!(
+                    "invalid worker request: length {} is over the {} MiB limit",
+                    len,
+                    MAX_REQUEST_BYTES / (1024 * 1024)
+                )));
+            }
+            let mut buf = vec![0; len as usize];
+            reader.read_exact(&mut buf).map_err(read_failed)?;
+            WorkRequest::decode(buf.as_slice())
+                .map(Some)
+                .map_err(|e| HoneError::io_error(format!("invalid worker request: {}", e)))
+        }
+    }
+}
+
+/// Write a response and flush it
+pub fn write_response<W: Write>(
+    writer: &mut W,
+    protocol: Protocol,
+    response: &WorkResponse,
+) -> HoneResult<()> {
+    let bytes = match protocol {
+        Protocol::Json => {
+            let mut line = serde_json::to_vec(response).map_err(|e| {
+                HoneError::io_error(format!("failed to encode worker response: {}", e))
+            })?;
+            line.push(b'\n');
+            line
+        }
+        Protocol::Proto => response.encode_length_delimited_to_vec(),
+    };
+    writer
+        .write_all(&bytes)
+        .and_then(|()| writer.flush())
+        .map_err(|e| HoneError::io_error(format!("failed to write worker response: {}", e)))
+}
+
+/// Answer requests from `reader` on `writer` until input ends. Requests run
+/// one at a time, so by the time a cancellation arrives its request has
+/// already been answered; cancellations are dropped.
+pub fn run<R: BufRead, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    mut handle: impl FnMut(&WorkRequest) -> WorkResponse,
+) -> HoneResult<()> {
+    let protocol = match reader.fill_buf().map_err(read_failed)? {
+        [] => return Ok(()),
+        start => Protocol::detect(start),
+    };
+    while let Some(request) = read_request(&mut reader, protocol)? {
+        if request.cancel {
+            continue;
+        }
+        let mut response = handle(&request);
+        response.request_id = request.request_id;
+        write_response(&mut writer, protocol, &response)?;
+    }
+    Ok(())
+}
+
+/// Replace each `@flagfile` argument with the file's lines. A relative
+/// flagfile is read from `dir`.
+pub fn expand_arguments(arguments: &[String], dir: &Path) -> HoneResult<Vec<String>> {
+    let mut expanded = Vec::with_capacity(arguments.len());
+    for argument in arguments {
+        let Some(flagfile) = argument.strip_prefix('@') else {
+            expanded.push(argument.clone());
+            continue;
+        };
+        let path = dir.join(flagfile);
+        let contents = std::fs::read_to_string(&path).map_err(|e| {
+            HoneError::io_error(format!("failed to read flagfile {}: {}", path.display(), e))
+        })?;
+        expanded.extend(
+            contents
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(str::to_string),
+        );
+    }
+    Ok(expanded)
+}
+
+/// Read a protobuf varint, or `None` at a clean end of input
+fn read_varint<R: Read>(reader: &mut R) -> HoneResult<Option<u64>> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8];
+        if reader.read(&mut byte).map_err(read_failed)? == 0 {
+            return if shift == 0 {
+                Ok(None)
+            } else {
+                Err(HoneError::io_error(
+                    "worker input ended inside a message length".to_string(),
+                ))
+            };
+        }
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(HoneError::io_error(
+        "invalid worker message length".to_string(),
+    ))
+}
+
+fn read_failed(e: std::io::Error) -> HoneError {
+    HoneError::io_error(format!("failed to read worker request: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: i32, arguments: &[&str]) -> WorkRequest {
+        WorkRequest {
+            arguments: arguments.iter().map(|a| a.to_string()).collect(),
+            request_id: id,
+            ..Default::default()
+        }
+    }
+
+    fn echo(request: &WorkRequest) -> WorkResponse {
+        WorkResponse::new(0, request.arguments.join(" "))
+    }
+
+    #[test]
+    fn test_proto_round_trip() {
+        let mut input = Vec::new();
+        for req in [request(1, &["compile", "a.hone"]), request(2, &["check"])] {
+            input.extend(req.encode_length_delimited_to_vec());
+        }
+        let mut cancel = request(1, &[]);
+        cancel.cancel = true;
+        input.extend(cancel.encode_length_delimited_to_vec());
+
+        let mut output = Vec::new();
+        run(input.as_slice(), &mut output, echo).unwrap();
+
+        let mut output = output.as_slice();
+        let first = WorkResponse::decode_length_delimited(&mut output).unwrap();
+        assert_eq!(
+            (first.request_id, first.output.as_str()),
+            (1, "compile a.hone")
+        );
+        let second = WorkResponse::decode_length_delimited(&mut output).unwrap();
+        assert_eq!((second.request_id, second.output.as_str()), (2, "check"));
+        assert!(output.is_empty(), "cancellations get no response");
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let input = "{\"arguments\":[\"check\",\"a.hone\"],\"requestId\":7,\"sandboxDir\":\"sb/7\"}\n\n{}\n";
+        let mut dirs = Vec::new();
+        let mut output = Vec::new();
+        run(input.as_bytes(), &mut output, |req| {
+            dirs.push(req.working_dir(Path::new("/exec")));
+            echo(req)
+        })
+        .unwrap();
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["requestId"], 7);
+        assert_eq!(lines[0]["exitCode"], 0);
+        assert_eq!(lines[0]["output"], "check a.hone");
+        assert_eq!(dirs, [PathBuf::from("/exec/sb/7"), PathBuf::from("/exec")]);
+    }
+
+    #[test]
+    fn test_detect_protocol() {
+        assert_eq!(Protocol::detect(b"{\"arguments\":[]}"), Protocol::Json);
+        assert_eq!(Protocol::detect(b"{}"), Protocol::Json);
+        // A 123-byte protobuf request: its length byte is `{`
+        assert_eq!(Protocol::detect(&[b'{', 0x0a, 0x07]), Protocol::Proto);
+        assert_eq!(Protocol::detect(&[0x02, 0x18, 0x01]), Protocol::Proto);
+    }
+
+    #[test]
+    fn test_oversized_request_is_an_error() {
+        // A varint length of 2^40, followed by nothing
+        let frame = [0x80, 0x80, 0x80, 0x80, 0x80, 0x20];
+        let err = read_request(&mut &frame[..], Protocol::Proto).unwrap_err();
+        assert!(err.to_string().contains("64 MiB"), "{}", err);
+    }
+
+    #[test]
+    fn test_expand_flagfiles() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("args"), "app.hone\n-o\nout.json\n").unwrap();
+
+        let arguments = vec!["compile".to_string(), "@args".to_string()];
+        assert_eq!(
+            expand_arguments(&arguments, dir).unwrap(),
+            ["compile", "app.hone", "-o", "out.json"]
+        );
+        assert!(expand_arguments(&["@missing".to_string()], dir).is_err());
+    }
+}
@@ -164,3 +164,1728 @@ fn test_stdin_check_mode_error() {
     let stderr = String::from_utf8_lossy(&output.stderr);
     assert!(stderr.contains("undefined variable"), "stderr: {}", stderr);
 }
+
+// --- Typegen drift detection ---
+
+#[test]
+fn test_typegen_check_detects_drift() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let schema = dir.path().join("schema.json");
+    let generated = dir.path().join("types.hone");
+    std::fs::write(
+        &schema,
+        r#"{"title": "App", "properties": {"port": {"type": "integer"}}}"#,
+    )
+    .unwrap();
+
+    let typegen = |extra: &[&str]| {
+        let mut args = vec![
+            "typegen",
+            schema.to_str().unwrap(),
+            "-o",
+            generated.to_str().unwrap(),
+        ];
+        args.extend_from_slice(extra);
+        hone_binary().args(&args).output().expect("run hone")
+    };
+
+    assert!(typegen(&[]).status.success());
+    assert!(typegen(&["--check"]).status.success());
+
+    std::fs::write(
+        &schema,
+        r#"{"title": "App", "properties": {"port": {"type": "string"}}}"#,
+    )
+    .unwrap();
+    let output = typegen(&["--check"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("out of date"), "stderr: {}", stderr);
+}
+
+#[test]
+fn test_overlay_new_compiles_with_new_variant_case() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let prod = dir.path().join("prod.hone");
+    std::fs::write(
+        &prod,
+        "variant env {\n  default prod {\n    let replicas = 5\n  }\n}\n\nname: \"api\"\nreplicas: replicas\n",
+    )
+    .unwrap();
+
+    let output = hone_binary()
+        .args([
+            "overlay",
+            "new",
+            "staging",
+            "--from",
+            prod.to_str().unwrap(),
+            "--keys",
+            "replicas",
+            "--variant",
+            "env",
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run hone");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let staging = dir.path().join("staging.hone");
+    let overlay = std::fs::read_to_string(&staging).unwrap();
+    assert!(overlay.contains("from \"./prod.hone\""), "{}", overlay);
+    assert!(overlay.contains("replicas: 5"), "{}", overlay);
+    assert!(!overlay.contains("name:"), "{}", overlay);
+
+    let output = hone_binary()
+        .args([
+            "compile",
+            staging.to_str().unwrap(),
+            "--variant",
+            "env=staging",
+        ])
+        .output()
+        .expect("run hone");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"name\": \"api\""), "{}", stdout);
+
+    // A second overlay of the same name is refused, and the variant keeps
+    // the one case
+    let base = std::fs::read_to_string(&prod).unwrap();
+    let output = hone_binary()
+        .args(["overlay", "new", "staging", "--from"])
+        .arg(&prod)
+        .args(["--keys", "replicas", "--variant", "env"])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run hone");
+    assert!(!output.status.success());
+    assert_eq!(std::fs::read_to_string(&prod).unwrap(), base);
+    assert_eq!(base.matches("staging").count(), 1, "{}", base);
+}
+
+#[test]
+fn test_interactive_without_tty_falls_back_to_flags() {
+    let file = write_temp_hone("expect args.env: string\nenv: args.env\n");
+    let output = hone_binary()
+        .args([
+            "compile",
+            file.path().to_str().unwrap(),
+            "--interactive",
+            "--set",
+            "env=prod",
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run hone");
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("not a terminal"), "stderr: {}", stderr);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\"env\": \"prod\""));
+}
+
+#[test]
+fn test_compile_output_write_options() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let source = dir.path().join("app.hone");
+    let out = dir.path().join("app.yaml");
+    std::fs::write(&source, "password: \"hunter2\"\n").unwrap();
+
+    let compile = |extra: &[&str]| {
+        let mut args = vec![
+            "compile",
+            source.to_str().unwrap(),
+            "-o",
+            out.to_str().unwrap(),
+            "--no-cache",
+        ];
+        args.extend_from_slice(extra);
+        hone_binary().args(&args).output().expect("run hone")
+    };
+
+    assert!(compile(&["--stamp", "--mode", "0600"]).status.success());
+    let written = std::fs::read_to_string(&out).unwrap();
+    assert!(
+        written.starts_with("# Code generated by hone from "),
+        "{}",
+        written
+    );
+    assert!(written.contains("password: hunter2"), "{}", written);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&out).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let output = compile(&["--no-clobber"]);
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("already exists"), "stderr: {}", stderr);
+
+    assert!(!compile(&["--mode", "rw-"]).status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_post_compile_hooks_from_manifest() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let source = dir.path().join("app.hone");
+    let out = dir.path().join("app.json");
+    std::fs::write(&source, "name: \"api\"\n").unwrap();
+    std::fs::write(
+        dir.path().join("hone.toml"),
+        "[hooks]\npost_compile = [\"grep -q api {output} && echo {format} > hook.log\"]\n",
+    )
+    .unwrap();
+
+    let compile = |extra: &[&str]| {
+        let mut args = vec![
+            "compile",
+            source.to_str().unwrap(),
+            "-o",
+            out.to_str().unwrap(),
+            "--no-cache",
+        ];
+        args.extend_from_slice(extra);
+        hone_binary().args(&args).output().expect("run hone")
+    };
+
+    assert!(compile(&[]).status.success());
+    let log = std::fs::read_to_string(dir.path().join("hook.log")).unwrap();
+    assert_eq!(log.trim(), "json");
+
+    // A failing hook fails the compile
+    std::fs::write(&source, "name: \"web\"\n").unwrap();
+    let output = compile(&[]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("post_compile hook failed"), "{}", stderr);
+
+    assert!(compile(&["--no-hooks"]).status.success());
+}
+
+#[test]
+fn test_validate_k8s_catches_api_version_typo_offline() {
+    let cache = tempfile::tempdir().expect("create temp dir");
+    let file = write_temp_hone(
+        "apiVersion: \"app/v1\"\nkind: \"Deployment\"\nmetadata { name: \"api\" }\n",
+    );
+
+    let output = hone_binary()
+        .args([
+            "compile",
+            file.path().to_str().unwrap(),
+            "--validate",
+            "k8s@1.29",
+            "--offline",
+        ])
+        .env("XDG_CACHE_HOME", cache.path())
+        .output()
+        .expect("run hone");
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("main: apiVersion: unknown apiVersion 'app/v1' for kind Deployment"),
+        "stderr: {}",
+        stderr
+    );
+    assert!(stderr.contains("use apps/v1"), "stderr: {}", stderr);
+}
+
+#[test]
+fn test_debug_prints_scope_at_position() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let file = dir.path().join("app.hone");
+    std::fs::write(
+        &file,
+        "let env = \"prod\"\n\nserver {\n  let port = 8080\n  url: \"http://${env}:${port}\"\n}\n",
+    )
+    .unwrap();
+
+    let output = hone_binary()
+        .args(["debug", file.to_str().unwrap(), "--at", "5:3"])
+        .output()
+        .expect("run hone");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("app.hone:5:3 in server"), "{}", stdout);
+    assert!(
+        stdout.contains("  env = \"prod\"\n  port = 8080\n"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_dap_stops_on_entry_and_runs_to_end() {
+    let file = write_temp_hone("let a = 1\nb: a + 1\n");
+    let frame = |body: String| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let requests = [
+        r#"{"seq":1,"type":"request","command":"initialize","arguments":{}}"#.to_string(),
+        format!(
+            r#"{{"seq":2,"type":"request","command":"launch","arguments":{{"program":{:?},"stopOnEntry":true}}}}"#,
+            file.path().to_str().unwrap()
+        ),
+        r#"{"seq":3,"type":"request","command":"configurationDone"}"#.to_string(),
+        r#"{"seq":4,"type":"request","command":"continue","arguments":{"threadId":1}}"#.to_string(),
+        r#"{"seq":5,"type":"request","command":"disconnect"}"#.to_string(),
+    ];
+    let input: String = requests.into_iter().map(frame).collect();
+
+    let output = run_stdin(&["dap"], &input);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains(r#""reason":"entry""#), "{}", stdout);
+    assert!(stdout.contains(r#""event":"terminated""#), "{}", stdout);
+}
+
+#[test]
+fn test_deny_warning_code_with_json_diagnostics() {
+    let file = write_temp_hone("let unused = 1\na: 1\n");
+    let path = file.path().to_str().unwrap();
+
+    let output = hone_binary()
+        .args(["compile", path, "--no-cache"])
+        .output()
+        .expect("run hone");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unused variable 'unused'"));
+
+    let output = hone_binary()
+        .args([
+            "compile",
+            path,
+            "--no-cache",
+            "--deny",
+            "warn:unused-let",
+            "--error-format",
+            "json",
+        ])
+        .output()
+        .expect("run hone");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let diagnostics: Vec<serde_json::Value> = stderr
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("one JSON object per line"))
+        .collect();
+    assert_eq!(diagnostics[0]["code"], "unused-let");
+    assert_eq!(diagnostics[0]["severity"], "error");
+    assert_eq!(diagnostics[0]["line"], 1);
+    assert!(diagnostics[1]["message"]
+        .as_str()
+        .unwrap()
+        .contains("treated as errors"));
+}
+
+#[test]
+fn test_number_precision_warnings_depend_on_format() {
+    let file =
+        write_temp_hone("total: 0.1 + 0.2\nexact: decimal(0.1 + 0.2, 2)\nid: 9007199254740993\n");
+    let path = file.path().to_str().unwrap();
+
+    let output = hone_binary()
+        .args(["compile", path, "--no-cache"])
+        .output()
+        .expect("run hone");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("'total' is 0.30000000000000004"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("'id' is 9007199254740993"), "{}", stderr);
+    assert!(!stderr.contains("'exact'"), "{}", stderr);
+
+    // YAML readers keep 64-bit ints
+    let output = hone_binary()
+        .args([
+            "compile",
+            path,
+            "--no-cache",
+            "--format",
+            "yaml",
+            "--deny",
+            "warn:float-precision",
+        ])
+        .output()
+        .expect("run hone");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("float-precision"), "{}", stderr);
+    assert!(!stderr.contains("int-precision"), "{}", stderr);
+}
+
+#[test]
+fn test_compile_stable_order_alpha() {
+    let source = "b: 1\na {\n  z: 1\n  y: 2\n}\n";
+    let output = run_stdin(&["compile", "-", "--format", "json"], source);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.find("\"b\"") < stdout.find("\"a\""), "{}", stdout);
+
+    let output = run_stdin(
+        &[
+            "compile",
+            "-",
+            "--format",
+            "json",
+            "--stable-order",
+            "alpha",
+        ],
+        source,
+    );
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.find("\"a\"") < stdout.find("\"b\""), "{}", stdout);
+    assert!(stdout.find("\"y\"") < stdout.find("\"z\""), "{}", stdout);
+}
+
+#[test]
+fn test_compile_number_keys_and_key_collision() {
+    let source = "ports {\n  for p in [443, 80, 8080, 80] {\n    [p]: true\n  }\n}\n";
+    let output = run_stdin(
+        &[
+            "compile",
+            "-",
+            "--format",
+            "json",
+            "--stable-order",
+            "alpha",
+        ],
+        source,
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        stdout.trim(),
+        r#"{"ports":{"80":true,"443":true,"8080":true}}"#
+    );
+
+    let output = run_stdin(&["compile", "-", "--key-collision", "error"], source);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("E0307"), "{}", stderr);
+    assert!(stderr.contains("key '80' is given twice"), "{}", stderr);
+}
+
+#[test]
+fn test_compile_report_memory_lists_largest_values() {
+    let source = "let names = [for i in range(0, 50) { \"service-${i}\" }]\n\
+                  count: len(names)\n\
+                  bytes: size_of(names)\n";
+    let output = run_stdin(&["compile", "-", "--report-memory"], source);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"count\": 50"), "stdout: {}", stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("peak heap growth during compilation:"),
+        "{}",
+        stderr
+    );
+    // The let and the loop building its value tie as the largest; ties are
+    // listed in source order
+    let lines: Vec<&str> = stderr.lines().collect();
+    let first = lines
+        .iter()
+        .position(|l| l.starts_with("largest values"))
+        .unwrap();
+    assert!(
+        lines[first + 1].contains("641 B  let names   <stdin>:1:1"),
+        "{}",
+        stderr
+    );
+    assert!(
+        lines[first + 2].contains("641 B  `for` loop  <stdin>:1:14"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_compile_profile_and_policy_timeout() {
+    let source = "fn spin(n) { len([for i in range(0, n) { for j in range(0, n) { i * j } }]) }
+                  policy slow warn when spin(300) < 0 && output.x > 0 { \"never\" }
+                  policy quick deny when output.x > 10 { \"x too big\" }
+                  x: 1
+";
+    let output = run_stdin(&["compile", "-", "--profile"], source);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    let first = lines
+        .iter()
+        .position(|l| l.starts_with("policy timings (slowest first):"))
+        .unwrap_or_else(|| panic!("{}", stderr));
+    assert!(lines[first + 1].contains("slow   <stdin>:2"), "{}", stderr);
+    assert!(lines[first + 2].contains("quick  <stdin>:3"), "{}", stderr);
+
+    let output = run_stdin(&["compile", "-", "--policy-timeout", "1ms"], source);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("policy 'slow' still running"), "{}", stderr);
+
+    let output = run_stdin(&["compile", "-", "--policy-timeout", "0s"], source);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("expected a duration above zero"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_compile_explain_inputs_lists_env_reads() {
+    let output = hone_binary()
+        .args(["compile", "-", "--allow-env", "--explain-inputs"])
+        .env("HONE_TEST_REGION", "eu-west-1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(b"region: env(\"HONE_TEST_REGION\")\n")?;
+            child.wait_with_output()
+        })
+        .expect("run hone");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("eu-west-1"), "stdout: {}", stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("env  HONE_TEST_REGION  <stdin>:1:9"),
+        "stderr: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_build_named_target_from_manifest() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    std::fs::write(
+        dir.path().join("hone.toml"),
+        "[targets.prod]\nentry = \"app.hone\"\nvariants = { env = \"production\" }\nformat = \"yaml\"\noutput = \"out/prod.yaml\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("app.hone"),
+        "variant env {\n  default dev {\n    replicas: 1\n  }\n  production {\n    replicas: 3\n  }\n}\nname: \"app\"\n",
+    )
+    .unwrap();
+    std::fs::create_dir(dir.path().join("out")).unwrap();
+
+    let output = hone_binary()
+        .args(["build", "prod", "--no-cache"])
+        .current_dir(dir.path())
+        .output()
+        .expect("run hone");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let written = std::fs::read_to_string(dir.path().join("out/prod.yaml")).unwrap();
+    assert!(written.contains("replicas: 3"), "{}", written);
+
+    let output = hone_binary()
+        .args(["build", "staging"])
+        .current_dir(dir.path())
+        .output()
+        .expect("run hone");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("prod"));
+}
+
+#[test]
+fn test_build_output_name_keeps_variants_apart() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    std::fs::write(
+        dir.path().join("hone.toml"),
+        "[targets.dev]\nentry = \"k8s.hone\"\noutput_dir = \"out\"\noutput_name = \"{document}-{variant.env}.{ext}\"\n\n[targets.prod]\nentry = \"k8s.hone\"\nvariants = { env = \"production\" }\noutput_dir = \"out\"\noutput_name = \"{document}-{variant.env}.{ext}\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("k8s.hone"),
+        "variant env {\n  default dev {\n    replicas: 1\n  }\n  production {\n    replicas: 3\n  }\n}\n---deployment\nkind: \"Deployment\"\n",
+    )
+    .unwrap();
+
+    let output = hone_binary()
+        .args(["build", "prod", "--no-cache"])
+        .current_dir(dir.path())
+        .output()
+        .expect("run hone");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(dir.path().join("out/deployment-production.yaml").exists());
+
+    // dev never selects env explicitly, so its name can't be filled in
+    let output = hone_binary()
+        .args(["build", "dev", "--no-cache"])
+        .current_dir(dir.path())
+        .output()
+        .expect("run hone");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("'env' is not selected"), "{}", stderr);
+
+    let output = hone_binary()
+        .args([
+            "compile",
+            "k8s.hone",
+            "--output-dir",
+            "cli",
+            "--output-name",
+            "{variant.env}/{document}.{ext}",
+            "--variant",
+            "env=dev",
+            "--no-cache",
+        ])
+        .current_dir(dir.path())
+        .output()
+        .expect("run hone");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let written = std::fs::read_to_string(dir.path().join("cli/dev/deployment.yaml")).unwrap();
+    assert!(written.contains("Deployment"), "{}", written);
+}
+
+#[test]
+fn test_compile_emit_derivation() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    std::fs::write(dir.path().join("base.hone"), "port: 80\n").unwrap();
+    std::fs::write(
+        dir.path().join("app.hone"),
+        "from \"./base.hone\"\nname: args.name\n",
+    )
+    .unwrap();
+
+    let run = || {
+        let output = hone_binary()
+            .args([
+                "compile",
+                "app.hone",
+                "-o",
+                "out.json",
+                "--set",
+                "name=api",
+                "--emit-derivation",
+                "build.json",
+            ])
+            .current_dir(dir.path())
+            .output()
+            .expect("run hone");
+        assert!(
+            output.status.success(),
+            "stderr: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        std::fs::read_to_string(dir.path().join("build.json")).unwrap()
+    };
+    let first = run();
+    assert_eq!(first, run(), "derivation is not stable");
+
+    let derivation: serde_json::Value = serde_json::from_str(&first).unwrap();
+    assert_eq!(derivation["entry"], "app.hone");
+    assert_eq!(derivation["sources"].as_array().unwrap().len(), 2);
+    assert_eq!(derivation["args"]["name"], "api");
+    assert_eq!(derivation["hermetic"], true);
+    assert_eq!(derivation["outputs"][0]["path"], "out.json");
+    let written = std::fs::read_to_string(dir.path().join("out.json")).unwrap();
+    let hash = derivation["outputs"][0]["sha256"].as_str().unwrap();
+    assert_eq!(hash, hone::cache::CacheKey::hash_string(&written));
+}
+
+#[test]
+fn test_compile_source_map() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    std::fs::write(dir.path().join("base.hone"), "server {\n  port: 80\n}\n").unwrap();
+    std::fs::write(
+        dir.path().join("app.hone"),
+        "from \"./base.hone\"\nname: \"api\"\n",
+    )
+    .unwrap();
+
+    let output = hone_binary()
+        .args([
+            "compile",
+            "app.hone",
+            "-o",
+            "out.yaml",
+            "--stamp",
+            "--source-map",
+        ])
+        .current_dir(dir.path())
+        .output()
+        .expect("run hone");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let written = std::fs::read_to_string(dir.path().join("out.yaml")).unwrap();
+    let map = std::fs::read_to_string(dir.path().join("out.yaml.map.json")).unwrap();
+    let map: serde_json::Value = serde_json::from_str(&map).unwrap();
+    assert_eq!(map["format"], "yaml");
+
+    let mappings = map["mappings"].as_array().unwrap();
+    let port = mappings
+        .iter()
+        .find(|m| m["path"] == "server.port")
+        .expect("server.port is mapped");
+    assert_eq!(port["source"]["file"], "base.hone");
+    assert_eq!(port["source"]["line"], 2);
+    let line = port["output"]["line"].as_u64().unwrap() as usize;
+    assert_eq!(written.lines().nth(line - 1), Some("  port: 80"));
+
+    let output = hone_binary()
+        .args(["compile", "app.hone", "--output-dir", "out", "--source-map"])
+        .current_dir(dir.path())
+        .output()
+        .expect("run hone");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_doctor_reports_dangling_imports() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    std::fs::write(
+        dir.path().join("hone.toml"),
+        "[targets.api]\nentry = \"app.hone\"\noutput = \"out.yaml\"\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("app.hone"), "name: \"api\"\n").unwrap();
+    let doctor = || {
+        hone_binary()
+            .args(["doctor", "--format", "json"])
+            .current_dir(dir.path())
+            .env("XDG_CACHE_HOME", dir.path().join("cache"))
+            .output()
+            .expect("run hone")
+    };
+
+    let output = doctor();
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let names: Vec<&str> = report["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["cache", "git", "lsp", "manifest", "imports"]);
+    assert_eq!(report["environment"]["hone"], env!("CARGO_PKG_VERSION"));
+
+    std::fs::write(
+        dir.path().join("app.hone"),
+        "from \"./base.hone\"\nname: \"api\"\n",
+    )
+    .unwrap();
+    let output = doctor();
+    assert!(!output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let imports = &report["checks"][4];
+    assert_eq!(imports["status"], "fail");
+    assert_eq!(
+        imports["details"][0],
+        "app.hone:1: \"./base.hone\" not found"
+    );
+}
+
+#[test]
+fn test_serve_once_writes_targets_and_notifies_webhook() {
+    use std::io::{Read, Write};
+
+    let dir = tempfile::tempdir().expect("create temp dir");
+    std::fs::write(
+        dir.path().join("hone.toml"),
+        "[targets.api]\nentry = \"app.hone\"\noutput_dir = \"out\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("app.hone"),
+        "---deployment\nreplicas: 2\n---service\nport: 80\ntoken: \"hunter2xyz\" @sensitive\n",
+    )
+    .unwrap();
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = conn.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length: usize = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("Content-Length: "))
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                if body.len() >= length {
+                    break;
+                }
+            }
+        }
+        conn.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+        String::from_utf8(request).unwrap()
+    });
+
+    let output = hone_binary()
+        .args(["serve", "api", "--once", "--webhook", &url])
+        .current_dir(dir.path())
+        .output()
+        .expect("run hone");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let written = std::fs::read_to_string(dir.path().join("out/service.yaml")).unwrap();
+    assert!(written.contains("port: 80"), "{}", written);
+    // The file gets the secret, the webhook only its mask
+    assert!(written.contains("hunter2xyz"), "{}", written);
+
+    let request = server.join().unwrap();
+    let body = request.split_once("\r\n\r\n").unwrap().1;
+    let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(payload["target"], "api");
+    assert_eq!(payload["files"].as_array().unwrap().len(), 2);
+    assert!(payload["files"][0]["content"]
+        .as_str()
+        .unwrap()
+        .contains("replicas: 2"));
+    let service = payload["files"][1]["content"].as_str().unwrap();
+    assert!(service.contains("token: ***"), "{}", service);
+    assert!(!body.contains("hunter2xyz"), "{}", body);
+}
+
+#[test]
+fn test_eval_pretty_prints_by_default() {
+    let output = hone_binary()
+        .args([
+            "eval",
+            "name: \"api\"\nreplicas: 3\nports: [1, 2, 3, 4]",
+            "--max-items",
+            "2",
+        ])
+        .output()
+        .expect("run hone");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "name:     \"api\"\nreplicas: 3\nports:    [1, 2, … 2 more]\n"
+    );
+}
+
+#[test]
+fn test_compile_stream_matches_regular_output() {
+    let f = write_temp_hone(
+        "let n = 4\nname: \"fixtures\"\nitems: for i in range(0, n) where i != 2 {\n  id: i\n  label: \"item-${i}\"\n}\nempty: for x in [] { x }\nmeta { count: n }\n",
+    );
+    let path = f.path().to_str().unwrap();
+    for format in ["json", "json-pretty"] {
+        let regular = hone_binary()
+            .args(["compile", path, "--format", format, "--no-cache"])
+            .output()
+            .expect("run hone");
+        let streamed = hone_binary()
+            .args(["compile", path, "--format", format, "--stream"])
+            .output()
+            .expect("run hone");
+        assert!(streamed.status.success(), "{:?}", streamed);
+        assert_eq!(streamed.stdout, regular.stdout);
+    }
+
+    let output = hone_binary()
+        .args(["compile", path, "--format", "toml", "--stream"])
+        .output()
+        .expect("run hone");
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn test_compile_stream_refuses_arrays_touched_elsewhere() {
+    let items = "items: for i in range(0, 3) { i }\n";
+    for (rest, what) in [
+        ("...{ items: [9] }\n", "a spread"),
+        ("when true { items +: [5] }\n", "a `when` block"),
+        ("count: len(self.items)\n", "a `self` reference"),
+    ] {
+        let f = write_temp_hone(&format!("{}{}", items, rest));
+        let path = f.path().to_str().unwrap();
+        let regular = hone_binary()
+            .args(["compile", path, "--format", "json", "--no-cache"])
+            .output()
+            .expect("run hone");
+        assert!(regular.status.success(), "{}: {:?}", rest, regular);
+        let streamed = hone_binary()
+            .args(["compile", path, "--format", "json", "--stream"])
+            .output()
+            .expect("run hone");
+        assert_eq!(streamed.status.code(), Some(3), "{}", rest);
+        assert!(streamed.stdout.is_empty(), "{}", rest);
+        // The report wraps long messages; compare the words
+        let stderr = String::from_utf8_lossy(&streamed.stderr)
+            .split_whitespace()
+            .filter(|word| *word != "│")
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert!(
+            stderr.contains(&format!("`items` is also written or read by {}", what)),
+            "{}: {}",
+            rest,
+            stderr
+        );
+    }
+
+    // `when` blocks and `self` references that leave the array alone still
+    // stream, with the same output
+    let f = write_temp_hone(&format!("{}n: 2\nwhen true {{ extra: self.n }}\n", items));
+    let path = f.path().to_str().unwrap();
+    let regular = hone_binary()
+        .args(["compile", path, "--format", "json", "--no-cache"])
+        .output()
+        .expect("run hone");
+    let streamed = hone_binary()
+        .args(["compile", path, "--format", "json", "--stream"])
+        .output()
+        .expect("run hone");
+    assert!(streamed.status.success(), "{:?}", streamed);
+    assert_eq!(streamed.stdout, regular.stdout);
+}
+
+#[test]
+fn test_compile_audit_determinism() {
+    let f = write_temp_hone(
+        "variant env {\n  default dev { replicas: 1 }\n  prod { replicas: 3 }\n}\nname: \"api\"\n",
+    );
+    let path = f.path().to_str().unwrap();
+    let output = hone_binary()
+        .args([
+            "compile",
+            path,
+            "--variant",
+            "env=prod",
+            "--audit-determinism",
+            "--format",
+            "json",
+        ])
+        .output()
+        .expect("run hone");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("determinism audit passed"));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "{\"replicas\":3,\"name\":\"api\"}\n"
+    );
+
+    let output = hone_binary()
+        .args(["compile", "-", "--audit-determinism"])
+        .output()
+        .expect("run hone");
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn test_compile_ndjson_writes_one_line_per_document() {
+    let f = write_temp_hone("a: 1\n---second\nb: \"x\\ny\"\n---third\nc: { d: [1, 2] }\n");
+    let path = f.path().to_str().unwrap();
+    let output = hone_binary()
+        .args(["compile", path, "--format", "ndjson"])
+        .output()
+        .expect("run hone");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "{\"a\":1}\n{\"b\":\"x\\ny\"}\n{\"c\":{\"d\":[1,2]}}\n"
+    );
+}
+
+#[test]
+fn test_compile_emit_style_flags() {
+    let f = write_temp_hone("name: \"api\"\nnote: null\nserver: { host: \"x\", tls: null }\n");
+    let path = f.path().to_str().unwrap();
+    let output = hone_binary()
+        .args([
+            "compile",
+            path,
+            "--format",
+            "yaml",
+            "--indent",
+            "4",
+            "--quote-strings",
+            "--omit-nulls",
+            "--no-cache",
+        ])
+        .output()
+        .expect("run hone");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "name: \"api\"\nserver:\n    host: \"x\"\n"
+    );
+
+    let f = write_temp_hone("db: { host: \"pg\" }\nports: [80, 443]\n");
+    let output = hone_binary()
+        .args([
+            "compile",
+            f.path().to_str().unwrap(),
+            "--format",
+            "dotenv",
+            "--env-separator",
+            "_",
+            "--env-arrays",
+            "indexed",
+            "--no-cache",
+        ])
+        .output()
+        .expect("run hone");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "DB_HOST=pg\nPORTS_0=80\nPORTS_1=443\n\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_persistent_worker_compiles_in_sandbox() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let root = dir.path();
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    std::fs::create_dir_all(root.join("sandbox/1")).unwrap();
+    std::fs::write(
+        root.join("src/app.hone"),
+        "import \"./lib.hone\" as lib\nport: lib.port\n",
+    )
+    .unwrap();
+    std::fs::write(root.join("src/lib.hone"), "let port = 1\n").unwrap();
+    // The sandbox links the entry in from the source tree but declares its
+    // own lib.hone, which imports must resolve to
+    std::os::unix::fs::symlink(root.join("src/app.hone"), root.join("sandbox/1/app.hone")).unwrap();
+    std::fs::write(root.join("sandbox/1/lib.hone"), "let port = 2\n").unwrap();
+    std::fs::write(root.join("sandbox/1/args"), "app.hone\n-o\nout.json\n").unwrap();
+
+    let requests = [
+        r#"{"arguments":["@args"],"requestId":1,"sandboxDir":"sandbox/1"}"#,
+        r#"{"arguments":["app.hone"],"requestId":2,"sandboxDir":"sandbox/1"}"#,
+        r#"{"arguments":["missing.hone","-o","x.json"],"requestId":3}"#,
+    ];
+    let mut child = hone_binary()
+        .args(["compile", "--persistent_worker"])
+        .current_dir(root)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("run hone");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(format!("{}\n", requests.join("\n")).as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let responses: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(responses.len(), 3);
+    assert_eq!(responses[0]["requestId"], 1);
+    assert_eq!(responses[0]["exitCode"], 0, "{}", responses[0]["output"]);
+    let written = std::fs::read_to_string(root.join("sandbox/1/out.json")).unwrap();
+    assert!(written.contains("\"port\": 2"), "{}", written);
+
+    assert_eq!(responses[1]["exitCode"], 2);
+    assert!(responses[1]["output"].as_str().unwrap().contains("-o"));
+    assert_eq!(responses[2]["requestId"], 3);
+    assert_eq!(responses[2]["exitCode"], 3);
+    assert!(responses[2]["output"]
+        .as_str()
+        .unwrap()
+        .contains("missing.hone"));
+}
+
+#[test]
+fn test_persistent_worker_skips_hooks_and_returns_warnings() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    std::fs::write(
+        dir.path().join("hone.toml"),
+        "[hooks]\npost_compile = [\"echo hooked\", \"echo ran > hooked.txt\"]\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("app.hone"), "let unused = 1\nport: 80\n").unwrap();
+
+    let mut child = hone_binary()
+        .args(["compile", "--persistent_worker"])
+        .current_dir(dir.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("run hone");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(
+            b"{\"arguments\":[\"app.hone\",\"-o\",\"out.json\",\"--no-cache\"],\"requestId\":1}\n",
+        )
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    // Stdout holds the one response and nothing a hook printed
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1, "{}", stdout);
+    let response: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(response["exitCode"], 0);
+    assert!(
+        response["output"]
+            .as_str()
+            .unwrap()
+            .contains("unused variable 'unused'"),
+        "{}",
+        response
+    );
+    assert!(dir.path().join("out.json").exists());
+    assert!(!dir.path().join("hooked.txt").exists());
+}
+
+#[test]
+fn test_conflicts_lists_overridden_keys() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    std::fs::write(dir.path().join("base.hone"), "port: 80\nname: \"api\"\n").unwrap();
+    std::fs::write(
+        dir.path().join("main.hone"),
+        "from \"./base.hone\"\n\nvariant env {\n  default dev {\n    port: 8080\n  }\n}\n\nport: 9090\n",
+    )
+    .unwrap();
+    let output = hone_binary()
+        .current_dir(dir.path())
+        .args(["conflicts", "main.hone", "--format", "json"])
+        .output()
+        .expect("run hone");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let conflicts: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let conflicts = conflicts.as_array().unwrap();
+    assert_eq!(conflicts.len(), 1, "{:?}", conflicts);
+    assert_eq!(conflicts[0]["path"], "port");
+    assert_eq!(conflicts[0]["value"], 9090);
+    let writes = conflicts[0]["writes"].as_array().unwrap();
+    let lines: Vec<_> = writes
+        .iter()
+        .map(|w| (w["line"].as_u64().unwrap(), w["wins"].as_bool().unwrap()))
+        .collect();
+    assert_eq!(lines, [(1, false), (5, false), (9, true)]);
+}
+
+#[test]
+fn test_upgrade_dry_run_then_write() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let file = dir.path().join("app.hone");
+    let source = "let port = null\nport: default(port, 80)\n";
+    std::fs::write(&file, source).unwrap();
+
+    let output = hone_binary()
+        .args(["upgrade", "--to", "2025", "--dry-run"])
+        .arg(dir.path())
+        .output()
+        .expect("run hone");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("+port: port ?? 80"), "{}", stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("app.hone:2:7: default-to-coalesce"),
+        "{}",
+        stderr
+    );
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), source);
+
+    let output = hone_binary()
+        .args(["upgrade", "--to", "2025"])
+        .arg(&file)
+        .output()
+        .expect("run hone");
+    assert!(output.status.success());
+    let upgraded = std::fs::read_to_string(&file).unwrap();
+    assert!(upgraded.contains("port: port ?? 80"), "{}", upgraded);
+
+    let output = hone_binary()
+        .args(["upgrade", "--to", "2030"])
+        .arg(&file)
+        .output()
+        .expect("run hone");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_stats_reports_per_file_metrics() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    std::fs::write(dir.path().join("base.hone"), "name: \"base\"\n").unwrap();
+    std::fs::write(
+        dir.path().join("app.hone"),
+        "from \"./base.hone\"\nlet xs = [1, 2]\nreplicas: len(xs)\n",
+    )
+    .unwrap();
+
+    let output = hone_binary()
+        .args(["stats", "--format", "json"])
+        .arg(dir.path())
+        .output()
+        .expect("run hone");
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("valid JSON");
+    let files = json.as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert!(files[0]["file"].as_str().unwrap().ends_with("app.hone"));
+    assert_eq!(files[0]["output_keys"], 1);
+    assert_eq!(files[0]["builtins"]["len"], 1);
+    assert_eq!(files[1]["imported_by"], 1);
+}
+
+#[test]
+fn test_sensitive_values_masked_in_diff_debug_and_errors() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    let file = dir.path().join("app.hone");
+    std::fs::write(
+        &file,
+        "schema Db {\n  url: string(\"^postgres://\")\n}\nuse Db\n\
+         let pw = args.pw @sensitive\npassword: pw\nurl: \"mysql://app:${pw}@db\"\n",
+    )
+    .unwrap();
+    let path = file.to_str().unwrap();
+
+    // The output keeps the real value; the schema error does not show it
+    let output = hone_binary()
+        .args(["compile", path, "--set", "pw=hunter2xyz"])
+        .output()
+        .expect("run hone");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("mysql://app:***@db"), "{}", stderr);
+    assert!(!stderr.contains("hunter2xyz"), "{}", stderr);
+
+    std::fs::write(
+        &file,
+        "let pw = args.pw @sensitive\npassword: pw\nurl: \"postgres://app:${pw}@db\"\n",
+    )
+    .unwrap();
+    let output = hone_binary()
+        .args(["compile", path, "--set", "pw=hunter2xyz"])
+        .output()
+        .expect("run hone");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("hunter2xyz"));
+
+    let output = hone_binary()
+        .args([
+            "diff",
+            path,
+            "--left",
+            "pw=hunter2xyz",
+            "--right",
+            "pw=s3cr3tpass",
+        ])
+        .output()
+        .expect("run hone");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("password: \"***\" -> \"***\""),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("hunter2xyz") && !stdout.contains("s3cr3tpass"));
+
+    let output = hone_binary()
+        .args(["debug", path, "--at", "3", "--set", "pw=hunter2xyz"])
+        .output()
+        .expect("run hone");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("  pw = \"***\""), "{}", stdout);
+    assert!(!stdout.contains("hunter2xyz"), "{}", stdout);
+}
+
+#[test]
+fn test_piped_diff_is_not_wrapped() {
+    let file = write_temp_hone(
+        "let x = args.x\ndescription: \"a fairly long description for ${x} that would wrap\"\n",
+    );
+    let output = hone_binary()
+        .env("COLUMNS", "30")
+        .args(["diff", file.path().to_str().unwrap()])
+        .args(["--left", "x=one", "--right", "x=two"])
+        .output()
+        .expect("run hone");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout
+        .lines()
+        .find(|l| l.contains("description"))
+        .unwrap_or_else(|| panic!("{}", stdout));
+    assert!(
+        line.contains("for one that would wrap\" -> \""),
+        "{}",
+        stdout
+    );
+    assert!(line.ends_with("for two that would wrap\""), "{}", stdout);
+}
+
+#[test]
+fn test_check_all_reports_upstream_errors_once() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("app")).unwrap();
+    std::fs::write(dir.path().join("base.hone"), "replicas: \n").unwrap();
+    std::fs::write(
+        dir.path().join("app/main.hone"),
+        "from \"../base.hone\"\nname: \"app\"\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("app/ok.hone"), "name: \"ok\"\n").unwrap();
+
+    let output = hone_binary()
+        .args([
+            "check",
+            "--all",
+            dir.path().to_str().unwrap(),
+            "--jobs",
+            "2",
+        ])
+        .output()
+        .expect("run hone");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert_eq!(stderr.matches("unexpected token").count(), 1, "{}", stderr);
+    assert!(stderr.contains("base.hone: FAILED"), "{}", stderr);
+    assert!(
+        stderr.contains("app/main.hone: skipped due to upstream error in base.hone"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("app/ok.hone: OK"), "{}", stderr);
+    assert!(
+        stderr.contains("Checked 3 files: 1 ok, 1 failed, 1 skipped"),
+        "{}",
+        stderr
+    );
+
+    std::fs::write(dir.path().join("base.hone"), "replicas: 2\n").unwrap();
+    let output = hone_binary()
+        .args(["check", "--all", dir.path().to_str().unwrap()])
+        .output()
+        .expect("run hone");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_release_notes_groups_changes_by_target() {
+    let dir = tempfile::tempdir().unwrap();
+    let write = |name: &str, source: &str| std::fs::write(dir.path().join(name), source).unwrap();
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .args(["-c", "user.name=hone", "-c", "user.email=hone@example.com"])
+            .args(args)
+            .current_dir(dir.path())
+            .output()
+            .expect("run git")
+            .status;
+        assert!(status.success(), "git {:?}", args);
+    };
+    write(
+        "hone.toml",
+        "[targets.api]\nentry = \"api.hone\"\noutput = \"out/api.yaml\"\n",
+    );
+    write(
+        "api.hone",
+        "replicas: 2\ndebug: true\npassword: \"hunter2xyz\" @sensitive\n",
+    );
+    git(&["init", "-q"]);
+    git(&["add", "-A"]);
+    git(&["commit", "-q", "-m", "first"]);
+    git(&["tag", "v1"]);
+
+    write(
+        "api.hone",
+        "replicas: 3\nimage: \"api:2\"\npassword: \"s3cr3tpass\" @sensitive\n",
+    );
+    write(
+        "hone.toml",
+        "[targets.api]\nentry = \"api.hone\"\noutput = \"out/api.yaml\"\n\n\
+         [targets.worker]\nentry = \"worker.hone\"\noutput = \"out/worker.yaml\"\n",
+    );
+    write("worker.hone", "queue: \"jobs\"\n");
+
+    let output = hone_binary()
+        .args([
+            "release-notes",
+            "--since",
+            "v1",
+            "--format",
+            "markdown",
+            dir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run hone");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.starts_with("## Changes since v1\n"), "{}", stdout);
+    assert!(
+        stdout.contains("- Added `image`: `\"api:2\"`"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("- Changed `replicas`: `2` -> `3`"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("- Removed `debug` (was `true`)"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("### `worker` (new target)"), "{}", stdout);
+    assert!(!stdout.contains("hunter2xyz") && !stdout.contains("s3cr3tpass"));
+
+    git(&["add", "-A"]);
+    git(&["commit", "-q", "-m", "second"]);
+    let output = hone_binary()
+        .args([
+            "release-notes",
+            "--since",
+            "HEAD",
+            "--until",
+            "v1",
+            dir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run hone");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("worker (removed target)"), "{}", stdout);
+    assert!(
+        stdout.contains("2 targets: 1 changed, 0 added, 1 removed, 0 unchanged"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_diff_and_release_notes_against_directory_snapshot() {
+    let project = tempfile::tempdir().unwrap();
+    let snapshot = tempfile::tempdir().unwrap();
+    let manifest = "[targets.api]\nentry = \"api.hone\"\noutput = \"out/api.yaml\"\n";
+    for dir in [project.path(), snapshot.path()] {
+        std::fs::write(dir.join("hone.toml"), manifest).unwrap();
+    }
+    std::fs::write(snapshot.path().join("api.hone"), "replicas: 2\n").unwrap();
+    let api = project.path().join("api.hone");
+    std::fs::write(&api, "replicas: 3\n").unwrap();
+    let rev = format!("dir:{}", snapshot.path().display());
+
+    let output = hone_binary()
+        .args(["diff", api.to_str().unwrap(), "--base", &rev])
+        .output()
+        .expect("run hone");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(stdout.contains("replicas: 2 -> 3"), "{}", stdout);
+
+    let output = hone_binary()
+        .args([
+            "release-notes",
+            "--since",
+            &rev,
+            project.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("run hone");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("1 changed"), "{}", stdout);
+
+    // Outside any repository, a revision has to be a dir: snapshot
+    let output = hone_binary()
+        .args(["diff", api.to_str().unwrap(), "--base", "main"])
+        .output()
+        .expect("run hone");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("is not in a git or jj repository"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_compile_jsonc_with_key_origins() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("base.hone"),
+        "## Emit target\ntarget: \"es2020\"\nstrict: true\n",
+    )
+    .unwrap();
+    let main = dir.path().join("tsconfig.hone");
+    std::fs::write(&main, "from \"./base.hone\"\nstrict: false\n").unwrap();
+
+    let output = hone_binary()
+        .args([
+            "compile",
+            main.to_str().unwrap(),
+            "--format",
+            "jsonc",
+            "--key-origins",
+            "--trailing-commas",
+            "--no-cache",
+        ])
+        .output()
+        .expect("run hone");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim_end(),
+        "{\n  // Emit target\n  // from base.hone:2\n  \"target\": \"es2020\",\n  \
+         // from tsconfig.hone:2\n  \"strict\": false,\n}"
+    );
+}
+
+#[test]
+fn test_freeze_writes_resolved_source() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    std::fs::write(
+        dir.path().join("lib.hone"),
+        r#"let env = args.env
+variant tier {
+  default small { memory: 512Mi }
+  large { memory: 2Gi }
+}
+
+## Replicas for this environment
+replicas: env == "prod" ? 3 : 1
+server {
+  host: "${env}.example.com"
+  ports: [for p in [80, 443] { p }]
+}
+"#,
+    )
+    .unwrap();
+
+    let output = hone_binary()
+        .args(["freeze", "lib.hone", "--set", "env=prod"])
+        .args(["--variant", "tier=large", "-o", "frozen.hone"])
+        .current_dir(dir.path())
+        .output()
+        .expect("run hone");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let frozen = std::fs::read_to_string(dir.path().join("frozen.hone")).unwrap();
+    assert!(frozen.starts_with(
+        "# Frozen by `hone freeze` from lib.hone\n# --set env=prod --variant tier=large\n"
+    ));
+    assert!(frozen.contains("memory: 2Gi\n## Replicas for this environment\nreplicas: 3\n"));
+    assert!(frozen.contains("  host: \"prod.example.com\"\n  ports: [80, 443]\n"));
+
+    let compile = |file: &str, extra: &[&str]| {
+        let output = hone_binary()
+            .args(["compile", file])
+            .args(extra)
+            .current_dir(dir.path())
+            .output()
+            .expect("run hone");
+        assert!(output.status.success());
+        output.stdout
+    };
+    assert_eq!(
+        compile("frozen.hone", &[]),
+        compile(
+            "lib.hone",
+            &["--set", "env=prod", "--variant", "tier=large"]
+        )
+    );
+}
+
+#[test]
+fn test_compile_report_mapping() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    std::fs::write(
+        dir.path().join("app.hone"),
+        r#"let region = "eu"
+db {
+  host: "${region}.db.internal"
+}
+servers: [{ name: "api" }]
+"#,
+    )
+    .unwrap();
+
+    let output = hone_binary()
+        .args([
+            "compile",
+            "app.hone",
+            "--format",
+            "dotenv",
+            "--report-mapping",
+        ])
+        .current_dir(dir.path())
+        .output()
+        .expect("run hone");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "DB__HOST=eu.db.internal\nSERVERS__0__NAME=api\n\n"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(
+        "  DB__HOST          db.host         app.hone:3  host: \"${region}.db.internal\"\n"
+    ));
+    assert!(stderr.contains(
+        "  SERVERS__0__NAME  servers.0.name  app.hone:5  servers: [{ name: \"api\" }]\n"
+    ));
+
+    let output = hone_binary()
+        .args([
+            "compile",
+            "app.hone",
+            "--format",
+            "yaml",
+            "--report-mapping",
+        ])
+        .current_dir(dir.path())
+        .output()
+        .expect("run hone");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("lists dotenv variables"));
+}
+
+#[test]
+fn test_unused_follows_manifest_targets() {
+    let dir = tempfile::tempdir().expect("create temp dir");
+    std::fs::write(
+        dir.path().join("hone.toml"),
+        "[targets.app]\nentry = \"app.hone\"\noutput = \"out/app.yaml\"\n",
+    )
+    .unwrap();
+    std::fs::create_dir(dir.path().join("lib")).unwrap();
+    std::fs::write(
+        dir.path().join("lib/common.hone"),
+        "let region = \"eu\"\nlet zone = \"a\"\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("lib/old.hone"), "let retired = 1\n").unwrap();
+    // Has output and is imported by nothing, but is not a target
+    std::fs::write(dir.path().join("scratch.hone"), "x: 1\n").unwrap();
+    std::fs::write(
+        dir.path().join("app.hone"),
+        "import { region } from \"./lib/common.hone\"\nregion: region\n",
+    )
+    .unwrap();
+
+    let output = hone_binary()
+        .current_dir(dir.path())
+        .args(["unused", "--format", "json"])
+        .output()
+        .expect("run hone");
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("valid JSON");
+    assert_eq!(json["entries"], serde_json::json!(["app.hone"]));
+    assert_eq!(
+        json["files"],
+        serde_json::json!(["lib/old.hone", "scratch.hone"])
+    );
+    let defs = json["definitions"].as_array().unwrap();
+    assert_eq!(defs.len(), 1, "{:?}", defs);
+    assert_eq!(defs[0]["file"], "lib/common.hone");
+    assert_eq!(defs[0]["line"], 2);
+    assert_eq!(defs[0]["kind"], "let");
+    assert_eq!(defs[0]["name"], "zone");
+
+    // An explicit entry replaces the targets
+    let output = hone_binary()
+        .current_dir(dir.path())
+        .args(["unused", "--entry", "scratch.hone"])
+        .output()
+        .expect("run hone");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("app.hone  file\n"), "{}", stdout);
+    assert!(
+        stdout.ends_with("unused: 3 files (from 1 entry point)\n"),
+        "{}",
+        stdout
+    );
+}
@@ -243,6 +243,53 @@ nested: flag ? (1 > 0 ? "a" : "b") : "c"
     assert!(json.contains(r#""nested":"a""#));
 }
 
+#[test]
+fn test_match_expression() {
+    let source = r#"
+let envs = ["prod", "staging", "qa", "dev"]
+replicas: {
+  for env in envs {
+    "${env}": match env {
+      "prod" => 5
+      "staging", "qa" => 2
+      _ => 1
+    }
+  }
+}
+"#;
+    let json = compile_to_json(source).unwrap();
+    assert!(json.contains(r#""replicas":{"prod":5,"staging":2,"qa":2,"dev":1}"#));
+
+    let err = compile_to_json("x: match \"dev\" { \"prod\" => 1 }").unwrap_err();
+    assert!(
+        err.to_string().contains("no match arm for \"dev\""),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_block_spread_in_several_places() {
+    let source = r#"
+let probes = block {
+  liveness { path: "/healthz" }
+  readiness { path: "/ready" }
+  ports +: [9090]
+}
+for name in ["web", "api"] {
+  "${name}": {
+    ports: [80]
+    ...probes
+    liveness { delay: 5 }
+  }
+}
+"#;
+    let json = compile_to_json(source).unwrap();
+    let expected = r#"{"ports":[80,9090],"liveness":{"path":"/healthz","delay":5},"readiness":{"path":"/ready"}}"#;
+    assert!(json.contains(&format!(r#""web":{}"#, expected)), "{}", json);
+    assert!(json.contains(&format!(r#""api":{}"#, expected)), "{}", json);
+}
+
 #[test]
 fn test_null_coalesce() {
     let source = r#"
@@ -521,6 +568,63 @@ app {
         }
     }
 
+    #[test]
+    fn test_import_data_files() {
+        let dir = TempDir::new().unwrap();
+        create_test_files(
+            dir.path(),
+            &[
+                ("regions.json", r#"[{"name": "us"}, {"name": "eu"}]"#),
+                ("logging.yaml", "level: info\nformat: json\n"),
+                ("limits.toml", "cpu = 2\n"),
+                ("services.csv", "name,replicas\napi,3\nweb,2\n"),
+                (
+                    "main.hone",
+                    r#"
+import "./regions.json" as regions
+import { level } from "./logging.yaml"
+import "./limits.toml"
+import "./services.csv" as services
+
+regions: map(regions, (r) => r.name)
+level: level
+cpu: limits.cpu
+replicas: map(services, (s) => to_int(s.replicas))
+"#,
+                ),
+            ],
+        );
+
+        let result = compile_file(dir.path().join("main.hone")).unwrap();
+        let json = hone::emit(&result, hone::OutputFormat::Json).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "regions": ["us", "eu"],
+                "level": "info",
+                "cpu": 2,
+                "replicas": [3, 2],
+            })
+        );
+
+        create_test_files(
+            dir.path(),
+            &[
+                ("broken.csv", "name,replicas\napi\n"),
+                ("bad.hone", "import \"./broken.csv\" as b\nx: b\n"),
+            ],
+        );
+        let err = compile_file(dir.path().join("bad.hone"))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("broken.csv") && err.contains("line 2 has 1 of the header's 2 fields"),
+            "{}",
+            err
+        );
+    }
+
     #[test]
     fn test_import_named() {
         let dir = TempDir::new().unwrap();
@@ -566,6 +670,51 @@ server {
         }
     }
 
+    #[test]
+    fn test_import_destructured() {
+        let dir = TempDir::new().unwrap();
+        create_test_files(
+            dir.path(),
+            &[
+                (
+                    "defaults.hone",
+                    r#"
+let server_defaults = { host: "0.0.0.0", port: 8080 }
+let [primary, ...replicas] = ["db-1", "db-2", "db-3"]
+"#,
+                ),
+                (
+                    "main.hone",
+                    r#"
+import "./defaults.hone" as defaults
+
+let { host, port } = defaults.server_defaults
+
+server {
+    listen: "${host}:${port}"
+    primary: defaults.primary
+    replicas: defaults.replicas
+}
+"#,
+                ),
+            ],
+        );
+
+        let result = compile_file(dir.path().join("main.hone")).unwrap();
+        let json = hone::emit(&result, hone::OutputFormat::Json).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "server": {
+                    "listen": "0.0.0.0:8080",
+                    "primary": "db-1",
+                    "replicas": ["db-2", "db-3"],
+                }
+            })
+        );
+    }
+
     #[test]
     fn test_from_inheritance() {
         let dir = TempDir::new().unwrap();
@@ -707,6 +856,54 @@ port: "not-an-int"
         let err = result.unwrap_err();
         assert!(matches!(err, hone::HoneError::TypeMismatch { .. }));
     }
+
+    #[test]
+    fn test_partial_use_checks_base_and_completes_in_child() {
+        let dir = TempDir::new().unwrap();
+        create_test_files(
+            dir.path(),
+            &[
+                (
+                    "base.hone",
+                    r#"
+schema Server {
+    host: string
+    port: int
+}
+
+partial use Server
+
+host: "localhost"
+"#,
+                ),
+                ("good.hone", "from \"./base.hone\"\nport: 8080\n"),
+                ("missing.hone", "from \"./base.hone\"\nhost: \"db\"\n"),
+                (
+                    "bad_base.hone",
+                    "schema Server {\n    host: string\n    port: int\n}\npartial use Server\nhost: 42\n",
+                ),
+                ("bad_child.hone", "from \"./bad_base.hone\"\nport: 1\n"),
+            ],
+        );
+
+        // The base compiles on its own without the fields it leaves open
+        assert!(compile_file(dir.path().join("base.hone")).is_ok());
+        assert!(compile_file(dir.path().join("good.hone")).is_ok());
+
+        // A child that never supplies `port` fails at its `from`
+        let err = compile_file(dir.path().join("missing.hone")).unwrap_err();
+        assert!(
+            matches!(&err, hone::HoneError::MissingField { field, .. } if field == "port"),
+            "{:?}",
+            err
+        );
+
+        // A type error in the base is reported against the base's own source
+        match compile_file(dir.path().join("bad_child.hone")).unwrap_err() {
+            hone::HoneError::TypeMismatch { src, .. } => assert!(src.contains("partial use")),
+            other => panic!("expected type mismatch, got {:?}", other),
+        }
+    }
 }
 
 // Variant system tests
@@ -1104,6 +1301,56 @@ config {
         assert!(result.contains("\"api\""), "got: {}", result);
         assert!(result.contains("\"worker\""), "got: {}", result);
     }
+
+    #[test]
+    fn test_for_index_variable_and_where() {
+        let source = r#"
+let services = [
+  { name: "auth", enabled: true },
+  { name: "legacy", enabled: false },
+  { name: "api", enabled: true },
+]
+
+ports {
+  for s, i in services where s.enabled {
+    "${s.name}": 8000 + i
+  }
+}
+names: for s in services where s.name != "auth" { s.name }
+"#;
+        let result = compile_to_json(source).unwrap();
+        assert!(
+            result.contains(r#""ports":{"auth":8000,"api":8002}"#),
+            "got: {}",
+            result
+        );
+        assert!(
+            result.contains(r#""names":["legacy","api"]"#),
+            "got: {}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_for_zip_destructures_pairs() {
+        let source = r#"
+let hosts = ["a.example.com", "b.example.com", "c.example.com"]
+let ports = [80, 443]
+urls: for (host, port) in zip(hosts, ports) { "${host}:${port}" }
+"#;
+        let result = compile_to_json(source).unwrap();
+        assert!(
+            result.contains(r#""urls":["a.example.com:80","b.example.com:443"]"#),
+            "got: {}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_for_where_formatting_roundtrip() {
+        let source = "names: for s, i in services where i > 0 { s }\n";
+        assert_eq!(hone::format_source(source).unwrap(), source);
+    }
 }
 
 mod assertion_error_display_tests {
@@ -1488,6 +1735,25 @@ mod expect_tests {
         assert!(result.unwrap().contains("\"port\":9090"));
     }
 
+    #[test]
+    fn test_expect_duration_from_string_arg() {
+        let source = "expect args.timeout: duration = 30s\ntimeout: args.timeout\nsecs: to_seconds(args.timeout)";
+        let result = compile_with_args(source, Value::Object(IndexMap::new())).unwrap();
+        assert!(result.contains("\"timeout\":\"30s\""), "{}", result);
+
+        let result = compile_with_args(
+            source,
+            make_args(&[("timeout", Value::String("2m".into()))]),
+        );
+        assert!(result.unwrap().contains("\"secs\":120"));
+
+        let result = compile_with_args(
+            source,
+            make_args(&[("timeout", Value::String("2 min".into()))]),
+        );
+        assert!(result.is_err(), "should fail on a malformed duration");
+    }
+
     #[test]
     fn test_expect_type_mismatch() {
         let source = "expect args.port: int\nport: args.port";
@@ -1504,6 +1770,40 @@ mod expect_tests {
         );
     }
 
+    #[test]
+    fn test_expect_coerces_set_values() {
+        let set = |pairs: &[(&str, &str)]| {
+            let pairs: Vec<_> = pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            hone::build_args_object(&pairs, &[], &[]).unwrap()
+        };
+        let source = "expect args.mode: string\nexpect args.flag: string\nexpect args.port: int\nmode: args.mode\nflag: args.flag\nport: args.port";
+        let result = compile_with_args(
+            source,
+            set(&[("mode", "0755"), ("flag", "true"), ("port", "8080")]),
+        )
+        .unwrap();
+        assert!(result.contains("\"mode\":\"0755\""), "{}", result);
+        assert!(result.contains("\"flag\":\"true\""), "{}", result);
+        assert!(result.contains("\"port\":8080"), "{}", result);
+
+        // A leading zero is kept for strings but still parses as an int
+        let result = compile_with_args(
+            "expect args.port: int\nport: args.port",
+            set(&[("port", "0080")]),
+        )
+        .unwrap();
+        assert!(result.contains("\"port\":80"), "{}", result);
+
+        let result = compile_with_args(
+            "expect args.debug: bool\ndebug: args.debug",
+            set(&[("debug", "yes")]),
+        );
+        assert!(result.is_err(), "'yes' is not a bool");
+    }
+
     #[test]
     fn test_expect_multiple_declarations() {
         let source =
@@ -1557,6 +1857,197 @@ mod expect_tests {
     }
 }
 
+mod enum_tests {
+    use hone::evaluator::value::Value;
+    use hone::{emit, Evaluator, Lexer, OutputFormat, Parser};
+    use indexmap::IndexMap;
+    use std::collections::HashMap;
+
+    const SOURCE: &str = r#"
+enum Env { dev, staging, prod }
+
+schema Service {
+    env: Env
+    replicas: int
+}
+
+expect args.env: Env = "dev"
+
+variant tier: Env {
+    dev {
+        replicas: 1
+    }
+
+    default prod {
+        replicas: 3
+    }
+}
+
+use Service
+
+env: args.env
+"#;
+
+    fn compile(
+        source: &str,
+        env: Option<&str>,
+        tier: Option<&str>,
+    ) -> Result<String, hone::HoneError> {
+        let mut lexer = Lexer::new(source, None);
+        let tokens = lexer.tokenize()?;
+        let mut parser = Parser::new(tokens, source, None);
+        let ast = parser.parse()?;
+        let mut evaluator = Evaluator::new(source);
+        let mut args = IndexMap::new();
+        if let Some(env) = env {
+            args.insert("env".to_string(), Value::String(env.to_string()));
+        }
+        evaluator.define("args", Value::Object(args));
+        let mut selections = HashMap::new();
+        if let Some(tier) = tier {
+            selections.insert("tier".to_string(), tier.to_string());
+        }
+        evaluator.set_variant_selections(selections);
+        let value = evaluator.evaluate(&ast)?;
+        emit(&value, OutputFormat::Json)
+    }
+
+    #[test]
+    fn test_enum_members_are_string_values() {
+        let json = compile(
+            "enum Env { dev, prod }\nname: Env.prod\nall: Env",
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(json, r#"{"name":"prod","all":{"dev":"dev","prod":"prod"}}"#);
+    }
+
+    #[test]
+    fn test_enum_typed_expect() {
+        assert_eq!(
+            compile(SOURCE, None, Some("dev")).unwrap(),
+            r#"{"replicas":1,"env":"dev"}"#
+        );
+        let err = format!("{:?}", compile(SOURCE, Some("qa"), None).unwrap_err());
+        assert!(err.contains("one of: dev, staging, prod"), "{}", err);
+    }
+
+    #[test]
+    fn test_enum_variant_value_without_case_takes_default() {
+        assert_eq!(
+            compile(SOURCE, Some("staging"), Some("staging")).unwrap(),
+            r#"{"replicas":3,"env":"staging"}"#
+        );
+        let err = format!("{:?}", compile(SOURCE, None, Some("stage")).unwrap_err());
+        assert!(err.contains("found: \"stage\""), "{}", err);
+    }
+
+    #[test]
+    fn test_enum_variant_must_cover_values() {
+        let missing = "enum Env { dev, staging, prod }\nvariant tier: Env {\n    dev {\n        a: 1\n    }\n}\n";
+        let err = format!("{:?}", compile(missing, None, Some("dev")).unwrap_err());
+        assert!(err.contains("no case for staging, prod"), "{}", err);
+
+        let unknown =
+            "enum Env { dev }\nvariant tier: Env {\n    default qa {\n        a: 1\n    }\n}\n";
+        let err = format!("{:?}", compile(unknown, None, None).unwrap_err());
+        assert!(err.contains("is not a value of enum Env"), "{}", err);
+    }
+
+    #[test]
+    fn test_enum_as_schema_field_type() {
+        let compiled = hone::Compiler::new(".").compile_source(
+            "enum Env { dev, prod }\nschema S {\n    env: Env\n}\nuse S\nenv: \"qa\"\n",
+        );
+        assert!(compiled.is_err());
+    }
+}
+
+mod final_key_tests {
+    use super::compile_to_json;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn final_key(err: hone::HoneError) -> String {
+        match err {
+            hone::HoneError::FinalKeyOverridden { key, .. } => key,
+            other => panic!("expected final key error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_final_key_compiles_like_a_plain_key() {
+        let output =
+            compile_to_json("final replicas: 3\nfinal security {\n    tls: true\n}\nfinal: 1\n")
+                .unwrap();
+        assert_eq!(
+            output,
+            r#"{"replicas":3,"security":{"tls":true},"final":1}"#
+        );
+    }
+
+    #[test]
+    fn test_final_key_overrides_in_the_same_file() {
+        let err =
+            compile_to_json("final replicas: 3\nwhen true {\n    replicas: 5\n}\n").unwrap_err();
+        assert_eq!(final_key(err), "replicas");
+
+        let err =
+            compile_to_json("final db {\n    host: \"a\"\n}\ndb {\n    port: 1\n}\n").unwrap_err();
+        assert_eq!(final_key(err), "db");
+
+        let err = compile_to_json("server: { port: 80 }\nserver {\n    final port: 8080\n}\n")
+            .unwrap_err();
+        assert_eq!(final_key(err), "server.port");
+    }
+
+    #[test]
+    fn test_final_key_set_by_variant_case() {
+        let source = "variant env {\n    default dev {\n        replicas: 1\n    }\n    prod {\n        debug: false\n    }\n}\nfinal replicas: 3\n";
+        let err = compile_to_json(source).unwrap_err();
+        assert_eq!(final_key(err), "replicas");
+    }
+
+    #[test]
+    fn test_final_key_inherited_through_from() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("platform.hone"),
+            "final replicas: 3\nname: \"base\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("team.hone"),
+            "from \"./platform.hone\"\nname: \"team\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("svc.hone"),
+            "from \"./team.hone\"\nreplicas: 10\n",
+        )
+        .unwrap();
+
+        let team = hone::compile_file(dir.path().join("team.hone")).unwrap();
+        assert_eq!(team.get_path(&["replicas"]), Some(&hone::Value::Int(3)));
+
+        // Two levels down the key is still owned by the platform file
+        match hone::compile_file(dir.path().join("svc.hone")).unwrap_err() {
+            hone::HoneError::FinalKeyOverridden {
+                key,
+                final_span,
+                help,
+                ..
+            } => {
+                assert_eq!(key, "replicas");
+                assert!(final_span.is_none());
+                assert!(help.contains("platform.hone:1:1"), "{}", help);
+            }
+            other => panic!("expected final key error, got {:?}", other),
+        }
+    }
+}
+
 mod variant_let_tests {
     use super::*;
 
@@ -1745,6 +2236,34 @@ mod operator_precedence_tests {
         );
     }
 
+    #[test]
+    fn test_pipeline_feeds_first_argument() {
+        let source = "let xs = [3, 1, 2, 5]\nfn add(a, b) { a + b }\nlet double = (x) => x * 2\n\
+                      sorted: xs\n  |> filter((x) => x > 1)\n  |> map(double)\n  |> sort()\n\
+                      sum: 2 |> add(3) |> double()\n\
+                      long: xs |> len() > 3\n\
+                      fallback: null ?? [2, 1] |> sort()\n";
+        let json = compile_to_json(source).unwrap();
+        assert!(json.contains("\"sorted\":[4,6,10]"), "{}", json);
+        assert!(json.contains("\"sum\":10"), "{}", json);
+        // `|>` binds looser than `??` and tighter than comparisons
+        assert!(json.contains("\"long\":true"), "{}", json);
+        assert!(json.contains("\"fallback\":[1,2]"), "{}", json);
+
+        let err = compile_to_json("x: [1] |> 3\n").unwrap_err().to_string();
+        assert!(err.contains("unexpected token"), "{}", err);
+    }
+
+    #[test]
+    fn test_try_recovers_from_bad_data() {
+        let source = "let data = from_json(\"{\\\"zones\\\": []}\")\n\
+                      zone: data.zones[0] |> try(\"us-east-1a\")\n\
+                      replicas: try(to_int(data.scaling.replicas), 2)\n";
+        let json = compile_to_json(source).unwrap();
+        assert!(json.contains("\"zone\":\"us-east-1a\""), "{}", json);
+        assert!(json.contains("\"replicas\":2"), "{}", json);
+    }
+
     #[test]
     fn test_precedence_unary_highest() {
         let source = "result: !false == true\n";
@@ -2462,6 +2981,58 @@ key: "value"
         let result = compiler.compile_source(source);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_output_size_budget_measures_target_format() {
+        let source = r#"
+policy size_budget deny when output_size_bytes() > 20 {
+    "output too large"
+}
+
+name: "service"
+"#;
+        let base_dir = std::env::current_dir().unwrap();
+        let mut compiler = Compiler::new(&base_dir);
+        // `name: service` is 13 bytes of YAML, but pretty JSON adds braces and quotes
+        compiler.set_output_format(OutputFormat::Yaml);
+        assert!(compiler.compile_source(source).is_ok());
+        compiler.set_output_format(OutputFormat::JsonPretty);
+        let err = compiler.compile_source(source).unwrap_err().message();
+        assert!(err.contains("size_budget"), "{}", err);
+    }
+
+    #[test]
+    fn test_count_budget_warns_with_budget_code() {
+        let source = r#"
+policy env_budget warn when count("containers.*.env.*") > 2 {
+    "no more than 2 environment variables in total"
+}
+policy debug_off warn when output.debug == true
+
+debug: true
+containers: [
+    { env: [{ name: "A" }, { name: "B" }] },
+    { env: [{ name: "C" }] },
+]
+"#;
+        let base_dir = std::env::current_dir().unwrap();
+        let mut compiler = Compiler::new(&base_dir);
+        compiler.compile_source(source).unwrap();
+        let codes: Vec<&str> = compiler
+            .warnings()
+            .iter()
+            .map(|w| w.code.as_str())
+            .collect();
+        assert_eq!(codes, ["budget", "policy"]);
+    }
+
+    #[test]
+    fn test_budget_helpers_only_in_policies() {
+        let err = compile_with_policies("size: output_size_bytes()\n")
+            .unwrap_err()
+            .message();
+        assert!(err.contains("output_size_bytes"), "{}", err);
+    }
 }
 
 mod typeprovider_tests {
@@ -2774,6 +3345,61 @@ name: 42
     }
 }
 
+// =============================================================================
+// Schema pattern constraint integration tests
+// =============================================================================
+
+mod schema_pattern_tests {
+    fn compile(source: &str) -> Result<hone::Value, hone::HoneError> {
+        hone::Compiler::new(".").compile_source(source)
+    }
+
+    #[test]
+    fn test_named_pattern_constraint() {
+        let schema = r#"
+type DnsLabel = string(1, 63, pattern: "^[a-z0-9]([a-z0-9-]*[a-z0-9])?$")
+schema Service {
+    name: DnsLabel
+    team: string(pattern: "^[a-z]+$")
+}
+use Service
+"#;
+        let ok = format!("{}\nname: \"api-v2\"\nteam: \"infra\"\n", schema);
+        assert!(compile(&ok).is_ok(), "{:?}", compile(&ok).err());
+
+        let source = format!("{}\nname: \"Api_V2\"\nteam: \"infra\"\n", schema);
+        let err = format!("{:?}", compile(&source).unwrap_err());
+        assert!(err.contains("PatternMismatch"), "{}", err);
+        assert!(
+            err.contains("string \\\"Api_V2\\\" does not match"),
+            "{}",
+            err
+        );
+
+        // The length limit applies alongside the pattern
+        let long = "a".repeat(64);
+        let source = format!("{}\nname: \"{}\"\nteam: \"infra\"\n", schema, long);
+        assert!(compile(&source).is_err());
+    }
+
+    #[test]
+    fn test_named_pattern_errors_point_at_argument() {
+        let source = "schema S {\n    a: string(pattern: \"[a-\")\n}\nuse S\na: \"x\"\n";
+        let err = compile(source).unwrap_err();
+        let hone::HoneError::TypeMismatch { span, .. } = err else {
+            panic!("expected a type mismatch, got {:?}", err);
+        };
+        assert_eq!(span.offset(), source.find("\"[a-\"").unwrap());
+        assert_eq!(span.len(), "\"[a-\"".len());
+
+        let err = compile("schema S {\n    a: int(min: 1)\n}\nuse S\na: 2\n").unwrap_err();
+        assert!(format!("{:?}", err).contains("int takes no named arguments"));
+        let err = compile("schema S {\n    a: string(pattern: \"a\", 3)\n}\nuse S\na: \"a\"\n")
+            .unwrap_err();
+        assert!(format!("{:?}", err).contains("positional arguments come before named ones"));
+    }
+}
+
 // =============================================================================
 // Triple-quoted string integration tests
 // =============================================================================
@@ -2805,6 +3431,31 @@ Welcome."""
         assert!(json.contains("Hello, Hone!"));
         assert!(json.contains("Welcome."));
     }
+
+    #[test]
+    fn test_triple_quoted_strips_indent_and_chomps() {
+        let source = r#"
+let app = "api"
+deploy {
+  script: """
+    #!/bin/sh
+    echo "deploying ${app}"
+      exit 0
+    """
+  values: """-
+    name: ${app}
+    replicas: 2
+    """
+}
+"#;
+        let json = compile_to_json(source).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            json["deploy"]["script"],
+            "#!/bin/sh\necho \"deploying api\"\n  exit 0\n"
+        );
+        assert_eq!(json["deploy"]["values"], "name: api\nreplicas: 2");
+    }
 }
 
 // =============================================================================
@@ -2895,6 +3546,40 @@ result: "value is ${x}"
         assert!(json.contains("value is null"));
     }
 
+    #[test]
+    fn test_interpolation_format_specs() {
+        let source = r#"
+let ratio = 2.0 / 3.0
+let port = 80
+let replicas = 3
+ratio: "${ratio:.2}"
+port: "${port:05}"
+share: "${ratio:.1%}"
+total: "${1234567:,}"
+mask: "0x${255:04X}"
+label: "[${"api":^7}]"
+sized: "${replicas > 1 ? "many" : "one":>5}"
+"#;
+        let json: serde_json::Value =
+            serde_json::from_str(&compile_to_json(source).unwrap()).unwrap();
+        assert_eq!(json["ratio"], "0.67");
+        assert_eq!(json["port"], "00080");
+        assert_eq!(json["share"], "66.7%");
+        assert_eq!(json["total"], "1,234,567");
+        assert_eq!(json["mask"], "0x00FF");
+        assert_eq!(json["label"], "[  api  ]");
+        assert_eq!(json["sized"], " many");
+
+        let err = compile_to_json("name: \"${\"api\":05}\"").unwrap_err();
+        assert!(
+            matches!(err, hone::HoneError::TypeMismatch { .. }),
+            "{:?}",
+            err
+        );
+        let err = compile_to_json("n: \"${1:5q}\"").unwrap_err();
+        assert!(err.to_string().contains("unexpected token"), "{}", err);
+    }
+
     #[test]
     fn test_null_coalesce() {
         let source = r#"
@@ -3614,14 +4299,14 @@ fn test_builtin_min_max_abs() {
 mn: min(3, 7)
 mx: max(3, 7)
 ab: abs(-42)
-ab_f: abs(-3.14)
+ab_f: abs(-2.5)
 "#;
     let json = compile_to_json(source).unwrap();
     let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
     assert_eq!(parsed["mn"], 3);
     assert_eq!(parsed["mx"], 7);
     assert_eq!(parsed["ab"], 42);
-    assert_eq!(parsed["ab_f"], 3.14);
+    assert_eq!(parsed["ab_f"], 2.5);
 }
 
 #[test]
@@ -3647,6 +4332,48 @@ hash: sha256("hello")
     );
 }
 
+#[test]
+fn test_builtin_to_toml_from_toml() {
+    let source = r#"
+let settings = { name: "api", server: { port: 8080, hosts: ["a", "b"] } }
+configmap {
+  data {
+    "app.toml": to_toml(settings)
+  }
+}
+let restored = from_toml(to_toml(settings))
+let owner = from_toml("[owner]\nname = \"ops\"")
+port: restored.server.port
+owner: owner.owner.name
+"#;
+    let json = compile_to_json(source).unwrap();
+    let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        parsed["configmap"]["data"]["app.toml"],
+        "name = \"api\"\n\n[server]\nport = 8080\nhosts = [\"a\", \"b\"]\n"
+    );
+    assert_eq!(parsed["port"], 8080);
+    assert_eq!(parsed["owner"], "ops");
+}
+
+#[test]
+fn test_builtin_dig() {
+    let source = r#"
+let cfg = { spec: { containers: [{ name: "api", image: "api:1" }] } }
+let field = "image"
+image: dig(cfg, "spec.containers.0.${field}")
+replicas: dig(cfg, "spec.replicas", 1)
+name: dig(cfg, ["spec", "containers", 0, "name"], "none")
+sidecar: dig(cfg, ["spec", "containers", 1, "name"], "none")
+"#;
+    let json = compile_to_json(source).unwrap();
+    let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed["image"], "api:1");
+    assert_eq!(parsed["replicas"], 1);
+    assert_eq!(parsed["name"], "api");
+    assert_eq!(parsed["sidecar"], "none");
+}
+
 #[test]
 fn test_builtin_type_of() {
     let source = r#"
@@ -3669,6 +4396,27 @@ tf: type_of(3.14)
     assert_eq!(parsed["tf"], "float");
 }
 
+#[test]
+fn test_builtin_assert_type() {
+    let source = r#"
+let check_port = (p) => assert_type(p, "int", "port must be a number")
+port: check_port(8080)
+region: assert_type(null, "string?")
+"#;
+    let json = compile_to_json(source).unwrap();
+    let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed["port"], 8080);
+    assert_eq!(parsed["region"], serde_json::Value::Null);
+
+    let source = "name: \"api\"\nport: assert_type(\"8080\", \"int\")\n";
+    let err = compile_to_json(source).unwrap_err();
+    let span = err.span().expect("error has a span");
+    assert_eq!(
+        &source[span.start..span.end],
+        "assert_type(\"8080\", \"int\")"
+    );
+}
+
 #[test]
 fn test_builtin_substring() {
     let source = r#"
@@ -3681,6 +4429,29 @@ tail: substring("hello world", 6)
     assert_eq!(parsed["tail"], "world");
 }
 
+#[test]
+fn test_builtin_string_utilities() {
+    let source = r#"
+let service = "orderService"
+name: kebab_case(service)
+env_var: upper(snake_case(service))
+label: title_case(service)
+id: pad_left(to_str(7), 3, "0")
+col: pad_right("port", 6) + "|"
+at: index_of("api-gateway", "-")
+missing: index_of(["a", "b"], "c")
+"#;
+    let json = compile_to_json(source).unwrap();
+    let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed["name"], "order-service");
+    assert_eq!(parsed["env_var"], "ORDER_SERVICE");
+    assert_eq!(parsed["label"], "Order Service");
+    assert_eq!(parsed["id"], "007");
+    assert_eq!(parsed["col"], "port  |");
+    assert_eq!(parsed["at"], 3);
+    assert_eq!(parsed["missing"], -1);
+}
+
 #[test]
 fn test_builtin_entries_from_entries() {
     let source = r#"
@@ -4106,3 +4877,179 @@ port: 8080
         result.err()
     );
 }
+
+#[test]
+fn test_key_order_follows_source_and_merge_order() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("base.hone"),
+        "zeta: 1\nserver {\n  port: 80\n  host: \"base\"\n}\n",
+    )
+    .unwrap();
+    let file = dir.path().join("main.hone");
+    std::fs::write(
+        &file,
+        r#"
+from "./base.hone"
+
+variant env {
+    default dev {
+        mode: "dev"
+    }
+}
+
+beta: 2
+server {
+    tls: true
+    port: 443
+}
+when true {
+    alpha: 3
+}
+for k in ["y", "x"] {
+    "${k}": k
+}
+...{ omega: 4 }
+data: from_json("{\"b\":1,\"a\":2}")
+json: to_json({ d: 1, c: 2 })
+"#,
+    )
+    .unwrap();
+
+    let mut compiler = hone::Compiler::new(dir.path());
+    let value = compiler.compile(&file).unwrap();
+    let json = emit(&value, OutputFormat::Json).unwrap();
+    assert_eq!(
+        json,
+        r#"{"zeta":1,"server":{"port":443,"host":"base","tls":true},"mode":"dev","beta":2,"alpha":3,"y":"y","x":"x","omega":4,"data":{"b":1,"a":2},"json":"{\"d\":1,\"c\":2}"}"#
+    );
+
+    let yaml = emit(&value, OutputFormat::Yaml).unwrap();
+    let keys: Vec<&str> = yaml
+        .lines()
+        .filter(|l| !l.starts_with(' '))
+        .filter_map(|l| l.split(':').next())
+        .collect();
+    assert_eq!(
+        keys,
+        ["zeta", "server", "mode", "beta", "alpha", "y", "x", "omega", "data", "json"]
+    );
+
+    let sorted = emit(&value.sorted_keys(), OutputFormat::Json).unwrap();
+    assert!(sorted.starts_with(r#"{"alpha":3,"beta":2,"data":{"a":2,"b":1},"json""#));
+}
+
+#[test]
+fn test_optimize_preserves_output() {
+    let source = r#"
+let base = 8000
+let name = "api"
+
+variant env {
+    default dev {
+        let replicas = 1
+    }
+
+    prod {
+        let replicas = 4
+    }
+}
+
+port: base + 80
+label: "${name}-${base / 2}"
+when replicas > 1 {
+    let mode = "ha"
+    ha: true
+} else {
+    let mode = "single"
+}
+mode: mode
+hosts: [for i in range(replicas) { "${name}-${i}" }]
+sizes: [1, when replicas > 2 { x: "large" }]
+server {
+    let name = "inner"
+    id: replicas > 1 ? name : "${name}!"
+}
+"#;
+    for env in ["dev", "prod"] {
+        let compile = |optimize: bool| {
+            let mut compiler = hone::Compiler::new(".");
+            compiler.set_optimize(optimize);
+            compiler.set_variants(HashMap::from([("env".to_string(), env.to_string())]));
+            emit(
+                &compiler.compile_source(source).unwrap(),
+                OutputFormat::Json,
+            )
+            .unwrap()
+        };
+        assert_eq!(compile(true), compile(false), "env={}", env);
+    }
+}
+
+#[test]
+fn test_durations_emit_as_literal_strings() {
+    let source = r#"
+let period = 1m30s
+period: period
+timeout: period / 3
+"#;
+    let yaml = compile_to_yaml(source).unwrap();
+    assert_eq!(yaml, "period: 1m30s\ntimeout: 30s");
+
+    // Schemas can require a duration where a bare int used to slip through
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("probe.hone");
+    std::fs::write(
+        &file,
+        "schema Probe {\n  timeout: duration\n}\nuse Probe\ntimeout: 30\n",
+    )
+    .unwrap();
+    let err = hone::Compiler::new(dir.path()).compile(&file).unwrap_err();
+    assert!(format!("{:?}", err).contains("duration"), "{:?}", err);
+}
+
+#[test]
+fn test_sizes_emit_as_kubernetes_quantities() {
+    let source = r#"
+let request = 256Mi
+resources {
+  requests { memory: request }
+  limits { memory: request * 4 }
+  storage: 100GB
+}
+"#;
+    let yaml = compile_to_yaml(source).unwrap();
+    assert_eq!(
+        yaml,
+        "resources:\n  requests:\n    memory: 256Mi\n  limits:\n    memory: 1Gi\n  storage: 100G"
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("limits.hone");
+    std::fs::write(
+        &file,
+        "schema Limits {\n  memory: size\n}\nuse Limits\nmemory: 512\n",
+    )
+    .unwrap();
+    let err = hone::Compiler::new(dir.path()).compile(&file).unwrap_err();
+    assert!(format!("{:?}", err).contains("size"), "{:?}", err);
+}
+
+#[test]
+fn test_radix_and_separated_number_literals() {
+    let source = r#"
+file {
+  mode: 0o644
+  mask: 0b0111 + 0x10
+  limit: 1_000_000
+  neg: -0xFF
+}
+"#;
+    let json = compile_to_json(source).unwrap();
+    assert_eq!(
+        json,
+        r#"{"file":{"mode":420,"mask":23,"limit":1000000,"neg":-255}}"#
+    );
+    assert!(compile_to_json("x: 0o789").is_err());
+    assert!(compile_to_json("x: 0x1_0000_0000_0000_0000").is_err());
+}